@@ -0,0 +1,415 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks of structs and unions that contain themselves by value. The
+//! bindings generator rejects them, which otherwise shows up late in a build.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::syntax::{self, preorder, MojomFile, Traversal, TypeName, TypeSpec};
+
+use super::diagnostic::Diagnostic;
+use super::versioning::TypeTable;
+
+const CYCLIC_CONTAINMENT_CODE: &str = "mojom/cyclic-containment";
+
+// The number of types the check visits at most. Real documents need far
+// fewer, so this only stops pathological inputs.
+const MAX_VISITED_TYPES: usize = 100_000;
+
+// A field that holds a struct or a union by value.
+struct ContainedField<'a> {
+    name: &'a syntax::Range,
+    // The qualified name of the type when it's a struct or a union declared
+    // in the document. Otherwise the name as written.
+    typ: String,
+    is_local: bool,
+}
+
+// Returns the name of the type that `spec` holds by value. Nullable types,
+// arrays, maps, handles and interfaces don't hold a value.
+fn by_value_ident(spec: &TypeSpec) -> Option<&str> {
+    if spec.is_nullable {
+        return None;
+    }
+    match &spec.type_name {
+        TypeName::BasicTypeName(_) => spec.referenced_idents().first().copied(),
+        _ => None,
+    }
+}
+
+// Returns structs and unions in `mojom` keyed by their qualified names, with
+// their fields that may hold structs or unions by value. Fields of enum and
+// interface types are dropped.
+fn contained_fields<'a>(
+    text: &'a str,
+    mojom: &'a MojomFile,
+    types: &TypeTable,
+) -> Vec<(String, Vec<ContainedField<'a>>)> {
+    let mut containers = Vec::new();
+    // Indices of enclosing structs in `containers`.
+    let mut structs = Vec::new();
+    let mut scope: Vec<&str> = Vec::new();
+    let contained = |scope: &[&str], name: &'a syntax::Range, spec: &TypeSpec| {
+        let ident = by_value_ident(spec)?;
+        let (typ, is_local) = match types.resolve_container(scope, ident) {
+            Some((qualified, true)) => (qualified, true),
+            Some((_, false)) => return None,
+            None => (ident.to_owned(), false),
+        };
+        Some(ContainedField {
+            name,
+            typ,
+            is_local,
+        })
+    };
+    let qualify = |scope: &[&str], name: &syntax::Range| {
+        let mut path = scope.to_vec();
+        path.push(&text[name.start..name.end]);
+        path.join(".")
+    };
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => scope.push(&text[node.name.start..node.name.end]),
+            Traversal::EnterStruct(node) => {
+                structs.push(containers.len());
+                containers.push((qualify(&scope, &node.name), Vec::new()));
+                scope.push(&text[node.name.start..node.name.end]);
+            }
+            Traversal::LeaveInterface(_) => {
+                scope.pop();
+            }
+            Traversal::LeaveStruct(_) => {
+                structs.pop();
+                scope.pop();
+            }
+            Traversal::StructField(node) => {
+                let spec = match node.type_spec(text) {
+                    Ok(spec) => spec,
+                    Err(_) => continue,
+                };
+                if let (Some(field), Some(&index)) =
+                    (contained(&scope, &node.name, &spec), structs.last())
+                {
+                    containers[index].1.push(field);
+                }
+            }
+            Traversal::Union(node) => {
+                let fields = node
+                    .fields
+                    .iter()
+                    .filter_map(|field| {
+                        let spec = field.type_spec(text).ok()?;
+                        contained(&scope, &field.name, &spec)
+                    })
+                    .collect();
+                containers.push((qualify(&scope, &node.name), fields));
+            }
+            _ => (),
+        }
+    }
+    containers
+}
+
+/// Returns the types that structs and unions in `mojom` hold by value, keyed
+/// by the qualified names of the structs and unions. Types declared in the
+/// document are qualified with `module_name`. Others are kept as written.
+pub(crate) fn contained_types(
+    text: &str,
+    mojom: &MojomFile,
+    module_name: Option<&str>,
+) -> HashMap<String, Vec<String>> {
+    let types = TypeTable::new(text, mojom);
+    contained_fields(text, mojom, &types)
+        .into_iter()
+        .map(|(name, fields)| {
+            let fields = fields
+                .into_iter()
+                .map(|field| match module_name {
+                    Some(module_name) if field.is_local => format!("{}.{}", module_name, field.typ),
+                    _ => field.typ,
+                })
+                .collect();
+            (name, fields)
+        })
+        .collect()
+}
+
+// Structs and unions and the types they hold by value. Imported types are
+// added as they are reached.
+struct ContainmentGraph<'a, F> {
+    types: &'a TypeTable<'a>,
+    find_imported: F,
+    edges: HashMap<String, Vec<String>>,
+    // Imported types of which edges aren't added yet.
+    queue: VecDeque<(String, Vec<String>)>,
+    visited: usize,
+}
+
+impl<'a, F> ContainmentGraph<'a, F>
+where
+    F: FnMut(&str) -> Option<(String, Vec<String>)>,
+{
+    // Returns the node of `name` that an imported type holds. Names that are
+    // qualified with the module of the document refer to the document.
+    fn imported_node(&mut self, name: &str) -> Option<String> {
+        if let Some((qualified, is_container)) = self.types.resolve_container(&[], name) {
+            return Some(qualified).filter(|_| is_container);
+        }
+        let (qualified, contained) = (self.find_imported)(name)?;
+        if !self.edges.contains_key(&qualified) {
+            self.edges.insert(qualified.clone(), Vec::new());
+            self.queue.push_back((qualified.clone(), contained));
+        }
+        Some(qualified)
+    }
+
+    // Adds edges of imported types until the budget runs out.
+    fn expand_imported(&mut self) {
+        while let Some((name, contained)) = self.queue.pop_front() {
+            if self.visited >= MAX_VISITED_TYPES {
+                return;
+            }
+            self.visited += 1;
+            let targets = contained
+                .iter()
+                .filter_map(|typ| self.imported_node(typ))
+                .collect();
+            self.edges.insert(name, targets);
+        }
+    }
+
+    // Returns the shortest path from `from` to `to`, both inclusive.
+    fn find_path(&mut self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut parents: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from(vec![from]);
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut path = vec![node.to_owned()];
+                let mut node = node;
+                while let Some(&parent) = parents.get(node) {
+                    path.push(parent.to_owned());
+                    node = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if self.visited >= MAX_VISITED_TYPES {
+                return None;
+            }
+            self.visited += 1;
+            for next in self.edges.get(node).into_iter().flatten() {
+                if next != from && !parents.contains_key(next.as_str()) {
+                    parents.insert(next, node);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Reports fields of structs and unions that hold their own type by value,
+/// directly or through other structs and unions. Nullable types, arrays,
+/// maps, handles and interfaces break cycles. `find_imported` is called for
+/// types that aren't declared in the document. It returns the fully
+/// qualified name of an imported struct or union and the types that it holds
+/// by value, and None for other types.
+pub fn check_cyclic_containment<F>(
+    text: &str,
+    mojom: &MojomFile,
+    find_imported: F,
+    diagnostics: &mut Vec<Diagnostic>,
+) where
+    F: FnMut(&str) -> Option<(String, Vec<String>)>,
+{
+    let types = TypeTable::new(text, mojom);
+    let containers = contained_fields(text, mojom, &types);
+    let mut graph = ContainmentGraph {
+        types: &types,
+        find_imported,
+        edges: HashMap::new(),
+        queue: VecDeque::new(),
+        visited: 0,
+    };
+    for (name, _) in &containers {
+        graph.edges.insert(name.clone(), Vec::new());
+    }
+    let mut fields = Vec::new();
+    for (name, contained) in &containers {
+        for field in contained {
+            let target = if field.is_local {
+                Some(field.typ.clone())
+            } else {
+                graph.imported_node(&field.typ)
+            };
+            if let Some(target) = target {
+                graph.edges.get_mut(name).unwrap().push(target.clone());
+                fields.push((name, field, target));
+            }
+        }
+    }
+    graph.expand_imported();
+
+    for (name, field, target) in fields {
+        let path = match graph.find_path(&target, name) {
+            Some(path) => path,
+            None => continue,
+        };
+        let message = format!(
+            "`{}` contains itself by value: {} → {}. Make a field in the cycle nullable.",
+            name,
+            name,
+            path.join(" → ")
+        );
+        diagnostics.push(Diagnostic::error(
+            CYCLIC_CONTAINMENT_CODE,
+            *field.name,
+            message,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_with<F>(text: &str, find_imported: F) -> Vec<(&str, String)>
+    where
+        F: FnMut(&str) -> Option<(String, Vec<String>)>,
+    {
+        let mojom = syntax::parse(text).unwrap();
+        let mut diagnostics = Vec::new();
+        check_cyclic_containment(text, &mojom, find_imported, &mut diagnostics);
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                assert_eq!(CYCLIC_CONTAINMENT_CODE, diagnostic.code);
+                let range = diagnostic.range;
+                (&text[range.start..range.end], diagnostic.message)
+            })
+            .collect()
+    }
+
+    fn check(text: &str) -> Vec<(&str, String)> {
+        check_with(text, |_| None)
+    }
+
+    #[test]
+    fn test_self_containment() {
+        let text = "struct Foo { Foo foo; };";
+        assert_eq!(
+            vec![(
+                "foo",
+                "`Foo` contains itself by value: Foo → Foo. Make a field in the cycle nullable."
+                    .to_owned()
+            )],
+            check(text)
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let text = r#"
+module test;
+struct Foo { int32 a; Bar bar; };
+struct Bar { string b; test.Foo foo; };
+"#;
+        let diagnostics = check(text);
+        assert_eq!(
+            vec!["bar", "foo"],
+            diagnostics
+                .iter()
+                .map(|(range, _)| *range)
+                .collect::<Vec<_>>()
+        );
+        assert!(diagnostics[0].1.contains("Foo → Bar → Foo"));
+        assert!(diagnostics[1].1.contains("Bar → Foo → Bar"));
+
+        // Unions hold their fields by value.
+        let text = "struct Foo { U u; }; union U { int32 a; Foo foo; };";
+        assert_eq!(2, check(text).len());
+    }
+
+    #[test]
+    fn test_broken_cycles() {
+        // A nullable back reference.
+        assert!(check("struct Foo { Bar bar; }; struct Bar { Foo? foo; };").is_empty());
+        // Arrays, maps, handles and interfaces.
+        let text = r#"
+interface I {};
+enum E { kA };
+struct Foo {
+  array<Foo> foos;
+  array<Foo, 2> fixed;
+  map<string, Foo> values;
+  pending_remote<I> remote;
+  handle<message_pipe> pipe;
+  E e;
+  Missing missing;
+};
+"#;
+        assert!(check(text).is_empty());
+    }
+
+    #[test]
+    fn test_imported_cycle() {
+        // `imported.Bar` holds `imported.Baz`, which holds `test.Foo`.
+        let text = "module test; import \"imported.mojom\"; struct Foo { imported.Bar bar; };";
+        let diagnostics = check_with(text, |name| match name {
+            "imported.Bar" => Some((name.to_owned(), vec!["imported.Baz".to_owned()])),
+            "imported.Baz" => Some((name.to_owned(), vec!["test.Foo".to_owned()])),
+            _ => None,
+        });
+        assert_eq!(1, diagnostics.len());
+        assert!(diagnostics[0]
+            .1
+            .contains("Foo → imported.Bar → imported.Baz → Foo"));
+
+        // Imported types that refer to each other don't involve the document.
+        let text = "module test; struct Foo { imported.Bar bar; };";
+        let diagnostics = check_with(text, |name| match name {
+            "imported.Bar" => Some((name.to_owned(), vec!["imported.Bar".to_owned()])),
+            _ => None,
+        });
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_budget() {
+        // An endless chain of imported types.
+        let text = "struct Foo { Imported0 next; };";
+        let diagnostics = check_with(text, |name| {
+            let index = name["Imported".len()..].parse::<usize>().ok()?;
+            Some((name.to_owned(), vec![format!("Imported{}", index + 1)]))
+        });
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_contained_types() {
+        let text = r#"
+module test;
+struct Foo { Bar bar; Bar? nullable; other.Baz baz; int32 a; };
+union Bar { Foo foo; };
+"#;
+        let mojom = syntax::parse(text).unwrap();
+        let types = contained_types(text, &mojom, Some("test"));
+        assert_eq!(
+            &vec!["test.Bar".to_owned(), "other.Baz".to_owned()],
+            types.get("Foo").unwrap()
+        );
+        assert_eq!(&vec!["test.Foo".to_owned()], types.get("Bar").unwrap());
+    }
+}
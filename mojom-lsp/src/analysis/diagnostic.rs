@@ -0,0 +1,110 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Deserialize;
+
+use crate::syntax::{Range, SyntaxError};
+
+/// The diagnostic code for syntax and semantics errors.
+pub const MOJOM_CODE: &str = "mojom";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A location in the same document that relates to a diagnostic, e.g. the
+/// first definition of a duplicated name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Related {
+    pub range: Range,
+    pub message: String,
+}
+
+/// A problem found in a mojom document. `range` is a byte offset range in the
+/// document text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub range: Range,
+    pub related: Option<Related>,
+    /// The range is unused code, e.g. an unused import. Editors may render it
+    /// faded.
+    pub unnecessary: bool,
+    /// The document couldn't be parsed. Only syntax errors are fatal. Other
+    /// diagnostics leave a usable syntax tree.
+    pub fatal: bool,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, range: Range, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            range,
+            related: None,
+            unnecessary: false,
+            fatal: false,
+        }
+    }
+
+    pub fn error(code: &'static str, range: Range, message: String) -> Self {
+        Diagnostic::new(Severity::Error, code, range, message)
+    }
+
+    /// Creates a diagnostic for a syntax error. The message is a single line
+    /// that lists the expected tokens, e.g. "expected ';' or '{' here".
+    pub fn syntax_error(err: &SyntaxError) -> Self {
+        let message = match (err.message(), err.expected().as_slice()) {
+            (Some(message), _) => message.to_owned(),
+            (None, []) => "unexpected input here".to_owned(),
+            (None, [expected]) => format!("expected {} here", expected),
+            (None, [init @ .., last]) => {
+                format!("expected {} or {} here", init.join(", "), last)
+            }
+        };
+        let mut diagnostic = Diagnostic::error(MOJOM_CODE, err.span(), message);
+        diagnostic.fatal = true;
+        diagnostic
+    }
+
+    pub fn with_related(mut self, range: Range, message: String) -> Self {
+        self.related = Some(Related { range, message });
+        self
+    }
+
+    pub fn mark_unnecessary(mut self) -> Self {
+        self.unnecessary = true;
+        self
+    }
+}
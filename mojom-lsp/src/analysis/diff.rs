@@ -0,0 +1,767 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares two versions of a mojom file and reports changes that can break
+//! compatibility. Additions aren't reported.
+
+use std::collections::HashMap;
+
+use crate::syntax::{
+    self, Const, Enum, Interface, InterfaceMember, Method, MojomFile, Parameter, Struct,
+    StructBody, StructField, Traversal, Union, UnionField,
+};
+
+use super::semantic::effective_ordinals;
+
+fn partial_text<'a>(text: &'a str, range: &syntax::Range) -> &'a str {
+    &text[range.start..range.end]
+}
+
+// Types are compared without whitespace so that `map<string,int32>` and
+// `map<string, int32>` are the same.
+fn same_text(a: &str, b: &str) -> bool {
+    a.chars()
+        .filter(|ch| !ch.is_whitespace())
+        .eq(b.chars().filter(|ch| !ch.is_whitespace()))
+}
+
+/// What changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    /// A type or a const was removed.
+    Removed,
+    /// A type was redeclared as another kind, e.g. a struct became a union.
+    KindChanged,
+    MethodRemoved,
+    /// A method was removed and another method took its ordinal.
+    MethodRenamed,
+    /// Request or response parameters of a method changed.
+    ParametersChanged,
+    EnumValueRemoved,
+    ConstValueChanged,
+    /// A struct or union field was removed.
+    FieldRemoved,
+    FieldTypeChanged,
+    /// The ordinal of a method or a field changed.
+    OrdinalChanged,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Removed => "removed",
+            ChangeKind::KindChanged => "kind-changed",
+            ChangeKind::MethodRemoved => "method-removed",
+            ChangeKind::MethodRenamed => "method-renamed",
+            ChangeKind::ParametersChanged => "parameters-changed",
+            ChangeKind::EnumValueRemoved => "enum-value-removed",
+            ChangeKind::ConstValueChanged => "const-value-changed",
+            ChangeKind::FieldRemoved => "field-removed",
+            ChangeKind::FieldTypeChanged => "field-type-changed",
+            ChangeKind::OrdinalChanged => "ordinal-changed",
+        }
+    }
+}
+
+impl std::fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A change between two versions of a mojom file.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The qualified name in the old file, e.g. `MyInterface.MyMethod`. The
+    /// module name isn't included.
+    pub name: String,
+    pub message: String,
+    /// The name of the declaration in the old file.
+    pub old: syntax::Range,
+    /// The name of the corresponding declaration in the new file. None when
+    /// it was removed.
+    pub new: Option<syntax::Range>,
+}
+
+enum Declaration<'a> {
+    Interface(&'a Interface),
+    Struct(&'a Struct),
+    Union(&'a Union),
+    Enum(&'a Enum),
+    Const(&'a Const),
+}
+
+impl<'a> Declaration<'a> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Declaration::Interface(_) => "interface",
+            Declaration::Struct(_) => "struct",
+            Declaration::Union(_) => "union",
+            Declaration::Enum(_) => "enum",
+            Declaration::Const(_) => "const",
+        }
+    }
+
+    fn name(&self) -> &'a syntax::Range {
+        match self {
+            Declaration::Interface(node) => &node.name,
+            Declaration::Struct(node) => &node.name,
+            Declaration::Union(node) => &node.name,
+            Declaration::Enum(node) => &node.name,
+            Declaration::Const(node) => &node.name,
+        }
+    }
+}
+
+// Declarations of a file in the order of appearance, keyed by their
+// qualified names.
+struct Declarations<'a> {
+    text: &'a str,
+    declarations: Vec<(String, Declaration<'a>)>,
+    index: HashMap<String, usize>,
+}
+
+impl<'a> Declarations<'a> {
+    fn new(text: &'a str, mojom: &'a MojomFile) -> Self {
+        let mut declarations = Vec::new();
+        let mut scope: Vec<&str> = Vec::new();
+        let qualify = |scope: &[&str], name: &syntax::Range| {
+            let mut path = scope.to_vec();
+            path.push(partial_text(text, name));
+            path.join(".")
+        };
+        for traversal in syntax::preorder(mojom) {
+            let declaration = match traversal {
+                Traversal::EnterInterface(node) => {
+                    declarations.push((qualify(&scope, &node.name), Declaration::Interface(node)));
+                    scope.push(partial_text(text, &node.name));
+                    continue;
+                }
+                Traversal::EnterStruct(node) => {
+                    declarations.push((qualify(&scope, &node.name), Declaration::Struct(node)));
+                    scope.push(partial_text(text, &node.name));
+                    continue;
+                }
+                Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                    scope.pop();
+                    continue;
+                }
+                Traversal::Union(node) => Declaration::Union(node),
+                Traversal::Enum(node) => Declaration::Enum(node),
+                Traversal::Const(node) => Declaration::Const(node),
+                _ => continue,
+            };
+            declarations.push((qualify(&scope, declaration.name()), declaration));
+        }
+        let mut index = HashMap::new();
+        for (i, (name, _)) in declarations.iter().enumerate() {
+            index.entry(name.clone()).or_insert(i);
+        }
+        Declarations {
+            text,
+            declarations,
+            index,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Declaration<'a>> {
+        self.index.get(name).map(|&i| &self.declarations[i].1)
+    }
+}
+
+// A struct field, a union field or a method with its effective ordinal.
+struct Member<'a, T> {
+    node: &'a T,
+    name: &'a str,
+    ordinal: u32,
+}
+
+fn members<'a, T>(
+    text: &'a str,
+    nodes: Vec<&'a T>,
+    name: impl Fn(&'a T) -> &'a syntax::Range,
+    ordinal: impl Fn(&'a T) -> Option<&'a syntax::Range>,
+) -> Vec<Member<'a, T>> {
+    let ordinals = nodes.iter().map(|&node| ordinal(node)).collect::<Vec<_>>();
+    nodes
+        .into_iter()
+        .zip(effective_ordinals(text, &ordinals))
+        .map(|(node, ordinal)| Member {
+            node,
+            name: partial_text(text, name(node)),
+            ordinal,
+        })
+        .collect()
+}
+
+fn struct_fields<'a>(text: &'a str, node: &'a Struct) -> Vec<Member<'a, StructField>> {
+    let nodes = node
+        .members
+        .iter()
+        .filter_map(|member| match member {
+            StructBody::Field(field) => Some(field),
+            _ => None,
+        })
+        .collect();
+    members(
+        text,
+        nodes,
+        |field| &field.name,
+        |field| field.ordinal.as_ref(),
+    )
+}
+
+fn union_fields<'a>(text: &'a str, node: &'a Union) -> Vec<Member<'a, UnionField>> {
+    let nodes = node.fields.iter().collect();
+    members(
+        text,
+        nodes,
+        |field| &field.name,
+        |field| field.ordinal.as_ref(),
+    )
+}
+
+fn interface_methods<'a>(text: &'a str, node: &'a Interface) -> Vec<Member<'a, Method>> {
+    let nodes = node
+        .members
+        .iter()
+        .filter_map(|member| match member {
+            InterfaceMember::Method(method) => Some(method),
+            _ => None,
+        })
+        .collect();
+    members(
+        text,
+        nodes,
+        |method| &method.name,
+        |method| method.ordinal.as_ref(),
+    )
+}
+
+// Formats parameter types like `(int32, string?)`. Parameter names don't
+// affect compatibility.
+fn signature(text: &str, params: &[Parameter]) -> String {
+    let types = params
+        .iter()
+        .map(|param| partial_text(text, &param.typ))
+        .collect::<Vec<_>>();
+    format!("({})", types.join(", "))
+}
+
+struct Differ<'a> {
+    old: &'a Declarations<'a>,
+    new: &'a Declarations<'a>,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn push(
+        &mut self,
+        kind: ChangeKind,
+        name: String,
+        old: &syntax::Range,
+        new: Option<&syntax::Range>,
+        message: String,
+    ) {
+        self.changes.push(Change {
+            kind,
+            name,
+            message,
+            old: *old,
+            new: new.cloned(),
+        });
+    }
+
+    fn diff_declaration(&mut self, name: &str, old: &Declaration, new: &Declaration) {
+        match (old, new) {
+            (Declaration::Interface(old), Declaration::Interface(new)) => {
+                self.diff_interface(name, old, new)
+            }
+            (Declaration::Struct(old), Declaration::Struct(new)) => {
+                self.diff_struct(name, old, new)
+            }
+            (Declaration::Union(old), Declaration::Union(new)) => self.diff_union(name, old, new),
+            (Declaration::Enum(old), Declaration::Enum(new)) => self.diff_enum(name, old, new),
+            (Declaration::Const(old), Declaration::Const(new)) => self.diff_const(name, old, new),
+            _ => {
+                let message = format!("`{}` changed from {} to {}", name, old.kind(), new.kind());
+                self.push(
+                    ChangeKind::KindChanged,
+                    name.to_owned(),
+                    old.name(),
+                    Some(new.name()),
+                    message,
+                );
+            }
+        }
+    }
+
+    fn diff_const(&mut self, name: &str, old: &Const, new: &Const) {
+        let old_value = partial_text(self.old.text, &old.value);
+        let new_value = partial_text(self.new.text, &new.value);
+        if !same_text(old_value, new_value) {
+            let message = format!(
+                "Value of `{}` changed from `{}` to `{}`",
+                name, old_value, new_value
+            );
+            let kind = ChangeKind::ConstValueChanged;
+            self.push(kind, name.to_owned(), &old.name, Some(&new.name), message);
+        }
+    }
+
+    fn diff_enum(&mut self, name: &str, old: &Enum, new: &Enum) {
+        for value in &old.values {
+            let value_name = partial_text(self.old.text, &value.name);
+            let found = new
+                .values
+                .iter()
+                .any(|new_value| partial_text(self.new.text, &new_value.name) == value_name);
+            if !found {
+                let qualified = format!("{}.{}", name, value_name);
+                let message = format!("Enum value `{}` was removed", qualified);
+                let kind = ChangeKind::EnumValueRemoved;
+                self.push(kind, qualified, &value.name, None, message);
+            }
+        }
+    }
+
+    // Compares fields of structs or unions. `typ_and_name` returns the type
+    // and the name of a field.
+    fn diff_fields<T>(
+        &mut self,
+        name: &str,
+        old: Vec<Member<T>>,
+        new: Vec<Member<T>>,
+        typ_and_name: impl Fn(&T) -> (&syntax::Range, &syntax::Range),
+    ) {
+        for old_field in &old {
+            let qualified = format!("{}.{}", name, old_field.name);
+            let (old_type, old_name) = typ_and_name(old_field.node);
+            let new_field = match new.iter().find(|field| field.name == old_field.name) {
+                Some(field) => field,
+                None => {
+                    let message = format!("Field `{}` was removed", qualified);
+                    self.push(ChangeKind::FieldRemoved, qualified, old_name, None, message);
+                    continue;
+                }
+            };
+            let (new_type, new_name) = typ_and_name(new_field.node);
+            let old_type = partial_text(self.old.text, old_type);
+            let new_type = partial_text(self.new.text, new_type);
+            if !same_text(old_type, new_type) {
+                let message = format!(
+                    "Type of `{}` changed from `{}` to `{}`",
+                    qualified, old_type, new_type
+                );
+                let kind = ChangeKind::FieldTypeChanged;
+                self.push(kind, qualified.clone(), old_name, Some(new_name), message);
+            }
+            if old_field.ordinal != new_field.ordinal {
+                let message = format!(
+                    "Ordinal of `{}` changed from @{} to @{}",
+                    qualified, old_field.ordinal, new_field.ordinal
+                );
+                let kind = ChangeKind::OrdinalChanged;
+                self.push(kind, qualified, old_name, Some(new_name), message);
+            }
+        }
+    }
+
+    fn diff_struct(&mut self, name: &str, old: &Struct, new: &Struct) {
+        let old = struct_fields(self.old.text, old);
+        let new = struct_fields(self.new.text, new);
+        self.diff_fields(name, old, new, |field| (&field.typ, &field.name));
+    }
+
+    fn diff_union(&mut self, name: &str, old: &Union, new: &Union) {
+        let old = union_fields(self.old.text, old);
+        let new = union_fields(self.new.text, new);
+        self.diff_fields(name, old, new, |field| (&field.typ, &field.name));
+    }
+
+    fn diff_interface(&mut self, name: &str, old: &Interface, new: &Interface) {
+        let old = interface_methods(self.old.text, old);
+        let new = interface_methods(self.new.text, new);
+
+        for old_method in &old {
+            let qualified = format!("{}.{}", name, old_method.name);
+            let old_name = &old_method.node.name;
+            if let Some(new_method) = new.iter().find(|m| m.name == old_method.name) {
+                self.diff_method(&qualified, old_method, new_method);
+                continue;
+            }
+            // A new method that took the ordinal of the removed method.
+            let renamed = new.iter().find(|m| {
+                m.ordinal == old_method.ordinal && !old.iter().any(|old| old.name == m.name)
+            });
+            match renamed {
+                Some(new_method) => {
+                    let message = format!(
+                        "Method `{}` was renamed to `{}`",
+                        qualified, new_method.name
+                    );
+                    let new_name = Some(&new_method.node.name);
+                    self.push(
+                        ChangeKind::MethodRenamed,
+                        qualified,
+                        old_name,
+                        new_name,
+                        message,
+                    );
+                }
+                None => {
+                    let message = format!("Method `{}` was removed", qualified);
+                    self.push(
+                        ChangeKind::MethodRemoved,
+                        qualified,
+                        old_name,
+                        None,
+                        message,
+                    );
+                }
+            }
+        }
+    }
+
+    fn diff_method(&mut self, name: &str, old: &Member<Method>, new: &Member<Method>) {
+        let old_name = &old.node.name;
+        let new_name = &new.node.name;
+        let old_response = old.node.response.as_ref().map(|res| &res.params[..]);
+        let new_response = new.node.response.as_ref().map(|res| &res.params[..]);
+        let param_lists = [
+            (
+                "Parameters",
+                Some(&old.node.params[..]),
+                Some(&new.node.params[..]),
+            ),
+            ("Response parameters", old_response, new_response),
+        ];
+        for (what, old_params, new_params) in param_lists.iter() {
+            let old_params = old_params.map(|params| signature(self.old.text, params));
+            let new_params = new_params.map(|params| signature(self.new.text, params));
+            let message = match (old_params, new_params) {
+                (Some(old), Some(new)) if !same_text(&old, &new) => {
+                    format!("{} of `{}` changed from `{}` to `{}`", what, name, old, new)
+                }
+                (Some(_), None) => format!("Response of `{}` was removed", name),
+                (None, Some(_)) => format!("Response of `{}` was added", name),
+                _ => continue,
+            };
+            let kind = ChangeKind::ParametersChanged;
+            self.push(kind, name.to_owned(), old_name, Some(new_name), message);
+        }
+        if old.ordinal != new.ordinal {
+            let message = format!(
+                "Ordinal of `{}` changed from @{} to @{}",
+                name, old.ordinal, new.ordinal
+            );
+            let kind = ChangeKind::OrdinalChanged;
+            self.push(kind, name.to_owned(), old_name, Some(new_name), message);
+        }
+    }
+}
+
+/// Compares `old` and `new`, which are parsed from `old_text` and
+/// `new_text`. Declarations are matched by their qualified names. Changes are
+/// ordered as the declarations in `old`.
+pub fn diff_mojom(old: &MojomFile, old_text: &str, new: &MojomFile, new_text: &str) -> Vec<Change> {
+    let old = Declarations::new(old_text, old);
+    let new = Declarations::new(new_text, new);
+    let mut differ = Differ {
+        old: &old,
+        new: &new,
+        changes: Vec::new(),
+    };
+    for (name, old_declaration) in &old.declarations {
+        match new.get(name) {
+            Some(new_declaration) => {
+                differ.diff_declaration(name, old_declaration, new_declaration)
+            }
+            None => {
+                let message = format!("`{}` ({}) was removed", name, old_declaration.kind());
+                let kind = ChangeKind::Removed;
+                differ.push(kind, name.clone(), old_declaration.name(), None, message);
+            }
+        }
+    }
+    differ.changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> Vec<(ChangeKind, String, String)> {
+        let old_mojom = syntax::parse(old).unwrap();
+        let new_mojom = syntax::parse(new).unwrap();
+        diff_mojom(&old_mojom, old, &new_mojom, new)
+            .into_iter()
+            .map(|change| (change.kind, change.name, change.message))
+            .collect()
+    }
+
+    fn kinds(old: &str, new: &str) -> Vec<(ChangeKind, String)> {
+        diff(old, new)
+            .into_iter()
+            .map(|(kind, name, _)| (kind, name))
+            .collect()
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let old = r#"
+module foo;
+interface Foo { Bar(map<string,int32> x) => (bool ok); };
+struct S { int32 a; const int32 kMax = 1; };
+"#;
+        // Additions, parameter names and whitespace aren't changes.
+        let new = r#"
+module foo;
+interface Foo {
+  Bar(map<string, int32> y) => (bool success);
+  Baz();
+};
+struct S { int32 a; const int32 kMax = 1; string b; };
+struct T {};
+"#;
+        assert!(diff(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_removed_declarations() {
+        let old = r#"
+struct S { enum E { A }; };
+union U { int32 a; };
+const int32 kFoo = 1;
+"#;
+        let new = r#"
+struct S {};
+interface U {};
+"#;
+        assert_eq!(
+            vec![
+                (
+                    ChangeKind::Removed,
+                    "S.E".to_owned(),
+                    "`S.E` (enum) was removed".to_owned()
+                ),
+                (
+                    ChangeKind::KindChanged,
+                    "U".to_owned(),
+                    "`U` changed from union to interface".to_owned()
+                ),
+                (
+                    ChangeKind::Removed,
+                    "kFoo".to_owned(),
+                    "`kFoo` (const) was removed".to_owned()
+                ),
+            ],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_methods() {
+        let old = r#"
+interface Foo {
+  A();
+  B@5();
+  C();
+};
+"#;
+        let new = r#"
+interface Foo {
+  A();
+  NewB@5();
+};
+"#;
+        assert_eq!(
+            vec![
+                (
+                    ChangeKind::MethodRenamed,
+                    "Foo.B".to_owned(),
+                    "Method `Foo.B` was renamed to `NewB`".to_owned()
+                ),
+                (
+                    ChangeKind::MethodRemoved,
+                    "Foo.C".to_owned(),
+                    "Method `Foo.C` was removed".to_owned()
+                ),
+            ],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let old = r#"
+interface Foo {
+  A(int32 x, string? y);
+  B() => (bool ok);
+  C();
+  D() => (int32 x);
+};
+"#;
+        let new = r#"
+interface Foo {
+  A(int32 x);
+  B();
+  C() => ();
+  D() => (int64 x);
+};
+"#;
+        assert_eq!(
+            vec![
+                (
+                    ChangeKind::ParametersChanged,
+                    "Foo.A".to_owned(),
+                    "Parameters of `Foo.A` changed from `(int32, string?)` to `(int32)`".to_owned()
+                ),
+                (
+                    ChangeKind::ParametersChanged,
+                    "Foo.B".to_owned(),
+                    "Response of `Foo.B` was removed".to_owned()
+                ),
+                (
+                    ChangeKind::ParametersChanged,
+                    "Foo.C".to_owned(),
+                    "Response of `Foo.C` was added".to_owned()
+                ),
+                (
+                    ChangeKind::ParametersChanged,
+                    "Foo.D".to_owned(),
+                    "Response parameters of `Foo.D` changed from `(int32)` to `(int64)`".to_owned()
+                ),
+            ],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_enum_values() {
+        let old = "enum E { A, B, C = 5 };";
+        let new = "enum E { A, C = 5, D };";
+        assert_eq!(
+            vec![(
+                ChangeKind::EnumValueRemoved,
+                "E.B".to_owned(),
+                "Enum value `E.B` was removed".to_owned()
+            )],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_const_values() {
+        let old = r#"
+const int32 kA = 1;
+const string kB = "b";
+interface Foo { const uint8 kC = 0x10; };
+"#;
+        let new = r#"
+const int32 kA = 2;
+const string kB = "b";
+interface Foo { const uint8 kC = 16; };
+"#;
+        assert_eq!(
+            vec![
+                (
+                    ChangeKind::ConstValueChanged,
+                    "kA".to_owned(),
+                    "Value of `kA` changed from `1` to `2`".to_owned()
+                ),
+                (
+                    ChangeKind::ConstValueChanged,
+                    "Foo.kC".to_owned(),
+                    "Value of `Foo.kC` changed from `0x10` to `16`".to_owned()
+                ),
+            ],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_fields() {
+        let old = r#"
+struct S { int32 a@0; string b@1; array<int8> c@2; };
+union U { int32 a; string b; };
+"#;
+        let new = r#"
+struct S { int32 a@0; array<uint8> c@2; };
+union U { int64 a; string b; };
+"#;
+        assert_eq!(
+            vec![
+                (
+                    ChangeKind::FieldRemoved,
+                    "S.b".to_owned(),
+                    "Field `S.b` was removed".to_owned()
+                ),
+                (
+                    ChangeKind::FieldTypeChanged,
+                    "S.c".to_owned(),
+                    "Type of `S.c` changed from `array<int8>` to `array<uint8>`".to_owned()
+                ),
+                (
+                    ChangeKind::FieldTypeChanged,
+                    "U.a".to_owned(),
+                    "Type of `U.a` changed from `int32` to `int64`".to_owned()
+                ),
+            ],
+            diff(old, new)
+        );
+    }
+
+    #[test]
+    fn test_ordinals() {
+        let old = r#"
+struct S { int32 a; int32 b; };
+union U { int32 a@0; string b@1; };
+interface Foo { A(); B(); };
+"#;
+        // Implicit ordinals follow declaration order.
+        let new = r#"
+struct S { int32 b; int32 a; };
+union U { int32 a@0; string b@2; };
+interface Foo { A@0(); B@1(); };
+"#;
+        assert_eq!(
+            vec![
+                (ChangeKind::OrdinalChanged, "S.a".to_owned()),
+                (ChangeKind::OrdinalChanged, "S.b".to_owned()),
+                (ChangeKind::OrdinalChanged, "U.b".to_owned()),
+            ],
+            kinds(old, new)
+        );
+        assert_eq!(
+            "Ordinal of `S.a` changed from @0 to @1",
+            diff(old, new)[0].2
+        );
+    }
+
+    #[test]
+    fn test_change_ranges() {
+        let old = "struct S { int32 a; };";
+        let new = "\nstruct S { int64 a; };";
+        let old_mojom = syntax::parse(old).unwrap();
+        let new_mojom = syntax::parse(new).unwrap();
+        let changes = diff_mojom(&old_mojom, old, &new_mojom, new);
+        assert_eq!(1, changes.len());
+        assert_eq!("a", &old[changes[0].old.start..changes[0].old.end]);
+        let new_range = changes[0].new.as_ref().unwrap();
+        assert_eq!("a", &new[new_range.start..new_range.end]);
+        assert_eq!("field-type-changed", changes[0].kind.as_str());
+    }
+}
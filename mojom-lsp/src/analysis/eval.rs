@@ -0,0 +1,561 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluation of integer constants, i.e. enum values and `const`
+//! initializers.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::syntax::{self, preorder, MojomFile, Traversal};
+
+use super::diagnostic::{Diagnostic, Severity};
+
+const ENUM_VALUE_DUPLICATE_CODE: &str = "mojom/enum-value-duplicate";
+const VALUE_OUT_OF_RANGE_CODE: &str = "mojom/value-out-of-range";
+const UNKNOWN_VALUE_CODE: &str = "mojom/unknown-value";
+
+fn partial_text<'a>(text: &'a str, range: &syntax::Range) -> &'a str {
+    &text[range.start..range.end]
+}
+
+fn qualify(scope: &[&str], name: &str) -> String {
+    let mut qualified = scope.join(".");
+    if !qualified.is_empty() {
+        qualified.push('.');
+    }
+    qualified.push_str(name);
+    qualified
+}
+
+// Parses an integer literal like `42`, `-1` or `0x10`. Returns None for other
+// literals such as floats, strings and booleans.
+fn parse_integer(literal: &str) -> Option<i128> {
+    let (negative, digits) = match literal.as_bytes().first()? {
+        b'-' => (true, &literal[1..]),
+        b'+' => (false, &literal[1..]),
+        _ => (false, literal),
+    };
+    let value = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None if !digits.is_empty() && digits.bytes().all(|ch| ch.is_ascii_digit()) => {
+            digits.parse::<i128>().ok()?
+        }
+        None => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// Returns true when `value` is an identifier rather than a literal.
+fn is_identifier(value: &str) -> bool {
+    let starts_with_alpha = value
+        .bytes()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == b'_');
+    starts_with_alpha && !matches!(value, "true" | "false" | "default")
+}
+
+// The range of values of builtin integer types.
+fn integer_range(type_name: &str) -> Option<(i128, i128)> {
+    let range = match type_name {
+        "int8" => (i8::MIN as i128, i8::MAX as i128),
+        "uint8" => (0, u8::MAX as i128),
+        "int16" => (i16::MIN as i128, i16::MAX as i128),
+        "uint16" => (0, u16::MAX as i128),
+        "int32" => (i32::MIN as i128, i32::MAX as i128),
+        "uint32" => (0, u32::MAX as i128),
+        "int64" => (i64::MIN as i128, i64::MAX as i128),
+        "uint64" => (0, u64::MAX as i128),
+        _ => return None,
+    };
+    Some(range)
+}
+
+// A declaration that has a value.
+enum Declaration<'a> {
+    Const {
+        scope: Vec<&'a str>,
+        value: &'a syntax::Range,
+    },
+    EnumValue {
+        // The enclosing scopes and the name of the enum.
+        scope: Vec<&'a str>,
+        value: Option<&'a syntax::Range>,
+        // The qualified name of the previous value of the enum.
+        previous: Option<String>,
+    },
+}
+
+// How an identifier in a value resolves.
+enum Resolution {
+    Found(String),
+    // Nothing is declared with the name.
+    Unknown,
+    // The name may be declared in an imported file.
+    External,
+}
+
+struct Evaluator<'a> {
+    text: &'a str,
+    module: Option<&'a str>,
+    // Declarations keyed by their qualified names.
+    declarations: HashMap<String, Declaration<'a>>,
+    // The first segments of qualified names that are declared in the
+    // document, e.g. `MyEnum` of `MyEnum.kValue`.
+    containers: Vec<&'a str>,
+    // Evaluated values. None is recorded while a value is evaluated so that
+    // cyclic references evaluate to None.
+    values: HashMap<String, Option<i128>>,
+    // Enum values whose implicit values overflow i128, i.e. that follow a
+    // value of i128::MAX or another overflowed value. They evaluate to None.
+    overflowed: HashSet<String>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(text: &'a str, mojom: &'a MojomFile) -> Self {
+        let mut module = None;
+        let mut declarations = HashMap::new();
+        let mut containers = Vec::new();
+        let mut scope = Vec::new();
+        let mut previous = None;
+        for traversal in preorder(mojom) {
+            match traversal {
+                Traversal::Module(node) => {
+                    module.get_or_insert(partial_text(text, &node.name));
+                }
+                Traversal::EnterInterface(node) => {
+                    scope.push(partial_text(text, &node.name));
+                }
+                Traversal::EnterStruct(node) => {
+                    scope.push(partial_text(text, &node.name));
+                }
+                Traversal::Enum(node) => {
+                    scope.push(partial_text(text, &node.name));
+                    previous = None;
+                }
+                Traversal::Feature(node) => {
+                    scope.push(partial_text(text, &node.name));
+                }
+                Traversal::LeaveInterface(_)
+                | Traversal::LeaveStruct(_)
+                | Traversal::LeaveEnum(_)
+                | Traversal::LeaveFeature(_) => {
+                    scope.pop();
+                }
+                Traversal::Const(node) => {
+                    let name = qualify(&scope, partial_text(text, &node.name));
+                    let declaration = Declaration::Const {
+                        scope: scope.clone(),
+                        value: &node.value,
+                    };
+                    declarations.entry(name).or_insert(declaration);
+                }
+                Traversal::EnumValue(node) => {
+                    let name = qualify(&scope, partial_text(text, &node.name));
+                    let declaration = Declaration::EnumValue {
+                        scope: scope.clone(),
+                        value: node.value.as_ref(),
+                        previous: previous.replace(name.clone()),
+                    };
+                    declarations.entry(name).or_insert(declaration);
+                }
+                _ => continue,
+            }
+            if scope.len() == 1 && !containers.contains(&scope[0]) {
+                containers.push(scope[0]);
+            }
+        }
+        Evaluator {
+            text,
+            module,
+            declarations,
+            containers,
+            values: HashMap::new(),
+            overflowed: HashSet::new(),
+        }
+    }
+
+    // Resolves `ident` that appears in `scope`. Inner scopes are searched
+    // first. Names may be qualified with the module name.
+    fn resolve(&self, scope: &[&str], ident: &str) -> Resolution {
+        let local_ident = self
+            .module
+            .and_then(|module| ident.strip_prefix(module)?.strip_prefix('.'));
+        let ident = local_ident.unwrap_or(ident);
+        for depth in (0..=scope.len()).rev() {
+            let qualified = qualify(&scope[..depth], ident);
+            if self.declarations.contains_key(&qualified) {
+                return Resolution::Found(qualified);
+            }
+        }
+        // Qualified names whose containers aren't declared here may refer to
+        // imported declarations.
+        match ident.split_once('.') {
+            Some((first, _)) if local_ident.is_none() && !self.containers.contains(&first) => {
+                Resolution::External
+            }
+            _ => Resolution::Unknown,
+        }
+    }
+
+    // Evaluates `value`, which is a literal or an identifier in `scope`.
+    fn eval_value(&mut self, scope: &[&str], value: &syntax::Range) -> Option<i128> {
+        let value = partial_text(self.text, value);
+        if !is_identifier(value) {
+            return parse_integer(value);
+        }
+        match self.resolve(scope, value) {
+            Resolution::Found(name) => self.eval(&name),
+            _ => None,
+        }
+    }
+
+    // Evaluates the declaration of `name`. Returns None when the value isn't
+    // an integer or can't be evaluated.
+    fn eval(&mut self, name: &str) -> Option<i128> {
+        if let Some(&value) = self.values.get(name) {
+            return value;
+        }
+        self.values.insert(name.to_owned(), None);
+        let value = match self.declarations.get(name)? {
+            Declaration::Const { scope, value } => {
+                let (scope, value) = (scope.clone(), *value);
+                self.eval_value(&scope, value)
+            }
+            Declaration::EnumValue {
+                scope,
+                value: Some(value),
+                ..
+            } => {
+                let (scope, value) = (scope.clone(), *value);
+                self.eval_value(&scope, value)
+            }
+            Declaration::EnumValue { previous, .. } => match previous.clone() {
+                Some(previous) => {
+                    let value = self.eval(&previous);
+                    let next = value.and_then(|value| value.checked_add(1));
+                    let overflowed = value.is_some() || self.overflowed.contains(&previous);
+                    if next.is_none() && overflowed {
+                        self.overflowed.insert(name.to_owned());
+                    }
+                    next
+                }
+                None => Some(0),
+            },
+        };
+        self.values.insert(name.to_owned(), value);
+        value
+    }
+
+    // Reports `value` when it is an identifier that isn't declared.
+    fn check_reference(
+        &self,
+        scope: &[&str],
+        value: &syntax::Range,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let ident = partial_text(self.text, value);
+        if is_identifier(ident) && matches!(self.resolve(scope, ident), Resolution::Unknown) {
+            let message = format!("Unknown value `{}`", ident);
+            diagnostics.push(Diagnostic::error(UNKNOWN_VALUE_CODE, *value, message));
+        }
+    }
+}
+
+// Checks the values of `node`. Values are int32. Values that refer to other
+// values, like `kMaxValue = kLast`, are aliases and may duplicate them.
+fn check_enum_values(
+    evaluator: &mut Evaluator,
+    scope: &[&str],
+    node: &syntax::Enum,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let text = evaluator.text;
+    let (min, max) = integer_range("int32").unwrap();
+    let mut seen: HashMap<i128, &syntax::Range> = HashMap::new();
+    for enum_value in &node.values {
+        let name = partial_text(text, &enum_value.name);
+        if let Some(value) = &enum_value.value {
+            evaluator.check_reference(scope, value, diagnostics);
+        }
+        let qualified = qualify(scope, name);
+        let value = match evaluator.eval(&qualified) {
+            Some(value) => value,
+            None if evaluator.overflowed.contains(&qualified) => {
+                let message = format!(
+                    "Enum value `{}` overflows, which is out of the range of int32",
+                    name
+                );
+                diagnostics.push(Diagnostic::error(
+                    VALUE_OUT_OF_RANGE_CODE,
+                    enum_value.name,
+                    message,
+                ));
+                continue;
+            }
+            None => continue,
+        };
+        let range = enum_value.value.as_ref().unwrap_or(&enum_value.name);
+        if value < min || value > max {
+            let message = format!(
+                "Enum value `{}` is {}, which is out of the range of int32",
+                name, value
+            );
+            diagnostics.push(Diagnostic::error(VALUE_OUT_OF_RANGE_CODE, *range, message));
+            continue;
+        }
+        let is_alias = enum_value
+            .value
+            .as_ref()
+            .is_some_and(|value| is_identifier(partial_text(text, value)));
+        if is_alias {
+            continue;
+        }
+        match seen.get(&value) {
+            Some(first) => {
+                let first_name = partial_text(text, first);
+                let message = format!(
+                    "Enum value `{}` is {}, which is the same as `{}`",
+                    name, value, first_name
+                );
+                let diagnostic = Diagnostic::new(
+                    Severity::Warning,
+                    ENUM_VALUE_DUPLICATE_CODE,
+                    *range,
+                    message,
+                )
+                .with_related(**first, format!("`{}` is {}", first_name, value));
+                diagnostics.push(diagnostic);
+            }
+            None => {
+                seen.insert(value, &enum_value.name);
+            }
+        }
+    }
+}
+
+// Checks that `node` is initialized with a value in the range of its type.
+fn check_const(
+    evaluator: &mut Evaluator,
+    scope: &[&str],
+    node: &syntax::Const,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let text = evaluator.text;
+    evaluator.check_reference(scope, &node.value, diagnostics);
+    let type_name = partial_text(text, &node.typ);
+    let (min, max) = match integer_range(type_name) {
+        Some(range) => range,
+        None => return,
+    };
+    let name = qualify(scope, partial_text(text, &node.name));
+    match evaluator.eval(&name) {
+        Some(value) if value < min || value > max => {
+            let message = format!(
+                "Value {} is out of the range of {} ({} to {})",
+                value, type_name, min, max
+            );
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                VALUE_OUT_OF_RANGE_CODE,
+                node.value,
+                message,
+            ));
+        }
+        _ => (),
+    }
+}
+
+// Evaluates the values of `node`. `scope` is the enclosing scopes and the
+// name of the enum. Values that can't be evaluated, e.g. references to
+// imported values, are None.
+pub(crate) fn eval_enum_values(
+    text: &str,
+    mojom: &MojomFile,
+    scope: &[&str],
+    node: &syntax::Enum,
+) -> Vec<Option<i128>> {
+    let mut evaluator = Evaluator::new(text, mojom);
+    node.values
+        .iter()
+        .map(|enum_value| evaluator.eval(&qualify(scope, partial_text(text, &enum_value.name))))
+        .collect()
+}
+
+// Evaluates enum values and integer consts. Reports duplicate enum values,
+// values out of the range of their types and references to unknown values.
+// References that may be declared in imported files aren't evaluated.
+pub(crate) fn check_values(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<Diagnostic>) {
+    let mut evaluator = Evaluator::new(text, mojom);
+    let mut scope = Vec::new();
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => scope.push(partial_text(text, &node.name)),
+            Traversal::EnterStruct(node) => scope.push(partial_text(text, &node.name)),
+            Traversal::Feature(node) => scope.push(partial_text(text, &node.name)),
+            Traversal::Enum(node) => {
+                scope.push(partial_text(text, &node.name));
+                check_enum_values(&mut evaluator, &scope, node, diagnostics);
+            }
+            Traversal::LeaveInterface(_)
+            | Traversal::LeaveStruct(_)
+            | Traversal::LeaveEnum(_)
+            | Traversal::LeaveFeature(_) => {
+                scope.pop();
+            }
+            Traversal::Const(node) => check_const(&mut evaluator, &scope, node, diagnostics),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let mojom = syntax::parse(text).unwrap();
+        let mut diagnostics = Vec::new();
+        check_values(text, &mojom, &mut diagnostics);
+        diagnostics
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<&'static str> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(Some(42), parse_integer("42"));
+        assert_eq!(Some(-1), parse_integer("-1"));
+        assert_eq!(Some(1), parse_integer("+1"));
+        assert_eq!(Some(255), parse_integer("0xFF"));
+        assert_eq!(Some(-16), parse_integer("-0X10"));
+        assert_eq!(None, parse_integer("1.5"));
+        assert_eq!(None, parse_integer("\"str\""));
+        assert_eq!(None, parse_integer("true"));
+        assert_eq!(None, parse_integer("-"));
+    }
+
+    #[test]
+    fn test_duplicate_enum_values() {
+        let text = "enum Foo { kA = 1, kB, kC = 2 };";
+        let diagnostics = check(text);
+        assert_eq!(vec![ENUM_VALUE_DUPLICATE_CODE], codes(&diagnostics));
+        let diagnostic = &diagnostics[0];
+        assert_eq!("2", partial_text(text, &diagnostic.range));
+        assert_eq!(
+            "Enum value `kC` is 2, which is the same as `kB`",
+            diagnostic.message
+        );
+        assert_eq!(
+            "kB",
+            partial_text(text, &diagnostic.related.as_ref().unwrap().range)
+        );
+
+        // Aliases may duplicate other values.
+        assert!(check("enum Foo { kA, kB, kMaxValue = kB };").is_empty());
+        assert!(check("enum Foo { kA = 0x10, kB = -1, kC = 0 };").is_empty());
+    }
+
+    #[test]
+    fn test_enum_value_references() {
+        let text = r#"
+            const int32 kTopLevel = 10;
+            interface Foo {
+                const int32 kMember = 20;
+                enum Bar {
+                    kA = kTopLevel,
+                    kB = kMember,
+                    kC,
+                    kD = kA,
+                    kE = Foo.kMember,
+                    kF = kUnknown,
+                    kG = imported.kValue,
+                    kH = Foo.kUnknown,
+                };
+            };
+        "#;
+        let diagnostics = check(text);
+        assert_eq!(vec![UNKNOWN_VALUE_CODE; 2], codes(&diagnostics));
+        assert_eq!("kUnknown", partial_text(text, &diagnostics[0].range));
+        assert_eq!("Foo.kUnknown", partial_text(text, &diagnostics[1].range));
+
+        let mojom = syntax::parse(text).unwrap();
+        let mut evaluator = Evaluator::new(text, &mojom);
+        assert_eq!(Some(10), evaluator.eval("Foo.Bar.kA"));
+        assert_eq!(Some(20), evaluator.eval("Foo.Bar.kB"));
+        assert_eq!(Some(21), evaluator.eval("Foo.Bar.kC"));
+        assert_eq!(Some(10), evaluator.eval("Foo.Bar.kD"));
+        assert_eq!(Some(20), evaluator.eval("Foo.Bar.kE"));
+        assert_eq!(None, evaluator.eval("Foo.Bar.kG"));
+
+        // Cycles don't evaluate.
+        let text = "enum Foo { kA = kB, kB = kA };";
+        assert!(check(text).is_empty());
+    }
+
+    #[test]
+    fn test_values_out_of_range() {
+        let text = r#"
+            enum Foo { kA = 0x7FFFFFFF, kB, kC = -0x80000001 };
+            const uint8 kByte = 300;
+            const int8 kNegative = -128;
+            const uint32 kRef = kNegative;
+            const float kFloat = 1.5;
+            const string kString = "300";
+        "#;
+        let diagnostics = check(text);
+        assert_eq!(
+            vec![
+                VALUE_OUT_OF_RANGE_CODE,
+                VALUE_OUT_OF_RANGE_CODE,
+                VALUE_OUT_OF_RANGE_CODE,
+                VALUE_OUT_OF_RANGE_CODE
+            ],
+            codes(&diagnostics)
+        );
+        assert_eq!(Severity::Error, diagnostics[0].severity);
+        assert_eq!("kB", partial_text(text, &diagnostics[0].range));
+        assert_eq!("-0x80000001", partial_text(text, &diagnostics[1].range));
+        assert_eq!(Severity::Warning, diagnostics[2].severity);
+        assert_eq!("300", partial_text(text, &diagnostics[2].range));
+        assert_eq!(
+            "Value 300 is out of the range of uint8 (0 to 255)",
+            diagnostics[2].message
+        );
+        assert_eq!("kNegative", partial_text(text, &diagnostics[3].range));
+    }
+
+    #[test]
+    fn test_implicit_value_overflow() {
+        let text = "enum Foo { kA = 0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF, kB, kC };";
+        let diagnostics = check(text);
+        assert_eq!(vec![VALUE_OUT_OF_RANGE_CODE; 3], codes(&diagnostics));
+        assert_eq!("kB", partial_text(text, &diagnostics[1].range));
+        assert_eq!(
+            "Enum value `kB` overflows, which is out of the range of int32",
+            diagnostics[1].message
+        );
+        // Values that follow an overflowed value overflow too.
+        assert_eq!("kC", partial_text(text, &diagnostics[2].range));
+
+        let mojom = syntax::parse(text).unwrap();
+        let mut evaluator = Evaluator::new(text, &mojom);
+        assert_eq!(Some(i128::MAX), evaluator.eval("Foo.kA"));
+        assert_eq!(None, evaluator.eval("Foo.kB"));
+    }
+}
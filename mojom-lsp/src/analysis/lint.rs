@@ -0,0 +1,295 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Style lints. These are opt-in and reported with their own diagnostic codes
+// so that clients can filter them out.
+
+use serde::Deserialize;
+
+use crate::syntax::{
+    self, preorder, InterfaceMember, MojomFile, Statement, StructBody, Traversal, TypeName,
+    TypeSpec,
+};
+
+use super::diagnostic::{Diagnostic, Severity};
+use super::naming::{check_naming, NamingOptions};
+use super::versioning::TypeTable;
+
+const MEMBER_ORDER_CODE: &str = "mojom-style/member-order";
+const NULLABLE_VALUE_TYPE_CODE: &str = "mojom/nullable-value-type";
+
+// The first Chromium milestone whose bindings generators accept nullable value
+// types.
+const NULLABLE_VALUE_TYPES_MILESTONE: u32 = 109;
+
+/// Opt-in style lints. All lints are disabled by default.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LintOptions {
+    /// Checks that consts and enums are declared before methods and fields.
+    pub member_order: bool,
+    /// Naming conventions.
+    pub naming: NamingOptions,
+    /// Reports nullable value types like `int32?` and nullable enums when
+    /// `target_milestone` doesn't support them.
+    pub nullable_value_types: bool,
+    /// The oldest Chromium milestone the files need to build in. None means
+    /// the latest milestone.
+    pub target_milestone: Option<u32>,
+}
+
+fn member_order_lint(text: &str, name: &syntax::Range, kind: &str, container: &str) -> Diagnostic {
+    let message = format!(
+        "{} `{}` should be declared before {}. Consider moving consts and enums to the top of the {}.",
+        kind,
+        &text[name.start..name.end],
+        if container == "interface" { "methods" } else { "fields" },
+        container
+    );
+    Diagnostic::new(Severity::Information, MEMBER_ORDER_CODE, *name, message)
+}
+
+// Chromium style declares constants and enums before methods in interfaces
+// and before fields in structs.
+fn check_member_order(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<Diagnostic>) {
+    for stmt in &mojom.stmts {
+        match stmt {
+            Statement::Interface(interface) => {
+                let mut seen_method = false;
+                for member in &interface.members {
+                    match member {
+                        InterfaceMember::Method(_) => seen_method = true,
+                        InterfaceMember::Const(node) if seen_method => {
+                            diagnostics.push(member_order_lint(
+                                text,
+                                &node.name,
+                                "Const",
+                                "interface",
+                            ));
+                        }
+                        InterfaceMember::Enum(node) if seen_method => {
+                            diagnostics.push(member_order_lint(
+                                text,
+                                &node.name,
+                                "Enum",
+                                "interface",
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Statement::Struct(stmt) => {
+                let mut seen_field = false;
+                for member in &stmt.members {
+                    match member {
+                        StructBody::Field(_) => seen_field = true,
+                        StructBody::Const(node) if seen_field => {
+                            diagnostics
+                                .push(member_order_lint(text, &node.name, "Const", "struct"));
+                        }
+                        StructBody::Enum(node) if seen_field => {
+                            diagnostics.push(member_order_lint(text, &node.name, "Enum", "struct"));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Returns true when `spec` or the elements of `spec` are nullable value types.
+fn has_nullable_value_type(types: &TypeTable, scope: &[&str], spec: &TypeSpec) -> bool {
+    if types.is_nullable_value_type(scope, spec) {
+        return true;
+    }
+    match &spec.type_name {
+        TypeName::Array(element) | TypeName::FixedArray(element, _, _) => {
+            has_nullable_value_type(types, scope, element)
+        }
+        TypeName::Map(_, value) => has_nullable_value_type(types, scope, value),
+        _ => false,
+    }
+}
+
+fn check_nullable_value_types(
+    text: &str,
+    mojom: &MojomFile,
+    milestone: u32,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let types = TypeTable::new(text, mojom);
+    let mut scope: Vec<&str> = Vec::new();
+    for traversal in preorder(mojom) {
+        let (typ, spec) = match traversal {
+            Traversal::EnterInterface(node) => {
+                scope.push(&text[node.name.start..node.name.end]);
+                continue;
+            }
+            Traversal::EnterStruct(node) => {
+                scope.push(&text[node.name.start..node.name.end]);
+                continue;
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+                continue;
+            }
+            Traversal::StructField(node) => (&node.typ, node.type_spec(text)),
+            Traversal::UnionField(node) => (&node.typ, node.type_spec(text)),
+            Traversal::Parameter(node) => (&node.typ, node.type_spec(text)),
+            _ => continue,
+        };
+        if !spec.is_ok_and(|spec| has_nullable_value_type(&types, &scope, &spec)) {
+            continue;
+        }
+        let message = format!(
+            "Nullable value type `{}` requires M{} or later but M{} is targeted.",
+            &text[typ.start..typ.end],
+            NULLABLE_VALUE_TYPES_MILESTONE,
+            milestone
+        );
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            NULLABLE_VALUE_TYPE_CODE,
+            *typ,
+            message,
+        ));
+    }
+}
+
+pub(crate) fn check_lints(
+    text: &str,
+    mojom: &MojomFile,
+    options: &LintOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if options.member_order {
+        check_member_order(text, mojom, diagnostics);
+    }
+    if let Some(milestone) = options.target_milestone {
+        if options.nullable_value_types && milestone < NULLABLE_VALUE_TYPES_MILESTONE {
+            check_nullable_value_types(text, mojom, milestone, diagnostics);
+        }
+    }
+    check_naming(text, mojom, &options.naming, diagnostics);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_order() {
+        let input = r#"
+        interface MyInterface {
+            const int32 kBefore = 1;
+            MyMethod();
+            enum MyEnum { kOne };
+            const int32 kAfter = 2;
+        };
+        struct MyStruct {
+            enum MyEnum { kOne };
+            int32 my_field;
+            const int32 kAfter = 1;
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+
+        let mut diagnostics = Vec::new();
+        check_lints(input, &mojom, &LintOptions::default(), &mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        let options = LintOptions {
+            member_order: true,
+            ..LintOptions::default()
+        };
+        check_lints(input, &mojom, &options, &mut diagnostics);
+        assert_eq!(3, diagnostics.len());
+        let names = diagnostics
+            .iter()
+            .map(|diagnostic| &input[diagnostic.range.start..diagnostic.range.end])
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["MyEnum", "kAfter", "kAfter"], names);
+        for diagnostic in &diagnostics {
+            assert_eq!(MEMBER_ORDER_CODE, diagnostic.code);
+            assert_eq!(Severity::Information, diagnostic.severity);
+        }
+    }
+
+    #[test]
+    fn test_nullable_value_types() {
+        let input = r#"
+        enum Color { kRed };
+        struct MyStruct {
+            enum Size { kSmall };
+            int32? count;
+            string? name;
+            Color? color;
+            Size? size;
+            array<int32?> counts;
+            map<string, bool?> flags;
+            MyStruct? next;
+        };
+        union MyUnion { uint8? byte; };
+        interface MyInterface {
+            MyMethod(double? value) => (Color? color, Unknown? unknown);
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let lint = |nullable_value_types, target_milestone| {
+            let options = LintOptions {
+                nullable_value_types,
+                target_milestone,
+                ..LintOptions::default()
+            };
+            let mut diagnostics = Vec::new();
+            check_lints(input, &mojom, &options, &mut diagnostics);
+            diagnostics
+                .iter()
+                .map(|diagnostic| &input[diagnostic.range.start..diagnostic.range.end])
+                .collect::<Vec<_>>()
+        };
+
+        let expected = vec![
+            "int32?",
+            "Color?",
+            "Size?",
+            "array<int32?>",
+            "map<string, bool?>",
+            "uint8?",
+            "double?",
+            "Color?",
+        ];
+        assert_eq!(expected, lint(true, Some(100)));
+        // The lint is disabled, or the targeted milestone supports them.
+        assert!(lint(false, Some(100)).is_empty());
+        assert!(lint(true, None).is_empty());
+        assert!(lint(true, Some(NULLABLE_VALUE_TYPES_MILESTONE)).is_empty());
+
+        let mut diagnostics = Vec::new();
+        let options = LintOptions {
+            nullable_value_types: true,
+            target_milestone: Some(100),
+            ..LintOptions::default()
+        };
+        check_lints(input, &mojom, &options, &mut diagnostics);
+        assert_eq!(NULLABLE_VALUE_TYPE_CODE, diagnostics[0].code);
+        assert_eq!(
+            "Nullable value type `int32?` requires M109 or later but M100 is targeted.",
+            diagnostics[0].message
+        );
+    }
+}
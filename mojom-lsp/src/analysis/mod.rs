@@ -0,0 +1,208 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Syntax and semantics checks for mojom documents. This module doesn't
+//! depend on LSP types so that it can be used by command line tools.
+
+mod containment;
+mod diagnostic;
+mod diff;
+mod eval;
+mod lint;
+mod naming;
+mod semantic;
+mod versioning;
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::syntax::{self, Module, MojomFile};
+
+pub use containment::check_cyclic_containment;
+pub use diagnostic::{Diagnostic, Related, Severity, MOJOM_CODE};
+pub use diff::{diff_mojom, Change, ChangeKind};
+pub use lint::LintOptions;
+pub use naming::{naming_violations, NameKind, NamingOptions, NamingViolation, NAMING_CODE_PREFIX};
+pub use semantic::{
+    check_ambiguous_references, check_type_references, check_unused_imports, find_condition,
+    Condition, MethodOrdinalCheck, UNUSED_IMPORT_CODE,
+};
+pub use versioning::check_stable_imports;
+
+pub(crate) use containment::contained_types;
+pub(crate) use eval::eval_enum_values;
+pub(crate) use semantic::{effective_ordinals, find_module, strip_module_qualifier};
+
+/// Options for optional checks.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Options {
+    pub lints: LintOptions,
+    pub method_ordinals: MethodOrdinalCheck,
+    /// Runs semantic checks such as duplicate names and unresolved types.
+    /// Syntax errors are always reported.
+    pub semantic_diagnostics: bool,
+    /// Severities that replace the default severity of diagnostics, keyed by
+    /// diagnostic code.
+    pub severity: HashMap<String, Severity>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            lints: LintOptions::default(),
+            method_ordinals: MethodOrdinalCheck::default(),
+            semantic_diagnostics: true,
+            severity: HashMap::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Applies `severity` overrides to `diagnostics`. Fatal diagnostics are
+    /// always errors.
+    pub fn override_severity(&self, diagnostics: &mut [Diagnostic]) {
+        for diagnostic in diagnostics
+            .iter_mut()
+            .filter(|diagnostic| !diagnostic.fatal)
+        {
+            if let Some(&severity) = self.severity.get(diagnostic.code) {
+                diagnostic.severity = severity;
+            }
+        }
+    }
+}
+
+/// The result of analyzing a mojom document.
+#[derive(Debug)]
+pub struct Analysis {
+    /// The syntax tree. None when the document has a syntax error.
+    pub mojom: Option<MojomFile>,
+    /// The first module statement, if any.
+    pub module: Option<Module>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// Returns true when the document couldn't be parsed. Other diagnostics,
+    /// including semantic errors, keep the syntax tree.
+    pub fn is_fatal(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.fatal)
+    }
+}
+
+/// Parses and checks `text`.
+pub fn analyze(text: &str, options: &Options) -> Analysis {
+    let mojom = match syntax::parse_with_comments(text) {
+        Ok(mojom) => mojom,
+        Err(err) => {
+            let diagnostic = Diagnostic::syntax_error(&err);
+            return Analysis {
+                mojom: None,
+                module: None,
+                diagnostics: vec![diagnostic],
+            };
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let module = find_module(text, &mojom, &mut diagnostics);
+    if options.semantic_diagnostics {
+        semantic::check_duplicate_names(text, &mojom, &mut diagnostics);
+        semantic::check_field_ordinals(text, &mojom, &mut diagnostics);
+        semantic::check_type_specs(text, &mojom, &mut diagnostics);
+        semantic::check_nullable_types(text, &mojom, &mut diagnostics);
+        semantic::check_method_ordinals(text, &mojom, options.method_ordinals, &mut diagnostics);
+        eval::check_values(text, &mojom, &mut diagnostics);
+        versioning::check_min_versions(text, &mojom, &mut diagnostics);
+        versioning::check_stable_types(text, &mojom, &mut diagnostics);
+    } else {
+        // The module is still needed to resolve names.
+        diagnostics.clear();
+    }
+    lint::check_lints(text, &mojom, &options.lints, &mut diagnostics);
+    options.override_severity(&mut diagnostics);
+    Analysis {
+        mojom: Some(mojom),
+        module,
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let analysis = analyze("module foo; struct Foo {};", &Options::default());
+        assert!(analysis.mojom.is_some());
+        assert!(analysis.module.is_some());
+        assert!(analysis.diagnostics.is_empty());
+        assert!(!analysis.is_fatal());
+
+        let input = "module foo;\nstruct Foo {}";
+        let analysis = analyze(input, &Options::default());
+        assert!(analysis.mojom.is_none());
+        assert!(analysis.is_fatal());
+        assert_eq!(1, analysis.diagnostics.len());
+        let diagnostic = &analysis.diagnostics[0];
+        assert!(diagnostic.fatal);
+        assert_eq!(Severity::Error, diagnostic.severity);
+        assert_eq!(MOJOM_CODE, diagnostic.code);
+        // Points at `}` after which `;` is missing.
+        assert_eq!(input.len() - 1, diagnostic.range.start);
+        assert_eq!(input.len(), diagnostic.range.end);
+        assert_eq!("expected ';' here", diagnostic.message);
+    }
+
+    #[test]
+    fn test_analyze_options() {
+        let input = "module foo; struct Foo { int32 a; int32 a; };";
+        let analysis = analyze(input, &Options::default());
+        assert_eq!(1, analysis.diagnostics.len());
+        assert_eq!(Severity::Error, analysis.diagnostics[0].severity);
+        // Semantic errors keep the syntax tree.
+        assert!(!analysis.is_fatal());
+        assert!(analysis.mojom.is_some());
+
+        let mut options = Options::default();
+        options
+            .severity
+            .insert("mojom/duplicate-name".to_owned(), Severity::Warning);
+        let analysis = analyze(input, &options);
+        assert_eq!(Severity::Warning, analysis.diagnostics[0].severity);
+
+        let options = Options {
+            semantic_diagnostics: false,
+            ..Options::default()
+        };
+        let analysis = analyze(input, &options);
+        assert!(analysis.diagnostics.is_empty());
+
+        // Syntax errors are reported regardless of options.
+        let analysis = analyze("struct Foo {", &options);
+        assert_eq!(1, analysis.diagnostics.len());
+
+        // Syntax errors are always errors.
+        let mut diagnostics = analysis.diagnostics;
+        let mut options = Options::default();
+        options
+            .severity
+            .insert(MOJOM_CODE.to_owned(), Severity::Warning);
+        options.override_severity(&mut diagnostics);
+        assert_eq!(Severity::Error, diagnostics[0].severity);
+    }
+}
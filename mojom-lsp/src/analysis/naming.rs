@@ -0,0 +1,432 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Naming conventions of the Chromium mojom style.
+
+use serde::Deserialize;
+
+use crate::syntax::{self, preorder, MojomFile, Traversal};
+
+use super::diagnostic::{Diagnostic, Severity};
+
+/// The diagnostic code prefix of naming lints.
+pub const NAMING_CODE_PREFIX: &str = "mojom-style/naming-";
+
+const TYPE_NAME_CODE: &str = "mojom-style/naming-type";
+const FIELD_NAME_CODE: &str = "mojom-style/naming-field";
+const CONSTANT_NAME_CODE: &str = "mojom-style/naming-constant";
+const METHOD_NAME_CODE: &str = "mojom-style/naming-method";
+const MODULE_NAME_CODE: &str = "mojom-style/naming-module";
+
+/// Naming lints. Each rule is enabled separately.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NamingOptions {
+    /// Interfaces, structs, unions and enums are UpperCamelCase.
+    pub types: bool,
+    /// Struct fields, union fields and parameters are lower_snake_case.
+    pub fields: bool,
+    /// Consts and enum values are kConstantStyle.
+    pub constants: bool,
+    /// Methods are UpperCamelCase.
+    pub methods: bool,
+    /// Module names are lowercase and dotted.
+    pub modules: bool,
+}
+
+impl NamingOptions {
+    /// Returns options with all rules enabled.
+    pub fn all() -> Self {
+        NamingOptions {
+            types: true,
+            fields: true,
+            constants: true,
+            methods: true,
+            modules: true,
+        }
+    }
+}
+
+/// What a name declares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameKind {
+    Type,
+    Field,
+    Parameter,
+    Const,
+    EnumValue,
+    Method,
+    Module,
+}
+
+/// A name that doesn't follow the naming convention of its kind.
+#[derive(Debug, PartialEq)]
+pub struct NamingViolation<'a> {
+    pub kind: NameKind,
+    pub name: &'a syntax::Range,
+    /// The name in the expected style.
+    pub suggestion: String,
+}
+
+// Splits `name` into words at underscores and case boundaries. Acronyms are
+// kept together, e.g. `URLLoader` is `URL` and `Loader`. Digits belong to
+// the preceding word.
+fn split_words(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars = part.char_indices().collect::<Vec<_>>();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (offset, ch) = chars[i];
+            let prev = chars[i - 1].1;
+            let next_is_lower = chars
+                .get(i + 1)
+                .is_some_and(|&(_, ch)| ch.is_ascii_lowercase());
+            let boundary = ch.is_ascii_uppercase()
+                && (prev.is_ascii_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_ascii_uppercase() && next_is_lower));
+            if boundary {
+                words.push(&part[start..offset]);
+                start = offset;
+            }
+        }
+        words.push(&part[start..]);
+    }
+    words
+}
+
+// Returns true for names like `URLLoader` or `Vp8Decoder`.
+pub(crate) fn is_upper_camel_case(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_uppercase())
+        && name.chars().all(|ch| ch.is_ascii_alphanumeric())
+        // All caps names are constants.
+        && (name.len() == 1 || name.chars().any(|ch| ch.is_ascii_lowercase()))
+}
+
+// Returns true for names like `url_loader` or `vp8_decoder`.
+pub(crate) fn is_lower_snake_case(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')
+        && !name.ends_with('_')
+        && !name.contains("__")
+}
+
+// Returns true for names like `kMaxValue` or `k2D`.
+pub(crate) fn is_constant_style(name: &str) -> bool {
+    match name.strip_prefix('k') {
+        Some(rest) => {
+            rest.starts_with(|ch: char| ch.is_ascii_uppercase() || ch.is_ascii_digit())
+                && rest.chars().all(|ch| ch.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+// Returns true for module names like `network.mojom`.
+fn is_module_name(name: &str) -> bool {
+    name.split('.').all(is_lower_snake_case)
+}
+
+// Returns the words of `name` without the `k` prefix of constants. Words of
+// all caps names like `MAX_VALUE` are lowercased so that they aren't taken as
+// acronyms.
+fn normalized_words(name: &str) -> Vec<String> {
+    let name = match name.strip_prefix('k') {
+        Some(rest) if rest.starts_with(|ch: char| ch.is_ascii_uppercase()) => rest,
+        _ => name,
+    };
+    let all_caps = !name.chars().any(|ch| ch.is_ascii_lowercase());
+    split_words(name)
+        .into_iter()
+        .map(|word| {
+            if all_caps {
+                word.to_ascii_lowercase()
+            } else {
+                word.to_owned()
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn to_upper_camel_case(name: &str) -> String {
+    normalized_words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|ch| ch.to_ascii_uppercase());
+            first.into_iter().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+pub(crate) fn to_lower_snake_case(name: &str) -> String {
+    let words = normalized_words(name);
+    let words = words.iter().map(|word| word.to_ascii_lowercase());
+    words.collect::<Vec<_>>().join("_")
+}
+
+pub(crate) fn to_constant_style(name: &str) -> String {
+    format!("k{}", to_upper_camel_case(name))
+}
+
+// Returns the expected spelling of `name`, or None when it already follows
+// the convention of `kind`.
+fn suggest(kind: NameKind, name: &str) -> Option<String> {
+    let suggestion = match kind {
+        NameKind::Type | NameKind::Method if !is_upper_camel_case(name) => {
+            to_upper_camel_case(name)
+        }
+        NameKind::Field | NameKind::Parameter if !is_lower_snake_case(name) => {
+            to_lower_snake_case(name)
+        }
+        NameKind::Const | NameKind::EnumValue if !is_constant_style(name) => {
+            to_constant_style(name)
+        }
+        NameKind::Module if !is_module_name(name) => name
+            .split('.')
+            .map(to_lower_snake_case)
+            .collect::<Vec<_>>()
+            .join("."),
+        _ => return None,
+    };
+    // Names without letters can't be fixed.
+    if suggestion.is_empty() || suggestion == name {
+        return None;
+    }
+    Some(suggestion)
+}
+
+fn is_enabled(options: &NamingOptions, kind: NameKind) -> bool {
+    match kind {
+        NameKind::Type => options.types,
+        NameKind::Field | NameKind::Parameter => options.fields,
+        NameKind::Const | NameKind::EnumValue => options.constants,
+        NameKind::Method => options.methods,
+        NameKind::Module => options.modules,
+    }
+}
+
+/// Returns the names in `mojom` that don't follow the naming conventions
+/// enabled by `options`.
+pub fn naming_violations<'a>(
+    text: &str,
+    mojom: &'a MojomFile,
+    options: &NamingOptions,
+) -> Vec<NamingViolation<'a>> {
+    let mut violations = Vec::new();
+    // Constants of features have fixed names like `name` and `default_state`.
+    let mut in_feature = false;
+    for traversal in preorder(mojom) {
+        let (kind, name) = match traversal {
+            Traversal::Feature(_) => {
+                in_feature = true;
+                continue;
+            }
+            Traversal::LeaveFeature(_) => {
+                in_feature = false;
+                continue;
+            }
+            Traversal::Const(_) if in_feature => continue,
+            Traversal::Module(node) => (NameKind::Module, &node.name),
+            Traversal::EnterInterface(node) => (NameKind::Type, &node.name),
+            Traversal::EnterStruct(node) => (NameKind::Type, &node.name),
+            Traversal::Union(node) => (NameKind::Type, &node.name),
+            Traversal::Enum(node) => (NameKind::Type, &node.name),
+            Traversal::StructField(node) => (NameKind::Field, &node.name),
+            Traversal::UnionField(node) => (NameKind::Field, &node.name),
+            Traversal::Parameter(node) => (NameKind::Parameter, &node.name),
+            Traversal::Const(node) => (NameKind::Const, &node.name),
+            Traversal::EnumValue(node) => (NameKind::EnumValue, &node.name),
+            Traversal::Method(node) => (NameKind::Method, &node.name),
+            _ => continue,
+        };
+        if !is_enabled(options, kind) {
+            continue;
+        }
+        if let Some(suggestion) = suggest(kind, &text[name.start..name.end]) {
+            violations.push(NamingViolation {
+                kind,
+                name,
+                suggestion,
+            });
+        }
+    }
+    violations
+}
+
+fn naming_lint(text: &str, violation: &NamingViolation) -> Diagnostic {
+    let (code, what, style) = match violation.kind {
+        NameKind::Type => (TYPE_NAME_CODE, "Type name", "UpperCamelCase"),
+        NameKind::Field => (FIELD_NAME_CODE, "Field name", "lower_snake_case"),
+        NameKind::Parameter => (FIELD_NAME_CODE, "Parameter name", "lower_snake_case"),
+        NameKind::Const => (CONSTANT_NAME_CODE, "Constant name", "kConstantStyle"),
+        NameKind::EnumValue => (CONSTANT_NAME_CODE, "Enum value", "kConstantStyle"),
+        NameKind::Method => (METHOD_NAME_CODE, "Method name", "UpperCamelCase"),
+        NameKind::Module => (MODULE_NAME_CODE, "Module name", "lowercase and dotted"),
+    };
+    let name = violation.name;
+    let message = format!(
+        "{} `{}` should be {}: `{}`",
+        what,
+        &text[name.start..name.end],
+        style,
+        violation.suggestion
+    );
+    Diagnostic::new(Severity::Warning, code, *name, message)
+}
+
+pub(crate) fn check_naming(
+    text: &str,
+    mojom: &MojomFile,
+    options: &NamingOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let violations = naming_violations(text, mojom, options);
+    diagnostics.extend(
+        violations
+            .iter()
+            .map(|violation| naming_lint(text, violation)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(vec!["URL", "Loader"], split_words("URLLoader"));
+        assert_eq!(vec!["my", "URL", "Loader"], split_words("myURLLoader"));
+        assert_eq!(vec!["url", "loader"], split_words("url_loader"));
+        assert_eq!(vec!["Vp8", "Decoder"], split_words("Vp8Decoder"));
+        assert_eq!(vec!["int32", "Value"], split_words("int32Value"));
+        assert_eq!(vec!["Get", "ID"], split_words("GetID"));
+        assert_eq!(vec!["MAX", "VALUE"], split_words("MAX_VALUE"));
+        assert_eq!(vec!["k", "Max"], split_words("k_Max"));
+    }
+
+    #[test]
+    fn test_classification() {
+        assert!(is_upper_camel_case("URLLoader"));
+        assert!(is_upper_camel_case("Vp8Decoder"));
+        assert!(is_upper_camel_case("A"));
+        assert!(!is_upper_camel_case("urlLoader"));
+        assert!(!is_upper_camel_case("URL_Loader"));
+        assert!(!is_upper_camel_case("URL"));
+
+        assert!(is_lower_snake_case("url_loader"));
+        assert!(is_lower_snake_case("vp8_decoder2"));
+        assert!(!is_lower_snake_case("urlLoader"));
+        assert!(!is_lower_snake_case("url__loader"));
+        assert!(!is_lower_snake_case("_url"));
+        assert!(!is_lower_snake_case("url_"));
+
+        assert!(is_constant_style("kMaxValue"));
+        assert!(is_constant_style("k2D"));
+        assert!(is_constant_style("kURLLoader"));
+        assert!(!is_constant_style("MAX_VALUE"));
+        assert!(!is_constant_style("kmax"));
+        assert!(!is_constant_style("k_max"));
+        assert!(!is_constant_style("k"));
+
+        assert!(is_module_name("network.mojom"));
+        assert!(is_module_name("gfx.mojom2"));
+        assert!(!is_module_name("Network.mojom"));
+    }
+
+    #[test]
+    fn test_conversion() {
+        assert_eq!("UrlLoader", to_upper_camel_case("url_loader"));
+        assert_eq!("MyURLLoader", to_upper_camel_case("myURLLoader"));
+        assert_eq!("MaxValue", to_upper_camel_case("MAX_VALUE"));
+        assert_eq!("url_loader", to_lower_snake_case("URLLoader"));
+        assert_eq!("int32_value", to_lower_snake_case("int32Value"));
+        assert_eq!("max_value", to_lower_snake_case("kMaxValue"));
+        assert_eq!("kMaxValue", to_constant_style("MAX_VALUE"));
+        assert_eq!("kMaxValue", to_constant_style("max_value"));
+        assert_eq!("kURLLoader", to_constant_style("URLLoader"));
+        assert_eq!("k2D", to_constant_style("2D"));
+    }
+
+    #[test]
+    fn test_naming_violations() {
+        let input = r#"
+        module My.Module;
+        interface url_loader {
+            const int32 MAX_SIZE = 1;
+            Start(int32 requestId) => (bool DidStart);
+            stop();
+        };
+        struct URLRequest {
+            enum request_kind { kGet, POST_DATA };
+            string methodName;
+            int32 body_size;
+        };
+        union Body { string Text; };
+        feature kMyFeature {
+            const string name = "MyFeature";
+            const bool default_state = false;
+        };
+        const int32 max_count = 1;
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+
+        let mut diagnostics = Vec::new();
+        check_naming(input, &mojom, &NamingOptions::default(), &mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        let options = NamingOptions::all();
+        let violations = naming_violations(input, &mojom, &options);
+        let summary = violations
+            .iter()
+            .map(|violation| {
+                let name = &input[violation.name.start..violation.name.end];
+                (name, violation.suggestion.as_str())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("My.Module", "my.module"),
+                ("url_loader", "UrlLoader"),
+                ("MAX_SIZE", "kMaxSize"),
+                ("requestId", "request_id"),
+                ("DidStart", "did_start"),
+                ("stop", "Stop"),
+                ("request_kind", "RequestKind"),
+                ("POST_DATA", "kPostData"),
+                ("methodName", "method_name"),
+                ("Text", "text"),
+                ("max_count", "kMaxCount"),
+            ],
+            summary
+        );
+
+        // Each rule is enabled separately.
+        let options = NamingOptions {
+            methods: true,
+            ..NamingOptions::default()
+        };
+        check_naming(input, &mojom, &options, &mut diagnostics);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(METHOD_NAME_CODE, diagnostics[0].code);
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(
+            "Method name `stop` should be UpperCamelCase: `Stop`",
+            diagnostics[0].message
+        );
+    }
+}
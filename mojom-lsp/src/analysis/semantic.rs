@@ -0,0 +1,1181 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::syntax::{
+    self, preorder, AttributeList, InterfaceMember, Method, Module, MojomFile, Parameter,
+    StructBody, Traversal, TypeName, TypeSpec, TypeSpecError,
+};
+
+use super::diagnostic::{Diagnostic, Severity, MOJOM_CODE};
+
+const ORDINAL_MISSING_CODE: &str = "mojom/ordinal-missing";
+const ORDINAL_INVALID_CODE: &str = "mojom/ordinal-invalid";
+const ORDINAL_DUPLICATE_CODE: &str = "mojom/ordinal-duplicate";
+const ORDINAL_GAP_CODE: &str = "mojom/ordinal-gap";
+const ORDINAL_IMPLICIT_CODE: &str = "mojom/ordinal-implicit";
+const INVALID_NULLABLE_CODE: &str = "mojom/invalid-nullable";
+const INVALID_ARRAY_SIZE_CODE: &str = "mojom/invalid-array-size";
+const DUPLICATE_NAME_CODE: &str = "mojom/duplicate-name";
+const CONDITIONAL_DUPLICATE_NAME_CODE: &str = "mojom/conditional-duplicate-name";
+const UNRESOLVED_TYPE_CODE: &str = "mojom/unresolved-type";
+const AMBIGUOUS_REFERENCE_CODE: &str = "mojom/ambiguous-reference";
+/// The diagnostic code for imports that no type reference resolves to.
+pub const UNUSED_IMPORT_CODE: &str = "mojom/unused-import";
+
+/// Controls validation of explicit method ordinals in interfaces.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MethodOrdinalCheck {
+    #[default]
+    Off,
+    /// Once an interface uses an explicit ordinal, all methods must have
+    /// unique explicit ordinals that are contiguous from 0.
+    On,
+    /// In addition to `On`, interfaces with more than one method and no
+    /// explicit ordinals are reported.
+    Strict,
+}
+
+impl std::str::FromStr for MethodOrdinalCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(MethodOrdinalCheck::Off),
+            "on" => Ok(MethodOrdinalCheck::On),
+            "strict" => Ok(MethodOrdinalCheck::Strict),
+            _ => Err(format!("Invalid method ordinal check: {}", s)),
+        }
+    }
+}
+
+fn partial_text<'a>(text: &'a str, range: &syntax::Range) -> &'a str {
+    &text[range.start..range.end]
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    syntax::line_col(text, offset).unwrap().line + 1
+}
+
+pub(crate) fn find_module(
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Module> {
+    let mut module: Option<Module> = None;
+    for stmt in &mojom.stmts {
+        if let syntax::Statement::Module(stmt) = stmt {
+            if let Some(ref module) = module {
+                let message = format!(
+                    "Found more than one module statement: {} and {}",
+                    partial_text(text, &module.name),
+                    partial_text(text, &stmt.name)
+                );
+                let diagnostic = Diagnostic::error(MOJOM_CODE, stmt.name, message)
+                    .with_related(module.name, "First module statement".to_owned());
+                diagnostics.push(diagnostic);
+            } else {
+                module = Some(stmt.clone());
+            }
+        }
+    }
+    module
+}
+
+// Parses an ordinal token like `@1`.
+fn parse_ordinal(text: &str, ordinal: &syntax::Range) -> Option<u32> {
+    partial_text(text, ordinal)
+        .trim_start_matches('@')
+        .parse::<u32>()
+        .ok()
+}
+
+fn check_interface_ordinals(
+    text: &str,
+    name: &syntax::Range,
+    methods: &[&Method],
+    mode: MethodOrdinalCheck,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let has_explicit = methods.iter().any(|method| method.ordinal.is_some());
+    if !has_explicit {
+        if mode == MethodOrdinalCheck::Strict && methods.len() > 1 {
+            let message = format!(
+                "Interface `{}` has {} methods without explicit ordinals. \
+                 Consider adding explicit ordinals (e.g. `@0`) to keep them stable.",
+                partial_text(text, name),
+                methods.len()
+            );
+            diagnostics.push(Diagnostic::new(
+                Severity::Information,
+                ORDINAL_IMPLICIT_CODE,
+                *name,
+                message,
+            ));
+        }
+        return;
+    }
+
+    let scope = format!("`{}`", partial_text(text, name));
+    let methods = methods
+        .iter()
+        .map(|method| (&method.name, method.ordinal.as_ref()))
+        .collect::<Vec<_>>();
+    check_scope_ordinals(text, &scope, &methods, diagnostics);
+}
+
+pub(crate) fn check_method_ordinals(
+    text: &str,
+    mojom: &MojomFile,
+    mode: MethodOrdinalCheck,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if mode == MethodOrdinalCheck::Off {
+        return;
+    }
+    for stmt in &mojom.stmts {
+        if let syntax::Statement::Interface(interface) = stmt {
+            let methods = interface
+                .members
+                .iter()
+                .filter_map(|member| match member {
+                    InterfaceMember::Method(method) => Some(method),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            check_interface_ordinals(text, &interface.name, &methods, mode, diagnostics);
+        }
+    }
+}
+
+// Checks ordinals of methods, fields or parameters in `scope`. When any of
+// them has an explicit ordinal, all of them must have unique ordinals that
+// are dense from 0. `items` are pairs of a name and an optional ordinal.
+// Mixed and sparse ordinals are reported as warnings because they only
+// matter when the wire format needs to stay stable.
+fn check_scope_ordinals(
+    text: &str,
+    scope: &str,
+    items: &[(&syntax::Range, Option<&syntax::Range>)],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if items.iter().all(|(_, ordinal)| ordinal.is_none()) {
+        return;
+    }
+
+    let mut ordinals = Vec::new();
+    let mut seen: HashMap<u32, &syntax::Range> = HashMap::new();
+    for (name, ordinal) in items {
+        let ordinal = match ordinal {
+            Some(ordinal) => *ordinal,
+            None => {
+                let message = format!(
+                    "`{}` has no explicit ordinal but others in {} do. Mixing explicit \
+                     and implicit ordinals changes the wire format when they are reordered.",
+                    partial_text(text, name),
+                    scope
+                );
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    ORDINAL_MISSING_CODE,
+                    **name,
+                    message,
+                ));
+                continue;
+            }
+        };
+        let value = match parse_ordinal(text, ordinal) {
+            Some(value) => value,
+            None => {
+                let message = format!("Ordinal {} is out of range", partial_text(text, ordinal));
+                diagnostics.push(Diagnostic::error(ORDINAL_INVALID_CODE, *ordinal, message));
+                continue;
+            }
+        };
+        if let Some(first) = seen.get(&value) {
+            let message = format!(
+                "Duplicate ordinal @{} in {} (first used at line {})",
+                value,
+                scope,
+                line_of(text, first.start)
+            );
+            let diagnostic = Diagnostic::error(ORDINAL_DUPLICATE_CODE, *ordinal, message)
+                .with_related(**first, format!("@{} is first used here", value));
+            diagnostics.push(diagnostic);
+            continue;
+        }
+        seen.insert(value, ordinal);
+        ordinals.push((value, ordinal));
+    }
+
+    // Report the first ordinal that leaves a gap.
+    ordinals.sort_by_key(|(value, _)| *value);
+    for (expected, (value, ordinal)) in ordinals.iter().enumerate() {
+        if *value as usize != expected {
+            let message = format!(
+                "Ordinal @{} leaves a gap in {}: expected @{}",
+                value, scope, expected
+            );
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                ORDINAL_GAP_CODE,
+                **ordinal,
+                message,
+            ));
+            break;
+        }
+    }
+}
+
+/// Returns the ordinals of fields or parameters as the bindings generator
+/// assigns them. `ordinals` are their explicit ordinals. A field without an
+/// explicit ordinal takes the ordinal next to the previous field.
+pub(crate) fn effective_ordinals(text: &str, ordinals: &[Option<&syntax::Range>]) -> Vec<u32> {
+    let mut next = 0;
+    ordinals
+        .iter()
+        .map(|ordinal| {
+            let value = ordinal
+                .and_then(|ordinal| parse_ordinal(text, ordinal))
+                .unwrap_or(next);
+            next = value.saturating_add(1);
+            value
+        })
+        .collect()
+}
+
+fn parameter_ordinals(params: &[Parameter]) -> Vec<(&syntax::Range, Option<&syntax::Range>)> {
+    params
+        .iter()
+        .map(|param| (&param.name, param.ordinal.as_ref()))
+        .collect()
+}
+
+// Checks ordinals of struct fields, union fields and method parameters.
+pub(crate) fn check_field_ordinals(
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterStruct(node) => {
+                let fields = node
+                    .members
+                    .iter()
+                    .filter_map(|member| match member {
+                        StructBody::Field(field) => Some((&field.name, field.ordinal.as_ref())),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let scope = format!("`{}`", partial_text(text, &node.name));
+                check_scope_ordinals(text, &scope, &fields, diagnostics);
+            }
+            Traversal::Union(node) => {
+                let fields = node
+                    .fields
+                    .iter()
+                    .map(|field| (&field.name, field.ordinal.as_ref()))
+                    .collect::<Vec<_>>();
+                let scope = format!("`{}`", partial_text(text, &node.name));
+                check_scope_ordinals(text, &scope, &fields, diagnostics);
+            }
+            Traversal::Method(node) => {
+                let method_name = partial_text(text, &node.name);
+                let scope = format!("parameters of `{}`", method_name);
+                check_scope_ordinals(text, &scope, &parameter_ordinals(&node.params), diagnostics);
+                if let Some(response) = &node.response {
+                    let scope = format!("response parameters of `{}`", method_name);
+                    let params = parameter_ordinals(&response.params);
+                    check_scope_ordinals(text, &scope, &params, diagnostics);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Returns why `spec` uses `?` where it isn't allowed, if it does.
+fn find_invalid_nullable(spec: &TypeSpec) -> Option<&'static str> {
+    match &spec.type_name {
+        TypeName::Map(key, value) => {
+            if key.is_nullable {
+                return Some("Map keys can't be nullable");
+            }
+            find_invalid_nullable(value)
+        }
+        TypeName::FixedArray(element, _, _) => {
+            if element.is_nullable {
+                return Some("Elements of fixed-size arrays can't be nullable");
+            }
+            find_invalid_nullable(element)
+        }
+        TypeName::Array(element) => find_invalid_nullable(element),
+        _ => None,
+    }
+}
+
+// Returns the types of fields, constants and parameters in `mojom` with their
+// parse results.
+fn collect_type_specs<'a>(
+    text: &str,
+    mojom: &'a MojomFile,
+) -> Vec<(&'a syntax::Range, anyhow::Result<TypeSpec>)> {
+    let mut types = Vec::new();
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::StructField(node) => types.push((&node.typ, node.type_spec(text))),
+            Traversal::Const(node) => types.push((&node.typ, node.type_spec(text))),
+            Traversal::Union(node) => types.extend(
+                node.fields
+                    .iter()
+                    .map(|field| (&field.typ, field.type_spec(text))),
+            ),
+            Traversal::Method(node) => types.extend(
+                node.params
+                    .iter()
+                    .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                    .map(|param| (&param.typ, param.type_spec(text))),
+            ),
+            _ => (),
+        }
+    }
+    types
+}
+
+// Reports types that are syntactically valid but can't be used, like
+// `array<int32, 0>`. The error is reported at the offending token.
+pub(crate) fn check_type_specs(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<Diagnostic>) {
+    for (_, spec) in collect_type_specs(text, mojom) {
+        // Syntax errors are reported by the parser.
+        let err = match spec.as_ref().err().and_then(|err| err.downcast_ref()) {
+            Some(err) => err,
+            None => continue,
+        };
+        let code = match err {
+            TypeSpecError::FixedArraySizeOutOfRange { .. } => INVALID_ARRAY_SIZE_CODE,
+        };
+        diagnostics.push(Diagnostic::error(code, *err.range(), err.to_string()));
+    }
+}
+
+// Checks where `?` is used. Nullable value types like `int32?` are allowed in
+// struct fields, method parameters and map values.
+// TODO: Report MinVersion requirements of nullable value types once
+// attributes are kept in the syntax tree.
+pub(crate) fn check_nullable_types(
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for (typ, spec) in collect_type_specs(text, mojom) {
+        let spec = match spec {
+            Ok(spec) => spec,
+            Err(_) => continue,
+        };
+        if let Some(reason) = find_invalid_nullable(&spec) {
+            let message = format!("{}: {}", reason, partial_text(text, typ));
+            diagnostics.push(Diagnostic::error(INVALID_NULLABLE_CODE, *typ, message));
+        }
+    }
+}
+
+/// An `[EnableIf=flag]` or `[EnableIfNot=flag]` condition. The declaration
+/// only exists when the build flag is set, or not set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition<'a> {
+    pub flag: &'a str,
+    /// False for `EnableIfNot`.
+    pub enabled: bool,
+}
+
+impl<'a> Condition<'a> {
+    fn excludes(&self, other: &Condition) -> bool {
+        self.flag == other.flag && self.enabled != other.enabled
+    }
+}
+
+impl<'a> std::fmt::Display for Condition<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.enabled {
+            true => write!(f, "EnableIf={}", self.flag),
+            false => write!(f, "EnableIfNot={}", self.flag),
+        }
+    }
+}
+
+/// Returns the `EnableIf` or `EnableIfNot` condition in `attributes`.
+pub fn find_condition<'a>(
+    text: &'a str,
+    attributes: &Option<AttributeList>,
+) -> Option<Condition<'a>> {
+    let attributes = attributes.as_ref()?;
+    let (attribute, enabled) = match attributes.find(text, "EnableIf") {
+        Some(attribute) => (attribute, true),
+        None => (attributes.find(text, "EnableIfNot")?, false),
+    };
+    let flag = partial_text(text, attribute.value.as_ref()?);
+    Some(Condition { flag, enabled })
+}
+
+// A declaration and its condition.
+struct Declared<'a> {
+    name: &'a syntax::Range,
+    condition: Option<Condition<'a>>,
+}
+
+// Names declared in a scope like the top level, an interface or a struct.
+#[derive(Default)]
+struct NameTable<'a> {
+    names: HashMap<&'a str, Vec<Declared<'a>>>,
+}
+
+impl<'a> NameTable<'a> {
+    // Records `name`. Reports an error at `name` when the same name is
+    // already declared in the table and both declarations can exist in the
+    // same build. Declarations that depend on different flags aren't
+    // reported because it isn't known which flags can be set together.
+    fn declare(
+        &mut self,
+        text: &'a str,
+        name: &'a syntax::Range,
+        attributes: &Option<AttributeList>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let ident = partial_text(text, name);
+        let condition = find_condition(text, attributes);
+        let declared = self.names.entry(ident).or_default();
+        let first = declared
+            .iter()
+            .find_map(|first| match (first.condition, condition) {
+                (None, None) => Some((first, None)),
+                (Some(a), Some(b)) if a == b => Some((first, Some(a))),
+                (Some(a), Some(b)) if a.excludes(&b) || a.flag != b.flag => None,
+                (a, b) => Some((first, a.or(b))),
+            });
+        if let Some((first, overlap)) = first {
+            let line = line_of(text, first.name.start);
+            let diagnostic = match overlap {
+                None => {
+                    let message = format!(
+                        "Duplicate name `{}` (first defined at line {})",
+                        ident, line
+                    );
+                    Diagnostic::error(DUPLICATE_NAME_CODE, *name, message)
+                }
+                Some(overlap) => {
+                    let message = format!(
+                        "Duplicate name `{}` when `{}` (first defined at line {})",
+                        ident, overlap, line
+                    );
+                    Diagnostic::error(CONDITIONAL_DUPLICATE_NAME_CODE, *name, message)
+                }
+            };
+            let related = format!("`{}` is first defined here", ident);
+            diagnostics.push(diagnostic.with_related(*first.name, related));
+        }
+        declared.push(Declared { name, condition });
+    }
+}
+
+// Checks that names are unique in each scope. Enum values and union fields
+// have their own scopes. Interfaces, structs and features open nested scopes
+// so their members may share names with top-level declarations.
+// Declarations with mutually exclusive `EnableIf` and `EnableIfNot`
+// conditions may share names.
+pub(crate) fn check_duplicate_names(
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut scopes = vec![NameTable::default()];
+    for traversal in preorder(mojom) {
+        // Unwrap should be safe because the top-level scope is never popped.
+        let scope = scopes.last_mut().unwrap();
+        match traversal {
+            Traversal::EnterInterface(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics);
+                scopes.push(NameTable::default());
+            }
+            Traversal::EnterStruct(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics);
+                scopes.push(NameTable::default());
+            }
+            Traversal::Feature(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics);
+                scopes.push(NameTable::default());
+            }
+            Traversal::LeaveInterface(_)
+            | Traversal::LeaveStruct(_)
+            | Traversal::LeaveFeature(_) => {
+                scopes.pop();
+            }
+            Traversal::Union(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics);
+                let mut fields = NameTable::default();
+                for field in &node.fields {
+                    fields.declare(text, &field.name, &field.attributes, diagnostics);
+                }
+            }
+            Traversal::Enum(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics);
+                let mut values = NameTable::default();
+                for value in &node.values {
+                    values.declare(text, &value.name, &value.attributes, diagnostics);
+                }
+            }
+            Traversal::Const(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics)
+            }
+            Traversal::Method(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics)
+            }
+            Traversal::StructField(node) => {
+                scope.declare(text, &node.name, &node.attributes, diagnostics)
+            }
+            _ => (),
+        }
+    }
+}
+
+// Returns the range of `name` in `typ`. Falls back to the whole type.
+fn find_name_in_type(text: &str, typ: &syntax::Range, name: &str) -> syntax::Range {
+    let type_text = partial_text(text, typ);
+    let is_boundary = |ch: Option<char>| match ch {
+        Some(ch) => !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'),
+        None => true,
+    };
+    for (pos, _) in type_text.match_indices(name) {
+        let before = type_text[..pos].chars().next_back();
+        let after = type_text[pos + name.len()..].chars().next();
+        if is_boundary(before) && is_boundary(after) {
+            let start = typ.start + pos;
+            return syntax::Range {
+                start,
+                end: start + name.len(),
+            };
+        }
+    }
+    *typ
+}
+
+/// Returns `name` without its module qualifier when it is qualified with
+/// `module_name` or a trailing part of it. In `module foo.bar;`, both
+/// `foo.bar.MyStruct` and `bar.MyStruct` become `MyStruct`.
+pub(crate) fn strip_module_qualifier<'a>(module_name: &str, name: &'a str) -> Option<&'a str> {
+    let mut qualifier = module_name;
+    loop {
+        let local_name = name
+            .strip_prefix(qualifier)
+            .and_then(|name| name.strip_prefix('.'));
+        if local_name.is_some() {
+            return local_name;
+        }
+        qualifier = &qualifier[qualifier.find('.')? + 1..];
+    }
+}
+
+/// Reports type references that don't resolve to any declaration. Names are
+/// looked up in enclosing interfaces and structs, then at the top level, then
+/// by `is_external` which should cover imported files. `is_external` is
+/// called only for names that aren't declared in the document. `module_name`
+/// is the module of the document; names qualified with it or a trailing part
+/// of it are looked up in the document.
+pub fn check_type_references<F>(
+    text: &str,
+    mojom: &MojomFile,
+    module_name: Option<&str>,
+    mut is_external: F,
+    diagnostics: &mut Vec<Diagnostic>,
+) where
+    F: FnMut(&str) -> bool,
+{
+    // Fully qualified names of types declared in the document.
+    let mut declared = HashSet::new();
+    let mut references = Vec::new();
+    let mut scope: Vec<&str> = Vec::new();
+    let mut declare = |scope: &[&str], name: &syntax::Range| {
+        let mut path = scope.to_vec();
+        path.push(partial_text(text, name));
+        declared.insert(path.join("."));
+    };
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => {
+                declare(&scope, &node.name);
+                scope.push(partial_text(text, &node.name));
+            }
+            Traversal::EnterStruct(node) => {
+                declare(&scope, &node.name);
+                scope.push(partial_text(text, &node.name));
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+            }
+            Traversal::Union(node) => {
+                declare(&scope, &node.name);
+                references.extend(
+                    node.fields
+                        .iter()
+                        .map(|field| (scope.clone(), &field.typ, field.type_spec(text))),
+                );
+            }
+            Traversal::Enum(node) => declare(&scope, &node.name),
+            Traversal::StructField(node) => {
+                references.push((scope.clone(), &node.typ, node.type_spec(text)))
+            }
+            Traversal::Const(node) => {
+                references.push((scope.clone(), &node.typ, node.type_spec(text)))
+            }
+            Traversal::Method(node) => references.extend(
+                node.params
+                    .iter()
+                    .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                    .map(|param| (scope.clone(), &param.typ, param.type_spec(text))),
+            ),
+            _ => (),
+        }
+    }
+
+    let mut resolves = |scope: &[&str], name: &str| {
+        let local_name =
+            module_name.and_then(|module_name| strip_module_qualifier(module_name, name));
+        (0..=scope.len()).rev().any(|depth| {
+            let prefix = scope[..depth].join(".");
+            std::iter::once(name).chain(local_name).any(|name| {
+                let qualified = if prefix.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                declared.contains(&qualified)
+            })
+        }) || is_external(name)
+    };
+
+    for (scope, typ, spec) in references {
+        let spec = match spec {
+            Ok(spec) => spec,
+            Err(_) => continue,
+        };
+        for name in spec.referenced_idents() {
+            if !resolves(&scope, name) {
+                let message = format!("Unresolved type `{}`", name);
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    UNRESOLVED_TYPE_CODE,
+                    find_name_in_type(text, typ, name),
+                    message,
+                ));
+            }
+        }
+    }
+}
+
+/// Reports type references for which `is_ambiguous` returns true, which
+/// should be names that more than one imported file declares. The first
+/// import wins, so these are hints.
+pub fn check_ambiguous_references<F>(
+    text: &str,
+    mojom: &MojomFile,
+    is_ambiguous: F,
+    diagnostics: &mut Vec<Diagnostic>,
+) where
+    F: Fn(&str) -> bool,
+{
+    for (typ, spec) in collect_type_specs(text, mojom) {
+        let spec = match spec {
+            Ok(spec) => spec,
+            Err(_) => continue,
+        };
+        for name in spec.referenced_idents() {
+            if is_ambiguous(name) {
+                let message = format!(
+                    "`{}` is declared in more than one imported file. The first import is used.",
+                    name
+                );
+                diagnostics.push(Diagnostic::new(
+                    Severity::Hint,
+                    AMBIGUOUS_REFERENCE_CODE,
+                    find_name_in_type(text, typ, name),
+                    message,
+                ));
+            }
+        }
+    }
+}
+
+/// Reports import statements for which `is_used` returns false. `is_used`
+/// takes the index of an import statement among the import statements of
+/// `mojom`.
+pub fn check_unused_imports<F>(
+    text: &str,
+    mojom: &MojomFile,
+    is_used: F,
+    diagnostics: &mut Vec<Diagnostic>,
+) where
+    F: Fn(usize) -> bool,
+{
+    let imports = mojom.stmts.iter().filter_map(|stmt| match stmt {
+        syntax::Statement::Import(stmt) => Some(stmt),
+        _ => None,
+    });
+    for (index, import) in imports.enumerate() {
+        if is_used(index) {
+            continue;
+        }
+        let message = format!("Unused import {}", partial_text(text, &import.path));
+        let diagnostic = Diagnostic::new(Severity::Hint, UNUSED_IMPORT_CODE, import.range, message)
+            .mark_unnecessary();
+        diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, mode: MethodOrdinalCheck) -> Vec<Diagnostic> {
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_method_ordinals(input, &mojom, mode, &mut diagnostics);
+        diagnostics
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_find_module() {
+        let input = "module foo; module bar;";
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let module = find_module(input, &mojom, &mut diagnostics).unwrap();
+        assert_eq!("foo", partial_text(input, &module.name));
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("bar", partial_text(input, &diagnostics[0].range));
+        let related = diagnostics[0].related.as_ref().unwrap();
+        assert_eq!("foo", partial_text(input, &related.range));
+    }
+
+    #[test]
+    fn test_method_ordinals() {
+        let input = r#"
+        interface Mixed {
+            MethodA@0();
+            MethodB();
+            MethodC@1();
+        };
+        interface Duplicated {
+            MethodA@0();
+            MethodB@1();
+            MethodC@1();
+        };
+        interface Gap {
+            MethodA@0();
+            MethodB@2();
+        };
+        interface Valid {
+            MethodB@1();
+            MethodA@0();
+        };
+        interface Implicit {
+            MethodA();
+            MethodB();
+        };
+        "#;
+
+        assert!(check(input, MethodOrdinalCheck::Off).is_empty());
+
+        let diagnostics = check(input, MethodOrdinalCheck::On);
+        assert_eq!(
+            vec![
+                ORDINAL_MISSING_CODE,
+                ORDINAL_DUPLICATE_CODE,
+                ORDINAL_GAP_CODE
+            ],
+            codes(&diagnostics)
+        );
+        // The implicit method in the mixed interface.
+        assert_eq!("MethodB", partial_text(input, &diagnostics[0].range));
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+        assert_eq!(3, line_of(input, diagnostics[0].range.start) - 1);
+        // Only the ordinal token is highlighted.
+        assert_eq!("@1", partial_text(input, &diagnostics[1].range));
+        assert_eq!(9, line_of(input, diagnostics[1].range.start) - 1);
+        assert_eq!("@2", partial_text(input, &diagnostics[2].range));
+
+        let diagnostics = check(input, MethodOrdinalCheck::Strict);
+        assert_eq!(4, diagnostics.len());
+        assert_eq!(ORDINAL_IMPLICIT_CODE, diagnostics[3].code);
+        assert_eq!("Implicit", partial_text(input, &diagnostics[3].range));
+        assert_eq!(Severity::Information, diagnostics[3].severity);
+    }
+
+    #[test]
+    fn test_field_ordinals() {
+        let input = r#"
+        struct Gap {
+            int32 a@0;
+            int32 b@2;
+        };
+        struct Valid {
+            int32 b@1;
+            int32 a@0;
+            int32 c@2;
+        };
+        union Mixed {
+            int32 a@0;
+            string b;
+        };
+        interface Foo {
+            Method(int32 a@1, int32 b@1, int32 c@0) => (int32 d@0, int32 e@4294967296);
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_field_ordinals(input, &mojom, &mut diagnostics);
+        assert_eq!(
+            vec![
+                ORDINAL_GAP_CODE,
+                ORDINAL_MISSING_CODE,
+                ORDINAL_DUPLICATE_CODE,
+                ORDINAL_INVALID_CODE
+            ],
+            codes(&diagnostics)
+        );
+        // Mixed and sparse ordinals have the same severities as in interfaces.
+        let severities = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.severity)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Severity::Warning,
+                Severity::Warning,
+                Severity::Error,
+                Severity::Error
+            ],
+            severities
+        );
+        // Only the ordinal token is highlighted.
+        assert_eq!("@2", partial_text(input, &diagnostics[0].range));
+        assert_eq!(4, line_of(input, diagnostics[0].range.start));
+        assert_eq!("b", partial_text(input, &diagnostics[1].range));
+        assert_eq!("@1", partial_text(input, &diagnostics[2].range));
+        assert!(diagnostics[2].message.contains("parameters of `Method`"));
+        let related = diagnostics[2].related.as_ref().unwrap();
+        assert_eq!(related.range.start, input.find("a@1").unwrap() + 1);
+        assert_eq!("@4294967296", partial_text(input, &diagnostics[3].range));
+    }
+
+    #[test]
+    fn test_type_references() {
+        let input = r#"
+        module my.mod;
+        struct Foo {
+            enum Kind { kA };
+            Kind kind;
+            array<map<string, Missing>>? values;
+            handle<message_pipe> pipe;
+            my.mod.Bar bar;
+            mod.Bar partially_qualified;
+            imported.Baz baz;
+        };
+        union Bar { Foo.Kind kind; pending_remote<Unknown> remote; };
+        interface Iface {
+            Method(Foo foo, Bar& request) => (map<Kind, string> result);
+        };
+        const uint8 kValue = 1;
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let is_external = |name: &str| name == "imported.Baz";
+        check_type_references(input, &mojom, Some("my.mod"), is_external, &mut diagnostics);
+        let names = diagnostics
+            .iter()
+            .map(|diagnostic| partial_text(input, &diagnostic.range))
+            .collect::<Vec<_>>();
+        // `Kind` in `Iface` isn't visible because it's declared in `Foo`.
+        assert_eq!(vec!["Missing", "Unknown", "Kind"], names);
+        assert_eq!(UNRESOLVED_TYPE_CODE, diagnostics[0].code);
+        assert_eq!(Severity::Warning, diagnostics[0].severity);
+    }
+
+    #[test]
+    fn test_strip_module_qualifier() {
+        assert_eq!(
+            Some("MyStruct"),
+            strip_module_qualifier("foo.bar", "foo.bar.MyStruct")
+        );
+        assert_eq!(
+            Some("MyStruct"),
+            strip_module_qualifier("foo.bar", "bar.MyStruct")
+        );
+        assert_eq!(
+            Some("MyIface.MyEnum"),
+            strip_module_qualifier("foo.bar", "bar.MyIface.MyEnum")
+        );
+        assert_eq!(Some("S"), strip_module_qualifier("a.b.c", "b.c.S"));
+        assert_eq!(Some("S"), strip_module_qualifier("a.b.c", "c.S"));
+        // Qualifiers are whole parts of the module name.
+        assert_eq!(None, strip_module_qualifier("foo.bar", "ar.MyStruct"));
+        assert_eq!(None, strip_module_qualifier("foo.bar", "foo.MyStruct"));
+        assert_eq!(None, strip_module_qualifier("foo.bar", "MyStruct"));
+    }
+
+    #[test]
+    fn test_ambiguous_references() {
+        let input = r#"
+        struct Foo {
+            bar.Shared shared;
+            array<bar.Shared> values;
+            bar.Unique unique;
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let is_ambiguous = |name: &str| name == "bar.Shared";
+        check_ambiguous_references(input, &mojom, is_ambiguous, &mut diagnostics);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(AMBIGUOUS_REFERENCE_CODE, diagnostics[1].code);
+        assert_eq!(Severity::Hint, diagnostics[1].severity);
+        assert_eq!("bar.Shared", partial_text(input, &diagnostics[1].range));
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let input = r#"
+        import "a.mojom";
+        import "b.mojom";
+        [Attr] import "c.mojom";
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_unused_imports(input, &mojom, |index| index == 1, &mut diagnostics);
+        let ranges = diagnostics
+            .iter()
+            .map(|diagnostic| partial_text(input, &diagnostic.range))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![r#"import "a.mojom";"#, r#"[Attr] import "c.mojom";"#],
+            ranges
+        );
+        assert_eq!(UNUSED_IMPORT_CODE, diagnostics[0].code);
+        assert_eq!(Severity::Hint, diagnostics[0].severity);
+        assert!(diagnostics[0].unnecessary);
+        assert_eq!(r#"Unused import "a.mojom""#, diagnostics[0].message);
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let input = r#"
+        struct Foo {
+            int32 a;
+            // Members may share names with top-level types.
+            Foo Foo;
+            string a;
+        };
+        interface Foo {
+            enum Kind { kA, kB, kA };
+            Method();
+            Method(int32 a);
+        };
+        union Bar { int32 x; string x; };
+        const int32 Bar = 1;
+        enum Kind { kA };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_duplicate_names(input, &mojom, &mut diagnostics);
+        let names = diagnostics
+            .iter()
+            .map(|diagnostic| partial_text(input, &diagnostic.range))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "Foo", "kA", "Method", "x", "Bar"], names);
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.code == DUPLICATE_NAME_CODE));
+
+        // The field `a` is first defined at line 3.
+        assert!(diagnostics[0].message.contains("line 3"));
+        let related = diagnostics[0].related.as_ref().unwrap();
+        assert_eq!(3, line_of(input, related.range.start));
+        // The interface collides with the struct, not with the field.
+        let related = diagnostics[1].related.as_ref().unwrap();
+        assert_eq!(2, line_of(input, related.range.start));
+        assert_eq!(8, line_of(input, diagnostics[1].range.start));
+
+        // Each feature has its own `name`.
+        let input = r#"
+        feature kFoo { const string name = "Foo"; };
+        feature kBar { const string name = "Bar"; const bool name = false; };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_duplicate_names(input, &mojom, &mut diagnostics);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(3, line_of(input, diagnostics[0].range.start));
+    }
+
+    #[test]
+    fn test_conditional_duplicate_names() {
+        let input = r#"
+        struct Foo {
+            [EnableIf=is_win] int32 window;
+            [EnableIfNot=is_win] uint64 window;
+        };
+        [EnableIf=is_android] interface Bar { Method(); };
+        [EnableIfNot=is_android] interface Bar {
+            [EnableIf=is_win] Method();
+            [EnableIf=is_mac] Method();
+        };
+        enum Kind { kA, [EnableIf=is_win] kB, [EnableIfNot=is_win] kB };
+        union Baz { [EnableIf=is_win] int32 x; [EnableIfNot=is_win] string x; };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_duplicate_names(input, &mojom, &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let input = r#"
+        struct Foo {
+            [EnableIf=is_win] int32 window;
+            [EnableIf=is_win] uint64 window;
+            int32 size;
+            [EnableIfNot=is_mac] int32 size;
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_duplicate_names(input, &mojom, &mut diagnostics);
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.code == CONDITIONAL_DUPLICATE_NAME_CODE));
+        assert_eq!(4, line_of(input, diagnostics[0].range.start));
+        assert_eq!(
+            "Duplicate name `window` when `EnableIf=is_win` (first defined at line 3)",
+            diagnostics[0].message
+        );
+        assert_eq!(
+            "Duplicate name `size` when `EnableIfNot=is_mac` (first defined at line 5)",
+            diagnostics[1].message
+        );
+
+        // A declaration without a condition collides with both variants.
+        let input = r#"
+        [EnableIf=is_win] const int32 kSize = 1;
+        [EnableIfNot=is_win] const int32 kSize = 2;
+        const int32 kSize = 3;
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_duplicate_names(input, &mojom, &mut diagnostics);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(4, line_of(input, diagnostics[0].range.start));
+        let related = diagnostics[0].related.as_ref().unwrap();
+        assert_eq!(2, line_of(input, related.range.start));
+    }
+
+    #[test]
+    fn test_nullable_types() {
+        let input = r#"
+        struct Valid {
+            int32? maybe_count;
+            bool? maybe_flag;
+            map<string, uint64?> sizes;
+            array<string?> names;
+            handle<message_pipe>? pipe;
+        };
+        struct Invalid {
+            map<string?, int32> nullable_key;
+            array<handle?, 4> nullable_elements;
+            map<string, array<int32?, 2>> nested;
+        };
+        interface Foo {
+            Method(int32? value) => (map<int32?, string> result);
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_nullable_types(input, &mojom, &mut diagnostics);
+        let types = diagnostics
+            .iter()
+            .map(|diagnostic| partial_text(input, &diagnostic.range))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "map<string?, int32>",
+                "array<handle?, 4>",
+                "map<string, array<int32?, 2>>",
+                "map<int32?, string>",
+            ],
+            types
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.code == INVALID_NULLABLE_CODE));
+    }
+
+    #[test]
+    fn test_type_specs() {
+        let input = r#"
+        struct Foo {
+            array<uint8, 16> valid;
+            array<uint8, 0> empty;
+            array<array<int32, 99999999999999999999>> overflow;
+        };
+        interface Bar {
+            Method(map<string, array<bool, 4294967296>> too_large);
+        };
+        "#;
+        let mojom = syntax::parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_type_specs(input, &mojom, &mut diagnostics);
+        let sizes = diagnostics
+            .iter()
+            .map(|diagnostic| partial_text(input, &diagnostic.range))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["0", "99999999999999999999", "4294967296"], sizes);
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.code == INVALID_ARRAY_SIZE_CODE));
+    }
+
+    #[test]
+    fn test_effective_ordinals() {
+        let input = "@3 @0";
+        let at3 = syntax::Range { start: 0, end: 2 };
+        let at0 = syntax::Range { start: 3, end: 5 };
+        let ordinals = [None, Some(&at3), None, Some(&at0), None];
+        assert_eq!(vec![0, 3, 4, 0, 1], effective_ordinals(input, &ordinals));
+        assert!(effective_ordinals(input, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_method_ordinal_overflow() {
+        let input = "interface Foo { MethodA@99999999999(); };";
+        let diagnostics = check(input, MethodOrdinalCheck::On);
+        assert_eq!(vec![ORDINAL_INVALID_CODE], codes(&diagnostics));
+    }
+}
@@ -0,0 +1,590 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks of `[MinVersion]` and `[Stable]` attributes, which versioned
+//! mojoms use to stay compatible across releases.
+
+use std::collections::HashMap;
+
+use crate::syntax::{self, preorder, AttributeList, MojomFile, Traversal, TypeSpec};
+
+use super::diagnostic::Diagnostic;
+use super::semantic::{effective_ordinals, strip_module_qualifier};
+
+const MIN_VERSION_INVALID_CODE: &str = "mojom/min-version-invalid";
+const MIN_VERSION_ORDER_CODE: &str = "mojom/min-version-order";
+const MIN_VERSION_NON_NULLABLE_CODE: &str = "mojom/min-version-non-nullable";
+const STABLE_DEPENDENCY_CODE: &str = "mojom/stable-dependency";
+
+fn partial_text<'a>(text: &'a str, range: &syntax::Range) -> &'a str {
+    &text[range.start..range.end]
+}
+
+fn is_stable(text: &str, attributes: &Option<AttributeList>) -> bool {
+    attributes
+        .as_ref()
+        .is_some_and(|attributes| attributes.find(text, "Stable").is_some())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Struct,
+    Union,
+    Enum,
+    Interface,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeclaredType {
+    kind: Kind,
+    stable: bool,
+}
+
+// User-defined types of a document keyed by their qualified names.
+pub(crate) struct TypeTable<'a> {
+    module_name: Option<&'a str>,
+    types: HashMap<String, DeclaredType>,
+}
+
+impl<'a> TypeTable<'a> {
+    pub(crate) fn new(text: &'a str, mojom: &MojomFile) -> Self {
+        let mut module_name = None;
+        let mut types = HashMap::new();
+        let mut scope: Vec<&str> = Vec::new();
+        let mut declare =
+            |scope: &[&str], name: &syntax::Range, kind, attributes: &Option<AttributeList>| {
+                let mut path = scope.to_vec();
+                path.push(partial_text(text, name));
+                let stable = is_stable(text, attributes);
+                types
+                    .entry(path.join("."))
+                    .or_insert(DeclaredType { kind, stable });
+            };
+        for traversal in preorder(mojom) {
+            match traversal {
+                Traversal::Module(node) => {
+                    module_name.get_or_insert(partial_text(text, &node.name));
+                }
+                Traversal::EnterInterface(node) => {
+                    declare(&scope, &node.name, Kind::Interface, &node.attributes);
+                    scope.push(partial_text(text, &node.name));
+                }
+                Traversal::EnterStruct(node) => {
+                    declare(&scope, &node.name, Kind::Struct, &node.attributes);
+                    scope.push(partial_text(text, &node.name));
+                }
+                Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                    scope.pop();
+                }
+                Traversal::Union(node) => {
+                    declare(&scope, &node.name, Kind::Union, &node.attributes)
+                }
+                Traversal::Enum(node) => declare(&scope, &node.name, Kind::Enum, &node.attributes),
+                _ => (),
+            }
+        }
+        TypeTable { module_name, types }
+    }
+
+    // Looks up `name` that is referenced in `scope` and returns the qualified
+    // name of the declaration. Inner scopes are searched first. Names
+    // qualified with the module name are also looked up.
+    fn lookup(&self, scope: &[&str], name: &str) -> Option<(String, DeclaredType)> {
+        let local_name = self
+            .module_name
+            .and_then(|module_name| strip_module_qualifier(module_name, name));
+        (0..=scope.len()).rev().find_map(|depth| {
+            std::iter::once(name).chain(local_name).find_map(|name| {
+                let mut path = scope[..depth].to_vec();
+                path.push(name);
+                let qualified = path.join(".");
+                let declared = self.types.get(&qualified).copied()?;
+                Some((qualified, declared))
+            })
+        })
+    }
+
+    fn resolve(&self, scope: &[&str], name: &str) -> Option<DeclaredType> {
+        self.lookup(scope, name).map(|(_, declared)| declared)
+    }
+
+    // Returns the qualified name of the declaration that `name` in `scope`
+    // refers to and whether it's a struct or a union.
+    pub(crate) fn resolve_container(&self, scope: &[&str], name: &str) -> Option<(String, bool)> {
+        self.lookup(scope, name).map(|(qualified, declared)| {
+            let is_container = matches!(declared.kind, Kind::Struct | Kind::Union);
+            (qualified, is_container)
+        })
+    }
+
+    // Returns true when `spec` is encoded as a pointer or a handle. Named
+    // types that aren't declared in the document are unknown and return
+    // false.
+    fn is_reference_type(&self, scope: &[&str], spec: &TypeSpec) -> bool {
+        if spec.is_reference_type() {
+            return true;
+        }
+        match &spec.type_name {
+            syntax::TypeName::BasicTypeName(name) => self
+                .resolve(scope, name)
+                .is_some_and(|declared| declared.kind != Kind::Enum),
+            _ => false,
+        }
+    }
+
+    // Returns true when `spec` is a nullable numeric type or a nullable enum
+    // declared in the document.
+    pub(crate) fn is_nullable_value_type(&self, scope: &[&str], spec: &TypeSpec) -> bool {
+        if spec.is_nullable_value_type() {
+            return true;
+        }
+        match &spec.type_name {
+            syntax::TypeName::BasicTypeName(name) if spec.is_nullable => self
+                .resolve(scope, name)
+                .is_some_and(|declared| declared.kind == Kind::Enum),
+            _ => false,
+        }
+    }
+}
+
+// A struct field or a parameter.
+struct VersionedField<'a> {
+    name: &'a syntax::Range,
+    typ: &'a syntax::Range,
+    spec: Option<TypeSpec>,
+    ordinal: Option<&'a syntax::Range>,
+    attributes: &'a Option<AttributeList>,
+}
+
+impl<'a> VersionedField<'a> {
+    fn field(text: &str, field: &'a syntax::StructField) -> Self {
+        VersionedField {
+            name: &field.name,
+            typ: &field.typ,
+            spec: field.type_spec(text).ok(),
+            ordinal: field.ordinal.as_ref(),
+            attributes: &field.attributes,
+        }
+    }
+
+    fn param(text: &str, param: &'a syntax::Parameter) -> Self {
+        VersionedField {
+            name: &param.name,
+            typ: &param.typ,
+            spec: param.type_spec(text).ok(),
+            ordinal: param.ordinal.as_ref(),
+            attributes: &param.attributes,
+        }
+    }
+}
+
+// Returns the MinVersion of `field`, which is 0 without the attribute.
+// Reports a value that isn't a u32 and returns None.
+fn min_version(
+    text: &str,
+    field: &VersionedField,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<u32> {
+    let attribute = field
+        .attributes
+        .as_ref()
+        .and_then(|attributes| attributes.find(text, "MinVersion"));
+    let attribute = match attribute {
+        Some(attribute) => attribute,
+        None => return Some(0),
+    };
+    let parsed = attribute
+        .value
+        .as_ref()
+        .and_then(|value| partial_text(text, value).parse::<u32>().ok());
+    if parsed.is_none() {
+        let range = attribute.value.as_ref().unwrap_or(&attribute.name);
+        let message = format!(
+            "MinVersion of `{}` must be a non-negative integer",
+            partial_text(text, field.name)
+        );
+        diagnostics.push(Diagnostic::error(MIN_VERSION_INVALID_CODE, *range, message));
+    }
+    parsed
+}
+
+// Checks the MinVersions of the fields of a struct or the parameters of a
+// method. `container` describes them in messages, e.g. "struct `Foo`".
+fn check_fields(
+    text: &str,
+    types: &TypeTable,
+    scope: &[&str],
+    container: &str,
+    fields: &[VersionedField],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let ordinals = fields.iter().map(|field| field.ordinal).collect::<Vec<_>>();
+    let ordinals = effective_ordinals(text, &ordinals);
+    let mut versioned = Vec::new();
+    for (field, ordinal) in fields.iter().zip(ordinals) {
+        let name = partial_text(text, field.name);
+        let version = match min_version(text, field, diagnostics) {
+            Some(version) => version,
+            None => continue,
+        };
+        versioned.push((ordinal, version, field));
+        if version == 0 {
+            continue;
+        }
+        let spec = match &field.spec {
+            Some(spec) => spec,
+            None => continue,
+        };
+        if !spec.is_nullable && types.is_reference_type(scope, spec) {
+            let message = format!(
+                "Non-nullable reference fields are only allowed in version 0 of {}. \
+                 `{}` is defined with [MinVersion={}].",
+                container, name, version
+            );
+            diagnostics.push(Diagnostic::error(
+                MIN_VERSION_NON_NULLABLE_CODE,
+                *field.typ,
+                message,
+            ));
+        }
+    }
+
+    // Fields are added in ordinal order, so MinVersion never decreases.
+    versioned.sort_by_key(|&(ordinal, _, _)| ordinal);
+    let mut latest: Option<(u32, &VersionedField)> = None;
+    for &(_, version, field) in &versioned {
+        match latest {
+            Some((latest_version, latest_field)) if version < latest_version => {
+                let message = format!(
+                    "`{}` has MinVersion {} but `{}`, which has a lower ordinal, has \
+                     MinVersion {}. MinVersion must not decrease in ordinal order.",
+                    partial_text(text, field.name),
+                    version,
+                    partial_text(text, latest_field.name),
+                    latest_version
+                );
+                let diagnostic = Diagnostic::error(MIN_VERSION_ORDER_CODE, *field.name, message)
+                    .with_related(*latest_field.name, format!("MinVersion {}", latest_version));
+                diagnostics.push(diagnostic);
+            }
+            _ => latest = Some((version, field)),
+        }
+    }
+}
+
+// Checks `[MinVersion]` of struct fields and method parameters: values are
+// u32, MinVersion doesn't decrease in ordinal order and fields added after
+// version 0 are nullable when they are reference types.
+pub(crate) fn check_min_versions(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<Diagnostic>) {
+    let types = TypeTable::new(text, mojom);
+    let mut scope = Vec::new();
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => scope.push(partial_text(text, &node.name)),
+            Traversal::EnterStruct(node) => {
+                let name = partial_text(text, &node.name);
+                let container = format!("struct `{}`", name);
+                let fields = node
+                    .members
+                    .iter()
+                    .filter_map(|member| match member {
+                        syntax::StructBody::Field(field) => {
+                            Some(VersionedField::field(text, field))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                scope.push(name);
+                check_fields(text, &types, &scope, &container, &fields, diagnostics);
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+            }
+            Traversal::Method(node) => {
+                let container = format!("method `{}`", partial_text(text, &node.name));
+                let params = node.params.iter();
+                let params = params.map(|param| VersionedField::param(text, param));
+                let params = params.collect::<Vec<_>>();
+                check_fields(text, &types, &scope, &container, &params, diagnostics);
+                if let Some(response) = &node.response {
+                    let container = format!("the response of {}", container);
+                    let params = response.params.iter();
+                    let params = params.map(|param| VersionedField::param(text, param));
+                    let params = params.collect::<Vec<_>>();
+                    check_fields(text, &types, &scope, &container, &params, diagnostics);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// A type reference in a `[Stable]` declaration.
+struct StableReference<'a> {
+    // The `[Stable]` declaration.
+    name: &'a syntax::Range,
+    scope: Vec<&'a str>,
+    typ: &'a syntax::Range,
+    spec: Option<TypeSpec>,
+}
+
+// Collects type references of `[Stable]` structs, unions and interfaces.
+// Members of nested declarations are included.
+fn stable_references<'a>(text: &'a str, mojom: &'a MojomFile) -> Vec<StableReference<'a>> {
+    let mut references = Vec::new();
+    let mut scope = Vec::new();
+    // The innermost `[Stable]` declaration and the depth of `scope` at which it
+    // was entered.
+    let mut stable: Vec<(&syntax::Range, usize)> = Vec::new();
+    for traversal in preorder(mojom) {
+        let typs: Vec<(&syntax::Range, Option<TypeSpec>)> = match traversal {
+            Traversal::EnterInterface(node) => {
+                if is_stable(text, &node.attributes) {
+                    stable.push((&node.name, scope.len()));
+                }
+                scope.push(partial_text(text, &node.name));
+                continue;
+            }
+            Traversal::EnterStruct(node) => {
+                if is_stable(text, &node.attributes) {
+                    stable.push((&node.name, scope.len()));
+                }
+                scope.push(partial_text(text, &node.name));
+                continue;
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+                if stable
+                    .last()
+                    .is_some_and(|&(_, depth)| depth == scope.len())
+                {
+                    stable.pop();
+                }
+                continue;
+            }
+            Traversal::Union(node) if is_stable(text, &node.attributes) => {
+                references.extend(node.fields.iter().map(|field| StableReference {
+                    name: &node.name,
+                    scope: scope.clone(),
+                    typ: &field.typ,
+                    spec: field.type_spec(text).ok(),
+                }));
+                continue;
+            }
+            Traversal::StructField(node) => vec![(&node.typ, node.type_spec(text).ok())],
+            Traversal::Method(node) => node
+                .params
+                .iter()
+                .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                .map(|param| (&param.typ, param.type_spec(text).ok()))
+                .collect(),
+            _ => continue,
+        };
+        if let Some(&(name, _)) = stable.last() {
+            references.extend(typs.into_iter().map(|(typ, spec)| StableReference {
+                name,
+                scope: scope.clone(),
+                typ,
+                spec,
+            }));
+        }
+    }
+    references
+}
+
+fn report_unstable_dependency(
+    text: &str,
+    reference: &StableReference,
+    ident: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let message = format!(
+        "[Stable] `{}` depends on `{}`, which is not marked [Stable]",
+        partial_text(text, reference.name),
+        ident
+    );
+    diagnostics.push(Diagnostic::error(
+        STABLE_DEPENDENCY_CODE,
+        *reference.typ,
+        message,
+    ));
+}
+
+// Reports types declared in the document that `[Stable]` structs, unions and
+// interfaces use without being `[Stable]` themselves.
+pub(crate) fn check_stable_types(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<Diagnostic>) {
+    let types = TypeTable::new(text, mojom);
+    for reference in stable_references(text, mojom) {
+        let spec = match &reference.spec {
+            Some(spec) => spec,
+            None => continue,
+        };
+        for ident in spec.referenced_idents() {
+            match types.resolve(&reference.scope, ident) {
+                Some(declared) if !declared.stable => {
+                    report_unstable_dependency(text, &reference, ident, diagnostics)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Reports imported types that `[Stable]` structs, unions and interfaces use
+/// without being `[Stable]` themselves. `is_stable` is called for names that
+/// aren't declared in the document and returns None for unknown names.
+pub fn check_stable_imports<F>(
+    text: &str,
+    mojom: &MojomFile,
+    mut is_stable: F,
+    diagnostics: &mut Vec<Diagnostic>,
+) where
+    F: FnMut(&str) -> Option<bool>,
+{
+    let types = TypeTable::new(text, mojom);
+    for reference in stable_references(text, mojom) {
+        let spec = match &reference.spec {
+            Some(spec) => spec,
+            None => continue,
+        };
+        for ident in spec.referenced_idents() {
+            if types.resolve(&reference.scope, ident).is_some() {
+                continue;
+            }
+            if is_stable(ident) == Some(false) {
+                report_unstable_dependency(text, &reference, ident, diagnostics);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_testdata(name: &str) -> String {
+        let path = std::path::Path::new("testdata/versioning").join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn check(text: &str) -> Vec<Diagnostic> {
+        let mojom = syntax::parse(text).unwrap();
+        let mut diagnostics = Vec::new();
+        check_min_versions(text, &mojom, &mut diagnostics);
+        check_stable_types(text, &mojom, &mut diagnostics);
+        diagnostics
+    }
+
+    // Returns the codes and the texts of the ranges of `diagnostics`.
+    fn summary<'a>(text: &'a str, diagnostics: &[Diagnostic]) -> Vec<(&'static str, &'a str)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, partial_text(text, &diagnostic.range)))
+            .collect()
+    }
+
+    #[test]
+    fn test_min_version_invalid() {
+        let text = read_testdata("min_version_invalid.mojom");
+        let diagnostics = check(&text);
+        assert_eq!(
+            vec![
+                (MIN_VERSION_INVALID_CODE, "-1"),
+                (MIN_VERSION_INVALID_CODE, "MinVersion"),
+                (MIN_VERSION_INVALID_CODE, "two"),
+            ],
+            summary(&text, &diagnostics)
+        );
+        assert_eq!(
+            "MinVersion of `negative` must be a non-negative integer",
+            diagnostics[0].message
+        );
+    }
+
+    #[test]
+    fn test_min_version_order() {
+        let text = read_testdata("min_version_order.mojom");
+        let diagnostics = check(&text);
+        assert_eq!(
+            vec![
+                (MIN_VERSION_ORDER_CODE, "older"),
+                (MIN_VERSION_ORDER_CODE, "done"),
+            ],
+            summary(&text, &diagnostics)
+        );
+        assert_eq!(
+            "`older` has MinVersion 1 but `newer`, which has a lower ordinal, has MinVersion 2. \
+             MinVersion must not decrease in ordinal order.",
+            diagnostics[0].message
+        );
+        let related = diagnostics[0].related.as_ref().unwrap();
+        assert_eq!("newer", partial_text(&text, &related.range));
+    }
+
+    #[test]
+    fn test_min_version_non_nullable() {
+        let text = read_testdata("min_version_non_nullable.mojom");
+        let diagnostics = check(&text);
+        assert_eq!(
+            vec![
+                (MIN_VERSION_NON_NULLABLE_CODE, "string"),
+                (MIN_VERSION_NON_NULLABLE_CODE, "array<int32>"),
+                (MIN_VERSION_NON_NULLABLE_CODE, "map<string, int32>"),
+                (MIN_VERSION_NON_NULLABLE_CODE, "Inner"),
+                (MIN_VERSION_NON_NULLABLE_CODE, "handle<message_pipe>"),
+                (MIN_VERSION_NON_NULLABLE_CODE, "pending_remote<Listener>"),
+            ],
+            summary(&text, &diagnostics)
+        );
+        assert_eq!(
+            "Non-nullable reference fields are only allowed in version 0 of struct \
+             `Versioned`. `name` is defined with [MinVersion=1].",
+            diagnostics[0].message
+        );
+        assert!(diagnostics[5].message.contains("of method `AddListener`"));
+    }
+
+    #[test]
+    fn test_stable_types() {
+        let text = read_testdata("stable.mojom");
+        let diagnostics = check(&text);
+        assert_eq!(
+            vec![
+                (STABLE_DEPENDENCY_CODE, "Unstable"),
+                (STABLE_DEPENDENCY_CODE, "array<UnstableEnum>"),
+                (STABLE_DEPENDENCY_CODE, "Unstable?"),
+                (STABLE_DEPENDENCY_CODE, "UnstableEnum"),
+            ],
+            summary(&text, &diagnostics)
+        );
+        assert_eq!(
+            "[Stable] `StableStruct` depends on `Unstable`, which is not marked [Stable]",
+            diagnostics[0].message
+        );
+
+        // Imported types are looked up by `is_stable`.
+        let mojom = syntax::parse(&text).unwrap();
+        let mut diagnostics = Vec::new();
+        let is_stable = |name: &str| match name {
+            "versioning.imported.StableType" => Some(true),
+            "versioning.imported.UnstableType" => Some(false),
+            _ => None,
+        };
+        check_stable_imports(&text, &mojom, is_stable, &mut diagnostics);
+        assert_eq!(
+            vec![(STABLE_DEPENDENCY_CODE, "versioning.imported.UnstableType")],
+            summary(&text, &diagnostics)
+        );
+    }
+}
@@ -12,61 +12,252 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::env;
-use std::fs;
-use std::io::{BufReader, Read};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+use serde_json::json;
+use structopt::StructOpt;
+
+// The number of the slowest files shown in the summary.
+const SLOWEST_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Invalid format: {}", s)),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+struct Opt {
+    /// Output format: text or json. With json, a single JSON document is
+    /// written to stdout and progress is written to stderr.
+    #[structopt(long, default_value = "text")]
+    format: Format,
+    /// Doesn't print files that are parsed successfully.
+    #[structopt(long)]
+    quiet: bool,
+    /// Stops at the first file that fails to parse.
+    #[structopt(long)]
+    fail_fast: bool,
+    /// Chromium src path.
+    #[structopt(parse(from_os_str))]
+    chromium_path: PathBuf,
+}
+
 #[derive(Debug)]
-struct ParseResult {
-    path: PathBuf,
-    parse_time: Duration,
+struct ParseError {
+    message: String,
+    // One-based line and column. None when the file couldn't be read.
+    location: Option<(usize, usize)>,
 }
 
-fn parse_single_mojom<P: AsRef<Path>>(path: P) -> ParseResult {
-    let mut reader = fs::File::open(path.as_ref())
-        .map(|file| BufReader::new(file))
-        .expect("Failed to open file");
-    let mut input = String::new();
-    reader.read_to_string(&mut input).unwrap();
+#[derive(Debug)]
+enum ParseResult {
+    Ok { path: PathBuf, parse_time: Duration },
+    Err { path: PathBuf, error: ParseError },
+}
+
+impl ParseResult {
+    fn is_ok(&self) -> bool {
+        matches!(self, ParseResult::Ok { .. })
+    }
+}
+
+fn parse_single_mojom(path: PathBuf) -> ParseResult {
+    let input = match std::fs::read_to_string(&path) {
+        Ok(input) => input,
+        Err(err) => {
+            let error = ParseError {
+                message: err.to_string(),
+                location: None,
+            };
+            return ParseResult::Err { path, error };
+        }
+    };
 
     let instant = Instant::now();
     match mojom_lsp::syntax::parse(&input) {
-        Ok(_mojom) => {
-            let elapsed = instant.elapsed();
-            println!("OK: {:?}", path.as_ref());
-            println!("    Took: {:?}", elapsed);
-            return ParseResult {
-                path: path.as_ref().to_owned(),
-                parse_time: elapsed,
+        Ok(_mojom) => ParseResult::Ok {
+            path,
+            parse_time: instant.elapsed(),
+        },
+        Err(err) => {
+            let (start, _) = err.range();
+            let error = ParseError {
+                message: err.to_string(),
+                location: Some((start.line + 1, start.col + 1)),
             };
+            ParseResult::Err { path, error }
         }
-        Err(err) => {
-            println!("Err: {:?}", path.as_ref());
-            println!("{:#?}", err);
-            std::process::exit(1);
+    }
+}
+
+// Returns a human-readable description of `result`.
+fn describe_result(result: &ParseResult) -> String {
+    match result {
+        ParseResult::Ok { path, parse_time } => {
+            format!("OK: {} ({:?})", path.display(), parse_time)
         }
+        ParseResult::Err { path, error } => match error.location {
+            Some((line, col)) => format!(
+                "Err: {}:{}:{}\n{}",
+                path.display(),
+                line,
+                col,
+                error.message
+            ),
+            None => format!("Err: {}: {}", path.display(), error.message),
+        },
+    }
+}
+
+fn json_report(results: &[ParseResult]) -> serde_json::Value {
+    let entries = results
+        .iter()
+        .map(|result| match result {
+            ParseResult::Ok { path, parse_time } => json!({
+                "path": path.display().to_string(),
+                "ok": true,
+                "parse_time_us": parse_time.as_micros() as u64,
+                "error": null,
+            }),
+            ParseResult::Err { path, error } => json!({
+                "path": path.display().to_string(),
+                "ok": false,
+                "parse_time_us": null,
+                "error": {
+                    "message": error.message,
+                    "line": error.location.map(|(line, _)| line),
+                    "col": error.location.map(|(_, col)| col),
+                },
+            }),
+        })
+        .collect::<Vec<_>>();
+    let passed = results.iter().filter(|result| result.is_ok()).count();
+    json!({
+        "results": entries,
+        "summary": {
+            "total": results.len(),
+            "passed": passed,
+            "failed": results.len() - passed,
+        },
+    })
+}
+
+fn print_summary(results: &[ParseResult]) {
+    let passed = results.iter().filter(|result| result.is_ok()).count();
+    println!();
+    println!("Total: {}", results.len());
+    println!("Passed: {}", passed);
+    println!("Failed: {}", results.len() - passed);
+
+    let mut times = results
+        .iter()
+        .filter_map(|result| match result {
+            ParseResult::Ok { path, parse_time } => Some((path, parse_time)),
+            ParseResult::Err { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    times.sort_by(|a, b| b.1.cmp(a.1));
+    if !times.is_empty() {
+        println!("Slowest:");
+    }
+    for (path, parse_time) in times.iter().take(SLOWEST_COUNT) {
+        println!("    {:?} {}", parse_time, path.display());
     }
 }
 
-fn parse_chromium_mojom_files<P: AsRef<Path>>(path: P) {
+fn parse_chromium_mojom_files(opt: &Opt) -> Vec<ParseResult> {
     let mut results = Vec::new();
-    let pattern = path.as_ref().join("**/*.mojom");
+    let pattern = opt.chromium_path.join("**/*.mojom");
     let pattern = pattern.to_str().unwrap();
     let entries = glob::glob(pattern).unwrap().filter_map(|e| e.ok());
     for entry in entries {
         let result = parse_single_mojom(entry);
+        if !(opt.quiet && result.is_ok()) {
+            let description = describe_result(&result);
+            match opt.format {
+                Format::Text => println!("{}", description),
+                Format::Json => eprintln!("{}", description),
+            }
+        }
+        let failed = !result.is_ok();
         results.push(result);
+        if failed && opt.fail_fast {
+            break;
+        }
     }
-
-    let longest = (&results)
-        .iter()
-        .max_by(|a, b| a.parse_time.cmp(&b.parse_time));
-    println!("Longest: {:#?}", longest);
+    results
 }
 
 fn main() {
-    let chromium_path = env::args().nth(1).expect("Must specify chromium src path");
-    parse_chromium_mojom_files(&chromium_path);
+    let opt = Opt::from_args();
+    let results = parse_chromium_mojom_files(&opt);
+    match opt.format {
+        Format::Text => print_summary(&results),
+        Format::Json => println!("{}", json_report(&results)),
+    }
+    if results.iter().any(|result| !result.is_ok()) {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_report() {
+        let results = vec![
+            ParseResult::Ok {
+                path: PathBuf::from("a.mojom"),
+                parse_time: Duration::from_micros(42),
+            },
+            ParseResult::Err {
+                path: PathBuf::from("b.mojom"),
+                error: ParseError {
+                    message: "expected ';'".to_owned(),
+                    location: Some((3, 7)),
+                },
+            },
+            ParseResult::Err {
+                path: PathBuf::from("c.mojom"),
+                error: ParseError {
+                    message: "No such file".to_owned(),
+                    location: None,
+                },
+            },
+        ];
+        let report = json_report(&results);
+
+        let entries = report["results"].as_array().unwrap();
+        assert_eq!(3, entries.len());
+        assert_eq!(
+            json!({"path": "a.mojom", "ok": true, "parse_time_us": 42, "error": null}),
+            entries[0]
+        );
+        assert_eq!(false, entries[1]["ok"]);
+        assert!(entries[1]["parse_time_us"].is_null());
+        assert_eq!(
+            json!({"message": "expected ';'", "line": 3, "col": 7}),
+            entries[1]["error"]
+        );
+        assert!(entries[2]["error"]["line"].is_null());
+        assert_eq!(
+            json!({"total": 3, "passed": 1, "failed": 2}),
+            report["summary"]
+        );
+    }
 }
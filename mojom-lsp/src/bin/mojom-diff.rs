@@ -0,0 +1,205 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+use structopt::StructOpt;
+
+use mojom_lsp::analysis::{diff_mojom, Change};
+use mojom_lsp::syntax::{self, MojomFile, Range};
+
+#[derive(StructOpt)]
+struct Opt {
+    /// Prints changes as JSON.
+    #[structopt(long)]
+    json: bool,
+    /// The old version of the mojom file.
+    #[structopt(parse(from_os_str))]
+    old: PathBuf,
+    /// The new version of the mojom file.
+    #[structopt(parse(from_os_str))]
+    new: PathBuf,
+}
+
+fn parse_file(path: &Path, text: &str) -> Result<MojomFile, String> {
+    syntax::parse(text).map_err(|err| {
+        let (start, _) = err.range();
+        format!(
+            "{}:{}:{}: syntax error\n{}",
+            path.display(),
+            start.line + 1,
+            start.col + 1,
+            err
+        )
+    })
+}
+
+// Returns the 1-based line and column of `range`.
+fn location(text: &str, range: &Range) -> (usize, usize) {
+    syntax::line_col(text, range.start).map_or((0, 0), |pos| (pos.line + 1, pos.col + 1))
+}
+
+fn text_report(old_path: &Path, old_text: &str, changes: &[Change]) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let (line, col) = location(old_text, &change.old);
+            format!(
+                "{}:{}:{}: {}: {}\n",
+                old_path.display(),
+                line,
+                col,
+                change.kind,
+                change.message
+            )
+        })
+        .collect()
+}
+
+fn json_report(
+    old_path: &Path,
+    old_text: &str,
+    new_path: &Path,
+    new_text: &str,
+    changes: &[Change],
+) -> serde_json::Value {
+    let changes = changes
+        .iter()
+        .map(|change| {
+            let (line, col) = location(old_text, &change.old);
+            let new = change.new.as_ref().map(|range| {
+                let (line, col) = location(new_text, range);
+                json!({"line": line, "col": col})
+            });
+            json!({
+                "kind": change.kind.as_str(),
+                "name": change.name,
+                "message": change.message,
+                "old": {"line": line, "col": col},
+                "new": new,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "old": old_path.display().to_string(),
+        "new": new_path.display().to_string(),
+        "changes": changes,
+    })
+}
+
+// Compares the files and returns the exit code: 0 when there is no change, 1
+// when there are changes and 2 on errors.
+fn run(opt: &Opt) -> i32 {
+    let read = |path: &PathBuf| {
+        std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))
+    };
+    let texts = read(&opt.old).and_then(|old| Ok((old, read(&opt.new)?)));
+    let (old_text, new_text) = match texts {
+        Ok(texts) => texts,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    let files =
+        parse_file(&opt.old, &old_text).and_then(|old| Ok((old, parse_file(&opt.new, &new_text)?)));
+    let (old, new) = match files {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+
+    let changes = diff_mojom(&old, &old_text, &new, &new_text);
+    if opt.json {
+        let report = json_report(&opt.old, &old_text, &opt.new, &new_text, &changes);
+        println!("{}", report);
+    } else {
+        print!("{}", text_report(&opt.old, &old_text, &changes));
+    }
+    if changes.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    std::process::exit(run(&opt));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"
+interface Foo {
+  Bar(int32 x);
+  Baz();
+};
+"#;
+
+    const NEW: &str = r#"
+interface Foo {
+  Bar(string x);
+};
+"#;
+
+    #[test]
+    fn test_reports() {
+        let old = syntax::parse(OLD).unwrap();
+        let new = syntax::parse(NEW).unwrap();
+        let changes = diff_mojom(&old, OLD, &new, NEW);
+
+        let report = text_report(Path::new("foo.mojom"), OLD, &changes);
+        let expected = "foo.mojom:3:3: parameters-changed: Parameters of `Foo.Bar` changed from `(int32)` to `(string)`\n\
+                        foo.mojom:4:3: method-removed: Method `Foo.Baz` was removed\n";
+        assert_eq!(expected, report);
+
+        let report = json_report(
+            Path::new("old.mojom"),
+            OLD,
+            Path::new("new.mojom"),
+            NEW,
+            &changes,
+        );
+        let changes = report["changes"].as_array().unwrap();
+        assert_eq!(2, changes.len());
+        assert_eq!("parameters-changed", changes[0]["kind"]);
+        assert_eq!("Foo.Bar", changes[0]["name"]);
+        assert_eq!(json!({"line": 3, "col": 3}), changes[0]["old"]);
+        assert_eq!(json!({"line": 3, "col": 3}), changes[0]["new"]);
+        assert_eq!("method-removed", changes[1]["kind"]);
+        assert!(changes[1]["new"].is_null());
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let opt = Opt {
+            json: false,
+            old: PathBuf::from("testdata/my_interface.mojom"),
+            new: PathBuf::from("testdata/nonexistent.mojom"),
+        };
+        assert_eq!(2, run(&opt));
+        let opt = Opt {
+            json: false,
+            old: PathBuf::from("testdata/my_interface.mojom"),
+            new: PathBuf::from("testdata/my_interface.mojom"),
+        };
+        assert_eq!(0, run(&opt));
+    }
+}
@@ -12,19 +12,206 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
 use structopt::StructOpt;
 
+use mojom_lsp::analysis::{self, MethodOrdinalCheck, Severity};
+use mojom_lsp::syntax;
+
 #[derive(StructOpt)]
-struct Opt {}
+struct Opt {
+    /// Copies the protocol messages between the client and the server to a
+    /// file, e.g. for bug reports.
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Checks mojom files and prints diagnostics. Exits with 1 when any
+    /// error is found.
+    Check {
+        /// Enables the member ordering lint.
+        #[structopt(long)]
+        member_order: bool,
+        /// Enables all naming convention lints.
+        #[structopt(long)]
+        naming: bool,
+        /// Method ordinal validation: off, on or strict.
+        #[structopt(long, default_value = "off")]
+        method_ordinals: MethodOrdinalCheck,
+        /// Files to check.
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Received,
+    Sent,
+}
+
+// A log of raw protocol frames. A header line is written whenever the
+// direction changes because reads may split messages.
+struct ProtocolLog<L> {
+    log: L,
+    direction: Option<Direction>,
+}
+
+impl<L: Write> ProtocolLog<L> {
+    fn write(&mut self, direction: Direction, buf: &[u8]) {
+        if buf.is_empty() {
+            return;
+        }
+        // Failing to write the log doesn't stop the server.
+        if self.direction != Some(direction) {
+            let header = match direction {
+                Direction::Received => "\n--- client to server ---\n",
+                Direction::Sent => "\n--- server to client ---\n",
+            };
+            self.log.write_all(header.as_bytes()).ok();
+            self.direction = Some(direction);
+        }
+        self.log.write_all(buf).ok();
+        self.log.flush().ok();
+    }
+}
+
+// Copies bytes that go through `inner` to a protocol log.
+struct Tee<T, L> {
+    inner: T,
+    log: Arc<Mutex<ProtocolLog<L>>>,
+}
+
+impl<T: Read, L: Write> Read for Tee<T, L> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.log
+            .lock()
+            .unwrap()
+            .write(Direction::Received, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<T: Write, L: Write> Write for Tee<T, L> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.log.lock().unwrap().write(Direction::Sent, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Wraps `reader` and `writer` so that they copy what they transfer to `log`.
+fn tee<R, W, L>(reader: R, writer: W, log: L) -> (Tee<R, L>, Tee<W, L>) {
+    let log = Arc::new(Mutex::new(ProtocolLog {
+        log,
+        direction: None,
+    }));
+    let reader = Tee {
+        inner: reader,
+        log: log.clone(),
+    };
+    let writer = Tee { inner: writer, log };
+    (reader, writer)
+}
+
+// Checks `files` and returns the exit code.
+fn check(files: &[PathBuf], options: &analysis::Options) -> i32 {
+    let mut exit_code = 0;
+    for path in files {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                exit_code = 1;
+                continue;
+            }
+        };
+        let analysis = analysis::analyze(&text, options);
+        for diagnostic in &analysis.diagnostics {
+            let pos = syntax::line_col(&text, diagnostic.range.start).unwrap();
+            println!(
+                "{}:{}:{}: {}: {} [{}]",
+                path.display(),
+                pos.line + 1,
+                pos.col + 1,
+                diagnostic.severity,
+                diagnostic.message,
+                diagnostic.code
+            );
+            if diagnostic.severity == Severity::Error {
+                exit_code = 1;
+            }
+        }
+    }
+    exit_code
+}
 
 pub fn main() -> anyhow::Result<()> {
-    // For help/version information. There is no option now.
-    let _ = Opt::from_args();
+    let opt = Opt::from_args();
 
     env_logger::init();
 
+    if let Some(Command::Check {
+        member_order,
+        naming,
+        method_ordinals,
+        files,
+    }) = opt.command
+    {
+        let mut options = analysis::Options::default();
+        options.lints.member_order = member_order;
+        if naming {
+            options.lints.naming = analysis::NamingOptions::all();
+        }
+        options.method_ordinals = method_ordinals;
+        std::process::exit(check(&files, &options));
+    }
+
     let stdin = std::io::stdin();
     let stdout = std::io::stdout();
-    let exit_code = mojom_lsp::server::start(stdin, stdout)?;
+    let exit_code = match opt.log_file {
+        Some(path) => {
+            let log = std::fs::File::create(&path)
+                .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+            let (reader, writer) = tee(stdin, stdout, log);
+            mojom_lsp::server::start(reader, writer)?
+        }
+        None => mojom_lsp::server::start(stdin, stdout)?,
+    };
     std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tee() {
+        let (mut reader, mut writer) =
+            tee(&b"Content-Length: 2\r\n\r\n{}"[..], Vec::new(), Vec::new());
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf).unwrap();
+        writer.write_all(b"response").unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+
+        let log = writer.log.lock().unwrap();
+        let expected = "\n--- client to server ---\nContent-Le\
+                        \n--- server to client ---\nresponse\
+                        \n--- client to server ---\nngth: 2\r\n\r\n{}";
+        assert_eq!(expected, String::from_utf8_lossy(&log.log));
+        assert_eq!(b"response", &writer.inner[..]);
+    }
+}
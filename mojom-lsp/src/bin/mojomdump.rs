@@ -0,0 +1,270 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use structopt::StructOpt;
+
+use mojom_lsp::syntax::{self, preorder, MojomFile, Parameter, Range, Traversal};
+
+#[derive(StructOpt)]
+struct Opt {
+    /// Prints the syntax tree as JSON, one line per file. Needs the `serde`
+    /// feature.
+    #[structopt(long)]
+    json: bool,
+    /// Files to dump. `-` reads stdin.
+    #[structopt(parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
+}
+
+const INDENT: &str = "  ";
+
+// Formats `(type name, ...)`.
+fn format_params(text: &str, params: &[Parameter]) -> String {
+    let params = params
+        .iter()
+        .map(|param| {
+            let mut res = format!(
+                "{} {}",
+                partial_text(text, &param.typ),
+                partial_text(text, &param.name)
+            );
+            if let Some(ordinal) = &param.ordinal {
+                res.push_str(partial_text(text, ordinal));
+            }
+            res
+        })
+        .collect::<Vec<_>>();
+    format!("({})", params.join(", "))
+}
+
+fn partial_text<'a>(text: &'a str, range: &Range) -> &'a str {
+    &text[range.start..range.end]
+}
+
+// Formats `mojom` as an indented tree of declarations.
+fn dump_tree(text: &str, mojom: &MojomFile) -> String {
+    let t = |range: &Range| partial_text(text, range);
+    let ordinal = |ordinal: &Option<Range>| ordinal.as_ref().map_or("", |range| t(range));
+
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut line = |depth: usize, line: String| {
+        writeln!(out, "{}{}", INDENT.repeat(depth), line).unwrap();
+    };
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterMojomFile(_) | Traversal::LeaveMojomFile(_) => (),
+            Traversal::Module(node) => line(depth, format!("module {}", t(&node.name))),
+            Traversal::Import(node) => line(depth, format!("import {}", t(&node.path))),
+            Traversal::EnterInterface(node) => {
+                line(depth, format!("interface {}", t(&node.name)));
+                depth += 1;
+            }
+            Traversal::EnterStruct(node) => {
+                line(depth, format!("struct {}", t(&node.name)));
+                depth += 1;
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => depth -= 1,
+            Traversal::Method(node) => {
+                let mut signature = format!(
+                    "method {}{}{}",
+                    t(&node.name),
+                    ordinal(&node.ordinal),
+                    format_params(text, &node.params)
+                );
+                if let Some(response) = &node.response {
+                    signature.push_str(" => ");
+                    signature.push_str(&format_params(text, &response.params));
+                }
+                line(depth, signature);
+            }
+            Traversal::Union(node) => {
+                line(depth, format!("union {}", t(&node.name)));
+                depth += 1;
+            }
+            Traversal::LeaveUnion(_) => depth -= 1,
+            Traversal::UnionField(node) => {
+                let field = format!(
+                    "field {} {}{}",
+                    t(&node.typ),
+                    t(&node.name),
+                    ordinal(&node.ordinal)
+                );
+                line(depth, field);
+            }
+            Traversal::Enum(node) => {
+                line(depth, format!("enum {}", t(&node.name)));
+                depth += 1;
+            }
+            Traversal::LeaveEnum(_) => depth -= 1,
+            Traversal::EnumValue(node) => {
+                let value = match &node.value {
+                    Some(init) => format!("{} = {}", t(&node.name), t(init)),
+                    None => t(&node.name).to_owned(),
+                };
+                line(depth, value);
+            }
+            // Parameters are printed with their methods.
+            Traversal::LeaveMethod(_)
+            | Traversal::Parameter(_)
+            | Traversal::EnterResponse(_)
+            | Traversal::LeaveResponse(_) => (),
+            Traversal::Const(node) => {
+                let decl = format!(
+                    "const {} {} = {}",
+                    t(&node.typ),
+                    t(&node.name),
+                    t(&node.value)
+                );
+                line(depth, decl);
+            }
+            Traversal::StructField(node) => {
+                let mut field = format!(
+                    "field {} {}{}",
+                    t(&node.typ),
+                    t(&node.name),
+                    ordinal(&node.ordinal)
+                );
+                if let Some(default) = &node.default {
+                    write!(field, " = {}", t(default)).unwrap();
+                }
+                line(depth, field);
+            }
+            Traversal::Feature(node) => {
+                line(depth, format!("feature {}", t(&node.name)));
+                depth += 1;
+            }
+            Traversal::LeaveFeature(_) => depth -= 1,
+        }
+    }
+    out
+}
+
+fn read_input(path: &PathBuf) -> std::io::Result<String> {
+    if path.as_os_str() == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
+
+// Dumps `files` and returns the exit code.
+fn dump(files: &[PathBuf], json: bool) -> i32 {
+    let mut exit_code = 0;
+    for path in files {
+        let text = match read_input(path) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                exit_code = 1;
+                continue;
+            }
+        };
+        let mojom = match syntax::parse(&text) {
+            Ok(mojom) => mojom,
+            Err(err) => {
+                let (start, _) = err.range();
+                eprintln!(
+                    "{}:{}:{}: syntax error\n{}",
+                    path.display(),
+                    start.line + 1,
+                    start.col + 1,
+                    err
+                );
+                exit_code = 1;
+                continue;
+            }
+        };
+        if json {
+            print_json(path, &text, &mojom);
+        } else {
+            if files.len() > 1 {
+                println!("# {}", path.display());
+            }
+            print!("{}", dump_tree(&text, &mojom));
+        }
+    }
+    exit_code
+}
+
+#[cfg(feature = "serde")]
+fn print_json(path: &Path, text: &str, mojom: &MojomFile) {
+    let mojom = syntax::to_resolved_json(mojom, text);
+    println!(
+        "{}",
+        serde_json::json!({"path": path.display().to_string(), "mojom": mojom})
+    );
+}
+
+// `main` rejects `--json` without the `serde` feature.
+#[cfg(not(feature = "serde"))]
+fn print_json(_path: &Path, _text: &str, _mojom: &MojomFile) {
+    unreachable!();
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if opt.json && !cfg!(feature = "serde") {
+        eprintln!("--json needs mojomdump built with the `serde` feature");
+        std::process::exit(2);
+    }
+    std::process::exit(dump(&opt.files, opt.json));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_golden(mojom_path: &str, golden_path: &str) {
+        let text = std::fs::read_to_string(mojom_path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let expected = std::fs::read_to_string(golden_path).unwrap();
+        assert_eq!(expected, dump_tree(&text, &mojom), "{}", mojom_path);
+    }
+
+    #[test]
+    fn test_dump_tree() {
+        check_golden(
+            "testdata/my_interface.mojom",
+            "testdata/mojomdump/my_interface.txt",
+        );
+        check_golden(
+            "testdata/foo_module/foo.mojom",
+            "testdata/mojomdump/foo.txt",
+        );
+        check_golden(
+            "testdata/mojomdump/declarations.mojom",
+            "testdata/mojomdump/declarations.txt",
+        );
+    }
+
+    #[test]
+    fn test_dump_errors() {
+        let files = vec![
+            PathBuf::from("testdata/broken.mojom"),
+            PathBuf::from("testdata/no_such_file.mojom"),
+        ];
+        assert_eq!(1, dump(&files, false));
+        let files = vec![PathBuf::from("testdata/my_service.mojom")];
+        assert_eq!(0, dump(&files, false));
+        #[cfg(feature = "serde")]
+        assert_eq!(0, dump(&files, true));
+    }
+}
@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate pest_derive;
 
+pub mod analysis;
 pub mod server;
 pub mod syntax;
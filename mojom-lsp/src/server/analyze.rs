@@ -0,0 +1,101 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::analysis;
+use crate::syntax::MojomFile;
+
+use super::diagnostic::check_document;
+use super::imported_files::ImportCache;
+use super::textpos::PositionEncoding;
+
+/// The result of `analyze_file()`.
+#[derive(Debug)]
+pub struct FileAnalysis {
+    /// The syntax tree. None when the file has a syntax error.
+    pub mojom: Option<MojomFile>,
+    /// Diagnostics the server would publish for the file.
+    pub diagnostics: Vec<lsp_types::Diagnostic>,
+}
+
+/// Checks the mojom file at `path` without a client. Imports are resolved
+/// relative to `root`, as the server does with the workspace root.
+pub fn analyze_file(
+    root: &Path,
+    path: &Path,
+    options: &analysis::Options,
+) -> std::io::Result<FileAnalysis> {
+    let root = root.canonicalize()?;
+    let path = path.canonicalize()?;
+    let text = std::fs::read_to_string(&path)?;
+    // Unwrap should be safe because `path` is absolute.
+    let uri = lsp_types::Url::from_file_path(&path).unwrap();
+    let root_paths = [root];
+    let mut import_cache = ImportCache::new();
+    let result = check_document(
+        &root_paths,
+        uri,
+        text,
+        PositionEncoding::default(),
+        options,
+        &mut import_cache,
+    );
+    Ok(FileAnalysis {
+        mojom: result.ast.map(|ast| ast.mojom),
+        diagnostics: result.diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_file() {
+        let options = analysis::Options::default();
+        let result = analyze_file(
+            Path::new("testdata"),
+            Path::new("testdata/my_service.mojom"),
+            &options,
+        )
+        .unwrap();
+        assert!(result.mojom.is_some());
+        // Nothing from `foo_module/foo.mojom` is used.
+        assert_eq!(1, result.diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::HINT),
+            result.diagnostics[0].severity
+        );
+        assert_eq!(1, result.diagnostics[0].range.start.line);
+
+        let result = analyze_file(
+            Path::new("testdata"),
+            Path::new("testdata/broken.mojom"),
+            &options,
+        )
+        .unwrap();
+        assert!(result.mojom.is_none());
+        assert_eq!(1, result.diagnostics.len());
+        // The error is reported at the unclosed `{`.
+        assert_eq!(2, result.diagnostics[0].range.start.line);
+
+        assert!(analyze_file(
+            Path::new("testdata"),
+            Path::new("testdata/nonexistent.mojom"),
+            &options
+        )
+        .is_err());
+    }
+}
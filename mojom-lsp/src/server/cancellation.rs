@@ -0,0 +1,94 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::protocol::Id;
+
+/// The client cancelled the request.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Cancelled;
+
+/// A flag that is set when the client cancels a request.
+#[derive(Clone, Default)]
+pub(crate) struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns Err when the request is cancelled. Long operations call this
+    /// between steps.
+    pub(crate) fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Cancellation tokens of requests, keyed by request id. It is shared by the
+/// thread that reads messages and the main loop.
+#[derive(Clone, Default)]
+pub(crate) struct CancellationRegistry {
+    tokens: Arc<Mutex<HashMap<Id, CancellationToken>>>,
+}
+
+impl CancellationRegistry {
+    /// Returns the token of request `id`. The token is already cancelled when
+    /// `$/cancelRequest` arrived before the request is handled.
+    pub(crate) fn token(&self, id: &Id) -> CancellationToken {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.entry(id.clone()).or_default().clone()
+    }
+
+    pub(crate) fn cancel(&self, id: Id) {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.entry(id).or_default().cancel();
+    }
+
+    /// Forgets request `id`. Called when the request has been answered.
+    pub(crate) fn remove(&self, id: &Id) {
+        self.tokens.lock().unwrap().remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_registry() {
+        let registry = CancellationRegistry::default();
+
+        let token = registry.token(&Id::Number(1));
+        assert_eq!(Ok(()), token.check());
+        registry.cancel(Id::Number(1));
+        assert_eq!(Err(Cancelled), token.check());
+
+        // Cancelled before the request is handled.
+        registry.cancel(Id::Number(2));
+        assert!(registry.token(&Id::Number(2)).is_cancelled());
+
+        registry.remove(&Id::Number(2));
+        assert!(!registry.token(&Id::Number(2)).is_cancelled());
+    }
+}
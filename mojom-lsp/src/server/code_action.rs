@@ -0,0 +1,500 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use lsp_types::{CodeAction, CodeActionKind, TextEdit, WorkspaceEdit};
+
+use crate::analysis::{
+    naming_violations, NameKind, NamingOptions, NAMING_CODE_PREFIX, UNUSED_IMPORT_CODE,
+};
+use crate::syntax::{self, leading_comments, Statement};
+
+use super::imported_files::ImportedFiles;
+use super::mojomast::MojomAst;
+use super::rename::{is_valid_name, rename};
+
+/// Returns true when `kind` is requested by `only` of code action params.
+/// Kinds are hierarchical, e.g. `source` requests `source.organizeImports`.
+pub(crate) fn is_requested(only: &Option<Vec<CodeActionKind>>, kind: &CodeActionKind) -> bool {
+    let only = match only {
+        Some(only) => only,
+        None => return true,
+    };
+    only.iter().any(|requested| {
+        let requested = requested.as_str();
+        kind.as_str() == requested || kind.as_str().starts_with(&format!("{}.", requested))
+    })
+}
+
+// An import statement with the comments that move together with it.
+struct ImportUnit<'a> {
+    path: &'a str,
+    stmt: &'a str,
+    // The statement with its leading comments and the trailing comment of
+    // its line.
+    text: &'a str,
+}
+
+// Returns the end of the line that contains `offset`, excluding the newline.
+fn line_end(text: &str, offset: usize) -> usize {
+    text[offset..]
+        .find('\n')
+        .map_or(text.len(), |i| offset + i)
+        .max(offset)
+}
+
+// Returns the end of the comment that follows `range` on the same line, or
+// the end of `range` when no comment follows it.
+fn trailing_comment_end(ast: &MojomAst, range: &syntax::Range) -> usize {
+    let comments = &ast.mojom.comments;
+    let next = comments.partition_point(|comment| comment.range.start < range.end);
+    match comments.get(next) {
+        Some(comment)
+            if !ast.text[range.end..comment.range.start]
+                .contains(|ch: char| ch == '\n' || !ch.is_whitespace()) =>
+        {
+            comment.range.end
+        }
+        _ => range.end,
+    }
+}
+
+// Splits the first contiguous run of import statements into units. Returns
+// the units and the byte range they cover.
+fn import_units(ast: &MojomAst) -> Option<(Vec<ImportUnit<'_>>, syntax::Range)> {
+    let imports = ast
+        .mojom
+        .stmts
+        .iter()
+        .skip_while(|stmt| !matches!(stmt, Statement::Import(_)))
+        .map_while(|stmt| match stmt {
+            Statement::Import(stmt) => Some(stmt),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let first = imports.first()?;
+    let region_start = leading_comments(&ast.mojom, first.range.start)
+        .first()
+        .map_or(first.range.start, |comment| comment.range.start);
+
+    let mut units = Vec::new();
+    let mut start = region_start;
+    for import in &imports {
+        // Anything between the previous unit and the statement, such as
+        // comments separated by a blank line, moves with the statement.
+        let end = trailing_comment_end(ast, &import.range);
+        let text = ast.text[start..end].trim_start();
+        let path = ast.text(&import.path);
+        units.push(ImportUnit {
+            path: &path[1..path.len() - 1],
+            stmt: ast.text(&import.range),
+            text,
+        });
+        start = end;
+    }
+    Some((
+        units,
+        syntax::Range {
+            start: region_start,
+            end: start,
+        },
+    ))
+}
+
+/// Returns a code action that sorts import statements by path and removes
+/// duplicated imports. The first occurrence of a duplicate is kept. Returns
+/// None when the imports are already organized.
+pub(crate) fn organize_imports(ast: &MojomAst) -> Option<CodeAction> {
+    let (units, range) = import_units(ast)?;
+
+    let mut sorted = units.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|unit| unit.path);
+    let mut seen = HashSet::new();
+    sorted.retain(|unit| seen.insert(unit.stmt));
+    let unchanged = sorted.len() == units.len()
+        && sorted
+            .iter()
+            .zip(&units)
+            .all(|(sorted, unit)| std::ptr::eq(*sorted, unit));
+    if unchanged {
+        return None;
+    }
+
+    // Units are joined with the line ending of the file.
+    let eol = if ast.text.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let new_text = sorted
+        .iter()
+        .flat_map(|unit| unit.text.lines())
+        .map(|line| line.trim_end_matches('\r'))
+        .collect::<Vec<_>>()
+        .join(eol);
+    let edit = TextEdit::new(ast.lsp_range(&range), new_text);
+    let mut changes = HashMap::new();
+    changes.insert(ast.uri.clone(), vec![edit]);
+    Some(CodeAction {
+        title: "Organize imports".to_owned(),
+        kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+        edit: Some(WorkspaceEdit::new(changes)),
+        ..Default::default()
+    })
+}
+
+// Returns the range to delete to remove the import statement at `range`.
+// The whole line is deleted when nothing but a trailing comment shares the
+// line with the statement.
+fn import_removal_range(text: &str, range: &syntax::Range) -> syntax::Range {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = line_end(text, range.end);
+    let rest = text[range.end..end].trim();
+    let alone = text[line_start..range.start].trim().is_empty()
+        && (rest.is_empty() || rest.starts_with("//"));
+    if !alone {
+        return *range;
+    }
+    syntax::Range {
+        start: line_start,
+        end: (end + 1).min(text.len()),
+    }
+}
+
+/// Returns a quick fix that removes the import statement reported by
+/// `diagnostic`. Returns None when `diagnostic` isn't an unused import
+/// diagnostic of `ast`.
+pub(crate) fn remove_unused_import(
+    ast: &MojomAst,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let code = lsp_types::NumberOrString::String(UNUSED_IMPORT_CODE.to_owned());
+    if diagnostic.code.as_ref() != Some(&code) {
+        return None;
+    }
+    let import = ast.mojom.stmts.iter().find_map(|stmt| match stmt {
+        Statement::Import(stmt) if ast.lsp_range(&stmt.range) == diagnostic.range => Some(stmt),
+        _ => None,
+    })?;
+    let range = import_removal_range(&ast.text, &import.range);
+    let edit = TextEdit::new(ast.lsp_range(&range), String::new());
+    let mut changes = HashMap::new();
+    changes.insert(ast.uri.clone(), vec![edit]);
+    let path = ast.text(&import.path);
+    Some(CodeAction {
+        title: format!("Remove unused import {}", path),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit::new(changes)),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// Returns a quick fix that renames the name reported by the naming lint
+/// `diagnostic` to the suggested name. Types and consts are renamed together
+/// with their references in `ast`. Enum values and modules aren't renamed
+/// because references to them can't be updated.
+pub(crate) fn rename_to_suggestion(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    match &diagnostic.code {
+        Some(lsp_types::NumberOrString::String(code)) if code.starts_with(NAMING_CODE_PREFIX) => (),
+        _ => return None,
+    }
+    // The diagnostic was reported with some rules enabled. Check all of them
+    // to find it regardless of the current settings.
+    let options = NamingOptions::all();
+    let violation = naming_violations(&ast.text, &ast.mojom, &options)
+        .into_iter()
+        .find(|violation| ast.lsp_range(violation.name) == diagnostic.range)?;
+    let suggestion = &violation.suggestion;
+    let edit = match violation.kind {
+        NameKind::Type | NameKind::Const => {
+            rename(ast, imported_files, violation.name.start, suggestion).ok()??
+        }
+        // Fields, parameters and methods aren't referenced in mojom files.
+        NameKind::Field | NameKind::Parameter | NameKind::Method => {
+            if !is_valid_name(suggestion) {
+                return None;
+            }
+            let edit = TextEdit::new(ast.lsp_range(violation.name), suggestion.clone());
+            let mut changes = HashMap::new();
+            changes.insert(ast.uri.clone(), vec![edit]);
+            WorkspaceEdit::new(changes)
+        }
+        NameKind::EnumValue | NameKind::Module => return None,
+    };
+    Some(CodeAction {
+        title: format!("Rename to {}", suggestion),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(edit),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::conversion::to_offset_range;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse_with_comments(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    // Applies the edits of `action` to `text`.
+    fn apply(text: &str, action: &CodeAction) -> String {
+        let edits = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let index = super::super::textpos::LineIndex::new(text, Default::default());
+        let mut ranges = edits
+            .values()
+            .next()
+            .unwrap()
+            .iter()
+            .map(|edit| {
+                let range = to_offset_range(&index, text, &edit.range);
+                (range.start, range.end, edit.new_text.as_str())
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|&(start, _, _)| std::cmp::Reverse(start));
+        let mut text = text.to_owned();
+        for (start, end, new_text) in ranges {
+            text.replace_range(start..end, new_text);
+        }
+        text
+    }
+
+    #[test]
+    fn test_organize_imports() {
+        let input = r#"module foo;
+
+// Needed for Bar.
+import "b/bar.mojom";
+import "a/foo.mojom";  // Trailing.
+[Attr] import "c/baz.mojom";
+/* Block. */
+import "a/foo.mojom";
+
+struct Foo {};
+"#;
+        let ast = create_ast(input);
+        let action = organize_imports(&ast).unwrap();
+        assert_eq!(Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS), action.kind);
+        // Comments move with their imports. The duplicate is removed together
+        // with its comment.
+        let expected = r#"module foo;
+
+import "a/foo.mojom";  // Trailing.
+// Needed for Bar.
+import "b/bar.mojom";
+[Attr] import "c/baz.mojom";
+
+struct Foo {};
+"#;
+        assert_eq!(expected, apply(input, &action));
+
+        // Already organized.
+        let ast = create_ast(expected);
+        assert!(organize_imports(&ast).is_none());
+    }
+
+    #[test]
+    fn test_organize_imports_same_line() {
+        let input = "import \"b.mojom\"; import \"a.mojom\";  // For A.\nstruct A {};\n";
+        let ast = create_ast(input);
+        let action = organize_imports(&ast).unwrap();
+        let expected = "import \"a.mojom\";  // For A.\nimport \"b.mojom\";\nstruct A {};\n";
+        assert_eq!(expected, apply(input, &action));
+
+        let ast = create_ast(expected);
+        assert!(organize_imports(&ast).is_none());
+    }
+
+    #[test]
+    fn test_organize_imports_crlf() {
+        let input =
+            "// B.\r\nimport \"b.mojom\";\r\nimport \"a.mojom\"; // A.\r\n\r\nstruct A {};\r\n";
+        let ast = create_ast(input);
+        let action = organize_imports(&ast).unwrap();
+        let expected =
+            "import \"a.mojom\"; // A.\r\n// B.\r\nimport \"b.mojom\";\r\n\r\nstruct A {};\r\n";
+        assert_eq!(expected, apply(input, &action));
+    }
+
+    #[test]
+    fn test_organize_imports_duplicates() {
+        let input = "import \"b.mojom\";\nimport \"a.mojom\";\n[A] import \"a.mojom\";\nimport \"a.mojom\";\n";
+        let ast = create_ast(input);
+        let action = organize_imports(&ast).unwrap();
+        let expected = "import \"a.mojom\";\n[A] import \"a.mojom\";\nimport \"b.mojom\";\n";
+        assert_eq!(expected, apply(input, &action));
+    }
+
+    #[test]
+    fn test_organize_imports_minimal_edit() {
+        let input = "// Header.\n\nimport \"b.mojom\";\nimport \"a.mojom\";\nstruct A {};\n";
+        let ast = create_ast(input);
+        let action = organize_imports(&ast).unwrap();
+        let edits = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+        let edit = &edits[&ast.uri][0];
+        // Only the import lines are replaced.
+        assert_eq!(
+            lsp_types::Range::new(
+                lsp_types::Position::new(2, 0),
+                lsp_types::Position::new(3, 17)
+            ),
+            edit.range
+        );
+        assert_eq!("import \"a.mojom\";\nimport \"b.mojom\";", edit.new_text);
+
+        let ast = create_ast("struct A {};\n");
+        assert!(organize_imports(&ast).is_none());
+    }
+
+    #[test]
+    fn test_remove_unused_import() {
+        let input = r#"import "a.mojom";  // For A.
+import "b.mojom"; import "c.mojom";
+struct A {};
+"#;
+        let ast = create_ast(input);
+        let diagnostic = |path: &str| {
+            let start = input.find(&format!("import \"{}", path)).unwrap();
+            let range = syntax::Range {
+                start,
+                end: start + r#"import "a.mojom";"#.len(),
+            };
+            lsp_types::Diagnostic {
+                range: ast.lsp_range(&range),
+                code: Some(lsp_types::NumberOrString::String(
+                    UNUSED_IMPORT_CODE.to_owned(),
+                )),
+                ..Default::default()
+            }
+        };
+
+        // The line is removed together with the trailing comment.
+        let action = remove_unused_import(&ast, &diagnostic("a.mojom")).unwrap();
+        assert_eq!(Some(CodeActionKind::QUICKFIX), action.kind);
+        assert_eq!(
+            "import \"b.mojom\"; import \"c.mojom\";\nstruct A {};\n",
+            apply(input, &action)
+        );
+
+        // Only the statement is removed when the line has another import.
+        let action = remove_unused_import(&ast, &diagnostic("c.mojom")).unwrap();
+        assert_eq!(
+            "import \"a.mojom\";  // For A.\nimport \"b.mojom\"; \nstruct A {};\n",
+            apply(input, &action)
+        );
+
+        // Other diagnostics are ignored.
+        let mut other = diagnostic("b.mojom");
+        other.code = None;
+        assert!(remove_unused_import(&ast, &other).is_none());
+    }
+
+    #[test]
+    fn test_rename_to_suggestion() {
+        let input = r#"const int32 MAX_SIZE = 1;
+struct url_request {
+  int32 bodySize = MAX_SIZE;
+};
+interface Loader {
+  enum Kind { GET };
+  start(url_request request);
+};
+"#;
+        let ast = create_ast(input);
+        let diagnostic = |name: &str, code: &str| {
+            let start = input.find(name).unwrap();
+            let range = syntax::Range {
+                start,
+                end: start + name.len(),
+            };
+            lsp_types::Diagnostic {
+                range: ast.lsp_range(&range),
+                code: Some(lsp_types::NumberOrString::String(code.to_owned())),
+                ..Default::default()
+            }
+        };
+
+        // Types are renamed together with their references.
+        let action = rename_to_suggestion(
+            &ast,
+            &None,
+            &diagnostic("url_request", "mojom-style/naming-type"),
+        )
+        .unwrap();
+        assert_eq!("Rename to UrlRequest", action.title);
+        assert_eq!(Some(CodeActionKind::QUICKFIX), action.kind);
+        let renamed = apply(input, &action);
+        assert!(renamed.contains("struct UrlRequest {"));
+        assert!(renamed.contains("start(UrlRequest request"));
+
+        let action = rename_to_suggestion(
+            &ast,
+            &None,
+            &diagnostic("MAX_SIZE", "mojom-style/naming-constant"),
+        )
+        .unwrap();
+        let renamed = apply(input, &action);
+        assert!(renamed.contains("const int32 kMaxSize = 1;"));
+        assert!(renamed.contains("int32 bodySize = kMaxSize;"));
+
+        let action = rename_to_suggestion(
+            &ast,
+            &None,
+            &diagnostic("bodySize", "mojom-style/naming-field"),
+        )
+        .unwrap();
+        assert_eq!("Rename to body_size", action.title);
+        assert!(apply(input, &action).contains("int32 body_size = MAX_SIZE;"));
+
+        let action = rename_to_suggestion(
+            &ast,
+            &None,
+            &diagnostic("start", "mojom-style/naming-method"),
+        )
+        .unwrap();
+        assert!(apply(input, &action).contains("  Start(url_request"));
+
+        // Enum values can be referenced in other files.
+        let enum_value = diagnostic("GET", "mojom-style/naming-constant");
+        assert!(rename_to_suggestion(&ast, &None, &enum_value).is_none());
+        // Other diagnostics are ignored.
+        let other = diagnostic("bodySize", UNUSED_IMPORT_CODE);
+        assert!(rename_to_suggestion(&ast, &None, &other).is_none());
+    }
+
+    #[test]
+    fn test_is_requested() {
+        let kind = CodeActionKind::SOURCE_ORGANIZE_IMPORTS;
+        assert!(is_requested(&None, &kind));
+        assert!(is_requested(&Some(vec![CodeActionKind::SOURCE]), &kind));
+        assert!(is_requested(&Some(vec![kind.clone()]), &kind));
+        assert!(!is_requested(&Some(vec![CodeActionKind::QUICKFIX]), &kind));
+        assert!(!is_requested(
+            &Some(vec![CodeActionKind::new("sour")]),
+            &kind
+        ));
+    }
+}
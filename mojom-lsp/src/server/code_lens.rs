@@ -0,0 +1,174 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Interfaces and structs have two lenses. One shows the number of references
+// in the workspace. It is resolved with `codeLens/resolve` because counting
+// references parses files in the workspace. The other shows the names of the
+// generated C++ class and TypeScript module.
+
+use std::path::{Path, PathBuf};
+
+use lsp_types::{CodeLens, Command, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::{self, preorder, Traversal};
+
+use super::mojomast::MojomAst;
+
+// The client is expected to show references at the position in `arguments`.
+pub(crate) const SHOW_REFERENCES_COMMAND: &str = "mojom.showReferences";
+
+// `data` of unresolved reference lenses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ReferencesLensData {
+    pub(crate) uri: Url,
+}
+
+// Returns `path` relative to the first root path that contains it.
+fn relative_path<'a>(root_paths: &[PathBuf], path: &'a Path) -> Option<&'a Path> {
+    root_paths
+        .iter()
+        .find_map(|root_path| path.strip_prefix(root_path).ok())
+}
+
+// Returns the title of the bindings lens like `C++: foo::mojom::Bar | TS:
+// foo/bar.mojom-webui.js`.
+fn bindings_title(ast: &MojomAst, root_paths: &[PathBuf], name: &str) -> String {
+    let cpp_name = ast
+        .module_name()
+        .into_iter()
+        .flat_map(|module| module.split('.'))
+        .chain(std::iter::once(name))
+        .collect::<Vec<_>>()
+        .join("::");
+    let ts_path = ast.uri.to_file_path().ok().and_then(|path| {
+        let path = relative_path(root_paths, &path)?;
+        Some(format!("{}-webui.js", path.to_string_lossy()))
+    });
+    match ts_path {
+        Some(ts_path) => format!("C++: {} | TS: {}", cpp_name, ts_path),
+        None => format!("C++: {}", cpp_name),
+    }
+}
+
+/// Returns the lenses of interfaces and structs in `ast`. Reference lenses
+/// come unresolved.
+pub(crate) fn code_lenses(ast: &MojomAst, root_paths: &[PathBuf]) -> Vec<CodeLens> {
+    let mut lenses = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        let name: &syntax::Range = match traversal {
+            Traversal::EnterInterface(node) => &node.name,
+            Traversal::EnterStruct(node) => &node.name,
+            _ => continue,
+        };
+        let range = ast.lsp_range(name);
+        let data = ReferencesLensData {
+            uri: ast.uri.clone(),
+        };
+        lenses.push(CodeLens {
+            range,
+            command: None,
+            // Unwrap should be safe because `data` has only strings.
+            data: Some(serde_json::to_value(data).unwrap()),
+        });
+        let command = Command {
+            title: bindings_title(ast, root_paths, ast.text(name)),
+            // The lens is informational.
+            command: String::new(),
+            arguments: None,
+        };
+        lenses.push(CodeLens {
+            range,
+            command: Some(command),
+            data: None,
+        });
+    }
+    lenses
+}
+
+/// Fills in the command of a reference lens that has `count` references.
+pub(crate) fn resolve_references_lens(
+    mut lens: CodeLens,
+    data: &ReferencesLensData,
+    count: usize,
+) -> CodeLens {
+    let title = match count {
+        1 => "1 reference".to_owned(),
+        count => format!("{} references", count),
+    };
+    let arguments = vec![
+        serde_json::to_value(&data.uri).unwrap(),
+        serde_json::to_value(lens.range.start).unwrap(),
+    ];
+    lens.command = Some(Command {
+        title,
+        command: SHOW_REFERENCES_COMMAND.to_owned(),
+        arguments: Some(arguments),
+    });
+    lens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ast(path: &Path) -> MojomAst {
+        let text = std::fs::read_to_string(path).unwrap();
+        let uri = Url::from_file_path(path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let module = crate::analysis::find_module(&text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text, mojom, module)
+    }
+
+    #[test]
+    fn test_code_lenses() {
+        let root_path = Path::new("testdata").canonicalize().unwrap();
+        let ast = create_ast(&root_path.join("usages/geometry.mojom"));
+        let lenses = code_lenses(&ast, std::slice::from_ref(&root_path));
+        // `Point` and `Locator`.
+        assert_eq!(4, lenses.len());
+
+        let point = &lenses[0];
+        assert_eq!(lsp_types::Position::new(4, 7), point.range.start);
+        assert!(point.command.is_none());
+        let data: ReferencesLensData = serde_json::from_value(point.data.clone().unwrap()).unwrap();
+        assert_eq!(ast.uri, data.uri);
+
+        let bindings = lenses[1].command.as_ref().unwrap();
+        assert_eq!(
+            "C++: geometry::mojom::Point | TS: usages/geometry.mojom-webui.js",
+            bindings.title
+        );
+        assert_eq!(lsp_types::Position::new(14, 10), lenses[3].range.start);
+
+        // Files outside the root paths have no TypeScript path.
+        let lenses = code_lenses(&ast, &[root_path.join("foo_module")]);
+        assert_eq!(
+            "C++: geometry::mojom::Locator",
+            lenses[3].command.as_ref().unwrap().title
+        );
+
+        let lens = resolve_references_lens(lenses[0].clone(), &data, 1);
+        let command = lens.command.unwrap();
+        assert_eq!("1 reference", command.title);
+        assert_eq!(SHOW_REFERENCES_COMMAND, command.command);
+        assert_eq!(
+            vec![
+                serde_json::to_value(&ast.uri).unwrap(),
+                serde_json::json!({ "line": 4, "character": 7 }),
+            ],
+            command.arguments.unwrap()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Commands of `workspace/executeCommand`. They let users recover from stale
+// caches without restarting the editor and inspect what the server sees.
+
+use std::collections::BTreeMap;
+
+use lsp_types::Url;
+use serde::Serialize;
+
+use crate::syntax::Statement;
+
+use super::imported_files::{import_paths, ImportStatus, ImportedFiles};
+use super::mojomast::MojomAst;
+
+// Drops parsed imports and checks open documents again.
+pub(crate) const RESTART_DIAGNOSTICS_COMMAND: &str = "mojom.restartDiagnostics";
+// Does what `mojom.restartDiagnostics` does and indexes the workspace from
+// scratch. The response is sent when indexing is done.
+pub(crate) const REINDEX_WORKSPACE_COMMAND: &str = "mojom.reindexWorkspace";
+// Returns `AnalysisSummary` of the document in the first argument.
+pub(crate) const SHOW_ANALYSIS_COMMAND: &str = "mojom.showAnalysis";
+
+pub(crate) const COMMANDS: &[&str] = &[
+    RESTART_DIAGNOSTICS_COMMAND,
+    REINDEX_WORKSPACE_COMMAND,
+    SHOW_ANALYSIS_COMMAND,
+];
+
+// The result of `mojom.showAnalysis`.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnalysisSummary {
+    pub(crate) uri: Url,
+    // False when the document has a syntax error. Other fields are empty
+    // then.
+    pub(crate) parsed: bool,
+    pub(crate) module_name: Option<String>,
+    // The number of statements of each kind, e.g. `"struct": 2`.
+    pub(crate) statements: BTreeMap<&'static str, usize>,
+    // Import statements in the order they appear.
+    pub(crate) imports: Vec<ImportSummary>,
+    // The number of files imported directly or indirectly.
+    pub(crate) imported_files: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImportSummary {
+    pub(crate) path: String,
+    pub(crate) status: ImportStatus,
+}
+
+fn statement_kind(stmt: &Statement) -> &'static str {
+    match stmt {
+        Statement::Module(_) => "module",
+        Statement::Import(_) => "import",
+        Statement::Interface(_) => "interface",
+        Statement::Struct(_) => "struct",
+        Statement::Union(_) => "union",
+        Statement::Enum(_) => "enum",
+        Statement::Const(_) => "const",
+        Statement::Feature(_) => "feature",
+    }
+}
+
+// Summarizes the analysis of the document at `uri`. `ast` is None when the
+// document has a syntax error.
+pub(crate) fn analysis_summary(
+    uri: Url,
+    ast: Option<&MojomAst>,
+    imported_files: Option<&ImportedFiles>,
+) -> AnalysisSummary {
+    let ast = match ast {
+        Some(ast) => ast,
+        None => {
+            return AnalysisSummary {
+                uri,
+                parsed: false,
+                module_name: None,
+                statements: BTreeMap::new(),
+                imports: Vec::new(),
+                imported_files: 0,
+            }
+        }
+    };
+    let mut statements = BTreeMap::new();
+    for stmt in &ast.mojom.stmts {
+        *statements.entry(statement_kind(stmt)).or_insert(0) += 1;
+    }
+    let imports = import_paths(&ast.text, &ast.mojom)
+        .into_iter()
+        .enumerate()
+        .map(|(index, path)| ImportSummary {
+            path,
+            status: imported_files.map_or(ImportStatus::NotFound, |imported_files| {
+                imported_files.import_status(index)
+            }),
+        })
+        .collect();
+    AnalysisSummary {
+        uri,
+        parsed: true,
+        module_name: ast.module_name().map(str::to_owned),
+        statements,
+        imports,
+        imported_files: imported_files.map_or(0, ImportedFiles::file_count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::syntax;
+
+    use super::super::imported_files::{check_imports, ImportCache};
+
+    #[test]
+    fn test_analysis_summary() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let uri = Url::from_file_path(root_path.join("test.mojom")).unwrap();
+        let text = r#"
+        module test.mojom;
+        import "foo_module/foo.mojom";
+        import "missing.mojom";
+        import "broken.mojom";
+        import "foo_module/foo.mojom";
+        struct A {};
+        struct B {};
+        interface C {};
+        "#;
+        let mojom = syntax::parse(text).unwrap();
+        let module = crate::analysis::find_module(text, &mojom, &mut Vec::new());
+        let ast = MojomAst::from_mojom(uri.clone(), text.to_owned(), mojom, module);
+        let imported_files = check_imports(&[&root_path], &ast, &mut ImportCache::new());
+
+        let summary = analysis_summary(uri.clone(), Some(&ast), Some(&imported_files));
+        assert!(summary.parsed);
+        assert_eq!(Some("test.mojom"), summary.module_name.as_deref());
+        let statements = summary
+            .statements
+            .iter()
+            .map(|(kind, count)| (*kind, *count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("import", 4),
+                ("interface", 1),
+                ("module", 1),
+                ("struct", 2)
+            ],
+            statements
+        );
+        let imports = summary
+            .imports
+            .iter()
+            .map(|import| (import.path.as_str(), import.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("foo_module/foo.mojom", ImportStatus::Parsed),
+                ("missing.mojom", ImportStatus::NotFound),
+                ("broken.mojom", ImportStatus::SyntaxError),
+                ("foo_module/foo.mojom", ImportStatus::Duplicate),
+            ],
+            imports
+        );
+        assert!(summary.imported_files >= 3);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!("notFound", json["imports"][1]["status"]);
+        assert_eq!(2, json["statements"]["struct"]);
+
+        let summary = analysis_summary(uri, None, None);
+        assert!(!summary.parsed);
+        assert!(summary.imports.is_empty());
+    }
+}
@@ -0,0 +1,763 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use lsp_types::{CompletionItem, CompletionItemKind, SymbolKind};
+
+use crate::syntax::{preorder, Traversal};
+
+use super::definition::{enclosing_scope, find_declaration_in_scope};
+use super::imported_files::ImportedFiles;
+use super::mojomast::MojomAst;
+
+const BUILTIN_TYPES: &[&str] = &[
+    "bool",
+    "int8",
+    "uint8",
+    "int16",
+    "uint16",
+    "int32",
+    "uint32",
+    "int64",
+    "uint64",
+    "float",
+    "double",
+    "string",
+    "handle",
+    "handle<message_pipe>",
+    "handle<shared_buffer>",
+    "handle<data_pipe_consumer>",
+    "handle<data_pipe_producer>",
+    "handle<platform>",
+    "array",
+    "map",
+    "pending_remote",
+    "pending_receiver",
+    "pending_associated_remote",
+    "pending_associated_receiver",
+];
+
+#[inline(always)]
+pub(crate) fn is_identifier_char(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'.'
+}
+
+// Returns `text` with comments and string literals replaced by spaces, and
+// whether `text` ends inside a comment or a string literal.
+pub(crate) fn mask_comments_and_strings(text: &str) -> (Vec<u8>, bool) {
+    enum State {
+        Code,
+        LineComment,
+        BlockComment,
+        String,
+    }
+
+    let bytes = text.as_bytes();
+    let mut masked = bytes.to_vec();
+    let mut state = State::Code;
+    let mut i = 0;
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        match state {
+            State::Code => match (bytes[i], next) {
+                (b'/', Some(b'/')) => state = State::LineComment,
+                (b'/', Some(b'*')) => {
+                    state = State::BlockComment;
+                    masked[i] = b' ';
+                    i += 1;
+                }
+                (b'"', _) => state = State::String,
+                _ => (),
+            },
+            State::LineComment => {
+                if bytes[i] == b'\n' {
+                    state = State::Code;
+                }
+            }
+            State::BlockComment => {
+                if bytes[i] == b'*' && next == Some(b'/') {
+                    state = State::Code;
+                    masked[i] = b' ';
+                    masked[i + 1] = b' ';
+                    i += 2;
+                    continue;
+                }
+            }
+            State::String => match bytes[i] {
+                b'\\' if i + 1 < masked.len() => {
+                    masked[i] = b' ';
+                    i += 1;
+                }
+                b'"' | b'\n' => state = State::Code,
+                _ => (),
+            },
+        }
+        if !matches!(state, State::Code) && masked[i] != b'\n' {
+            masked[i] = b' ';
+        }
+        i += 1;
+    }
+    let in_comment_or_string = !matches!(state, State::Code);
+    (masked, in_comment_or_string)
+}
+
+pub(crate) fn last_non_space(text: &[u8], end: usize) -> Option<usize> {
+    text[..end].iter().rposition(|ch| !ch.is_ascii_whitespace())
+}
+
+// Returns the position of the innermost unclosed bracket before `end`.
+pub(crate) fn enclosing_bracket(text: &[u8], end: usize) -> Option<usize> {
+    let mut depth = 0;
+    for pos in (0..end).rev() {
+        match text[pos] {
+            b')' | b'>' | b'}' | b']' => depth += 1,
+            b'(' | b'<' | b'{' | b'[' => {
+                if depth == 0 {
+                    return Some(pos);
+                }
+                depth -= 1;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+// Returns the start of the statement that ends at `end`. Attributes are not
+// part of the statement.
+fn statement_start(text: &[u8], end: usize) -> usize {
+    text[..end]
+        .iter()
+        .rposition(|&ch| ch == b';' || ch == b'{' || ch == b'}' || ch == b']')
+        .map(|pos| pos + 1)
+        .unwrap_or(0)
+}
+
+// Returns the words before `brace`, e.g. `["struct", "Foo"]` for
+// `struct Foo {`.
+fn block_header(text: &[u8], brace: usize) -> Vec<&str> {
+    match std::str::from_utf8(&text[statement_start(text, brace)..brace]) {
+        Ok(header) => header.split_whitespace().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Returns true when `brace` opens the body of a struct or a union.
+fn is_struct_or_union_body(text: &[u8], brace: usize) -> bool {
+    match block_header(text, brace).first() {
+        Some(&keyword) => keyword == "struct" || keyword == "union",
+        None => false,
+    }
+}
+
+// A heuristic to decide whether `start` is where a type name is written.
+fn is_type_position(text: &[u8], start: usize) -> bool {
+    let prev = match last_non_space(text, start) {
+        Some(prev) => prev,
+        None => return false,
+    };
+    match text[prev] {
+        // `array<`, `map<` and `pending_remote<`.
+        b'<' => true,
+        // The first parameter of a method.
+        b'(' => true,
+        b',' => match enclosing_bracket(text, prev) {
+            Some(pos) => text[pos] == b'(' || text[pos] == b'<',
+            None => false,
+        },
+        b'{' | b';' | b'}' | b']' => {
+            let brace = match text[prev] {
+                b'{' => Some(prev),
+                _ => enclosing_bracket(text, prev + 1),
+            };
+            match brace {
+                Some(brace) if text[brace] == b'{' => is_struct_or_union_body(text, brace),
+                _ => false,
+            }
+        }
+        _ if is_identifier_char(text[prev]) => {
+            let word_start = text[..=prev]
+                .iter()
+                .rposition(|&ch| !is_identifier_char(ch))
+                .map(|pos| pos + 1)
+                .unwrap_or(0);
+            &text[word_start..=prev] == b"const"
+        }
+        _ => false,
+    }
+}
+
+fn into_completion_kind(kind: SymbolKind) -> Option<CompletionItemKind> {
+    match kind {
+        SymbolKind::INTERFACE => Some(CompletionItemKind::INTERFACE),
+        SymbolKind::STRUCT => Some(CompletionItemKind::STRUCT),
+        SymbolKind::ENUM => Some(CompletionItemKind::ENUM),
+        _ => None,
+    }
+}
+
+fn completion_item(
+    label: String,
+    kind: CompletionItemKind,
+    detail: Option<String>,
+) -> CompletionItem {
+    CompletionItem {
+        label,
+        kind: Some(kind),
+        detail,
+        ..Default::default()
+    }
+}
+
+// Returns types declared in `ast`. Nested types are qualified with their
+// enclosing names.
+fn declared_types(ast: &MojomAst) -> Vec<CompletionItem> {
+    let mut items = Vec::new();
+    let mut path = Vec::new();
+    let mut push = |path: &[&str], name: &str, kind: CompletionItemKind| {
+        let mut label = path.join(".");
+        if !label.is_empty() {
+            label.push('.');
+        }
+        label.push_str(name);
+        items.push(completion_item(label, kind, None));
+    };
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => {
+                push(&path, ast.text(&node.name), CompletionItemKind::INTERFACE);
+                path.push(ast.text(&node.name));
+            }
+            Traversal::EnterStruct(node) => {
+                push(&path, ast.text(&node.name), CompletionItemKind::STRUCT);
+                path.push(ast.text(&node.name));
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                path.pop();
+            }
+            Traversal::Union(node) => push(&path, ast.text(&node.name), CompletionItemKind::STRUCT),
+            Traversal::Enum(node) => push(&path, ast.text(&node.name), CompletionItemKind::ENUM),
+            _ => (),
+        }
+    }
+    items
+}
+
+// Returns declarations in imported files of which kinds `into_kind` accepts.
+fn imported_symbols(
+    imported_files: &ImportedFiles,
+    module_name: Option<&str>,
+    root_paths: &[PathBuf],
+    into_kind: impl Fn(SymbolKind) -> Option<CompletionItemKind>,
+) -> Vec<CompletionItem> {
+    let mut items = Vec::new();
+    for symbol in imported_files.symbols() {
+        let kind = match into_kind(symbol.kind) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        // Declarations in other modules are referred with the module name.
+        let label = match symbol.module_name {
+            Some(name) if Some(name) != module_name => format!("{}.{}", name, symbol.ident),
+            _ => symbol.ident.to_owned(),
+        };
+        let detail = symbol
+            .uri
+            .to_file_path()
+            .ok()
+            .map(|path| {
+                root_paths
+                    .iter()
+                    .find_map(|root_path| path.strip_prefix(root_path).ok())
+                    .map(|path| path.to_owned())
+                    .unwrap_or(path)
+            })
+            .map(|path| format!("from {}", path.display()));
+        items.push(completion_item(label, kind, detail));
+    }
+    items
+}
+
+// Returns the name of the attribute of which value starts at `start`, e.g.
+// `RuntimeFeature` in `[Stable, RuntimeFeature=`.
+fn attribute_name_before(text: &[u8], start: usize) -> Option<&str> {
+    let equal = last_non_space(text, start)?;
+    if text[equal] != b'=' {
+        return None;
+    }
+    let name_end = last_non_space(text, equal)? + 1;
+    let name_start = text[..name_end]
+        .iter()
+        .rposition(|&ch| !is_identifier_char(ch))
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+    let separator = last_non_space(text, name_start)?;
+    if name_start == name_end || !matches!(text[separator], b'[' | b',') {
+        return None;
+    }
+    match enclosing_bracket(text, name_start) {
+        Some(bracket) if text[bracket] == b'[' => {
+            std::str::from_utf8(&text[name_start..name_end]).ok()
+        }
+        _ => None,
+    }
+}
+
+// Returns completion items of the value of the attribute `name`. Features
+// are offered for `RuntimeFeature` and build flags for `EnableIf` and
+// `EnableIfNot`.
+fn attribute_values(
+    name: &str,
+    ast: Option<&MojomAst>,
+    imported_files: Option<&ImportedFiles>,
+    root_paths: &[PathBuf],
+    build_flags: &[String],
+) -> Vec<CompletionItem> {
+    match name {
+        "RuntimeFeature" => {
+            let mut items = Vec::new();
+            if let Some(ast) = ast {
+                for traversal in preorder(&ast.mojom) {
+                    if let Traversal::Feature(node) = traversal {
+                        let label = ast.text(&node.name).to_owned();
+                        items.push(completion_item(label, CompletionItemKind::CONSTANT, None));
+                    }
+                }
+            }
+            if let Some(imported_files) = imported_files {
+                let module_name = ast.and_then(|ast| ast.module_name());
+                items.extend(imported_symbols(
+                    imported_files,
+                    module_name,
+                    root_paths,
+                    |kind| match kind {
+                        SymbolKind::OBJECT => Some(CompletionItemKind::CONSTANT),
+                        _ => None,
+                    },
+                ));
+            }
+            items
+        }
+        "EnableIf" | "EnableIfNot" => build_flags
+            .iter()
+            .map(|flag| completion_item(flag.clone(), CompletionItemKind::VALUE, None))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Returns the type of the struct field of which default value starts at
+// `start` and the enclosing scope, when `ast` is parsed from the same text up
+// to `start`.
+fn parsed_field_type<'a>(
+    ast: &'a MojomAst,
+    text: &str,
+    start: usize,
+) -> Option<(&'a str, Vec<&'a str>)> {
+    if ast.text.as_bytes().get(..start) != Some(&text.as_bytes()[..start]) {
+        return None;
+    }
+    let typ = preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::StructField(node) if node.name.end <= start && start <= node.range.end => {
+            Some(ast.text(&node.typ))
+        }
+        _ => None,
+    })?;
+    Some((typ, enclosing_scope(ast, start)))
+}
+
+// Finds the type of a struct field in `text` when the document doesn't
+// parse, e.g. `MyEnum` in `struct Foo { MyEnum my_field =`. `equal` is the
+// position of `=`. The enclosing scope is taken from headers of enclosing
+// blocks.
+fn textual_field_type(text: &[u8], equal: usize) -> Option<(&str, Vec<&str>)> {
+    let brace = enclosing_bracket(text, equal)?;
+    if text[brace] != b'{' || block_header(text, brace).first() != Some(&"struct") {
+        return None;
+    }
+    let header = std::str::from_utf8(&text[statement_start(text, equal)..equal]).ok()?;
+    let typ = match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [typ, _name] => *typ,
+        _ => return None,
+    };
+
+    let mut scope = Vec::new();
+    let mut brace = Some(brace);
+    while let Some(pos) = brace {
+        match block_header(text, pos).as_slice() {
+            [keyword, name] if text[pos] == b'{' && matches!(*keyword, "struct" | "interface") => {
+                scope.push(*name)
+            }
+            _ => break,
+        }
+        brace = enclosing_bracket(text, pos);
+    }
+    scope.reverse();
+    Some((typ, scope))
+}
+
+// Returns the kind of the declaration of `typ` in `ast` and the names of its
+// values when it's an enum. Unions are reported as structs.
+fn declared_members<'a>(
+    ast: &'a MojomAst,
+    typ: &str,
+    scope: &[&str],
+) -> Option<(SymbolKind, Vec<&'a str>)> {
+    let declaration = find_declaration_in_scope(typ, scope, ast)?;
+    preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::Enum(node) if node.name == *declaration.name => {
+            let values = node.values.iter().map(|value| ast.text(&value.name));
+            Some((SymbolKind::ENUM, values.collect()))
+        }
+        Traversal::EnterStruct(node) if node.name == *declaration.name => {
+            Some((SymbolKind::STRUCT, Vec::new()))
+        }
+        Traversal::Union(node) if node.name == *declaration.name => {
+            Some((SymbolKind::STRUCT, Vec::new()))
+        }
+        _ => None,
+    })
+}
+
+// Returns completion items of the default value of a struct field when
+// `start` follows `=` of the field: values of an enum type, or `default` for
+// struct and union types.
+fn default_values(
+    text: &str,
+    masked: &[u8],
+    start: usize,
+    ast: Option<&MojomAst>,
+    imported_files: Option<&ImportedFiles>,
+) -> Option<Vec<CompletionItem>> {
+    let equal = last_non_space(masked, start)?;
+    if masked[equal] != b'=' {
+        return None;
+    }
+    let (typ, scope) = match ast.and_then(|ast| parsed_field_type(ast, text, start)) {
+        Some(field) => field,
+        None => textual_field_type(masked, equal)?,
+    };
+    // Nullable fields take the same values.
+    let typ = typ.trim_end_matches('?');
+    let members = ast
+        .and_then(|ast| declared_members(ast, typ, &scope))
+        .or_else(|| imported_files?.find_members(typ));
+    let items = match members {
+        // Enum values are qualified with the type as written.
+        Some((SymbolKind::ENUM, values)) => values
+            .into_iter()
+            .map(|value| {
+                let label = format!("{}.{}", typ, value);
+                completion_item(label, CompletionItemKind::ENUM_MEMBER, None)
+            })
+            .collect(),
+        Some((SymbolKind::STRUCT, _)) => {
+            vec![completion_item(
+                "default".to_owned(),
+                CompletionItemKind::KEYWORD,
+                None,
+            )]
+        }
+        _ => Vec::new(),
+    };
+    Some(items)
+}
+
+/// Returns completion items at `offset` of `text`. `ast` can be parsed from
+/// an older text because documents don't parse while typing.
+pub(crate) fn completion(
+    text: &str,
+    offset: usize,
+    ast: Option<&MojomAst>,
+    imported_files: Option<&ImportedFiles>,
+    root_paths: &[PathBuf],
+    build_flags: &[String],
+) -> Vec<CompletionItem> {
+    let offset = std::cmp::min(offset, text.len());
+    if !text.is_char_boundary(offset) {
+        return Vec::new();
+    }
+    let (masked, in_comment_or_string) = mask_comments_and_strings(&text[..offset]);
+    if in_comment_or_string {
+        return Vec::new();
+    }
+    let start = masked
+        .iter()
+        .rposition(|&ch| !is_identifier_char(ch))
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+    if let Some(name) = attribute_name_before(&masked, start) {
+        return attribute_values(name, ast, imported_files, root_paths, build_flags);
+    }
+    if let Some(items) = default_values(text, &masked, start, ast, imported_files) {
+        return items;
+    }
+    if !is_type_position(&masked, start) {
+        return Vec::new();
+    }
+
+    let mut items = BUILTIN_TYPES
+        .iter()
+        .map(|name| completion_item(name.to_string(), CompletionItemKind::KEYWORD, None))
+        .collect::<Vec<_>>();
+    if let Some(ast) = ast {
+        items.extend(declared_types(ast));
+    }
+    if let Some(imported_files) = imported_files {
+        let module_name = ast.and_then(|ast| ast.module_name());
+        items.extend(imported_symbols(
+            imported_files,
+            module_name,
+            root_paths,
+            into_completion_kind,
+        ));
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::syntax;
+
+    use super::super::imported_files::{check_imports, ImportCache};
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    // `|` in `input` is the cursor.
+    fn complete(input: &str) -> Vec<CompletionItem> {
+        let offset = input.find('|').unwrap();
+        let text = input.replace('|', "");
+        completion(&text, offset, None, None, &[], &[])
+    }
+
+    #[test]
+    fn test_type_position() {
+        assert!(!complete("struct Foo { int32 a; in|").is_empty());
+        assert!(!complete("struct Foo {\n  |").is_empty());
+        assert!(!complete("struct Foo { array<|").is_empty());
+        assert!(!complete("struct Foo { map<string, |").is_empty());
+        assert!(!complete("union Foo { |").is_empty());
+        assert!(!complete("struct Foo { [MinVersion=1] |").is_empty());
+        assert!(!complete("struct Foo { enum E { kA }; |").is_empty());
+        assert!(!complete("interface Foo { Method(|").is_empty());
+        assert!(!complete("interface Foo { Method(int32 a, |").is_empty());
+        assert!(!complete("interface Foo { Method() => (|").is_empty());
+        assert!(!complete("const |").is_empty());
+
+        // Method names, field names and top-level statements.
+        assert!(complete("interface Foo { |").is_empty());
+        assert!(complete("struct Foo { int32 |").is_empty());
+        assert!(complete("|").is_empty());
+        assert!(complete("struct Foo {}; |").is_empty());
+        assert!(complete("struct Foo { enum E { kA, |").is_empty());
+        assert!(complete("[Attr=1, |").is_empty());
+
+        // Comments and strings.
+        assert!(complete("struct Foo { // |").is_empty());
+        assert!(complete("struct Foo { /* a, |").is_empty());
+        assert!(complete("import \"foo(|").is_empty());
+        assert!(!complete("struct Foo { /* a; */ |").is_empty());
+    }
+
+    #[test]
+    fn test_mask_comments_and_strings() {
+        let (masked, in_comment_or_string) = mask_comments_and_strings("a \"b\\\"c\" d");
+        assert_eq!(b"a      \" d", masked.as_slice());
+        assert!(!in_comment_or_string);
+
+        // The text ends with an escape in a string.
+        let (masked, in_comment_or_string) = mask_comments_and_strings("import \"foo\\");
+        assert_eq!(b"import      ", masked.as_slice());
+        assert!(in_comment_or_string);
+    }
+
+    #[test]
+    fn test_attribute_values() {
+        let complete = |input: &str| {
+            let offset = input.find('|').unwrap();
+            let text = input.replace('|', "");
+            // The AST is parsed from an older text.
+            let old_text = "feature kMyFeature { const bool default_state = true; };";
+            let mojom = syntax::parse(old_text).unwrap();
+            let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+            let ast = MojomAst::from_mojom(uri, old_text.to_owned(), mojom, None);
+            let build_flags = vec!["is_win".to_owned(), "is_mac".to_owned()];
+            let items = completion(&text, offset, Some(&ast), None, &[], &build_flags);
+            labels(&items)
+                .iter()
+                .map(|&label| label.to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["kMyFeature"], complete("[RuntimeFeature=|"));
+        assert_eq!(
+            vec!["kMyFeature"],
+            complete("[Stable, RuntimeFeature = kMy|")
+        );
+        assert_eq!(
+            vec!["kMyFeature"],
+            complete("interface Foo {\n  [RuntimeFeature=|]\n  Bar();\n};")
+        );
+        assert_eq!(vec!["is_win", "is_mac"], complete("[EnableIf=|"));
+        assert_eq!(
+            vec!["is_win", "is_mac"],
+            complete("struct Foo { [EnableIfNot=is|")
+        );
+        assert!(complete("[MinVersion=|").is_empty());
+        assert!(complete("const int32 kA = |").is_empty());
+        assert!(complete("enum E { kA, kB = |").is_empty());
+    }
+
+    #[test]
+    fn test_default_values() {
+        // The document is parsed.
+        let text = "struct Foo {\n  enum E { kA, kB };\n  E e = kA;\n  E? f = kB;\n};";
+        let mojom = syntax::parse(text).unwrap();
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom, None);
+        let complete_parsed = |before: &str| {
+            let offset = text.find(before).unwrap() + before.len();
+            let start = text[..offset].trim_end_matches(|ch| ch != ' ').len();
+            assert!(parsed_field_type(&ast, text, start).is_some());
+            let items = completion(text, offset, Some(&ast), None, &[], &[]);
+            labels(&items)
+                .iter()
+                .map(|&label| label.to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["E.kA", "E.kB"], complete_parsed("E e = "));
+        assert_eq!(vec!["E.kA", "E.kB"], complete_parsed("E? f = k"));
+
+        // The document doesn't parse while typing. The AST is parsed from an
+        // older text.
+        let old_text =
+            "enum Color { kRed, kGreen };\nstruct Bar {};\nstruct Foo {\n  enum E { kA };\n};";
+        let mojom = syntax::parse(old_text).unwrap();
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, old_text.to_owned(), mojom, None);
+        let complete_fallback = |input: &str| {
+            let offset = input.find('|').unwrap();
+            let text = input.replace('|', "");
+            let items = completion(&text, offset, Some(&ast), None, &[], &[]);
+            labels(&items)
+                .iter()
+                .map(|&label| label.to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["Color.kRed", "Color.kGreen"],
+            complete_fallback("struct Foo {\n  Color color = |")
+        );
+        assert_eq!(
+            vec!["Color.kRed", "Color.kGreen"],
+            complete_fallback("struct Foo {\n  [MinVersion=1] Color color@1 = kG|\n};")
+        );
+        assert_eq!(
+            vec!["E.kA"],
+            complete_fallback("struct Foo {\n  enum E { kA };\n  E e = |")
+        );
+        assert_eq!(
+            vec!["default"],
+            complete_fallback("struct Foo { Bar? bar = |")
+        );
+        assert!(complete_fallback("struct Foo { int32 a = |").is_empty());
+        assert!(complete_fallback("struct Foo { Unknown a = |").is_empty());
+        assert!(complete_fallback("const Color kColor = |").is_empty());
+        assert!(complete_fallback("enum E { kA = |").is_empty());
+    }
+
+    #[test]
+    fn test_imported_default_values() {
+        let path = Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+        let root_paths = vec![Path::new("testdata").canonicalize().unwrap()];
+        let imported_files = check_imports(&root_paths, &ast, &mut ImportCache::new());
+
+        let complete = |input: &str| {
+            let items = completion(
+                input,
+                input.len(),
+                Some(&ast),
+                Some(&imported_files),
+                &root_paths,
+                &[],
+            );
+            labels(&items)
+                .iter()
+                .map(|&label| label.to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                "foo.FooStruct.FooEnum.kOne",
+                "foo.FooStruct.FooEnum.kTwo",
+                "foo.FooStruct.FooEnum.kThree",
+            ],
+            complete("struct S { foo.FooStruct.FooEnum e = ")
+        );
+        assert_eq!(vec!["default"], complete("struct S { foo.FooStruct s = "));
+    }
+
+    #[test]
+    fn test_completion_items() {
+        let path = Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+        let root_paths = vec![Path::new("testdata").canonicalize().unwrap()];
+        let imported_files = check_imports(&root_paths, &ast, &mut ImportCache::new());
+
+        let input = "interface MyService { Method(";
+        let offset = input.len();
+        let items = completion(
+            input,
+            offset,
+            Some(&ast),
+            Some(&imported_files),
+            &root_paths,
+            &[],
+        );
+        let labels = labels(&items);
+        assert!(labels.contains(&"uint64"));
+        assert!(labels.contains(&"MyService"));
+        assert!(labels.contains(&"MyInterface.MyInnerEnum"));
+        assert!(labels.contains(&"foo.FooStruct.FooEnum"));
+
+        let item = items
+            .iter()
+            .find(|item| item.label == "foo.FooStruct")
+            .unwrap();
+        assert_eq!(Some(CompletionItemKind::STRUCT), item.kind);
+        assert_eq!(Some("from foo_module/foo.mojom"), item.detail.as_deref());
+        let item = items
+            .iter()
+            .find(|item| item.label == "MyInterface")
+            .unwrap();
+        assert_eq!(Some(CompletionItemKind::INTERFACE), item.kind);
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::protocol::{
+    read_messages, write_any_message, ErrorCodes, Id, InvalidMessage, Message, ResponseError,
+    ResponseMessage, DEFAULT_MAX_CONTENT_LENGTH,
+};
+
+/// Message I/O between a server and a client. Messages from the peer arrive
+/// on `receiver` and messages to the peer are sent to `sender`. The
+/// connection is closed when either side drops its end.
+pub struct Connection {
+    pub sender: Sender<Message>,
+    pub receiver: Receiver<Message>,
+}
+
+impl Connection {
+    /// Creates a pair of connected connections, one for a server and one for
+    /// a client. Messages are exchanged without serialization.
+    pub fn memory() -> (Connection, Connection) {
+        let (server_sender, client_receiver) = channel();
+        let (client_sender, server_receiver) = channel();
+        let server = Connection {
+            sender: server_sender,
+            receiver: server_receiver,
+        };
+        let client = Connection {
+            sender: client_sender,
+            receiver: client_receiver,
+        };
+        (server, client)
+    }
+
+    /// Creates a connection that reads and writes messages of the base
+    /// protocol on `reader` and `writer`, e.g. stdin and stdout. Reading and
+    /// writing happen on separate threads. Messages that can't be parsed are
+    /// answered with parse errors and skipped.
+    pub fn stream<R, W>(reader: R, writer: W) -> (Connection, StreamHandle)
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (incoming_sender, incoming_receiver) = channel();
+        let (outgoing_sender, outgoing_receiver) = channel();
+        let writer = start_writer_thread(writer, outgoing_receiver);
+        let handle = StreamHandle {
+            max_content_length: Arc::new(AtomicUsize::new(DEFAULT_MAX_CONTENT_LENGTH)),
+            error: Default::default(),
+            error_sender: Arc::new(Mutex::new(Some(outgoing_sender.clone()))),
+            writer: Arc::new(Mutex::new(Some(writer))),
+        };
+        start_reader_thread(BufReader::new(reader), incoming_sender, handle.clone());
+        let connection = Connection {
+            sender: outgoing_sender,
+            receiver: incoming_receiver,
+        };
+        (connection, handle)
+    }
+}
+
+/// Controls the threads of a stream connection.
+#[derive(Clone)]
+pub struct StreamHandle {
+    max_content_length: Arc<AtomicUsize>,
+    error: Arc<Mutex<Option<anyhow::Error>>>,
+    // The reader thread answers invalid messages with it. Taken when the
+    // writer is joined because the reader may block forever.
+    error_sender: Arc<Mutex<Option<Sender<Message>>>>,
+    writer: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl StreamHandle {
+    /// Sets the maximum size of a message. Larger messages are skipped.
+    pub fn set_max_content_length(&self, max_content_length: usize) {
+        self.max_content_length
+            .store(max_content_length, Ordering::Relaxed);
+    }
+
+    /// Takes the error that stopped reading, e.g. an I/O error. The receiver
+    /// of the connection is disconnected after the error is recorded.
+    pub fn take_error(&self) -> Option<anyhow::Error> {
+        self.error.lock().unwrap().take()
+    }
+
+    /// Waits until the writer thread writes all queued messages. The sender
+    /// of the connection and its clones must be dropped beforehand, otherwise
+    /// this blocks forever.
+    pub fn join_writer(&self) {
+        self.error_sender.lock().unwrap().take();
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            if writer.join().is_err() {
+                log::error!("The writer thread panicked");
+            }
+        }
+    }
+}
+
+pub(crate) fn start_writer_thread<W: Write + Send + 'static>(
+    writer: W,
+    receiver: Receiver<Message>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut writer = BufWriter::new(writer);
+        // Terminate the thread when all senders are dropped.
+        for message in receiver {
+            if let Err(err) = write_any_message(&mut writer, message) {
+                log::error!("Failed to write a message: {}", err);
+                break;
+            }
+        }
+    })
+}
+
+// Messages that are already received are read together and passed at once
+// so that the server sees `$/cancelRequest` among them before it handles the
+// requests. Reading continues after an `InvalidMessage`.
+fn start_reader_thread<R>(mut reader: BufReader<R>, sender: Sender<Message>, handle: StreamHandle)
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || loop {
+        let mut messages = Vec::new();
+        let mut error = None;
+        loop {
+            // Wait for the next message so that the latest limit applies to
+            // it.
+            if let Err(err) = reader.fill_buf() {
+                error = Some(err.into());
+                break;
+            }
+            let max_content_length = handle.max_content_length.load(Ordering::Relaxed);
+            match read_messages(&mut reader, max_content_length) {
+                Ok(batch) => messages.extend(batch),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+            if reader.buffer().is_empty() {
+                break;
+            }
+        }
+        for message in messages {
+            if sender.send(message).is_err() {
+                return;
+            }
+        }
+        match error.map(|err| err.downcast::<InvalidMessage>()) {
+            Some(Ok(InvalidMessage(message))) => {
+                log::warn!("{}", message);
+                let response = ResponseMessage {
+                    id: Id::Null,
+                    result: None,
+                    error: Some(ResponseError::new(ErrorCodes::ParseError, message)),
+                };
+                let error_sender = handle.error_sender.lock().unwrap();
+                let sent = match error_sender.as_ref() {
+                    Some(error_sender) => error_sender.send(Message::Response(response)),
+                    None => return,
+                };
+                if sent.is_err() {
+                    return;
+                }
+            }
+            Some(Err(err)) => {
+                // `sender` is dropped after the error is recorded.
+                *handle.error.lock().unwrap() = Some(err);
+                return;
+            }
+            None => (),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::protocol::{read_message, write_notification, write_request};
+
+    #[test]
+    fn test_stream() {
+        let (reader, mut writer) = pipe::pipe();
+        let (r, w) = pipe::pipe();
+        let (connection, handle) = Connection::stream(reader, w);
+        let mut r = BufReader::new(r);
+
+        write_request(
+            &mut writer,
+            Id::Number(1),
+            "shutdown",
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        match connection.receiver.recv().unwrap() {
+            Message::Request(request) => assert_eq!("shutdown", request.method),
+            _ => unreachable!(),
+        }
+
+        // Messages over the limit get parse errors.
+        handle.set_max_content_length(10);
+        write_notification(&mut writer, "exit", serde_json::Value::Null).unwrap();
+        match read_message(&mut r).unwrap() {
+            Message::Response(response) => {
+                assert_eq!(Id::Null, response.id);
+                assert_eq!(-32700, response.error.unwrap().code);
+            }
+            _ => unreachable!(),
+        }
+        assert!(handle.take_error().is_none());
+
+        // Responses are written with `jsonrpc`.
+        let response = ResponseMessage {
+            id: Id::Number(1),
+            result: Some(serde_json::Value::Null),
+            error: None,
+        };
+        connection.sender.send(Message::Response(response)).unwrap();
+        match read_message(&mut r).unwrap() {
+            Message::Response(response) => assert_eq!(Id::Number(1), response.id),
+            _ => unreachable!(),
+        }
+
+        // The input ends in the middle of a message.
+        write!(writer, "Content-Length: 100\r\n\r\n{{").unwrap();
+        drop(writer);
+        assert!(connection.receiver.recv().is_err());
+        assert!(handle.take_error().is_some());
+    }
+
+    #[test]
+    fn test_memory() {
+        let (server, client) = Connection::memory();
+        let request = Message::Request(super::super::protocol::RequestMessage {
+            id: Id::Number(1),
+            method: "shutdown".to_owned(),
+            params: serde_json::Value::Null,
+        });
+        client.sender.send(request).unwrap();
+        assert!(matches!(server.receiver.recv(), Ok(Message::Request(_))));
+
+        drop(server);
+        assert!(client.receiver.recv().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Conversions between byte offset ranges of the syntax tree and LSP ranges.
+// `line_index` must be the line index of `text`.
+
+use crate::syntax;
+
+use super::textpos::LineIndex;
+
+/// Converts `range` of `text` into an LSP range.
+pub(crate) fn to_lsp_range(
+    line_index: &LineIndex,
+    text: &str,
+    range: &syntax::Range,
+) -> lsp_types::Range {
+    lsp_types::Range::new(
+        line_index.position(text, range.start),
+        line_index.position(text, range.end),
+    )
+}
+
+/// Converts an LSP range of `text` into byte offsets. Positions are clamped
+/// like `LineIndex::offset()` and a reversed range is collapsed to its
+/// start.
+pub(crate) fn to_offset_range(
+    line_index: &LineIndex,
+    text: &str,
+    range: &lsp_types::Range,
+) -> syntax::Range {
+    let start = line_index.offset(text, &range.start);
+    let end = line_index.offset(text, &range.end).max(start);
+    syntax::Range { start, end }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Position;
+
+    use super::super::textpos::PositionEncoding;
+
+    fn lsp_range(start: (u32, u32), end: (u32, u32)) -> lsp_types::Range {
+        lsp_types::Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "ab\r\n\ncdé\n";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        // Every pair of offsets at character boundaries, including the line
+        // endings and the end of the text.
+        let offsets = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect::<Vec<_>>();
+        for &start in offsets.iter() {
+            for &end in offsets.iter().filter(|&&end| end >= start) {
+                let range = syntax::Range { start, end };
+                let lsp = to_lsp_range(&index, text, &range);
+                // `\r\n` is a single line ending, so the offset of `\n`
+                // maps back to `\r`.
+                let expected = |offset: usize| match offset {
+                    3 => 2,
+                    offset => offset,
+                };
+                let expected = syntax::Range {
+                    start: expected(start),
+                    end: expected(end),
+                };
+                assert_eq!(expected, to_offset_range(&index, text, &lsp));
+            }
+        }
+
+        // Line boundaries.
+        let range = syntax::Range { start: 2, end: 5 };
+        assert_eq!(
+            lsp_range((0, 2), (2, 0)),
+            to_lsp_range(&index, text, &range)
+        );
+        let range = syntax::Range { start: 5, end: 10 };
+        assert_eq!(
+            lsp_range((2, 0), (3, 0)),
+            to_lsp_range(&index, text, &range)
+        );
+        for lsp in [
+            lsp_range((0, 0), (1, 0)),
+            lsp_range((1, 0), (2, 0)),
+            lsp_range((2, 1), (2, 3)),
+            lsp_range((2, 0), (3, 0)),
+        ] {
+            let range = to_offset_range(&index, text, &lsp);
+            assert_eq!(lsp, to_lsp_range(&index, text, &range));
+        }
+    }
+
+    #[test]
+    fn test_to_offset_range_clamped() {
+        let text = "ab\ncd";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        // Past the end of the line and past the end of the text.
+        let range = to_offset_range(&index, text, &lsp_range((0, 1), (0, 10)));
+        assert_eq!(syntax::Range { start: 1, end: 2 }, range);
+        let range = to_offset_range(&index, text, &lsp_range((1, 0), (5, 0)));
+        assert_eq!(syntax::Range { start: 3, end: 5 }, range);
+        // Reversed.
+        let range = to_offset_range(&index, text, &lsp_range((1, 1), (0, 1)));
+        assert_eq!(syntax::Range { start: 4, end: 4 }, range);
+    }
+}
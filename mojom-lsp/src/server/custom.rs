@@ -0,0 +1,408 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Requests that aren't part of the protocol. Editor extensions use them to
+// get structured data that standard requests don't provide.
+
+use std::convert::TryFrom;
+
+use lsp_types::{Location, Range, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::analysis::eval_enum_values;
+use crate::syntax::{self, preorder, InterfaceMember, StructBody, Traversal, TypeName, TypeSpec};
+
+use super::mojomast::MojomAst;
+
+pub(crate) const TYPE_INFO_METHOD: &str = "mojom/typeInfo";
+pub(crate) const SERVER_STATUS_METHOD: &str = "mojom/serverStatus";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TypeInfoParams {
+    pub(crate) text_document: TextDocumentIdentifier,
+    // A fully qualified name like `foo.mojom.MyStruct` or
+    // `MyInterface.MyEnum`. The name is resolved at the top level of the
+    // document, which includes its imported files.
+    pub(crate) type_name: String,
+}
+
+// The result of `mojom/typeInfo`.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TypeInfo {
+    // "interface", "struct", "union" or "enum".
+    pub(crate) kind: &'static str,
+    pub(crate) name: String,
+    // The name with the module and the enclosing declarations, e.g.
+    // `foo.mojom.MyInterface.MyEnum`.
+    pub(crate) qualified_name: String,
+    pub(crate) uri: Url,
+    pub(crate) range: Range,
+    pub(crate) selection_range: Range,
+    // Fields of structs and unions.
+    pub(crate) fields: Vec<FieldInfo>,
+    // Values of enums.
+    pub(crate) values: Vec<EnumValueInfo>,
+    // Methods of interfaces.
+    pub(crate) methods: Vec<MethodInfo>,
+}
+
+// A field or a method parameter.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FieldInfo {
+    pub(crate) name: String,
+    // The type as written, e.g. `array<int32>?`.
+    #[serde(rename = "type")]
+    pub(crate) typ: String,
+    // The parsed type. See `type_spec_json()`. Null when the type is
+    // invalid.
+    pub(crate) type_spec: Value,
+    // The explicit ordinal like `@1`.
+    pub(crate) ordinal: Option<u32>,
+    pub(crate) range: Range,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EnumValueInfo {
+    pub(crate) name: String,
+    // The evaluated value. Null when it can't be evaluated, e.g. when it
+    // refers to a value in an imported file.
+    pub(crate) value: Option<i64>,
+    pub(crate) range: Range,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MethodInfo {
+    pub(crate) name: String,
+    pub(crate) ordinal: Option<u32>,
+    pub(crate) parameters: Vec<FieldInfo>,
+    // Null when the method has no response.
+    pub(crate) response_parameters: Option<Vec<FieldInfo>>,
+    pub(crate) range: Range,
+}
+
+// Describes `spec` like `{"kind": "array", "element": {...}, "nullable":
+// false}`. `kind` is one of "array", "map", "interfaceRequest",
+// "pendingRemote", "pendingReceiver", "handle", "associated" and "named".
+// Fixed-size arrays have `size`.
+fn type_spec_json(spec: &TypeSpec) -> Value {
+    let mut value = match &spec.type_name {
+        TypeName::FixedArray(element, size, _) => {
+            json!({ "kind": "array", "element": type_spec_json(element), "size": size })
+        }
+        TypeName::Array(element) => json!({ "kind": "array", "element": type_spec_json(element) }),
+        TypeName::Map(key, value) => json!({
+            "kind": "map",
+            "key": type_spec_json(key),
+            "value": type_spec_json(value),
+        }),
+        TypeName::InterfaceRequest(name, associated) => {
+            json!({ "kind": "interfaceRequest", "name": name, "associated": associated })
+        }
+        TypeName::PendingRemote(name) => {
+            json!({ "kind": "pendingRemote", "name": name, "associated": false })
+        }
+        TypeName::PendingReceiver(name) => {
+            json!({ "kind": "pendingReceiver", "name": name, "associated": false })
+        }
+        TypeName::PendingAssociatedRemote(name) => {
+            json!({ "kind": "pendingRemote", "name": name, "associated": true })
+        }
+        TypeName::PendingAssociatedReceiver(name) => {
+            json!({ "kind": "pendingReceiver", "name": name, "associated": true })
+        }
+        TypeName::Handle(handle_type) => json!({ "kind": "handle", "handleType": handle_type }),
+        TypeName::Associated(name) => json!({ "kind": "associated", "name": name }),
+        TypeName::BasicTypeName(name) => json!({ "kind": "named", "name": name }),
+    };
+    value["nullable"] = spec.is_nullable.into();
+    value
+}
+
+fn parse_ordinal(ast: &MojomAst, ordinal: &Option<syntax::Range>) -> Option<u32> {
+    ast.text(ordinal.as_ref()?).strip_prefix('@')?.parse().ok()
+}
+
+fn field_info(
+    ast: &MojomAst,
+    name: &syntax::Range,
+    typ: &syntax::Range,
+    type_spec: anyhow::Result<TypeSpec>,
+    ordinal: &Option<syntax::Range>,
+    range: &syntax::Range,
+) -> FieldInfo {
+    FieldInfo {
+        name: ast.text(name).to_owned(),
+        typ: ast.text(typ).to_owned(),
+        type_spec: type_spec
+            .map(|spec| type_spec_json(&spec))
+            .unwrap_or(Value::Null),
+        ordinal: parse_ordinal(ast, ordinal),
+        range: ast.lsp_range(range),
+    }
+}
+
+fn parameter_infos(ast: &MojomAst, params: &[syntax::Parameter]) -> Vec<FieldInfo> {
+    params
+        .iter()
+        .map(|param| {
+            let spec = param.type_spec(&ast.text);
+            field_info(
+                ast,
+                &param.name,
+                &param.typ,
+                spec,
+                &param.ordinal,
+                &param.range,
+            )
+        })
+        .collect()
+}
+
+/// Returns the info of the type that is declared at `definition` in `ast`.
+/// Returns None when `definition` isn't a type, e.g. a const.
+pub(crate) fn type_info(ast: &MojomAst, definition: &Location) -> Option<TypeInfo> {
+    if definition.uri != ast.uri {
+        return None;
+    }
+    let text = &ast.text;
+    let mut scope = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        let (kind, range, name) = match traversal {
+            Traversal::EnterInterface(node) => ("interface", &node.range, &node.name),
+            Traversal::EnterStruct(node) => ("struct", &node.range, &node.name),
+            Traversal::Union(node) => ("union", &node.range, &node.name),
+            Traversal::Enum(node) => ("enum", &node.range, &node.name),
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) | Traversal::LeaveEnum(_) => {
+                scope.pop();
+                continue;
+            }
+            _ => continue,
+        };
+        if ast.lsp_range(name) != definition.range {
+            // Unions have no members to enter.
+            if kind != "union" {
+                scope.push(ast.text(name));
+            }
+            continue;
+        }
+
+        let mut info = TypeInfo {
+            kind,
+            name: ast.text(name).to_owned(),
+            qualified_name: ast
+                .module_name()
+                .into_iter()
+                .chain(scope.iter().copied())
+                .chain(std::iter::once(ast.text(name)))
+                .collect::<Vec<_>>()
+                .join("."),
+            uri: ast.uri.clone(),
+            range: ast.lsp_range(range),
+            selection_range: definition.range,
+            fields: Vec::new(),
+            values: Vec::new(),
+            methods: Vec::new(),
+        };
+        match traversal {
+            Traversal::EnterInterface(node) => {
+                info.methods = node
+                    .members
+                    .iter()
+                    .filter_map(|member| match member {
+                        InterfaceMember::Method(method) => Some(method),
+                        _ => None,
+                    })
+                    .map(|method| MethodInfo {
+                        name: ast.text(&method.name).to_owned(),
+                        ordinal: parse_ordinal(ast, &method.ordinal),
+                        parameters: parameter_infos(ast, &method.params),
+                        response_parameters: method
+                            .response
+                            .as_ref()
+                            .map(|response| parameter_infos(ast, &response.params)),
+                        range: ast.lsp_range(&method.range),
+                    })
+                    .collect();
+            }
+            Traversal::EnterStruct(node) => {
+                info.fields = node
+                    .members
+                    .iter()
+                    .filter_map(|member| match member {
+                        StructBody::Field(field) => Some(field),
+                        _ => None,
+                    })
+                    .map(|field| {
+                        let spec = field.type_spec(text);
+                        field_info(
+                            ast,
+                            &field.name,
+                            &field.typ,
+                            spec,
+                            &field.ordinal,
+                            &field.range,
+                        )
+                    })
+                    .collect();
+            }
+            Traversal::Union(node) => {
+                info.fields = node
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let spec = field.type_spec(text);
+                        field_info(
+                            ast,
+                            &field.name,
+                            &field.typ,
+                            spec,
+                            &field.ordinal,
+                            &field.range,
+                        )
+                    })
+                    .collect();
+            }
+            Traversal::Enum(node) => {
+                scope.push(ast.text(name));
+                let values = eval_enum_values(text, &ast.mojom, &scope, node);
+                info.values = node
+                    .values
+                    .iter()
+                    .zip(values)
+                    .map(|(enum_value, value)| EnumValueInfo {
+                        name: ast.text(&enum_value.name).to_owned(),
+                        value: value.and_then(|value| i64::try_from(value).ok()),
+                        range: ast.lsp_range(&enum_value.range),
+                    })
+                    .collect();
+            }
+            _ => unreachable!(),
+        }
+        return Some(info);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let module = crate::analysis::find_module(text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, module)
+    }
+
+    fn definition(ast: &MojomAst, name: &str) -> Location {
+        let declaration = super::super::definition::find_declaration_preorder(name, ast).unwrap();
+        Location::new(ast.uri.clone(), ast.lsp_range(declaration.name))
+    }
+
+    #[test]
+    fn test_type_spec_json() {
+        let spec = syntax::typespec("map<string, array<pending_remote<Foo>?, 4>>?").unwrap();
+        assert_eq!(
+            json!({
+                "kind": "map",
+                "key": { "kind": "named", "name": "string", "nullable": false },
+                "value": {
+                    "kind": "array",
+                    "element": {
+                        "kind": "pendingRemote",
+                        "name": "Foo",
+                        "associated": false,
+                        "nullable": true,
+                    },
+                    "size": 4,
+                    "nullable": false,
+                },
+                "nullable": true,
+            }),
+            type_spec_json(&spec)
+        );
+        let spec = syntax::typespec("handle<message_pipe>").unwrap();
+        assert_eq!(
+            json!({ "kind": "handle", "handleType": "message_pipe", "nullable": false }),
+            type_spec_json(&spec)
+        );
+    }
+
+    #[test]
+    fn test_type_info() {
+        let ast = create_ast(
+            r#"
+module my.pkg;
+struct Outer {
+  enum Inner { kA = 2, kB, kC = kA, };
+  Inner? inner@1;
+  array<uint8, 0> invalid;
+};
+union Choice { int32 number; string text; };
+interface Foo {
+  const int32 kMax = 1;
+  Bar@3(int32 a) => ();
+  Baz(Outer.Inner inner);
+};
+"#,
+        );
+
+        let info = type_info(&ast, &definition(&ast, "Outer.Inner")).unwrap();
+        assert_eq!("enum", info.kind);
+        assert_eq!("my.pkg.Outer.Inner", info.qualified_name);
+        let values = info
+            .values
+            .iter()
+            .map(|value| (value.name.as_str(), value.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("kA", Some(2)), ("kB", Some(3)), ("kC", Some(2))],
+            values
+        );
+
+        let info = type_info(&ast, &definition(&ast, "Outer")).unwrap();
+        assert_eq!("struct", info.kind);
+        assert_eq!("my.pkg.Outer", info.qualified_name);
+        assert_eq!(2, info.fields.len());
+        assert_eq!("Inner?", info.fields[0].typ);
+        assert_eq!(Some(1), info.fields[0].ordinal);
+        assert_eq!(true, info.fields[0].type_spec["nullable"]);
+        assert_eq!(Value::Null, info.fields[1].type_spec);
+
+        let info = type_info(&ast, &definition(&ast, "Choice")).unwrap();
+        assert_eq!("union", info.kind);
+        let fields = info
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["number", "text"], fields);
+
+        let info = type_info(&ast, &definition(&ast, "my.pkg.Foo")).unwrap();
+        assert_eq!("interface", info.kind);
+        assert_eq!(2, info.methods.len());
+        assert_eq!(Some(3), info.methods[0].ordinal);
+        assert_eq!("a", info.methods[0].parameters[0].name);
+        assert_eq!(Some(Vec::new()), info.methods[0].response_parameters);
+        assert_eq!(None, info.methods[1].response_parameters);
+
+        assert!(type_info(&ast, &definition(&ast, "Foo.kMax")).is_none());
+    }
+}
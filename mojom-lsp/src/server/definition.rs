@@ -12,69 +12,338 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use lsp_types::{Location, Position, Range};
+use std::collections::HashMap;
 
+use lsp_types::Location;
+
+use crate::analysis::strip_module_qualifier;
 use crate::syntax::{self, preorder, Traversal};
 
 use super::mojomast::MojomAst;
 
-pub(crate) fn create_lsp_range(ast: &MojomAst, field: &syntax::Range) -> Range {
-    let pos = ast.line_col(field.start);
-    let start = Position::new(pos.line as u32, pos.col as u32);
-    let pos = ast.line_col(field.end);
-    let end = Position::new(pos.line as u32, pos.col as u32);
-    Range::new(start, end)
+/// A definition found in a document.
+pub(crate) struct Declaration<'a> {
+    /// The name of the definition.
+    pub(crate) name: &'a syntax::Range,
+    /// The whole declaration.
+    pub(crate) range: &'a syntax::Range,
 }
 
-fn match_field<'a, 'b, 'c>(
-    target: &'a str,
-    field: &'b syntax::Range,
-    ast: &'a MojomAst,
-    path: &'c mut Vec<&'a str>,
-) -> Option<Location> {
-    let name = ast.text(field);
-    path.push(name);
-    let ident = path.join(".");
-    path.pop();
-    if ident == target {
-        let range = create_lsp_range(ast, field);
-        return Some(Location::new(ast.uri.clone(), range));
-    }
-    None
+// A declaration in a symbol table.
+#[derive(Debug)]
+struct Symbol {
+    name: syntax::Range,
+    range: syntax::Range,
 }
 
-pub(crate) fn find_definition_preorder(ident: &str, ast: &MojomAst) -> Option<Location> {
-    let mut path = Vec::new();
-    for traversal in preorder(&ast.mojom) {
-        let loc = match traversal {
-            Traversal::EnterInterface(node) => {
-                let loc = match_field(ident, &node.name, ast, &mut path);
-                let name = ast.text(&node.name);
-                path.push(name);
-                loc
-            }
-            Traversal::LeaveInterface(_) => {
-                path.pop();
-                None
+/// Declarations of a document keyed by their qualified names like
+/// `MyInterface.MyMethod` or `MyEnum.kValue`.
+#[derive(Debug, Default)]
+pub(crate) struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub(crate) fn new(text: &str, mojom: &syntax::MojomFile) -> SymbolTable {
+        let mut symbols = HashMap::new();
+        let mut path: Vec<&str> = Vec::new();
+        let mut insert = |path: &[&str], name: &syntax::Range, range: &syntax::Range| {
+            let mut ident = path.join(".");
+            if !ident.is_empty() {
+                ident.push('.');
             }
-            Traversal::EnterStruct(node) => {
-                let loc = match_field(ident, &node.name, ast, &mut path);
-                let name = ast.text(&node.name);
-                path.push(name);
-                loc
+            ident.push_str(&text[name.start..name.end]);
+            // The first declaration wins when a name is declared twice.
+            symbols.entry(ident).or_insert_with(|| Symbol {
+                name: *name,
+                range: *range,
+            });
+        };
+        for traversal in preorder(mojom) {
+            match traversal {
+                Traversal::EnterInterface(node) => {
+                    insert(&path, &node.name, &node.range);
+                    path.push(&text[node.name.start..node.name.end]);
+                }
+                Traversal::EnterStruct(node) => {
+                    insert(&path, &node.name, &node.range);
+                    path.push(&text[node.name.start..node.name.end]);
+                }
+                // Enum values are referred as `MyEnum.kValue`.
+                Traversal::Enum(node) => {
+                    insert(&path, &node.name, &node.range);
+                    path.push(&text[node.name.start..node.name.end]);
+                }
+                // Constants of features are referred as `kFeature.name`.
+                Traversal::Feature(node) => {
+                    insert(&path, &node.name, &node.range);
+                    path.push(&text[node.name.start..node.name.end]);
+                }
+                Traversal::LeaveInterface(_)
+                | Traversal::LeaveStruct(_)
+                | Traversal::LeaveEnum(_)
+                | Traversal::LeaveFeature(_) => {
+                    path.pop();
+                }
+                Traversal::Union(node) => insert(&path, &node.name, &node.range),
+                Traversal::EnumValue(node) => insert(&path, &node.name, &node.range),
+                Traversal::Const(node) => insert(&path, &node.name, &node.range),
+                Traversal::Method(node) => insert(&path, &node.name, &node.range),
+                Traversal::StructField(node) => insert(&path, &node.name, &node.range),
+                _ => (),
             }
-            Traversal::LeaveStruct(_) => {
-                path.pop();
-                None
+        }
+        SymbolTable { symbols }
+    }
+
+    /// Returns the qualified name of the declaration of which name is at
+    /// `name`.
+    pub(crate) fn qualified_name(&self, name: &syntax::Range) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol)| symbol.name == *name)
+            .map(|(ident, _)| ident.as_str())
+    }
+
+    /// Returns the range of the whole declaration of which name is at `name`.
+    pub(crate) fn declaration_range(&self, name: &syntax::Range) -> Option<&syntax::Range> {
+        self.symbols
+            .values()
+            .find(|symbol| symbol.name == *name)
+            .map(|symbol| &symbol.range)
+    }
+
+    fn get(&self, ident: &str) -> Option<Declaration<'_>> {
+        self.symbols.get(ident).map(|symbol| Declaration {
+            name: &symbol.name,
+            range: &symbol.range,
+        })
+    }
+}
+
+/// Finds the declaration of `ident`. `ident` should be a fully qualified name
+/// like `MyInterface.MyMethod` or `MyEnum.kValue`, optionally prefixed with
+/// the module name of the document or a trailing part of it.
+pub(crate) fn find_declaration_preorder<'a>(
+    ident: &str,
+    ast: &'a MojomAst,
+) -> Option<Declaration<'a>> {
+    let symbols = ast.symbols();
+    symbols.get(ident).or_else(|| {
+        let local_ident = strip_module_qualifier(ast.module_name()?, ident)?;
+        symbols.get(local_ident)
+    })
+}
+
+/// Returns the names of the interfaces, structs and enums that enclose
+/// `offset`, outermost first.
+pub(crate) fn enclosing_scope(ast: &MojomAst, offset: usize) -> Vec<&str> {
+    let contains = |range: &syntax::Range| range.start <= offset && offset < range.end;
+    let mut scope = Vec::new();
+    let mut depth = 0;
+    for traversal in preorder(&ast.mojom) {
+        let (name, range) = match traversal {
+            Traversal::EnterInterface(node) => (&node.name, &node.range),
+            Traversal::EnterStruct(node) => (&node.name, &node.range),
+            Traversal::Enum(node) => (&node.name, &node.range),
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) | Traversal::LeaveEnum(_) => {
+                depth -= 1;
+                continue;
             }
-            Traversal::Union(node) => match_field(ident, &node.name, ast, &mut path),
-            Traversal::Enum(node) => match_field(ident, &node.name, ast, &mut path),
-            Traversal::Const(node) => match_field(ident, &node.name, ast, &mut path),
-            _ => None,
+            _ => continue,
         };
-        if loc.is_some() {
-            return loc;
+        // Containers that don't enclose `offset` are skipped with their
+        // members.
+        if scope.len() == depth && contains(range) {
+            scope.push(ast.text(name));
         }
+        depth += 1;
+    }
+    scope
+}
+
+/// Finds the declaration of `ident` that appears in `scope`. Inner scopes are
+/// searched first, then top-level declarations.
+pub(crate) fn find_declaration_in_scope<'a>(
+    ident: &str,
+    scope: &[&str],
+    ast: &'a MojomAst,
+) -> Option<Declaration<'a>> {
+    (1..=scope.len())
+        .rev()
+        .find_map(|depth| {
+            let qualified = format!("{}.{}", scope[..depth].join("."), ident);
+            ast.symbols().get(&qualified)
+        })
+        .or_else(|| find_declaration_preorder(ident, ast))
+}
+
+/// Returns the attribute section that contains `offset`.
+pub(crate) fn attribute_list_at(ast: &MojomAst, offset: usize) -> Option<&syntax::AttributeList> {
+    preorder(&ast.mojom)
+        .filter_map(|traversal| traversal.attributes())
+        .find(|list| list.range.start <= offset && offset < list.range.end)
+}
+
+/// Returns true when the value of `attribute` can name a declaration, e.g.
+/// `kFeature` in `[RuntimeFeature=kFeature]`. Values of `EnableIf` and
+/// `EnableIfNot` are build flags, which aren't declared in mojom files.
+pub(crate) fn refers_to_declaration(ast: &MojomAst, attribute: &syntax::Attribute) -> bool {
+    attribute.value.is_some() && !matches!(ast.text(&attribute.name), "EnableIf" | "EnableIfNot")
+}
+
+pub(crate) fn find_definition_in_scope(
+    ident: &str,
+    scope: &[&str],
+    ast: &MojomAst,
+) -> Option<Location> {
+    find_declaration_in_scope(ident, scope, ast).map(|declaration| {
+        let range = ast.lsp_range(declaration.name);
+        Location::new(ast.uri.clone(), range)
+    })
+}
+
+pub(crate) fn find_definition_preorder(ident: &str, ast: &MojomAst) -> Option<Location> {
+    find_declaration_preorder(ident, ast).map(|declaration| {
+        let range = ast.lsp_range(declaration.name);
+        Location::new(ast.uri.clone(), range)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Url;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let module = crate::analysis::find_module(text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, module)
+    }
+
+    fn declared_name<'a>(ident: &str, ast: &'a MojomAst) -> Option<&'a str> {
+        find_declaration_preorder(ident, ast).map(|declaration| ast.text(declaration.name))
+    }
+
+    #[test]
+    fn test_find_declaration() {
+        let ast = create_ast(
+            r#"
+            module my.pkg;
+            enum MyEnum { kFirstValue, kSecondValue };
+            interface MyInterface {
+                enum InnerEnum { kInner };
+                DoSomething(int32 value);
+            };
+            struct MyStruct {
+                int32 my_field;
+            };
+            "#,
+        );
+
+        assert_eq!(
+            Some("kSecondValue"),
+            declared_name("MyEnum.kSecondValue", &ast)
+        );
+        assert_eq!(
+            Some("kSecondValue"),
+            declared_name("my.pkg.MyEnum.kSecondValue", &ast)
+        );
+        assert_eq!(
+            Some("kInner"),
+            declared_name("MyInterface.InnerEnum.kInner", &ast)
+        );
+        assert_eq!(
+            Some("DoSomething"),
+            declared_name("MyInterface.DoSomething", &ast)
+        );
+        assert_eq!(Some("my_field"), declared_name("MyStruct.my_field", &ast));
+        assert_eq!(
+            Some("kSecondValue"),
+            declared_name("pkg.MyEnum.kSecondValue", &ast)
+        );
+
+        // Members need to be qualified with their container.
+        assert_eq!(None, declared_name("kSecondValue", &ast));
+        assert_eq!(None, declared_name("DoSomething", &ast));
+        assert_eq!(None, declared_name("other.MyEnum.kSecondValue", &ast));
+
+        let name = find_declaration_preorder("MyInterface.InnerEnum.kInner", &ast)
+            .unwrap()
+            .name;
+        assert_eq!(
+            Some("MyInterface.InnerEnum.kInner"),
+            ast.symbols().qualified_name(name)
+        );
+    }
+
+    #[test]
+    fn test_find_declaration_in_scope() {
+        let text = r#"
+            module my.pkg;
+            enum FooEnum { kTopLevel };
+            interface MyInterface {
+                enum FooEnum { kNested };
+                DoSomething(FooEnum value) => (Other other);
+            };
+            struct Other {
+                FooEnum value;
+                MyInterface.FooEnum nested;
+            };
+            "#;
+        let ast = create_ast(text);
+        let scope_at = |pattern: &str| enclosing_scope(&ast, text.find(pattern).unwrap());
+        let resolve = |pattern: &str, ident: &str| {
+            let scope = scope_at(pattern);
+            let declaration = find_declaration_in_scope(ident, &scope, &ast)?;
+            Some(&text[declaration.range.start..declaration.range.end])
+        };
+
+        assert_eq!(Vec::<&str>::new(), scope_at("module"));
+        assert_eq!(vec!["MyInterface"], scope_at("DoSomething"));
+        assert_eq!(vec!["MyInterface", "FooEnum"], scope_at("kNested"));
+        assert_eq!(vec!["Other"], scope_at("FooEnum value;"));
+
+        // A parameter refers to the nested enum of its interface by its short
+        // name.
+        let nested = Some("enum FooEnum { kNested };");
+        assert_eq!(nested, resolve("FooEnum value)", "FooEnum"));
+        let nested_value = Some("kNested");
+        assert_eq!(nested_value, resolve("FooEnum value)", "FooEnum.kNested"));
+        // Outer scopes and module qualified names.
+        assert!(resolve("Other other", "Other")
+            .unwrap()
+            .starts_with("struct Other"));
+        assert_eq!(nested, resolve("Other other", "my.pkg.MyInterface.FooEnum"));
+        // Other containers don't see the nested enum.
+        let top_level = Some("enum FooEnum { kTopLevel };");
+        assert_eq!(top_level, resolve("FooEnum value;", "FooEnum"));
+        assert_eq!(nested, resolve("FooEnum value;", "MyInterface.FooEnum"));
+        assert_eq!(None, resolve("FooEnum value;", "kNested"));
+    }
+
+    #[test]
+    fn test_find_feature_declaration() {
+        let text = r#"
+            module my.pkg;
+            feature kMyFeature {
+              const string name = "MyFeature";
+              const bool default_state = false;
+            };
+            [RuntimeFeature=kMyFeature]
+            interface MyInterface {};
+            "#;
+        let ast = create_ast(text);
+        assert_eq!(Some("kMyFeature"), declared_name("kMyFeature", &ast));
+        assert_eq!(Some("kMyFeature"), declared_name("my.pkg.kMyFeature", &ast));
+        assert_eq!(Some("name"), declared_name("kMyFeature.name", &ast));
+        assert_eq!(None, declared_name("name", &ast));
+
+        let offset = text.find("kMyFeature]").unwrap();
+        let list = attribute_list_at(&ast, offset).unwrap();
+        assert!(refers_to_declaration(&ast, &list.attributes[0]));
+        assert!(attribute_list_at(&ast, text.find("interface").unwrap()).is_none());
     }
-    None
 }
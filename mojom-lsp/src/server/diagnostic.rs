@@ -12,216 +12,1749 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs::File;
-use std::io::Read;
+use std::any::Any;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::{self, JoinHandle};
 
 use lsp_types::Url as Uri;
 
+use crate::analysis::{self, strip_module_qualifier};
 use crate::syntax;
 
-use super::imported_files::{check_imports, ImportedFiles};
+use super::cancellation::{CancellationToken, Cancelled};
+use super::command::AnalysisSummary;
+use super::conversion::to_lsp_range;
+use super::custom::TypeInfo;
+use super::imported_files::{
+    check_imports, find_gen_path, find_import_definition, ImportCache, ImportedFiles,
+};
+use super::inlay_hint::InlayHint;
 use super::messagesender::MessageSender;
+use super::metrics::Metrics;
 use super::mojomast::MojomAst;
 use super::protocol::NotificationMessage;
+use super::rename::RenameError;
+use super::settings::Settings;
+use super::textpos::{LineIndex, PositionEncoding};
+use super::type_hierarchy::TypeHierarchyItem;
+use super::workspace_graph::WorkspaceGraph;
 
-pub(crate) fn create_diagnostic(range: lsp_types::Range, message: String) -> lsp_types::Diagnostic {
+fn into_lsp_severity(severity: analysis::Severity) -> lsp_types::DiagnosticSeverity {
+    match severity {
+        analysis::Severity::Error => lsp_types::DiagnosticSeverity::ERROR,
+        analysis::Severity::Warning => lsp_types::DiagnosticSeverity::WARNING,
+        analysis::Severity::Information => lsp_types::DiagnosticSeverity::INFORMATION,
+        analysis::Severity::Hint => lsp_types::DiagnosticSeverity::HINT,
+    }
+}
+
+// Converts a diagnostic of `text` into an LSP diagnostic. `line_index` is the
+// line index of `text`.
+pub(crate) fn into_lsp_diagnostic(
+    uri: &Uri,
+    text: &str,
+    line_index: &LineIndex,
+    diagnostic: &analysis::Diagnostic,
+) -> lsp_types::Diagnostic {
+    let related_information = diagnostic.related.as_ref().map(|related| {
+        let location =
+            lsp_types::Location::new(uri.clone(), to_lsp_range(line_index, text, &related.range));
+        vec![lsp_types::DiagnosticRelatedInformation {
+            location,
+            message: related.message.clone(),
+        }]
+    });
     lsp_types::Diagnostic {
-        range: range,
-        severity: Some(lsp_types::DiagnosticSeverity::ERROR),
-        code: Some(lsp_types::NumberOrString::String("mojom".to_owned())),
+        range: to_lsp_range(line_index, text, &diagnostic.range),
+        severity: Some(into_lsp_severity(diagnostic.severity)),
+        code: Some(lsp_types::NumberOrString::String(
+            diagnostic.code.to_owned(),
+        )),
         code_description: None,
         data: None,
         source: Some("mojom-lsp".to_owned()),
-        message: message,
-        related_information: None,
-        tags: None,
+        message: diagnostic.message.clone(),
+        related_information,
+        tags: diagnostic
+            .unnecessary
+            .then(|| vec![lsp_types::DiagnosticTag::UNNECESSARY]),
+    }
+}
+
+// The parts of the client capabilities that change how diagnostics are
+// published and how definitions are returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ClientCaps {
+    // `relatedInformation` is shown.
+    pub(crate) related_information: bool,
+    // `DiagnosticTag::UNNECESSARY` is rendered.
+    pub(crate) unnecessary_tag: bool,
+    // Definitions can be returned as `LocationLink`s.
+    pub(crate) definition_link: bool,
+}
+
+impl ClientCaps {
+    pub(crate) fn new(capabilities: &lsp_types::ClientCapabilities) -> ClientCaps {
+        let text_document = capabilities.text_document.as_ref();
+        let publish =
+            text_document.and_then(|text_document| text_document.publish_diagnostics.as_ref());
+        let definition = text_document.and_then(|text_document| text_document.definition.as_ref());
+        ClientCaps {
+            related_information: publish
+                .and_then(|publish| publish.related_information)
+                .unwrap_or(false),
+            unnecessary_tag: publish
+                .and_then(|publish| publish.tag_support.as_ref())
+                .is_some_and(|tags| {
+                    tags.value_set
+                        .contains(&lsp_types::DiagnosticTag::UNNECESSARY)
+                }),
+            definition_link: definition
+                .and_then(|definition| definition.link_support)
+                .unwrap_or(false),
+        }
+    }
+}
+
+// Drops what the client doesn't support from `diagnostic`. Related
+// information is appended to the message instead, e.g. "Duplicate ordinal
+// (@1 is first used here at line 3)".
+fn fit_to_client(
+    mut diagnostic: lsp_types::Diagnostic,
+    caps: &ClientCaps,
+) -> lsp_types::Diagnostic {
+    if !caps.related_information {
+        for related in diagnostic.related_information.take().into_iter().flatten() {
+            diagnostic.message = format!(
+                "{} ({} at line {})",
+                diagnostic.message,
+                related.message,
+                related.location.range.start.line + 1
+            );
+        }
+    }
+    if !caps.unnecessary_tag {
+        diagnostic.tags = None;
+    }
+    diagnostic
+}
+
+// The locations found by a references request, or why it stopped.
+type LocationsResult = Result<Vec<lsp_types::Location>, Cancelled>;
+
+enum DiagnosticMessage {
+    SetRootPaths(Vec<PathBuf>),
+    UpdateSettings(Settings),
+    DidOpen(Uri),
+    DidClose(Uri),
+    CheckSyntax((Uri, String, Option<i32> /* version */)),
+    DidChangeWatchedFiles(Vec<Uri>),
+    DidSave((Uri, String)),
+    GotoDefinition(
+        (
+            Uri,
+            lsp_types::Position,
+            CancellationToken,
+            Sender<Result<Option<lsp_types::GotoDefinitionResponse>, Cancelled>>,
+        ),
+    ),
+    GotoDeclaration(
+        (
+            Uri,
+            lsp_types::Position,
+            CancellationToken,
+            Sender<Result<Option<lsp_types::Location>, Cancelled>>,
+        ),
+    ),
+    Hover((Uri, lsp_types::Position, Sender<Option<lsp_types::Hover>>)),
+    DocumentSymbol((Uri, Sender<Vec<lsp_types::DocumentSymbol>>)),
+    FoldingRange((Uri, Sender<Vec<lsp_types::FoldingRange>>)),
+    CodeLens((Uri, Sender<Vec<lsp_types::CodeLens>>)),
+    InlayHint((Uri, lsp_types::Range, Sender<Vec<InlayHint>>)),
+    DocumentLink((Uri, Sender<Vec<lsp_types::DocumentLink>>)),
+    ResolveDocumentLink((lsp_types::DocumentLink, Sender<lsp_types::DocumentLink>)),
+    References(
+        (
+            Uri,
+            lsp_types::Position,
+            bool, /* include_declaration */
+            CancellationToken,
+            Sender<LocationsResult>,
+        ),
+    ),
+    DocumentHighlight(
+        (
+            Uri,
+            lsp_types::Position,
+            Sender<Vec<lsp_types::DocumentHighlight>>,
+        ),
+    ),
+    Completion(
+        (
+            Uri,
+            lsp_types::Position,
+            Sender<Vec<lsp_types::CompletionItem>>,
+        ),
+    ),
+    SignatureHelp(
+        (
+            Uri,
+            lsp_types::Position,
+            Sender<Option<lsp_types::SignatureHelp>>,
+        ),
+    ),
+    PrepareRename(
+        (
+            Uri,
+            lsp_types::Position,
+            Sender<Result<Option<lsp_types::Range>, RenameError>>,
+        ),
+    ),
+    Rename(
+        (
+            Uri,
+            lsp_types::Position,
+            String, /* new_name */
+            Sender<Result<Option<lsp_types::WorkspaceEdit>, RenameError>>,
+        ),
+    ),
+    OrganizeImports((Uri, Sender<Option<lsp_types::CodeAction>>)),
+    QuickFixes(
+        (
+            Uri,
+            Vec<lsp_types::Diagnostic>,
+            Sender<Vec<lsp_types::CodeAction>>,
+        ),
+    ),
+    PrepareTypeHierarchy((Uri, lsp_types::Position, Sender<Option<TypeHierarchyItem>>)),
+    Subtypes(
+        (
+            TypeHierarchyItem,
+            CancellationToken,
+            Sender<Result<Vec<TypeHierarchyItem>, Cancelled>>,
+        ),
+    ),
+    TypeInfo((Uri, String /* type_name */, Sender<Option<TypeInfo>>)),
+    Restart,
+    ShowAnalysis((Uri, Sender<Option<AnalysisSummary>>)),
+    // Stops the thread.
+    Shutdown,
+}
+
+/// Why a message to the diagnostics thread has no result.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DiagError {
+    Cancelled,
+    // The diagnostics thread panicked while handling the request.
+    Failed,
+    // The diagnostics thread has exited.
+    Disconnected,
+}
+
+impl std::fmt::Display for DiagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagError::Cancelled => write!(f, "Request cancelled"),
+            DiagError::Failed => write!(f, "The diagnostics thread failed to handle the message"),
+            DiagError::Disconnected => write!(f, "The diagnostics thread is gone"),
+        }
+    }
+}
+
+impl std::error::Error for DiagError {}
+
+// The maximum number of other files that one request searches, e.g. files
+// that import a document. Files that aren't open are parsed on the
+// diagnostics thread, which delays diagnostics meanwhile.
+const MAX_SEARCHED_FILES: usize = 256;
+
+// A soft time limit of a request. Slow steps are skipped once it's exceeded
+// and the best result found so far is returned.
+#[derive(Clone, Copy, Default)]
+struct Deadline(Option<Instant>);
+
+impl Deadline {
+    // A zero `timeout` means no limit.
+    fn after(timeout: Duration) -> Deadline {
+        if timeout.is_zero() {
+            return Deadline(None);
+        }
+        Deadline(Some(Instant::now() + timeout))
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.0.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+pub(crate) struct DiagnosticsThread {
+    handle: JoinHandle<()>,
+    sender: Sender<DiagnosticMessage>,
+}
+
+impl DiagnosticsThread {
+    // Stops the thread and waits for it. Messages that are already queued are
+    // handled first.
+    pub(crate) fn shutdown(self) -> Result<(), DiagError> {
+        // The thread may already be gone. Joining tells why.
+        let _ = self.sender.send(DiagnosticMessage::Shutdown);
+        self.handle.join().map_err(|_| DiagError::Failed)
+    }
+
+    fn send(&self, msg: DiagnosticMessage) -> Result<(), DiagError> {
+        self.sender.send(msg).map_err(|_| DiagError::Disconnected)
+    }
+
+    pub(crate) fn set_root_paths(&self, root_paths: Vec<PathBuf>) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::SetRootPaths(root_paths))
+    }
+
+    pub(crate) fn update_settings(&self, settings: Settings) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::UpdateSettings(settings))
+    }
+
+    pub(crate) fn did_open(&self, uri: Uri) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::DidOpen(uri))
+    }
+
+    pub(crate) fn did_close(&self, uri: Uri) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::DidClose(uri))
+    }
+
+    pub(crate) fn check(
+        &self,
+        uri: Uri,
+        text: String,
+        version: Option<i32>,
+    ) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::CheckSyntax((uri, text, version)))
+    }
+
+    pub(crate) fn did_change_watched_files(&self, uris: Vec<Uri>) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::DidChangeWatchedFiles(uris))
+    }
+
+    pub(crate) fn did_save(&self, uri: Uri, text: String) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::DidSave((uri, text)))
+    }
+
+    // Sends `msg` and waits for its reply. The reply sender of `msg` is
+    // dropped without a reply when the thread panics.
+    fn request<R>(&self, msg: DiagnosticMessage, receiver: Receiver<R>) -> Result<R, DiagError> {
+        self.send(msg)?;
+        receiver.recv().map_err(|_| DiagError::Failed)
+    }
+
+    pub(crate) fn goto_definition(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        token: CancellationToken,
+    ) -> Result<Option<lsp_types::GotoDefinitionResponse>, DiagError> {
+        let (loc_sender, loc_receiver) = channel();
+        let msg = DiagnosticMessage::GotoDefinition((uri, pos, token, loc_sender));
+        self.request(msg, loc_receiver)?
+            .map_err(|Cancelled| DiagError::Cancelled)
+    }
+
+    pub(crate) fn goto_declaration(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        token: CancellationToken,
+    ) -> Result<Option<lsp_types::Location>, DiagError> {
+        let (loc_sender, loc_receiver) = channel();
+        let msg = DiagnosticMessage::GotoDeclaration((uri, pos, token, loc_sender));
+        self.request(msg, loc_receiver)?
+            .map_err(|Cancelled| DiagError::Cancelled)
+    }
+
+    pub(crate) fn hover(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Option<lsp_types::Hover>, DiagError> {
+        let (hover_sender, hover_receiver) = channel::<Option<lsp_types::Hover>>();
+        let msg = DiagnosticMessage::Hover((uri, pos, hover_sender));
+        self.request(msg, hover_receiver)
+    }
+
+    pub(crate) fn document_symbol(
+        &self,
+        uri: Uri,
+    ) -> Result<Vec<lsp_types::DocumentSymbol>, DiagError> {
+        let (symbol_sender, symbol_receiver) = channel::<Vec<lsp_types::DocumentSymbol>>();
+        let msg = DiagnosticMessage::DocumentSymbol((uri, symbol_sender));
+        self.request(msg, symbol_receiver)
+    }
+
+    pub(crate) fn folding_range(
+        &self,
+        uri: Uri,
+    ) -> Result<Vec<lsp_types::FoldingRange>, DiagError> {
+        let (range_sender, range_receiver) = channel::<Vec<lsp_types::FoldingRange>>();
+        let msg = DiagnosticMessage::FoldingRange((uri, range_sender));
+        self.request(msg, range_receiver)
+    }
+
+    pub(crate) fn code_lens(&self, uri: Uri) -> Result<Vec<lsp_types::CodeLens>, DiagError> {
+        let (lens_sender, lens_receiver) = channel();
+        let msg = DiagnosticMessage::CodeLens((uri, lens_sender));
+        self.request(msg, lens_receiver)
+    }
+
+    pub(crate) fn inlay_hint(
+        &self,
+        uri: Uri,
+        range: lsp_types::Range,
+    ) -> Result<Vec<InlayHint>, DiagError> {
+        let (hint_sender, hint_receiver) = channel();
+        let msg = DiagnosticMessage::InlayHint((uri, range, hint_sender));
+        self.request(msg, hint_receiver)
+    }
+
+    pub(crate) fn document_link(
+        &self,
+        uri: Uri,
+    ) -> Result<Vec<lsp_types::DocumentLink>, DiagError> {
+        let (link_sender, link_receiver) = channel();
+        let msg = DiagnosticMessage::DocumentLink((uri, link_sender));
+        self.request(msg, link_receiver)
+    }
+
+    pub(crate) fn resolve_document_link(
+        &self,
+        link: lsp_types::DocumentLink,
+    ) -> Result<lsp_types::DocumentLink, DiagError> {
+        let (link_sender, link_receiver) = channel();
+        let msg = DiagnosticMessage::ResolveDocumentLink((link, link_sender));
+        self.request(msg, link_receiver)
+    }
+
+    pub(crate) fn references(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        include_declaration: bool,
+        token: CancellationToken,
+    ) -> Result<Vec<lsp_types::Location>, DiagError> {
+        let (loc_sender, loc_receiver) = channel();
+        let msg = DiagnosticMessage::References((uri, pos, include_declaration, token, loc_sender));
+        self.request(msg, loc_receiver)?
+            .map_err(|Cancelled| DiagError::Cancelled)
+    }
+
+    pub(crate) fn document_highlight(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Vec<lsp_types::DocumentHighlight>, DiagError> {
+        let (highlight_sender, highlight_receiver) = channel();
+        let msg = DiagnosticMessage::DocumentHighlight((uri, pos, highlight_sender));
+        self.request(msg, highlight_receiver)
+    }
+
+    pub(crate) fn completion(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Vec<lsp_types::CompletionItem>, DiagError> {
+        let (item_sender, item_receiver) = channel::<Vec<lsp_types::CompletionItem>>();
+        let msg = DiagnosticMessage::Completion((uri, pos, item_sender));
+        self.request(msg, item_receiver)
+    }
+
+    pub(crate) fn signature_help(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Option<lsp_types::SignatureHelp>, DiagError> {
+        let (help_sender, help_receiver) = channel();
+        let msg = DiagnosticMessage::SignatureHelp((uri, pos, help_sender));
+        self.request(msg, help_receiver)
+    }
+
+    pub(crate) fn prepare_rename(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Result<Option<lsp_types::Range>, RenameError>, DiagError> {
+        let (range_sender, range_receiver) = channel();
+        let msg = DiagnosticMessage::PrepareRename((uri, pos, range_sender));
+        self.request(msg, range_receiver)
+    }
+
+    pub(crate) fn rename(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        new_name: String,
+    ) -> Result<Result<Option<lsp_types::WorkspaceEdit>, RenameError>, DiagError> {
+        let (edit_sender, edit_receiver) = channel();
+        let msg = DiagnosticMessage::Rename((uri, pos, new_name, edit_sender));
+        self.request(msg, edit_receiver)
+    }
+
+    pub(crate) fn organize_imports(
+        &self,
+        uri: Uri,
+    ) -> Result<Option<lsp_types::CodeAction>, DiagError> {
+        let (action_sender, action_receiver) = channel();
+        let msg = DiagnosticMessage::OrganizeImports((uri, action_sender));
+        self.request(msg, action_receiver)
+    }
+
+    pub(crate) fn quick_fixes(
+        &self,
+        uri: Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+    ) -> Result<Vec<lsp_types::CodeAction>, DiagError> {
+        let (action_sender, action_receiver) = channel();
+        let msg = DiagnosticMessage::QuickFixes((uri, diagnostics, action_sender));
+        self.request(msg, action_receiver)
+    }
+
+    pub(crate) fn prepare_type_hierarchy(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Option<TypeHierarchyItem>, DiagError> {
+        let (item_sender, item_receiver) = channel();
+        let msg = DiagnosticMessage::PrepareTypeHierarchy((uri, pos, item_sender));
+        self.request(msg, item_receiver)
+    }
+
+    pub(crate) fn subtypes(
+        &self,
+        item: TypeHierarchyItem,
+        token: CancellationToken,
+    ) -> Result<Vec<TypeHierarchyItem>, DiagError> {
+        let (items_sender, items_receiver) = channel();
+        let msg = DiagnosticMessage::Subtypes((item, token, items_sender));
+        self.request(msg, items_receiver)?
+            .map_err(|Cancelled| DiagError::Cancelled)
+    }
+
+    pub(crate) fn type_info(
+        &self,
+        uri: Uri,
+        type_name: String,
+    ) -> Result<Option<TypeInfo>, DiagError> {
+        let (info_sender, info_receiver) = channel();
+        let msg = DiagnosticMessage::TypeInfo((uri, type_name, info_sender));
+        self.request(msg, info_receiver)
+    }
+
+    // Drops cached files and checks open documents again.
+    pub(crate) fn restart(&self) -> Result<(), DiagError> {
+        self.send(DiagnosticMessage::Restart)
+    }
+
+    pub(crate) fn show_analysis(&self, uri: Uri) -> Result<Option<AnalysisSummary>, DiagError> {
+        let (summary_sender, summary_receiver) = channel();
+        let msg = DiagnosticMessage::ShowAnalysis((uri, summary_sender));
+        self.request(msg, summary_receiver)
+    }
+}
+
+pub(crate) fn start_diagnostics_thread(
+    root_paths: Vec<PathBuf>,
+    settings: Settings,
+    encoding: PositionEncoding,
+    client_caps: ClientCaps,
+    msg_sender: MessageSender,
+    metrics: Metrics,
+    workspace_graph: WorkspaceGraph,
+) -> DiagnosticsThread {
+    let mut diag = Diagnostic::new(
+        root_paths,
+        settings,
+        encoding,
+        client_caps,
+        msg_sender,
+        metrics,
+    );
+    diag.workspace_graph = workspace_graph;
+    let (sender, receiver) = channel::<DiagnosticMessage>();
+    let handle = thread::spawn(move || run_diagnostics_loop(&mut diag, receiver));
+
+    DiagnosticsThread {
+        handle: handle,
+        sender: sender,
+    }
+}
+
+// Messages that are received but not handled yet. A check of a document is
+// dropped when a newer text of the document arrives, so only the newest text
+// is parsed when the thread is behind.
+#[derive(Default)]
+struct Scheduler {
+    messages: VecDeque<DiagnosticMessage>,
+}
+
+impl Scheduler {
+    fn push(&mut self, msg: DiagnosticMessage) {
+        if let DiagnosticMessage::CheckSyntax((uri, _, _)) = &msg {
+            self.messages.retain(|queued| match queued {
+                DiagnosticMessage::CheckSyntax((queued_uri, _, _)) => queued_uri != uri,
+                _ => true,
+            });
+        }
+        self.messages.push_back(msg);
+    }
+
+    // Takes messages that are already sent without waiting.
+    fn receive_available(&mut self, receiver: &Receiver<DiagnosticMessage>) {
+        for msg in receiver.try_iter() {
+            self.push(msg);
+        }
+    }
+
+    fn pop(&mut self) -> Option<DiagnosticMessage> {
+        self.messages.pop_front()
+    }
+}
+
+// Processes messages until `Shutdown` or until all senders are dropped.
+fn run_diagnostics_loop(diag: &mut Diagnostic, receiver: Receiver<DiagnosticMessage>) {
+    let mut scheduler = Scheduler::default();
+    loop {
+        // Debounce rapid changes. Messages that are already sent are queued
+        // before the next one is handled.
+        scheduler.receive_available(&receiver);
+        let msg = match scheduler.pop() {
+            Some(msg) => msg,
+            None => match receiver.recv() {
+                Ok(msg) => {
+                    scheduler.push(msg);
+                    continue;
+                }
+                Err(_) => break,
+            },
+        };
+        if let DiagnosticMessage::Shutdown = msg {
+            break;
+        }
+
+        // A panic must not stop the thread. The reply sender of `msg` is
+        // dropped so the requester gets an empty result.
+        let checked_uri = match &msg {
+            DiagnosticMessage::CheckSyntax((uri, _, _)) => Some(uri.clone()),
+            _ => None,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            handle_message(diag, msg);
+        }));
+        if let Err(payload) = result {
+            log::error!("Diagnostics thread panicked: {}", panic_message(&*payload));
+            if let Some(uri) = checked_uri {
+                diag.publish(uri, Vec::new(), PublishSource::Direct, None);
+            }
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+fn handle_message(diag: &mut Diagnostic, msg: DiagnosticMessage) {
+    match msg {
+        DiagnosticMessage::SetRootPaths(root_paths) => {
+            diag.set_root_paths(root_paths);
+        }
+        DiagnosticMessage::UpdateSettings(settings) => {
+            diag.update_settings(settings);
+        }
+        DiagnosticMessage::DidOpen(uri) => {
+            diag.did_open(uri);
+        }
+        DiagnosticMessage::DidClose(uri) => {
+            diag.did_close(uri);
+        }
+        DiagnosticMessage::CheckSyntax((uri, text, version)) => {
+            diag.check(uri, text, version);
+        }
+        DiagnosticMessage::DidChangeWatchedFiles(uris) => {
+            diag.did_change_watched_files(&uris);
+        }
+        DiagnosticMessage::DidSave((uri, text)) => {
+            diag.did_save(uri, text);
+        }
+        DiagnosticMessage::GotoDefinition((uri, pos, token, loc_sender)) => {
+            let deadline = diag.definition_deadline();
+            let res = diag
+                .find_definition(uri.clone(), pos, &token, deadline)
+                .map(|loc| loc.map(|loc| diag.definition_response(&uri, pos, loc)));
+            loc_sender.send(res).unwrap();
+        }
+        DiagnosticMessage::GotoDeclaration((uri, pos, token, loc_sender)) => {
+            let deadline = diag.definition_deadline();
+            let loc = diag.find_declaration(uri, pos, &token, deadline);
+            loc_sender.send(loc).unwrap();
+        }
+        DiagnosticMessage::Hover((uri, pos, hover_sender)) => {
+            let hover = diag.hover(uri, pos);
+            hover_sender.send(hover).unwrap();
+        }
+        DiagnosticMessage::DocumentSymbol((uri, symbol_sender)) => {
+            let symbols = diag.document_symbol(uri);
+            symbol_sender.send(symbols).unwrap();
+        }
+        DiagnosticMessage::FoldingRange((uri, range_sender)) => {
+            let ranges = diag.folding_range(uri);
+            range_sender.send(ranges).unwrap();
+        }
+        DiagnosticMessage::CodeLens((uri, lens_sender)) => {
+            let lenses = diag.code_lens(uri);
+            lens_sender.send(lenses).unwrap();
+        }
+        DiagnosticMessage::InlayHint((uri, range, hint_sender)) => {
+            let hints = diag.inlay_hint(uri, range);
+            hint_sender.send(hints).unwrap();
+        }
+        DiagnosticMessage::DocumentLink((uri, link_sender)) => {
+            let links = diag.document_link(uri);
+            link_sender.send(links).unwrap();
+        }
+        DiagnosticMessage::ResolveDocumentLink((link, link_sender)) => {
+            let link = diag.resolve_document_link(link);
+            link_sender.send(link).unwrap();
+        }
+        DiagnosticMessage::References((uri, pos, include_declaration, token, loc_sender)) => {
+            let locations = diag.references(uri, pos, include_declaration, &token);
+            loc_sender.send(locations).unwrap();
+        }
+        DiagnosticMessage::DocumentHighlight((uri, pos, highlight_sender)) => {
+            let highlights = diag.document_highlight(uri, pos);
+            highlight_sender.send(highlights).unwrap();
+        }
+        DiagnosticMessage::Completion((uri, pos, item_sender)) => {
+            let items = diag.completion(uri, pos);
+            item_sender.send(items).unwrap();
+        }
+        DiagnosticMessage::SignatureHelp((uri, pos, help_sender)) => {
+            let help = diag.signature_help(uri, pos);
+            help_sender.send(help).unwrap();
+        }
+        DiagnosticMessage::PrepareRename((uri, pos, range_sender)) => {
+            let range = diag.prepare_rename(uri, pos);
+            range_sender.send(range).unwrap();
+        }
+        DiagnosticMessage::Rename((uri, pos, new_name, edit_sender)) => {
+            let edit = diag.rename(uri, pos, &new_name);
+            edit_sender.send(edit).unwrap();
+        }
+        DiagnosticMessage::OrganizeImports((uri, action_sender)) => {
+            let action = diag.organize_imports(uri);
+            action_sender.send(action).unwrap();
+        }
+        DiagnosticMessage::QuickFixes((uri, diagnostics, action_sender)) => {
+            let actions = diag.quick_fixes(uri, &diagnostics);
+            action_sender.send(actions).unwrap();
+        }
+        DiagnosticMessage::PrepareTypeHierarchy((uri, pos, item_sender)) => {
+            let item = diag.prepare_type_hierarchy(uri, pos);
+            item_sender.send(item).unwrap();
+        }
+        DiagnosticMessage::Subtypes((item, token, items_sender)) => {
+            let items = diag.subtypes(&item, &token);
+            items_sender.send(items).unwrap();
+        }
+        DiagnosticMessage::TypeInfo((uri, type_name, info_sender)) => {
+            let info = diag.type_info(uri, &type_name);
+            info_sender.send(info).unwrap();
+        }
+        DiagnosticMessage::Restart => {
+            diag.restart();
+        }
+        DiagnosticMessage::ShowAnalysis((uri, summary_sender)) => {
+            let summary = diag.show_analysis(uri);
+            summary_sender.send(summary).unwrap();
+        }
+        // `run_diagnostics_loop()` stops before handling it.
+        DiagnosticMessage::Shutdown => (),
+    }
+}
+
+// Which check published the current diagnostics of a document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PublishSource {
+    // The document was checked directly.
+    Direct,
+    // The document was parsed as an imported file of another document.
+    Import,
+}
+
+// An open document.
+#[derive(Default)]
+struct Document {
+    // The latest text of the document.
+    text: String,
+    // Parsed syntax tree with the original text. None when the document has
+    // a syntax error.
+    ast: Option<MojomAst>,
+    // Parsed mojom files that are imported from the document.
+    imported_files: Option<ImportedFiles>,
+    // The source of the last diagnostics published for the document.
+    last_source: Option<PublishSource>,
+    // The last successfully parsed syntax tree and its imported files. They
+    // are kept while the document has a syntax error so that completion
+    // keeps working during typing.
+    last_valid_ast: Option<MojomAst>,
+    last_valid_imported_files: Option<ImportedFiles>,
+}
+
+// A document that isn't open in the editor, read from the disk.
+struct ClosedDocument {
+    uri: Uri,
+    // The modification time of the file when it was read.
+    modified: SystemTime,
+    document: Document,
+}
+
+struct Diagnostic {
+    // Workspace root paths. Imports are resolved against them in order.
+    root_paths: Vec<PathBuf>,
+    // Server settings.
+    settings: Settings,
+    // The position encoding negotiated with the client.
+    encoding: PositionEncoding,
+    // What the client supports in published diagnostics.
+    client_caps: ClientCaps,
+    // A message sender. It is used in the diagnostics thread to send
+    // notifications.
+    msg_sender: MessageSender,
+    // Documents that are open in the editor.
+    documents: HashMap<Uri, Document>,
+    // Recently used documents that aren't open, least recently used first.
+    closed_documents: VecDeque<ClosedDocument>,
+    // Parsed imported files that are reused while they are unchanged.
+    import_cache: ImportCache,
+    // Parse durations and import cache lookups are recorded in it.
+    metrics: Metrics,
+    // `gen` directories of Chromium output directories. They are discovered
+    // once and kept until root paths or settings change so that the same
+    // directory is used consistently.
+    gen_paths: Option<Vec<PathBuf>>,
+    // Files of the workspace that import each file or declare each module.
+    workspace_graph: WorkspaceGraph,
+}
+
+impl Diagnostic {
+    fn new(
+        root_paths: Vec<PathBuf>,
+        settings: Settings,
+        encoding: PositionEncoding,
+        client_caps: ClientCaps,
+        msg_sender: MessageSender,
+        metrics: Metrics,
+    ) -> Self {
+        let mut import_cache = ImportCache::new();
+        import_cache.set_metrics(metrics.clone());
+        Diagnostic {
+            root_paths,
+            settings,
+            encoding,
+            client_caps,
+            msg_sender: msg_sender,
+            documents: HashMap::new(),
+            closed_documents: VecDeque::new(),
+            import_cache,
+            metrics,
+            gen_paths: None,
+            workspace_graph: WorkspaceGraph::default(),
+        }
+    }
+
+    // Returns directories to resolve imports against.
+    fn import_paths(&mut self) -> Vec<PathBuf> {
+        let root_paths = &self.root_paths;
+        let settings = &self.settings;
+        let gen_paths = self.gen_paths.get_or_insert_with(|| {
+            settings.gen_paths(root_paths).unwrap_or_else(|| {
+                root_paths
+                    .iter()
+                    .filter_map(|path| find_gen_path(path))
+                    .collect()
+            })
+        });
+        settings.import_paths(root_paths, gen_paths)
+    }
+
+    // Checks open documents again because imports may resolve differently.
+    fn set_root_paths(&mut self, root_paths: Vec<PathBuf>) {
+        if self.root_paths != root_paths {
+            self.root_paths = root_paths;
+            self.gen_paths = None;
+            self.closed_documents.clear();
+            self.check_all();
+        }
+    }
+
+    fn update_settings(&mut self, settings: Settings) {
+        let settings = settings.keep_initialization_only(&self.settings);
+        if self.settings != settings {
+            self.settings = settings;
+            self.gen_paths = None;
+            self.closed_documents.clear();
+            self.check_all();
+        }
+    }
+
+    // Checks all open documents again.
+    fn check_all(&mut self) {
+        let texts = self
+            .documents
+            .iter()
+            .map(|(uri, document)| (uri.clone(), document.text.clone()))
+            .collect::<Vec<_>>();
+        for (uri, text) in texts {
+            self.check(uri, text, None);
+        }
+    }
+
+    fn did_open(&mut self, uri: Uri) {
+        self.closed_documents.retain(|closed| closed.uri != uri);
+        self.documents.entry(uri).or_default();
+    }
+
+    fn did_close(&mut self, uri: Uri) {
+        // Nothing will update diagnostics of the document after closing.
+        if self.documents.remove(&uri).is_some() {
+            self.publish(uri, Vec::new(), PublishSource::Direct, None);
+        }
+    }
+
+    // Forgets parsed files at `uris` and checks open documents that import
+    // them again. Open documents themselves are kept because the client
+    // sends their texts.
+    fn did_change_watched_files(&mut self, uris: &[Uri]) {
+        let paths = uris
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .collect::<Vec<_>>();
+        for path in &paths {
+            self.import_cache.invalidate(path);
+        }
+        let depends_on_changes = |document: &Document| {
+            document
+                .imported_files
+                .as_ref()
+                .is_some_and(|imported_files| {
+                    paths.iter().any(|path| imported_files.depends_on(path))
+                })
+        };
+        self.closed_documents
+            .retain(|closed| !uris.contains(&closed.uri) && !depends_on_changes(&closed.document));
+
+        let texts = self
+            .documents
+            .iter()
+            .filter(|(_, document)| depends_on_changes(document))
+            .map(|(uri, document)| (uri.clone(), document.text.clone()))
+            .collect::<Vec<_>>();
+        for (uri, text) in texts {
+            self.check(uri, text, None);
+        }
+    }
+
+    // Checks the saved document again, then open documents that import it.
+    // Parsed copies of the file are dropped since other documents read it
+    // from the disk.
+    fn did_save(&mut self, uri: Uri, text: String) {
+        if self.documents.contains_key(&uri) {
+            self.check(uri.clone(), text, None);
+        }
+        self.did_change_watched_files(&[uri]);
+    }
+
+    fn check(&mut self, uri: Uri, text: String, version: Option<i32>) {
+        let (mut document, diagnostics) = self.parse_document(uri.clone(), text);
+        if let Some(old) = self.documents.remove(&uri) {
+            document.last_source = old.last_source;
+            if document.ast.is_none() {
+                document.last_valid_ast = old.ast.or(old.last_valid_ast);
+                document.last_valid_imported_files =
+                    old.imported_files.or(old.last_valid_imported_files);
+            }
+        }
+        let imported_files = document.imported_files.take();
+        self.documents.insert(uri.clone(), document);
+        self.publish(uri.clone(), diagnostics, PublishSource::Direct, version);
+
+        if let Some(imported_files) = imported_files {
+            self.publish_import_diagnostics(&imported_files);
+            if let Some(document) = self.documents.get_mut(&uri) {
+                document.imported_files = Some(imported_files);
+            }
+        }
+    }
+
+    fn definition_deadline(&self) -> Deadline {
+        Deadline::after(Duration::from_millis(self.settings.definition_timeout_ms))
+    }
+
+    // Same as `find_declaration` except for `[Native]` types. Their C++
+    // definitions are searched when `native_definitions` is enabled and the
+    // mojom declaration is returned when nothing is found or `deadline` is
+    // exceeded.
+    fn find_definition(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        token: &CancellationToken,
+        deadline: Deadline,
+    ) -> Result<Option<lsp_types::Location>, Cancelled> {
+        let declaration = match self.find_declaration(uri, pos, token, deadline)? {
+            Some(declaration) => declaration,
+            None => return Ok(None),
+        };
+        if !self.settings.native_definitions || deadline.is_exceeded() {
+            return Ok(Some(declaration));
+        }
+        token.check()?;
+        let root_path = self.root_paths.first().cloned().unwrap_or_default();
+        let encoding = self.encoding;
+        let definition = self
+            .with_document(&declaration.uri, |document| {
+                let ast = document.ast.as_ref()?;
+                let name = super::native_definition::native_type_name(ast, &declaration)?;
+                let path = declaration.uri.to_file_path().ok()?;
+                super::native_definition::find_native_definition(&root_path, &path, name, encoding)
+            })
+            .flatten();
+        Ok(Some(definition.unwrap_or(declaration)))
+    }
+
+    // Returns `location` as a link from the reference at `pos` of `uri` when
+    // the client supports links. The target range of a link is the whole
+    // declaration when `location` is the name of a mojom declaration.
+    fn definition_response(
+        &mut self,
+        uri: &Uri,
+        pos: lsp_types::Position,
+        location: lsp_types::Location,
+    ) -> lsp_types::GotoDefinitionResponse {
+        if !self.client_caps.definition_link {
+            return location.into();
+        }
+        let origin_selection_range = self
+            .with_document(uri, |document| {
+                let ast = document.ast.as_ref()?;
+                let range = origin_range(ast, ast.offset(&pos))?;
+                Some(ast.lsp_range(&range))
+            })
+            .flatten();
+        // C++ headers of native types aren't parsed.
+        let target_range = if location.uri.path().ends_with(".mojom") {
+            self.with_document(&location.uri, |document| {
+                let ast = document.ast.as_ref()?;
+                let name = ast.offset_range(&location.range);
+                let range = ast.symbols().declaration_range(&name)?;
+                Some(ast.lsp_range(range))
+            })
+            .flatten()
+        } else {
+            None
+        };
+        let link = lsp_types::LocationLink {
+            origin_selection_range,
+            target_range: target_range.unwrap_or(location.range),
+            target_uri: location.uri,
+            target_selection_range: location.range,
+        };
+        vec![link].into()
+    }
+
+    // Other files of the module aren't searched once `deadline` is exceeded.
+    fn find_declaration(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        token: &CancellationToken,
+        deadline: Deadline,
+    ) -> Result<Option<lsp_types::Location>, Cancelled> {
+        token.check()?;
+        let root_paths = self.import_paths();
+        let loader = self.import_cache.loader();
+        // The module and the name to look up in other files of the module
+        // when neither the document nor its imports declare the name.
+        let mut unresolved = None;
+        let location = self
+            .with_document(&uri, |document| {
+                // Documents that aren't open are parsed with their imports, which
+                // can take a while.
+                token.check()?;
+                let ast = match document.ast.as_ref() {
+                    Some(ast) => ast,
+                    None => return Ok(None),
+                };
+                let offset = ast.offset(&pos);
+                if let Some(location) =
+                    find_import_definition(&root_paths, ast, offset, loader.as_ref())
+                {
+                    return Ok(Some(location));
+                }
+                let ident = match get_reference(ast, offset) {
+                    Some(ident) => ident,
+                    None => return Ok(None),
+                };
+                let scope = super::definition::enclosing_scope(ast, offset);
+                if let Some(location) = find_definition_in_doc(ast, &scope, ident) {
+                    return Ok(Some(location));
+                }
+                token.check()?;
+                let location = find_definition_in_imported_files(&document.imported_files, ident);
+                if location.is_none() {
+                    unresolved = ast
+                        .module_name()
+                        .map(|module_name| (module_name.to_owned(), ident.to_owned()));
+                }
+                Ok(location)
+            })
+            .unwrap_or(Ok(None))?;
+        match (location, unresolved) {
+            (Some(location), _) => Ok(Some(location)),
+            (None, Some((module_name, ident))) => {
+                self.find_definition_in_module(&uri, &module_name, &ident, token, deadline)
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    // Finds the definition of `ident` in the files of the workspace that
+    // declare `module_name` other than `uri`. Chromium splits modules into
+    // files that don't import each other. Files may need to be parsed, so
+    // the search stops when `deadline` is exceeded or after
+    // MAX_SEARCHED_FILES files.
+    fn find_definition_in_module(
+        &mut self,
+        uri: &Uri,
+        module_name: &str,
+        ident: &str,
+        token: &CancellationToken,
+        deadline: Deadline,
+    ) -> Result<Option<lsp_types::Location>, Cancelled> {
+        let path = uri.to_file_path().ok();
+        let mut files = self.workspace_graph.module_files(module_name);
+        files.retain(|file| path.as_ref() != Some(file));
+        if files.len() > MAX_SEARCHED_FILES {
+            log::warn!(
+                "Module {} has {} files. Only {} are searched",
+                module_name,
+                files.len(),
+                MAX_SEARCHED_FILES
+            );
+            files.truncate(MAX_SEARCHED_FILES);
+        }
+        for file in files {
+            token.check()?;
+            if deadline.is_exceeded() {
+                log::warn!("Gave up finding `{}` in module {}", ident, module_name);
+                return Ok(None);
+            }
+            let file_uri = match Uri::from_file_path(&file) {
+                Ok(file_uri) => file_uri,
+                Err(_) => continue,
+            };
+            let location = self
+                .scan_document(&file_uri, |document| {
+                    let ast = document.ast.as_ref()?;
+                    // The index may be outdated.
+                    if ast.module_name() != Some(module_name) {
+                        return None;
+                    }
+                    super::definition::find_definition_preorder(ident, ast)
+                })
+                .flatten();
+            if location.is_some() {
+                return Ok(location);
+            }
+        }
+        Ok(None)
+    }
+
+    fn hover(&mut self, uri: Uri, pos: lsp_types::Position) -> Option<lsp_types::Hover> {
+        self.with_document(&uri, |document| {
+            let ast = document.ast.as_ref()?;
+            let offset = ast.offset(&pos);
+            super::hover::hover(ast, offset).or_else(|| {
+                let ident = get_reference(ast, offset)?;
+                super::hover::declaration_hover(ast, ident).or_else(|| {
+                    let value = document.imported_files.as_ref()?.find_hover(ident)?;
+                    Some(lsp_types::Hover {
+                        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+                            kind: lsp_types::MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: None,
+                    })
+                })
+            })
+        })
+        .flatten()
+    }
+
+    fn document_symbol(&mut self, uri: Uri) -> Vec<lsp_types::DocumentSymbol> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::document_symbol::document_symbols(ast),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    fn folding_range(&mut self, uri: Uri) -> Vec<lsp_types::FoldingRange> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::folding_range::folding_ranges(ast),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    fn code_lens(&mut self, uri: Uri) -> Vec<lsp_types::CodeLens> {
+        let root_paths = self.root_paths.clone();
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::code_lens::code_lenses(ast, &root_paths),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    // Hints aren't shown while the document has a syntax error because the
+    // ordinals may be outdated.
+    fn inlay_hint(&mut self, uri: Uri, range: lsp_types::Range) -> Vec<InlayHint> {
+        if !self.settings.inlay_hints.ordinals {
+            return Vec::new();
+        }
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::inlay_hint::ordinal_hints(ast, &range),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    fn document_link(&mut self, uri: Uri) -> Vec<lsp_types::DocumentLink> {
+        let import_paths = self.import_paths();
+        let loader = self.import_cache.loader();
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::document_link::document_links(&import_paths, ast, loader.as_ref()),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    fn resolve_document_link(&mut self, link: lsp_types::DocumentLink) -> lsp_types::DocumentLink {
+        let import_paths = self.import_paths();
+        let loader = self.import_cache.loader();
+        super::document_link::resolve_document_link(&import_paths, link, loader.as_ref())
+    }
+
+    fn references(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        include_declaration: bool,
+        token: &CancellationToken,
+    ) -> Result<Vec<lsp_types::Location>, Cancelled> {
+        token.check()?;
+        let mut unresolved = None;
+        let definition = self
+            .with_document(&uri, |document| {
+                let ast = document.ast.as_ref()?;
+                let ident = get_identifier(&ast.text, ast.offset(&pos))?;
+                let definition = super::references::resolve(ast, &document.imported_files, ident);
+                if definition.is_none() {
+                    unresolved = ast
+                        .module_name()
+                        .map(|module_name| (module_name.to_owned(), ident.to_owned()));
+                }
+                definition
+            })
+            .flatten();
+        let definition = match (definition, unresolved) {
+            (Some(definition), _) => definition,
+            (None, Some((module_name, ident))) => {
+                match self.find_definition_in_module(
+                    &uri,
+                    &module_name,
+                    &ident,
+                    token,
+                    Deadline::default(),
+                )? {
+                    Some(definition) => definition,
+                    None => return Ok(Vec::new()),
+                }
+            }
+            (None, None) => return Ok(Vec::new()),
+        };
+
+        // The module and the qualified name of the definition. Files of the
+        // module can refer to it without importing the file that declares it.
+        let target = self
+            .with_document(&definition.uri, |document| {
+                let ast = document.ast.as_ref()?;
+                let name = ast.offset_range(&definition.range);
+                let ident = ast.symbols().qualified_name(&name)?;
+                Some((ast.module_name()?.to_owned(), ident.to_owned()))
+            })
+            .flatten();
+
+        // The document comes first, then other files sorted by path.
+        let mut paths = BTreeSet::new();
+        if let Ok(path) = definition.uri.to_file_path() {
+            paths.extend(self.workspace_graph.dependents(&path));
+            paths.insert(path);
+        }
+        if let Some((module_name, _)) = &target {
+            paths.extend(self.workspace_graph.module_files(module_name));
+        }
+        if let Ok(path) = uri.to_file_path() {
+            paths.remove(&path);
+        }
+        if paths.len() > MAX_SEARCHED_FILES {
+            log::warn!(
+                "{} files may refer to {}. Only {} are searched",
+                paths.len(),
+                definition.uri,
+                MAX_SEARCHED_FILES
+            );
+        }
+        let uris = std::iter::once(uri.clone()).chain(
+            paths
+                .into_iter()
+                .take(MAX_SEARCHED_FILES)
+                .filter_map(|path| Uri::from_file_path(path).ok()),
+        );
+
+        let mut locations = Vec::new();
+        // Declarations in other files aren't occurrences in the document.
+        if include_declaration && definition.uri != uri {
+            locations.push(definition.clone());
+        }
+        for file_uri in uris {
+            token.check()?;
+            let include_declaration = include_declaration && file_uri == uri;
+            let references = self.scan_document(&file_uri, |document| {
+                let ast = match &document.ast {
+                    Some(ast) => ast,
+                    None => return Vec::new(),
+                };
+                let fallback = |ident: &str| {
+                    let (module_name, qualified) = target.as_ref()?;
+                    if ast.module_name() != Some(module_name.as_str()) {
+                        return None;
+                    }
+                    let local = strip_module_qualifier(module_name, ident).unwrap_or(ident);
+                    if local == qualified {
+                        Some(definition.clone())
+                    } else {
+                        None
+                    }
+                };
+                super::references::references_to(
+                    ast,
+                    &document.imported_files,
+                    &definition,
+                    include_declaration,
+                    &fallback,
+                )
+            });
+            locations.extend(references.unwrap_or_default());
+        }
+        Ok(locations)
+    }
+
+    fn document_highlight(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Vec<lsp_types::DocumentHighlight> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => super::references::document_highlight(
+                ast,
+                &document.imported_files,
+                ast.offset(&pos),
+            ),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
+    }
+
+    fn completion(&mut self, uri: Uri, pos: lsp_types::Position) -> Vec<lsp_types::CompletionItem> {
+        let root_paths = self.import_paths();
+        let build_flags = self.settings.build_flags.clone();
+        let encoding = self.encoding;
+        self.with_document(&uri, |document| {
+            // The AST may be older than the text while the document has a
+            // syntax error.
+            let offset = LineIndex::new(&document.text, encoding).offset(&document.text, &pos);
+            let ast = document.ast.as_ref().or(document.last_valid_ast.as_ref());
+            let imported_files = document
+                .imported_files
+                .as_ref()
+                .or(document.last_valid_imported_files.as_ref());
+            super::completion::completion(
+                &document.text,
+                offset,
+                ast,
+                imported_files,
+                &root_paths,
+                &build_flags,
+            )
+        })
+        .unwrap_or_default()
+    }
+
+    fn signature_help(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Option<lsp_types::SignatureHelp> {
+        let encoding = self.encoding;
+        self.with_document(&uri, |document| {
+            let offset = LineIndex::new(&document.text, encoding).offset(&document.text, &pos);
+            let ast = document.ast.as_ref().or(document.last_valid_ast.as_ref())?;
+            super::signature_help::signature_help(&document.text, offset, ast)
+        })
+        .flatten()
+    }
+
+    fn prepare_rename(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Result<Option<lsp_types::Range>, RenameError> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => {
+                let offset = ast.offset(&pos);
+                super::rename::prepare_rename(ast, &document.imported_files, offset)
+            }
+            None => Ok(None),
+        })
+        .unwrap_or(Ok(None))
     }
-}
-
-enum DiagnosticMessage {
-    CheckSyntax((Uri, String)),
-    GotoDefinition(
-        (
-            Uri,
-            lsp_types::Position,
-            Sender<Option<lsp_types::Location>>,
-        ),
-    ),
-}
 
-pub(crate) struct DiagnosticsThread {
-    handle: JoinHandle<()>,
-    sender: Sender<DiagnosticMessage>,
-}
+    fn rename(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        new_name: &str,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>, RenameError> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => {
+                let offset = ast.offset(&pos);
+                super::rename::rename(ast, &document.imported_files, offset, new_name)
+            }
+            None => Ok(None),
+        })
+        .unwrap_or(Ok(None))
+    }
 
-impl DiagnosticsThread {
-    #[allow(unused)]
-    pub(crate) fn join(self) {
-        self.handle.join().unwrap();
+    fn organize_imports(&mut self, uri: Uri) -> Option<lsp_types::CodeAction> {
+        self.with_document(&uri, |document| {
+            super::code_action::organize_imports(document.ast.as_ref()?)
+        })
+        .flatten()
     }
 
-    pub(crate) fn check(&self, uri: Uri, text: String) {
-        self.sender
-            .send(DiagnosticMessage::CheckSyntax((uri, text)))
-            .unwrap();
+    fn quick_fixes(
+        &mut self,
+        uri: Uri,
+        diagnostics: &[lsp_types::Diagnostic],
+    ) -> Vec<lsp_types::CodeAction> {
+        self.with_document(&uri, |document| match &document.ast {
+            Some(ast) => diagnostics
+                .iter()
+                .filter_map(|diagnostic| {
+                    super::code_action::remove_unused_import(ast, diagnostic).or_else(|| {
+                        super::code_action::rename_to_suggestion(
+                            ast,
+                            &document.imported_files,
+                            diagnostic,
+                        )
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        })
+        .unwrap_or_default()
     }
 
-    pub(crate) fn goto_definition(
-        &self,
+    fn prepare_type_hierarchy(
+        &mut self,
         uri: Uri,
         pos: lsp_types::Position,
-    ) -> Option<lsp_types::Location> {
-        let (loc_sender, loc_receiver) = channel::<Option<lsp_types::Location>>();
-        self.sender
-            .send(DiagnosticMessage::GotoDefinition((uri, pos, loc_sender)))
-            .unwrap();
-        let loc = loc_receiver.recv().unwrap();
-        loc
+    ) -> Option<TypeHierarchyItem> {
+        let definition = self
+            .with_document(&uri, |document| {
+                let ast = document.ast.as_ref()?;
+                super::references::resolve_at(ast, &document.imported_files, ast.offset(&pos))
+            })
+            .flatten()?;
+        self.with_document(&definition.uri, |document| {
+            super::type_hierarchy::type_item(document.ast.as_ref()?, &definition)
+        })
+        .flatten()
     }
-}
-
-pub(crate) fn start_diagnostics_thread(
-    root_path: PathBuf,
-    msg_sender: MessageSender,
-) -> DiagnosticsThread {
-    let mut diag = Diagnostic::new(root_path, msg_sender);
-    let (sender, receiver) = channel::<DiagnosticMessage>();
-    let handle = thread::spawn(move || loop {
-        let msg = match receiver.recv() {
-            Ok(msg) => msg,
-            Err(_) => break,
-        };
 
-        match msg {
-            DiagnosticMessage::CheckSyntax((uri, text)) => {
-                diag.check(uri, text);
-            }
-            DiagnosticMessage::GotoDefinition((uri, pos, loc_sender)) => {
-                let loc = diag.find_definition(uri, pos);
-                loc_sender.send(loc).unwrap();
+    // Returns the methods that use the type of `item`. Only the document
+    // that declares the type and the files that import it can refer to the
+    // type. Importers are looked up in the workspace graph, so files that
+    // were never opened are found once the workspace is indexed, up to
+    // MAX_SEARCHED_FILES of them. Open and cached documents are searched too
+    // because the graph is empty until then and may miss imports that
+    // aren't saved yet.
+    fn subtypes(
+        &mut self,
+        item: &TypeHierarchyItem,
+        token: &CancellationToken,
+    ) -> Result<Vec<TypeHierarchyItem>, Cancelled> {
+        if item.kind == lsp_types::SymbolKind::METHOD {
+            return Ok(Vec::new());
+        }
+        let definition = item.location();
+        let mut uris = BTreeSet::new();
+        uris.insert(item.uri.clone());
+        if let Ok(path) = item.uri.to_file_path() {
+            let mut dependents = self.workspace_graph.dependents(&path);
+            if dependents.len() > MAX_SEARCHED_FILES {
+                log::warn!(
+                    "{} files import {}. Only {} are searched",
+                    dependents.len(),
+                    path.display(),
+                    MAX_SEARCHED_FILES
+                );
+                dependents.truncate(MAX_SEARCHED_FILES);
             }
+            uris.extend(
+                dependents
+                    .into_iter()
+                    .filter_map(|path| Uri::from_file_path(path).ok()),
+            );
         }
-    });
+        uris.extend(self.documents.keys().cloned());
+        uris.extend(
+            self.closed_documents
+                .iter()
+                .map(|closed| closed.uri.clone()),
+        );
 
-    DiagnosticsThread {
-        handle: handle,
-        sender: sender,
+        let mut usages = Vec::new();
+        for uri in &uris {
+            token.check()?;
+            let found = self.scan_document(uri, |document| match &document.ast {
+                Some(ast) => {
+                    super::type_hierarchy::method_usages(ast, &document.imported_files, &definition)
+                }
+                None => Vec::new(),
+            });
+            usages.extend(found.unwrap_or_default());
+        }
+        usages.sort_by(|a, b| {
+            let key =
+                |item: &TypeHierarchyItem| (item.range.start.line, item.range.start.character);
+            (a.uri.as_str(), key(a)).cmp(&(b.uri.as_str(), key(b)))
+        });
+        Ok(usages)
     }
-}
 
-struct Diagnostic {
-    // Workspace root path.
-    root_path: PathBuf,
-    // A message sender. It is used in the diagnostics thread to send
-    // notifications.
-    msg_sender: MessageSender,
-    // Current parsed syntax tree with the original text.
-    ast: Option<MojomAst>,
-    // Parsed mojom files that are imported from the current document.
-    imported_files: Option<ImportedFiles>,
-}
+    // Returns the info of the type named `type_name`. The name is resolved at
+    // the top level of the document of `uri` and its imported files.
+    fn type_info(&mut self, uri: Uri, type_name: &str) -> Option<TypeInfo> {
+        let definition = self
+            .with_document(&uri, |document| {
+                let ast = document.ast.as_ref()?;
+                find_definition_in_doc(ast, &[], type_name).or_else(|| {
+                    find_definition_in_imported_files(&document.imported_files, type_name)
+                })
+            })
+            .flatten()?;
+        self.with_document(&definition.uri, |document| {
+            super::custom::type_info(document.ast.as_ref()?, &definition)
+        })
+        .flatten()
+    }
 
-impl Diagnostic {
-    fn new(root_path: PathBuf, msg_sender: MessageSender) -> Self {
-        Diagnostic {
-            root_path: root_path,
-            msg_sender: msg_sender,
-            ast: None,
-            imported_files: None,
-        }
+    fn restart(&mut self) {
+        self.import_cache = ImportCache::new();
+        self.import_cache.set_metrics(self.metrics.clone());
+        self.closed_documents.clear();
+        self.gen_paths = None;
+        self.check_all();
     }
 
-    fn check(&mut self, uri: Uri, text: String) {
-        self.check_syntax(uri.clone(), text);
-        self.check_imported_files();
+    fn show_analysis(&mut self, uri: Uri) -> Option<AnalysisSummary> {
+        self.with_document(&uri, |document| {
+            super::command::analysis_summary(
+                uri.clone(),
+                document.ast.as_ref(),
+                document.imported_files.as_ref(),
+            )
+        })
     }
 
-    fn find_definition(
-        &mut self,
-        uri: Uri,
-        pos: lsp_types::Position,
-    ) -> Option<lsp_types::Location> {
-        if !self.is_same_uri(&uri) {
-            // TODO: Don't use unwrap().
-            self.open(uri).unwrap();
-        }
-
-        if let Some(ast) = &self.ast {
-            let ident = get_identifier(&ast.text, &pos);
-            let loc = find_definition_in_doc(ast, &ident).or(find_definition_in_imported_files(
-                &self.imported_files,
-                &ident,
-            ));
-            loc
-        } else {
-            None
-        }
+    // Calls `f` with the document of `uri`. Documents that aren't open are
+    // read from the disk and kept while the file isn't modified.
+    fn with_document<R, F>(&mut self, uri: &Uri, f: F) -> Option<R>
+    where
+        F: FnOnce(&Document) -> R,
+    {
+        self.visit_document(uri, true, f)
     }
 
-    fn is_same_uri(&self, uri: &Uri) -> bool {
-        if let Some(ast) = &self.ast {
-            *uri == ast.uri
-        } else {
-            false
-        }
-    }
-
-    fn open(&mut self, uri: Uri) -> std::io::Result<()> {
-        let path = uri.to_file_path().unwrap();
-        let mut text = String::new();
-        File::open(path).and_then(|mut f| f.read_to_string(&mut text))?;
-        self.check(uri, text);
-        Ok(())
-    }
-
-    fn check_syntax(&mut self, uri: Uri, text: String) {
-        let mojom = syntax::parse(&text);
-        let diagnostics = match mojom {
-            Ok(mojom) => {
-                let analytics = super::semantic::check_semantics(&text, &mojom);
-                // TODO: Don't store ast when semantics check fails?
-                self.ast = Some(MojomAst::from_mojom(
-                    uri.clone(),
-                    text,
-                    mojom,
-                    analytics.module,
-                ));
-                analytics.diagnostics
+    // Like `with_document()`, but documents read from the disk aren't kept.
+    // Searches that visit many files use it so that they don't evict the
+    // documents the user works with from the cache.
+    fn scan_document<R, F>(&mut self, uri: &Uri, f: F) -> Option<R>
+    where
+        F: FnOnce(&Document) -> R,
+    {
+        self.visit_document(uri, false, f)
+    }
+
+    // Calls `f` with the document of `uri`. Documents read from the disk are
+    // added to the closed document cache when `keep` is true.
+    fn visit_document<R, F>(&mut self, uri: &Uri, keep: bool, f: F) -> Option<R>
+    where
+        F: FnOnce(&Document) -> R,
+    {
+        if let Some(document) = self.documents.get(uri) {
+            return Some(f(document));
+        }
+
+        let path = uri.to_file_path().ok()?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if let Some(pos) = self
+            .closed_documents
+            .iter()
+            .position(|closed| &closed.uri == uri)
+        {
+            let mut closed = self.closed_documents.remove(pos).unwrap();
+            if Some(closed.modified) == modified {
+                // Imported files may change without notifications when the
+                // client doesn't watch files. The import cache parses them
+                // again when their modification times change.
+                if let Some(ast) = &closed.document.ast {
+                    let import_paths = self.import_paths();
+                    let imported_files = check_imports(&import_paths, ast, &mut self.import_cache);
+                    closed.document.imported_files = Some(imported_files);
+                }
+                self.closed_documents.push_back(closed);
+                return self
+                    .closed_documents
+                    .back()
+                    .map(|closed| f(&closed.document));
             }
+        }
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
             Err(err) => {
-                self.ast = None;
-                let (start, end) = err.range();
-                let range = into_lsp_range(&start, &end);
-                let diagnostic = create_diagnostic(range, err.to_string());
-                vec![diagnostic]
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                return None;
             }
         };
+        let (document, _) = self.parse_document(uri.clone(), text);
+        let res = f(&document);
+        let capacity = self.settings.cache.closed_documents;
+        match modified {
+            Some(modified) if keep && capacity > 0 => {
+                self.closed_documents.push_back(ClosedDocument {
+                    uri: uri.clone(),
+                    modified,
+                    document,
+                });
+                while self.closed_documents.len() > capacity {
+                    self.closed_documents.pop_front();
+                }
+            }
+            _ => (),
+        }
+        Some(res)
+    }
 
-        let params = lsp_types::PublishDiagnosticsParams {
-            uri: uri,
-            diagnostics: diagnostics,
-            // TODO: Support version
-            version: None,
+    // Parses `text` and its imported files. Returns the document and its
+    // diagnostics.
+    fn parse_document(&mut self, uri: Uri, text: String) -> (Document, Vec<lsp_types::Diagnostic>) {
+        let import_paths = self.import_paths();
+        let start = Instant::now();
+        let name = uri.to_string();
+        let result = check_document(
+            &import_paths,
+            uri,
+            text.clone(),
+            self.encoding,
+            &self.settings.checks,
+            &mut self.import_cache,
+        );
+        self.metrics.record_parse(&name, start.elapsed());
+        let document = Document {
+            text,
+            ast: result.ast,
+            imported_files: result.imported_files,
+            ..Default::default()
         };
-        publish_diagnostics(&self.msg_sender, params);
+        (document, result.diagnostics)
     }
 
-    fn check_imported_files(&mut self) {
-        if let Some(ast) = &self.ast {
-            let imported_files = check_imports(&self.root_path, ast);
-            self.imported_files = Some(imported_files);
+    // Publishes diagnostics of imported files that are open in the editor.
+    // Results of direct checks always win because the editor may have
+    // unsaved changes.
+    fn publish_import_diagnostics(&mut self, imported_files: &ImportedFiles) {
+        for (uri, diagnostics) in imported_files.diagnostics() {
+            let last_source = match self.documents.get(uri) {
+                Some(document) => document.last_source,
+                None => continue,
+            };
+            let should_publish = match last_source {
+                Some(PublishSource::Direct) => false,
+                // Clear old results when there is no error anymore.
+                Some(PublishSource::Import) => true,
+                None => !diagnostics.is_empty(),
+            };
+            if should_publish {
+                let diagnostics = diagnostics.to_vec();
+                self.publish(uri.clone(), diagnostics, PublishSource::Import, None);
+            }
         }
     }
-}
 
-pub(crate) fn into_lsp_range(start: &syntax::LineCol, end: &syntax::LineCol) -> lsp_types::Range {
-    lsp_types::Range {
-        start: lsp_types::Position::new(start.line as u32, start.col as u32),
-        end: lsp_types::Position::new(end.line as u32, end.col as u32),
+    fn publish(
+        &mut self,
+        uri: Uri,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        source: PublishSource,
+        version: Option<i32>,
+    ) {
+        if let Some(document) = self.documents.get_mut(&uri) {
+            document.last_source = Some(source);
+        }
+        let caps = &self.client_caps;
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| fit_to_client(diagnostic, caps))
+            .collect();
+        let params = lsp_types::PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version,
+        };
+        publish_diagnostics(&self.msg_sender, params);
     }
 }
 
@@ -234,17 +1767,102 @@ fn publish_diagnostics(msg_sender: &MessageSender, params: lsp_types::PublishDia
     msg_sender.send_notification(msg);
 }
 
-fn get_offset_from_position(text: &str, pos: &lsp_types::Position) -> usize {
-    let pos_line = pos.line as usize;
-    let pos_col = pos.character as usize;
-    let mut offset = 0;
-    for (i, line) in text.lines().enumerate() {
-        if i == pos_line {
-            break;
+// The result of `check_document()`.
+pub(crate) struct CheckResult {
+    // None when the document has a syntax error.
+    pub(crate) ast: Option<MojomAst>,
+    pub(crate) imported_files: Option<ImportedFiles>,
+    pub(crate) diagnostics: Vec<lsp_types::Diagnostic>,
+}
+
+// Runs all checks on `text` and its imported files. This doesn't publish
+// anything so that it can be used without a client.
+pub(crate) fn check_document(
+    root_paths: &[PathBuf],
+    uri: Uri,
+    text: String,
+    encoding: PositionEncoding,
+    options: &analysis::Options,
+    import_cache: &mut ImportCache,
+) -> CheckResult {
+    let analysis = analysis::analyze(&text, options);
+    let is_fatal = analysis.is_fatal();
+    let line_index = LineIndex::new(&text, encoding);
+    let mut diagnostics = analysis
+        .diagnostics
+        .iter()
+        .map(|diagnostic| into_lsp_diagnostic(&uri, &text, &line_index, diagnostic))
+        .collect::<Vec<_>>();
+    // Only syntax errors drop the syntax tree. Documents with semantic errors
+    // or lints keep it so that navigation works while they are fixed.
+    let module = analysis.module;
+    let ast = analysis
+        .mojom
+        .filter(|_| !is_fatal)
+        .map(|mojom| MojomAst::new(uri, text, mojom, module, encoding));
+    let imported_files = ast
+        .as_ref()
+        .map(|ast| check_imports(root_paths, ast, import_cache));
+    // Type references can be resolved only after imports are parsed.
+    match (&ast, &imported_files) {
+        (Some(ast), Some(imported_files)) if options.semantic_diagnostics => {
+            let mut unresolved = Vec::new();
+            // Indices of import statements that provide referenced types.
+            let mut used_imports = HashSet::new();
+            let mut ambiguous = HashSet::new();
+            analysis::check_type_references(
+                &ast.text,
+                &ast.mojom,
+                ast.module_name(),
+                |name| match imported_files.find_origin(name) {
+                    Some(origin) => {
+                        used_imports.insert(origin);
+                        if imported_files.is_ambiguous(name) {
+                            ambiguous.insert(name.to_owned());
+                        }
+                        true
+                    }
+                    None => false,
+                },
+                &mut unresolved,
+            );
+            analysis::check_ambiguous_references(
+                &ast.text,
+                &ast.mojom,
+                |name| ambiguous.contains(name),
+                &mut unresolved,
+            );
+            analysis::check_stable_imports(
+                &ast.text,
+                &ast.mojom,
+                |name| imported_files.is_stable(name),
+                &mut unresolved,
+            );
+            analysis::check_cyclic_containment(
+                &ast.text,
+                &ast.mojom,
+                |name| imported_files.find_contained_types(name),
+                &mut unresolved,
+            );
+            // Imports that failed to parse are reported elsewhere.
+            analysis::check_unused_imports(
+                &ast.text,
+                &ast.mojom,
+                |index| used_imports.contains(&index) || !imported_files.is_parsed(index),
+                &mut unresolved,
+            );
+            options.override_severity(&mut unresolved);
+            diagnostics.extend(unresolved.iter().map(|diagnostic| {
+                into_lsp_diagnostic(&ast.uri, &ast.text, ast.line_index(), diagnostic)
+            }));
         }
-        offset += line.len() + 1;
+        _ => (),
+    }
+    CheckResult {
+        ast,
+        imported_files,
+        diagnostics,
     }
-    offset + pos_col
 }
 
 #[inline(always)]
@@ -252,29 +1870,78 @@ fn is_identifier_char(ch: char) -> bool {
     ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
 }
 
-fn get_identifier<'a>(text: &'a str, pos: &lsp_types::Position) -> &'a str {
-    // TODO: The current implementation isn't accurate.
+// Returns the identifier that contains `offset` or ends at `offset`. Returns
+// None when the cursor is on whitespace or punctuation.
+fn get_identifier(text: &str, offset: usize) -> Option<&str> {
+    identifier_range(text, offset).map(|range| &text[range.start..range.end])
+}
 
-    let offset = get_offset_from_position(text, pos);
-    let mut s = offset;
-    for ch in text[..offset].chars().rev() {
-        if !is_identifier_char(ch) {
-            break;
-        }
-        s -= 1;
+// Same as `get_identifier` except that the range of the identifier is
+// returned.
+fn identifier_range(text: &str, offset: usize) -> Option<syntax::Range> {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
     }
-    let mut e = offset;
-    for ch in text[offset..].chars() {
-        if !is_identifier_char(ch) {
-            break;
-        }
-        e += 1;
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| is_identifier_char(ch))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, ch)| !is_identifier_char(ch))
+        .map_or(text.len(), |(i, _)| offset + i);
+    let ident = &text[start..end];
+    if ident.is_empty() || ident.chars().all(|ch| ch == '.') {
+        None
+    } else {
+        Some(syntax::Range { start, end })
+    }
+}
+
+// Same as `get_identifier` except in attribute sections, where only values
+// that can name declarations are identifiers. Attribute names and build flags
+// like `is_win` in `[EnableIf=is_win]` aren't.
+fn get_reference(ast: &MojomAst, offset: usize) -> Option<&str> {
+    reference_range(ast, offset).map(|range| ast.text(&range))
+}
+
+// Same as `get_reference` except that the range of the reference is returned.
+fn reference_range(ast: &MojomAst, offset: usize) -> Option<syntax::Range> {
+    let list = match super::definition::attribute_list_at(ast, offset) {
+        Some(list) => list,
+        None => return identifier_range(&ast.text, offset),
+    };
+    let attribute = list.attributes.iter().find(|attribute| {
+        let value = attribute.value.as_ref();
+        value.is_some_and(|value| value.start <= offset && offset <= value.end)
+    })?;
+    if !super::definition::refers_to_declaration(ast, attribute) {
+        return None;
     }
-    &text[s..e]
+    identifier_range(&ast.text, offset)
+}
+
+// Returns the range of what goto definition at `offset` starts from: the path
+// of an import statement or a reference.
+fn origin_range(ast: &MojomAst, offset: usize) -> Option<syntax::Range> {
+    let path = ast.mojom.stmts.iter().find_map(|stmt| match stmt {
+        syntax::Statement::Import(stmt) if stmt.path.start <= offset && offset < stmt.path.end => {
+            Some(stmt.path)
+        }
+        _ => None,
+    });
+    path.or_else(|| reference_range(ast, offset))
 }
 
-fn find_definition_in_doc(ast: &MojomAst, ident: &str) -> Option<lsp_types::Location> {
-    super::definition::find_definition_preorder(ident, ast)
+fn find_definition_in_doc(
+    ast: &MojomAst,
+    scope: &[&str],
+    ident: &str,
+) -> Option<lsp_types::Location> {
+    super::definition::find_definition_in_scope(ident, scope, ast)
 }
 
 fn find_definition_in_imported_files(
@@ -285,3 +1952,1035 @@ fn find_definition_in_imported_files(
         .as_ref()
         .and_then(|ref imported_files| imported_files.find_definition(ident))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::messagesender::start_message_sender_thread;
+    use super::super::protocol;
+    use super::super::test_util::TempDir;
+
+    // Creates the state of the diagnostics thread whose messages are
+    // discarded.
+    fn new_diagnostic(
+        root_path: PathBuf,
+        settings: Settings,
+        client_caps: ClientCaps,
+    ) -> Diagnostic {
+        let msg_sender_thread = start_message_sender_thread(std::io::sink());
+        Diagnostic::new(
+            vec![root_path],
+            settings,
+            PositionEncoding::default(),
+            client_caps,
+            msg_sender_thread.get_sender(),
+            Metrics::default(),
+        )
+    }
+
+    #[test]
+    fn test_multiple_documents() {
+        let mut diag = new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+
+        let token = CancellationToken::default();
+
+        // These files don't exist. Definitions are found in open documents.
+        let foo = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let bar = Uri::parse("file:///nonexistent/bar.mojom").unwrap();
+        diag.did_open(foo.clone());
+        diag.check(
+            foo.clone(),
+            "struct Foo {};\nstruct Baz { Foo foo; };".to_owned(),
+            None,
+        );
+        diag.did_open(bar.clone());
+        diag.check(bar.clone(), "struct Bar {};".to_owned(), None);
+
+        let loc = diag
+            .find_definition(
+                foo.clone(),
+                lsp_types::Position::new(1, 14),
+                &token,
+                Deadline::default(),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(foo, loc.uri);
+        assert_eq!(0, loc.range.start.line);
+        let loc = diag
+            .find_definition(
+                bar.clone(),
+                lsp_types::Position::new(0, 8),
+                &token,
+                Deadline::default(),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(bar, loc.uri);
+
+        diag.did_close(foo.clone());
+        assert!(!diag.documents.contains_key(&foo));
+        assert!(diag
+            .find_definition(
+                foo,
+                lsp_types::Position::new(1, 14),
+                &token,
+                Deadline::default()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_diagnostics_thread_gone() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        let diag = DiagnosticsThread {
+            handle: thread::spawn(|| {}),
+            sender,
+        };
+
+        let uri = Uri::parse("file:///test.mojom").unwrap();
+        let pos = lsp_types::Position::new(0, 0);
+        let token = CancellationToken::default();
+        assert_eq!(
+            Err(DiagError::Disconnected),
+            diag.goto_definition(uri.clone(), pos, token)
+        );
+        assert_eq!(Err(DiagError::Disconnected), diag.hover(uri.clone(), pos));
+        assert_eq!(
+            Err(DiagError::Disconnected),
+            diag.document_symbol(uri.clone())
+        );
+        assert_eq!(
+            Err(DiagError::Disconnected),
+            diag.check(uri, String::new(), None)
+        );
+        assert_eq!(Ok(()), diag.shutdown());
+    }
+
+    #[test]
+    fn test_unresolved_types() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+
+        let uri = Uri::from_file_path(root_path.join("test.mojom")).unwrap();
+        let text = r#"import "foo_module/foo.mojom";
+struct Test {
+    foo.FooStruct foo;
+    foo.BarStruct bar;
+    array<Missing> missing;
+};"#;
+        let (_, diagnostics) = diag.parse_document(uri, text.to_owned());
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(4, diagnostics[0].range.start.line);
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::WARNING),
+            diagnostics[0].severity
+        );
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+
+        // `BarStruct` is defined in an import of `foo_module/foo.mojom`.
+        let uri = Uri::from_file_path(root_path.join("test.mojom")).unwrap();
+        let text = r#"import "foo_module/foo.mojom";
+import "my_interface.mojom";
+struct Test {
+    foo.BarStruct bar;
+};"#;
+        let (_, diagnostics) = diag.parse_document(uri.clone(), text.to_owned());
+        assert_eq!(1, diagnostics.len());
+        let diagnostic = &diagnostics[0];
+        assert_eq!(
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 0),
+                lsp_types::Position::new(1, 28)
+            ),
+            diagnostic.range
+        );
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::HINT),
+            diagnostic.severity
+        );
+        assert_eq!(
+            Some(vec![lsp_types::DiagnosticTag::UNNECESSARY]),
+            diagnostic.tags
+        );
+
+        // Module-qualified references use the import too.
+        let text = text.replace("foo.BarStruct bar", "foo.FooStruct foo");
+        let (_, diagnostics) = diag.parse_document(uri, text);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(1, diagnostics[0].range.start.line);
+    }
+
+    #[test]
+    fn test_update_settings() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+
+        let uri = Uri::from_file_path(root_path.join("test.mojom")).unwrap();
+        let text = r#"import "foo.mojom";
+struct Test {
+    foo.FooStruct foo;
+};"#;
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), text.to_owned(), None);
+        let resolves = |diag: &Diagnostic| {
+            let imported_files = diag.documents[&uri].imported_files.as_ref().unwrap();
+            imported_files.find_definition("foo.FooStruct").is_some()
+        };
+        assert!(!resolves(&diag));
+
+        // The open document is checked again with the new import root.
+        let value = serde_json::json!({ "importRoots": ["foo_module"] });
+        diag.update_settings(Settings::from_value(Some(value)));
+        assert!(resolves(&diag));
+        let parses = |diag: &Diagnostic| diag.metrics.snapshot()["parseDurations"]["count"].clone();
+        let count = parses(&diag);
+
+        // Settings that are only read from `initializationOptions` don't
+        // check documents again.
+        let value = serde_json::json!({
+            "importRoots": ["foo_module"],
+            "maxContentLength": 1024,
+            "indexOnStartup": false,
+        });
+        diag.update_settings(Settings::from_value(Some(value)));
+        assert_eq!(count, parses(&diag));
+        assert_eq!(
+            protocol::DEFAULT_MAX_CONTENT_LENGTH,
+            diag.settings.max_content_length
+        );
+    }
+
+    #[test]
+    fn test_gen_imports() {
+        let root_path = std::path::Path::new("testdata/chromium/src")
+            .canonicalize()
+            .unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+
+        // `gen_module/b.mojom` exists only under `out/Default/gen`.
+        let uri = Uri::from_file_path(root_path.join("a.mojom")).unwrap();
+        let text = std::fs::read_to_string(root_path.join("a.mojom")).unwrap();
+        let (_, diagnostics) = diag.parse_document(uri, text);
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            Some(vec![root_path.join("out/Default/gen")]),
+            diag.gen_paths
+        );
+    }
+
+    #[test]
+    fn test_goto_import_definition() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+
+        let token = CancellationToken::default();
+        // my_service.mojom imports my_interface.mojom at line 0 and
+        // foo_module/foo.mojom at line 1.
+        let uri = Uri::from_file_path(root_path.join("my_service.mojom")).unwrap();
+        let loc = diag
+            .find_definition(
+                uri.clone(),
+                lsp_types::Position::new(0, 12),
+                &token,
+                Deadline::default(),
+            )
+            .unwrap()
+            .unwrap();
+        assert!(loc.uri.path().ends_with("testdata/my_interface.mojom"));
+        // my_interface.mojom has no module statement.
+        assert_eq!(lsp_types::Range::default(), loc.range);
+
+        let loc = diag
+            .find_definition(
+                uri,
+                lsp_types::Position::new(1, 10),
+                &token,
+                Deadline::default(),
+            )
+            .unwrap()
+            .unwrap();
+        assert!(loc.uri.path().ends_with("foo_module/foo.mojom"));
+        // Points at `foo` in `module foo;`.
+        assert_eq!(lsp_types::Position::new(0, 7), loc.range.start);
+
+        let missing = Uri::from_file_path(root_path.join("missing_import.mojom")).unwrap();
+        diag.did_open(missing.clone());
+        diag.check(
+            missing.clone(),
+            r#"import "nonexistent.mojom";"#.to_owned(),
+            None,
+        );
+        assert!(diag
+            .find_definition(
+                missing,
+                lsp_types::Position::new(0, 10),
+                &token,
+                Deadline::default()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_native_definition() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+        let token = CancellationToken::default();
+        let uri = Uri::from_file_path(root_path.join("native/native.mojom")).unwrap();
+        // `NativeStruct` in `NativeStruct value;`.
+        let pos = lsp_types::Position::new(9, 4);
+
+        // Without the setting, the definition is the mojom declaration.
+        let declaration = diag
+            .find_declaration(uri.clone(), pos, &token, Deadline::default())
+            .unwrap();
+        let declaration = declaration.unwrap();
+        assert_eq!(uri, declaration.uri);
+        assert_eq!(3, declaration.range.start.line);
+        let definition = diag
+            .find_definition(uri.clone(), pos, &token, Deadline::default())
+            .unwrap();
+        assert_eq!(Some(declaration.clone()), definition);
+
+        let settings = Settings {
+            native_definitions: true,
+            ..Settings::default()
+        };
+        diag.update_settings(settings);
+        let definition = diag
+            .find_definition(uri.clone(), pos, &token, Deadline::default())
+            .unwrap();
+        assert!(definition
+            .unwrap()
+            .uri
+            .path()
+            .ends_with("native/cpp/native_struct.h"));
+        // The declaration stays in mojom.
+        let found = diag
+            .find_declaration(uri.clone(), pos, &token, Deadline::default())
+            .unwrap();
+        assert_eq!(Some(declaration.clone()), found);
+
+        // Types that aren't native are defined in mojom.
+        let pos = lsp_types::Position::new(8, 8);
+        let definition = diag
+            .find_definition(uri.clone(), pos, &token, Deadline::default())
+            .unwrap();
+        assert_eq!(uri, definition.unwrap().uri);
+
+        // The native search is skipped once the deadline is exceeded.
+        let expired = Deadline(Some(Instant::now()));
+        let definition = diag
+            .find_definition(uri.clone(), lsp_types::Position::new(9, 4), &token, expired)
+            .unwrap();
+        assert_eq!(Some(declaration), definition);
+    }
+
+    #[test]
+    fn test_deadline() {
+        assert!(!Deadline::default().is_exceeded());
+        assert!(!Deadline::after(Duration::ZERO).is_exceeded());
+        assert!(!Deadline::after(Duration::from_secs(60)).is_exceeded());
+        assert!(Deadline(Some(Instant::now())).is_exceeded());
+    }
+
+    #[test]
+    fn test_type_hierarchy() {
+        let root_path = std::path::Path::new("testdata").canonicalize().unwrap();
+        let mut diag = new_diagnostic(
+            root_path.clone(),
+            Settings::default(),
+            ClientCaps::default(),
+        );
+        let token = CancellationToken::default();
+
+        let path = root_path.join("usages/canvas.mojom");
+        let uri = Uri::from_file_path(&path).unwrap();
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), std::fs::read_to_string(&path).unwrap(), None);
+
+        // `Point` in `geometry.mojom.Point from`.
+        let item = diag
+            .prepare_type_hierarchy(uri.clone(), lsp_types::Position::new(5, 28))
+            .unwrap();
+        assert_eq!("Point", item.name);
+        assert!(item.uri.path().ends_with("usages/geometry.mojom"));
+
+        // The declaring document is searched as well as open documents.
+        let usages = diag.subtypes(&item, &token).unwrap();
+        let names = usages
+            .iter()
+            .map(|usage| (usage.detail.as_deref().unwrap(), usage.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("Canvas", "DrawLine"),
+                ("Canvas", "MovePath"),
+                ("Locator", "Locate")
+            ],
+            names
+        );
+        assert!(diag.subtypes(&usages[0], &token).unwrap().is_empty());
+
+        // `Canvas` is a type but isn't used by any method.
+        let item = diag
+            .prepare_type_hierarchy(uri.clone(), lsp_types::Position::new(4, 12))
+            .unwrap();
+        assert_eq!(lsp_types::SymbolKind::INTERFACE, item.kind);
+        assert!(diag.subtypes(&item, &token).unwrap().is_empty());
+        // `DrawLine` isn't a type.
+        assert!(diag
+            .prepare_type_hierarchy(uri, lsp_types::Position::new(5, 4))
+            .is_none());
+    }
+
+    #[test]
+    fn test_closed_document_cache() {
+        let temp_dir = TempDir::new("cache");
+        let dir = temp_dir.path().to_owned();
+        let mut settings = Settings::default();
+        settings.cache.closed_documents = 1;
+        let mut diag = new_diagnostic(dir.clone(), settings, ClientCaps::default());
+        let token = CancellationToken::default();
+        let path = dir.join("a.mojom");
+        let uri = Uri::from_file_path(&path).unwrap();
+        let find_foo = |diag: &mut Diagnostic| {
+            let pos = lsp_types::Position::new(2, 15);
+            let loc = diag
+                .find_definition(uri.clone(), pos, &token, Deadline::default())
+                .unwrap();
+            loc.map(|loc| loc.range.start.line)
+        };
+
+        std::fs::write(&path, "struct Foo {};\n\nstruct Baz { Foo foo; };").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(Some(0), find_foo(&mut diag));
+        assert_eq!(1, diag.closed_documents.len());
+
+        // The cached document is used while the modification time is the same.
+        std::fs::write(&path, "\nstruct Foo {};\nstruct Baz { Foo foo; };").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert_eq!(Some(0), find_foo(&mut diag));
+
+        let later = modified + std::time::Duration::from_secs(1);
+        file.set_modified(later).unwrap();
+        assert_eq!(Some(1), find_foo(&mut diag));
+        assert_eq!(1, diag.closed_documents.len());
+
+        // Imported files of cached documents are checked again.
+        let imported = dir.join("imported.mojom");
+        std::fs::write(&imported, "struct Imp {};").unwrap();
+        let importer = dir.join("importer.mojom");
+        std::fs::write(
+            &importer,
+            "import \"imported.mojom\";\nstruct User { Imp imp; };",
+        )
+        .unwrap();
+        let importer = Uri::from_file_path(&importer).unwrap();
+        let find_imp = |diag: &mut Diagnostic| {
+            let pos = lsp_types::Position::new(1, 15);
+            let loc = diag
+                .find_definition(importer.clone(), pos, &token, Deadline::default())
+                .unwrap();
+            loc.map(|loc| loc.range.start.line)
+        };
+        assert_eq!(Some(0), find_imp(&mut diag));
+        let modified = std::fs::metadata(&imported).unwrap().modified().unwrap();
+        std::fs::write(&imported, "\nstruct Imp {};").unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&imported)
+            .unwrap();
+        file.set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(Some(1), find_imp(&mut diag));
+        assert_eq!(1, diag.closed_documents.len());
+
+        // Least recently used documents are dropped.
+        let other = dir.join("b.mojom");
+        std::fs::write(&other, "struct Bar {};").unwrap();
+        let other = Uri::from_file_path(&other).unwrap();
+        diag.document_symbol(other.clone());
+        assert_eq!(1, diag.closed_documents.len());
+        assert_eq!(other, diag.closed_documents[0].uri);
+    }
+
+    #[test]
+    fn test_module_files() {
+        let temp_dir = TempDir::new("module");
+        let dir = temp_dir.path().to_owned();
+        let mut diag = new_diagnostic(dir.clone(), Settings::default(), ClientCaps::default());
+        let token = CancellationToken::default();
+
+        // a.mojom and b.mojom declare the same module without importing each
+        // other. c.mojom imports b.mojom.
+        let texts = [
+            ("a.mojom", "module foo.mojom;\nstruct A { B b; };"),
+            ("b.mojom", "module foo.mojom;\nstruct B {};"),
+            (
+                "c.mojom",
+                "module bar.mojom;\nimport \"b.mojom\";\nstruct C { foo.mojom.B b; };",
+            ),
+        ];
+        let mut files = std::collections::BTreeMap::new();
+        for (name, text) in texts.iter() {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            let file = super::super::workspace_symbol::index_file(&path, diag.encoding);
+            files.insert(path, file);
+        }
+        let uri = |name: &str| Uri::from_file_path(dir.join(name)).unwrap();
+        let location = |name: &str, line, character| {
+            let pos = lsp_types::Position::new(line, character);
+            lsp_types::Location::new(uri(name), lsp_types::Range::new(pos, pos))
+        };
+        let starts = |locations: Vec<lsp_types::Location>| {
+            locations
+                .into_iter()
+                .map(|location| {
+                    let range = lsp_types::Range::new(location.range.start, location.range.start);
+                    lsp_types::Location::new(location.uri, range)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Sibling files are unknown until the workspace is indexed.
+        let pos = lsp_types::Position::new(1, 11);
+        assert_eq!(
+            None,
+            diag.find_definition(uri("a.mojom"), pos, &token, Deadline::default())
+                .unwrap()
+        );
+        diag.workspace_graph.update(&dir, &files);
+
+        let definition = diag
+            .find_definition(uri("a.mojom"), pos, &token, Deadline::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(location("b.mojom", 1, 7), starts(vec![definition])[0]);
+
+        assert_eq!(
+            vec![
+                location("b.mojom", 1, 7),
+                location("a.mojom", 1, 11),
+                location("c.mojom", 2, 11),
+            ],
+            starts(diag.references(uri("a.mojom"), pos, true, &token).unwrap())
+        );
+        let pos = lsp_types::Position::new(1, 7);
+        assert_eq!(
+            vec![location("a.mojom", 1, 11), location("c.mojom", 2, 11)],
+            starts(diag.references(uri("b.mojom"), pos, false, &token).unwrap())
+        );
+        // Dependents are searched without evicting cached documents.
+        assert!(diag
+            .closed_documents
+            .iter()
+            .all(|closed| closed.uri != uri("c.mojom")));
+
+        let cancelled = CancellationToken::default();
+        cancelled.cancel();
+        assert_eq!(
+            Err(Cancelled),
+            diag.references(uri("b.mojom"), pos, false, &cancelled)
+        );
+        assert_eq!(
+            Err(Cancelled),
+            diag.find_definition_in_module(
+                &uri("a.mojom"),
+                "foo.mojom",
+                "B",
+                &cancelled,
+                Deadline::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_subtypes_in_dependents() {
+        let temp_dir = TempDir::new("subtypes");
+        let dir = temp_dir.path().to_owned();
+        let mut diag = new_diagnostic(dir.clone(), Settings::default(), ClientCaps::default());
+        let token = CancellationToken::default();
+
+        // Only a.mojom is opened. b.mojom imports it and is never opened.
+        // c.mojom doesn't import it.
+        let texts = [
+            ("a.mojom", "module a.mojom;\nstruct Point {};"),
+            (
+                "b.mojom",
+                "module b.mojom;\nimport \"a.mojom\";\ninterface B { Move(a.mojom.Point p); };",
+            ),
+            ("c.mojom", "module c.mojom;\ninterface C { Move(); };"),
+        ];
+        let mut files = std::collections::BTreeMap::new();
+        for (name, text) in texts.iter() {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            let file = super::super::workspace_symbol::index_file(&path, diag.encoding);
+            files.insert(path, file);
+        }
+        let uri = Uri::from_file_path(dir.join("a.mojom")).unwrap();
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), texts[0].1.to_owned(), None);
+        let item = diag
+            .prepare_type_hierarchy(uri, lsp_types::Position::new(1, 8))
+            .unwrap();
+        assert_eq!("Point", item.name);
+
+        // Importers are unknown until the workspace is indexed.
+        assert!(diag.subtypes(&item, &token).unwrap().is_empty());
+        diag.workspace_graph.update(&dir, &files);
+        let usages = diag.subtypes(&item, &token).unwrap();
+        assert_eq!(1, usages.len());
+        assert_eq!("Move", usages[0].name);
+        assert_eq!(Some("B"), usages[0].detail.as_deref());
+        assert_eq!(
+            Uri::from_file_path(dir.join("b.mojom")).unwrap(),
+            usages[0].uri
+        );
+
+        let cancelled = CancellationToken::default();
+        cancelled.cancel();
+        assert_eq!(Err(Cancelled), diag.subtypes(&item, &cancelled));
+    }
+
+    #[test]
+    fn test_goto_definition_emoji() {
+        let token = CancellationToken::default();
+        // "😀" is 2 UTF-16 units and 4 bytes.
+        let text = "/* 😀 */ struct Foo {};\n/* 😀 */ struct Baz { Foo foo; };";
+        let uri = Uri::parse("file:///nonexistent/emoji.mojom").unwrap();
+
+        for (encoding, col) in &[(PositionEncoding::Utf16, 22), (PositionEncoding::Utf8, 24)] {
+            let mut diag =
+                new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+            diag.encoding = *encoding;
+            diag.did_open(uri.clone());
+            diag.check(uri.clone(), text.to_owned(), None);
+
+            // `Foo` in the field of `Baz`.
+            let pos = lsp_types::Position::new(1, *col);
+            let loc = diag
+                .find_definition(uri.clone(), pos, &token, Deadline::default())
+                .unwrap()
+                .unwrap();
+            let start = col - 6;
+            assert_eq!(
+                lsp_types::Range::new(
+                    lsp_types::Position::new(0, start),
+                    lsp_types::Position::new(0, start + 3)
+                ),
+                loc.range
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_identifier() {
+        let text = "struct Foo { Bar bar; };";
+        assert_eq!(Some("Foo"), get_identifier(text, 7));
+        assert_eq!(Some("Foo"), get_identifier(text, 8));
+        assert_eq!(Some("Foo"), get_identifier(text, 10));
+        // Whitespace and punctuation.
+        assert_eq!(None, get_identifier(text, 11));
+        assert_eq!(None, get_identifier(text, 12));
+        assert_eq!(None, get_identifier(text, text.len()));
+        // Past the end of the document.
+        assert_eq!(None, get_identifier(text, text.len() + 10));
+        assert_eq!(Some("Bar"), get_identifier("Bar", 10));
+
+        // "é" is 2 bytes.
+        let text = "// é\nFoo";
+        assert_eq!(Some("Foo"), get_identifier(text, 6));
+        assert_eq!(None, get_identifier(text, 4));
+        let text = "/*é*/Foo";
+        assert_eq!(Some("Foo"), get_identifier(text, 7));
+        assert_eq!(None, get_identifier(text, 3));
+
+        assert_eq!(Some("foo.mojom.Bar"), get_identifier("foo.mojom.Bar x;", 4));
+        assert_eq!(None, get_identifier("a . b", 2));
+    }
+
+    #[test]
+    fn test_get_reference() {
+        let text = "[RuntimeFeature=kFoo, EnableIf=is_win] interface Foo {};";
+        let uri = Uri::parse("file:///test.mojom").unwrap();
+        let mojom = crate::syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom, None);
+        let offset = |pattern: &str| text.find(pattern).unwrap();
+        assert_eq!(Some("kFoo"), get_reference(&ast, offset("kFoo")));
+        assert_eq!(Some("kFoo"), get_reference(&ast, offset(", Enable")));
+        assert_eq!(None, get_reference(&ast, offset("RuntimeFeature")));
+        assert_eq!(None, get_reference(&ast, offset("EnableIf")));
+        assert_eq!(None, get_reference(&ast, offset("is_win")));
+        assert_eq!(Some("Foo"), get_reference(&ast, offset("Foo {")));
+    }
+
+    #[test]
+    fn test_goto_definition_in_scope() {
+        let token = CancellationToken::default();
+        let mut diag = new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+        let uri = Uri::parse("file:///nonexistent/scope.mojom").unwrap();
+        let text =
+            "interface MyInterface {\n  enum FooEnum { kValue };\n  Method(FooEnum value);\n};";
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), text.to_owned(), None);
+
+        // `FooEnum` in the parameter of `Method`.
+        let pos = lsp_types::Position::new(2, 10);
+        let loc = diag
+            .find_definition(uri.clone(), pos, &token, Deadline::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 7),
+                lsp_types::Position::new(1, 14)
+            ),
+            loc.range
+        );
+    }
+
+    #[test]
+    fn test_goto_definition_edges() {
+        let token = CancellationToken::default();
+        let mut diag = new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+        let uri = Uri::parse("file:///nonexistent/edges.mojom").unwrap();
+        let text = "struct Foo {};\r\nstruct Baz {\r\n  /*é*/Foo\r\n  foo; };\r\n// Foo";
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), text.to_owned(), None);
+
+        let mut find = |line, character| {
+            let pos = lsp_types::Position::new(line, character);
+            diag.find_definition(uri.clone(), pos, &token, Deadline::default())
+                .unwrap()
+                .map(|location| location.range)
+        };
+        let foo = Some(lsp_types::Range::new(
+            lsp_types::Position::new(0, 7),
+            lsp_types::Position::new(0, 10),
+        ));
+        // At the end of a CRLF line and past it.
+        assert_eq!(foo, find(2, 10));
+        assert_eq!(foo, find(2, 100));
+        assert_eq!(foo, find(0, 10));
+        // `é` in the comment right before the identifier.
+        assert_eq!(foo, find(2, 7));
+        assert_eq!(None, find(2, 4));
+        // Whitespace and punctuation.
+        assert_eq!(None, find(1, 0));
+        assert_eq!(None, find(0, 11));
+        assert_eq!(None, find(1, 12));
+        // At the end of the document and past it.
+        assert_eq!(foo, find(4, 6));
+        assert_eq!(foo, find(100, 0));
+    }
+
+    #[test]
+    fn test_client_caps() {
+        let capabilities = serde_json::json!({
+            "textDocument": {
+                "publishDiagnostics": {
+                    "relatedInformation": true,
+                    "tagSupport": { "valueSet": [1, 2] },
+                },
+                "definition": { "linkSupport": true },
+            },
+        });
+        let capabilities = serde_json::from_value(capabilities).unwrap();
+        let supported = ClientCaps::new(&capabilities);
+        assert!(supported.related_information);
+        assert!(supported.unnecessary_tag);
+        assert!(supported.definition_link);
+        let unsupported = ClientCaps::new(&Default::default());
+        assert_eq!(ClientCaps::default(), unsupported);
+
+        let uri = Uri::parse("file:///nonexistent/caps.mojom").unwrap();
+        let related = lsp_types::DiagnosticRelatedInformation {
+            location: lsp_types::Location::new(
+                uri.clone(),
+                lsp_types::Range::new(
+                    lsp_types::Position::new(2, 0),
+                    lsp_types::Position::new(2, 2),
+                ),
+            ),
+            message: "@0 is first used here".to_owned(),
+        };
+        let diagnostic = lsp_types::Diagnostic {
+            message: "Duplicate ordinal".to_owned(),
+            related_information: Some(vec![related]),
+            tags: Some(vec![lsp_types::DiagnosticTag::UNNECESSARY]),
+            ..Default::default()
+        };
+
+        let publish = |client_caps: ClientCaps| {
+            let (reader, writer) = pipe::pipe();
+            let msg_sender_thread = start_message_sender_thread(writer);
+            let mut diag = Diagnostic::new(
+                vec![PathBuf::new()],
+                Settings::default(),
+                PositionEncoding::default(),
+                client_caps,
+                msg_sender_thread.get_sender(),
+                Metrics::default(),
+            );
+            diag.publish(
+                uri.clone(),
+                vec![diagnostic.clone()],
+                PublishSource::Direct,
+                None,
+            );
+            let mut reader = std::io::BufReader::new(reader);
+            let params = match protocol::read_message(&mut reader).unwrap() {
+                protocol::Message::Notification(msg) => msg.params,
+                _ => unreachable!(),
+            };
+            let mut params =
+                serde_json::from_value::<lsp_types::PublishDiagnosticsParams>(params).unwrap();
+            params.diagnostics.remove(0)
+        };
+
+        // Supported information is published as is.
+        assert_eq!(diagnostic, publish(supported));
+
+        // Otherwise the related location is in the message.
+        let published = publish(unsupported);
+        assert_eq!(
+            "Duplicate ordinal (@0 is first used here at line 3)",
+            published.message
+        );
+        assert!(published.related_information.is_none());
+        assert!(published.tags.is_none());
+    }
+
+    #[test]
+    fn test_debounce_checks() {
+        let (reader, writer) = pipe::pipe();
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let mut diag = Diagnostic::new(
+            vec![PathBuf::new()],
+            Settings::default(),
+            PositionEncoding::default(),
+            ClientCaps::default(),
+            msg_sender_thread.get_sender(),
+            Metrics::default(),
+        );
+
+        // Three rapid changes are queued before the loop runs.
+        let uri = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let (sender, receiver) = channel();
+        sender
+            .send(DiagnosticMessage::DidOpen(uri.clone()))
+            .unwrap();
+        for (version, text) in &[(1, "struct"), (2, "struct Foo"), (3, "struct Foo {};")] {
+            let msg =
+                DiagnosticMessage::CheckSyntax((uri.clone(), text.to_string(), Some(*version)));
+            sender.send(msg).unwrap();
+        }
+        drop(sender);
+        run_diagnostics_loop(&mut diag, receiver);
+
+        // Publish a marker to know when all notifications are read.
+        let marker = Uri::parse("file:///nonexistent/marker.mojom").unwrap();
+        diag.publish(marker.clone(), Vec::new(), PublishSource::Direct, None);
+
+        let mut reader = std::io::BufReader::new(reader);
+        let mut published = Vec::new();
+        loop {
+            let params = match protocol::read_message(&mut reader).unwrap() {
+                protocol::Message::Notification(msg) => msg.params,
+                _ => unreachable!(),
+            };
+            let params =
+                serde_json::from_value::<lsp_types::PublishDiagnosticsParams>(params).unwrap();
+            if params.uri == marker {
+                break;
+            }
+            published.push(params);
+        }
+        assert_eq!(1, published.len());
+        assert_eq!(uri, published[0].uri);
+        assert_eq!(Some(3), published[0].version);
+        assert!(published[0].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_definition_response() {
+        let uri = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let text = "struct Foo {\n  int32 a;\n};\nstruct Bar { Foo foo; };";
+        // `Foo` in `Foo foo;`.
+        let pos = lsp_types::Position::new(3, 14);
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            lsp_types::Range::new(
+                lsp_types::Position::new(start.0, start.1),
+                lsp_types::Position::new(end.0, end.1),
+            )
+        };
+        let token = CancellationToken::default();
+
+        // Clients without link support get the location of the name.
+        let mut diag = new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), text.to_owned(), None);
+        let location = diag
+            .find_definition(uri.clone(), pos, &token, Deadline::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(range((0, 7), (0, 10)), location.range);
+        assert_eq!(
+            lsp_types::GotoDefinitionResponse::Scalar(location.clone()),
+            diag.definition_response(&uri, pos, location.clone())
+        );
+
+        // Links underline the reference and span the whole declaration.
+        let mut diag = new_diagnostic(
+            PathBuf::new(),
+            Settings::default(),
+            ClientCaps {
+                definition_link: true,
+                ..ClientCaps::default()
+            },
+        );
+        diag.did_open(uri.clone());
+        diag.check(uri.clone(), text.to_owned(), None);
+        let link = lsp_types::LocationLink {
+            origin_selection_range: Some(range((3, 13), (3, 16))),
+            target_uri: uri.clone(),
+            target_range: range((0, 0), (2, 2)),
+            target_selection_range: range((0, 7), (0, 10)),
+        };
+        assert_eq!(
+            lsp_types::GotoDefinitionResponse::Link(vec![link]),
+            diag.definition_response(&uri, pos, location)
+        );
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let foo = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let bar = Uri::parse("file:///nonexistent/bar.mojom").unwrap();
+        let check = |uri: &Uri, version| {
+            DiagnosticMessage::CheckSyntax((uri.clone(), String::new(), Some(version)))
+        };
+        let mut scheduler = Scheduler::default();
+        scheduler.push(check(&foo, 1));
+        scheduler.push(DiagnosticMessage::DidOpen(bar.clone()));
+        scheduler.push(check(&bar, 1));
+        scheduler.push(check(&foo, 2));
+        scheduler.push(check(&foo, 3));
+
+        let mut handled = Vec::new();
+        while let Some(msg) = scheduler.pop() {
+            handled.push(match msg {
+                DiagnosticMessage::DidOpen(uri) => (uri, None),
+                DiagnosticMessage::CheckSyntax((uri, _, version)) => (uri, version),
+                _ => unreachable!(),
+            });
+        }
+        assert_eq!(
+            vec![(bar.clone(), None), (bar, Some(1)), (foo, Some(3))],
+            handled
+        );
+    }
+
+    #[test]
+    fn test_goto_definition_after_burst() {
+        let msg_sender_thread = start_message_sender_thread(std::io::sink());
+        let metrics = Metrics::default();
+        let mut diag = Diagnostic::new(
+            vec![PathBuf::new()],
+            Settings::default(),
+            PositionEncoding::default(),
+            ClientCaps::default(),
+            msg_sender_thread.get_sender(),
+            metrics.clone(),
+        );
+
+        // 50 changes arrive before a definition request while the thread is
+        // busy.
+        let uri = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let (sender, receiver) = channel();
+        sender
+            .send(DiagnosticMessage::DidOpen(uri.clone()))
+            .unwrap();
+        for version in 1..=50 {
+            let text = format!("struct Foo {{}};\nstruct Bar{} {{ Foo foo; }};", version);
+            let msg = DiagnosticMessage::CheckSyntax((uri.clone(), text, Some(version)));
+            sender.send(msg).unwrap();
+        }
+        let (loc_sender, loc_receiver) = channel();
+        let pos = lsp_types::Position::new(1, 15);
+        let token = CancellationToken::default();
+        let msg = DiagnosticMessage::GotoDefinition((uri.clone(), pos, token, loc_sender));
+        sender.send(msg).unwrap();
+        drop(sender);
+        run_diagnostics_loop(&mut diag, receiver);
+
+        let loc = match loc_receiver.recv().unwrap().unwrap() {
+            Some(lsp_types::GotoDefinitionResponse::Scalar(loc)) => loc,
+            res => panic!("Unexpected response: {:?}", res),
+        };
+        assert_eq!(uri, loc.uri);
+        assert_eq!(0, loc.range.start.line);
+        // Only the newest text is parsed.
+        assert_eq!(1, metrics.snapshot()["parseDurations"]["count"]);
+    }
+
+    #[test]
+    fn test_goto_definition_cancelled() {
+        let mut diag = new_diagnostic(PathBuf::new(), Settings::default(), ClientCaps::default());
+        let uri = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        diag.did_open(uri.clone());
+        diag.check(
+            uri.clone(),
+            "struct Foo {};\nstruct Baz { Foo foo; };".to_owned(),
+            None,
+        );
+
+        let token = CancellationToken::default();
+        token.cancel();
+        let (sender, receiver) = channel();
+        let (loc_sender, loc_receiver) = channel();
+        let pos = lsp_types::Position::new(1, 14);
+        let msg = DiagnosticMessage::GotoDefinition((uri, pos, token, loc_sender));
+        sender.send(msg).unwrap();
+        drop(sender);
+        run_diagnostics_loop(&mut diag, receiver);
+        assert_eq!(Err(Cancelled), loc_receiver.recv().unwrap());
+    }
+}
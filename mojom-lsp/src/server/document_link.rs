@@ -0,0 +1,153 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use lsp_types::{DocumentLink, Url};
+
+use crate::syntax::{self, Statement};
+
+use super::imported_files::{find_imported_file, ImportLoader};
+use super::mojomast::MojomAst;
+
+fn target_uri<P: AsRef<Path>>(
+    root_paths: &[P],
+    path: &str,
+    loader: &dyn ImportLoader,
+) -> Option<Url> {
+    let path = find_imported_file(root_paths, path, loader)?;
+    Url::from_file_path(path).ok()
+}
+
+/// Returns links of import paths in `ast`. Links to files that don't exist
+/// have no target and keep the import path in `data` so that
+/// `resolve_document_link()` can try again later.
+pub(crate) fn document_links<P: AsRef<Path>>(
+    root_paths: &[P],
+    ast: &MojomAst,
+    loader: &dyn ImportLoader,
+) -> Vec<DocumentLink> {
+    let mut links = Vec::new();
+    for stmt in &ast.mojom.stmts {
+        let stmt = match stmt {
+            Statement::Import(stmt) => stmt,
+            _ => continue,
+        };
+        // Excludes the quotes.
+        let range = syntax::Range {
+            start: stmt.path.start + 1,
+            end: stmt.path.end - 1,
+        };
+        let path = ast.text(&range);
+        let target = target_uri(root_paths, path, loader);
+        let data = match target {
+            Some(_) => None,
+            None => Some(serde_json::json!({ "path": path })),
+        };
+        links.push(DocumentLink {
+            range: ast.lsp_range(&range),
+            target,
+            tooltip: None,
+            data,
+        });
+    }
+    links
+}
+
+/// Resolves the target of `link` that was missing when the link was created.
+pub(crate) fn resolve_document_link<P: AsRef<Path>>(
+    root_paths: &[P],
+    mut link: DocumentLink,
+    loader: &dyn ImportLoader,
+) -> DocumentLink {
+    if link.target.is_some() {
+        return link;
+    }
+    let path = link
+        .data
+        .as_ref()
+        .and_then(|data| data.get("path"))
+        .and_then(|path| path.as_str());
+    if let Some(path) = path {
+        link.target = target_uri(root_paths, path, loader);
+    }
+    link
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::imported_files::FileLoader;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let path = Path::new("testdata/test.mojom");
+        let uri = Url::from_file_path(std::env::current_dir().unwrap().join(path)).unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    #[test]
+    fn test_document_links() {
+        let text = r#"import "my_interface.mojom";
+import "foo.mojom";
+"#;
+        let ast = create_ast(text);
+        let links = document_links(&["testdata"], &ast, &FileLoader);
+        assert_eq!(2, links.len());
+
+        let expected = Path::new("testdata/my_interface.mojom")
+            .canonicalize()
+            .unwrap();
+        assert_eq!(
+            Some(Url::from_file_path(expected).unwrap()),
+            links[0].target
+        );
+        assert_eq!(None, links[0].data);
+        // The quotes aren't part of the link.
+        assert_eq!(
+            lsp_types::Range::new(
+                lsp_types::Position::new(0, 8),
+                lsp_types::Position::new(0, 26)
+            ),
+            links[0].range
+        );
+
+        // `foo.mojom` doesn't exist under `testdata`.
+        assert_eq!(None, links[1].target);
+        assert!(links[1].data.is_some());
+    }
+
+    #[test]
+    fn test_resolve_document_link() {
+        let ast = create_ast("import \"foo.mojom\";");
+        let link = document_links(&["testdata"], &ast, &FileLoader)
+            .pop()
+            .unwrap();
+
+        let resolved = resolve_document_link(&["testdata"], link.clone(), &FileLoader);
+        assert_eq!(None, resolved.target);
+
+        // The file is found once the import root is added.
+        let resolved =
+            resolve_document_link(&["testdata", "testdata/foo_module"], link, &FileLoader);
+        let expected = Path::new("testdata/foo_module/foo.mojom")
+            .canonicalize()
+            .unwrap();
+        assert_eq!(
+            Some(Url::from_file_path(expected).unwrap()),
+            resolved.target
+        );
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{DocumentSymbol, SymbolKind};
+
+use crate::analysis::find_condition;
+use crate::syntax::{self, preorder, AttributeList, Traversal};
+
+use super::mojomast::MojomAst;
+
+// The detail is the `EnableIf` or `EnableIfNot` condition followed by the
+// text at `detail`, e.g. `[EnableIf=is_win] int32`, so that variants of a
+// declaration can be told apart.
+fn create_symbol(
+    ast: &MojomAst,
+    kind: SymbolKind,
+    range: &syntax::Range,
+    name: &syntax::Range,
+    attributes: &Option<AttributeList>,
+    detail: Option<&syntax::Range>,
+) -> DocumentSymbol {
+    let condition =
+        find_condition(&ast.text, attributes).map(|condition| format!("[{}]", condition));
+    let detail = detail.map(|detail| ast.text(detail));
+    let detail = match (condition, detail) {
+        (Some(condition), Some(detail)) => Some(format!("{} {}", condition, detail)),
+        (condition, detail) => condition.or_else(|| detail.map(str::to_owned)),
+    };
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name: ast.text(name).to_owned(),
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: ast.lsp_range(range),
+        selection_range: ast.lsp_range(name),
+        children: None,
+    }
+}
+
+fn create_enum_symbol(ast: &MojomAst, node: &syntax::Enum) -> DocumentSymbol {
+    let mut symbol = create_symbol(
+        ast,
+        SymbolKind::ENUM,
+        &node.range,
+        &node.name,
+        &node.attributes,
+        None,
+    );
+    let values = node
+        .values
+        .iter()
+        .map(|value| {
+            create_symbol(
+                ast,
+                SymbolKind::ENUM_MEMBER,
+                &value.range,
+                &value.name,
+                &value.attributes,
+                value.value.as_ref(),
+            )
+        })
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        symbol.children = Some(values);
+    }
+    symbol
+}
+
+// Appends `symbol` to the innermost container, or to `symbols` when there is
+// no container.
+fn push_symbol(
+    symbols: &mut Vec<DocumentSymbol>,
+    containers: &mut [DocumentSymbol],
+    symbol: DocumentSymbol,
+) {
+    match containers.last_mut() {
+        Some(container) => container.children.get_or_insert_with(Vec::new).push(symbol),
+        None => symbols.push(symbol),
+    }
+}
+
+/// Returns the outline of the document. Interfaces, structs and features
+/// contain their members.
+pub(crate) fn document_symbols(ast: &MojomAst) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut containers: Vec<DocumentSymbol> = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        let symbol = match traversal {
+            Traversal::EnterInterface(node) => {
+                let symbol = create_symbol(
+                    ast,
+                    SymbolKind::INTERFACE,
+                    &node.range,
+                    &node.name,
+                    &node.attributes,
+                    None,
+                );
+                containers.push(symbol);
+                continue;
+            }
+            Traversal::EnterStruct(node) => {
+                let symbol = create_symbol(
+                    ast,
+                    SymbolKind::STRUCT,
+                    &node.range,
+                    &node.name,
+                    &node.attributes,
+                    None,
+                );
+                containers.push(symbol);
+                continue;
+            }
+            Traversal::Feature(node) => {
+                let symbol = create_symbol(
+                    ast,
+                    SymbolKind::OBJECT,
+                    &node.range,
+                    &node.name,
+                    &node.attributes,
+                    None,
+                );
+                containers.push(symbol);
+                continue;
+            }
+            Traversal::LeaveInterface(_)
+            | Traversal::LeaveStruct(_)
+            | Traversal::LeaveFeature(_) => {
+                // Unwrap should be safe because Leave* always follows Enter*.
+                containers.pop().unwrap()
+            }
+            Traversal::Method(node) => create_symbol(
+                ast,
+                SymbolKind::METHOD,
+                &node.range,
+                &node.name,
+                &node.attributes,
+                None,
+            ),
+            Traversal::StructField(node) => create_symbol(
+                ast,
+                SymbolKind::FIELD,
+                &node.range,
+                &node.name,
+                &node.attributes,
+                Some(&node.typ),
+            ),
+            Traversal::Union(node) => create_symbol(
+                ast,
+                SymbolKind::STRUCT,
+                &node.range,
+                &node.name,
+                &node.attributes,
+                None,
+            ),
+            Traversal::Enum(node) => create_enum_symbol(ast, node),
+            Traversal::Const(node) => create_symbol(
+                ast,
+                SymbolKind::CONSTANT,
+                &node.range,
+                &node.name,
+                &node.attributes,
+                Some(&node.typ),
+            ),
+            _ => continue,
+        };
+        push_symbol(&mut symbols, &mut containers, symbol);
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Read;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    fn names(symbols: &[DocumentSymbol]) -> Vec<&str> {
+        symbols.iter().map(|symbol| symbol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let input = r#"
+        const int32 kTopLevel = 1;
+        enum TopEnum { kA, kB = 2 };
+        union MyUnion { int32 a; string b; };
+        struct MyStruct {
+            const int32 kInStruct = 2;
+            int32 field;
+        };
+        interface MyInterface {
+            enum InnerEnum { kOne };
+            Method(int32 arg) => ();
+        };
+        "#;
+        let ast = create_ast(input);
+        let symbols = document_symbols(&ast);
+        assert_eq!(
+            vec!["kTopLevel", "TopEnum", "MyUnion", "MyStruct", "MyInterface"],
+            names(&symbols)
+        );
+
+        assert_eq!(SymbolKind::CONSTANT, symbols[0].kind);
+        assert_eq!(Some("int32".to_owned()), symbols[0].detail);
+
+        let values = symbols[1].children.as_ref().unwrap();
+        assert_eq!(vec!["kA", "kB"], names(values));
+        assert_eq!(SymbolKind::ENUM_MEMBER, values[1].kind);
+
+        assert!(symbols[2].children.is_none());
+
+        let members = symbols[3].children.as_ref().unwrap();
+        assert_eq!(SymbolKind::STRUCT, symbols[3].kind);
+        assert_eq!(vec!["kInStruct", "field"], names(members));
+        assert_eq!(SymbolKind::FIELD, members[1].kind);
+
+        let members = symbols[4].children.as_ref().unwrap();
+        assert_eq!(SymbolKind::INTERFACE, symbols[4].kind);
+        assert_eq!(vec!["InnerEnum", "Method"], names(members));
+        assert_eq!(SymbolKind::METHOD, members[1].kind);
+
+        // The range covers the declaration and the selection range covers the
+        // name.
+        let method = &members[1];
+        assert_eq!(10, method.range.start.line);
+        assert_eq!(12, method.range.start.character);
+        assert_eq!(36, method.range.end.character);
+        assert_eq!(12, method.selection_range.start.character);
+        assert_eq!(18, method.selection_range.end.character);
+    }
+
+    #[test]
+    fn test_document_symbols_conditions() {
+        let input = r#"
+        struct Window {
+            [EnableIf=is_win] int32 id;
+            [EnableIfNot=is_win] int64 id;
+        };
+        [EnableIf=is_mac]
+        interface Mac {};
+        "#;
+        let ast = create_ast(input);
+        let symbols = document_symbols(&ast);
+        let fields = symbols[0].children.as_ref().unwrap();
+        assert_eq!(Some("[EnableIf=is_win] int32".to_owned()), fields[0].detail);
+        assert_eq!(
+            Some("[EnableIfNot=is_win] int64".to_owned()),
+            fields[1].detail
+        );
+        assert_eq!(None, symbols[0].detail);
+        assert_eq!(Some("[EnableIf=is_mac]".to_owned()), symbols[1].detail);
+    }
+
+    #[test]
+    fn test_document_symbols_testdata() {
+        let mut text = String::new();
+        File::open("testdata/my_interface.mojom")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        let ast = create_ast(&text);
+        let symbols = document_symbols(&ast);
+        assert!(names(&symbols).contains(&"MyInterface"));
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::syntax::{self, preorder, CommentKind, Statement, Traversal};
+
+use super::mojomast::MojomAst;
+
+fn create_folding_range(
+    start_line: usize,
+    end_line: usize,
+    kind: Option<FoldingRangeKind>,
+) -> FoldingRange {
+    FoldingRange {
+        start_line: start_line as u32,
+        start_character: None,
+        end_line: end_line as u32,
+        end_character: None,
+        kind,
+    }
+}
+
+// Folds the lines between `{` and `}` of `body`. The line of `}` stays
+// visible. Returns None when there is nothing to fold.
+fn body_folding_range(ast: &MojomAst, body: &syntax::Range) -> Option<FoldingRange> {
+    let start_line = ast.line(body.start);
+    let end_line = ast.line(body.end).checked_sub(1)?;
+    if end_line <= start_line {
+        return None;
+    }
+    Some(create_folding_range(start_line, end_line, None))
+}
+
+// Folds the leading run of consecutive import statements.
+fn imports_folding_range(ast: &MojomAst) -> Option<FoldingRange> {
+    let mut imports = ast
+        .mojom
+        .stmts
+        .iter()
+        .skip_while(|stmt| !matches!(stmt, Statement::Import(_)))
+        .take_while(|stmt| matches!(stmt, Statement::Import(_)));
+    let first = imports.next()?;
+    let last = imports.last()?;
+    let start_line = ast.line(first.range().start);
+    let end_line = ast.line(last.range().end);
+    Some(create_folding_range(
+        start_line,
+        end_line,
+        Some(FoldingRangeKind::Imports),
+    ))
+}
+
+/// Returns folding ranges of interface, struct, union and enum bodies,
+/// multi-line block comments and imports.
+pub(crate) fn folding_ranges(ast: &MojomAst) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    ranges.extend(imports_folding_range(ast));
+    for traversal in preorder(&ast.mojom) {
+        let body = match traversal {
+            Traversal::EnterInterface(node) => Some(&node.body),
+            Traversal::EnterStruct(node) => node.body.as_ref(),
+            Traversal::Union(node) => Some(&node.body),
+            Traversal::Enum(node) => node.body.as_ref(),
+            _ => None,
+        };
+        ranges.extend(body.and_then(|body| body_folding_range(ast, body)));
+    }
+    for comment in &ast.mojom.comments {
+        if comment.kind != CommentKind::Block {
+            continue;
+        }
+        let start_line = ast.line(comment.range.start);
+        let end_line = ast.line(comment.range.end);
+        if end_line > start_line {
+            let range = create_folding_range(start_line, end_line, Some(FoldingRangeKind::Comment));
+            ranges.push(range);
+        }
+    }
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ast(uri: lsp_types::Url, text: &str) -> MojomAst {
+        let mojom = syntax::parse_with_comments(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    fn lines(ranges: &[FoldingRange]) -> Vec<(u32, u32)> {
+        ranges
+            .iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_ranges_testdata() {
+        let path = std::path::Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let ast = create_ast(uri, &text);
+
+        let ranges = folding_ranges(&ast);
+        assert_eq!(vec![(0, 1), (3, 4)], lines(&ranges));
+        assert_eq!(Some(FoldingRangeKind::Imports), ranges[0].kind);
+        assert_eq!(None, ranges[1].kind);
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let input = r#"module my.mod;
+/* A block
+   comment. */
+// Line comments don't fold.
+import "a.mojom";
+struct MyStruct {
+    enum Kind {
+        kA,
+        kB,
+    };
+    enum Short { kA };
+    Kind kind;
+};
+union MyUnion {
+    int32 a;
+    string b;
+};
+[Native] struct NativeStruct;
+interface MyInterface {};
+"#;
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, input);
+
+        let ranges = folding_ranges(&ast);
+        // A single import doesn't fold.
+        assert_eq!(vec![(1, 2), (5, 11), (6, 8), (13, 15)], lines(&ranges));
+        assert_eq!(Some(FoldingRangeKind::Comment), ranges[0].kind);
+    }
+}
@@ -0,0 +1,550 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::syntax::{self, leading_comments, preorder, CommentKind, Traversal, TypeName, TypeSpec};
+
+use super::definition::find_declaration_preorder;
+use super::mojomast::MojomAst;
+
+struct BuiltinType {
+    name: &'static str,
+    // Size in bytes in a struct payload.
+    size: &'static str,
+    nullable: bool,
+    // The type in the C++ bindings.
+    cpp: &'static str,
+}
+
+const BUILTIN_TYPES: &[BuiltinType] = &[
+    BuiltinType {
+        name: "bool",
+        size: "1 bit (packed with adjacent bools)",
+        nullable: false,
+        cpp: "bool",
+    },
+    BuiltinType {
+        name: "int8",
+        size: "1 byte",
+        nullable: false,
+        cpp: "int8_t",
+    },
+    BuiltinType {
+        name: "uint8",
+        size: "1 byte",
+        nullable: false,
+        cpp: "uint8_t",
+    },
+    BuiltinType {
+        name: "int16",
+        size: "2 bytes",
+        nullable: false,
+        cpp: "int16_t",
+    },
+    BuiltinType {
+        name: "uint16",
+        size: "2 bytes",
+        nullable: false,
+        cpp: "uint16_t",
+    },
+    BuiltinType {
+        name: "int32",
+        size: "4 bytes",
+        nullable: false,
+        cpp: "int32_t",
+    },
+    BuiltinType {
+        name: "uint32",
+        size: "4 bytes",
+        nullable: false,
+        cpp: "uint32_t",
+    },
+    BuiltinType {
+        name: "int64",
+        size: "8 bytes",
+        nullable: false,
+        cpp: "int64_t",
+    },
+    BuiltinType {
+        name: "uint64",
+        size: "8 bytes",
+        nullable: false,
+        cpp: "uint64_t",
+    },
+    BuiltinType {
+        name: "float",
+        size: "4 bytes (IEEE 754)",
+        nullable: false,
+        cpp: "float",
+    },
+    BuiltinType {
+        name: "double",
+        size: "8 bytes (IEEE 754)",
+        nullable: false,
+        cpp: "double",
+    },
+    BuiltinType {
+        name: "string",
+        size: "8 bytes (pointer to UTF-8 encoded data)",
+        nullable: true,
+        cpp: "std::string",
+    },
+];
+
+fn find_builtin(name: &str) -> Option<&'static BuiltinType> {
+    BUILTIN_TYPES.iter().find(|builtin| builtin.name == name)
+}
+
+fn handle_cpp_type(kind: Option<&str>) -> &'static str {
+    match kind {
+        None => "mojo::ScopedHandle",
+        Some("message_pipe") => "mojo::ScopedMessagePipeHandle",
+        Some("shared_buffer") => "mojo::ScopedSharedBufferHandle",
+        Some("data_pipe_consumer") => "mojo::ScopedDataPipeConsumerHandle",
+        Some("data_pipe_producer") => "mojo::ScopedDataPipeProducerHandle",
+        Some("platform") => "mojo::PlatformHandle",
+        Some(_) => "mojo::ScopedHandle",
+    }
+}
+
+fn nullability(nullable_capable: bool) -> &'static str {
+    if nullable_capable {
+        "Can be nullable (`?`)."
+    } else {
+        "Value type."
+    }
+}
+
+// Describes a type in one line. Used for element types of arrays and maps.
+fn describe_brief(spec: &TypeSpec) -> String {
+    let nullable = if spec.is_nullable { "nullable " } else { "" };
+    match &spec.type_name {
+        TypeName::BasicTypeName(name) => match find_builtin(name) {
+            Some(builtin) => format!("{}`{}` ({})", nullable, name, builtin.size),
+            None => format!("{}`{}` (user-defined type)", nullable, name),
+        },
+        TypeName::Handle(kind) => {
+            format!("{}handle ({})", nullable, handle_cpp_type(kind.as_deref()))
+        }
+        TypeName::Array(_) | TypeName::FixedArray(_, _, _) => format!("{}array", nullable),
+        TypeName::Map(_, _) => format!("{}map", nullable),
+        TypeName::InterfaceRequest(ident, _) => {
+            format!("{}interface request of `{}`", nullable, ident)
+        }
+        TypeName::Associated(ident) => format!("{}associated interface `{}`", nullable, ident),
+        TypeName::PendingRemote(ident) => format!("{}remote of `{}`", nullable, ident),
+        TypeName::PendingReceiver(ident) => format!("{}receiver of `{}`", nullable, ident),
+        TypeName::PendingAssociatedRemote(ident) => {
+            format!("{}associated remote of `{}`", nullable, ident)
+        }
+        TypeName::PendingAssociatedReceiver(ident) => {
+            format!("{}associated receiver of `{}`", nullable, ident)
+        }
+    }
+}
+
+// Describes a builtin type. Returns None for user-defined types.
+fn describe(spec: &TypeSpec) -> Option<String> {
+    let mut lines = Vec::new();
+    match &spec.type_name {
+        TypeName::BasicTypeName(name) => {
+            let builtin = find_builtin(name)?;
+            lines.push(format!("Size: {}.", builtin.size));
+            lines.push(nullability(builtin.nullable).to_owned());
+            lines.push(format!("C++: `{}`", builtin.cpp));
+        }
+        TypeName::Handle(kind) => {
+            lines.push("Size: 4 bytes (index into the message's handle table).".to_owned());
+            lines.push(nullability(true).to_owned());
+            lines.push(format!("C++: `{}`", handle_cpp_type(kind.as_deref())));
+        }
+        TypeName::Array(element) => {
+            lines.push("Size: 8 bytes (pointer to the array data).".to_owned());
+            lines.push(nullability(true).to_owned());
+            lines.push(format!("Element: {}", describe_brief(element)));
+            lines.push("C++: `std::vector`".to_owned());
+        }
+        TypeName::FixedArray(element, size, _) => {
+            lines.push(format!(
+                "Fixed-size array of {} elements. Size: 8 bytes (pointer to the array data).",
+                size
+            ));
+            lines.push(nullability(true).to_owned());
+            lines.push(format!("Element: {}", describe_brief(element)));
+            lines.push("C++: `std::vector`".to_owned());
+        }
+        TypeName::Map(key, value) => {
+            lines.push("Size: 8 bytes (pointer to the map data).".to_owned());
+            lines.push(nullability(true).to_owned());
+            lines.push(format!("Key: {}", describe_brief(key)));
+            lines.push(format!("Value: {}", describe_brief(value)));
+            lines.push("C++: `base::flat_map`".to_owned());
+        }
+        TypeName::InterfaceRequest(ident, is_associated) => {
+            if *is_associated {
+                lines.push("Size: 4 bytes (associated endpoint index).".to_owned());
+                lines.push(format!("C++: `mojo::PendingAssociatedReceiver<{}>`", ident));
+            } else {
+                lines.push("Size: 4 bytes (message pipe handle).".to_owned());
+                lines.push(format!("C++: `mojo::PendingReceiver<{}>`", ident));
+            }
+            lines.push(nullability(true).to_owned());
+        }
+        TypeName::Associated(ident) => {
+            lines.push("Size: 8 bytes (associated endpoint index and version).".to_owned());
+            lines.push(nullability(true).to_owned());
+            lines.push(format!("C++: `mojo::PendingAssociatedRemote<{}>`", ident));
+        }
+        TypeName::PendingRemote(ident) => {
+            lines.push("Size: 8 bytes (message pipe handle and version).".to_owned());
+            lines.push(format!("C++: `mojo::PendingRemote<{}>`", ident));
+            lines.push(nullability(true).to_owned());
+        }
+        TypeName::PendingReceiver(ident) => {
+            lines.push("Size: 4 bytes (message pipe handle).".to_owned());
+            lines.push(format!("C++: `mojo::PendingReceiver<{}>`", ident));
+            lines.push(nullability(true).to_owned());
+        }
+        TypeName::PendingAssociatedRemote(ident) => {
+            lines.push("Size: 8 bytes (associated endpoint index and version).".to_owned());
+            lines.push(format!("C++: `mojo::PendingAssociatedRemote<{}>`", ident));
+            lines.push(nullability(true).to_owned());
+        }
+        TypeName::PendingAssociatedReceiver(ident) => {
+            lines.push("Size: 4 bytes (associated endpoint index).".to_owned());
+            lines.push(format!("C++: `mojo::PendingAssociatedReceiver<{}>`", ident));
+            lines.push(nullability(true).to_owned());
+        }
+    }
+    if spec.is_nullable {
+        lines.push("This type is nullable.".to_owned());
+    }
+    Some(lines.join("\n\n"))
+}
+
+#[inline(always)]
+fn is_type_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
+}
+
+// Finds the type_spec that contains `offset`.
+fn find_type_spec_at(mojom: &syntax::MojomFile, offset: usize) -> Option<syntax::Range> {
+    let contains = |range: &syntax::Range| range.start <= offset && offset < range.end;
+    for traversal in preorder(mojom) {
+        let typ = match traversal {
+            Traversal::StructField(node) => Some(&node.typ),
+            Traversal::Const(node) => Some(&node.typ),
+            Traversal::Union(node) => node
+                .fields
+                .iter()
+                .map(|field| &field.typ)
+                .find(|typ| contains(typ)),
+            Traversal::Method(node) => node
+                .params
+                .iter()
+                .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                .map(|param| &param.typ)
+                .find(|typ| contains(typ)),
+            _ => None,
+        };
+        if let Some(typ) = typ {
+            if contains(typ) {
+                return Some(*typ);
+            }
+        }
+    }
+    None
+}
+
+// Returns the sub-type of `type_text` which starts with the word under
+// `offset`. For `map<string, array<uint8>>` and an offset on `array`, this
+// returns the range of `array<uint8>`.
+fn find_sub_type(type_text: &str, offset: usize) -> Option<(usize, usize)> {
+    let bytes = type_text.as_bytes();
+    let mut start = offset;
+    while start > 0 && is_type_char(bytes[start - 1] as char) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while end < bytes.len() && is_type_char(bytes[end] as char) {
+        end += 1;
+    }
+    if start == end {
+        return None;
+    }
+
+    // Extend to the matching '>' for parameterized types.
+    let mut pos = end;
+    while pos < bytes.len() && bytes[pos] == b' ' {
+        pos += 1;
+    }
+    if pos < bytes.len() && bytes[pos] == b'<' {
+        let mut depth = 0;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'<' => depth += 1,
+                b'>' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => (),
+            }
+            pos += 1;
+        }
+        end = std::cmp::min(pos + 1, bytes.len());
+    }
+    if end < bytes.len() && bytes[end] == b'?' {
+        end += 1;
+    }
+    Some((start, end))
+}
+
+pub(crate) fn hover(ast: &MojomAst, offset: usize) -> Option<lsp_types::Hover> {
+    let typ = find_type_spec_at(&ast.mojom, offset)?;
+    let type_text = ast.text(&typ);
+    let (start, end) = find_sub_type(type_text, offset - typ.start)?;
+    let sub_type = &type_text[start..end];
+
+    let spec = syntax::typespec(sub_type).ok()?;
+    let description = describe(&spec)?;
+
+    let value = format!("```mojom\n{}\n```\n\n{}", sub_type, description);
+    let range = syntax::Range {
+        start: typ.start + start,
+        end: typ.start + end,
+    };
+    Some(lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(ast.lsp_range(&range)),
+    })
+}
+
+// Returns lines of the comments above the declaration that starts at
+// `decl_start`, without comment markers.
+fn doc_comments(ast: &MojomAst, decl_start: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    for comment in leading_comments(&ast.mojom, decl_start) {
+        let text = ast.text(&comment.range);
+        match comment.kind {
+            CommentKind::Line => lines.push(text.trim_start_matches('/').trim()),
+            CommentKind::Block => {
+                let text = text.trim_start_matches("/*").trim_end_matches("*/");
+                let block = text
+                    .lines()
+                    .map(|line| line.trim().trim_start_matches('*').trim())
+                    .collect::<Vec<_>>();
+                let start = block.iter().position(|line| !line.is_empty());
+                let end = block.iter().rposition(|line| !line.is_empty());
+                if let (Some(start), Some(end)) = (start, end) {
+                    lines.extend_from_slice(&block[start..=end]);
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Creates hover contents for the declaration at `range` of `ast` of which
+/// name is at `name`. Contains the line of the declaration that has the name,
+/// without attributes, and doc comments above the declaration.
+pub(crate) fn declaration_markdown(
+    ast: &MojomAst,
+    range: &syntax::Range,
+    name: &syntax::Range,
+) -> String {
+    let text = &ast.text;
+    let before_name = &text[range.start..name.start];
+    let line_start = before_name.rfind('\n').map_or(0, |pos| pos + 1);
+    // Attributes end with `]`. Types and names don't have one.
+    let start = match before_name[line_start..].rfind(']') {
+        Some(pos) => line_start + pos + 1,
+        None => line_start,
+    };
+    let declaration = &text[range.start + start..range.end];
+    let header = declaration.lines().next().unwrap_or("").trim();
+    let mut value = format!("```mojom\n{}\n```", header);
+    let comments = doc_comments(ast, range.start);
+    if !comments.is_empty() {
+        value.push_str("\n\n");
+        value.push_str(&comments.join("\n"));
+    }
+    value
+}
+
+/// Returns hover contents for a definition in the document.
+pub(crate) fn declaration_hover(ast: &MojomAst, ident: &str) -> Option<lsp_types::Hover> {
+    let declaration = find_declaration_preorder(ident, ast)?;
+    Some(lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: declaration_markdown(ast, declaration.range, declaration.name),
+        }),
+        range: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hover_text(input: &str, target: &str) -> Option<String> {
+        let mojom = syntax::parse(input).unwrap();
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, input.to_owned(), mojom, None);
+        let offset = input.find(target).unwrap();
+        hover(&ast, offset).map(|hover| match hover.contents {
+            lsp_types::HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn test_hover() {
+        let input = r#"
+        struct MyStruct {
+            int64 my_id;
+            array<uint8, 16> my_bytes;
+            map<string, array<MyStruct>?> my_map;
+            handle<message_pipe>? my_pipe;
+            MyStruct? my_struct;
+        };
+        interface MyInterface {
+            Method(pending_remote<MyInterface> remote) => (uint32 result);
+        };
+        "#;
+
+        let text = hover_text(input, "int64").unwrap();
+        assert!(text.starts_with("```mojom\nint64\n```"));
+        assert!(text.contains("8 bytes"));
+
+        let text = hover_text(input, "array<uint8, 16>").unwrap();
+        assert!(text.contains("Fixed-size array of 16 elements"));
+        assert!(text.contains("Element: `uint8` (1 byte)"));
+
+        let text = hover_text(input, "uint8, 16").unwrap();
+        assert!(text.starts_with("```mojom\nuint8\n```"));
+
+        let text = hover_text(input, "array<MyStruct>?").unwrap();
+        assert!(text.starts_with("```mojom\narray<MyStruct>?\n```"));
+        assert!(text.contains("Element: `MyStruct` (user-defined type)"));
+        assert!(text.contains("This type is nullable."));
+
+        let text = hover_text(input, "handle<message_pipe>").unwrap();
+        assert!(text.contains("mojo::ScopedMessagePipeHandle"));
+
+        let text = hover_text(input, "pending_remote").unwrap();
+        assert!(text.contains("mojo::PendingRemote<MyInterface>"));
+
+        let text = hover_text(input, "uint32 result").unwrap();
+        assert!(text.contains("4 bytes"));
+
+        // User-defined types and non-type positions.
+        assert!(hover_text(input, "MyStruct? my_struct").is_none());
+        assert!(hover_text(input, "my_id").is_none());
+    }
+
+    #[test]
+    fn test_declaration_hover() {
+        let path = std::path::Path::new("testdata/my_interface.mojom");
+        let text = std::fs::read_to_string(path).unwrap();
+        let mojom = syntax::parse_with_comments(&text).unwrap();
+        let uri = lsp_types::Url::parse("file:///my_interface.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+        let value = |ident: &str| match declaration_hover(&ast, ident).unwrap().contents {
+            lsp_types::HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            "```mojom\ninterface MyInterface {\n```\n\nMyInterface is my own interface.",
+            value("MyInterface")
+        );
+        assert_eq!("```mojom\nkTwo\n```", value("MyInterface.MyInnerEnum.kTwo"));
+        assert!(declaration_hover(&ast, "Unknown").is_none());
+
+        let input = r#"
+        // The answer.
+        //
+        // See also kQuestion.
+        const uint32 kAnswer = 42;
+
+        const uint32 kQuestion = 0;
+
+        // A stable struct.
+        [Stable, RenamedFrom="old.Struct"]
+        struct MyStruct {
+          [MinVersion=1] int32 my_field;
+        };
+        [Stable] enum MyEnum { kValue };
+        "#;
+        let mojom = syntax::parse_with_comments(input).unwrap();
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, input.to_owned(), mojom, None);
+        let hover = declaration_hover(&ast, "kAnswer").unwrap();
+        match hover.contents {
+            lsp_types::HoverContents::Markup(content) => assert_eq!(
+                "```mojom\nconst uint32 kAnswer = 42;\n```\n\nThe answer.\n\nSee also kQuestion.",
+                content.value
+            ),
+            _ => unreachable!(),
+        }
+        let hover = declaration_hover(&ast, "kQuestion").unwrap();
+        match hover.contents {
+            lsp_types::HoverContents::Markup(content) => {
+                assert_eq!("```mojom\nconst uint32 kQuestion = 0;\n```", content.value)
+            }
+            _ => unreachable!(),
+        }
+
+        // Attributes aren't the header.
+        let value = |ident: &str| match declaration_hover(&ast, ident).unwrap().contents {
+            lsp_types::HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            "```mojom\nstruct MyStruct {\n```\n\nA stable struct.",
+            value("MyStruct")
+        );
+        assert_eq!("```mojom\nint32 my_field;\n```", value("MyStruct.my_field"));
+        assert_eq!("```mojom\nenum MyEnum { kValue };\n```", value("MyEnum"));
+
+        // Block comments and comments separated by a blank line.
+        let input = r#"
+        /**
+         * A block.
+         */
+        struct Block {};
+
+        // Separated.
+
+        struct Separated {};
+        "#;
+        let mojom = syntax::parse_with_comments(input).unwrap();
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = MojomAst::from_mojom(uri, input.to_owned(), mojom, None);
+        let value = |ident: &str| match declaration_hover(&ast, ident).unwrap().contents {
+            lsp_types::HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            "```mojom\nstruct Block {};\n```\n\nA block.",
+            value("Block")
+        );
+        assert_eq!("```mojom\nstruct Separated {};\n```", value("Separated"));
+    }
+}
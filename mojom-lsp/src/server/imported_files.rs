@@ -12,22 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 
-use lsp_types::{Location, Range, Url};
+use lsp_types::{Location, Range, SymbolKind, Url};
+use serde::Serialize;
 
+use crate::analysis;
 use crate::syntax::{self, preorder, Traversal};
 
-use super::definition::create_lsp_range;
+use super::diagnostic::into_lsp_diagnostic;
+use super::hover::declaration_markdown;
+use super::metrics::Metrics;
 use super::mojomast::MojomAst;
-use super::semantic;
+use super::textpos::{LineIndex, PositionEncoding};
+
+// Imports of imports are followed up to this depth. Directly imported files
+// have depth 1.
+const MAX_IMPORT_DEPTH: usize = 8;
+
+// The diagnostic source for errors found while parsing imported files.
+pub(crate) const IMPORT_SOURCE: &str = "mojom-lsp (import)";
 
 #[derive(Debug)]
 struct ImportDefinition {
     pub ident: String,
+    pub kind: SymbolKind,
     pub range: Range,
+    // Hover contents of the declaration.
+    pub hover: String,
+    // Whether the declaration has the `[Stable]` attribute.
+    pub stable: bool,
 }
 
 #[derive(Debug)]
@@ -35,13 +52,18 @@ struct Import {
     uri: Url,
     module_name: Option<String>,
     definitions: Vec<ImportDefinition>,
+    // Paths of import statements in the imported file.
+    imports: Vec<String>,
+    // Types that structs and unions hold by value, keyed by their
+    // identifiers.
+    contained_types: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug)]
 enum ImportError {
     IoError(std::io::Error),
     NotFound(String /* path */),
-    SyntaxError(String),
+    SyntaxError(Url, Vec<lsp_types::Diagnostic>),
 }
 
 impl From<std::io::Error> for ImportError {
@@ -55,56 +77,468 @@ impl From<std::io::Error> for ImportError {
 
 type ImportResult = std::result::Result<Import, ImportError>;
 
+// How the file of an import statement was loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ImportStatus {
+    Parsed,
+    NotFound,
+    ReadError,
+    SyntaxError,
+    // The file is the document itself or is imported by an earlier import
+    // statement.
+    Duplicate,
+}
+
+// Reads imported files. Tests replace this to observe file accesses.
+pub(crate) trait ImportLoader: Send + Sync {
+    fn modified(&self, path: &Path) -> std::io::Result<SystemTime>;
+    fn read(&self, path: &Path) -> std::io::Result<String>;
+}
+
+pub(crate) struct FileLoader;
+
+impl ImportLoader for FileLoader {
+    fn modified(&self, path: &Path) -> std::io::Result<SystemTime> {
+        std::fs::metadata(path)?.modified()
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+struct CacheEntry {
+    modified: SystemTime,
+    // Ranges of `result` are converted with this encoding.
+    encoding: PositionEncoding,
+    result: Arc<ImportResult>,
+}
+
+// Parsed imported files keyed by canonical path. A file is parsed again only
+// when its modification time changes.
+pub(crate) struct ImportCache {
+    loader: Arc<dyn ImportLoader>,
+    root_paths: Vec<PathBuf>,
+    entries: HashMap<PathBuf, CacheEntry>,
+    metrics: Metrics,
+}
+
+impl ImportCache {
+    pub(crate) fn new() -> Self {
+        ImportCache::with_loader(Box::new(FileLoader))
+    }
+
+    pub(crate) fn with_loader(loader: Box<dyn ImportLoader>) -> Self {
+        ImportCache {
+            loader: loader.into(),
+            root_paths: Vec::new(),
+            entries: HashMap::new(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    // Hits and misses are recorded in `metrics`.
+    // Lookups outside the cache, such as document links, resolve imports
+    // with the same loader.
+    pub(crate) fn loader(&self) -> Arc<dyn ImportLoader> {
+        self.loader.clone()
+    }
+
+    pub(crate) fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+
+    // Import paths are relative to one of the root paths. Clears the cache
+    // when the root paths change.
+    fn set_root_paths<P: AsRef<Path>>(&mut self, root_paths: &[P]) {
+        let root_paths = root_paths
+            .iter()
+            .map(|root_path| root_path.as_ref().to_owned())
+            .collect::<Vec<_>>();
+        if self.root_paths != root_paths {
+            self.entries.clear();
+            self.root_paths = root_paths;
+        }
+    }
+
+    fn get(&mut self, path: &Path, encoding: PositionEncoding) -> Arc<ImportResult> {
+        let modified = match self.loader.modified(path) {
+            Ok(modified) => modified,
+            Err(err) => return Arc::new(Err(err.into())),
+        };
+        if let Some(entry) = self.entries.get(path) {
+            if entry.modified == modified && entry.encoding == encoding {
+                self.metrics.record_import_cache(true);
+                return entry.result.clone();
+            }
+        }
+        self.metrics.record_import_cache(false);
+        let result = Arc::new(parse_imported(self.loader.as_ref(), path, encoding));
+        let entry = CacheEntry {
+            modified,
+            encoding,
+            result: result.clone(),
+        };
+        self.entries.insert(path.to_owned(), entry);
+        result
+    }
+
+    // Drops the parsed file at `path`. Modification times alone can miss
+    // changes made within their resolution.
+    pub(crate) fn invalidate(&mut self, path: &Path) {
+        self.entries.remove(path);
+    }
+}
+
+// A declaration in an imported file.
+pub(crate) struct ImportedSymbol<'a> {
+    pub uri: &'a Url,
+    pub module_name: Option<&'a str>,
+    pub ident: &'a str,
+    pub kind: SymbolKind,
+}
+
+// A parsed imported file and the import statement of the document that
+// brought it in.
+#[derive(Debug)]
+struct ParsedImport {
+    // The index of the import statement among the import statements of the
+    // document. Files imported by imported files inherit the index.
+    origin: usize,
+    result: Arc<ImportResult>,
+}
+
 #[derive(Debug)]
 pub(crate) struct ImportedFiles {
-    parsed_imports: Vec<ImportResult>,
+    // The module of the importing document. Unqualified names resolve only
+    // to files in the same module.
+    module_name: Option<String>,
+    parsed_imports: Vec<ParsedImport>,
+    // Paths of all imported files including missing ones. A missing file has
+    // the path under the first root path.
+    paths: Vec<PathBuf>,
 }
 
 impl ImportedFiles {
-    pub(crate) fn find_definition(&self, ident: &str) -> Option<Location> {
-        let valid_imports = self.parsed_imports.iter().filter_map(|i| i.as_ref().ok());
-        for imported in valid_imports {
-            for definition in &imported.definitions {
-                if definition.ident == ident {
-                    let loc = Location::new(imported.uri.clone(), definition.range.clone());
-                    return Some(loc);
+    fn valid_imports(&self) -> impl Iterator<Item = (usize, &Import)> {
+        self.parsed_imports
+            .iter()
+            .filter_map(|parsed| Some((parsed.origin, parsed.result.as_ref().as_ref().ok()?)))
+    }
+
+    // Returns true when `ident` refers to `definition` of `imported`. `ident`
+    // can be qualified with the module name of `imported` or a trailing part
+    // of it, e.g. `foo.bar.MyStruct` and `bar.MyStruct` for `module foo.bar;`.
+    fn matches(&self, imported: &Import, definition: &ImportDefinition, ident: &str) -> bool {
+        match imported.module_name.as_deref() {
+            Some(module_name) => match analysis::strip_module_qualifier(module_name, ident) {
+                Some(local_ident) => local_ident == definition.ident,
+                None => {
+                    ident == definition.ident && self.module_name.as_deref() == Some(module_name)
                 }
+            },
+            None => ident == definition.ident,
+        }
+    }
+
+    // Returns definitions that `ident` refers to, closer imports first.
+    fn find_all<'a: 'b, 'b>(
+        &'a self,
+        ident: &'b str,
+    ) -> impl Iterator<Item = (usize, &'a Import, &'a ImportDefinition)> + 'b {
+        self.valid_imports().flat_map(move |(origin, imported)| {
+            imported
+                .definitions
+                .iter()
+                .filter(move |definition| self.matches(imported, definition, ident))
+                .map(move |definition| (origin, imported, definition))
+        })
+    }
+
+    fn find(&self, ident: &str) -> Option<(usize, &Import, &ImportDefinition)> {
+        self.find_all(ident).next()
+    }
+
+    // Returns true when more than one imported file declares `ident`.
+    pub(crate) fn is_ambiguous(&self, ident: &str) -> bool {
+        let mut uris = self.find_all(ident).map(|(_, imported, _)| &imported.uri);
+        match uris.next() {
+            Some(first) => uris.any(|uri| uri != first),
+            None => false,
+        }
+    }
+
+    // Returns true when the file at `path` is imported directly or
+    // indirectly, or would be imported if it existed.
+    pub(crate) fn depends_on(&self, path: &Path) -> bool {
+        self.paths.iter().any(|imported| imported == path)
+    }
+
+    // Returns the index of the import statement that provides the definition
+    // of `ident`.
+    pub(crate) fn find_origin(&self, ident: &str) -> Option<usize> {
+        self.find(ident).map(|(origin, _, _)| origin)
+    }
+
+    // Returns true when the file imported by the `index`-th import statement
+    // was parsed successfully. Duplicated imports are never parsed.
+    pub(crate) fn is_parsed(&self, index: usize) -> bool {
+        self.parsed_imports
+            .iter()
+            .find(|parsed| parsed.origin == index)
+            .is_some_and(|parsed| parsed.result.is_ok())
+    }
+
+    // Returns how the file imported by the `index`-th import statement was
+    // loaded.
+    pub(crate) fn import_status(&self, index: usize) -> ImportStatus {
+        let parsed = match self
+            .parsed_imports
+            .iter()
+            .find(|parsed| parsed.origin == index)
+        {
+            Some(parsed) => parsed,
+            None => return ImportStatus::Duplicate,
+        };
+        match parsed.result.as_ref() {
+            Ok(_) => ImportStatus::Parsed,
+            Err(ImportError::NotFound(_)) => ImportStatus::NotFound,
+            Err(ImportError::IoError(_)) => ImportStatus::ReadError,
+            Err(ImportError::SyntaxError(..)) => ImportStatus::SyntaxError,
+        }
+    }
+
+    // Returns the number of files imported directly or indirectly, including
+    // missing ones.
+    pub(crate) fn file_count(&self) -> usize {
+        self.paths.len()
+    }
+
+    // Returns whether the declaration of `ident` is `[Stable]`. Returns None
+    // when `ident` isn't found.
+    pub(crate) fn is_stable(&self, ident: &str) -> Option<bool> {
+        self.find(ident).map(|(_, _, definition)| definition.stable)
+    }
+
+    pub(crate) fn find_definition(&self, ident: &str) -> Option<Location> {
+        self.find(ident)
+            .map(|(_, imported, definition)| Location::new(imported.uri.clone(), definition.range))
+    }
+
+    // Returns hover contents of the definition of `ident`.
+    pub(crate) fn find_hover(&self, ident: &str) -> Option<String> {
+        self.find(ident)
+            .map(|(_, _, definition)| definition.hover.clone())
+    }
 
-                if let Some(module_name) = &imported.module_name {
-                    let canonocal_name = format!("{}.{}", module_name, definition.ident);
-                    if canonocal_name == ident {
-                        let loc = Location::new(imported.uri.clone(), definition.range.clone());
-                        return Some(loc);
-                    }
+    // Returns the fully qualified name of the struct or union that `ident`
+    // refers to and the types that it holds by value.
+    pub(crate) fn find_contained_types(&self, ident: &str) -> Option<(String, Vec<String>)> {
+        let (_, imported, definition) = self.find(ident)?;
+        let contained = imported.contained_types.get(&definition.ident)?;
+        let name = match &imported.module_name {
+            Some(module_name) => format!("{}.{}", module_name, definition.ident),
+            None => definition.ident.clone(),
+        };
+        Some((name, contained.clone()))
+    }
+
+    // Returns the kind of the declaration of `ident` and the names of its
+    // direct members, e.g. values of an enum.
+    pub(crate) fn find_members(&self, ident: &str) -> Option<(SymbolKind, Vec<&str>)> {
+        let (_, imported, definition) = self.find(ident)?;
+        let prefix = format!("{}.", definition.ident);
+        let members = imported
+            .definitions
+            .iter()
+            .filter_map(|member| member.ident.strip_prefix(&prefix))
+            .filter(|name| !name.contains('.'))
+            .collect();
+        Some((definition.kind, members))
+    }
+
+    // Returns all declarations in successfully parsed imported files.
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = ImportedSymbol<'_>> {
+        self.valid_imports().flat_map(|(_, imported)| {
+            imported
+                .definitions
+                .iter()
+                .map(move |definition| ImportedSymbol {
+                    uri: &imported.uri,
+                    module_name: imported.module_name.as_deref(),
+                    ident: &definition.ident,
+                    kind: definition.kind,
+                })
+        })
+    }
+
+    // Returns diagnostics of successfully read imported files. Files without
+    // syntax errors have empty diagnostics so that old results can be cleared.
+    pub(crate) fn diagnostics(&self) -> impl Iterator<Item = (&Url, &[lsp_types::Diagnostic])> {
+        self.parsed_imports
+            .iter()
+            .filter_map(|parsed| match parsed.result.as_ref() {
+                Ok(imported) => Some((&imported.uri, &[][..])),
+                Err(ImportError::SyntaxError(uri, diagnostics)) => {
+                    Some((uri, diagnostics.as_slice()))
                 }
-            }
+                Err(_) => None,
+            })
+    }
+}
+
+// Returns paths of import statements in `mojom`.
+pub(crate) fn import_paths(text: &str, mojom: &syntax::MojomFile) -> Vec<String> {
+    let mut paths = Vec::new();
+    for stmt in &mojom.stmts {
+        if let syntax::Statement::Import(stmt) = stmt {
+            let path = &text[stmt.path.start..stmt.path.end];
+            paths.push(path[1..path.len() - 1].to_owned());
         }
-        None
     }
+    paths
+}
+
+// Returns the path of the file imported as `path`. Root paths are tried in
+// order and the first one that has the file wins. Falls back to the path
+// under the first root so that the missing file is reported.
+fn resolve_import<P: AsRef<Path>>(
+    root_paths: &[P],
+    path: &str,
+    loader: &dyn ImportLoader,
+) -> PathBuf {
+    let candidates = root_paths
+        .iter()
+        .map(|root_path| root_path.as_ref().join(path))
+        .collect::<Vec<_>>();
+    candidates
+        .iter()
+        .find(|candidate| loader.modified(candidate).is_ok())
+        .or_else(|| candidates.first())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(path))
 }
 
-pub(crate) fn check_imports<P: AsRef<Path>>(root_path: P, ast: &MojomAst) -> ImportedFiles {
-    let root_path = root_path.as_ref();
+// Returns the canonical path of the file imported as `path`, or None when the
+// file doesn't exist under any of the root paths.
+pub(crate) fn find_imported_file<P: AsRef<Path>>(
+    root_paths: &[P],
+    path: &str,
+    loader: &dyn ImportLoader,
+) -> Option<PathBuf> {
+    let path = resolve_import(root_paths, path, loader);
+    loader.modified(&path).ok()?;
+    path.canonicalize().ok()
+}
+
+// Returns the `gen` directory of a Chromium output directory under
+// `root_path`, e.g. `out/Default/gen`. Generated mojom files are imported
+// relative to it. The first one in name order is chosen when there are
+// multiple output directories.
+pub(crate) fn find_gen_path(root_path: &Path) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(root_path.join("out")).ok()?;
+    let mut gen_paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("gen"))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    gen_paths.sort();
+    gen_paths.into_iter().next()
+}
+
+// Returns the location of the file imported by the import statement at
+// `offset`. The location points at the module statement of the file if it
+// has one. Returns None when `offset` isn't on an import path or the file
+// doesn't exist.
+pub(crate) fn find_import_definition<P: AsRef<Path>>(
+    root_paths: &[P],
+    ast: &MojomAst,
+    offset: usize,
+    loader: &dyn ImportLoader,
+) -> Option<Location> {
+    let path = ast.mojom.stmts.iter().find_map(|stmt| match stmt {
+        syntax::Statement::Import(stmt) if stmt.path.start <= offset && offset < stmt.path.end => {
+            let path = ast.text(&stmt.path);
+            Some(path[1..path.len() - 1].to_owned())
+        }
+        _ => None,
+    })?;
+    let path = find_imported_file(root_paths, &path, loader)?;
+    let text = loader.read(&path).ok()?;
+    let uri = Url::from_file_path(&path).ok()?;
+
+    let mojom = syntax::parse(&text).ok();
+    let module = mojom
+        .as_ref()
+        .and_then(|mojom| analysis::find_module(&text, mojom, &mut Vec::new()));
+    let range = match (mojom, module) {
+        (Some(mojom), Some(module)) => {
+            let imported = MojomAst::new(uri.clone(), text, mojom, None, ast.encoding());
+            imported.lsp_range(&module.name)
+        }
+        _ => Range::default(),
+    };
+    Some(Location::new(uri, range))
+}
+
+// Parses files imported from `ast` and files imported from them. Files are
+// parsed in breadth-first order so that definitions in closer imports are
+// found first.
+pub(crate) fn check_imports<P: AsRef<Path>>(
+    root_paths: &[P],
+    ast: &MojomAst,
+    cache: &mut ImportCache,
+) -> ImportedFiles {
+    cache.set_root_paths(root_paths);
     let mut parsed_imports = Vec::new();
-    for stmt in &ast.mojom.stmts {
-        match stmt {
-            syntax::Statement::Import(stmt) => {
-                let path = ast.text(&stmt.path);
-                let path = root_path.join(&path[1..path.len() - 1]);
-                let imported = parse_imported(&path);
-                parsed_imports.push(imported);
+    let mut paths = Vec::new();
+
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    if let Ok(path) = ast.uri.to_file_path() {
+        visited.insert(path.canonicalize().unwrap_or(path));
+    }
+    let mut queue: VecDeque<(PathBuf, usize, usize)> = import_paths(&ast.text, &ast.mojom)
+        .iter()
+        .enumerate()
+        .map(|(origin, path)| {
+            let path = resolve_import(root_paths, path, cache.loader.as_ref());
+            (path, 1, origin)
+        })
+        .collect();
+    while let Some((path, depth, origin)) = queue.pop_front() {
+        let canonical_path = path.canonicalize().unwrap_or(path);
+        if !visited.insert(canonical_path.clone()) {
+            continue;
+        }
+        paths.push(canonical_path.clone());
+        let imported = cache.get(&canonical_path, ast.encoding());
+        if let Ok(imported) = imported.as_ref() {
+            if depth < MAX_IMPORT_DEPTH {
+                for path in &imported.imports {
+                    let path = resolve_import(root_paths, path, cache.loader.as_ref());
+                    queue.push_back((path, depth + 1, origin));
+                }
             }
-            _ => (),
         }
+        parsed_imports.push(ParsedImport {
+            origin,
+            result: imported,
+        });
     }
 
     ImportedFiles {
+        module_name: ast.module_name().map(|module_name| module_name.to_owned()),
         parsed_imports: parsed_imports,
+        paths,
     }
 }
 
 fn add_definition<'a, 'b, 'c>(
+    kind: SymbolKind,
     field: &'a syntax::Range,
+    declaration: &'a syntax::Range,
+    attributes: &'a Option<syntax::AttributeList>,
     ast: &'b MojomAst,
     path: &'c mut Vec<&'b str>,
     definitions: &'c mut Vec<ImportDefinition>,
@@ -113,33 +547,60 @@ fn add_definition<'a, 'b, 'c>(
     path.push(name);
     let ident = path.join(".");
     path.pop();
-    let range = create_lsp_range(&ast, field);
+    let range = ast.lsp_range(field);
     definitions.push(ImportDefinition {
         ident: ident,
+        kind,
         range: range,
+        hover: declaration_markdown(ast, declaration, field),
+        stable: attributes
+            .as_ref()
+            .is_some_and(|attributes| attributes.find(&ast.text, "Stable").is_some()),
     });
 }
 
-fn parse_imported<P: AsRef<Path>>(path: P) -> ImportResult {
-    let mut text = String::new();
-    File::open(path.as_ref())?.read_to_string(&mut text)?;
+fn parse_imported(
+    loader: &dyn ImportLoader,
+    path: &Path,
+    encoding: PositionEncoding,
+) -> ImportResult {
+    let text = loader.read(path)?;
 
-    let mojom = syntax::parse(&text).map_err(|err| ImportError::SyntaxError(err.to_string()))?;
-
-    // Unwrap shoud be safe because we opened file already.
-    let path = path.as_ref().canonicalize().unwrap();
+    // Unwrap shoud be safe because we read the file already.
+    let path = path.canonicalize().unwrap();
     let uri = Url::from_file_path(&path).unwrap();
 
+    // Comments are the documentation in hovers of definitions.
+    let mojom = match syntax::parse_with_comments(&text) {
+        Ok(mojom) => mojom,
+        Err(err) => {
+            let diagnostic = analysis::Diagnostic::syntax_error(&err);
+            let line_index = LineIndex::new(&text, encoding);
+            let mut diagnostic = into_lsp_diagnostic(&uri, &text, &line_index, &diagnostic);
+            diagnostic.source = Some(IMPORT_SOURCE.to_owned());
+            return Err(ImportError::SyntaxError(uri, vec![diagnostic]));
+        }
+    };
+
     // TODO: Maybe store semantics errors.
-    let analysis = semantic::check_semantics(&text, &mojom);
-    let ast = MojomAst::from_mojom(uri, text, mojom, analysis.module);
+    let module = analysis::find_module(&text, &mojom, &mut Vec::new());
+    let imports = import_paths(&text, &mojom);
+    let ast = MojomAst::new(uri, text, mojom, module, encoding);
 
     let mut path = Vec::new();
     let mut definitions: Vec<ImportDefinition> = Vec::new();
     for traversal in preorder(&ast.mojom) {
         match traversal {
             Traversal::EnterInterface(node) => {
-                add_definition(&node.name, &ast, &mut path, &mut definitions);
+                add_definition(
+                    SymbolKind::INTERFACE,
+                    &node.name,
+                    &node.range,
+                    &node.attributes,
+                    &ast,
+                    &mut path,
+                    &mut definitions,
+                );
                 let name = ast.text(&node.name);
                 path.push(name);
             }
@@ -147,26 +608,109 @@ fn parse_imported<P: AsRef<Path>>(path: P) -> ImportResult {
                 path.pop();
             }
             Traversal::EnterStruct(node) => {
-                add_definition(&node.name, &ast, &mut path, &mut definitions);
+                add_definition(
+                    SymbolKind::STRUCT,
+                    &node.name,
+                    &node.range,
+                    &node.attributes,
+                    &ast,
+                    &mut path,
+                    &mut definitions,
+                );
                 let name = ast.text(&node.name);
                 path.push(name);
             }
             Traversal::LeaveStruct(_) => {
                 path.pop();
             }
-            Traversal::Union(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
-            Traversal::Enum(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
-            Traversal::Const(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
+            Traversal::Union(node) => add_definition(
+                SymbolKind::STRUCT,
+                &node.name,
+                &node.range,
+                &node.attributes,
+                &ast,
+                &mut path,
+                &mut definitions,
+            ),
+            Traversal::Enum(node) => {
+                add_definition(
+                    SymbolKind::ENUM,
+                    &node.name,
+                    &node.range,
+                    &node.attributes,
+                    &ast,
+                    &mut path,
+                    &mut definitions,
+                );
+                path.push(ast.text(&node.name));
+            }
+            Traversal::LeaveEnum(_) => {
+                path.pop();
+            }
+            Traversal::Feature(node) => {
+                add_definition(
+                    SymbolKind::OBJECT,
+                    &node.name,
+                    &node.range,
+                    &node.attributes,
+                    &ast,
+                    &mut path,
+                    &mut definitions,
+                );
+                path.push(ast.text(&node.name));
+            }
+            Traversal::LeaveFeature(_) => {
+                path.pop();
+            }
+            Traversal::EnumValue(node) => add_definition(
+                SymbolKind::ENUM_MEMBER,
+                &node.name,
+                &node.range,
+                &node.attributes,
+                &ast,
+                &mut path,
+                &mut definitions,
+            ),
+            Traversal::Const(node) => add_definition(
+                SymbolKind::CONSTANT,
+                &node.name,
+                &node.range,
+                &node.attributes,
+                &ast,
+                &mut path,
+                &mut definitions,
+            ),
+            Traversal::Method(node) => add_definition(
+                SymbolKind::METHOD,
+                &node.name,
+                &node.range,
+                &node.attributes,
+                &ast,
+                &mut path,
+                &mut definitions,
+            ),
+            Traversal::StructField(node) => add_definition(
+                SymbolKind::FIELD,
+                &node.name,
+                &node.range,
+                &node.attributes,
+                &ast,
+                &mut path,
+                &mut definitions,
+            ),
             _ => (),
         }
     }
 
     let module_name = ast.module_name().map(|name| name.to_owned());
+    let contained_types = analysis::contained_types(&ast.text, &ast.mojom, ast.module_name());
 
     Ok(Import {
         uri: ast.uri.clone(),
         module_name: module_name,
         definitions: definitions,
+        imports,
+        contained_types,
     })
 }
 
@@ -174,6 +718,10 @@ fn parse_imported<P: AsRef<Path>>(path: P) -> ImportResult {
 mod tests {
     use super::*;
 
+    use std::fs::File;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     fn create_uri<P: AsRef<Path>>(path: P) -> Url {
         let path = path.as_ref().canonicalize().unwrap();
         Url::from_file_path(path).unwrap()
@@ -181,13 +729,53 @@ mod tests {
 
     #[test]
     fn test_parse_imported() {
-        let res = parse_imported("testdata/my_interface.mojom");
+        let res = parse_imported(
+            &FileLoader,
+            Path::new("testdata/my_interface.mojom"),
+            PositionEncoding::default(),
+        );
         assert!(res.is_ok());
     }
 
+    // Serves `text` for any path. Paths must exist because they are
+    // canonicalized.
+    struct TextLoader(&'static str);
+
+    impl ImportLoader for TextLoader {
+        fn modified(&self, _path: &Path) -> std::io::Result<SystemTime> {
+            Ok(SystemTime::UNIX_EPOCH)
+        }
+
+        fn read(&self, _path: &Path) -> std::io::Result<String> {
+            Ok(self.0.to_owned())
+        }
+    }
+
+    #[test]
+    fn test_parse_imported_enum_values() {
+        let text = r#"
+module foo;
+enum E { kA = 1, kB, [MinVersion=1] kC = 5, [Default] kD, };
+enum F { kX };
+"#;
+        let import = parse_imported(
+            &TextLoader(text),
+            Path::new("testdata/my_interface.mojom"),
+            PositionEncoding::default(),
+        )
+        .unwrap();
+        let values = import
+            .definitions
+            .iter()
+            .filter(|definition| definition.kind == SymbolKind::ENUM_MEMBER)
+            .map(|definition| definition.ident.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["E.kA", "E.kB", "E.kC", "E.kD", "F.kX"], values);
+    }
+
     #[test]
     fn test_check_imports() {
-        let root_path = "testdata";
+        let root_paths = ["testdata"];
         let file_path = "testdata/my_service.mojom";
         let mut text = String::new();
         File::open(&file_path)
@@ -196,12 +784,332 @@ mod tests {
             .unwrap();
         let uri = create_uri(&file_path);
         let mojom = syntax::parse(&text).unwrap();
-        let analytics = semantic::check_semantics(&text, &mojom);
-        let ast = MojomAst::from_mojom(uri, text, mojom, analytics.module);
+        let module = analysis::find_module(&text, &mojom, &mut Vec::new());
+        let ast = MojomAst::from_mojom(uri, text, mojom, module);
 
-        let imports = check_imports(&root_path, &ast);
+        let imports = check_imports(&root_paths, &ast, &mut ImportCache::new());
 
-        let res = imports.find_definition("FooStruct.FooEnum");
+        let res = imports.find_definition("foo.FooStruct.FooEnum");
         assert!(res.is_some());
+
+        // Names in other modules need to be qualified.
+        assert!(imports.find_definition("FooStruct.FooEnum").is_none());
+        let res = imports
+            .find_definition("foo.FooStruct.FooEnum.kTwo")
+            .unwrap();
+        assert_eq!(create_uri("testdata/foo_module/foo.mojom"), res.uri);
+        assert_eq!(lsp_types::Position::new(7, 25), res.range.start);
+
+        // Methods are qualified with their interface.
+        let res = imports.find_definition("MyInterface.GetService").unwrap();
+        assert_eq!(create_uri("testdata/my_interface.mojom"), res.uri);
+        assert_eq!(6, res.range.start.line);
+
+        // Defined in an import of an import.
+        let res = imports.find_definition("foo.BarStruct").unwrap();
+        assert_eq!(create_uri("testdata/foo_module/bar.mojom"), res.uri);
+
+        // Closer imports win.
+        let res = imports.find_definition("foo.kShared").unwrap();
+        assert_eq!(create_uri("testdata/foo_module/foo.mojom"), res.uri);
+        assert!(imports.is_ambiguous("foo.kShared"));
+        assert!(!imports.is_ambiguous("foo.BarStruct"));
+
+        // my_interface.mojom imports my_service.mojom but it isn't parsed
+        // again.
+        let uris = imports
+            .diagnostics()
+            .map(|(uri, _)| uri.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(3, uris.len());
+        assert!(!uris.contains(&create_uri(file_path)));
+
+        let canonical = |path: &str| Path::new(path).canonicalize().unwrap();
+        assert!(imports.depends_on(&canonical("testdata/foo_module/bar.mojom")));
+        assert!(imports.depends_on(&canonical("testdata/my_interface.mojom")));
+        assert!(!imports.depends_on(&canonical(file_path)));
+        assert!(!imports.depends_on(&canonical("testdata/broken.mojom")));
+    }
+
+    // Serves texts of files by path suffix. The files need to exist because
+    // imported files are canonicalized.
+    struct FilesLoader(Vec<(&'static str, &'static str)>);
+
+    impl FilesLoader {
+        fn find(&self, path: &Path) -> std::io::Result<&'static str> {
+            self.0
+                .iter()
+                .find(|(suffix, _)| path.ends_with(suffix))
+                .map(|&(_, text)| text)
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+    }
+
+    impl ImportLoader for FilesLoader {
+        fn modified(&self, path: &Path) -> std::io::Result<SystemTime> {
+            self.find(path).map(|_| SystemTime::UNIX_EPOCH)
+        }
+
+        fn read(&self, path: &Path) -> std::io::Result<String> {
+            self.find(path).map(|text| text.to_owned())
+        }
+    }
+
+    fn check_partial_qualification(module_name: &str) -> ImportedFiles {
+        let loader = FilesLoader(vec![
+            (
+                "my_interface.mojom",
+                r#"
+module foo.bar;
+struct MyStruct {};
+interface MyIface { enum MyEnum { kA }; };
+"#,
+            ),
+            (
+                "foo_module/bar.mojom",
+                r#"
+module chromium.other.bar;
+struct MyStruct {};
+struct Deep {};
+"#,
+            ),
+        ]);
+        let text = format!(
+            "module {};\nimport \"my_interface.mojom\";\nimport \"foo_module/bar.mojom\";",
+            module_name
+        );
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(&text).unwrap();
+        let module = analysis::find_module(&text, &mojom, &mut Vec::new());
+        let ast = MojomAst::from_mojom(uri, text, mojom, module);
+        let mut cache = ImportCache::with_loader(Box::new(loader));
+        check_imports(&["testdata"], &ast, &mut cache)
+    }
+
+    #[test]
+    fn test_partial_module_qualification() {
+        let first = create_uri("testdata/my_interface.mojom");
+        let second = create_uri("testdata/foo_module/bar.mojom");
+        let imports = check_partial_qualification("test");
+        let find_uri = |ident: &str| imports.find_definition(ident).map(|location| location.uri);
+
+        // Two-level module names.
+        assert_eq!(Some(first.clone()), find_uri("foo.bar.MyStruct"));
+        assert_eq!(Some(first.clone()), find_uri("bar.MyIface.MyEnum"));
+        assert_eq!(Some(first.clone()), find_uri("bar.MyIface.MyEnum.kA"));
+        assert!(!imports.is_ambiguous("foo.bar.MyStruct"));
+
+        // Three-level module names.
+        assert_eq!(Some(second.clone()), find_uri("chromium.other.bar.Deep"));
+        assert_eq!(Some(second.clone()), find_uri("other.bar.MyStruct"));
+        assert_eq!(Some(second.clone()), find_uri("bar.Deep"));
+        assert_eq!(None, find_uri("chromium.Deep"));
+
+        // Both modules end with `bar`. The first import wins.
+        assert_eq!(Some(first.clone()), find_uri("bar.MyStruct"));
+        assert!(imports.is_ambiguous("bar.MyStruct"));
+
+        // Unqualified names resolve only in the same module.
+        assert_eq!(None, find_uri("MyStruct"));
+        let imports = check_partial_qualification("foo.bar");
+        assert_eq!(
+            Some(first),
+            imports
+                .find_definition("MyStruct")
+                .map(|location| location.uri)
+        );
+        assert!(!imports.is_ambiguous("MyStruct"));
+    }
+
+    #[test]
+    fn test_cyclic_containment() {
+        let loader = FilesLoader(vec![(
+            "my_interface.mojom",
+            r#"
+module imported;
+import "my_service.mojom";
+struct Bar { Baz baz; Baz? nullable; };
+union Baz { int32 a; test.Foo foo; };
+"#,
+        )]);
+        let text =
+            "module test;\nimport \"my_interface.mojom\";\nstruct Foo { imported.Bar bar; };";
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(text).unwrap();
+        let module = analysis::find_module(text, &mojom, &mut Vec::new());
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom, module);
+        let mut cache = ImportCache::with_loader(Box::new(loader));
+        let imports = check_imports(&["testdata"], &ast, &mut cache);
+        assert_eq!(
+            Some(("imported.Bar".to_owned(), vec!["imported.Baz".to_owned()])),
+            imports.find_contained_types("imported.Bar")
+        );
+        assert_eq!(None, imports.find_contained_types("imported.Missing"));
+
+        let mut diagnostics = Vec::new();
+        analysis::check_cyclic_containment(
+            &ast.text,
+            &ast.mojom,
+            |name| imports.find_contained_types(name),
+            &mut diagnostics,
+        );
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "`Foo` contains itself by value: Foo → imported.Bar → imported.Baz → Foo. \
+             Make a field in the cycle nullable.",
+            diagnostics[0].message
+        );
+    }
+
+    #[test]
+    fn test_stable_imports() {
+        let file_path = "testdata/versioning/stable.mojom";
+        let text = std::fs::read_to_string(file_path).unwrap();
+        let uri = create_uri(file_path);
+        let mojom = syntax::parse(&text).unwrap();
+        let module = analysis::find_module(&text, &mojom, &mut Vec::new());
+        let ast = MojomAst::from_mojom(uri, text, mojom, module);
+        let imports = check_imports(&["testdata/versioning"], &ast, &mut ImportCache::new());
+
+        assert_eq!(
+            Some(true),
+            imports.is_stable("versioning.imported.StableType")
+        );
+        assert_eq!(Some(false), imports.is_stable("imported.UnstableType"));
+        assert_eq!(None, imports.is_stable("versioning.imported.Unknown"));
+
+        let mut diagnostics = Vec::new();
+        analysis::check_stable_imports(
+            &ast.text,
+            &ast.mojom,
+            |name| imports.is_stable(name),
+            &mut diagnostics,
+        );
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "versioning.imported.UnstableType",
+            ast.text(&diagnostics[0].range)
+        );
+    }
+
+    #[test]
+    fn test_import_diagnostics() {
+        let text = r#"
+        import "my_interface.mojom";
+        import "broken.mojom";
+        import "not_found.mojom";
+        "#
+        .to_owned();
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+
+        let imports = check_imports(&["testdata"], &ast, &mut ImportCache::new());
+        let diagnostics = imports.diagnostics().collect::<Vec<_>>();
+        assert_eq!(2, diagnostics.len());
+
+        assert_eq!(&create_uri("testdata/my_interface.mojom"), diagnostics[0].0);
+        assert!(diagnostics[0].1.is_empty());
+
+        assert_eq!(&create_uri("testdata/broken.mojom"), diagnostics[1].0);
+        assert_eq!(1, diagnostics[1].1.len());
+        let diagnostic = &diagnostics[1].1[0];
+        assert_eq!(Some(IMPORT_SOURCE), diagnostic.source.as_deref());
+        assert_eq!(2, diagnostic.range.start.line);
+    }
+
+    #[test]
+    fn test_find_imported_file() {
+        let root_paths = ["testdata", "testdata/foo_module"];
+        let expected = Path::new("testdata/foo_module/foo.mojom")
+            .canonicalize()
+            .unwrap();
+        assert_eq!(
+            Some(expected.clone()),
+            find_imported_file(&root_paths, "foo.mojom", &FileLoader)
+        );
+
+        // Files are looked up through the loader.
+        let loader = FilesLoader(vec![("foo_module/foo.mojom", "")]);
+        assert_eq!(
+            Some(expected),
+            find_imported_file(&root_paths, "foo.mojom", &loader)
+        );
+        let loader = FilesLoader(Vec::new());
+        assert_eq!(None, find_imported_file(&root_paths, "foo.mojom", &loader));
+    }
+
+    #[test]
+    fn test_find_gen_path() {
+        let root_path = Path::new("testdata/chromium/src");
+        let gen_path = root_path.join("out/Default/gen");
+        assert_eq!(Some(gen_path), find_gen_path(root_path));
+        assert_eq!(None, find_gen_path(Path::new("testdata")));
+    }
+
+    #[test]
+    fn test_check_imports_multiple_roots() {
+        let text = r#"
+        import "foo_module/foo.mojom";
+        import "bar.mojom";
+        "#
+        .to_owned();
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+
+        // `foo_module/foo.mojom` resolves only under the second root and
+        // `bar.mojom` only under the first root.
+        let root_paths = ["testdata/foo_module", "testdata"];
+        let imports = check_imports(&root_paths, &ast, &mut ImportCache::new());
+        let res = imports.find_definition("foo.FooStruct").unwrap();
+        assert_eq!(create_uri("testdata/foo_module/foo.mojom"), res.uri);
+        let res = imports.find_definition("foo.BarStruct").unwrap();
+        assert_eq!(create_uri("testdata/foo_module/bar.mojom"), res.uri);
+
+        // Neither import resolves with the first root only.
+        let imports = check_imports(&root_paths[..1], &ast, &mut ImportCache::new());
+        assert!(imports.find_definition("foo.FooStruct").is_none());
+    }
+
+    struct CountingLoader {
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl ImportLoader for CountingLoader {
+        fn modified(&self, path: &Path) -> std::io::Result<SystemTime> {
+            FileLoader.modified(path)
+        }
+
+        fn read(&self, path: &Path) -> std::io::Result<String> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            FileLoader.read(path)
+        }
+    }
+
+    #[test]
+    fn test_import_cache() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let loader = CountingLoader {
+            reads: reads.clone(),
+        };
+        let mut cache = ImportCache::with_loader(Box::new(loader));
+
+        let text = r#"import "foo_module/bar.mojom";"#.to_owned();
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text, mojom, None);
+
+        let imports = check_imports(&["testdata"], &ast, &mut cache);
+        assert!(imports.find_definition("foo.BarStruct").is_some());
+        assert_eq!(1, reads.load(Ordering::SeqCst));
+
+        let imports = check_imports(&["testdata"], &ast, &mut cache);
+        assert!(imports.find_definition("foo.BarStruct").is_some());
+        assert_eq!(1, reads.load(Ordering::SeqCst));
+
+        // Changing the root path clears the cache.
+        check_imports(&["testdata/foo_module/.."], &ast, &mut cache);
+        assert_eq!(2, reads.load(Ordering::SeqCst));
     }
 }
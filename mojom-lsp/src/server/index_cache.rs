@@ -0,0 +1,449 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Saves the workspace symbol index to a file so that files that haven't
+// changed since the last run aren't parsed again after a restart. A cache that
+// can't be read or was written by another version of the server is ignored
+// and the workspace is indexed from scratch.
+//
+// The cache is JSON because it's the only serde format the server depends on.
+// It's kept small by storing symbols as arrays without their URIs, which are
+// derived from the paths of their files.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use lsp_types::{Location, Range, SymbolInformation, SymbolKind, Url};
+use serde::{Deserialize, Serialize};
+
+use super::settings::CacheSettings;
+use super::textpos::PositionEncoding;
+
+// Bump this when the format of the cache changes.
+const FORMAT_VERSION: u32 = 4;
+
+// The directory in the workspace root to save the cache in when the OS cache
+// directory is unknown.
+const WORKSPACE_CACHE_DIR: &str = ".mojom-lsp-cache";
+
+/// Symbols and imports of an indexed file with the metadata to tell whether
+/// the file has changed since.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct IndexedFile {
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) size: u64,
+    // The hash of the contents. Modification times can be as coarse as a
+    // second, so an edit that keeps the size may keep the metadata too.
+    pub(crate) hash: Option<u64>,
+    pub(crate) module_name: Option<String>,
+    // Paths of import statements.
+    pub(crate) imports: Vec<String>,
+    pub(crate) symbols: Vec<SymbolInformation>,
+}
+
+impl IndexedFile {
+    /// Creates an entry without symbols for a file with `metadata`.
+    pub(crate) fn new(metadata: Option<&Metadata>) -> Self {
+        IndexedFile {
+            modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            size: metadata.map_or(0, |metadata| metadata.len()),
+            hash: None,
+            module_name: None,
+            imports: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Returns true when the file at `path` still has the modification time,
+    /// the size and the contents it had when it was indexed. The file is read
+    /// only when its metadata hasn't changed.
+    pub(crate) fn is_fresh(&self, path: &Path, metadata: &Metadata) -> bool {
+        self.modified.is_some()
+            && self.modified == metadata.modified().ok()
+            && self.size == metadata.len()
+            && self.hash.is_some()
+            && self.hash == std::fs::read(path).ok().map(|bytes| hash_bytes(&bytes))
+    }
+}
+
+// A symbol in the cache: the name, the kind, the container and the range as
+// `[start line, start character, end line, end character]`.
+type CachedSymbol = (String, SymbolKind, Option<String>, [u32; 4]);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedFile {
+    modified: Option<SystemTime>,
+    size: u64,
+    hash: Option<u64>,
+    module_name: Option<String>,
+    imports: Vec<String>,
+    symbols: Vec<CachedSymbol>,
+}
+
+impl CachedFile {
+    fn new(file: &IndexedFile) -> CachedFile {
+        let symbols = file
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let range = &symbol.location.range;
+                (
+                    symbol.name.clone(),
+                    symbol.kind,
+                    symbol.container_name.clone(),
+                    [
+                        range.start.line,
+                        range.start.character,
+                        range.end.line,
+                        range.end.character,
+                    ],
+                )
+            })
+            .collect();
+        CachedFile {
+            modified: file.modified,
+            size: file.size,
+            hash: file.hash,
+            module_name: file.module_name.clone(),
+            imports: file.imports.clone(),
+            symbols,
+        }
+    }
+
+    // Returns None when `path` can't be a URI.
+    #[allow(deprecated)]
+    fn into_indexed_file(self, path: &Path) -> Option<IndexedFile> {
+        let uri = Url::from_file_path(path).ok()?;
+        let symbols = self
+            .symbols
+            .into_iter()
+            .map(|(name, kind, container_name, range)| {
+                let range = Range::new(
+                    lsp_types::Position::new(range[0], range[1]),
+                    lsp_types::Position::new(range[2], range[3]),
+                );
+                SymbolInformation {
+                    name,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri.clone(), range),
+                    container_name,
+                }
+            })
+            .collect();
+        Some(IndexedFile {
+            modified: self.modified,
+            size: self.size,
+            hash: self.hash,
+            module_name: self.module_name,
+            imports: self.imports,
+            symbols,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexCache<'a> {
+    format_version: u32,
+    crate_version: Cow<'a, str>,
+    root_path: Cow<'a, Path>,
+    // Positions of symbols depend on the encoding.
+    encoding: PositionEncoding,
+    files: BTreeMap<PathBuf, CachedFile>,
+}
+
+fn crate_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+// Returns the directory where applications put caches on the OS.
+fn os_cache_dir() -> Option<PathBuf> {
+    let env_path = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    if cfg!(windows) {
+        env_path("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        env_path("XDG_CACHE_HOME")
+            .filter(|path| path.is_absolute())
+            .or_else(|| env_path("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+/// Hashes `bytes` with FNV-1a, which is stable across Rust versions unlike
+/// `DefaultHasher`.
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Returns the path of the cache of the index of `root_path`. The name of the
+/// file is derived from `root_path` so that workspaces can share a directory.
+/// Returns None when the cache is disabled or there is no workspace root.
+pub(crate) fn cache_path(settings: &CacheSettings, root_path: &Path) -> Option<PathBuf> {
+    if !settings.workspace_index || root_path.as_os_str().is_empty() {
+        return None;
+    }
+    let dir = match &settings.index_dir {
+        Some(dir) => root_path.join(dir),
+        None => os_cache_dir()
+            .map(|dir| dir.join("mojom-lsp"))
+            .unwrap_or_else(|| root_path.join(WORKSPACE_CACHE_DIR)),
+    };
+    Some(dir.join(format!(
+        "index-{:016x}.json",
+        hash_bytes(root_path.to_string_lossy().as_bytes())
+    )))
+}
+
+/// Reads indexed files of `root_path` from the cache at `path`. Returns None
+/// when there is no usable cache.
+pub(crate) fn load(
+    path: &Path,
+    root_path: &Path,
+    encoding: PositionEncoding,
+) -> Option<BTreeMap<PathBuf, IndexedFile>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+            }
+            return None;
+        }
+    };
+    let cache = match serde_json::from_slice::<IndexCache>(&bytes) {
+        Ok(cache) => cache,
+        Err(err) => {
+            log::warn!(
+                "Ignoring the broken index cache {}: {}",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    };
+    let is_compatible = cache.format_version == FORMAT_VERSION
+        && cache.crate_version == crate_version()
+        && cache.root_path == root_path
+        && cache.encoding == encoding;
+    if !is_compatible {
+        log::info!("Ignoring the outdated index cache {}", path.display());
+        return None;
+    }
+    let files = cache
+        .files
+        .into_iter()
+        .map(|(path, file)| {
+            let file = file.into_indexed_file(&path)?;
+            Some((path, file))
+        })
+        .collect::<Option<BTreeMap<_, _>>>();
+    if files.is_none() {
+        log::warn!("Ignoring the broken index cache {}", path.display());
+    }
+    files
+}
+
+/// Writes indexed files of `root_path` to the cache at `path`. The cache is
+/// written to a temporary file first so that readers never see a partial
+/// cache.
+pub(crate) fn save(
+    path: &Path,
+    root_path: &Path,
+    encoding: PositionEncoding,
+    files: &BTreeMap<PathBuf, IndexedFile>,
+) -> anyhow::Result<()> {
+    let cache = IndexCache {
+        format_version: FORMAT_VERSION,
+        crate_version: Cow::Borrowed(crate_version()),
+        root_path: Cow::Borrowed(root_path),
+        encoding,
+        files: files
+            .iter()
+            .map(|(path, file)| (path.clone(), CachedFile::new(file)))
+            .collect(),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp_path, serde_json::to_vec(&cache)?)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// The part of a cache that `prune` reads. Other fields are ignored.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheRoot {
+    root_path: PathBuf,
+}
+
+/// Removes caches in the directory of `path` whose workspace roots no longer
+/// exist. Caches of workspaces accumulate in the OS cache directory because
+/// each root has its own file.
+pub(crate) fn prune(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::warn!("Failed to read {}: {}", dir.display(), err);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let cache_path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if cache_path == path || !name.starts_with("index-") || !name.ends_with(".json") {
+            continue;
+        }
+        let root_path = std::fs::read(&cache_path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CacheRoot>(&bytes).ok())
+            .map(|cache| cache.root_path);
+        match root_path {
+            Some(root_path) if !root_path.exists() => (),
+            _ => continue,
+        }
+        log::info!("Removing the index cache of {}", cache_path.display());
+        if let Err(err) = std::fs::remove_file(&cache_path) {
+            log::warn!("Failed to remove {}: {}", cache_path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::test_util::TempDir;
+
+    fn create_file(name: &str) -> IndexedFile {
+        let uri = lsp_types::Url::parse("file:///src/a.mojom").unwrap();
+        #[allow(deprecated)]
+        let symbol = SymbolInformation {
+            name: name.to_owned(),
+            kind: lsp_types::SymbolKind::STRUCT,
+            tags: None,
+            deprecated: None,
+            location: lsp_types::Location::new(
+                uri,
+                Range::new(
+                    lsp_types::Position::new(1, 7),
+                    lsp_types::Position::new(1, 10),
+                ),
+            ),
+            container_name: Some("foo".to_owned()),
+        };
+        IndexedFile {
+            modified: Some(SystemTime::UNIX_EPOCH),
+            size: 1,
+            hash: Some(1),
+            module_name: Some("foo.mojom".to_owned()),
+            imports: vec!["b.mojom".to_owned()],
+            symbols: vec![symbol],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = TempDir::new("index");
+        let dir = temp_dir.path().to_owned();
+        let path = dir.join("index.json");
+        let root_path = Path::new("/src");
+        let encoding = PositionEncoding::Utf16;
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::from("/src/a.mojom"), create_file("Foo"));
+
+        save(&path, root_path, encoding, &files).unwrap();
+        assert_eq!(Some(&files), load(&path, root_path, encoding).as_ref());
+        // Caches of other roots and encodings are ignored.
+        assert_eq!(None, load(&path, Path::new("/other"), encoding));
+        assert_eq!(None, load(&path, root_path, PositionEncoding::Utf8));
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        // URIs of symbols aren't repeated.
+        assert!(!text.contains("file://"));
+        let outdated = text.replacen(
+            &format!("\"formatVersion\":{}", FORMAT_VERSION),
+            "\"formatVersion\":0",
+            1,
+        );
+        assert_ne!(text, outdated);
+        std::fs::write(&path, outdated).unwrap();
+        assert_eq!(None, load(&path, root_path, encoding));
+
+        std::fs::write(&path, &text[..text.len() / 2]).unwrap();
+        assert_eq!(None, load(&path, root_path, encoding));
+        assert_eq!(None, load(&dir.join("missing.json"), root_path, encoding));
+    }
+
+    #[test]
+    fn test_prune() {
+        let temp_dir = TempDir::new("prune");
+        let dir = temp_dir.path().to_owned();
+        let existing = dir.join("root");
+        std::fs::create_dir_all(&existing).unwrap();
+        let encoding = PositionEncoding::Utf16;
+        let files = BTreeMap::new();
+        let current = dir.join("index-0.json");
+        let other = dir.join("index-1.json");
+        let stale = dir.join("index-2.json");
+        let unrelated = dir.join("other.json");
+        save(&current, &dir.join("removed"), encoding, &files).unwrap();
+        save(&other, &existing, encoding, &files).unwrap();
+        save(&stale, &dir.join("removed"), encoding, &files).unwrap();
+        save(&unrelated, &dir.join("removed"), encoding, &files).unwrap();
+
+        prune(&current);
+        // The cache being written is kept even if its root is gone.
+        assert!(current.exists());
+        assert!(other.exists());
+        assert!(!stale.exists());
+        assert!(unrelated.exists());
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let root_path = Path::new("/src/chromium");
+        let mut settings = CacheSettings {
+            index_dir: Some(PathBuf::from(".mojom-lsp-cache")),
+            ..Default::default()
+        };
+        let path = cache_path(&settings, root_path).unwrap();
+        assert_eq!(
+            Path::new("/src/chromium/.mojom-lsp-cache"),
+            path.parent().unwrap()
+        );
+        // Roots have their own caches.
+        let other = cache_path(&settings, Path::new("/src/other")).unwrap();
+        assert_ne!(path.file_name(), other.file_name());
+
+        assert_eq!(None, cache_path(&settings, Path::new("")));
+        settings.workspace_index = false;
+        assert_eq!(None, cache_path(&settings, root_path));
+    }
+}
@@ -12,19 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{BufRead, Write};
-
 use anyhow::anyhow;
 
-use super::protocol::{read_message, write_success_result, Message};
+use super::command::COMMANDS;
+use super::connection::Connection;
+use super::on_type_formatting::TRIGGER_CHARACTERS;
+use super::protocol::{ErrorCodes, Message, NotificationMessage, ResponseError, ResponseMessage};
+use super::textpos::PositionEncoding;
 
 fn create_server_capabilities() -> lsp_types::ServerCapabilities {
     let options = lsp_types::TextDocumentSyncOptions {
         open_close: Some(true),
-        change: Some(lsp_types::TextDocumentSyncKind::FULL),
+        change: Some(lsp_types::TextDocumentSyncKind::INCREMENTAL),
         will_save: None,
         will_save_wait_until: None,
-        save: None,
+        // Saves re-check dependents of the document, which needs its text.
+        save: Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(
+            lsp_types::SaveOptions {
+                include_text: Some(true),
+            },
+        )),
     };
 
     let text_document_sync = lsp_types::TextDocumentSyncCapability::Options(options);
@@ -33,28 +40,69 @@ fn create_server_capabilities() -> lsp_types::ServerCapabilities {
     lsp_types::ServerCapabilities {
         text_document_sync: Some(text_document_sync),
         selection_range_provider: None,
-        hover_provider: None,
-        completion_provider: None,
-        signature_help_provider: None,
+        hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
+        completion_provider: Some(lsp_types::CompletionOptions {
+            trigger_characters: Some(vec!["<".to_owned(), " ".to_owned()]),
+            ..Default::default()
+        }),
+        signature_help_provider: Some(lsp_types::SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+            retrigger_characters: None,
+            work_done_progress_options: Default::default(),
+        }),
         definition_provider: Some(lsp_types::OneOf::Left(true)),
         type_definition_provider: None,
         implementation_provider: None,
-        references_provider: None,
-        document_highlight_provider: None,
-        document_symbol_provider: None,
-        workspace_symbol_provider: None,
-        code_action_provider: None,
-        code_lens_provider: None,
+        references_provider: Some(lsp_types::OneOf::Left(true)),
+        document_highlight_provider: Some(lsp_types::OneOf::Left(true)),
+        document_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+        workspace_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+        code_action_provider: Some(lsp_types::CodeActionProviderCapability::Options(
+            lsp_types::CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    lsp_types::CodeActionKind::QUICKFIX,
+                    lsp_types::CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                ]),
+                work_done_progress_options: Default::default(),
+                resolve_provider: None,
+            },
+        )),
+        code_lens_provider: Some(lsp_types::CodeLensOptions {
+            resolve_provider: Some(true),
+        }),
         document_formatting_provider: None,
-        document_range_formatting_provider: None,
-        document_on_type_formatting_provider: None,
-        rename_provider: None,
-        document_link_provider: None,
+        document_range_formatting_provider: Some(lsp_types::OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(lsp_types::DocumentOnTypeFormattingOptions {
+            first_trigger_character: TRIGGER_CHARACTERS[0].to_owned(),
+            more_trigger_character: Some(
+                TRIGGER_CHARACTERS[1..]
+                    .iter()
+                    .map(|&ch| ch.to_owned())
+                    .collect(),
+            ),
+        }),
+        rename_provider: Some(lsp_types::OneOf::Right(lsp_types::RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        document_link_provider: Some(lsp_types::DocumentLinkOptions {
+            resolve_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        }),
         color_provider: None,
-        folding_range_provider: None,
+        folding_range_provider: Some(lsp_types::FoldingRangeProviderCapability::Simple(true)),
         declaration_provider: Some(lsp_types::DeclarationCapability::Simple(true)),
-        execute_command_provider: None,
-        workspace: None,
+        execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+            commands: COMMANDS.iter().map(|&command| command.to_owned()).collect(),
+            work_done_progress_options: Default::default(),
+        }),
+        workspace: Some(lsp_types::WorkspaceServerCapabilities {
+            workspace_folders: Some(lsp_types::WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(lsp_types::OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
         experimental: None,
         call_hierarchy_provider: None,
         semantic_tokens_provider: None,
@@ -63,22 +111,33 @@ fn create_server_capabilities() -> lsp_types::ServerCapabilities {
     }
 }
 
+// Returns the parameters of `initialize`, the position encoding that is used
+// for the session and notifications that arrived before `initialized`.
 pub(crate) fn initialize(
-    reader: &mut impl BufRead,
-    writer: &mut impl Write,
-) -> anyhow::Result<lsp_types::InitializeParams> {
-    use lsp_types::notification::Notification;
+    connection: &Connection,
+) -> anyhow::Result<(
+    lsp_types::InitializeParams,
+    PositionEncoding,
+    Vec<NotificationMessage>,
+)> {
+    use lsp_types::notification::{Exit, Initialized, Notification};
     use lsp_types::request::Request;
 
-    let message = read_message(reader)?;
-    let (id, params) = match message {
+    let message = connection.receiver.recv()?;
+    let (id, params, encoding) = match message {
         Message::Request(req) => {
             if req.method != lsp_types::request::Initialize::METHOD {
                 let error_message = anyhow!("Expected initialize message but got {:?}", req.method);
                 return Err(error_message);
             }
+            // lsp_types doesn't know `positionEncodings` yet.
+            let encoding = req
+                .params
+                .get("capabilities")
+                .map(PositionEncoding::negotiate)
+                .unwrap_or_default();
             let params = serde_json::from_value::<lsp_types::InitializeParams>(req.params)?;
-            (req.id, params)
+            (req.id, params, encoding)
         }
         _ => {
             let error_message = anyhow!("Expected initialize message but got {:?}", message);
@@ -94,22 +153,48 @@ pub(crate) fn initialize(
             version: Some("0.1.0".to_string()),
         }),
     };
-    write_success_result(writer, id, res)?;
+    let mut res = serde_json::to_value(res)?;
+    res["capabilities"]["positionEncoding"] = encoding.as_str().into();
+    res["capabilities"]["inlayHintProvider"] = true.into();
+    res["capabilities"]["typeHierarchyProvider"] = true.into();
+    let response = ResponseMessage {
+        id,
+        result: Some(res),
+        error: None,
+    };
+    connection.sender.send(Message::Response(response))?;
 
-    let message = read_message(reader)?;
-    match message {
-        Message::Notofication(notif) => {
-            if notif.method != lsp_types::notification::Initialized::METHOD {
-                let error_message =
-                    anyhow!("Expected initialized message but got {:?}", notif.method);
-                return Err(error_message);
+    // Clients may send messages before `initialized`. Requests are answered
+    // with errors. Notifications are kept so that they are handled after
+    // initialization. `exit` stops waiting.
+    let mut notifications = Vec::new();
+    loop {
+        match connection.receiver.recv()? {
+            Message::Notification(notif) if notif.method == Initialized::METHOD => break,
+            Message::Notification(notif) => {
+                log::debug!("[recv] Notification before initialized: {}", notif.method);
+                let is_exit = notif.method == Exit::METHOD;
+                notifications.push(notif);
+                if is_exit {
+                    break;
+                }
             }
+            Message::Request(req) => {
+                log::warn!("Received {} before initialized", req.method);
+                let response = ResponseMessage {
+                    id: req.id,
+                    result: None,
+                    error: Some(ResponseError::new(
+                        ErrorCodes::ServerNotInitialized,
+                        "Server is not initialized".to_owned(),
+                    )),
+                };
+                connection.sender.send(Message::Response(response))?;
+            }
+            // The server hasn't sent requests yet.
+            Message::Response(res) => log::warn!("Received unexpected response: {}", res.id),
         }
-        _ => {
-            let error_message = anyhow!("Expected initialized message but got {:?}", message);
-            return Err(error_message);
-        }
-    };
+    }
 
-    Ok(params)
+    Ok((params, encoding, notifications))
 }
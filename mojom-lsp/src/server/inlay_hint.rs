@@ -0,0 +1,158 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// lsp_types doesn't know inlay hints yet. These are the parts of the protocol
+// that the server uses.
+
+use lsp_types::{Position, Range, TextDocumentIdentifier};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::effective_ordinals;
+use crate::syntax::{self, preorder, StructBody, Traversal};
+
+use super::mojomast::MojomAst;
+
+pub(crate) const INLAY_HINT_METHOD: &str = "textDocument/inlayHint";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InlayHintParams {
+    pub(crate) text_document: TextDocumentIdentifier,
+    pub(crate) range: Range,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InlayHint {
+    pub(crate) position: Position,
+    pub(crate) label: String,
+    pub(crate) padding_left: bool,
+}
+
+// Pushes hints of fields or parameters that don't have explicit ordinals.
+// `fields` are pairs of a name and an optional ordinal.
+fn push_ordinal_hints(
+    ast: &MojomAst,
+    fields: &[(&syntax::Range, Option<&syntax::Range>)],
+    range: &syntax::Range,
+    hints: &mut Vec<InlayHint>,
+) {
+    let ordinals = fields
+        .iter()
+        .map(|(_, ordinal)| *ordinal)
+        .collect::<Vec<_>>();
+    let values = effective_ordinals(&ast.text, &ordinals);
+    for ((name, ordinal), value) in fields.iter().zip(values) {
+        if ordinal.is_some() || name.end < range.start || range.end < name.end {
+            continue;
+        }
+        hints.push(InlayHint {
+            position: ast.line_index().position(&ast.text, name.end),
+            label: format!("@{}", value),
+            padding_left: true,
+        });
+    }
+}
+
+/// Returns hints of implicit ordinals of struct fields, union fields and
+/// method parameters in `range`. The hint is placed after the name.
+pub(crate) fn ordinal_hints(ast: &MojomAst, range: &Range) -> Vec<InlayHint> {
+    let range = ast.offset_range(range);
+    let mut hints = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::EnterStruct(node) => {
+                let fields = node
+                    .members
+                    .iter()
+                    .filter_map(|member| match member {
+                        StructBody::Field(field) => Some((&field.name, field.ordinal.as_ref())),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                push_ordinal_hints(ast, &fields, &range, &mut hints);
+            }
+            Traversal::Union(node) => {
+                let fields = node
+                    .fields
+                    .iter()
+                    .map(|field| (&field.name, field.ordinal.as_ref()))
+                    .collect::<Vec<_>>();
+                push_ordinal_hints(ast, &fields, &range, &mut hints);
+            }
+            Traversal::Method(node) => {
+                let param_lists = std::iter::once(&node.params)
+                    .chain(node.response.iter().map(|response| &response.params));
+                for params in param_lists {
+                    let params = params
+                        .iter()
+                        .map(|param| (&param.name, param.ordinal.as_ref()))
+                        .collect::<Vec<_>>();
+                    push_ordinal_hints(ast, &params, &range, &mut hints);
+                }
+            }
+            _ => (),
+        }
+    }
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    fn whole_range() -> Range {
+        Range::new(Position::new(0, 0), Position::new(u32::MAX, 0))
+    }
+
+    fn labels(hints: &[InlayHint]) -> Vec<&str> {
+        hints.iter().map(|hint| hint.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ordinal_hints() {
+        let input = r#"struct Foo {
+  int32 a;
+  int32 b@3;
+  const int32 kC = 1;
+  int32 c;
+  int32 d@0;
+  int32 e;
+};
+union Bar { int32 x; string y; };
+interface Baz {
+  Method(int32 p, int32 q@2, int32 r) => (bool s);
+};
+"#;
+        let ast = create_ast(input);
+        let hints = ordinal_hints(&ast, &whole_range());
+        assert_eq!(
+            vec!["@0", "@4", "@1", "@0", "@1", "@0", "@3", "@0"],
+            labels(&hints)
+        );
+        // After the name of `a`.
+        assert_eq!(Position::new(1, 9), hints[0].position);
+
+        // Only hints in the range.
+        let range = Range::new(Position::new(4, 0), Position::new(6, 0));
+        let hints = ordinal_hints(&ast, &range);
+        assert_eq!(vec!["@4"], labels(&hints));
+    }
+}
@@ -12,108 +12,238 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
-use std::sync::mpsc::{channel, Sender};
-use std::thread;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use lsp_types::TraceOption;
 use serde_json::Value;
 
-use super::protocol::{self, NotificationMessage, ResponseError};
+use super::protocol::{
+    Id, Message, NotificationMessage, RequestMessage, ResponseError, ResponseMessage,
+};
+use super::trace::Tracer;
 
-#[derive(Debug)]
-struct SuccessResponse {
-    id: u64,
-    result: Value,
+// Requests sent to the client are forgotten after this duration so that a
+// client that never responds doesn't leak them.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct PendingRequest {
+    sent_at: Instant,
+    sender: Sender<ResponseMessage>,
 }
 
-#[derive(Debug)]
-struct ErrorResponse {
-    id: u64,
-    err: ResponseError,
+// Requests sent to the client that are waiting for responses.
+#[derive(Default)]
+struct PendingRequests {
+    next_id: i64,
+    requests: HashMap<Id, PendingRequest>,
 }
 
-#[derive(Debug)]
-enum SendingMessage {
-    SuccessResponse(SuccessResponse),
-    ErrorResponse(ErrorResponse),
-    Notification(NotificationMessage),
+impl PendingRequests {
+    fn add(&mut self, sender: Sender<ResponseMessage>) -> Id {
+        let id = Id::Number(self.next_id);
+        self.next_id += 1;
+        let request = PendingRequest {
+            sent_at: Instant::now(),
+            sender,
+        };
+        self.requests.insert(id.clone(), request);
+        id
+    }
+
+    // Drops requests sent more than `timeout` ago. Receivers of dropped
+    // requests are disconnected.
+    fn remove_expired(&mut self, timeout: Duration) {
+        self.requests
+            .retain(|_, request| request.sent_at.elapsed() < timeout);
+    }
 }
 
+// Sends messages to the client through a connection. Requests keep track of
+// their responses. All messages are traced with `$/logTrace` when the client
+// enables trace.
 #[derive(Clone)]
 pub(crate) struct MessageSender {
-    sender: Sender<SendingMessage>,
+    sender: Sender<Message>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    tracer: Tracer,
 }
 
 impl MessageSender {
-    pub(crate) fn send_success_response(&self, id: u64, res: Value) {
+    pub(crate) fn new(sender: Sender<Message>) -> MessageSender {
+        MessageSender {
+            sender,
+            pending_requests: Default::default(),
+            tracer: Default::default(),
+        }
+    }
+
+    // Changes the trace setting of every clone.
+    pub(crate) fn set_trace(&self, value: TraceOption) {
+        self.tracer.set(value);
+    }
+
+    // Traces a message from the client. The main loop calls this before
+    // handling each message.
+    pub(crate) fn trace_received(&self, msg: &Message) {
+        if let Some(log) = self.tracer.received(msg) {
+            self.send_log_trace(log);
+        }
+    }
+
+    pub(crate) fn send_success_response(&self, id: Id, res: Value) {
         log::debug!("[send] Success: id = {}", id);
-        let msg = SendingMessage::SuccessResponse(SuccessResponse {
-            id: id,
-            result: res,
+        let msg = Message::Response(ResponseMessage {
+            id,
+            result: Some(res),
+            error: None,
         });
         self.send(msg);
     }
 
-    pub(crate) fn send_error_response(&self, id: u64, err: ResponseError) {
+    pub(crate) fn send_error_response(&self, id: Id, err: ResponseError) {
         log::debug!("[send] Error: message = '{}'", err.message);
-        let msg = SendingMessage::ErrorResponse(ErrorResponse { id: id, err: err });
+        let msg = Message::Response(ResponseMessage {
+            id,
+            result: None,
+            error: Some(err),
+        });
         self.send(msg);
     }
 
     pub(crate) fn send_notification(&self, notif: NotificationMessage) {
         log::debug!("[send] {}", notif.method);
-        let msg = SendingMessage::Notification(notif);
+        let msg = Message::Notification(notif);
         self.send(msg);
     }
 
-    fn send(&self, msg: SendingMessage) {
+    // Sends a request to the client. The returned receiver gets the response.
+    // It is disconnected when the client doesn't respond in time.
+    pub(crate) fn send_request(&self, method: &str, params: Value) -> Receiver<ResponseMessage> {
+        let (sender, receiver) = channel();
+        let id = {
+            let mut pending_requests = self.pending_requests.lock().unwrap();
+            pending_requests.remove_expired(REQUEST_TIMEOUT);
+            pending_requests.add(sender)
+        };
+        log::debug!("[send] Request: id = {}, method = {}", id, method);
+        let msg = Message::Request(RequestMessage {
+            id,
+            method: method.to_owned(),
+            params,
+        });
+        self.send(msg);
+        receiver
+    }
+
+    // Passes a response from the client to the sender of the request.
+    pub(crate) fn handle_response(&self, response: ResponseMessage) {
+        let mut pending_requests = self.pending_requests.lock().unwrap();
+        pending_requests.remove_expired(REQUEST_TIMEOUT);
+        match pending_requests.requests.remove(&response.id) {
+            // The receiver may be already dropped.
+            Some(request) => request.sender.send(response).unwrap_or(()),
+            None => log::warn!("Received a response to unknown request: {}", response.id),
+        }
+    }
+
+    fn send(&self, msg: Message) {
+        if let Some(log) = self.tracer.sending(&msg) {
+            self.send_log_trace(log);
+        }
         // TODO: Make sure using unwrap() makes sense.
         self.sender.send(msg).unwrap();
     }
+
+    // `$/logTrace` itself isn't traced.
+    fn send_log_trace(&self, log: NotificationMessage) {
+        self.sender.send(Message::Notification(log)).unwrap();
+    }
 }
 
+// Writes messages to `writer` on a separate thread. Tests use this to read
+// what the server sends.
+#[cfg(test)]
 pub(crate) struct MessageSenderThread {
-    sender: Sender<SendingMessage>,
-    handle: thread::JoinHandle<()>,
+    msg_sender: MessageSender,
 }
 
+#[cfg(test)]
 impl MessageSenderThread {
-    #[allow(unused)]
-    pub(crate) fn join(self) {
-        self.handle.join().unwrap();
-    }
-
     pub(crate) fn get_sender(&self) -> MessageSender {
-        MessageSender {
-            sender: self.sender.clone(),
-        }
+        self.msg_sender.clone()
     }
 }
 
-pub(crate) fn start_message_sender_thread<W: Write + Send + 'static>(
-    mut writer: W,
+#[cfg(test)]
+pub(crate) fn start_message_sender_thread<W: std::io::Write + Send + 'static>(
+    writer: W,
 ) -> MessageSenderThread {
     let (sender, receiver) = channel();
-    let handle = thread::spawn(move || loop {
-        let msg = receiver.recv();
-        // Terminate the thread when recv() failed.
-        let msg = if let Ok(msg) = msg { msg } else { break };
-
-        match msg {
-            SendingMessage::SuccessResponse(res) => {
-                protocol::write_success_response(&mut writer, res.id, res.result).unwrap();
-            }
-            SendingMessage::ErrorResponse(res) => {
-                protocol::write_error_response(&mut writer, res.id, res.err).unwrap();
-            }
-            SendingMessage::Notification(notif) => {
-                protocol::write_notification(&mut writer, &notif.method, notif.params).unwrap();
-            }
-        };
-    });
-
+    super::connection::start_writer_thread(writer, receiver);
     MessageSenderThread {
-        sender: sender,
-        handle: handle,
+        msg_sender: MessageSender::new(sender),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+
+    use super::super::protocol::{read_message, Message};
+
+    #[test]
+    fn test_send_request() {
+        let (reader, writer) = pipe::pipe();
+        let mut reader = BufReader::new(reader);
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let msg_sender = msg_sender_thread.get_sender();
+
+        let params = serde_json::json!({ "token": "indexing" });
+        let receiver = msg_sender.send_request("window/workDoneProgress/create", params);
+
+        // A fake client responds to the request.
+        let request = match read_message(&mut reader).unwrap() {
+            Message::Request(request) => request,
+            _ => unreachable!(),
+        };
+        assert_eq!("window/workDoneProgress/create", request.method);
+        let response = ResponseMessage {
+            id: request.id.clone(),
+            result: Some(Value::Null),
+            error: None,
+        };
+        msg_sender.handle_response(response);
+        assert_eq!(request.id, receiver.recv().unwrap().id);
+
+        // Ids aren't reused.
+        let receiver = msg_sender.send_request("workspace/applyEdit", Value::Null);
+        let request = match read_message(&mut reader).unwrap() {
+            Message::Request(request) => request,
+            _ => unreachable!(),
+        };
+        assert_eq!(Id::Number(1), request.id);
+
+        // The client never responds. Expired requests are dropped when any
+        // response arrives.
+        for request in msg_sender
+            .pending_requests
+            .lock()
+            .unwrap()
+            .requests
+            .values_mut()
+        {
+            request.sent_at = Instant::now() - REQUEST_TIMEOUT;
+        }
+        msg_sender.handle_response(ResponseMessage {
+            id: Id::Number(100),
+            result: Some(Value::Null),
+            error: None,
+        });
+        assert!(receiver.recv().is_err());
     }
 }
@@ -0,0 +1,192 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Counters and histograms that describe how busy the server is. They are
+// returned by `mojom/serverStatus`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+// Parses that take longer than this are logged as warnings.
+const SLOW_PARSE_THRESHOLD: Duration = Duration::from_millis(500);
+
+// Upper bounds of the buckets of parse durations in milliseconds. The last
+// bucket has no upper bound.
+const PARSE_BUCKETS_MS: &[u64] = &[1, 5, 10, 50, 100, 500, 1000];
+
+struct Histogram {
+    bounds: &'static [u64],
+    // One more than `bounds`.
+    counts: Vec<u64>,
+    total: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            total: Duration::default(),
+            max: Duration::default(),
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    fn record(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        let index = self.bounds.partition_point(|&bound| (bound as f64) < ms);
+        self.counts[index] += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    fn to_json(&self) -> Value {
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| json!({ "le": self.bounds.get(i), "count": count }))
+            .collect::<Vec<_>>();
+        json!({
+            "count": self.count(),
+            "totalMs": self.total.as_secs_f64() * 1000.0,
+            "maxMs": self.max.as_secs_f64() * 1000.0,
+            "buckets": buckets,
+        })
+    }
+}
+
+struct Registry {
+    // Requests and notifications from the client by method.
+    messages: BTreeMap<String, u64>,
+    parse_durations: Histogram,
+    import_cache_hits: u64,
+    import_cache_misses: u64,
+    documents_open: usize,
+}
+
+// Clones share the registry so that every thread updates the same metrics.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry {
+            messages: BTreeMap::new(),
+            parse_durations: Histogram::new(PARSE_BUCKETS_MS),
+            import_cache_hits: 0,
+            import_cache_misses: 0,
+            documents_open: 0,
+        };
+        Metrics {
+            registry: Arc::new(Mutex::new(registry)),
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn record_message(&self, method: &str) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry.messages.entry(method.to_owned()).or_default() += 1;
+    }
+
+    // Records how long it took to parse `name` and warns when it was slow.
+    pub(crate) fn record_parse(&self, name: &str, duration: Duration) {
+        if duration > SLOW_PARSE_THRESHOLD {
+            log::warn!("Parsing {} took {} ms", name, duration.as_millis());
+        }
+        let mut registry = self.registry.lock().unwrap();
+        registry.parse_durations.record(duration);
+    }
+
+    pub(crate) fn record_import_cache(&self, hit: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        if hit {
+            registry.import_cache_hits += 1;
+        } else {
+            registry.import_cache_misses += 1;
+        }
+    }
+
+    pub(crate) fn set_documents_open(&self, count: usize) {
+        self.registry.lock().unwrap().documents_open = count;
+    }
+
+    pub(crate) fn snapshot(&self) -> Value {
+        let registry = self.registry.lock().unwrap();
+        let lookups = registry.import_cache_hits + registry.import_cache_misses;
+        let hit_rate = match lookups {
+            0 => None,
+            lookups => Some(registry.import_cache_hits as f64 / lookups as f64),
+        };
+        json!({
+            "messages": registry.messages,
+            "parseDurations": registry.parse_durations.to_json(),
+            "importCache": {
+                "hits": registry.import_cache_hits,
+                "misses": registry.import_cache_misses,
+                "hitRate": hit_rate,
+            },
+            "documentsOpen": registry.documents_open,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::default();
+        let shared = metrics.clone();
+        metrics.record_message("textDocument/hover");
+        shared.record_message("textDocument/hover");
+        shared.record_parse("a.mojom", Duration::from_millis(3));
+        shared.record_parse("b.mojom", Duration::from_millis(2000));
+        shared.record_import_cache(true);
+        shared.record_import_cache(true);
+        shared.record_import_cache(false);
+        shared.record_import_cache(true);
+        shared.set_documents_open(2);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(json!({ "textDocument/hover": 2 }), snapshot["messages"]);
+        let parse_durations = &snapshot["parseDurations"];
+        assert_eq!(2, parse_durations["count"]);
+        assert_eq!(2000.0, parse_durations["maxMs"]);
+        assert_eq!(
+            json!({ "le": 5, "count": 1 }),
+            parse_durations["buckets"][1]
+        );
+        assert_eq!(
+            json!({ "le": null, "count": 1 }),
+            parse_durations["buckets"][7]
+        );
+        assert_eq!(0.75, snapshot["importCache"]["hitRate"]);
+        assert_eq!(2, snapshot["documentsOpen"]);
+
+        let snapshot = Metrics::default().snapshot();
+        assert_eq!(Value::Null, snapshot["importCache"]["hitRate"]);
+    }
+}
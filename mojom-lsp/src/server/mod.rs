@@ -12,14 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod analyze;
+mod cancellation;
+mod code_action;
+mod code_lens;
+mod command;
+mod completion;
+mod connection;
+mod conversion;
+mod custom;
 mod definition;
 mod diagnostic;
+mod document_link;
+mod document_symbol;
+mod folding_range;
+mod hover;
 mod imported_files;
+mod index_cache;
 mod initialization;
+mod inlay_hint;
 mod messagesender;
+mod metrics;
 mod mojomast;
+mod native_definition;
+mod on_type_formatting;
+mod progress;
 mod protocol;
-mod semantic;
+mod range_formatting;
+mod references;
+mod rename;
 mod server;
+mod settings;
+mod signature_help;
+#[cfg(test)]
+mod test_util;
+mod text_document;
+mod textpos;
+mod trace;
+mod type_hierarchy;
+mod workspace_graph;
+mod workspace_symbol;
 
-pub use server::start;
+pub use analyze::{analyze_file, FileAnalysis};
+pub use connection::{Connection, StreamHandle};
+pub use protocol::{
+    Id, Message, NotificationMessage, RequestMessage, ResponseError, ResponseMessage,
+};
+pub use server::{start, Server};
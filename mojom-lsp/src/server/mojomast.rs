@@ -14,6 +14,10 @@
 
 use crate::syntax::{self, Module, MojomFile};
 
+use super::conversion::{to_lsp_range, to_offset_range};
+use super::definition::SymbolTable;
+use super::textpos::{LineIndex, PositionEncoding};
+
 #[derive(Debug)]
 pub(crate) struct MojomAst {
     pub(crate) uri: lsp_types::Url,
@@ -21,31 +25,72 @@ pub(crate) struct MojomAst {
     pub(crate) mojom: MojomFile,
 
     module: Option<Module>,
+    line_index: LineIndex,
+    symbols: SymbolTable,
 }
 
 impl MojomAst {
-    pub(crate) fn from_mojom(
+    pub(crate) fn new(
         uri: lsp_types::Url,
         text: String,
         mojom: MojomFile,
         module: Option<Module>,
+        encoding: PositionEncoding,
     ) -> MojomAst {
+        let line_index = LineIndex::new(&text, encoding);
+        let symbols = SymbolTable::new(&text, &mojom);
         MojomAst {
             uri: uri,
             text: text,
             mojom: mojom,
             module: module,
+            line_index,
+            symbols,
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn from_mojom(
+        uri: lsp_types::Url,
+        text: String,
+        mojom: MojomFile,
+        module: Option<Module>,
+    ) -> MojomAst {
+        MojomAst::new(uri, text, mojom, module, PositionEncoding::default())
+    }
+
+    pub(crate) fn encoding(&self) -> PositionEncoding {
+        self.line_index.encoding()
+    }
+
+    pub(crate) fn line_index(&self) -> &LineIndex {
+        &self.line_index
+    }
+
+    pub(crate) fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
     pub(crate) fn text(&self, field: &syntax::Range) -> &str {
         // Can panic.
         &self.text[field.start..field.end]
     }
 
-    pub(crate) fn line_col(&self, offset: usize) -> syntax::LineCol {
-        // Can panic.
-        syntax::line_col(&self.text, offset).unwrap()
+    /// Returns the zero-based line of `offset`.
+    pub(crate) fn line(&self, offset: usize) -> usize {
+        self.line_index.line(offset)
+    }
+
+    pub(crate) fn lsp_range(&self, field: &syntax::Range) -> lsp_types::Range {
+        to_lsp_range(&self.line_index, &self.text, field)
+    }
+
+    pub(crate) fn offset_range(&self, range: &lsp_types::Range) -> syntax::Range {
+        to_offset_range(&self.line_index, &self.text, range)
+    }
+
+    pub(crate) fn offset(&self, pos: &lsp_types::Position) -> usize {
+        self.line_index.offset(&self.text, pos)
     }
 
     pub(crate) fn module_name(&self) -> Option<&str> {
@@ -0,0 +1,253 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// `[Native] struct MyStruct;` only declares the type in mojom. The definition
+// lives in C++. This is a best-effort search for the C++ definition in
+// headers that typemaps next to the mojom file refer to, and in headers in
+// the same directory.
+
+use std::path::{Path, PathBuf};
+
+use lsp_types::{Location, Url};
+
+use crate::syntax::{preorder, Traversal};
+
+use super::mojomast::MojomAst;
+use super::textpos::{LineIndex, PositionEncoding};
+
+/// Returns the name of the `[Native]` struct or enum that is declared at
+/// `declaration` in `ast`.
+pub(crate) fn native_type_name<'a>(ast: &'a MojomAst, declaration: &Location) -> Option<&'a str> {
+    if declaration.uri != ast.uri {
+        return None;
+    }
+    preorder(&ast.mojom).find_map(|traversal| {
+        let (attributes, name) = match traversal {
+            Traversal::EnterStruct(node) => (&node.attributes, &node.name),
+            Traversal::Enum(node) => (&node.attributes, &node.name),
+            _ => return None,
+        };
+        if ast.lsp_range(name) != declaration.range {
+            return None;
+        }
+        attributes.as_ref()?.find(&ast.text, "Native")?;
+        Some(ast.text(name))
+    })
+}
+
+fn is_identifier_char(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || ch == b'_'
+}
+
+// Returns quoted paths of headers in a typemap or BUILD.gn file. Source
+// absolute paths like `//foo/bar.h` are relative to `root_path`.
+fn typemap_headers(text: &str, dir: &Path, root_path: &Path) -> Vec<PathBuf> {
+    text.split('"')
+        .skip(1)
+        .step_by(2)
+        .filter(|path| path.ends_with(".h"))
+        .map(|path| match path.strip_prefix("//") {
+            Some(path) => root_path.join(path),
+            None => dir.join(path),
+        })
+        .collect()
+}
+
+// Returns headers to search in order: headers in typemaps next to
+// `mojom_path` and then headers in the same directory.
+fn candidate_headers(mojom_path: &Path, root_path: &Path) -> Vec<PathBuf> {
+    let dir = match mojom_path.parent() {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let mut files = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>(),
+        Err(_) => return Vec::new(),
+    };
+    files.sort();
+
+    let mut headers = Vec::new();
+    let typemaps = files.iter().filter(|path| {
+        path.extension().is_some_and(|ext| ext == "typemap")
+            || path.file_name().is_some_and(|name| name == "BUILD.gn")
+    });
+    for typemap in typemaps {
+        if let Ok(text) = std::fs::read_to_string(typemap) {
+            headers.extend(typemap_headers(&text, dir, root_path));
+        }
+    }
+    headers.extend(
+        files
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "h")),
+    );
+    let mut seen = Vec::new();
+    headers.retain(|path| {
+        let new = !seen.contains(path);
+        seen.push(path.clone());
+        new
+    });
+    headers
+}
+
+// Returns the offset where the identifier at the end of `text` starts.
+fn trailing_identifier_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .find(|&(_, ch)| !ch.is_ascii_alphanumeric() && ch != '_')
+        .map_or(0, |(pos, ch)| pos + ch.len_utf8())
+}
+
+// Returns the offset of `name` in a C++ definition like `class Foo {`,
+// `enum class Foo : int {` or `using Foo = ...;`. Forward declarations are
+// skipped. Export macros like `class COMPONENT_EXPORT(FOO) Foo` are allowed.
+fn find_cpp_definition(text: &str, name: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut from = 0;
+    while let Some(pos) = text[from..].find(name) {
+        let start = from + pos;
+        let end = start + name.len();
+        from = end;
+        let is_word = (start == 0 || !is_identifier_char(bytes[start - 1]))
+            && (end == bytes.len() || !is_identifier_char(bytes[end]));
+        if !is_word {
+            continue;
+        }
+
+        // The keyword before the name, skipping an export macro.
+        let mut before = text[..start].trim_end();
+        if before.ends_with(')') {
+            let open = match before.rfind('(') {
+                Some(open) => open,
+                None => continue,
+            };
+            before = before[..open].trim_end();
+            let macro_start = trailing_identifier_start(before);
+            before = before[..macro_start].trim_end();
+        }
+        let keyword_start = trailing_identifier_start(before);
+        let keyword = &before[keyword_start..];
+
+        let after = text[end..].trim_start();
+        let is_definition = match keyword {
+            "class" | "struct" | "enum" => !after.starts_with(';'),
+            "using" => after.starts_with('='),
+            _ => false,
+        };
+        if is_definition {
+            return Some(start);
+        }
+    }
+    None
+}
+
+/// Searches C++ headers for the definition of the `[Native]` type `name`
+/// that is declared in the mojom file at `mojom_path`.
+pub(crate) fn find_native_definition(
+    root_path: &Path,
+    mojom_path: &Path,
+    name: &str,
+    encoding: PositionEncoding,
+) -> Option<Location> {
+    candidate_headers(mojom_path, root_path)
+        .into_iter()
+        .find_map(|path| {
+            let text = std::fs::read_to_string(&path).ok()?;
+            let start = find_cpp_definition(&text, name)?;
+            let index = LineIndex::new(&text, encoding);
+            let range = lsp_types::Range::new(
+                index.position(&text, start),
+                index.position(&text, start + name.len()),
+            );
+            let uri = Url::from_file_path(path.canonicalize().unwrap_or(path)).ok()?;
+            Some(Location::new(uri, range))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::syntax;
+
+    #[test]
+    fn test_find_cpp_definition() {
+        let text = "class Foo;\nclass EXPORT(x) Foo : public Bar {\n";
+        assert_eq!(text.find("Foo :"), find_cpp_definition(text, "Foo"));
+        let text = "enum class Foo : int32_t { kOne };";
+        assert_eq!(Some(11), find_cpp_definition(text, "Foo"));
+        let text = "using Foo = base::flat_map<int, int>;";
+        assert_eq!(Some(6), find_cpp_definition(text, "Foo"));
+        // Forward declarations, other names and uses aren't definitions.
+        assert!(find_cpp_definition("struct Foo;", "Foo").is_none());
+        assert!(find_cpp_definition("class FooBar {};", "Foo").is_none());
+        assert!(find_cpp_definition("Foo foo;", "Foo").is_none());
+        assert!(find_cpp_definition("using Bar = Foo;", "Foo").is_none());
+        // Non-ASCII text right before a name or a macro.
+        assert!(find_cpp_definition("// é→Foo", "Foo").is_none());
+        assert!(find_cpp_definition("é→EXPORT(x) Foo {", "Foo").is_none());
+        let text = "/*→*/class Foo {";
+        assert_eq!(text.find("Foo"), find_cpp_definition(text, "Foo"));
+    }
+
+    #[test]
+    fn test_find_native_definition() {
+        let root_path = Path::new("testdata").canonicalize().unwrap();
+        let mojom_path = root_path.join("native/native.mojom");
+        let text = std::fs::read_to_string(&mojom_path).unwrap();
+        let uri = Url::from_file_path(&mojom_path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let ast = MojomAst::from_mojom(uri.clone(), text.clone(), mojom, None);
+
+        let declaration = |name: &str| {
+            let start = text.find(name).unwrap();
+            let range = crate::syntax::Range {
+                start,
+                end: start + name.len(),
+            };
+            Location::new(uri.clone(), ast.lsp_range(&range))
+        };
+        assert_eq!(
+            Some("NativeStruct"),
+            native_type_name(&ast, &declaration("NativeStruct"))
+        );
+        assert_eq!(
+            Some("NativeEnum"),
+            native_type_name(&ast, &declaration("NativeEnum"))
+        );
+        assert!(native_type_name(&ast, &declaration("MojomStruct")).is_none());
+
+        // The header in the typemap has the struct.
+        let location =
+            find_native_definition(&root_path, &mojom_path, "NativeStruct", Default::default())
+                .unwrap();
+        assert!(location.uri.path().ends_with("native/cpp/native_struct.h"));
+        assert_eq!(lsp_types::Position::new(8, 31), location.range.start);
+
+        // The header next to the mojom file has the enum.
+        let location =
+            find_native_definition(&root_path, &mojom_path, "NativeEnum", Default::default())
+                .unwrap();
+        assert!(location.uri.path().ends_with("native/native_enum.h"));
+        assert_eq!(lsp_types::Position::new(4, 11), location.range.start);
+
+        assert!(
+            find_native_definition(&root_path, &mojom_path, "Missing", Default::default())
+                .is_none()
+        );
+    }
+}
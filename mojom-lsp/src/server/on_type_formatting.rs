@@ -0,0 +1,228 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Re-indents the line where `}` or a newline is typed. The text usually
+// doesn't parse while typing, so the enclosing block is found by matching
+// braces when parsing fails.
+
+use lsp_types::{FormattingOptions, Position, Range, TextEdit};
+
+use crate::syntax::{self, preorder, Traversal};
+
+use super::completion::mask_comments_and_strings;
+use super::textpos::LineIndex;
+
+pub(crate) const TRIGGER_CHARACTERS: &[&str] = &["}", "\n"];
+
+// Returns the offset of the `{` of the innermost body that contains the
+// line starting at `line_start`, using the syntax tree. None when `text`
+// doesn't parse.
+fn enclosing_body(text: &str, line_start: usize) -> Option<Option<usize>> {
+    let mojom = syntax::parse(text).ok()?;
+    let mut opener = None;
+    for traversal in preorder(&mojom) {
+        let body = match traversal {
+            Traversal::EnterInterface(node) => Some(&node.body),
+            Traversal::EnterStruct(node) => node.body.as_ref(),
+            Traversal::Union(node) => Some(&node.body),
+            Traversal::Enum(node) => node.body.as_ref(),
+            Traversal::Feature(node) => Some(&node.body),
+            _ => None,
+        };
+        if let Some(body) = body {
+            // Preorder visits inner bodies after outer ones.
+            if body.start < line_start && line_start < body.end {
+                opener = Some(body.start);
+            }
+        }
+    }
+    Some(opener)
+}
+
+// Returns the offset of the innermost `{` that is unclosed at `end`. Only
+// braces are matched because `<` and `>` also appear in `=>`.
+fn enclosing_brace(text: &[u8], end: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &ch) in text[..end].iter().enumerate().rev() {
+        match ch {
+            b'}' => depth += 1,
+            b'{' if depth == 0 => return Some(i),
+            b'{' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+pub(crate) fn indentation(line: &str) -> &str {
+    let end = line
+        .find(|ch: char| ch != ' ' && ch != '\t')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+// One level of indentation.
+pub(crate) fn indent_unit(options: &FormattingOptions) -> String {
+    if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_owned()
+    }
+}
+
+fn line_at(text: &str, offset: usize) -> &str {
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+    &text[start..end]
+}
+
+/// Returns edits that re-indent the line at `pos` after `ch` is typed.
+pub(crate) fn on_type_formatting(
+    text: &str,
+    line_index: &LineIndex,
+    pos: Position,
+    ch: &str,
+    options: &FormattingOptions,
+) -> Vec<TextEdit> {
+    let line_start = line_index.offset(text, &Position::new(pos.line, 0));
+    let line = line_at(text, line_start);
+    let current = indentation(line);
+    let closes = line[current.len()..].starts_with('}');
+    // `}` in the middle of a line is left alone.
+    if ch == "}" && !closes {
+        return Vec::new();
+    }
+
+    let opener = match enclosing_body(text, line_start) {
+        Some(opener) => opener,
+        None => {
+            let (masked, _) = mask_comments_and_strings(text);
+            enclosing_brace(&masked, line_start)
+        }
+    };
+    let mut expected = match opener {
+        Some(opener) => indentation(line_at(text, opener)).to_owned(),
+        None => String::new(),
+    };
+    if opener.is_some() && !closes {
+        expected.push_str(&indent_unit(options));
+    }
+    if expected == current {
+        return Vec::new();
+    }
+
+    let start = line_index.position(text, line_start);
+    let end = line_index.position(text, line_start + current.len());
+    vec![TextEdit::new(Range::new(start, end), expected)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::textpos::PositionEncoding;
+
+    fn create_options(tab_size: u32, insert_spaces: bool) -> FormattingOptions {
+        FormattingOptions {
+            tab_size,
+            insert_spaces,
+            ..Default::default()
+        }
+    }
+
+    fn format(text: &str, pos: Position, ch: &str, options: &FormattingOptions) -> Vec<TextEdit> {
+        let line_index = LineIndex::new(text, PositionEncoding::Utf16);
+        on_type_formatting(text, &line_index, pos, ch, options)
+    }
+
+    fn edit(line: u32, end: u32, new_text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(line, 0), Position::new(line, end)),
+            new_text.to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_interface_in_file() {
+        let text = r#"module test;
+
+interface Foo {
+  Bar();
+
+};
+"#;
+        let options = create_options(2, true);
+        assert_eq!(
+            vec![edit(4, 0, "  ")],
+            format(text, Position::new(4, 0), "\n", &options)
+        );
+        // Top-level lines aren't indented.
+        let text = "module test;\n  \ninterface Foo {};\n";
+        assert_eq!(
+            vec![edit(1, 2, "")],
+            format(text, Position::new(1, 0), "\n", &options)
+        );
+
+        // The body doesn't parse while the method is typed.
+        let text = "interface Foo {\n  Bar(int32\n\n};\n";
+        assert_eq!(
+            vec![edit(2, 0, "    ")],
+            format(text, Position::new(2, 0), "\n", &create_options(4, true))
+        );
+        assert_eq!(
+            vec![edit(2, 0, "\t")],
+            format(text, Position::new(2, 0), "\n", &create_options(4, false))
+        );
+    }
+
+    #[test]
+    fn test_enum_in_struct() {
+        let text = r#"struct Foo {
+  enum Bar {
+    kA,
+
+  };
+};
+"#;
+        let options = create_options(2, true);
+        assert_eq!(
+            vec![edit(3, 0, "    ")],
+            format(text, Position::new(3, 0), "\n", &options)
+        );
+        // Already indented.
+        let text = text.replace("kA,\n\n", "kA,\n    \n");
+        assert!(format(&text, Position::new(3, 4), "\n", &options).is_empty());
+
+        // `}` is aligned with the line of the opening brace.
+        let text = "struct Foo {\n  enum Bar {\n    kA,\n    }\n";
+        assert_eq!(
+            vec![edit(3, 4, "  ")],
+            format(text, Position::new(3, 5), "}", &options)
+        );
+        let text = "struct Foo {\n  enum Bar {\n    kA,\n  };\n    }\n";
+        assert_eq!(
+            vec![edit(4, 4, "")],
+            format(text, Position::new(4, 5), "}", &options)
+        );
+        // Braces in comments are ignored.
+        let text = "struct Foo {\n  // {\n    }\n";
+        assert_eq!(
+            vec![edit(2, 4, "")],
+            format(text, Position::new(2, 5), "}", &options)
+        );
+        // `}` after other tokens is left alone.
+        let text = "struct Foo {\n  enum Bar { kA }\n";
+        assert!(format(text, Position::new(1, 17), "}", &options).is_empty());
+    }
+}
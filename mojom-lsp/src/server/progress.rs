@@ -0,0 +1,310 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+
+use super::messagesender::MessageSender;
+use super::protocol::{NotificationMessage, ResponseMessage};
+
+// Used to make unique progress tokens.
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts progress reports for long operations. Reports are sent only when
+/// the client supports `window.workDoneProgress`.
+#[derive(Clone)]
+pub(crate) struct ProgressReporter {
+    msg_sender: Option<MessageSender>,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(
+        msg_sender: MessageSender,
+        capabilities: &lsp_types::ClientCapabilities,
+    ) -> Self {
+        let supported = capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        ProgressReporter {
+            msg_sender: if supported { Some(msg_sender) } else { None },
+        }
+    }
+
+    /// A reporter that sends nothing.
+    #[allow(unused)]
+    pub(crate) fn disabled() -> Self {
+        ProgressReporter { msg_sender: None }
+    }
+
+    /// Creates a progress token. `total` is the number of items to process.
+    /// This doesn't wait for the client to accept the token. `begin` is sent
+    /// once it does, and reports until then are dropped.
+    pub(crate) fn begin(&self, title: &str, total: usize) -> Progress {
+        let client = self.msg_sender.as_ref().map(|msg_sender| {
+            let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+            let token = NumberOrString::String(format!("mojom-lsp/progress/{}", token));
+            let receiver = create_token(msg_sender, &token);
+            ProgressClient {
+                msg_sender: msg_sender.clone(),
+                token,
+                state: TokenState::Pending(receiver),
+            }
+        });
+        Progress {
+            client,
+            title: title.to_owned(),
+            total,
+            percentage: 0,
+        }
+    }
+}
+
+// Sends `window/workDoneProgress/create`. The receiver gets the response.
+fn create_token(msg_sender: &MessageSender, token: &NumberOrString) -> Receiver<ResponseMessage> {
+    use lsp_types::request::WorkDoneProgressCreate;
+
+    let params = lsp_types::WorkDoneProgressCreateParams {
+        token: token.clone(),
+    };
+    let params = serde_json::to_value(params).unwrap();
+    msg_sender.send_request(WorkDoneProgressCreate::METHOD, params)
+}
+
+enum TokenState {
+    // Waiting for the response to `window/workDoneProgress/create`.
+    Pending(Receiver<ResponseMessage>),
+    Accepted,
+    // The client rejected the token or didn't respond in time.
+    Rejected,
+}
+
+struct ProgressClient {
+    msg_sender: MessageSender,
+    token: NumberOrString,
+    state: TokenState,
+}
+
+impl ProgressClient {
+    fn send(&self, value: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        };
+        self.msg_sender.send_notification(NotificationMessage {
+            method: lsp_types::notification::Progress::METHOD.to_owned(),
+            params: serde_json::to_value(params).unwrap(),
+        });
+    }
+}
+
+/// An ongoing progress. `end` is sent when dropped.
+pub(crate) struct Progress {
+    // None when the client doesn't support progress.
+    client: Option<ProgressClient>,
+    title: String,
+    total: usize,
+    // The last reported percentage.
+    percentage: u32,
+}
+
+impl Progress {
+    /// Reports that `done` items out of `total` are processed. Reports are
+    /// sent only when the percentage changes.
+    pub(crate) fn report(&mut self, done: usize) {
+        let percentage = match self.total {
+            0 => 100,
+            total => (done.min(total) * 100 / total) as u32,
+        };
+        if percentage == self.percentage {
+            return;
+        }
+        let message = format!("{}/{}", done, self.total);
+        let client = match self.accepted_client() {
+            Some(client) => client,
+            None => return,
+        };
+        client.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message: Some(message),
+            percentage: Some(percentage),
+        }));
+        self.percentage = percentage;
+    }
+
+    // Returns the client when it has accepted the token. Sends `begin` when
+    // the response has just arrived.
+    fn accepted_client(&mut self) -> Option<&ProgressClient> {
+        let client = self.client.as_mut()?;
+        if let TokenState::Pending(receiver) = &client.state {
+            client.state = match receiver.try_recv() {
+                Ok(response) if response.error.is_none() => TokenState::Accepted,
+                Err(TryRecvError::Empty) => return None,
+                _ => {
+                    log::warn!("Failed to create a progress token");
+                    TokenState::Rejected
+                }
+            };
+            if let TokenState::Accepted = client.state {
+                client.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: self.title.clone(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(self.percentage),
+                }));
+            }
+        }
+        match client.state {
+            TokenState::Accepted => Some(client),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(client) = self.accepted_client() {
+            client.send(WorkDoneProgress::End(WorkDoneProgressEnd { message: None }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+
+    use super::super::messagesender::start_message_sender_thread;
+    use super::super::protocol::{read_message, Message, RequestMessage, ResponseError};
+
+    fn read_progress(reader: &mut impl std::io::BufRead) -> WorkDoneProgress {
+        match read_message(reader).unwrap() {
+            Message::Notification(msg) => {
+                assert_eq!(lsp_types::notification::Progress::METHOD, msg.method);
+                let params = serde_json::from_value::<ProgressParams>(msg.params).unwrap();
+                let ProgressParamsValue::WorkDone(value) = params.value;
+                value
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_request(reader: &mut impl std::io::BufRead) -> RequestMessage {
+        match read_message(reader).unwrap() {
+            Message::Request(request) => request,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let (reader, writer) = pipe::pipe();
+        let mut reader = BufReader::new(reader);
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let msg_sender = msg_sender_thread.get_sender();
+        let capabilities = serde_json::json!({ "window": { "workDoneProgress": true } });
+        let capabilities = serde_json::from_value(capabilities).unwrap();
+        let reporter = ProgressReporter::new(msg_sender.clone(), &capabilities);
+
+        // Simulates indexing 4 files. `begin` doesn't wait for the client,
+        // and the report before the client accepts the token is dropped.
+        let mut progress = reporter.begin("Indexing", 4);
+        progress.report(1);
+
+        // The fake client accepts the token.
+        let request = read_request(&mut reader);
+        assert_eq!("window/workDoneProgress/create", request.method);
+        msg_sender.handle_response(ResponseMessage {
+            id: request.id,
+            result: Some(serde_json::Value::Null),
+            error: None,
+        });
+        for done in 2..=4 {
+            progress.report(done);
+        }
+        drop(progress);
+
+        match read_progress(&mut reader) {
+            WorkDoneProgress::Begin(begin) => assert_eq!("Indexing", begin.title),
+            _ => unreachable!(),
+        }
+        let mut percentages = Vec::new();
+        loop {
+            match read_progress(&mut reader) {
+                WorkDoneProgress::Report(report) => percentages.push(report.percentage.unwrap()),
+                WorkDoneProgress::End(_) => break,
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(vec![50, 75, 100], percentages);
+    }
+
+    #[test]
+    fn test_progress_rejected() {
+        let (reader, writer) = pipe::pipe();
+        let mut reader = BufReader::new(reader);
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let msg_sender = msg_sender_thread.get_sender();
+        let capabilities = serde_json::json!({ "window": { "workDoneProgress": true } });
+        let capabilities = serde_json::from_value(capabilities).unwrap();
+        let reporter = ProgressReporter::new(msg_sender.clone(), &capabilities);
+
+        let mut progress = reporter.begin("Indexing", 2);
+        let request = read_request(&mut reader);
+        msg_sender.handle_response(ResponseMessage {
+            id: request.id,
+            result: None,
+            error: Some(ResponseError {
+                code: -32603,
+                message: "Rejected".to_owned(),
+                data: None,
+            }),
+        });
+        progress.report(1);
+        progress.report(2);
+        drop(progress);
+
+        // Nothing is sent after the request.
+        drop(reporter);
+        drop(msg_sender);
+        drop(msg_sender_thread);
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_progress_unsupported() {
+        let (reader, writer) = pipe::pipe();
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let capabilities = lsp_types::ClientCapabilities::default();
+        let reporter = ProgressReporter::new(msg_sender_thread.get_sender(), &capabilities);
+        let mut progress = reporter.begin("Indexing", 1);
+        progress.report(1);
+        drop(progress);
+
+        // Nothing is sent.
+        drop(reporter);
+        drop(msg_sender_thread);
+        let mut reader = BufReader::new(reader);
+        assert!(read_message(&mut reader).is_err());
+    }
+}
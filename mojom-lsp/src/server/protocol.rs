@@ -12,36 +12,70 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 
-use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_slice, Value};
 
+/// A request id. JSON-RPC allows both numbers and strings. Errors of messages
+/// that couldn't be parsed are sent with a null id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+    Null,
+}
+
+impl From<lsp_types::NumberOrString> for Id {
+    fn from(id: lsp_types::NumberOrString) -> Self {
+        match id {
+            lsp_types::NumberOrString::Number(id) => Id::Number(id.into()),
+            lsp_types::NumberOrString::String(id) => Id::String(id),
+        }
+    }
+}
+
+impl std::fmt::Display for Id {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Id::Number(id) => write!(f, "{}", id),
+            Id::String(id) => write!(f, "{:?}", id),
+            Id::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// A JSON-RPC message. Requests and responses are distinguished from
+/// notifications by `id`.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
-pub(crate) enum Message {
+pub enum Message {
     Request(RequestMessage),
     Response(ResponseMessage),
-    Notofication(NotificationMessage),
+    Notification(NotificationMessage),
 }
 
-impl Message {
-    fn from_slice(buf: &[u8]) -> serde_json::Result<Message> {
-        from_slice::<Message>(buf)
-    }
+// The content of a message. JSON-RPC allows sending an array of messages at
+// once. `Batch` comes first because a struct can be deserialized from an
+// array.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Batch(Vec<Message>),
+    Single(Message),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct RequestMessage {
-    pub id: u64,
+pub struct RequestMessage {
+    pub id: Id,
     pub method: String,
     pub params: Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct ResponseMessage {
-    pub id: u64,
+pub struct ResponseMessage {
+    pub id: Id,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -51,15 +85,14 @@ pub(crate) struct ResponseMessage {
 #[derive(Serialize)]
 pub(crate) struct JsonRpcRequestMessage<'a> {
     jsonrpc: &'a str,
-    id: u64,
+    id: Id,
     method: &'a str,
     params: Value,
 }
 
-#[allow(unused)]
 pub(crate) fn write_request(
     writer: &mut impl Write,
-    id: u64,
+    id: Id,
     method: &str,
     params: Value,
 ) -> anyhow::Result<()> {
@@ -73,7 +106,7 @@ pub(crate) fn write_request(
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct ResponseError {
+pub struct ResponseError {
     pub code: i32,
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -129,7 +162,7 @@ impl From<ErrorCodes> for i32 {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct NotificationMessage {
+pub struct NotificationMessage {
     pub method: String,
     pub params: Value,
 }
@@ -152,15 +185,16 @@ fn read_header(reader: &mut impl io::BufRead) -> io::Result<Header> {
             break;
         }
 
-        let header_fields = line.trim().split(": ").collect::<Vec<_>>();
-        if header_fields.len() != 2 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid header",
-            ));
-        }
-        let name = header_fields[0].to_ascii_lowercase();
-        let value = header_fields[1];
+        // Values may contain colons, e.g. in `Content-Type`.
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid header",
+                ))
+            }
+        };
 
         if name == "content-length" {
             let value = match value.parse::<usize>() {
@@ -168,6 +202,11 @@ fn read_header(reader: &mut impl io::BufRead) -> io::Result<Header> {
                 Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
             };
             content_length = Some(value);
+        } else if name == "content-type" && !is_utf8_content_type(value) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported content type: {}", value),
+            ));
         }
     }
 
@@ -179,20 +218,110 @@ fn read_header(reader: &mut impl io::BufRead) -> io::Result<Header> {
         ))
 }
 
-pub(crate) fn read_message(reader: &mut impl io::BufRead) -> anyhow::Result<Message> {
+// Returns true when `content_type` is utf-8 based. The charset defaults to
+// utf-8. `utf8` is also accepted for backwards compatibility.
+fn is_utf8_content_type(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .all(|(_, charset)| {
+            let charset = charset.trim().trim_matches('"');
+            charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+        })
+}
+
+/// The default maximum content length of a message.
+pub(crate) const DEFAULT_MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// A message that was read but couldn't be parsed. The reader is at the
+/// start of the next message, so the server can respond with a parse error
+/// and keep reading.
+#[derive(Debug)]
+pub(crate) struct InvalidMessage(pub String);
+
+impl std::fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidMessage {}
+
+// Reads `len` bytes into `buf`, or skips them when `buf` is None. The buffer
+// grows as data arrives instead of trusting `len` for the allocation. Fails
+// when the input ends before `len` bytes.
+fn read_body(
+    reader: &mut impl io::BufRead,
+    len: usize,
+    buf: Option<&mut Vec<u8>>,
+) -> io::Result<()> {
+    let mut body = reader.take(len as u64);
+    let n = match buf {
+        Some(buf) => body.read_to_end(buf)?,
+        None => io::copy(&mut body, &mut io::sink())? as usize,
+    };
+    if n < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Truncated message: {} of {} bytes", n, len),
+        ));
+    }
+    Ok(())
+}
+
+fn read_content(
+    reader: &mut impl io::BufRead,
+    max_content_length: usize,
+) -> anyhow::Result<Vec<u8>> {
     let header = read_header(reader)?;
-    let mut buf = vec![0; header.content_length];
-    reader.read_exact(&mut buf)?;
-    match Message::from_slice(&buf) {
+    let len = header.content_length;
+    if len > max_content_length {
+        read_body(reader, len, None)?;
+        return Err(InvalidMessage(format!(
+            "Message too large: {} bytes exceeds the limit of {} bytes",
+            len, max_content_length
+        ))
+        .into());
+    }
+    if len == 0 {
+        return Err(InvalidMessage("Empty message".to_owned()).into());
+    }
+    let mut buf = Vec::new();
+    read_body(reader, len, Some(&mut buf))?;
+    Ok(buf)
+}
+
+// Reads a single message. Tests use this to read what the server writes.
+#[cfg(test)]
+pub(crate) fn read_message(reader: &mut impl io::BufRead) -> anyhow::Result<Message> {
+    let buf = read_content(reader, DEFAULT_MAX_CONTENT_LENGTH)?;
+    match from_slice::<Message>(&buf) {
         Ok(message) => Ok(message),
-        Err(_) => Err(anyhow!("Failed to parse message")),
+        Err(_) => Err(anyhow::anyhow!("Failed to parse message")),
+    }
+}
+
+// Reads a message or a batch of messages. Messages in a batch are returned
+// in order. Messages larger than `max_content_length` are skipped and
+// reported as `InvalidMessage`, as are empty and malformed messages.
+pub(crate) fn read_messages(
+    reader: &mut impl io::BufRead,
+    max_content_length: usize,
+) -> anyhow::Result<Vec<Message>> {
+    let buf = read_content(reader, max_content_length)?;
+    match from_slice::<Content>(&buf) {
+        Ok(Content::Batch(messages)) => Ok(messages),
+        Ok(Content::Single(message)) => Ok(vec![message]),
+        Err(err) => Err(InvalidMessage(format!("Failed to parse message: {}", err)).into()),
     }
 }
 
 #[derive(Serialize)]
 struct JsonRpcResponseMessage<'a> {
     jsonrpc: &'a str,
-    id: u64,
+    id: Id,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -209,21 +338,9 @@ fn write_message<M: Serialize>(writer: &mut impl Write, message: M) -> anyhow::R
     Ok(())
 }
 
-pub(crate) fn write_success_result<R>(
-    writer: &mut impl Write,
-    id: u64,
-    res: R,
-) -> anyhow::Result<()>
-where
-    R: serde::Serialize,
-{
-    let res = serde_json::to_value(&res)?;
-    write_success_response(writer, id, res)
-}
-
 pub(crate) fn write_success_response(
     writer: &mut impl Write,
-    id: u64,
+    id: Id,
     result: Value,
 ) -> anyhow::Result<()> {
     let message = JsonRpcResponseMessage {
@@ -237,7 +354,7 @@ pub(crate) fn write_success_response(
 
 pub(crate) fn write_error_response(
     writer: &mut impl Write,
-    id: u64,
+    id: Id,
     error: ResponseError,
 ) -> anyhow::Result<()> {
     let message = JsonRpcResponseMessage {
@@ -269,6 +386,22 @@ pub(crate) fn write_notification(
     write_message(writer, message)
 }
 
+// Writes `message` with the `jsonrpc` field.
+pub(crate) fn write_any_message(writer: &mut impl Write, message: Message) -> anyhow::Result<()> {
+    match message {
+        Message::Request(req) => write_request(writer, req.id, &req.method, req.params),
+        Message::Response(ResponseMessage {
+            id,
+            error: Some(error),
+            ..
+        }) => write_error_response(writer, id, error),
+        Message::Response(res) => {
+            write_success_response(writer, res.id, res.result.unwrap_or(Value::Null))
+        }
+        Message::Notification(notif) => write_notification(writer, &notif.method, notif.params),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -279,5 +412,116 @@ mod tests {
         let mut reader = io::BufReader::new(&input[..]);
         let header = read_header(&mut reader).unwrap();
         assert_eq!(208, header.content_length);
+
+        let input = b"Content-Length: 10\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(10, header.content_length);
+
+        let input = b"Content-Length: 10\r\nContent-Type: text/plain; charset=utf-16\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        assert!(read_header(&mut reader).is_err());
+
+        let input = b"Content-Length 10\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        assert!(read_header(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_message_ids() {
+        let value = serde_json::json!({"id": 1, "method": "shutdown", "params": null});
+        let message = serde_json::from_value::<Message>(value.clone()).unwrap();
+        match &message {
+            Message::Request(request) => assert_eq!(Id::Number(1), request.id),
+            _ => unreachable!(),
+        }
+        assert_eq!(value, serde_json::to_value(&message).unwrap());
+
+        let value = serde_json::json!({"id": "abc", "method": "shutdown", "params": null});
+        let message = serde_json::from_value::<Message>(value.clone()).unwrap();
+        match &message {
+            Message::Request(request) => assert_eq!(Id::String("abc".to_owned()), request.id),
+            _ => unreachable!(),
+        }
+        assert_eq!(value, serde_json::to_value(&message).unwrap());
+
+        let value = serde_json::json!({"id": "abc", "result": null});
+        let message = serde_json::from_value::<Message>(value).unwrap();
+        assert!(matches!(message, Message::Response(_)));
+
+        let value = serde_json::json!({"method": "exit", "params": null});
+        let message = serde_json::from_value::<Message>(value.clone()).unwrap();
+        assert!(matches!(message, Message::Notification(_)));
+        assert_eq!(value, serde_json::to_value(&message).unwrap());
+    }
+
+    #[test]
+    fn test_read_messages() {
+        let content = r#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "shutdown", "params": null},
+            {"jsonrpc": "2.0", "method": "exit", "params": null}
+        ]"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let messages = read_messages(&mut reader, DEFAULT_MAX_CONTENT_LENGTH).unwrap();
+        assert_eq!(2, messages.len());
+        match &messages[0] {
+            Message::Request(request) => assert_eq!("shutdown", request.method),
+            _ => unreachable!(),
+        }
+        match &messages[1] {
+            Message::Notification(notification) => assert_eq!("exit", notification.method),
+            _ => unreachable!(),
+        }
+
+        let content = r#"{"jsonrpc": "2.0", "method": "exit", "params": null}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let messages = read_messages(&mut reader, DEFAULT_MAX_CONTENT_LENGTH).unwrap();
+        assert_eq!(1, messages.len());
+    }
+
+    fn is_invalid_message(result: anyhow::Result<Vec<Message>>) -> bool {
+        matches!(result, Err(err) if err.is::<InvalidMessage>())
+    }
+
+    #[test]
+    fn test_read_messages_limits() {
+        let content = r#"{"jsonrpc": "2.0", "method": "exit", "params": null}"#;
+        let message = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+
+        // An oversized message is skipped and the next one is read.
+        let input = format!("{}{}", message, message);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(is_invalid_message(read_messages(&mut reader, 10)));
+        let messages = read_messages(&mut reader, content.len()).unwrap();
+        assert_eq!(1, messages.len());
+
+        // A huge content length doesn't allocate up front. The input ends
+        // before the advertised length.
+        let input = format!("Content-Length: 99999999999\r\n\r\n{}", content);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_messages(&mut reader, usize::MAX).unwrap_err();
+        assert!(!err.is::<InvalidMessage>());
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_messages(&mut reader, DEFAULT_MAX_CONTENT_LENGTH).unwrap_err();
+        assert!(!err.is::<InvalidMessage>());
+
+        // A truncated body.
+        let input = &message[..message.len() - 1];
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_messages(&mut reader, DEFAULT_MAX_CONTENT_LENGTH).unwrap_err();
+        let err = err.downcast::<io::Error>().unwrap();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+        // An empty message doesn't block and the next one is read.
+        let input = format!("Content-Length: 0\r\n\r\n{}", message);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(is_invalid_message(read_messages(
+            &mut reader,
+            DEFAULT_MAX_CONTENT_LENGTH
+        )));
+        let messages = read_messages(&mut reader, DEFAULT_MAX_CONTENT_LENGTH).unwrap();
+        assert_eq!(1, messages.len());
     }
 }
@@ -0,0 +1,219 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Formats the top-level statements that a range touches. Lines are
+// re-indented by the nesting of braces and trailing whitespace is removed.
+// Lines inside parentheses, brackets and block comments keep their
+// indentation because they are often aligned by hand.
+
+use lsp_types::{FormattingOptions, Range, TextEdit};
+
+use crate::syntax;
+
+use super::completion::mask_comments_and_strings;
+use super::on_type_formatting::{indent_unit, indentation};
+use super::textpos::LineIndex;
+
+/// Returns edits that format the statements intersecting `range`. A range
+/// that cuts through a statement formats the whole statement. Nothing is
+/// formatted when `text` doesn't parse.
+pub(crate) fn range_formatting(
+    text: &str,
+    line_index: &LineIndex,
+    range: &Range,
+    options: &FormattingOptions,
+) -> Vec<TextEdit> {
+    let mojom = match syntax::parse_with_comments(text) {
+        Ok(mojom) => mojom,
+        Err(_) => return Vec::new(),
+    };
+    let range = syntax::Range {
+        start: line_index.offset(text, &range.start),
+        end: line_index.offset(text, &range.end),
+    };
+    let stmts = mojom.statements_in(&range);
+    let (start, end) = match (stmts.first(), stmts.last()) {
+        (Some(first), Some(last)) => (first.range().start, last.range().end),
+        _ => return Vec::new(),
+    };
+
+    let in_block_comment = |offset: usize| {
+        mojom.comments.iter().any(|comment| {
+            comment.kind == syntax::CommentKind::Block
+                && comment.range.start < offset
+                && offset < comment.range.end
+        })
+    };
+    let (masked, _) = mask_comments_and_strings(text);
+    let unit = indent_unit(options);
+    let edit = |start: usize, end: usize, new_text: String| {
+        let range = Range::new(
+            line_index.position(text, start),
+            line_index.position(text, end),
+        );
+        TextEdit::new(range, new_text)
+    };
+
+    let mut edits = Vec::new();
+    // Statements are top-level, so nothing is open at their first line.
+    let mut braces = 0_usize;
+    let mut parens = 0_usize;
+    let mut line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    while line_start < end {
+        let line_end = text[line_start..]
+            .find('\n')
+            .map_or(text.len(), |i| line_start + i);
+        let line = &text[line_start..line_end];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let current = indentation(line);
+        let content_end = line.trim_end_matches([' ', '\t']).len();
+
+        if content_end == 0 {
+            if !line.is_empty() {
+                edits.push(edit(line_start, line_start + line.len(), String::new()));
+            }
+        } else {
+            if parens == 0 && !in_block_comment(line_start) {
+                let closes = masked[line_start + current.len()] == b'}';
+                let level = braces.saturating_sub(closes as usize);
+                let expected = unit.repeat(level);
+                if expected != current {
+                    edits.push(edit(line_start, line_start + current.len(), expected));
+                }
+            }
+            if content_end < line.len() {
+                edits.push(edit(
+                    line_start + content_end,
+                    line_start + line.len(),
+                    String::new(),
+                ));
+            }
+        }
+
+        for &ch in &masked[line_start..line_end] {
+            match ch {
+                b'{' => braces += 1,
+                b'}' => braces = braces.saturating_sub(1),
+                b'(' | b'[' => parens += 1,
+                b')' | b']' => parens = parens.saturating_sub(1),
+                _ => (),
+            }
+        }
+        line_start = line_end + 1;
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Position;
+
+    use super::super::textpos::PositionEncoding;
+
+    fn create_options(tab_size: u32, insert_spaces: bool) -> FormattingOptions {
+        FormattingOptions {
+            tab_size,
+            insert_spaces,
+            ..Default::default()
+        }
+    }
+
+    // Formats the lines from `start` to `end` and returns the formatted text.
+    fn format(text: &str, start: u32, end: u32, options: &FormattingOptions) -> String {
+        let line_index = LineIndex::new(text, PositionEncoding::Utf16);
+        let range = Range::new(Position::new(start, 0), Position::new(end, 0));
+        let edits = range_formatting(text, &line_index, &range, options);
+        let mut formatted = text.to_owned();
+        for edit in edits.iter().rev() {
+            let start = line_index.offset(text, &edit.range.start);
+            let end = line_index.offset(text, &edit.range.end);
+            formatted.replace_range(start..end, &edit.new_text);
+        }
+        formatted
+    }
+
+    #[test]
+    fn test_format_statement() {
+        let text = r#"module test;
+struct Foo {
+int32 a;  
+      enum Bar {
+  kA,
+   };
+    };
+interface Baz {
+        Qux();
+};
+"#;
+        let options = create_options(2, true);
+        // Only the struct is formatted even though the range starts at the
+        // middle of it.
+        assert_eq!(
+            r#"module test;
+struct Foo {
+  int32 a;
+  enum Bar {
+    kA,
+  };
+};
+interface Baz {
+        Qux();
+};
+"#,
+            format(text, 3, 4, &options)
+        );
+        assert_eq!(
+            "struct Foo {\n\tint32 a;\n};\n",
+            format(
+                "struct Foo {\n    int32 a;\n};\n",
+                1,
+                1,
+                &create_options(4, false)
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_aligned_lines() {
+        let text = r#"interface Foo {
+    Bar(int32 a,
+        int32 b);
+    /* A comment
+       aligned by hand. */
+    [Attr]
+    Baz();
+};
+"#;
+        assert_eq!(
+            text.replace("    Bar", "  Bar")
+                .replace("    /*", "  /*")
+                .replace("    [Attr]", "  [Attr]")
+                .replace("    Baz", "  Baz"),
+            format(text, 0, 8, &create_options(2, true))
+        );
+    }
+
+    #[test]
+    fn test_format_outside_statements() {
+        let options = create_options(2, true);
+        let text = "struct Foo {\n    int32 a;\n};\n\n\n";
+        // Blank lines between statements.
+        assert_eq!(text, format(text, 4, 4, &options));
+        // Text that doesn't parse isn't formatted.
+        let text = "struct Foo {\n    int32 a\n};\n";
+        assert_eq!(text, format(text, 0, 3, &options));
+    }
+}
@@ -0,0 +1,457 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, Location, Range};
+
+use crate::syntax::{self, preorder, Traversal};
+
+use super::definition::{find_definition_in_scope, find_definition_preorder};
+use super::imported_files::ImportedFiles;
+use super::mojomast::MojomAst;
+
+#[inline(always)]
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
+}
+
+/// Resolves `ident` to its definition. Names qualified with the module name of
+/// the document are also resolved in the document.
+pub(crate) fn resolve(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    ident: &str,
+) -> Option<Location> {
+    find_definition_preorder(ident, ast).or_else(|| {
+        imported_files
+            .as_ref()
+            .and_then(|imported_files| imported_files.find_definition(ident))
+    })
+}
+
+// A range that may refer to a definition and the names of its enclosing
+// interface or struct.
+struct Candidate<'a> {
+    range: &'a syntax::Range,
+    scope: Vec<&'a str>,
+}
+
+/// How a name occurs in a document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RefKind {
+    Declaration,
+    Reference,
+}
+
+// Returns ranges of type references and values that can refer to enum values,
+// i.e. enum value initializers, const values and field defaults. Type
+// references can be parameterized like `map<string, MyStruct>`.
+fn reference_candidates(ast: &MojomAst) -> Vec<Candidate<'_>> {
+    let mut candidates = Vec::new();
+    let mut scope = Vec::new();
+    let mut ranges = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => scope.push(ast.text(&node.name)),
+            Traversal::EnterStruct(node) => scope.push(ast.text(&node.name)),
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+            }
+            Traversal::StructField(node) => {
+                ranges.push(&node.typ);
+                ranges.extend(node.default.as_ref());
+            }
+            Traversal::Const(node) => {
+                ranges.push(&node.typ);
+                ranges.push(&node.value);
+            }
+            Traversal::Union(node) => ranges.extend(node.fields.iter().map(|field| &field.typ)),
+            Traversal::Method(node) => ranges.extend(
+                node.params
+                    .iter()
+                    .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                    .map(|param| &param.typ),
+            ),
+            Traversal::Enum(node) => {
+                // Initializers can refer to other values of the enum without
+                // the enum name.
+                let mut scope = scope.clone();
+                scope.push(ast.text(&node.name));
+                let values = node.values.iter().filter_map(|value| value.value.as_ref());
+                candidates.extend(values.map(|range| Candidate {
+                    range,
+                    scope: scope.clone(),
+                }));
+            }
+            _ => (),
+        }
+        // String literals never refer to definitions.
+        let ranges = ranges
+            .drain(..)
+            .filter(|range| !ast.text(range).starts_with('"'));
+        candidates.extend(ranges.map(|range| Candidate {
+            range,
+            scope: scope.clone(),
+        }));
+    }
+    candidates
+}
+
+// Resolves `ident` that appears in `scope`. Inner scopes are searched first.
+fn resolve_in_scope(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    scope: &[&str],
+    ident: &str,
+) -> Option<Location> {
+    find_definition_in_scope(ident, scope, ast).or_else(|| {
+        imported_files
+            .as_ref()
+            .and_then(|imported_files| imported_files.find_definition(ident))
+    })
+}
+
+// Splits the text of `range` into identifiers.
+fn identifiers(ast: &MojomAst, range: &syntax::Range) -> Vec<syntax::Range> {
+    let text = ast.text(range);
+    let mut idents = Vec::new();
+    let mut start = None;
+    for (offset, ch) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, is_identifier_char(ch)) {
+            (None, true) => start = Some(offset),
+            (Some(s), false) => {
+                idents.push(syntax::Range {
+                    start: range.start + s,
+                    end: range.start + offset,
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    idents
+}
+
+// Returns `range` and its prefixes that end at `.`. In `MyStruct.MyEnum`,
+// `MyStruct` refers to the struct.
+fn qualified_prefixes(ast: &MojomAst, range: &syntax::Range) -> Vec<syntax::Range> {
+    let text = ast.text(range);
+    text.match_indices('.')
+        .map(|(pos, _)| range.start + pos)
+        .chain(std::iter::once(range.end))
+        .map(|end| syntax::Range {
+            start: range.start,
+            end,
+        })
+        .collect()
+}
+
+/// Resolves the reference at `offset`. When the reference is qualified, the
+/// shortest part that covers `offset` and refers to a definition is resolved.
+/// Returns the range of the resolved part and the location of its definition.
+pub(crate) fn resolve_reference_at(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+) -> Option<(syntax::Range, Location)> {
+    for candidate in reference_candidates(ast) {
+        for range in identifiers(ast, candidate.range) {
+            if range.start <= offset && offset <= range.end {
+                return qualified_prefixes(ast, &range)
+                    .into_iter()
+                    .filter(|prefix| offset <= prefix.end)
+                    .find_map(|prefix| {
+                        let ident = ast.text(&prefix);
+                        resolve_in_scope(ast, imported_files, &candidate.scope, ident)
+                            .map(|definition| (prefix, definition))
+                    });
+            }
+        }
+    }
+    None
+}
+
+// Returns the name of the declaration at `offset`.
+fn declaration_at(ast: &MojomAst, offset: usize) -> Option<&syntax::Range> {
+    let contains = |name: &syntax::Range| name.start <= offset && offset <= name.end;
+    preorder(&ast.mojom).find_map(|traversal| {
+        let name = match traversal {
+            Traversal::EnterInterface(node) => &node.name,
+            Traversal::EnterStruct(node) => &node.name,
+            Traversal::Union(node) => &node.name,
+            Traversal::Enum(node) => {
+                return std::iter::once(&node.name)
+                    .chain(node.values.iter().map(|value| &value.name))
+                    .find(|name| contains(name));
+            }
+            Traversal::Const(node) => &node.name,
+            Traversal::Method(node) => &node.name,
+            Traversal::StructField(node) => &node.name,
+            _ => return None,
+        };
+        Some(name).filter(|name| contains(name))
+    })
+}
+
+/// Resolves the declaration or the reference at `offset` to the location of
+/// its definition.
+pub(crate) fn resolve_at(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+) -> Option<Location> {
+    if let Some(name) = declaration_at(ast, offset) {
+        return Some(Location::new(ast.uri.clone(), ast.lsp_range(name)));
+    }
+    resolve_reference_at(ast, imported_files, offset).map(|(_, definition)| definition)
+}
+
+/// Finds occurrences of `definition` in the document. The declaration comes
+/// first when it is in the document. References include qualifiers of other
+/// references.
+pub(crate) fn occurrences(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    definition: &Location,
+) -> Vec<(Range, RefKind)> {
+    occurrences_with(ast, imported_files, definition, &|_| None)
+}
+
+/// Same as `occurrences` except that names that aren't declared in the
+/// document or its imports are resolved with `fallback`, e.g. names declared
+/// in other files of the module.
+pub(crate) fn occurrences_with(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    definition: &Location,
+    fallback: &dyn Fn(&str) -> Option<Location>,
+) -> Vec<(Range, RefKind)> {
+    let mut occurrences = Vec::new();
+    if definition.uri == ast.uri {
+        occurrences.push((definition.range, RefKind::Declaration));
+    }
+    for candidate in reference_candidates(ast) {
+        let ranges = identifiers(ast, candidate.range)
+            .into_iter()
+            .flat_map(|range| qualified_prefixes(ast, &range));
+        for range in ranges {
+            let ident = ast.text(&range);
+            let resolved = resolve_in_scope(ast, imported_files, &candidate.scope, ident)
+                .or_else(|| fallback(ident));
+            if resolved.as_ref() == Some(definition) {
+                occurrences.push((ast.lsp_range(&range), RefKind::Reference));
+            }
+        }
+    }
+    occurrences
+}
+
+/// Returns true when the type or value at `range`, which appears in `scope`,
+/// refers to `definition`. Parameterized types like `array<MyStruct>` refer to
+/// their element types.
+pub(crate) fn refers_to(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    scope: &[&str],
+    range: &syntax::Range,
+    definition: &Location,
+) -> bool {
+    identifiers(ast, range)
+        .iter()
+        .flat_map(|range| qualified_prefixes(ast, range))
+        .any(|range| {
+            let ident = ast.text(&range);
+            resolve_in_scope(ast, imported_files, scope, ident).as_ref() == Some(definition)
+        })
+}
+
+/// Finds references to `definition` in the document. Names that aren't
+/// declared in the document or its imports are resolved with `fallback`.
+pub(crate) fn references_to(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    definition: &Location,
+    include_declaration: bool,
+    fallback: &dyn Fn(&str) -> Option<Location>,
+) -> Vec<Location> {
+    occurrences_with(ast, imported_files, definition, fallback)
+        .into_iter()
+        .filter(|(_, kind)| include_declaration || *kind == RefKind::Reference)
+        .map(|(range, _)| Location::new(ast.uri.clone(), range))
+        .collect()
+}
+
+/// Returns highlights of the symbol at `offset`. The declaration is a write
+/// and references are reads.
+pub(crate) fn document_highlight(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+) -> Vec<DocumentHighlight> {
+    let definition = match resolve_at(ast, imported_files, offset) {
+        Some(definition) => definition,
+        None => return Vec::new(),
+    };
+    occurrences(ast, imported_files, &definition)
+        .into_iter()
+        .map(|(range, kind)| {
+            let kind = match kind {
+                RefKind::Declaration => DocumentHighlightKind::WRITE,
+                RefKind::Reference => DocumentHighlightKind::READ,
+            };
+            DocumentHighlight {
+                range,
+                kind: Some(kind),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use super::super::imported_files::{check_imports, ImportCache};
+
+    // Finds references to `ident` in the document.
+    fn find_references(
+        ast: &MojomAst,
+        imported_files: &Option<ImportedFiles>,
+        ident: &str,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let definition = match resolve(ast, imported_files, ident) {
+            Some(definition) => definition,
+            None => return Vec::new(),
+        };
+
+        let mut locations = Vec::new();
+        // Declarations in imported files aren't occurrences in the document.
+        if include_declaration && definition.uri != ast.uri {
+            locations.push(definition.clone());
+        }
+        locations.extend(references_to(
+            ast,
+            imported_files,
+            &definition,
+            include_declaration,
+            &|_| None,
+        ));
+        locations
+    }
+
+    fn create_ast(uri: lsp_types::Url, text: &str) -> MojomAst {
+        let mojom = syntax::parse(text).unwrap();
+        let module = crate::analysis::find_module(text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, module)
+    }
+
+    #[test]
+    fn test_find_references() {
+        let input = r#"
+        module my.mod;
+        struct MyStruct {
+            enum Kind { kA, kB = kA };
+            MyStruct? next;
+            Kind kind;
+        };
+        union MyUnion { MyStruct a; };
+        interface MyInterface {
+            Method(array<MyStruct> values) => (my.mod.MyStruct? result);
+        };
+        "#;
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, input);
+
+        let locations = find_references(&ast, &None, "MyStruct", false);
+        let lines = locations
+            .iter()
+            .map(|location| location.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![4, 7, 9, 9], lines);
+
+        let locations = find_references(&ast, &None, "my.mod.MyStruct", true);
+        assert_eq!(5, locations.len());
+        assert_eq!(2, locations[0].range.start.line);
+
+        // `Kind` is referred without the enclosing struct name.
+        let locations = find_references(&ast, &None, "MyStruct.Kind", false);
+        assert_eq!(1, locations.len());
+        assert_eq!(5, locations[0].range.start.line);
+
+        assert!(find_references(&ast, &None, "Unknown", true).is_empty());
+    }
+
+    #[test]
+    fn test_document_highlight() {
+        let input = r#"
+        struct MyStruct {
+            enum Kind { kA, kB = kA };
+            Kind kind = Kind.kA;
+            string name = "kA";
+        };
+        const MyStruct.Kind kDefault = MyStruct.Kind.kA;
+        interface MyInterface {
+            Method(MyStruct value, int32 count);
+        };
+        "#;
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, input);
+        let highlights = |needle: &str| {
+            let offset = input.find(needle).unwrap();
+            document_highlight(&ast, &None, offset)
+                .into_iter()
+                .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        // On the declaration of an enum value. The string literal isn't a
+        // reference.
+        let read = DocumentHighlightKind::READ;
+        let write = DocumentHighlightKind::WRITE;
+        assert_eq!(
+            vec![(2, write), (2, read), (3, read), (6, read)],
+            highlights("kA,")
+        );
+        // On a reference.
+        assert_eq!(
+            vec![(1, write), (6, read), (6, read), (8, read)],
+            highlights("MyStruct value")
+        );
+        // Builtin types have no declaration.
+        assert!(highlights("int32 count").is_empty());
+    }
+
+    #[test]
+    fn test_find_references_testdata() {
+        let path = Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let ast = create_ast(uri.clone(), &text);
+        let imported_files = Some(check_imports(&["testdata"], &ast, &mut ImportCache::new()));
+
+        let locations = find_references(&ast, &imported_files, "MyInterface", true);
+        assert_eq!(2, locations.len());
+        // The declaration in the imported file.
+        assert!(locations[0].uri.path().ends_with("my_interface.mojom"));
+        assert_eq!(uri, locations[1].uri);
+        assert_eq!(4, locations[1].range.start.line);
+    }
+}
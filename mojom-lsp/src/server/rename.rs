@@ -0,0 +1,385 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use lsp_types::{Location, Position, Range, TextEdit, WorkspaceEdit};
+
+use crate::syntax::{self, preorder, Traversal};
+
+use super::definition::find_declaration_preorder;
+use super::imported_files::ImportedFiles;
+use super::mojomast::MojomAst;
+use super::references::{occurrences, resolve_reference_at, RefKind};
+
+// Names that can't be used as identifiers. See `keyword` in mojom.pest.
+const KEYWORDS: &[&str] = &[
+    "array",
+    "associated",
+    "const",
+    "handle",
+    "import",
+    "interface",
+    "map",
+    "module",
+    "struct",
+    "union",
+    "enum",
+    "true",
+    "false",
+    "default",
+    "pending_receiver",
+    "pending_remote",
+    "pending_associated_receiver",
+    "pending_associated_remote",
+];
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum RenameError {
+    // The identifier is declared in an imported file.
+    Imported(String),
+    InvalidName(String),
+    // The new name is already used in the same scope.
+    Collision(String),
+}
+
+impl std::fmt::Display for RenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::Imported(name) => {
+                write!(f, "{} is declared in an imported file", name)
+            }
+            RenameError::InvalidName(name) => write!(f, "{} is not a valid identifier", name),
+            RenameError::Collision(name) => write!(f, "{} is already declared", name),
+        }
+    }
+}
+
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_char = match chars.next() {
+        Some(ch) => ch.is_ascii_alphabetic() || ch == '_',
+        None => false,
+    };
+    starts_with_char
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+// A type or const declaration that can be renamed.
+struct Declaration<'a> {
+    // The names of the enclosing interface or struct.
+    scope: Vec<&'a str>,
+    name: &'a syntax::Range,
+}
+
+fn declarations(ast: &MojomAst) -> Vec<Declaration<'_>> {
+    let mut declarations = Vec::new();
+    let mut scope = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        let name = match traversal {
+            Traversal::EnterInterface(node) => {
+                declarations.push(Declaration {
+                    scope: scope.clone(),
+                    name: &node.name,
+                });
+                scope.push(ast.text(&node.name));
+                continue;
+            }
+            Traversal::EnterStruct(node) => {
+                declarations.push(Declaration {
+                    scope: scope.clone(),
+                    name: &node.name,
+                });
+                scope.push(ast.text(&node.name));
+                continue;
+            }
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+                continue;
+            }
+            Traversal::Union(node) => &node.name,
+            Traversal::Enum(node) => &node.name,
+            Traversal::Const(node) => &node.name,
+            _ => continue,
+        };
+        declarations.push(Declaration {
+            scope: scope.clone(),
+            name,
+        });
+    }
+    declarations
+}
+
+// The declaration to rename and the range of the name under the cursor.
+struct Target<'a> {
+    declaration: Declaration<'a>,
+    range: Range,
+}
+
+fn find_target<'a>(
+    ast: &'a MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+) -> Result<Option<Target<'a>>, RenameError> {
+    let mut declarations = declarations(ast);
+    if let Some(pos) = declarations
+        .iter()
+        .position(|declaration| declaration.name.start <= offset && offset <= declaration.name.end)
+    {
+        let declaration = declarations.swap_remove(pos);
+        let range = ast.lsp_range(declaration.name);
+        return Ok(Some(Target { declaration, range }));
+    }
+
+    let (reference, definition) = match resolve_reference_at(ast, imported_files, offset) {
+        Some(resolved) => resolved,
+        None => return Ok(None),
+    };
+    if definition.uri != ast.uri {
+        return Err(RenameError::Imported(ast.text(&reference).to_owned()));
+    }
+    let pos = declarations
+        .iter()
+        .position(|declaration| ast.lsp_range(declaration.name) == definition.range);
+    let declaration = match pos {
+        Some(pos) => declarations.swap_remove(pos),
+        None => return Ok(None),
+    };
+    // Only the last part of a qualified reference is renamed.
+    let name_len = ast.text(declaration.name).len();
+    let range = ast.lsp_range(&syntax::Range {
+        start: reference.end - name_len,
+        end: reference.end,
+    });
+    Ok(Some(Target { declaration, range }))
+}
+
+/// Returns the range of the name to rename at `offset`, or None when there is
+/// nothing to rename.
+pub(crate) fn prepare_rename(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+) -> Result<Option<Range>, RenameError> {
+    Ok(find_target(ast, imported_files, offset)?.map(|target| target.range))
+}
+
+/// Renames the declaration at `offset`, or the declaration that the reference
+/// at `offset` refers to, to `new_name`. References in other files aren't
+/// updated.
+pub(crate) fn rename(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    offset: usize,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>, RenameError> {
+    if !is_valid_name(new_name) {
+        return Err(RenameError::InvalidName(new_name.to_owned()));
+    }
+    let target = match find_target(ast, imported_files, offset)? {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+
+    let declaration = &target.declaration;
+    let old_name = ast.text(declaration.name);
+    if new_name != old_name {
+        let sibling = declaration
+            .scope
+            .iter()
+            .copied()
+            .chain(std::iter::once(new_name))
+            .collect::<Vec<_>>()
+            .join(".");
+        if find_declaration_preorder(&sibling, ast).is_some() {
+            return Err(RenameError::Collision(new_name.to_owned()));
+        }
+    }
+
+    let definition = Location::new(ast.uri.clone(), ast.lsp_range(declaration.name));
+    let mut edits = vec![TextEdit::new(definition.range, new_name.to_owned())];
+    let references = occurrences(ast, imported_files, &definition)
+        .into_iter()
+        .filter(|(_, kind)| *kind == RefKind::Reference);
+    for (range, _) in references {
+        // References can be qualified like `MyStruct.MyEnum`. Identifiers are
+        // ASCII so the name is the last `old_name.len()` characters.
+        let end = range.end;
+        let start = Position::new(end.line, end.character - old_name.len() as u32);
+        edits.push(TextEdit::new(Range::new(start, end), new_name.to_owned()));
+    }
+
+    let mut changes = HashMap::new();
+    changes.insert(ast.uri.clone(), edits);
+    Ok(Some(WorkspaceEdit::new(changes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use super::super::imported_files::{check_imports, ImportCache};
+
+    fn create_ast(uri: lsp_types::Url, text: &str) -> MojomAst {
+        let mojom = syntax::parse(text).unwrap();
+        let module = crate::analysis::find_module(text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, module)
+    }
+
+    fn apply_edit(text: &str, edit: &WorkspaceEdit) -> String {
+        let mut edits = edit
+            .changes
+            .as_ref()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        let mut lines = text.lines().map(|line| line.to_owned()).collect::<Vec<_>>();
+        for edit in edits.iter().rev() {
+            let line = &mut lines[edit.range.start.line as usize];
+            let start = edit.range.start.character as usize;
+            let end = edit.range.end.character as usize;
+            line.replace_range(start..end, &edit.new_text);
+        }
+        lines.join("\n")
+    }
+
+    const INPUT: &str = r#"
+        module my.mod;
+        const int32 kSize = 4;
+        struct MyStruct {
+            enum Kind { kA = kSize, kB };
+            MyStruct? next;
+            Kind kind;
+        };
+        union MyUnion { MyStruct a; };
+        interface MyInterface {
+            Method(MyStruct.Kind kind) => (my.mod.MyStruct? result);
+        };
+    "#;
+
+    fn offset(pattern: &str) -> usize {
+        INPUT.find(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_rename() {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, INPUT);
+
+        // From the declaration.
+        let edit = rename(&ast, &None, offset("MyStruct {"), "Renamed")
+            .unwrap()
+            .unwrap();
+        let expected = INPUT.replace("MyStruct", "Renamed");
+        assert_eq!(expected.trim_end(), apply_edit(INPUT, &edit).trim_end());
+
+        // From a reference. Only the last part of qualified names changes.
+        let edit = rename(&ast, &None, offset("Kind kind;"), "Type")
+            .unwrap()
+            .unwrap();
+        let renamed = apply_edit(INPUT, &edit);
+        assert!(renamed.contains("enum Type { kA = kSize, kB };"));
+        assert!(renamed.contains("Type kind;"));
+        assert!(renamed.contains("Method(MyStruct.Type kind)"));
+
+        let edit = rename(&ast, &None, offset("kSize,"), "kLength")
+            .unwrap()
+            .unwrap();
+        let renamed = apply_edit(INPUT, &edit);
+        assert!(renamed.contains("const int32 kLength = 4;"));
+        assert!(renamed.contains("kA = kLength"));
+
+        // Nothing to rename.
+        assert_eq!(Ok(None), rename(&ast, &None, offset("int32 kSize"), "Foo"));
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, INPUT);
+        let pos = offset("MyUnion");
+
+        assert_eq!(
+            Err(RenameError::InvalidName("1Union".to_owned())),
+            rename(&ast, &None, pos, "1Union")
+        );
+        assert_eq!(
+            Err(RenameError::InvalidName("struct".to_owned())),
+            rename(&ast, &None, pos, "struct")
+        );
+        assert_eq!(
+            Err(RenameError::Collision("MyInterface".to_owned())),
+            rename(&ast, &None, pos, "MyInterface")
+        );
+        // Nested names only collide in the same scope.
+        assert!(rename(&ast, &None, pos, "Kind").is_ok());
+        assert_eq!(
+            Err(RenameError::Collision("next".to_owned())),
+            rename(&ast, &None, offset("Kind {"), "next")
+        );
+    }
+
+    #[test]
+    fn test_prepare_rename() {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let ast = create_ast(uri, INPUT);
+
+        let range = prepare_rename(&ast, &None, offset("my.mod.MyStruct?"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Position::new(10, 50), range.start);
+        assert_eq!(Position::new(10, 58), range.end);
+
+        // The qualifier of `MyStruct.Kind` refers to the struct.
+        let range = prepare_rename(&ast, &None, offset("MyStruct.Kind"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Position::new(10, 19), range.start);
+
+        assert_eq!(
+            Ok(None),
+            prepare_rename(&ast, &None, offset("module my.mod"))
+        );
+    }
+
+    #[test]
+    fn test_rename_imported() {
+        let path = Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = lsp_types::Url::from_file_path(&path).unwrap();
+        let ast = create_ast(uri, &text);
+        let imported_files = Some(check_imports(&["testdata"], &ast, &mut ImportCache::new()));
+
+        let offset = text.find("MyInterface my_interface").unwrap();
+        assert_eq!(
+            Err(RenameError::Imported("MyInterface".to_owned())),
+            prepare_rename(&ast, &imported_files, offset)
+        );
+        assert!(rename(&ast, &imported_files, offset, "Renamed").is_err());
+
+        // Declarations in the document can be renamed.
+        let offset = text.find("MyService").unwrap();
+        assert!(rename(&ast, &imported_files, offset, "Renamed")
+            .unwrap()
+            .is_some());
+    }
+}
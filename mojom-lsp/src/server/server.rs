@@ -12,17 +12,43 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
 
+use anyhow::anyhow;
 use serde_json::Value;
 
 use super::protocol::{
-    read_message, ErrorCodes, Message, NotificationMessage, RequestMessage, ResponseError,
+    ErrorCodes, Id, Message, NotificationMessage, RequestMessage, ResponseError,
 };
 
-use super::diagnostic::{start_diagnostics_thread, DiagnosticsThread};
-use super::messagesender::{start_message_sender_thread, MessageSender};
+use super::cancellation::{CancellationRegistry, CancellationToken};
+use super::code_action::is_requested;
+use super::code_lens::{resolve_references_lens, ReferencesLensData};
+use super::command::{
+    REINDEX_WORKSPACE_COMMAND, RESTART_DIAGNOSTICS_COMMAND, SHOW_ANALYSIS_COMMAND,
+};
+use super::connection::Connection;
+use super::custom::{TypeInfoParams, SERVER_STATUS_METHOD, TYPE_INFO_METHOD};
+use super::diagnostic::{start_diagnostics_thread, ClientCaps, DiagError, DiagnosticsThread};
+use super::inlay_hint::{InlayHintParams, INLAY_HINT_METHOD};
+use super::messagesender::MessageSender;
+use super::metrics::Metrics;
+use super::on_type_formatting::on_type_formatting;
+use super::progress::ProgressReporter;
+use super::range_formatting::range_formatting;
+use super::settings::Settings;
+use super::text_document::apply_change;
+use super::textpos::{LineIndex, PositionEncoding};
+use super::trace::{SetTraceParams, SET_TRACE_METHOD};
+use super::type_hierarchy::{
+    TypeHierarchyParams, PREPARE_TYPE_HIERARCHY_METHOD, SUBTYPES_METHOD, SUPERTYPES_METHOD,
+};
+use super::workspace_graph::WorkspaceGraph;
+use super::workspace_symbol::{start_workspace_symbol_thread, WorkspaceSymbolThread};
 
 #[derive(PartialEq)]
 enum State {
@@ -36,17 +62,44 @@ struct ServerContext {
     msg_sender: MessageSender,
     // A handler to the diagnostics thread.
     diag: DiagnosticsThread,
+    // A handler to the workspace symbol thread.
+    workspace_symbol: WorkspaceSymbolThread,
+    // Texts of open documents. Incremental changes are applied to them.
+    documents: HashMap<lsp_types::Url, String>,
+    // Workspace folders in the order the client sent them.
+    workspace_folders: Vec<lsp_types::Url>,
+    // The position encoding negotiated with the client.
+    encoding: PositionEncoding,
     // Set when `exit` notification is received.
     exit_code: Option<i32>,
+    // Cancellation tokens of requests. `$/cancelRequest` is recorded by the
+    // thread that reads messages.
+    cancellations: CancellationRegistry,
+    // Shared with the diagnostics thread.
+    metrics: Metrics,
 }
 
 impl ServerContext {
-    fn new(msg_sender: MessageSender, diag: DiagnosticsThread) -> ServerContext {
+    fn new(
+        msg_sender: MessageSender,
+        diag: DiagnosticsThread,
+        workspace_symbol: WorkspaceSymbolThread,
+        workspace_folders: Vec<lsp_types::Url>,
+        encoding: PositionEncoding,
+        cancellations: CancellationRegistry,
+        metrics: Metrics,
+    ) -> ServerContext {
         ServerContext {
             state: State::Initialized,
             msg_sender: msg_sender,
             diag: diag,
+            workspace_symbol,
+            documents: HashMap::new(),
+            workspace_folders,
+            encoding,
             exit_code: None,
+            cancellations,
+            metrics,
         }
     }
 }
@@ -57,7 +110,134 @@ fn get_request_params<P: serde::de::DeserializeOwned>(
     params: Value,
 ) -> std::result::Result<P, ResponseError> {
     serde_json::from_value::<P>(params)
-        .map_err(|err| ResponseError::new(ErrorCodes::InvalidRequest, err.to_string()))
+        .map_err(|err| ResponseError::new(ErrorCodes::InvalidParams, err.to_string()))
+}
+
+// Deserializes `params` and passes them to `handler`.
+fn with_params<P: serde::de::DeserializeOwned>(
+    params: Value,
+    handler: impl FnOnce(P) -> RequestResult,
+) -> RequestResult {
+    handler(get_request_params(params)?)
+}
+
+type RequestHandler = fn(&mut ServerContext, Value, CancellationToken) -> RequestResult;
+
+// Returns the handler of `method`, or None when the server doesn't support
+// the request.
+fn find_request_handler(method: &str) -> Option<RequestHandler> {
+    use lsp_types::request::*;
+
+    const HANDLERS: &[(&str, RequestHandler)] = &[
+        (Initialize::METHOD, |_, _, _| initialize_request()),
+        (Shutdown::METHOD, |ctx, _, _| shutdown_request(ctx)),
+        (GotoDefinition::METHOD, |ctx, params, token| {
+            with_params(params, |params| {
+                goto_definition_request(&mut ctx.diag, params, token)
+            })
+        }),
+        (GotoDeclaration::METHOD, |ctx, params, token| {
+            with_params(params, |params| {
+                goto_declaration_request(&mut ctx.diag, params, token)
+            })
+        }),
+        (HoverRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| hover_request(&mut ctx.diag, params))
+        }),
+        (DocumentSymbolRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                document_symbol_request(&mut ctx.diag, params)
+            })
+        }),
+        (CodeLensRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| code_lens_request(&mut ctx.diag, params))
+        }),
+        (CodeLensResolve::METHOD, |ctx, params, token| {
+            with_params(params, |lens| {
+                code_lens_resolve_request(&mut ctx.diag, lens, token)
+            })
+        }),
+        (FoldingRangeRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                folding_range_request(&mut ctx.diag, params)
+            })
+        }),
+        (INLAY_HINT_METHOD, |ctx, params, _| {
+            with_params(params, |params| inlay_hint_request(&mut ctx.diag, params))
+        }),
+        (DocumentLinkRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                document_link_request(&mut ctx.diag, params)
+            })
+        }),
+        (DocumentLinkResolve::METHOD, |ctx, params, _| {
+            with_params(params, |link| {
+                document_link_resolve_request(&mut ctx.diag, link)
+            })
+        }),
+        (References::METHOD, |ctx, params, token| {
+            with_params(params, |params| {
+                references_request(&mut ctx.diag, params, token)
+            })
+        }),
+        (DocumentHighlightRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                document_highlight_request(&mut ctx.diag, params)
+            })
+        }),
+        (Completion::METHOD, |ctx, params, _| {
+            with_params(params, |params| completion_request(&mut ctx.diag, params))
+        }),
+        (SignatureHelpRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                signature_help_request(&mut ctx.diag, params)
+            })
+        }),
+        (PrepareRenameRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                prepare_rename_request(&mut ctx.diag, params)
+            })
+        }),
+        (Rename::METHOD, |ctx, params, _| {
+            with_params(params, |params| rename_request(&mut ctx.diag, params))
+        }),
+        (CodeActionRequest::METHOD, |ctx, params, _| {
+            with_params(params, |params| code_action_request(&mut ctx.diag, params))
+        }),
+        (PREPARE_TYPE_HIERARCHY_METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                prepare_type_hierarchy_request(&mut ctx.diag, params)
+            })
+        }),
+        // Mojom has no inheritance.
+        (SUPERTYPES_METHOD, |_, _, _| Ok(Value::Array(Vec::new()))),
+        (SUBTYPES_METHOD, |ctx, params, token| {
+            with_params(params, |params| {
+                subtypes_request(&mut ctx.diag, params, token)
+            })
+        }),
+        (SERVER_STATUS_METHOD, |ctx, _, _| server_status_request(ctx)),
+        (TYPE_INFO_METHOD, |ctx, params, _| {
+            with_params(params, |params| type_info_request(&mut ctx.diag, params))
+        }),
+        (OnTypeFormatting::METHOD, |ctx, params, _| {
+            with_params(params, |params| on_type_formatting_request(ctx, params))
+        }),
+        (RangeFormatting::METHOD, |ctx, params, _| {
+            with_params(params, |params| range_formatting_request(ctx, params))
+        }),
+        (ExecuteCommand::METHOD, |ctx, params, _| {
+            with_params(params, |params| {
+                execute_command_request(&mut ctx.diag, params)
+            })
+        }),
+        // Accept following requests but do nothing.
+        (WillSaveWaitUntil::METHOD, |_, _, _| Ok(Value::Null)),
+    ];
+    HANDLERS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .map(|(_, handler)| *handler)
 }
 
 fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Result<()> {
@@ -65,6 +245,15 @@ fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Resul
     let method = msg.method.as_str();
     log::debug!("[recv] Request: id = {}, method = {}", id, method);
 
+    // JSON-RPC allows null ids only in responses to messages that couldn't
+    // be parsed. Such requests are rejected without being handled.
+    if id == Id::Null {
+        let message = format!("Request {} has a null id", method);
+        let err = ResponseError::new(ErrorCodes::InvalidRequest, message);
+        ctx.msg_sender.send_error_response(id, err);
+        return Ok(());
+    }
+
     // Workaround for Eglot. It sends "exit" as a request, not as a notification.
     if method == "exit" {
         exit_notification(ctx);
@@ -72,13 +261,40 @@ fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Resul
     }
 
     use lsp_types::request::*;
-    let res = match method {
-        Initialize::METHOD => initialize_request(),
-        Shutdown::METHOD => shutdown_request(ctx),
-        GotoDefinition::METHOD => get_request_params(msg.params)
-            .and_then(|params| goto_definition_request(&mut ctx.diag, params)),
-        _ => unimplemented_request(id, method),
+
+    let token = ctx.cancellations.token(&id);
+    if token.is_cancelled() {
+        ctx.cancellations.remove(&id);
+        let err = ResponseError::new(ErrorCodes::RequestCancelled, "Request cancelled".to_owned());
+        ctx.msg_sender.send_error_response(id, err);
+        return Ok(());
+    }
+
+    // The workspace symbol thread sends the response because indexing can
+    // take a long time.
+    if method == WorkspaceSymbol::METHOD {
+        ctx.cancellations.remove(&id);
+        match get_request_params::<lsp_types::WorkspaceSymbolParams>(msg.params) {
+            Ok(params) => ctx.workspace_symbol.query(id, params.query),
+            Err(err) => ctx.msg_sender.send_error_response(id, err),
+        }
+        return Ok(());
+    }
+    // Reindexing is answered by the workspace symbol thread when it's done.
+    if method == ExecuteCommand::METHOD && msg.params["command"] == REINDEX_WORKSPACE_COMMAND {
+        ctx.cancellations.remove(&id);
+        match ctx.diag.restart() {
+            Ok(()) => ctx.workspace_symbol.reindex(id),
+            Err(err) => ctx.msg_sender.send_error_response(id, err.into()),
+        }
+        return Ok(());
+    }
+
+    let res = match find_request_handler(method) {
+        Some(handler) => handler(ctx, msg.params, token),
+        None => unimplemented_request(&id, method),
     };
+    ctx.cancellations.remove(&id);
     match res {
         Ok(res) => {
             ctx.msg_sender.send_success_response(id, res);
@@ -90,12 +306,22 @@ fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Resul
 
 type RequestResult = std::result::Result<Value, ResponseError>;
 
-fn unimplemented_request(id: u64, method_name: &str) -> RequestResult {
+impl From<DiagError> for ResponseError {
+    fn from(err: DiagError) -> Self {
+        let code = match err {
+            DiagError::Cancelled => ErrorCodes::RequestCancelled,
+            DiagError::Failed | DiagError::Disconnected => ErrorCodes::InternalError,
+        };
+        ResponseError::new(code, err.to_string())
+    }
+}
+
+fn unimplemented_request(id: &Id, method_name: &str) -> RequestResult {
     let msg = format!(
         "Unimplemented request: id = {} method = {}",
         id, method_name
     );
-    let err = ResponseError::new(ErrorCodes::InternalError, msg);
+    let err = ResponseError::new(ErrorCodes::MethodNotFound, msg);
     Err(err)
 }
 
@@ -110,18 +336,323 @@ fn initialize_request() -> RequestResult {
 
 fn shutdown_request(ctx: &mut ServerContext) -> RequestResult {
     ctx.state = State::ShuttingDown;
+    ctx.workspace_symbol.cancel();
     Ok(Value::Null)
 }
 
 fn goto_definition_request(
     diag: &mut DiagnosticsThread,
     params: lsp_types::TextDocumentPositionParams,
+    token: CancellationToken,
+) -> RequestResult {
+    match diag.goto_definition(params.text_document.uri, params.position, token)? {
+        Some(res) => Ok(serde_json::to_value(res).unwrap()),
+        None => Ok(Value::Null),
+    }
+}
+
+fn goto_declaration_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
+    token: CancellationToken,
+) -> RequestResult {
+    match diag.goto_declaration(params.text_document.uri, params.position, token)? {
+        Some(loc) => Ok(serde_json::to_value(loc).unwrap()),
+        None => Ok(Value::Null),
+    }
+}
+
+fn hover_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
 ) -> RequestResult {
-    if let Some(loc) = diag.goto_definition(params.text_document.uri, params.position) {
-        let res = serde_json::to_value(loc).unwrap();
-        return Ok(res);
+    match diag.hover(params.text_document.uri, params.position)? {
+        Some(hover) => Ok(serde_json::to_value(hover).unwrap()),
+        None => Ok(Value::Null),
     }
-    return Ok(Value::Null);
+}
+
+fn code_lens_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::CodeLensParams,
+) -> RequestResult {
+    let lenses = diag.code_lens(params.text_document.uri)?;
+    Ok(serde_json::to_value(lenses).unwrap())
+}
+
+// Counts references of a reference lens in the same files that references
+// requests search, so that the count matches the references the lens shows.
+fn code_lens_resolve_request(
+    diag: &mut DiagnosticsThread,
+    lens: lsp_types::CodeLens,
+    token: CancellationToken,
+) -> RequestResult {
+    // Lenses without data are already resolved.
+    let data = lens
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<ReferencesLensData>(data).ok());
+    let lens = match data {
+        Some(data) => {
+            let references = diag.references(data.uri.clone(), lens.range.start, false, token)?;
+            resolve_references_lens(lens, &data, references.len())
+        }
+        None => lens,
+    };
+    Ok(serde_json::to_value(lens).unwrap())
+}
+
+fn document_symbol_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::DocumentSymbolParams,
+) -> RequestResult {
+    let symbols = diag.document_symbol(params.text_document.uri)?;
+    let res = lsp_types::DocumentSymbolResponse::Nested(symbols);
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+fn folding_range_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::FoldingRangeParams,
+) -> RequestResult {
+    let ranges = diag.folding_range(params.text_document.uri)?;
+    Ok(serde_json::to_value(ranges).unwrap())
+}
+
+fn inlay_hint_request(diag: &mut DiagnosticsThread, params: InlayHintParams) -> RequestResult {
+    let hints = diag.inlay_hint(params.text_document.uri, params.range)?;
+    Ok(serde_json::to_value(hints).unwrap())
+}
+
+fn document_link_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::DocumentLinkParams,
+) -> RequestResult {
+    let links = diag.document_link(params.text_document.uri)?;
+    Ok(serde_json::to_value(links).unwrap())
+}
+
+fn document_link_resolve_request(
+    diag: &mut DiagnosticsThread,
+    link: lsp_types::DocumentLink,
+) -> RequestResult {
+    let link = diag.resolve_document_link(link)?;
+    Ok(serde_json::to_value(link).unwrap())
+}
+
+fn references_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::ReferenceParams,
+    token: CancellationToken,
+) -> RequestResult {
+    let locations = diag.references(
+        params.text_document_position.text_document.uri,
+        params.text_document_position.position,
+        params.context.include_declaration,
+        token,
+    )?;
+    Ok(serde_json::to_value(locations).unwrap())
+}
+
+fn document_highlight_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::DocumentHighlightParams,
+) -> RequestResult {
+    let highlights = diag.document_highlight(
+        params.text_document_position_params.text_document.uri,
+        params.text_document_position_params.position,
+    )?;
+    Ok(serde_json::to_value(highlights).unwrap())
+}
+
+fn completion_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::CompletionParams,
+) -> RequestResult {
+    let items = diag.completion(
+        params.text_document_position.text_document.uri,
+        params.text_document_position.position,
+    )?;
+    Ok(serde_json::to_value(items).unwrap())
+}
+
+fn signature_help_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::SignatureHelpParams,
+) -> RequestResult {
+    let help = diag.signature_help(
+        params.text_document_position_params.text_document.uri,
+        params.text_document_position_params.position,
+    )?;
+    Ok(serde_json::to_value(help).unwrap())
+}
+
+fn prepare_rename_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
+) -> RequestResult {
+    match diag.prepare_rename(params.text_document.uri, params.position)? {
+        Ok(Some(range)) => {
+            let res = lsp_types::PrepareRenameResponse::Range(range);
+            Ok(serde_json::to_value(res).unwrap())
+        }
+        Ok(None) => Ok(Value::Null),
+        Err(err) => Err(ResponseError::new(
+            ErrorCodes::InvalidParams,
+            err.to_string(),
+        )),
+    }
+}
+
+fn rename_request(diag: &mut DiagnosticsThread, params: lsp_types::RenameParams) -> RequestResult {
+    let edit = diag.rename(
+        params.text_document_position.text_document.uri,
+        params.text_document_position.position,
+        params.new_name,
+    )?;
+    match edit {
+        Ok(edit) => Ok(serde_json::to_value(edit).unwrap()),
+        Err(err) => Err(ResponseError::new(
+            ErrorCodes::InvalidParams,
+            err.to_string(),
+        )),
+    }
+}
+
+fn code_action_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::CodeActionParams,
+) -> RequestResult {
+    let mut actions = Vec::new();
+    let uri = params.text_document.uri;
+    let kind = lsp_types::CodeActionKind::QUICKFIX;
+    if is_requested(&params.context.only, &kind) && !params.context.diagnostics.is_empty() {
+        let quick_fixes = diag.quick_fixes(uri.clone(), params.context.diagnostics)?;
+        actions.extend(
+            quick_fixes
+                .into_iter()
+                .map(lsp_types::CodeActionOrCommand::CodeAction),
+        );
+    }
+    let kind = lsp_types::CodeActionKind::SOURCE_ORGANIZE_IMPORTS;
+    if is_requested(&params.context.only, &kind) {
+        if let Some(action) = diag.organize_imports(uri)? {
+            actions.push(lsp_types::CodeActionOrCommand::CodeAction(action));
+        }
+    }
+    Ok(serde_json::to_value(actions).unwrap())
+}
+
+fn prepare_type_hierarchy_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
+) -> RequestResult {
+    let item = diag.prepare_type_hierarchy(params.text_document.uri, params.position)?;
+    // The result is an array of items.
+    Ok(serde_json::to_value(item.map(|item| vec![item])).unwrap())
+}
+
+fn subtypes_request(
+    diag: &mut DiagnosticsThread,
+    params: TypeHierarchyParams,
+    token: CancellationToken,
+) -> RequestResult {
+    let items = diag.subtypes(params.item, token)?;
+    Ok(serde_json::to_value(items).unwrap())
+}
+
+fn type_info_request(diag: &mut DiagnosticsThread, params: TypeInfoParams) -> RequestResult {
+    let uri = params.text_document.uri;
+    match diag.type_info(uri, params.type_name.clone())? {
+        Some(info) => Ok(serde_json::to_value(info).unwrap()),
+        None => Err(ResponseError::new(
+            ErrorCodes::InvalidParams,
+            format!("Type not found: {}", params.type_name),
+        )),
+    }
+}
+
+// `mojom.reindexWorkspace` is handled in `handle_request()`.
+fn execute_command_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::ExecuteCommandParams,
+) -> RequestResult {
+    match params.command.as_str() {
+        RESTART_DIAGNOSTICS_COMMAND => {
+            diag.restart()?;
+            Ok(Value::Null)
+        }
+        SHOW_ANALYSIS_COMMAND => {
+            let uri = params
+                .arguments
+                .into_iter()
+                .next()
+                .and_then(|argument| serde_json::from_value::<lsp_types::Url>(argument).ok())
+                .ok_or_else(|| {
+                    ResponseError::new(
+                        ErrorCodes::InvalidParams,
+                        "Expected a document URI".to_owned(),
+                    )
+                })?;
+            match diag.show_analysis(uri.clone())? {
+                Some(summary) => Ok(serde_json::to_value(summary).unwrap()),
+                None => Err(ResponseError::new(
+                    ErrorCodes::InvalidParams,
+                    format!("Document not found: {}", uri),
+                )),
+            }
+        }
+        command => Err(ResponseError::new(
+            ErrorCodes::InvalidParams,
+            format!("Unknown command: {}", command),
+        )),
+    }
+}
+
+fn server_status_request(ctx: &ServerContext) -> RequestResult {
+    let root_path = get_root_paths(&ctx.workspace_folders).into_iter().next();
+    let mut status = ctx.metrics.snapshot();
+    status["version"] = env!("CARGO_PKG_VERSION").into();
+    status["rootPath"] = serde_json::to_value(root_path).unwrap();
+    Ok(status)
+}
+
+// Formats the latest text because the diagnostics thread may not have
+// parsed the typed character yet.
+fn on_type_formatting_request(
+    ctx: &ServerContext,
+    params: lsp_types::DocumentOnTypeFormattingParams,
+) -> RequestResult {
+    let position = params.text_document_position;
+    let edits = match ctx.documents.get(&position.text_document.uri) {
+        Some(text) => {
+            let line_index = LineIndex::new(text, ctx.encoding);
+            on_type_formatting(
+                text,
+                &line_index,
+                position.position,
+                &params.ch,
+                &params.options,
+            )
+        }
+        None => Vec::new(),
+    };
+    Ok(serde_json::to_value(edits).unwrap())
+}
+
+// Formats the latest text for the same reason as on-type formatting.
+fn range_formatting_request(
+    ctx: &ServerContext,
+    params: lsp_types::DocumentRangeFormattingParams,
+) -> RequestResult {
+    let edits = match ctx.documents.get(&params.text_document.uri) {
+        Some(text) => {
+            let line_index = LineIndex::new(text, ctx.encoding);
+            range_formatting(text, &line_index, &params.range, &params.options)
+        }
+        None => Vec::new(),
+    };
+    Ok(serde_json::to_value(edits).unwrap())
 }
 
 // Notifications
@@ -134,21 +665,51 @@ fn handle_notification(ctx: &mut ServerContext, msg: NotificationMessage) -> any
     log::debug!("[recv] Notification: method = {}", msg.method);
 
     use lsp_types::notification::*;
-    match msg.method.as_str() {
-        Exit::METHOD => exit_notification(ctx),
+    // Failures of the diagnostics thread don't stop the server. They are
+    // logged because notifications have no response.
+    let res = match msg.method.as_str() {
+        Exit::METHOD => {
+            exit_notification(ctx);
+            Ok(())
+        }
         DidOpenTextDocument::METHOD => {
-            get_params(msg.params).map(|params| did_open_text_document(ctx, params))?;
+            get_params(msg.params).map(|params| did_open_text_document(ctx, params))?
         }
         DidChangeTextDocument::METHOD => {
-            get_params(msg.params).map(|params| did_change_text_document(ctx, params))?;
+            get_params(msg.params).map(|params| did_change_text_document(ctx, params))?
+        }
+        DidCloseTextDocument::METHOD => {
+            get_params(msg.params).map(|params| did_close_text_document(ctx, params))?
+        }
+        DidSaveTextDocument::METHOD => {
+            get_params(msg.params).map(|params| did_save_text_document(ctx, params))?
+        }
+        Cancel::METHOD => {
+            get_params(msg.params).map(|params| cancel_notification(ctx, params))?;
+            Ok(())
+        }
+        DidChangeWorkspaceFolders::METHOD => {
+            get_params(msg.params).map(|params| did_change_workspace_folders(ctx, params))?
+        }
+        DidChangeConfiguration::METHOD => {
+            get_params(msg.params).map(|params| did_change_configuration(ctx, params))?
+        }
+        DidChangeWatchedFiles::METHOD => {
+            get_params(msg.params).map(|params| did_change_watched_files(ctx, params))?
+        }
+        SET_TRACE_METHOD => {
+            get_params(msg.params).map(|params| set_trace(ctx, params))?;
+            Ok(())
         }
         // Accept following notifications but do nothing.
-        DidChangeConfiguration::METHOD => (),
-        WillSaveTextDocument::METHOD => (),
-        DidSaveTextDocument::METHOD => (),
+        WillSaveTextDocument::METHOD => Ok(()),
         _ => {
             log::warn!("Received unimplemented notification: {:#?}", msg);
+            Ok(())
         }
+    };
+    if let Err(err) = res {
+        log::error!("Failed to handle {}: {}", msg.method, err);
     }
     Ok(())
 }
@@ -162,23 +723,117 @@ fn exit_notification(ctx: &mut ServerContext) {
     }
 }
 
-fn did_open_text_document(ctx: &mut ServerContext, params: lsp_types::DidOpenTextDocumentParams) {
+fn set_trace(ctx: &mut ServerContext, params: SetTraceParams) {
+    ctx.msg_sender.set_trace(params.value);
+}
+
+fn cancel_notification(ctx: &mut ServerContext, params: lsp_types::CancelParams) {
+    // The reader thread already cancelled the request. The request has been
+    // answered at this point because messages are handled in order.
+    ctx.cancellations.remove(&params.id.into());
+}
+
+fn did_change_configuration(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidChangeConfigurationParams,
+) -> Result<(), DiagError> {
+    // Malformed settings are ignored and the current settings are kept.
+    match Settings::parse(params.settings) {
+        Some(settings) => ctx.diag.update_settings(settings),
+        None => Ok(()),
+    }
+}
+
+fn did_change_workspace_folders(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidChangeWorkspaceFoldersParams,
+) -> Result<(), DiagError> {
+    let event = params.event;
+    ctx.workspace_folders
+        .retain(|uri| !event.removed.iter().any(|folder| &folder.uri == uri));
+    for folder in event.added {
+        if !ctx.workspace_folders.contains(&folder.uri) {
+            ctx.workspace_folders.push(folder.uri);
+        }
+    }
     ctx.diag
-        .check(params.text_document.uri, params.text_document.text);
+        .set_root_paths(get_root_paths(&ctx.workspace_folders))
+}
+
+fn did_change_watched_files(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidChangeWatchedFilesParams,
+) -> Result<(), DiagError> {
+    let uris = params
+        .changes
+        .into_iter()
+        .map(|change| change.uri)
+        .collect::<Vec<_>>();
+    for uri in &uris {
+        ctx.workspace_symbol.did_change_file(uri.clone());
+    }
+    ctx.diag.did_change_watched_files(uris)
+}
+
+// Clients that don't include the text on save are handled by reading the
+// saved file.
+fn did_save_text_document(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidSaveTextDocumentParams,
+) -> Result<(), DiagError> {
+    let uri = params.text_document.uri;
+    ctx.workspace_symbol.did_change_file(uri.clone());
+    // Clients that don't send the text have already sent every change, so
+    // the open document has the saved text. Only files that aren't open are
+    // read from the disk.
+    let text = params
+        .text
+        .or_else(|| ctx.documents.get(&uri).cloned())
+        .or_else(|| std::fs::read_to_string(uri.to_file_path().ok()?).ok());
+    let text = match text {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    if let Some(document) = ctx.documents.get_mut(&uri) {
+        *document = text.clone();
+    }
+    ctx.diag.did_save(uri, text)
+}
+
+fn did_open_text_document(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidOpenTextDocumentParams,
+) -> Result<(), DiagError> {
+    let uri = params.text_document.uri;
+    let text = params.text_document.text;
+    let version = params.text_document.version;
+    ctx.documents.insert(uri.clone(), text.clone());
+    ctx.metrics.set_documents_open(ctx.documents.len());
+    ctx.diag.did_open(uri.clone())?;
+    ctx.diag.check(uri, text, Some(version))
+}
+
+fn did_close_text_document(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidCloseTextDocumentParams,
+) -> Result<(), DiagError> {
+    ctx.documents.remove(&params.text_document.uri);
+    ctx.metrics.set_documents_open(ctx.documents.len());
+    ctx.diag.did_close(params.text_document.uri)
 }
 
 fn did_change_text_document(
     ctx: &mut ServerContext,
     params: lsp_types::DidChangeTextDocumentParams,
-) {
-    let uri = params.text_document.uri.clone();
-    let content = params
-        .content_changes
-        .iter()
-        .map(|i| i.text.to_owned())
-        .collect::<Vec<_>>();
-    let text = content.join("");
-    ctx.diag.check(uri, text);
+) -> Result<(), DiagError> {
+    let uri = params.text_document.uri;
+    let version = params.text_document.version;
+    let text = ctx.documents.entry(uri.clone()).or_default();
+    for change in &params.content_changes {
+        apply_change(text, change, ctx.encoding);
+    }
+    let text = text.clone();
+    ctx.diag.check(uri, text, Some(version))
 }
 
 fn is_chromium_src_dir(path: &PathBuf) -> bool {
@@ -208,65 +863,267 @@ fn find_chromium_src_dir(mut path: PathBuf) -> PathBuf {
     original
 }
 
-fn get_root_path(params: &lsp_types::InitializeParams) -> Option<PathBuf> {
-    let uri = match params.root_uri {
-        Some(ref uri) => uri,
-        None => return None,
+fn get_workspace_folders(params: &lsp_types::InitializeParams) -> Vec<lsp_types::Url> {
+    match params.workspace_folders {
+        Some(ref folders) if !folders.is_empty() => {
+            folders.iter().map(|folder| folder.uri.clone()).collect()
+        }
+        _ => params.root_uri.iter().cloned().collect(),
+    }
+}
+
+fn get_root_paths(workspace_folders: &[lsp_types::Url]) -> Vec<PathBuf> {
+    let mut root_paths = Vec::new();
+    for uri in workspace_folders {
+        let path = match uri.to_file_path() {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+
+        // Try to find chromium's `src` directory and use it if exists.
+        let path = find_chromium_src_dir(path);
+        if !root_paths.contains(&path) {
+            root_paths.push(path);
+        }
+    }
+    root_paths
+}
+
+// Returns the request id of `$/cancelRequest`.
+fn cancel_request_id(message: &Message) -> Option<Id> {
+    use lsp_types::notification::{Cancel, Notification};
+
+    let notification = match message {
+        Message::Notification(notification) if notification.method == Cancel::METHOD => {
+            notification
+        }
+        _ => return None,
     };
-    let path = match uri.to_file_path() {
-        Ok(path) => path,
-        Err(_) => return None,
+    let params = serde_json::from_value::<lsp_types::CancelParams>(notification.params.clone());
+    Some(params.ok()?.id.into())
+}
+
+// Passes messages to the main loop on a separate thread so that
+// `$/cancelRequest` is observed while a request is being handled. Messages
+// that already arrived are taken together and cancellations among them are
+// recorded before they are passed to the main loop.
+fn start_dispatcher_thread(
+    receiver: Receiver<Message>,
+    cancellations: CancellationRegistry,
+) -> Receiver<Message> {
+    let (sender, main_receiver) = channel();
+    thread::spawn(move || {
+        while let Ok(message) = receiver.recv() {
+            let messages = std::iter::once(message)
+                .chain(receiver.try_iter())
+                .collect::<Vec<_>>();
+            for id in messages.iter().filter_map(cancel_request_id) {
+                cancellations.cancel(id);
+            }
+            for message in messages {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    main_receiver
+}
+
+// Asks the client to send `workspace/didChangeWatchedFiles` for mojom files
+// when the client supports dynamic registration. The response is ignored.
+fn register_file_watcher(msg_sender: &MessageSender, capabilities: &lsp_types::ClientCapabilities) {
+    use lsp_types::notification::{DidChangeWatchedFiles, Notification};
+    use lsp_types::request::{RegisterCapability, Request};
+
+    let supported = capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+        .and_then(|watched_files| watched_files.dynamic_registration)
+        .unwrap_or(false);
+    if !supported {
+        return;
+    }
+    let options = lsp_types::DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![lsp_types::FileSystemWatcher {
+            glob_pattern: "**/*.mojom".to_owned(),
+            kind: None,
+        }],
     };
+    let params = lsp_types::RegistrationParams {
+        registrations: vec![lsp_types::Registration {
+            id: "mojom-lsp/watched-files".to_owned(),
+            method: DidChangeWatchedFiles::METHOD.to_owned(),
+            register_options: Some(serde_json::to_value(options).unwrap()),
+        }],
+    };
+    let params = serde_json::to_value(params).unwrap();
+    msg_sender.send_request(RegisterCapability::METHOD, params);
+}
 
-    // Try to find chromium's `src` directory and use it if exists.
-    let path = find_chromium_src_dir(path);
-    Some(path)
+fn handle_message(ctx: &mut ServerContext, message: Message) -> anyhow::Result<()> {
+    ctx.msg_sender.trace_received(&message);
+    match &message {
+        Message::Request(request) => ctx.metrics.record_message(&request.method),
+        Message::Notification(notification) => ctx.metrics.record_message(&notification.method),
+        Message::Response(_) => (),
+    }
+    match message {
+        Message::Request(request) => handle_request(ctx, request),
+        Message::Notification(notification) => handle_notification(ctx, notification),
+        Message::Response(response) => {
+            ctx.msg_sender.handle_response(response);
+            Ok(())
+        }
+    }
 }
 
-// Returns exit code.
-pub fn start<R, W>(reader: R, writer: W) -> anyhow::Result<i32>
-where
-    R: Read,
-    W: Write + Send + 'static,
-{
-    let mut reader = BufReader::new(reader);
-    let mut writer = BufWriter::new(writer);
+/// A language server that talks to a client over a `Connection`.
+pub struct Server {
+    ctx: ServerContext,
+    receiver: Receiver<Message>,
+    max_content_length: usize,
+    // Notifications that arrived before `initialized`.
+    early_notifications: Vec<NotificationMessage>,
+}
 
-    let params = super::initialization::initialize(&mut reader, &mut writer)?;
+impl Server {
+    /// Answers `initialize` on `connection`, waits for `initialized` and
+    /// starts the threads of the server. Fails when the first message isn't
+    /// `initialize`. Notifications before `initialized` are handled by
+    /// `run()`.
+    pub fn new(connection: Connection) -> anyhow::Result<Server> {
+        let (params, encoding, early_notifications) =
+            super::initialization::initialize(&connection)?;
 
-    let root_path = get_root_path(&params).unwrap_or(PathBuf::new());
+        let workspace_folders = get_workspace_folders(&params);
+        let root_paths = get_root_paths(&workspace_folders);
+        // Workspace symbols are indexed only under the first root.
+        let root_path = root_paths.first().cloned().unwrap_or_default();
+        let settings = Settings::from_value(params.initialization_options.clone());
+        let max_content_length = settings.max_content_length;
+        let index_cache_path = super::index_cache::cache_path(&settings.cache, &root_path);
+        let index_on_startup = settings.index_on_startup;
 
-    let msg_sender_thread = start_message_sender_thread(writer);
-    let diag = start_diagnostics_thread(root_path, msg_sender_thread.get_sender());
+        let msg_sender = MessageSender::new(connection.sender);
+        msg_sender.set_trace(params.trace.unwrap_or(lsp_types::TraceOption::Off));
+        let client_caps = ClientCaps::new(&params.capabilities);
+        let metrics = Metrics::default();
+        let workspace_graph = WorkspaceGraph::default();
+        let diag = start_diagnostics_thread(
+            root_paths,
+            settings,
+            encoding,
+            client_caps,
+            msg_sender.clone(),
+            metrics.clone(),
+            workspace_graph.clone(),
+        );
+        register_file_watcher(&msg_sender, &params.capabilities);
+        let progress = ProgressReporter::new(msg_sender.clone(), &params.capabilities);
+        let workspace_symbol = start_workspace_symbol_thread(
+            root_path,
+            encoding,
+            index_cache_path,
+            index_on_startup,
+            workspace_graph,
+            msg_sender.clone(),
+            progress,
+        );
 
-    let mut ctx = ServerContext::new(msg_sender_thread.get_sender(), diag);
-    loop {
-        let message = read_message(&mut reader)?;
-        match message {
-            Message::Request(request) => handle_request(&mut ctx, request)?,
-            Message::Notofication(notification) => handle_notification(&mut ctx, notification)?,
-            _ => unreachable!(),
-        };
+        let cancellations = CancellationRegistry::default();
+        let ctx = ServerContext::new(
+            msg_sender,
+            diag,
+            workspace_symbol,
+            workspace_folders,
+            encoding,
+            cancellations.clone(),
+            metrics,
+        );
+        let receiver = start_dispatcher_thread(connection.receiver, cancellations);
+        Ok(Server {
+            ctx,
+            receiver,
+            max_content_length,
+            early_notifications,
+        })
+    }
+
+    /// The maximum size of a message from `initializationOptions`.
+    /// Connections that read streams should apply it.
+    pub fn max_content_length(&self) -> usize {
+        self.max_content_length
+    }
+
+    /// Handles messages until `exit` and returns the exit code. Fails when
+    /// the connection is closed before `exit`. The server's threads finish
+    /// their queued messages before this returns.
+    pub fn run(mut self) -> anyhow::Result<i32> {
+        let res = self.handle_messages();
+        let ctx = self.ctx;
+        ctx.workspace_symbol.shutdown();
+        if let Err(err) = ctx.diag.shutdown() {
+            log::error!("Failed to stop the diagnostics thread: {}", err);
+        }
+        res
+    }
 
-        if let Some(exit_code) = ctx.exit_code {
-            return Ok(exit_code);
+    fn handle_messages(&mut self) -> anyhow::Result<i32> {
+        let ctx = &mut self.ctx;
+        for notification in self.early_notifications.drain(..) {
+            handle_message(ctx, Message::Notification(notification))?;
+            if let Some(exit_code) = ctx.exit_code {
+                return Ok(exit_code);
+            }
+        }
+        loop {
+            let message = self
+                .receiver
+                .recv()
+                .map_err(|_| anyhow!("Connection closed before exit"))?;
+            handle_message(ctx, message)?;
+            if let Some(exit_code) = ctx.exit_code {
+                return Ok(exit_code);
+            }
         }
     }
 }
 
+/// Runs a server that reads messages from `reader` and writes messages to
+/// `writer`. Returns the exit code.
+pub fn start<R, W>(reader: R, writer: W) -> anyhow::Result<i32>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let (connection, handle) = Connection::stream(reader, writer);
+    // Errors of reading explain why the connection was closed.
+    let server = Server::new(connection).map_err(|err| handle.take_error().unwrap_or(err))?;
+    handle.set_max_content_length(server.max_content_length());
+    let res = server
+        .run()
+        .map_err(|err| handle.take_error().unwrap_or(err));
+    // Messages that are still queued, e.g. the response to `shutdown`, are
+    // written before the exit code is returned.
+    handle.join_writer();
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::protocol::{self, read_message, write_notification, write_request};
+    use super::super::test_util::TempDir;
     use super::*;
 
+    use std::io::BufReader;
+
     use lsp_types::notification::*;
     use lsp_types::request::*;
     use pipe::pipe;
 
-    #[test]
-    fn test_server_init() {
-        let (reader, mut writer) = pipe();
-
+    fn initialize_params() -> serde_json::Value {
         let capabilities = lsp_types::ClientCapabilities {
             workspace: None,
             text_document: None,
@@ -280,14 +1137,24 @@ mod tests {
             process_id: None,
             root_path: None,
             root_uri: None,
-            initialization_options: None,
+            // Tests must not write the index of their roots to the cache
+            // directory of the user.
+            initialization_options: Some(serde_json::json!({
+                "cache": { "workspaceIndex": false },
+            })),
             capabilities: capabilities,
             trace: None,
             workspace_folders: None,
             client_info: None,
             locale: None,
         };
-        let params = serde_json::to_value(&params).unwrap();
+        serde_json::to_value(&params).unwrap()
+    }
+
+    #[test]
+    fn test_server_init() {
+        let (reader, mut writer) = pipe();
+        let params = initialize_params();
 
         let (r, w) = pipe();
         let handle = std::thread::spawn(move || {
@@ -297,7 +1164,7 @@ mod tests {
 
         write_request(
             &mut writer,
-            1,
+            Id::Number(1),
             lsp_types::request::Initialize::METHOD,
             params,
         )
@@ -307,7 +1174,12 @@ mod tests {
         let msg = read_message(&mut r).unwrap();
         match msg {
             protocol::Message::Response(msg) => {
-                assert_eq!(1, msg.id);
+                assert_eq!(Id::Number(1), msg.id);
+                // The client doesn't advertise `positionEncodings`.
+                let result = msg.result.unwrap();
+                assert_eq!("utf-16", result["capabilities"]["positionEncoding"]);
+                assert_eq!(true, result["capabilities"]["inlayHintProvider"]);
+                assert_eq!(true, result["capabilities"]["typeHierarchyProvider"]);
             }
             _ => unreachable!(),
         }
@@ -321,7 +1193,7 @@ mod tests {
 
         write_request(
             &mut writer,
-            2,
+            Id::Number(2),
             lsp_types::request::Shutdown::METHOD,
             serde_json::Value::Null,
         )
@@ -330,7 +1202,7 @@ mod tests {
         let msg = read_message(&mut r).unwrap();
         match msg {
             protocol::Message::Response(msg) => {
-                assert_eq!(2, msg.id);
+                assert_eq!(Id::Number(2), msg.id);
             }
             _ => unreachable!(),
         }
@@ -348,4 +1220,1193 @@ mod tests {
         let status = handle.join().unwrap();
         assert!(status.is_ok());
     }
+
+    #[test]
+    fn test_get_root_paths() {
+        let testdata = std::path::Path::new("testdata").canonicalize().unwrap();
+        let foo_module = testdata.join("foo_module");
+        let root_uri = lsp_types::Url::from_file_path(&testdata).unwrap();
+        let folder_uri = lsp_types::Url::from_file_path(&foo_module).unwrap();
+
+        #[allow(deprecated)]
+        let mut params = lsp_types::InitializeParams {
+            root_uri: Some(root_uri.clone()),
+            ..serde_json::from_value(initialize_params()).unwrap()
+        };
+        // Falls back to `rootUri`.
+        let folders = get_workspace_folders(&params);
+        assert_eq!(vec![testdata.clone()], get_root_paths(&folders));
+
+        params.workspace_folders = Some(vec![
+            lsp_types::WorkspaceFolder {
+                uri: folder_uri.clone(),
+                name: "foo_module".to_owned(),
+            },
+            lsp_types::WorkspaceFolder {
+                uri: root_uri,
+                name: "testdata".to_owned(),
+            },
+        ]);
+        let folders = get_workspace_folders(&params);
+        assert_eq!(vec![foo_module, testdata], get_root_paths(&folders));
+    }
+
+    #[test]
+    fn test_invalid_message() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let handle = std::thread::spawn(move || start(reader, w));
+        let mut r = BufReader::new(r);
+        write_request(
+            &mut writer,
+            Id::Number(1),
+            Initialize::METHOD,
+            initialize_params(),
+        )
+        .unwrap();
+        read_message(&mut r).unwrap();
+        write_notification(&mut writer, Initialized::METHOD, Value::Null).unwrap();
+
+        // Empty and malformed messages get parse errors. The server keeps
+        // running.
+        for content in &["", "{"] {
+            write!(
+                writer,
+                "Content-Length: {}\r\n\r\n{}",
+                content.len(),
+                content
+            )
+            .unwrap();
+            match read_message(&mut r).unwrap() {
+                protocol::Message::Response(msg) => {
+                    assert_eq!(Id::Null, msg.id);
+                    assert_eq!(-32700, msg.error.unwrap().code);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        write_request(&mut writer, Id::Number(2), Shutdown::METHOD, Value::Null).unwrap();
+        read_message(&mut r).unwrap();
+        write_notification(&mut writer, Exit::METHOD, Value::Null).unwrap();
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_shutdown_with_pending_messages() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let handle = std::thread::spawn(move || start(reader, w));
+        write_request(
+            &mut writer,
+            Id::Number(1),
+            Initialize::METHOD,
+            initialize_params(),
+        )
+        .unwrap();
+        write_notification(&mut writer, Initialized::METHOD, Value::Null).unwrap();
+
+        // Nothing is read until `exit` so messages pile up in the queues.
+        let uris = (0..8)
+            .map(|i| lsp_types::Url::parse(&format!("file:///test{}.mojom", i)).unwrap())
+            .collect::<Vec<_>>();
+        for uri in &uris {
+            let params = lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    "mojom".to_owned(),
+                    1,
+                    "struct Foo { Missing missing; };".to_owned(),
+                ),
+            };
+            write_notification(
+                &mut writer,
+                DidOpenTextDocument::METHOD,
+                serde_json::to_value(params).unwrap(),
+            )
+            .unwrap();
+        }
+        write_request(&mut writer, Id::Number(2), Shutdown::METHOD, Value::Null).unwrap();
+        write_notification(&mut writer, Exit::METHOD, Value::Null).unwrap();
+
+        // Reads everything the server writes until it closes the output.
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let mut r = BufReader::new(r);
+            let mut messages = Vec::new();
+            while let Ok(message) = read_message(&mut r) {
+                messages.push(message);
+            }
+            sender
+                .send((messages, handle.join().unwrap().unwrap()))
+                .unwrap();
+        });
+        let (messages, exit_code) = receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("The server didn't stop");
+        assert_eq!(0, exit_code);
+
+        // Queued diagnostics are published before the server stops.
+        let mut published = messages
+            .iter()
+            .filter_map(|message| match message {
+                protocol::Message::Notification(msg)
+                    if msg.method == PublishDiagnostics::METHOD =>
+                {
+                    let params = serde_json::from_value::<lsp_types::PublishDiagnosticsParams>(
+                        msg.params.clone(),
+                    )
+                    .unwrap();
+                    Some(params.uri)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        published.sort();
+        published.dedup();
+        assert_eq!(uris, published);
+        assert!(messages.iter().any(|message| matches!(
+            message,
+            protocol::Message::Response(msg) if msg.id == Id::Number(2)
+        )));
+    }
+
+    #[test]
+    fn test_closed_connection() {
+        // The input ends before `exit`.
+        let (reader, writer) = pipe();
+        let (_r, w) = pipe();
+        let handle = std::thread::spawn(move || start(reader, w));
+        drop(writer);
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    // A client that exchanges messages with a server in the same process.
+    struct TestClient {
+        connection: Connection,
+        handle: std::thread::JoinHandle<anyhow::Result<i32>>,
+    }
+
+    impl TestClient {
+        // Starts a server and initializes it.
+        fn start() -> TestClient {
+            TestClient::start_with(initialize_params(), Vec::new())
+        }
+
+        // Starts a server whose root is `testdata` and opens the file at
+        // `path` in it. Returns the URI of the file.
+        fn start_in_testdata(path: &str) -> (TestClient, lsp_types::Url) {
+            let testdata = std::path::Path::new("testdata").canonicalize().unwrap();
+            let mut params = initialize_params();
+            params["rootUri"] =
+                serde_json::to_value(lsp_types::Url::from_file_path(&testdata).unwrap()).unwrap();
+            let client = TestClient::start_with(params, Vec::new());
+
+            let path = testdata.join(path);
+            let uri = lsp_types::Url::from_file_path(&path).unwrap();
+            client.open(&uri, &std::fs::read_to_string(&path).unwrap());
+            client.read_published_diagnostics();
+            (client, uri)
+        }
+
+        // Starts a server without initializing it.
+        fn connect() -> TestClient {
+            let (server, connection) = Connection::memory();
+            TestClient {
+                connection,
+                handle: std::thread::spawn(move || Server::new(server)?.run()),
+            }
+        }
+
+        // Starts a server with `params` of `initialize`. `messages` are sent
+        // right after `initialized` before the server starts handling them.
+        fn start_with(params: Value, messages: Vec<Message>) -> TestClient {
+            let client = TestClient::connect();
+            client.request(1, Initialize::METHOD, params);
+            client.notify(Initialized::METHOD, Value::Null);
+            for message in messages {
+                client.connection.sender.send(message).unwrap();
+            }
+            client.read_response(1);
+            client
+        }
+
+        fn request(&self, id: i64, method: &str, params: Value) {
+            let message = Message::Request(RequestMessage {
+                id: Id::Number(id),
+                method: method.to_owned(),
+                params,
+            });
+            self.connection.sender.send(message).unwrap();
+        }
+
+        fn notify(&self, method: &str, params: impl serde::Serialize) {
+            let message = Message::Notification(NotificationMessage {
+                method: method.to_owned(),
+                params: serde_json::to_value(params).unwrap(),
+            });
+            self.connection.sender.send(message).unwrap();
+        }
+
+        fn respond(&self, id: Id, result: Value) {
+            let message = Message::Response(protocol::ResponseMessage {
+                id,
+                result: Some(result),
+                error: None,
+            });
+            self.connection.sender.send(message).unwrap();
+        }
+
+        // Receives the next message. Fails instead of hanging when the server
+        // doesn't send anything.
+        fn receive(&self) -> Message {
+            let timeout = std::time::Duration::from_secs(10);
+            self.connection.receiver.recv_timeout(timeout).unwrap()
+        }
+
+        // Receives the next message, which must be a response to `id`.
+        fn read_response(&self, id: i64) -> protocol::ResponseMessage {
+            match self.receive() {
+                Message::Response(msg) => {
+                    assert_eq!(Id::Number(id), msg.id);
+                    msg
+                }
+                msg => panic!("Expected a response but got {:?}", msg),
+            }
+        }
+
+        // Receives the next message, which must be
+        // `textDocument/publishDiagnostics`.
+        fn read_published_diagnostics(&self) -> lsp_types::PublishDiagnosticsParams {
+            match self.receive() {
+                Message::Notification(msg) => {
+                    assert_eq!(PublishDiagnostics::METHOD, msg.method);
+                    serde_json::from_value(msg.params).unwrap()
+                }
+                msg => panic!("Expected diagnostics but got {:?}", msg),
+            }
+        }
+
+        fn open(&self, uri: &lsp_types::Url, text: &str) {
+            let params = lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    "mojom".to_owned(),
+                    1,
+                    text.to_owned(),
+                ),
+            };
+            self.notify(DidOpenTextDocument::METHOD, params);
+        }
+
+        // Shuts down the server with a shutdown request of `id`.
+        fn stop(self, id: i64) {
+            self.request(id, Shutdown::METHOD, Value::Null);
+            self.read_response(id);
+            self.notify(Exit::METHOD, Value::Null);
+            assert_eq!(0, self.handle.join().unwrap().unwrap());
+        }
+    }
+
+    fn position_params(uri: &str, line: u32, character: u32) -> Value {
+        let params = lsp_types::TextDocumentPositionParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: lsp_types::Url::parse(uri).unwrap(),
+            },
+            position: lsp_types::Position::new(line, character),
+        };
+        serde_json::to_value(params).unwrap()
+    }
+
+    #[test]
+    fn test_messages_before_initialized() {
+        let client = TestClient::connect();
+        client.request(1, Initialize::METHOD, initialize_params());
+        client.read_response(1);
+
+        // The document is checked after initialization. The request fails
+        // without stopping the server.
+        let uri = lsp_types::Url::parse("file:///broken.mojom").unwrap();
+        client.open(&uri, "struct Broken {");
+        let params = lsp_types::DocumentSymbolParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        client.request(
+            2,
+            DocumentSymbolRequest::METHOD,
+            serde_json::to_value(params).unwrap(),
+        );
+        let res = client.read_response(2);
+        assert_eq!(-32002, res.error.unwrap().code);
+
+        client.notify(Initialized::METHOD, Value::Null);
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert_eq!(1, published.diagnostics.len());
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_exit_before_initialized() {
+        let client = TestClient::connect();
+        client.request(1, Initialize::METHOD, initialize_params());
+        client.read_response(1);
+        client.notify(Exit::METHOD, Value::Null);
+        assert_eq!(1, client.handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        // The cancellation arrives together with the request so that it is
+        // recorded before the request is handled.
+        let request = Message::Request(RequestMessage {
+            id: Id::Number(2),
+            method: GotoDefinition::METHOD.to_owned(),
+            params: position_params("file:///test.mojom", 0, 0),
+        });
+        let cancel = lsp_types::CancelParams {
+            id: lsp_types::NumberOrString::Number(2),
+        };
+        let cancel = Message::Notification(NotificationMessage {
+            method: Cancel::METHOD.to_owned(),
+            params: serde_json::to_value(cancel).unwrap(),
+        });
+        let client = TestClient::start_with(initialize_params(), vec![request, cancel]);
+
+        let res = client.read_response(2);
+        assert_eq!(-32800, res.error.unwrap().code);
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_null_request_id() {
+        let request = Message::Request(RequestMessage {
+            id: Id::Null,
+            method: Shutdown::METHOD.to_owned(),
+            params: Value::Null,
+        });
+        let client = TestClient::start_with(initialize_params(), vec![request]);
+
+        let res = match client.receive() {
+            Message::Response(res) => res,
+            msg => panic!("Expected a response but got {:?}", msg),
+        };
+        assert_eq!(Id::Null, res.id);
+        assert_eq!(-32600, res.error.unwrap().code);
+
+        // The shutdown request wasn't handled, so exiting is an error.
+        client.notify(Exit::METHOD, Value::Null);
+        assert_eq!(1, client.handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_unknown_response() {
+        let client = TestClient::start();
+
+        // Responses to unknown requests are ignored.
+        client.respond(Id::Number(100), Value::Null);
+
+        client.stop(2);
+    }
+
+    #[test]
+    fn test_request_errors() {
+        let client = TestClient::start();
+
+        client.request(2, DocumentColor::METHOD, serde_json::json!({}));
+        let res = client.read_response(2);
+        assert_eq!(-32601, res.error.unwrap().code);
+
+        let params = serde_json::json!("garbage");
+        client.request(3, GotoDefinition::METHOD, params);
+        let res = client.read_response(3);
+        assert_eq!(-32602, res.error.unwrap().code);
+
+        // Ignored requests succeed with an empty result.
+        let params = lsp_types::WillSaveTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: lsp_types::Url::parse("file:///test.mojom").unwrap(),
+            },
+            reason: lsp_types::TextDocumentSaveReason::MANUAL,
+        };
+        let params = serde_json::to_value(params).unwrap();
+        client.request(4, WillSaveWaitUntil::METHOD, params);
+        let res = client.read_response(4);
+        assert!(res.error.is_none());
+
+        client.stop(5);
+    }
+
+    #[test]
+    fn test_set_trace() {
+        let client = TestClient::start();
+        let read_log_trace = || match client.receive() {
+            Message::Notification(msg) => {
+                assert_eq!("$/logTrace", msg.method);
+                msg.params
+            }
+            msg => panic!("Expected a trace but got {:?}", msg),
+        };
+
+        // Trace is off by default.
+        client.request(2, DocumentColor::METHOD, serde_json::json!({}));
+        client.read_response(2);
+
+        client.notify("$/setTrace", serde_json::json!({ "value": "messages" }));
+        client.request(3, DocumentColor::METHOD, serde_json::json!({}));
+        let log = read_log_trace();
+        assert_eq!(
+            serde_json::json!({ "message": "Received request 'textDocument/documentColor - (3)'." }),
+            log
+        );
+        let log = read_log_trace();
+        assert!(log["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Sending response 'textDocument/documentColor - (3)'. Request failed"));
+        client.read_response(3);
+
+        // `$/setTrace` is traced with the previous value.
+        client.notify("$/setTrace", serde_json::json!({ "value": "verbose" }));
+        let log = read_log_trace();
+        assert_eq!("Received notification '$/setTrace'.", log["message"]);
+        assert!(log["verbose"].is_null());
+        client.notify(WillSaveTextDocument::METHOD, serde_json::json!({}));
+        let log = read_log_trace();
+        assert_eq!("Params: {}", log["verbose"]);
+
+        client.notify("$/setTrace", serde_json::json!({ "value": "off" }));
+        read_log_trace();
+        client.request(4, DocumentColor::METHOD, serde_json::json!({}));
+        client.read_response(4);
+
+        client.stop(5);
+    }
+
+    #[test]
+    fn test_goto_definition() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(&uri, "struct Foo {};\nstruct Bar {\n  Foo foo;\n};");
+        let published = client.read_published_diagnostics();
+        assert!(published.diagnostics.is_empty());
+
+        client.request(
+            2,
+            GotoDefinition::METHOD,
+            position_params(uri.as_str(), 2, 3),
+        );
+        let res = client.read_response(2);
+        let location = serde_json::from_value::<lsp_types::Location>(res.result.unwrap()).unwrap();
+        assert_eq!(uri, location.uri);
+        assert_eq!(lsp_types::Position::new(0, 7), location.range.start);
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_goto_definition_link() {
+        let mut params = initialize_params();
+        params["capabilities"] = serde_json::json!({
+            "textDocument": { "definition": { "linkSupport": true } },
+        });
+        let client = TestClient::start_with(params, Vec::new());
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(&uri, "struct Foo {};\nstruct Bar {\n  Foo foo;\n};");
+        client.read_published_diagnostics();
+
+        client.request(
+            2,
+            GotoDefinition::METHOD,
+            position_params(uri.as_str(), 2, 3),
+        );
+        let res = client.read_response(2);
+        let links =
+            serde_json::from_value::<Vec<lsp_types::LocationLink>>(res.result.unwrap()).unwrap();
+        assert_eq!(1, links.len());
+        let origin = links[0].origin_selection_range.unwrap();
+        assert_eq!(lsp_types::Position::new(2, 2), origin.start);
+        assert_eq!(lsp_types::Position::new(2, 5), origin.end);
+        assert_eq!(uri, links[0].target_uri);
+        assert_eq!(lsp_types::Position::new(0, 0), links[0].target_range.start);
+        assert_eq!(
+            lsp_types::Position::new(0, 7),
+            links[0].target_selection_range.start
+        );
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_goto_definition_runtime_feature() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let text = r#"feature kMyFeature {
+  const string name = "MyFeature";
+  const bool default_state = false;
+};
+[RuntimeFeature=kMyFeature]
+interface Foo {};
+"#;
+        client.open(&uri, text);
+        client.read_published_diagnostics();
+
+        client.request(
+            2,
+            GotoDefinition::METHOD,
+            position_params(uri.as_str(), 4, 20),
+        );
+        let res = client.read_response(2);
+        let location = serde_json::from_value::<lsp_types::Location>(res.result.unwrap()).unwrap();
+        assert_eq!(uri, location.uri);
+        assert_eq!(lsp_types::Position::new(0, 8), location.range.start);
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_goto_definition_missing_file() {
+        let client = TestClient::start();
+
+        let params = position_params("file:///no/such/file.mojom", 0, 0);
+        client.request(2, GotoDefinition::METHOD, params.clone());
+        let res = client.read_response(2);
+        assert!(res.error.is_none());
+        assert_eq!(Value::Null, res.result.unwrap_or_default());
+
+        client.request(3, GotoDeclaration::METHOD, params);
+        let res = client.read_response(3);
+        assert!(res.error.is_none());
+        assert_eq!(Value::Null, res.result.unwrap_or_default());
+
+        client.stop(4);
+    }
+
+    #[test]
+    fn test_invalid_fixed_array_size() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(
+            &uri,
+            "struct Foo {\n  array<uint8, 99999999999999999999> bytes;\n};",
+        );
+        let published = client.read_published_diagnostics();
+        assert_eq!(1, published.diagnostics.len());
+        let diagnostic = &published.diagnostics[0];
+        assert_eq!(
+            Some(lsp_types::NumberOrString::String(
+                "mojom/invalid-array-size".to_owned()
+            )),
+            diagnostic.code
+        );
+        assert_eq!(lsp_types::Position::new(1, 15), diagnostic.range.start);
+        assert_eq!(lsp_types::Position::new(1, 35), diagnostic.range.end);
+
+        // The server keeps handling requests on the document.
+        client.request(2, HoverRequest::METHOD, position_params(uri.as_str(), 1, 4));
+        let res = client.read_response(2);
+        assert!(res.error.is_none());
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_execute_command() {
+        let (client, uri) = TestClient::start_in_testdata("usages/canvas.mojom");
+
+        // Commands that check documents again publish diagnostics around
+        // their responses.
+        let execute = |id: i64, params: Value| {
+            client.request(id, ExecuteCommand::METHOD, params);
+            loop {
+                match client.receive() {
+                    Message::Response(msg) => {
+                        assert_eq!(Id::Number(id), msg.id);
+                        break msg;
+                    }
+                    Message::Notification(msg) => {
+                        assert_eq!(PublishDiagnostics::METHOD, msg.method)
+                    }
+                    msg => panic!("Unexpected message {:?}", msg),
+                }
+            }
+        };
+
+        let show_analysis = serde_json::json!({
+            "command": "mojom.showAnalysis",
+            "arguments": [uri],
+        });
+        let summary = execute(2, show_analysis.clone()).result.unwrap();
+        assert_eq!(uri.as_str(), summary["uri"]);
+        assert_eq!("canvas.mojom", summary["moduleName"]);
+        assert_eq!(1, summary["statements"]["interface"]);
+        assert_eq!("usages/geometry.mojom", summary["imports"][0]["path"]);
+        assert_eq!("parsed", summary["imports"][0]["status"]);
+
+        // The response is sent after the workspace is indexed again.
+        let res = execute(
+            3,
+            serde_json::json!({ "command": "mojom.reindexWorkspace" }),
+        );
+        assert!(res.error.is_none());
+        assert_eq!(Some(Value::Null), res.result);
+
+        let res = execute(
+            4,
+            serde_json::json!({ "command": "mojom.restartDiagnostics" }),
+        );
+        assert!(res.error.is_none());
+        assert_eq!(Some(Value::Null), res.result);
+        // Answered after the document is checked again.
+        let res = execute(5, show_analysis);
+        assert_eq!(summary, res.result.unwrap());
+
+        let res = execute(6, serde_json::json!({ "command": "mojom.unknown" }));
+        assert_eq!(-32602, res.error.unwrap().code);
+        let res = execute(7, serde_json::json!({ "command": "mojom.showAnalysis" }));
+        assert!(res.error.is_some());
+
+        client.stop(8);
+    }
+
+    #[test]
+    fn test_type_info() {
+        let (client, uri) = TestClient::start_in_testdata("usages/canvas.mojom");
+
+        let type_info = |id: i64, type_name: &str| {
+            let params = serde_json::json!({
+                "textDocument": { "uri": uri },
+                "typeName": type_name,
+            });
+            client.request(id, "mojom/typeInfo", params);
+            client.read_response(id)
+        };
+
+        let res = type_info(2, "canvas.mojom.Canvas");
+        let info = res.result.unwrap();
+        assert_eq!("interface", info["kind"]);
+        assert_eq!(uri.as_str(), info["uri"]);
+        assert_eq!(4, info["selectionRange"]["start"]["line"]);
+        let methods = info["methods"].as_array().unwrap();
+        assert_eq!(3, methods.len());
+        let draw_line = &methods[0];
+        assert_eq!("DrawLine", draw_line["name"]);
+        assert_eq!(Value::Null, draw_line["responseParameters"]);
+        assert_eq!(
+            serde_json::json!({
+                "name": "from",
+                "type": "geometry.mojom.Point",
+                "typeSpec": { "kind": "named", "name": "geometry.mojom.Point", "nullable": false },
+                "ordinal": null,
+                "range": {
+                    "start": { "line": 5, "character": 11 },
+                    "end": { "line": 5, "character": 36 },
+                },
+            }),
+            draw_line["parameters"][0]
+        );
+        assert_eq!("success", methods[1]["responseParameters"][0]["name"]);
+
+        // Types in imported files are described with their files.
+        let res = type_info(3, "geometry.mojom.Direction");
+        let info = res.result.unwrap();
+        assert_eq!("enum", info["kind"]);
+        assert!(info["uri"]
+            .as_str()
+            .unwrap()
+            .ends_with("usages/geometry.mojom"));
+        assert_eq!(
+            serde_json::json!([["kForward", 0], ["kBackward", 1]]),
+            Value::Array(
+                info["values"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|value| serde_json::json!([value["name"], value["value"]]))
+                    .collect()
+            )
+        );
+
+        let res = type_info(4, "geometry.mojom.Point");
+        let info = res.result.unwrap();
+        assert_eq!("geometry.mojom.Point", info["qualifiedName"]);
+        let fields = info["fields"].as_array().unwrap();
+        assert_eq!(
+            vec!["x", "y"],
+            fields
+                .iter()
+                .map(|field| field["name"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        let res = type_info(5, "geometry.mojom.Missing");
+        assert_eq!(-32602, res.error.unwrap().code);
+
+        client.stop(6);
+    }
+
+    #[test]
+    fn test_code_lens() {
+        let (client, uri) = TestClient::start_in_testdata("usages/geometry.mojom");
+
+        let params = lsp_types::CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        client.request(
+            2,
+            CodeLensRequest::METHOD,
+            serde_json::to_value(params).unwrap(),
+        );
+        let res = client.read_response(2);
+        let lenses =
+            serde_json::from_value::<Vec<lsp_types::CodeLens>>(res.result.unwrap()).unwrap();
+        let starts = lenses
+            .iter()
+            .map(|lens| (lens.range.start.line, lens.range.start.character))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(4, 7), (4, 7), (14, 10), (14, 10)], starts);
+        assert_eq!(
+            "C++: geometry::mojom::Locator | TS: usages/geometry.mojom-webui.js",
+            lenses[3].command.as_ref().unwrap().title
+        );
+
+        // Files that import the declaration are known once the workspace is
+        // indexed, which is done before the query is answered.
+        client.request(
+            10,
+            WorkspaceSymbol::METHOD,
+            serde_json::json!({ "query": "Point" }),
+        );
+        client.read_response(10);
+
+        // `Point` is used in both files under `usages`.
+        client.request(
+            3,
+            CodeLensResolve::METHOD,
+            serde_json::to_value(&lenses[0]).unwrap(),
+        );
+        let res = client.read_response(3);
+        let lens = serde_json::from_value::<lsp_types::CodeLens>(res.result.unwrap()).unwrap();
+        let command = lens.command.unwrap();
+        assert_eq!("4 references", command.title);
+        assert_eq!("mojom.showReferences", command.command);
+
+        client.request(
+            4,
+            CodeLensResolve::METHOD,
+            serde_json::to_value(&lenses[2]).unwrap(),
+        );
+        let res = client.read_response(4);
+        let lens = serde_json::from_value::<lsp_types::CodeLens>(res.result.unwrap()).unwrap();
+        assert_eq!("0 references", lens.command.unwrap().title);
+
+        // Resolved lenses are returned as they are.
+        client.request(
+            5,
+            CodeLensResolve::METHOD,
+            serde_json::to_value(&lenses[1]).unwrap(),
+        );
+        let res = client.read_response(5);
+        assert_eq!(
+            serde_json::to_value(&lenses[1]).unwrap(),
+            res.result.unwrap()
+        );
+
+        // Unsaved changes of open documents are counted.
+        let canvas = uri.join("canvas.mojom").unwrap();
+        let text = std::fs::read_to_string(canvas.to_file_path().unwrap()).unwrap();
+        let text = text.replace("Clear();", "Clear();\n  Fill(geometry.mojom.Point at);");
+        client.open(&canvas, &text);
+        client.read_published_diagnostics();
+        client.request(
+            11,
+            CodeLensResolve::METHOD,
+            serde_json::to_value(&lenses[0]).unwrap(),
+        );
+        let res = client.read_response(11);
+        let lens = serde_json::from_value::<lsp_types::CodeLens>(res.result.unwrap()).unwrap();
+        assert_eq!("5 references", lens.command.unwrap().title);
+
+        client.stop(6);
+    }
+
+    #[test]
+    fn test_server_status() {
+        let client = TestClient::start();
+
+        client.request(2, "mojom/serverStatus", Value::Null);
+        let status = client.read_response(2).result.unwrap();
+        assert_eq!(0, status["parseDurations"]["count"]);
+        assert_eq!(env!("CARGO_PKG_VERSION"), status["version"]);
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(&uri, "struct Foo {};");
+        client.read_published_diagnostics();
+
+        client.request(3, "mojom/serverStatus", Value::Null);
+        let status = client.read_response(3).result.unwrap();
+        assert_eq!(1, status["parseDurations"]["count"]);
+        assert_eq!(1, status["documentsOpen"]);
+        assert_eq!(1, status["messages"]["textDocument/didOpen"]);
+        assert_eq!(2, status["messages"]["mojom/serverStatus"]);
+
+        client.stop(4);
+    }
+
+    #[test]
+    fn test_on_type_formatting() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(&uri, "interface Foo {\n  Bar(\n\n};\n");
+        client.read_published_diagnostics();
+
+        let params = lsp_types::DocumentOnTypeFormattingParams {
+            text_document_position: lsp_types::TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                lsp_types::Position::new(2, 0),
+            ),
+            ch: "\n".to_owned(),
+            options: lsp_types::FormattingOptions {
+                tab_size: 2,
+                insert_spaces: true,
+                ..Default::default()
+            },
+        };
+        client.request(
+            2,
+            OnTypeFormatting::METHOD,
+            serde_json::to_value(params).unwrap(),
+        );
+        let res = client.read_response(2);
+        let edits =
+            serde_json::from_value::<Vec<lsp_types::TextEdit>>(res.result.unwrap()).unwrap();
+        assert_eq!(
+            vec![lsp_types::TextEdit::new(
+                lsp_types::Range::new(
+                    lsp_types::Position::new(2, 0),
+                    lsp_types::Position::new(2, 0)
+                ),
+                "  ".to_owned()
+            )],
+            edits
+        );
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_range_formatting() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(
+            &uri,
+            "struct Foo {\nint32 a;\n};\nstruct Bar {\nint32 b;\n};\n",
+        );
+        client.read_published_diagnostics();
+
+        // The range starts in the middle of `Foo`.
+        let params = lsp_types::DocumentRangeFormattingParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(1, 2),
+                lsp_types::Position::new(1, 4),
+            ),
+            options: lsp_types::FormattingOptions {
+                tab_size: 2,
+                insert_spaces: true,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+        };
+        client.request(
+            2,
+            RangeFormatting::METHOD,
+            serde_json::to_value(params).unwrap(),
+        );
+        let res = client.read_response(2);
+        let edits =
+            serde_json::from_value::<Vec<lsp_types::TextEdit>>(res.result.unwrap()).unwrap();
+        assert_eq!(
+            vec![lsp_types::TextEdit::new(
+                lsp_types::Range::new(
+                    lsp_types::Position::new(1, 0),
+                    lsp_types::Position::new(1, 0)
+                ),
+                "  ".to_owned()
+            )],
+            edits
+        );
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_published_severities() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        client.open(&uri, "module a;\nmodule b;\nstruct Foo { Missing m; };");
+        let published = client.read_published_diagnostics();
+        let severities = published
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, Some(lsp_types::DiagnosticSeverity::ERROR)),
+                (2, Some(lsp_types::DiagnosticSeverity::WARNING)),
+            ],
+            severities
+        );
+
+        // The syntax tree is kept despite the errors.
+        client.request(2, HoverRequest::METHOD, position_params(uri.as_str(), 2, 8));
+        let res = client.read_response(2);
+        let hover = serde_json::from_value::<lsp_types::Hover>(res.result.unwrap()).unwrap();
+        match hover.contents {
+            lsp_types::HoverContents::Markup(content) => assert!(content.value.contains("Foo")),
+            contents => panic!("Unexpected hover contents: {:?}", contents),
+        }
+
+        client.stop(3);
+    }
+
+    #[test]
+    fn test_close_document() {
+        let client = TestClient::start();
+
+        let uri = lsp_types::Url::parse("file:///broken.mojom").unwrap();
+        client.open(&uri, "struct Broken {");
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert_eq!(1, published.diagnostics.len());
+
+        let params = lsp_types::DidCloseTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+        };
+        client.notify(DidCloseTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+
+        client.stop(2);
+    }
+
+    #[test]
+    fn test_did_change_watched_files() {
+        let temp_dir = TempDir::new("watch");
+        let dir = temp_dir.path().to_owned();
+        let dir = dir.canonicalize().unwrap();
+        let imported = dir.join("imported.mojom");
+        std::fs::write(&imported, "struct Imported {};").unwrap();
+        let importer = dir.join("importer.mojom");
+        let text = "import \"imported.mojom\";\nstruct Importer { Imported value; };";
+        std::fs::write(&importer, text).unwrap();
+
+        let mut params = initialize_params();
+        params["rootUri"] = lsp_types::Url::from_file_path(&dir)
+            .unwrap()
+            .as_str()
+            .into();
+        params["capabilities"]["workspace"] =
+            serde_json::json!({ "didChangeWatchedFiles": { "dynamicRegistration": true } });
+        let client = TestClient::start_with(params, Vec::new());
+
+        // The server registers a watcher for mojom files.
+        match client.receive() {
+            Message::Request(msg) => {
+                assert_eq!(RegisterCapability::METHOD, msg.method);
+                let registration = &msg.params["registrations"][0];
+                assert_eq!(DidChangeWatchedFiles::METHOD, registration["method"]);
+                assert_eq!(
+                    "**/*.mojom",
+                    registration["registerOptions"]["watchers"][0]["globPattern"]
+                );
+                client.respond(msg.id, Value::Null);
+            }
+            _ => unreachable!(),
+        }
+
+        let uri = lsp_types::Url::from_file_path(&importer).unwrap();
+        client.open(&uri, text);
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+
+        // Deleting the imported file is reported without editing the
+        // document.
+        std::fs::remove_file(&imported).unwrap();
+        let params = lsp_types::DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: lsp_types::Url::from_file_path(&imported).unwrap(),
+                typ: lsp_types::FileChangeType::DELETED,
+            }],
+        };
+        client.notify(DidChangeWatchedFiles::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert!(!published.diagnostics.is_empty());
+
+        // Creating it again resolves the import.
+        std::fs::write(&imported, "struct Imported {};").unwrap();
+        let params = lsp_types::DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: lsp_types::Url::from_file_path(&imported).unwrap(),
+                typ: lsp_types::FileChangeType::CREATED,
+            }],
+        };
+        client.notify(DidChangeWatchedFiles::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+
+        client.stop(2);
+    }
+
+    #[test]
+    fn test_did_save() {
+        let temp_dir = TempDir::new("save");
+        let dir = temp_dir.path().to_owned();
+        let dir = dir.canonicalize().unwrap();
+        let imported = dir.join("imported.mojom");
+        std::fs::write(&imported, "struct Imported {};").unwrap();
+        let importer = dir.join("importer.mojom");
+        let text = "import \"imported.mojom\";\nstruct Importer { Imported a; Added b; };";
+        std::fs::write(&importer, text).unwrap();
+
+        let mut params = initialize_params();
+        params["rootUri"] = lsp_types::Url::from_file_path(&dir)
+            .unwrap()
+            .as_str()
+            .into();
+        let client = TestClient::start_with(params, Vec::new());
+
+        let importer_uri = lsp_types::Url::from_file_path(&importer).unwrap();
+        client.open(&importer_uri, text);
+        let published = client.read_published_diagnostics();
+        assert_eq!(importer_uri, published.uri);
+        assert_eq!(1, published.diagnostics.len());
+
+        let imported_uri = lsp_types::Url::from_file_path(&imported).unwrap();
+        client.open(&imported_uri, "struct Imported {};");
+        let published = client.read_published_diagnostics();
+        assert_eq!(imported_uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+
+        // Adding the struct doesn't affect the importer until the file is
+        // saved.
+        let added = "struct Imported {};\nstruct Added {};";
+        let params = lsp_types::DidChangeTextDocumentParams {
+            text_document: lsp_types::VersionedTextDocumentIdentifier::new(imported_uri.clone(), 2),
+            content_changes: vec![lsp_types::TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: added.to_owned(),
+            }],
+        };
+        client.notify(DidChangeTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(imported_uri, published.uri);
+
+        std::fs::write(&imported, added).unwrap();
+        let params = lsp_types::DidSaveTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(imported_uri.clone()),
+            text: Some(added.to_owned()),
+        };
+        client.notify(DidSaveTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(imported_uri, published.uri);
+        let published = client.read_published_diagnostics();
+        assert_eq!(importer_uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+
+        // Without the text, the open document is checked rather than the
+        // file, which another program may have changed.
+        std::fs::write(&imported, "struct Imported {").unwrap();
+        let params = lsp_types::DidSaveTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(imported_uri.clone()),
+            text: None,
+        };
+        client.notify(DidSaveTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(imported_uri, published.uri);
+        assert!(published.diagnostics.is_empty());
+        let published = client.read_published_diagnostics();
+        assert_eq!(importer_uri, published.uri);
+
+        // Files that aren't open are read.
+        std::fs::write(&imported, "struct Imported {};").unwrap();
+        let params = lsp_types::DidCloseTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(imported_uri.clone()),
+        };
+        client.notify(DidCloseTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(imported_uri, published.uri);
+        let params = lsp_types::DidSaveTextDocumentParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(imported_uri.clone()),
+            text: None,
+        };
+        client.notify(DidSaveTextDocument::METHOD, params);
+        let published = client.read_published_diagnostics();
+        assert_eq!(importer_uri, published.uri);
+        assert_eq!(1, published.diagnostics.len());
+
+        client.stop(2);
+    }
+
+    #[test]
+    fn test_string_request_id() {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || Server::new(server)?.run());
+
+        let request = |id: &Id, method: &str, params: Value| {
+            let message = Message::Request(RequestMessage {
+                id: id.clone(),
+                method: method.to_owned(),
+                params,
+            });
+            client.sender.send(message).unwrap();
+        };
+        let notify = |method: &str| {
+            let message = Message::Notification(NotificationMessage {
+                method: method.to_owned(),
+                params: Value::Null,
+            });
+            client.sender.send(message).unwrap();
+        };
+
+        let id = Id::String("init".to_owned());
+        request(&id, Initialize::METHOD, initialize_params());
+        match client.receiver.recv().unwrap() {
+            Message::Response(msg) => {
+                assert_eq!(id, msg.id);
+                assert!(msg.result.is_some());
+            }
+            _ => unreachable!(),
+        }
+        notify(Initialized::METHOD);
+
+        let id = Id::String("shutdown".to_owned());
+        request(&id, Shutdown::METHOD, Value::Null);
+        match client.receiver.recv().unwrap() {
+            Message::Response(msg) => assert_eq!(id, msg.id),
+            _ => unreachable!(),
+        }
+        notify(Exit::METHOD);
+
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
 }
@@ -0,0 +1,320 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::analysis;
+
+use super::protocol;
+
+/// Server settings. Clients pass these as `initializationOptions` and
+/// `workspace/didChangeConfiguration`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct Settings {
+    // Additional directories to search imported files in. Relative paths are
+    // relative to the first workspace root. They are searched after
+    // workspace roots.
+    pub(crate) import_roots: Vec<PathBuf>,
+    // Chromium output directories such as `out/Default`, relative to the
+    // first workspace root. Imports are also searched in their `gen`
+    // directories. They are discovered when not set.
+    pub(crate) out_dirs: Vec<PathBuf>,
+    // Optional checks. These are flattened so that e.g. `lints` is a
+    // top-level setting.
+    #[serde(flatten)]
+    pub(crate) checks: analysis::Options,
+    pub(crate) inlay_hints: InlayHintSettings,
+    pub(crate) cache: CacheSettings,
+    // Goto definition on `[Native]` types searches C++ headers for their
+    // definitions.
+    pub(crate) native_definitions: bool,
+    // Goto definition and declaration give up slow steps after this many
+    // milliseconds and return the best result found so far. 0 disables it.
+    pub(crate) definition_timeout_ms: u64,
+    // Indexes the workspace when the server starts rather than on the first
+    // workspace symbol query. Goto definition and references need the index
+    // to search other files of the module and dependents. Only read from
+    // `initializationOptions`.
+    pub(crate) index_on_startup: bool,
+    // Build flags offered when completing values of `EnableIf` and
+    // `EnableIfNot`.
+    pub(crate) build_flags: Vec<String>,
+    // The maximum size of a message from the client in bytes. Larger
+    // messages are skipped. Only read from `initializationOptions`.
+    pub(crate) max_content_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub(crate) struct InlayHintSettings {
+    // Shows implicit ordinals of fields and parameters.
+    pub(crate) ordinals: bool,
+}
+
+impl Default for InlayHintSettings {
+    fn default() -> Self {
+        InlayHintSettings { ordinals: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct CacheSettings {
+    // The number of documents that aren't open in the editor to keep parsed.
+    pub(crate) closed_documents: usize,
+    // Saves the workspace symbol index so that unchanged files aren't parsed
+    // again after restarts.
+    pub(crate) workspace_index: bool,
+    // The directory to save the index in, relative to the first workspace
+    // root. Defaults to the cache directory of the OS.
+    pub(crate) index_dir: Option<PathBuf>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            closed_documents: 16,
+            workspace_index: true,
+            index_dir: None,
+        }
+    }
+}
+
+// Flags that Chromium commonly passes to the mojom bindings generator.
+const DEFAULT_BUILD_FLAGS: &[&str] = &[
+    "is_android",
+    "is_chromeos",
+    "is_fuchsia",
+    "is_ios",
+    "is_linux",
+    "is_mac",
+    "is_posix",
+    "is_win",
+    "use_ozone",
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            import_roots: Vec::new(),
+            out_dirs: Vec::new(),
+            checks: analysis::Options::default(),
+            inlay_hints: InlayHintSettings::default(),
+            cache: CacheSettings::default(),
+            native_definitions: false,
+            definition_timeout_ms: 1000,
+            index_on_startup: true,
+            build_flags: DEFAULT_BUILD_FLAGS
+                .iter()
+                .map(|&flag| flag.to_owned())
+                .collect(),
+            max_content_length: protocol::DEFAULT_MAX_CONTENT_LENGTH,
+        }
+    }
+}
+
+impl Settings {
+    pub(crate) fn from_value(value: Option<Value>) -> Settings {
+        let value = match value {
+            Some(value) => value,
+            None => return Settings::default(),
+        };
+        Settings::parse(value).unwrap_or_default()
+    }
+
+    // Parses settings sent by `workspace/didChangeConfiguration`. Clients that
+    // send all of their settings put ours in the `mojom` section. Returns None
+    // when the settings are malformed.
+    pub(crate) fn parse(value: Value) -> Option<Settings> {
+        let value = match value {
+            Value::Object(mut map) if map.contains_key("mojom") => map.remove("mojom").unwrap(),
+            value => value,
+        };
+        match serde_json::from_value::<Settings>(value) {
+            Ok(settings) => Some(settings),
+            Err(err) => {
+                log::warn!("Invalid settings: {}", err);
+                None
+            }
+        }
+    }
+
+    // Settings from `workspace/didChangeConfiguration` don't change the ones
+    // that are only read from `initializationOptions`. Those keep the values
+    // of `current` so that changing them doesn't check documents again.
+    pub(crate) fn keep_initialization_only(mut self, current: &Settings) -> Settings {
+        self.cache.workspace_index = current.cache.workspace_index;
+        self.cache.index_dir = current.cache.index_dir.clone();
+        self.index_on_startup = current.index_on_startup;
+        self.max_content_length = current.max_content_length;
+        self
+    }
+
+    // Returns `gen` directories of `out_dirs`. Returns None when `out_dirs`
+    // isn't set.
+    pub(crate) fn gen_paths(&self, root_paths: &[PathBuf]) -> Option<Vec<PathBuf>> {
+        if self.out_dirs.is_empty() {
+            return None;
+        }
+        let base = base_path(root_paths);
+        let paths = self.out_dirs.iter().map(|path| base.join(path).join("gen"));
+        Some(paths.collect())
+    }
+
+    // Returns directories to resolve imports against in order: workspace
+    // roots, import roots and then `gen_paths`.
+    pub(crate) fn import_paths(
+        &self,
+        root_paths: &[PathBuf],
+        gen_paths: &[PathBuf],
+    ) -> Vec<PathBuf> {
+        let base = base_path(root_paths);
+        let import_roots = self.import_roots.iter().map(|path| base.join(path));
+        let mut paths = root_paths.to_vec();
+        for path in import_roots.chain(gen_paths.iter().cloned()) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+}
+
+// Relative paths in settings are relative to the first workspace root.
+fn base_path(root_paths: &[PathBuf]) -> &Path {
+    root_paths
+        .first()
+        .map(PathBuf::as_path)
+        .unwrap_or(Path::new(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use analysis::MethodOrdinalCheck;
+
+    #[test]
+    fn test_from_value() {
+        let settings = Settings::from_value(None);
+        assert!(!settings.checks.lints.member_order);
+        assert_eq!(MethodOrdinalCheck::Off, settings.checks.method_ordinals);
+
+        let value = serde_json::json!({ "lints": { "memberOrder": true }, "unknown": 1 });
+        let settings = Settings::from_value(Some(value));
+        assert!(settings.checks.lints.member_order);
+        assert!(!settings.checks.lints.naming.types);
+
+        let value =
+            serde_json::json!({ "lints": { "naming": { "types": true, "modules": true } } });
+        let settings = Settings::from_value(Some(value));
+        assert!(settings.checks.lints.naming.types);
+        assert!(settings.checks.lints.naming.modules);
+        assert!(!settings.checks.lints.naming.fields);
+
+        assert!(settings.inlay_hints.ordinals);
+        let value = serde_json::json!({ "inlayHints": { "ordinals": false } });
+        let settings = Settings::from_value(Some(value));
+        assert!(!settings.inlay_hints.ordinals);
+
+        assert_eq!(16, settings.cache.closed_documents);
+        let value = serde_json::json!({ "cache": { "closedDocuments": 0 } });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(0, settings.cache.closed_documents);
+        assert!(settings.cache.workspace_index);
+        let value =
+            serde_json::json!({ "cache": { "indexDir": ".cache", "workspaceIndex": false } });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(Some(PathBuf::from(".cache")), settings.cache.index_dir);
+        assert!(!settings.cache.workspace_index);
+
+        assert_eq!(64 * 1024 * 1024, settings.max_content_length);
+        assert!(!settings.native_definitions);
+        let value = serde_json::json!({ "nativeDefinitions": true });
+        let settings = Settings::from_value(Some(value));
+        assert!(settings.native_definitions);
+
+        assert_eq!(1000, settings.definition_timeout_ms);
+        let value = serde_json::json!({ "definitionTimeoutMs": 0 });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(0, settings.definition_timeout_ms);
+
+        assert!(settings.build_flags.iter().any(|flag| flag == "is_win"));
+        let value = serde_json::json!({ "buildFlags": ["is_cast"] });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(vec!["is_cast".to_owned()], settings.build_flags);
+
+        let value = serde_json::json!({ "maxContentLength": 1024 });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(1024, settings.max_content_length);
+
+        let value = serde_json::json!({ "methodOrdinals": "strict" });
+        let settings = Settings::from_value(Some(value));
+        assert_eq!(MethodOrdinalCheck::Strict, settings.checks.method_ordinals);
+
+        let value = serde_json::json!({ "lints": { "memberOrder": "yes" } });
+        let settings = Settings::from_value(Some(value));
+        assert!(!settings.checks.lints.member_order);
+
+        let value = serde_json::json!({
+            "semanticDiagnostics": false,
+            "severity": { "mojom/unresolved-type": "error" },
+        });
+        let settings = Settings::from_value(Some(value));
+        assert!(!settings.checks.semantic_diagnostics);
+        assert_eq!(
+            Some(&analysis::Severity::Error),
+            settings.checks.severity.get("mojom/unresolved-type")
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = serde_json::json!({ "mojom": { "importRoots": ["gen"] } });
+        let settings = Settings::parse(value).unwrap();
+        assert_eq!(vec![PathBuf::from("gen")], settings.import_roots);
+
+        let value = serde_json::json!({ "severity": { "mojom/duplicate-name": "fatal" } });
+        assert!(Settings::parse(value).is_none());
+    }
+
+    #[test]
+    fn test_import_paths() {
+        let value = serde_json::json!({ "importRoots": ["gen", "/abs", "."] });
+        let settings = Settings::from_value(Some(value));
+        let root_paths = vec![PathBuf::from("/src"), PathBuf::from("/other")];
+        let gen_paths = vec![PathBuf::from("/src/out/Default/gen")];
+        let paths = settings.import_paths(&root_paths, &gen_paths);
+        // `.` is the same as the first root.
+        let expected = ["/src", "/other", "/src/gen", "/abs", "/src/out/Default/gen"];
+        let expected = expected.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(expected, paths);
+    }
+
+    #[test]
+    fn test_gen_paths() {
+        let root_paths = vec![PathBuf::from("/src")];
+        assert_eq!(None, Settings::default().gen_paths(&root_paths));
+
+        let value = serde_json::json!({ "outDirs": ["out/Release", "/out/Debug"] });
+        let settings = Settings::from_value(Some(value));
+        let expected = ["/src/out/Release/gen", "/out/Debug/gen"];
+        let expected = expected.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(Some(expected), settings.gen_paths(&root_paths));
+    }
+}
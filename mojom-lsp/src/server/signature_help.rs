@@ -0,0 +1,287 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{ParameterInformation, ParameterLabel, SignatureHelp, SignatureInformation};
+
+use crate::syntax::{preorder, Method, Parameter, Traversal};
+
+use super::completion::{
+    enclosing_bracket, is_identifier_char, last_non_space, mask_comments_and_strings,
+};
+use super::mojomast::MojomAst;
+
+// The parameter list that the cursor is in.
+struct ParameterListContext<'a> {
+    method_name: &'a str,
+    // The `(` of the request parameters.
+    request_paren: usize,
+    in_response: bool,
+    // The number of commas between the `(` and the cursor.
+    commas: usize,
+}
+
+// Counts commas in `text` that aren't nested in other brackets.
+fn count_commas(text: &[u8]) -> usize {
+    let mut depth = 0;
+    let mut commas = 0;
+    for &ch in text {
+        match ch {
+            b'(' | b'<' | b'[' | b'{' => depth += 1,
+            b')' | b'>' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => commas += 1,
+            _ => (),
+        }
+    }
+    commas
+}
+
+// Finds the method declaration whose parameter list contains `offset`.
+// `masked` is the text before `offset` with comments and strings masked.
+fn parameter_list_context<'a>(
+    text: &'a str,
+    masked: &[u8],
+    offset: usize,
+) -> Option<ParameterListContext<'a>> {
+    let paren = enclosing_bracket(masked, offset)?;
+    if masked[paren] != b'(' {
+        return None;
+    }
+    let commas = count_commas(&masked[paren + 1..offset]);
+
+    // Response parameters follow `) =>`.
+    let mut request_paren = paren;
+    let mut in_response = false;
+    if let Some(arrow) = last_non_space(masked, paren) {
+        if arrow > 0 && &masked[arrow - 1..=arrow] == b"=>" {
+            let close = last_non_space(masked, arrow - 1)?;
+            if masked[close] != b')' {
+                return None;
+            }
+            request_paren = enclosing_bracket(masked, close)?;
+            in_response = true;
+        }
+    }
+
+    // The method name, optionally followed by an ordinal like `@1`.
+    let mut name_end = last_non_space(masked, request_paren)? + 1;
+    let ordinal_start = masked[..name_end]
+        .iter()
+        .rposition(|ch| !ch.is_ascii_digit())?;
+    if masked[ordinal_start] == b'@' {
+        name_end = last_non_space(masked, ordinal_start)? + 1;
+    }
+    let name_start = masked[..name_end]
+        .iter()
+        .rposition(|&ch| !is_identifier_char(ch))
+        .map_or(0, |pos| pos + 1);
+    if name_start == name_end {
+        return None;
+    }
+    Some(ParameterListContext {
+        method_name: &text[name_start..name_end],
+        request_paren,
+        in_response,
+        commas,
+    })
+}
+
+// Finds the method that is being edited. Methods of an up-to-date `ast` are
+// found by offset. Otherwise the method is looked up by name because offsets
+// of an older text don't match.
+fn find_method<'a>(
+    ast: &'a MojomAst,
+    text: &str,
+    context: &ParameterListContext,
+) -> Option<&'a Method> {
+    let up_to_date = ast.text == text;
+    preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::Method(method) if up_to_date => {
+            let range = &method.range;
+            if range.start <= context.request_paren && context.request_paren < range.end {
+                Some(method)
+            } else {
+                None
+            }
+        }
+        Traversal::Method(method) if ast.text(&method.name) == context.method_name => Some(method),
+        _ => None,
+    })
+}
+
+// Appends `params` to `label` and returns the ranges of the parameters in
+// `label`.
+fn push_params(ast: &MojomAst, params: &[Parameter], label: &mut String) -> Vec<[u32; 2]> {
+    let mut ranges = Vec::new();
+    label.push('(');
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+        let start = label.len() as u32;
+        label.push_str(ast.text(&param.typ));
+        label.push(' ');
+        label.push_str(ast.text(&param.name));
+        ranges.push([start, label.len() as u32]);
+    }
+    label.push(')');
+    ranges
+}
+
+// Formats the signature of `method`, e.g.
+// `Method(int32 a, string b) => (bool result)`.
+fn signature_information(ast: &MojomAst, method: &Method) -> SignatureInformation {
+    let mut label = ast.text(&method.name).to_owned();
+    let mut ranges = push_params(ast, &method.params, &mut label);
+    if let Some(response) = &method.response {
+        label.push_str(" => ");
+        ranges.extend(push_params(ast, &response.params, &mut label));
+    }
+    let parameters = ranges
+        .into_iter()
+        .map(|range| ParameterInformation {
+            label: ParameterLabel::LabelOffsets(range),
+            documentation: None,
+        })
+        .collect();
+    SignatureInformation {
+        label,
+        documentation: None,
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+/// Returns the signature of the method declaration whose parameter list
+/// contains `offset` of `text`. `ast` can be parsed from an older text
+/// because documents don't parse while typing. The active parameter counts
+/// request parameters first, then response parameters.
+pub(crate) fn signature_help(text: &str, offset: usize, ast: &MojomAst) -> Option<SignatureHelp> {
+    let offset = std::cmp::min(offset, text.len());
+    if !text.is_char_boundary(offset) {
+        return None;
+    }
+    let (masked, in_comment_or_string) = mask_comments_and_strings(&text[..offset]);
+    if in_comment_or_string {
+        return None;
+    }
+    let context = parameter_list_context(text, &masked, offset)?;
+    let method = find_method(ast, text, &context)?;
+
+    let mut active_parameter = context.commas;
+    if context.in_response {
+        active_parameter += method.params.len();
+    }
+    let active_parameter = Some(active_parameter as u32);
+    let mut signature = signature_information(ast, method);
+    signature.active_parameter = active_parameter;
+    Some(SignatureHelp {
+        signatures: vec![signature],
+        active_signature: Some(0),
+        active_parameter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::syntax;
+
+    const INPUT: &str = r#"interface Foo {
+  Method@1(int32 a, map<string, int32> b, string c) => (bool ok, string error);
+  Other();
+};
+"#;
+
+    fn create_ast(text: &str) -> MojomAst {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        MojomAst::from_mojom(uri, text.to_owned(), mojom, None)
+    }
+
+    // Returns the active parameter when the cursor is right after `before`.
+    fn active_parameter(text: &str, ast: &MojomAst, before: &str) -> Option<u32> {
+        let offset = text.find(before).unwrap() + before.len();
+        let help = signature_help(text, offset, ast)?;
+        assert_eq!(
+            "Method(int32 a, map<string, int32> b, string c) => (bool ok, string error)",
+            help.signatures[0].label
+        );
+        help.active_parameter
+    }
+
+    #[test]
+    fn test_signature_help() {
+        let ast = create_ast(INPUT);
+        assert_eq!(Some(0), active_parameter(INPUT, &ast, "Method@1("));
+        // The comma in `map<string, int32>` isn't counted.
+        assert_eq!(Some(1), active_parameter(INPUT, &ast, "int32 a,"));
+        assert_eq!(Some(2), active_parameter(INPUT, &ast, "int32> b,"));
+        // Response parameters follow request parameters.
+        assert_eq!(Some(3), active_parameter(INPUT, &ast, "=> ("));
+        assert_eq!(Some(4), active_parameter(INPUT, &ast, "bool ok,"));
+
+        assert!(active_parameter(INPUT, &ast, "interface Foo {").is_none());
+        let offset = INPUT.find("Other(").unwrap() + "Other(".len();
+        let help = signature_help(INPUT, offset, &ast).unwrap();
+        assert_eq!("Other()", help.signatures[0].label);
+    }
+
+    #[test]
+    fn test_signature_help_parameter_labels() {
+        let ast = create_ast(INPUT);
+        let offset = INPUT.find("Method@1(").unwrap() + "Method@1(".len();
+        let help = signature_help(INPUT, offset, &ast).unwrap();
+        let signature = &help.signatures[0];
+        let labels = signature
+            .parameters
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|param| match param.label {
+                ParameterLabel::LabelOffsets([start, end]) => {
+                    &signature.label[start as usize..end as usize]
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "int32 a",
+                "map<string, int32> b",
+                "string c",
+                "bool ok",
+                "string error"
+            ],
+            labels
+        );
+    }
+
+    #[test]
+    fn test_signature_help_while_typing() {
+        // The document doesn't parse while typing. The older tree is used.
+        let ast = create_ast(INPUT);
+        let text = INPUT.replace("string c)", "string c, )");
+        let offset = text.find("string c,").unwrap() + "string c,".len();
+        let help = signature_help(&text, offset, &ast).unwrap();
+        assert_eq!(Some(3), help.active_parameter);
+
+        // Comments are ignored.
+        let text = "interface Foo {\n  Method(int32 a, // b, c\n";
+        let help = signature_help(text, text.len() - 1, &ast);
+        assert!(help.is_none());
+        let help = signature_help(text, text.len(), &ast).unwrap();
+        assert_eq!(Some(1), help.active_parameter);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::TextDocumentContentChangeEvent;
+
+use super::conversion::to_offset_range;
+use super::textpos::{LineIndex, PositionEncoding};
+
+// Applies `change` to `text`. A change without a range replaces the whole
+// text.
+pub(crate) fn apply_change(
+    text: &mut String,
+    change: &TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
+) {
+    match &change.range {
+        Some(range) => {
+            let line_index = LineIndex::new(text, encoding);
+            let range = to_offset_range(&line_index, text, range);
+            text.replace_range(range.start..range.end, &change.text);
+        }
+        None => {
+            *text = change.text.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::{Position, Range};
+
+    const UTF16: PositionEncoding = PositionEncoding::Utf16;
+
+    fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_apply_change() {
+        let mut text = "struct Foo {\n  int32 a;\n  int32 b;\n};\n".to_owned();
+
+        // Insert at the end of the file.
+        apply_change(&mut text, &change((4, 0), (4, 0), "// end\n"), UTF16);
+        assert_eq!("struct Foo {\n  int32 a;\n  int32 b;\n};\n// end\n", text);
+
+        // Delete multiple lines.
+        apply_change(&mut text, &change((1, 0), (3, 0), ""), UTF16);
+        assert_eq!("struct Foo {\n};\n// end\n", text);
+
+        // Replace the whole text.
+        let full = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "module é;\n".to_owned(),
+        };
+        apply_change(&mut text, &full, UTF16);
+        assert_eq!("module é;\n", text);
+
+        // Edit after a multi-byte character in the same line.
+        apply_change(&mut text, &change((0, 8), (0, 8), "x"), UTF16);
+        assert_eq!("module éx;\n", text);
+        apply_change(&mut text, &change((0, 7), (0, 8), "e"), UTF16);
+        assert_eq!("module ex;\n", text);
+
+        // "é" is 2 bytes in UTF-8.
+        apply_change(&mut text, &full, UTF16);
+        apply_change(
+            &mut text,
+            &change((0, 9), (0, 9), "x"),
+            PositionEncoding::Utf8,
+        );
+        assert_eq!("module éx;\n", text);
+    }
+}
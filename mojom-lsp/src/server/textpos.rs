@@ -0,0 +1,278 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::Position;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How `character` of LSP positions is counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum PositionEncoding {
+    Utf8,
+    // The only encoding that all clients support.
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// Chooses the encoding from `general.positionEncodings` of the client
+    /// capabilities. UTF-8 is preferred when the client supports it.
+    pub(crate) fn negotiate(capabilities: &Value) -> Self {
+        let supports_utf8 = capabilities
+            .pointer("/general/positionEncodings")
+            .and_then(|encodings| encodings.as_array())
+            .map(|encodings| encodings.iter().any(|encoding| encoding == "utf-8"))
+            .unwrap_or(false);
+        if supports_utf8 {
+            PositionEncoding::Utf8
+        } else {
+            PositionEncoding::Utf16
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PositionEncoding::Utf8 => "utf-8",
+            PositionEncoding::Utf16 => "utf-16",
+        }
+    }
+
+    fn len(&self, ch: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => ch.len_utf8(),
+            PositionEncoding::Utf16 => ch.len_utf16(),
+        }
+    }
+}
+
+/// Converts between byte offsets of a text and LSP positions. Line starts are
+/// computed once so that a conversion only scans a single line.
+#[derive(Debug, Clone)]
+pub(crate) struct LineIndex {
+    // Byte offsets of the first character of each line.
+    line_starts: Vec<usize>,
+    encoding: PositionEncoding,
+}
+
+impl LineIndex {
+    pub(crate) fn new(text: &str, encoding: PositionEncoding) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(offset, _)| offset + 1));
+        LineIndex {
+            line_starts,
+            encoding,
+        }
+    }
+
+    pub(crate) fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Returns the zero-based line of `offset`.
+    pub(crate) fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// Converts `offset` of `text` into an LSP position. An offset inside a
+    /// multi-byte character is rounded down to the start of the character.
+    pub(crate) fn position(&self, text: &str, offset: usize) -> Position {
+        self.scan_position(text, offset).0
+    }
+
+    // Same as `position` but also returns the number of characters scanned.
+    fn scan_position(&self, text: &str, offset: usize) -> (Position, usize) {
+        let offset = offset.min(text.len());
+        let line = self.line(offset);
+        let line_start = self.line_starts[line];
+        let mut scanned = 0;
+        let character = text[line_start..]
+            .char_indices()
+            .take_while(|(i, ch)| line_start + i + ch.len_utf8() <= offset)
+            .map(|(_, ch)| {
+                scanned += 1;
+                self.encoding.len(ch)
+            })
+            .sum::<usize>();
+        (Position::new(line as u32, character as u32), scanned)
+    }
+
+    /// Converts `pos` into a byte offset of `text`. Positions beyond the end
+    /// of a line or the end of the text are clamped.
+    pub(crate) fn offset(&self, text: &str, pos: &Position) -> usize {
+        self.scan_offset(text, pos).0
+    }
+
+    // Same as `offset` but also returns the number of characters scanned.
+    fn scan_offset(&self, text: &str, pos: &Position) -> (usize, usize) {
+        let line_start = match self.line_starts.get(pos.line as usize) {
+            Some(&line_start) => line_start,
+            None => return (text.len(), 0),
+        };
+        // Positions past the end of the line are clamped to the line ending,
+        // which can be `\r\n`.
+        let line = &text[line_start..];
+        let mut units = 0;
+        for (scanned, (i, ch)) in line.char_indices().enumerate() {
+            let line_end = ch == '\n' || line[i..].starts_with("\r\n");
+            if line_end || units >= pos.character as usize {
+                return (line_start + i, scanned);
+            }
+            units += self.encoding.len(ch);
+        }
+        (text.len(), line.chars().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::syntax;
+
+    use super::super::conversion::to_lsp_range;
+
+    #[test]
+    fn test_negotiate() {
+        let capabilities = serde_json::json!({});
+        assert_eq!(
+            PositionEncoding::Utf16,
+            PositionEncoding::negotiate(&capabilities)
+        );
+        let capabilities = serde_json::json!({
+            "general": { "positionEncodings": ["utf-16", "utf-8"] }
+        });
+        assert_eq!(
+            PositionEncoding::Utf8,
+            PositionEncoding::negotiate(&capabilities)
+        );
+        let capabilities = serde_json::json!({
+            "general": { "positionEncodings": ["utf-32", "utf-16"] }
+        });
+        assert_eq!(
+            PositionEncoding::Utf16,
+            PositionEncoding::negotiate(&capabilities)
+        );
+    }
+
+    #[test]
+    fn test_offset() {
+        let text = "ab\ncd\n";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        assert_eq!(0, index.offset(text, &Position::new(0, 0)));
+        assert_eq!(2, index.offset(text, &Position::new(0, 2)));
+        // Clamped to the end of the line.
+        assert_eq!(2, index.offset(text, &Position::new(0, 10)));
+        assert_eq!(4, index.offset(text, &Position::new(1, 1)));
+        assert_eq!(6, index.offset(text, &Position::new(2, 0)));
+        assert_eq!(6, index.offset(text, &Position::new(5, 0)));
+
+        // CRLF line endings.
+        let text = "ab\r\ncd";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        assert_eq!(2, index.offset(text, &Position::new(0, 2)));
+        assert_eq!(2, index.offset(text, &Position::new(0, 10)));
+        assert_eq!(5, index.offset(text, &Position::new(1, 1)));
+        assert_eq!(6, index.offset(text, &Position::new(1, 10)));
+
+        // "é" is 2 bytes and 1 UTF-16 unit. "𝄞" is 4 bytes and 2 UTF-16 units.
+        let text = "é𝄞x";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        assert_eq!(2, index.offset(text, &Position::new(0, 1)));
+        assert_eq!(6, index.offset(text, &Position::new(0, 3)));
+        let index = LineIndex::new(text, PositionEncoding::Utf8);
+        assert_eq!(2, index.offset(text, &Position::new(0, 2)));
+        assert_eq!(6, index.offset(text, &Position::new(0, 6)));
+    }
+
+    #[test]
+    fn test_position() {
+        let text = "ab\ncd";
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        assert_eq!(Position::new(0, 0), index.position(text, 0));
+        assert_eq!(Position::new(0, 2), index.position(text, 2));
+        assert_eq!(Position::new(1, 0), index.position(text, 3));
+        assert_eq!(Position::new(1, 2), index.position(text, 5));
+        assert_eq!(Position::new(1, 2), index.position(text, 10));
+    }
+
+    #[test]
+    fn test_large_text() {
+        // Converting every identifier of a large document only scans the
+        // lines of the identifiers.
+        let text = (0..10_000)
+            .map(|i| format!("struct S{} {{ int32 field_{}; }}; // é😀\n", i, i))
+            .collect::<String>();
+        let index = LineIndex::new(&text, PositionEncoding::Utf16);
+        // Each conversion scans at most one line.
+        let max_line_len = text.lines().map(|line| line.chars().count()).max().unwrap();
+        let mut count = 0;
+        for (offset, _) in text.match_indices("field_") {
+            let (start, scanned) = index.scan_position(&text, offset);
+            assert!(scanned <= max_line_len);
+            let (end, scanned) = index.scan_position(&text, offset + 6);
+            assert!(scanned <= max_line_len);
+            assert_eq!(start.line, end.line);
+            let (start_offset, scanned) = index.scan_offset(&text, &start);
+            assert!(scanned <= max_line_len);
+            assert_eq!(offset, start_offset);
+            count += 1;
+        }
+        assert_eq!(10_000, count);
+
+        // Lines and columns match `syntax::line_col()`, which counts
+        // characters.
+        let index = LineIndex::new(&text, PositionEncoding::Utf8);
+        for offset in (0..text.len()).step_by(997) {
+            if !text.is_char_boundary(offset) {
+                continue;
+            }
+            let expected = syntax::line_col(&text, offset).unwrap();
+            let position = index.position(&text, offset);
+            assert_eq!(expected.line, index.line(offset));
+            assert_eq!(expected.line as u32, position.line);
+            let line_start = index.offset(&text, &Position::new(position.line, 0));
+            let chars = text[line_start..offset].chars().count();
+            assert_eq!(expected.col, chars);
+        }
+    }
+
+    #[test]
+    fn test_emoji_before_identifier() {
+        // "😀" is 4 bytes, 2 UTF-16 units and 1 character.
+        let text = "const int32 a = 1;\n/* 😀 */ const int32 kFoo = 1;\n";
+        let start = text.find("kFoo").unwrap();
+        let range = syntax::Range {
+            start,
+            end: start + 4,
+        };
+
+        let index = LineIndex::new(text, PositionEncoding::Utf16);
+        assert_eq!(
+            lsp_types::Range::new(Position::new(1, 21), Position::new(1, 25)),
+            to_lsp_range(&index, text, &range)
+        );
+        assert_eq!(start, index.offset(text, &Position::new(1, 21)));
+
+        let index = LineIndex::new(text, PositionEncoding::Utf8);
+        assert_eq!(
+            lsp_types::Range::new(Position::new(1, 23), Position::new(1, 27)),
+            to_lsp_range(&index, text, &range)
+        );
+        assert_eq!(start, index.offset(text, &Position::new(1, 23)));
+
+        // Inside the emoji.
+        let emoji = text.find('😀').unwrap();
+        assert_eq!(Position::new(1, 3), index.position(text, emoji + 1));
+    }
+}
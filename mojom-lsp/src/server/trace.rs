@@ -0,0 +1,246 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// lsp_types doesn't know `$/setTrace` and `$/logTrace` yet. These are the
+// parts of the protocol that the server uses.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lsp_types::TraceOption;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::protocol::{Id, Message, NotificationMessage};
+
+pub(crate) const SET_TRACE_METHOD: &str = "$/setTrace";
+pub(crate) const LOG_TRACE_METHOD: &str = "$/logTrace";
+
+// Payloads in verbose traces are cut at this length so that large documents
+// don't flood the client's log.
+const MAX_VERBOSE_LENGTH: usize = 4096;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SetTraceParams {
+    pub(crate) value: TraceOption,
+}
+
+#[derive(Debug, Serialize)]
+struct LogTraceParams {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verbose: Option<String>,
+}
+
+// Describes messages between the server and the client as `$/logTrace`
+// notifications. Clones share the trace value so that `$/setTrace` applies
+// to every thread.
+#[derive(Clone, Default)]
+pub(crate) struct Tracer {
+    value: Arc<AtomicU8>,
+    // Methods of requests that are waiting for responses, so that responses
+    // can be described with them. Ids of both sides may collide.
+    received_requests: Arc<Mutex<HashMap<Id, String>>>,
+    sent_requests: Arc<Mutex<HashMap<Id, String>>>,
+}
+
+impl Tracer {
+    pub(crate) fn set(&self, value: TraceOption) {
+        let value = match value {
+            TraceOption::Off => 0,
+            TraceOption::Messages => 1,
+            TraceOption::Verbose => 2,
+        };
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> TraceOption {
+        match self.value.load(Ordering::Relaxed) {
+            0 => TraceOption::Off,
+            1 => TraceOption::Messages,
+            _ => TraceOption::Verbose,
+        }
+    }
+
+    // Returns a `$/logTrace` notification that describes `message` from the
+    // client. None when trace is off.
+    pub(crate) fn received(&self, message: &Message) -> Option<NotificationMessage> {
+        self.describe(
+            message,
+            "Received",
+            &self.received_requests,
+            &self.sent_requests,
+        )
+    }
+
+    // Returns a `$/logTrace` notification that describes `message` to the
+    // client. None when trace is off.
+    pub(crate) fn sending(&self, message: &Message) -> Option<NotificationMessage> {
+        self.describe(
+            message,
+            "Sending",
+            &self.sent_requests,
+            &self.received_requests,
+        )
+    }
+
+    // `requests` keeps requests of the side that sends `message` and
+    // `responded` keeps requests of the other side.
+    fn describe(
+        &self,
+        message: &Message,
+        verb: &str,
+        requests: &Mutex<HashMap<Id, String>>,
+        responded: &Mutex<HashMap<Id, String>>,
+    ) -> Option<NotificationMessage> {
+        let trace = self.get();
+        let (message, payload) = match message {
+            Message::Request(request) => {
+                if trace == TraceOption::Off {
+                    return None;
+                }
+                let mut requests = requests.lock().unwrap();
+                requests.insert(request.id.clone(), request.method.clone());
+                let message = format!("{} request '{} - ({})'.", verb, request.method, request.id);
+                (message, format!("Params: {}", request.params))
+            }
+            Message::Notification(notification) => {
+                if trace == TraceOption::Off {
+                    return None;
+                }
+                let message = format!("{} notification '{}'.", verb, notification.method);
+                (message, format!("Params: {}", notification.params))
+            }
+            Message::Response(response) => {
+                // Forget the request even when trace is off so that turning
+                // trace off doesn't leak it.
+                let method = responded.lock().unwrap().remove(&response.id);
+                if trace == TraceOption::Off {
+                    return None;
+                }
+                let name = match method {
+                    Some(method) => format!("{} - ({})", method, response.id),
+                    None => response.id.to_string(),
+                };
+                match &response.error {
+                    Some(err) => {
+                        let message = format!(
+                            "{} response '{}'. Request failed: {} ({}).",
+                            verb, name, err.message, err.code
+                        );
+                        let data = err.data.as_ref().unwrap_or(&Value::Null);
+                        (message, format!("Error data: {}", data))
+                    }
+                    None => {
+                        let message = format!("{} response '{}'.", verb, name);
+                        let result = response.result.as_ref().unwrap_or(&Value::Null);
+                        (message, format!("Result: {}", result))
+                    }
+                }
+            }
+        };
+        let verbose = match trace {
+            TraceOption::Verbose => Some(truncate(payload)),
+            _ => None,
+        };
+        let params = LogTraceParams { message, verbose };
+        Some(NotificationMessage {
+            method: LOG_TRACE_METHOD.to_owned(),
+            // Unwrap should be safe because the params only have strings.
+            params: serde_json::to_value(params).unwrap(),
+        })
+    }
+}
+
+fn truncate(mut payload: String) -> String {
+    if payload.len() <= MAX_VERBOSE_LENGTH {
+        return payload;
+    }
+    let mut end = MAX_VERBOSE_LENGTH;
+    while !payload.is_char_boundary(end) {
+        end -= 1;
+    }
+    payload.truncate(end);
+    payload.push_str("...");
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::protocol::{RequestMessage, ResponseMessage};
+
+    fn request(id: i64, method: &str) -> Message {
+        Message::Request(RequestMessage {
+            id: Id::Number(id),
+            method: method.to_owned(),
+            params: serde_json::json!({ "a": 1 }),
+        })
+    }
+
+    fn response(id: i64) -> Message {
+        Message::Response(ResponseMessage {
+            id: Id::Number(id),
+            result: Some(Value::Null),
+            error: None,
+        })
+    }
+
+    #[test]
+    fn test_tracer() {
+        let tracer = Tracer::default();
+        assert!(tracer.received(&request(1, "textDocument/hover")).is_none());
+        assert!(tracer.sending(&response(1)).is_none());
+
+        tracer.set(TraceOption::Messages);
+        let log = tracer.received(&request(2, "textDocument/hover")).unwrap();
+        assert_eq!(LOG_TRACE_METHOD, log.method);
+        assert_eq!(
+            serde_json::json!({ "message": "Received request 'textDocument/hover - (2)'." }),
+            log.params
+        );
+        let log = tracer.sending(&response(2)).unwrap();
+        assert_eq!(
+            "Sending response 'textDocument/hover - (2)'.",
+            log.params["message"]
+        );
+        // The request was forgotten.
+        let log = tracer.sending(&response(2)).unwrap();
+        assert_eq!("Sending response '2'.", log.params["message"]);
+
+        // Requests of the server are described in responses of the client.
+        tracer.set(TraceOption::Verbose);
+        let log = tracer
+            .sending(&request(2, "workspace/configuration"))
+            .unwrap();
+        assert_eq!("Params: {\"a\":1}", log.params["verbose"]);
+        let log = tracer.received(&response(2)).unwrap();
+        assert_eq!(
+            "Received response 'workspace/configuration - (2)'.",
+            log.params["message"]
+        );
+        assert_eq!("Result: null", log.params["verbose"]);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("abc", truncate("abc".to_owned()));
+        let payload = "é".repeat(MAX_VERBOSE_LENGTH);
+        let truncated = truncate(payload);
+        assert!(truncated.ends_with("..."));
+        assert_eq!(MAX_VERBOSE_LENGTH + 3, truncated.len());
+    }
+}
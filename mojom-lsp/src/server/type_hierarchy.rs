@@ -0,0 +1,200 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// lsp_types doesn't know type hierarchy yet. These are the parts of the
+// protocol that the server uses.
+//
+// Mojom has no inheritance. The subtypes of a type are the methods that take
+// or return the type so that users can see which interfaces use it.
+
+use lsp_types::{Location, Range, SymbolKind, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::syntax::{self, preorder, Traversal};
+
+use super::imported_files::ImportedFiles;
+use super::mojomast::MojomAst;
+use super::references::refers_to;
+
+pub(crate) const PREPARE_TYPE_HIERARCHY_METHOD: &str = "textDocument/prepareTypeHierarchy";
+pub(crate) const SUPERTYPES_METHOD: &str = "typeHierarchy/supertypes";
+pub(crate) const SUBTYPES_METHOD: &str = "typeHierarchy/subtypes";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TypeHierarchyParams {
+    pub(crate) item: TypeHierarchyItem,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TypeHierarchyItem {
+    pub(crate) name: String,
+    pub(crate) kind: SymbolKind,
+    // The interface of a method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) detail: Option<String>,
+    pub(crate) uri: Url,
+    pub(crate) range: Range,
+    pub(crate) selection_range: Range,
+}
+
+impl TypeHierarchyItem {
+    fn new(
+        ast: &MojomAst,
+        kind: SymbolKind,
+        range: &syntax::Range,
+        name: &syntax::Range,
+    ) -> TypeHierarchyItem {
+        TypeHierarchyItem {
+            name: ast.text(name).to_owned(),
+            kind,
+            detail: None,
+            uri: ast.uri.clone(),
+            range: ast.lsp_range(range),
+            selection_range: ast.lsp_range(name),
+        }
+    }
+
+    /// The location of the declaration that the item represents.
+    pub(crate) fn location(&self) -> Location {
+        Location::new(self.uri.clone(), self.selection_range)
+    }
+}
+
+/// Returns the item of the type that is declared at `definition` in `ast`.
+/// Returns None when `definition` isn't a type, e.g. a const.
+pub(crate) fn type_item(ast: &MojomAst, definition: &Location) -> Option<TypeHierarchyItem> {
+    if definition.uri != ast.uri {
+        return None;
+    }
+    preorder(&ast.mojom).find_map(|traversal| {
+        let (kind, range, name) = match traversal {
+            Traversal::EnterInterface(node) => (SymbolKind::INTERFACE, &node.range, &node.name),
+            Traversal::EnterStruct(node) => (SymbolKind::STRUCT, &node.range, &node.name),
+            Traversal::Union(node) => (SymbolKind::STRUCT, &node.range, &node.name),
+            Traversal::Enum(node) => (SymbolKind::ENUM, &node.range, &node.name),
+            _ => return None,
+        };
+        if ast.lsp_range(name) != definition.range {
+            return None;
+        }
+        Some(TypeHierarchyItem::new(ast, kind, range, name))
+    })
+}
+
+/// Returns the methods in `ast` whose request or response parameters refer
+/// to `definition`, including parameterized types like `array<MyStruct>`.
+pub(crate) fn method_usages(
+    ast: &MojomAst,
+    imported_files: &Option<ImportedFiles>,
+    definition: &Location,
+) -> Vec<TypeHierarchyItem> {
+    let mut usages = Vec::new();
+    let mut scope = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => scope.push(ast.text(&node.name)),
+            Traversal::EnterStruct(node) => scope.push(ast.text(&node.name)),
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                scope.pop();
+            }
+            Traversal::Method(node) => {
+                let uses = node
+                    .params
+                    .iter()
+                    .chain(node.response.iter().flat_map(|res| res.params.iter()))
+                    .any(|param| refers_to(ast, imported_files, &scope, &param.typ, definition));
+                if uses {
+                    let mut item =
+                        TypeHierarchyItem::new(ast, SymbolKind::METHOD, &node.range, &node.name);
+                    item.detail = scope.last().map(|name| name.to_string());
+                    usages.push(item);
+                }
+            }
+            _ => (),
+        }
+    }
+    usages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use super::super::imported_files::{check_imports, ImportCache};
+    use super::super::references::resolve_at;
+
+    fn create_ast(path: &str) -> MojomAst {
+        let path = Path::new(path).canonicalize().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+        let mojom = syntax::parse(&text).unwrap();
+        let module = crate::analysis::find_module(&text, &mojom, &mut Vec::new());
+        MojomAst::from_mojom(uri, text, mojom, module)
+    }
+
+    fn method_names(usages: &[TypeHierarchyItem]) -> Vec<(&str, &str)> {
+        usages
+            .iter()
+            .map(|item| (item.detail.as_deref().unwrap(), item.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_type_hierarchy() {
+        let geometry = create_ast("testdata/usages/geometry.mojom");
+        let canvas = create_ast("testdata/usages/canvas.mojom");
+        let canvas_imports = Some(check_imports(
+            &["testdata"],
+            &canvas,
+            &mut ImportCache::new(),
+        ));
+
+        // Prepare from a reference in another file.
+        let offset = canvas.text.find("Point from").unwrap();
+        let definition = resolve_at(&canvas, &canvas_imports, offset).unwrap();
+        assert_eq!(geometry.uri, definition.uri);
+        let item = type_item(&geometry, &definition).unwrap();
+        assert_eq!("Point", item.name);
+        assert_eq!(SymbolKind::STRUCT, item.kind);
+        assert_eq!(definition, item.location());
+
+        // The struct is used in two interfaces.
+        let usages = method_usages(&geometry, &None, &item.location());
+        assert_eq!(vec![("Locator", "Locate")], method_names(&usages));
+        assert_eq!(SymbolKind::METHOD, usages[0].kind);
+        assert_eq!(geometry.uri, usages[0].uri);
+        let usages = method_usages(&canvas, &canvas_imports, &item.location());
+        assert_eq!(
+            vec![("Canvas", "DrawLine"), ("Canvas", "MovePath")],
+            method_names(&usages)
+        );
+
+        // Enums work the same.
+        let offset = geometry.text.find("Direction").unwrap();
+        let definition = resolve_at(&geometry, &None, offset).unwrap();
+        let item = type_item(&geometry, &definition).unwrap();
+        assert_eq!(SymbolKind::ENUM, item.kind);
+        let usages = method_usages(&canvas, &canvas_imports, &item.location());
+        assert_eq!(vec![("Canvas", "MovePath")], method_names(&usages));
+
+        // Consts aren't types.
+        let offset = geometry.text.find("kOrigin").unwrap();
+        let definition = resolve_at(&geometry, &None, offset).unwrap();
+        assert!(type_item(&geometry, &definition).is_none());
+    }
+}
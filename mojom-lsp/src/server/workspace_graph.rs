@@ -0,0 +1,132 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Relations between files of the workspace index: the files that import each
+// file and the files that declare each module. Chromium splits a module into
+// files that don't import each other, so names of a module can be declared
+// in files that a document doesn't import. The workspace symbol thread
+// updates the graph and the diagnostics thread reads it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::index_cache::IndexedFile;
+
+#[derive(Debug, Default)]
+struct Graph {
+    dependents: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    modules: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+// Clones share the graph. The graph is empty until the workspace is indexed.
+#[derive(Clone, Default)]
+pub(crate) struct WorkspaceGraph {
+    graph: Arc<RwLock<Graph>>,
+}
+
+impl WorkspaceGraph {
+    // Replaces the graph with the relations of `files`. Import paths are
+    // relative to `root_path`.
+    pub(crate) fn update(&self, root_path: &Path, files: &BTreeMap<PathBuf, IndexedFile>) {
+        let mut graph = Graph::default();
+        for (path, file) in files {
+            for import in &file.imports {
+                graph
+                    .dependents
+                    .entry(root_path.join(import))
+                    .or_default()
+                    .insert(path.clone());
+            }
+            if let Some(module_name) = &file.module_name {
+                graph
+                    .modules
+                    .entry(module_name.clone())
+                    .or_default()
+                    .insert(path.clone());
+            }
+        }
+        *self.graph.write().unwrap() = graph;
+    }
+
+    // Returns the files that import the file at `path`, sorted by path.
+    pub(crate) fn dependents(&self, path: &Path) -> Vec<PathBuf> {
+        let graph = self.graph.read().unwrap();
+        match graph.dependents.get(path) {
+            Some(paths) => paths.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // Returns the files that declare `module_name`, sorted by path.
+    pub(crate) fn module_files(&self, module_name: &str) -> Vec<PathBuf> {
+        let graph = self.graph.read().unwrap();
+        match graph.modules.get(module_name) {
+            Some(paths) => paths.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_file(module_name: Option<&str>, imports: &[&str]) -> IndexedFile {
+        IndexedFile {
+            module_name: module_name.map(str::to_owned),
+            imports: imports.iter().map(|&import| import.to_owned()).collect(),
+            ..IndexedFile::new(None)
+        }
+    }
+
+    #[test]
+    fn test_workspace_graph() {
+        let root_path = Path::new("/src");
+        let mut files = BTreeMap::new();
+        files.insert(
+            PathBuf::from("/src/b/b.mojom"),
+            create_file(Some("foo.mojom"), &["a.mojom"]),
+        );
+        files.insert(
+            PathBuf::from("/src/a.mojom"),
+            create_file(Some("foo.mojom"), &[]),
+        );
+        files.insert(
+            PathBuf::from("/src/c.mojom"),
+            create_file(None, &["a.mojom", "b/b.mojom"]),
+        );
+
+        let graph = WorkspaceGraph::default();
+        let shared = graph.clone();
+        assert!(graph.module_files("foo.mojom").is_empty());
+        shared.update(root_path, &files);
+
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(
+            paths(&["/src/b/b.mojom", "/src/c.mojom"]),
+            graph.dependents(Path::new("/src/a.mojom"))
+        );
+        assert_eq!(
+            paths(&["/src/c.mojom"]),
+            graph.dependents(Path::new("/src/b/b.mojom"))
+        );
+        assert!(graph.dependents(Path::new("/src/c.mojom")).is_empty());
+        assert_eq!(
+            paths(&["/src/a.mojom", "/src/b/b.mojom"]),
+            graph.module_files("foo.mojom")
+        );
+        assert!(graph.module_files("bar.mojom").is_empty());
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use lsp_types::{Location, SymbolInformation, SymbolKind, Url};
+
+use crate::analysis;
+use crate::syntax::{self, preorder, Traversal};
+
+use super::imported_files::import_paths;
+use super::index_cache::{self, IndexedFile};
+use super::messagesender::MessageSender;
+use super::mojomast::MojomAst;
+use super::progress::ProgressReporter;
+use super::protocol::Id;
+use super::textpos::PositionEncoding;
+use super::workspace_graph::WorkspaceGraph;
+
+// The maximum number of symbols returned for a query.
+const MAX_RESULTS: usize = 256;
+
+fn is_mojom_file(path: &Path) -> bool {
+    path.extension() == Some(std::ffi::OsStr::new("mojom"))
+}
+
+// Collects mojom files under `dir`. Hidden directories and symbolic links are
+// skipped. Returns false when cancelled.
+fn collect_mojom_files(dir: &Path, cancelled: &AtomicBool, files: &mut Vec<PathBuf>) -> bool {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::debug!("Failed to read {}: {}", dir.display(), err);
+            return true;
+        }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if cancelled.load(Ordering::Relaxed) {
+            return false;
+        }
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !is_hidden && !collect_mojom_files(&path, cancelled, files) {
+                return false;
+            }
+        } else if file_type.is_file() && is_mojom_file(&path) {
+            files.push(path);
+        }
+    }
+    true
+}
+
+#[allow(deprecated)]
+fn create_symbol(
+    ast: &MojomAst,
+    kind: SymbolKind,
+    name: &syntax::Range,
+    container: &[&str],
+) -> SymbolInformation {
+    let container_name = match container.join(".") {
+        name if name.is_empty() => None,
+        name => Some(name),
+    };
+    SymbolInformation {
+        name: ast.text(name).to_owned(),
+        kind,
+        tags: None,
+        deprecated: None,
+        location: Location::new(ast.uri.clone(), ast.lsp_range(name)),
+        container_name,
+    }
+}
+
+// Parses `text` of the file at `path`. Returns None when the file has a
+// syntax error.
+fn parse_file(path: &Path, text: String, encoding: PositionEncoding) -> Option<MojomAst> {
+    let mojom = syntax::parse(&text).ok()?;
+    let uri = Url::from_file_path(path).ok()?;
+    let module = analysis::find_module(&text, &mojom, &mut Vec::new());
+    Some(MojomAst::new(uri, text, mojom, module, encoding))
+}
+
+// Indexes symbols and imports of the file at `path`. Containers of symbols
+// are the module name and enclosing interfaces or structs. Files with syntax
+// errors have no symbols.
+pub(crate) fn index_file(path: &Path, encoding: PositionEncoding) -> IndexedFile {
+    let mut file = IndexedFile::new(std::fs::metadata(path).ok().as_ref());
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return file,
+    };
+    file.hash = Some(index_cache::hash_bytes(text.as_bytes()));
+    let ast = match parse_file(path, text, encoding) {
+        Some(ast) => ast,
+        None => return file,
+    };
+    file.module_name = ast.module_name().map(str::to_owned);
+    file.imports = import_paths(&ast.text, &ast.mojom);
+
+    let symbols = &mut file.symbols;
+    let mut container = ast.module_name().into_iter().collect::<Vec<_>>();
+    for traversal in preorder(&ast.mojom) {
+        let (kind, name) = match traversal {
+            Traversal::EnterInterface(node) => {
+                let symbol = create_symbol(&ast, SymbolKind::INTERFACE, &node.name, &container);
+                symbols.push(symbol);
+                container.push(ast.text(&node.name));
+                continue;
+            }
+            Traversal::EnterStruct(node) => {
+                let symbol = create_symbol(&ast, SymbolKind::STRUCT, &node.name, &container);
+                symbols.push(symbol);
+                container.push(ast.text(&node.name));
+                continue;
+            }
+            Traversal::Feature(node) => {
+                let symbol = create_symbol(&ast, SymbolKind::OBJECT, &node.name, &container);
+                symbols.push(symbol);
+                container.push(ast.text(&node.name));
+                continue;
+            }
+            Traversal::LeaveInterface(_)
+            | Traversal::LeaveStruct(_)
+            | Traversal::LeaveFeature(_) => {
+                container.pop();
+                continue;
+            }
+            Traversal::Union(node) => (SymbolKind::STRUCT, &node.name),
+            Traversal::Enum(node) => (SymbolKind::ENUM, &node.name),
+            Traversal::Const(node) => (SymbolKind::CONSTANT, &node.name),
+            Traversal::Method(node) => (SymbolKind::METHOD, &node.name),
+            _ => continue,
+        };
+        symbols.push(create_symbol(&ast, kind, name, &container));
+    }
+    file
+}
+
+// Symbols of mojom files in the workspace, keyed by path.
+struct WorkspaceIndex {
+    files: BTreeMap<PathBuf, IndexedFile>,
+    encoding: PositionEncoding,
+}
+
+impl WorkspaceIndex {
+    // Indexes all mojom files under `root_path`. Files that haven't changed
+    // since they were saved in the cache at `cache_path` aren't parsed. The
+    // index is partial when cancelled.
+    fn build(
+        root_path: &Path,
+        encoding: PositionEncoding,
+        cache_path: Option<&Path>,
+        cancelled: &AtomicBool,
+        progress: &ProgressReporter,
+    ) -> Self {
+        Self::build_with(
+            root_path,
+            encoding,
+            cache_path,
+            cancelled,
+            progress,
+            &mut |path| index_file(path, encoding),
+        )
+    }
+
+    // Same as `build` except that files are indexed with `index_file`.
+    fn build_with(
+        root_path: &Path,
+        encoding: PositionEncoding,
+        cache_path: Option<&Path>,
+        cancelled: &AtomicBool,
+        progress: &ProgressReporter,
+        index_file: &mut dyn FnMut(&Path) -> IndexedFile,
+    ) -> Self {
+        let mut cached = cache_path
+            .and_then(|cache_path| index_cache::load(cache_path, root_path, encoding))
+            .unwrap_or_default();
+        let mut index = WorkspaceIndex {
+            files: BTreeMap::new(),
+            encoding,
+        };
+        let mut changed = false;
+        let mut files = Vec::new();
+        collect_mojom_files(root_path, cancelled, &mut files);
+        let mut progress = progress.begin("Indexing mojom files", files.len());
+        for (i, path) in files.into_iter().enumerate() {
+            if cancelled.load(Ordering::Relaxed) {
+                return index;
+            }
+            let metadata = std::fs::metadata(&path).ok();
+            let file = match (cached.remove(&path), &metadata) {
+                (Some(file), Some(metadata)) if file.is_fresh(&path, metadata) => file,
+                _ => {
+                    changed = true;
+                    index_file(&path)
+                }
+            };
+            index.files.insert(path, file);
+            progress.report(i + 1);
+        }
+        // Files that are left in the cache were deleted. Changes after this
+        // aren't saved because they are noticed by their modification times
+        // on the next start.
+        if let Some(cache_path) = cache_path {
+            if changed || !cached.is_empty() {
+                let result = index_cache::save(cache_path, root_path, encoding, &index.files);
+                match result {
+                    Ok(()) => index_cache::prune(cache_path),
+                    Err(err) => log::warn!("Failed to save {}: {}", cache_path.display(), err),
+                }
+            }
+        }
+        index
+    }
+
+    fn update(&mut self, path: &Path) {
+        if path.exists() {
+            let file = index_file(path, self.encoding);
+            self.files.insert(path.to_owned(), file);
+        } else {
+            self.files.remove(path);
+        }
+    }
+
+    // Returns symbols whose names contain `query`, ignoring case.
+    fn query(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        self.files
+            .values()
+            .flat_map(|file| &file.symbols)
+            .filter(|symbol| symbol.name.to_lowercase().contains(&query))
+            .take(MAX_RESULTS)
+            .cloned()
+            .collect()
+    }
+}
+
+enum WorkspaceSymbolMessage {
+    Query((Id /* request id */, String)),
+    DidChangeFile(Url),
+    Reindex(Id /* request id */),
+}
+
+pub(crate) struct WorkspaceSymbolThread {
+    handle: JoinHandle<()>,
+    sender: Sender<WorkspaceSymbolMessage>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl WorkspaceSymbolThread {
+    // Stops indexing and waits until queued messages are handled.
+    pub(crate) fn shutdown(self) {
+        self.cancel();
+        drop(self.sender);
+        if self.handle.join().is_err() {
+            log::error!("The workspace symbol thread panicked");
+        }
+    }
+
+    // Queries symbols. The response is sent from the workspace symbol thread
+    // because the first query needs to index the workspace.
+    pub(crate) fn query(&self, id: Id, query: String) {
+        self.sender
+            .send(WorkspaceSymbolMessage::Query((id, query)))
+            .unwrap();
+    }
+
+    // Indexes the file at `uri` again. Deleted files are removed.
+    pub(crate) fn did_change_file(&self, uri: Url) {
+        self.sender
+            .send(WorkspaceSymbolMessage::DidChangeFile(uri))
+            .unwrap();
+    }
+
+    // Indexes the workspace from scratch, ignoring the saved index. The
+    // response is sent from the workspace symbol thread when indexing is done.
+    pub(crate) fn reindex(&self, id: Id) {
+        self.sender
+            .send(WorkspaceSymbolMessage::Reindex(id))
+            .unwrap();
+    }
+
+    // Stops indexing. Queries are answered with partial results after this.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn start_workspace_symbol_thread(
+    root_path: PathBuf,
+    encoding: PositionEncoding,
+    cache_path: Option<PathBuf>,
+    index_on_startup: bool,
+    workspace_graph: WorkspaceGraph,
+    msg_sender: MessageSender,
+    progress: ProgressReporter,
+) -> WorkspaceSymbolThread {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = channel::<WorkspaceSymbolMessage>();
+    let thread_cancelled = cancelled.clone();
+    let handle = thread::spawn(move || {
+        // Builds the index and shares its imports and modules with the
+        // diagnostics thread.
+        let build_index = || {
+            let index = WorkspaceIndex::build(
+                &root_path,
+                encoding,
+                cache_path.as_deref(),
+                &thread_cancelled,
+                &progress,
+            );
+            workspace_graph.update(&root_path, &index.files);
+            index
+        };
+        // The workspace is indexed on the first query unless
+        // `index_on_startup` is set. The diagnostics thread doesn't know
+        // sibling module files and dependents until then. Messages are
+        // queued while indexing.
+        let mut index = if index_on_startup {
+            Some(build_index())
+        } else {
+            None
+        };
+        while let Ok(msg) = receiver.recv() {
+            match msg {
+                WorkspaceSymbolMessage::Query((id, query)) => {
+                    let index = index.get_or_insert_with(build_index);
+                    let symbols = index.query(&query);
+                    msg_sender.send_success_response(id, serde_json::to_value(symbols).unwrap());
+                }
+                WorkspaceSymbolMessage::DidChangeFile(uri) => {
+                    if let (Some(index), Ok(path)) = (index.as_mut(), uri.to_file_path()) {
+                        if is_mojom_file(&path) {
+                            index.update(&path);
+                            workspace_graph.update(&root_path, &index.files);
+                        }
+                    }
+                }
+                WorkspaceSymbolMessage::Reindex(id) => {
+                    if let Some(cache_path) = &cache_path {
+                        if let Err(err) = std::fs::remove_file(cache_path) {
+                            if err.kind() != std::io::ErrorKind::NotFound {
+                                log::warn!("Failed to remove {}: {}", cache_path.display(), err);
+                            }
+                        }
+                    }
+                    index = Some(build_index());
+                    msg_sender.send_success_response(id, serde_json::Value::Null);
+                }
+            }
+        }
+    });
+
+    WorkspaceSymbolThread {
+        handle,
+        sender,
+        cancelled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::test_util::TempDir;
+
+    fn names(symbols: &[SymbolInformation]) -> Vec<&str> {
+        symbols.iter().map(|symbol| symbol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_workspace_index() {
+        let root_path = Path::new("testdata").canonicalize().unwrap();
+        let index = WorkspaceIndex::build(
+            &root_path,
+            PositionEncoding::default(),
+            None,
+            &AtomicBool::new(false),
+            &ProgressReporter::disabled(),
+        );
+        let file_symbols = |path: &str| {
+            let file = &index.files[&root_path.join(path)];
+            names(&file.symbols).join(" ")
+        };
+        assert_eq!(
+            "kShared FooStruct FooEnum",
+            file_symbols("foo_module/foo.mojom")
+        );
+        assert_eq!(
+            "Canvas DrawLine MovePath Clear",
+            file_symbols("usages/canvas.mojom")
+        );
+        // broken.mojom has no symbols but is indexed.
+        assert_eq!("", file_symbols("broken.mojom"));
+
+        let symbols = index.query("foo");
+        assert_eq!(vec!["FooStruct", "FooEnum"], names(&symbols));
+        assert_eq!(SymbolKind::STRUCT, symbols[0].kind);
+        assert_eq!(Some("foo"), symbols[0].container_name.as_deref());
+        assert_eq!(Some("foo.FooStruct"), symbols[1].container_name.as_deref());
+        assert!(symbols[0]
+            .location
+            .uri
+            .path()
+            .ends_with("foo_module/foo.mojom"));
+
+        // Case-insensitive.
+        let symbols = index.query("MYINNER");
+        assert_eq!(vec!["MyInnerEnum"], names(&symbols));
+        assert_eq!(Some("MyInterface"), symbols[0].container_name.as_deref());
+
+        let symbols = index.query("dosomething");
+        assert_eq!(SymbolKind::METHOD, symbols[0].kind);
+
+        assert!(index.query("nonexistent").is_empty());
+    }
+
+    #[test]
+    fn test_workspace_index_cache() {
+        let temp_dir = TempDir::new("workspace");
+        let root_path = temp_dir.path().to_owned();
+        let cache_path = root_path.join(".mojom-lsp-cache/index.json");
+        std::fs::create_dir_all(&root_path).unwrap();
+        std::fs::write(root_path.join("a.mojom"), "struct Foo {};").unwrap();
+        std::fs::write(root_path.join("b.mojom"), "struct Bar {};").unwrap();
+        std::fs::write(root_path.join("c.mojom"), "struct Baz {};").unwrap();
+        let encoding = PositionEncoding::default();
+        // Returns the index and the names of parsed files.
+        let build = || {
+            let mut parsed = Vec::new();
+            let mut index_file = |path: &Path| {
+                parsed.push(path.file_name().unwrap().to_string_lossy().into_owned());
+                index_file(path, encoding)
+            };
+            let index = WorkspaceIndex::build_with(
+                &root_path,
+                encoding,
+                Some(&cache_path),
+                &AtomicBool::new(false),
+                &ProgressReporter::disabled(),
+                &mut index_file,
+            );
+            parsed.sort();
+            (index, parsed)
+        };
+
+        let (_, parsed) = build();
+        assert_eq!(vec!["a.mojom", "b.mojom", "c.mojom"], parsed);
+        assert!(cache_path.exists());
+        let (index, parsed) = build();
+        assert!(parsed.is_empty());
+        assert_eq!(vec!["Bar"], names(&index.query("bar")));
+
+        // Only the modified file is parsed again.
+        std::fs::write(root_path.join("b.mojom"), "struct Bar2 {};").unwrap();
+        let (index, parsed) = build();
+        assert_eq!(vec!["b.mojom"], parsed);
+        assert_eq!(vec!["Bar2"], names(&index.query("bar")));
+        let (_, parsed) = build();
+        assert!(parsed.is_empty());
+
+        // An edit that keeps the size and the modification time is noticed.
+        let path = root_path.join("b.mojom");
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "struct Bar3 {};").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+        let (index, parsed) = build();
+        assert_eq!(vec!["b.mojom"], parsed);
+        assert_eq!(vec!["Bar3"], names(&index.query("bar")));
+
+        // Deleted files are dropped from the cache.
+        std::fs::remove_file(root_path.join("c.mojom")).unwrap();
+        let (index, parsed) = build();
+        assert!(parsed.is_empty());
+        assert!(index.query("baz").is_empty());
+
+        // A broken cache is ignored.
+        std::fs::write(&cache_path, "{").unwrap();
+        let (_, parsed) = build();
+        assert_eq!(vec!["a.mojom", "b.mojom"], parsed);
+    }
+
+    #[test]
+    fn test_workspace_index_cancelled() {
+        let root_path = Path::new("testdata").canonicalize().unwrap();
+        let index = WorkspaceIndex::build(
+            &root_path,
+            PositionEncoding::default(),
+            None,
+            &AtomicBool::new(true),
+            &ProgressReporter::disabled(),
+        );
+        assert!(index.files.is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::syntax::{MojomFile, Range};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CommentKind {
+    /// `// ...`
+    Line,
+    /// `/* ... */`
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// The range of the comment, including `//` or `/*` and `*/`.
+    pub range: Range,
+    // The offset of the next comment or token after the comment.
+    next: usize,
+    // True when nothing but whitespace precedes the comment in its line.
+    own_line: bool,
+    // True when a blank line follows the comment.
+    blank_line_after: bool,
+}
+
+// Finds comments in `text` with a lexical pass. String literals are skipped.
+// The grammar treats comments as whitespace so this doesn't need a
+// successful parse.
+pub(crate) fn collect_comments(text: &str) -> Vec<Comment> {
+    let bytes = text.as_bytes();
+    let mut comments: Vec<Comment> = Vec::new();
+    let mut own_line = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        let (kind, end) = match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = text[i..]
+                    .find('\n')
+                    .map(|pos| i + pos)
+                    .unwrap_or(text.len());
+                (CommentKind::Line, end)
+            }
+            (b'/', Some(b'*')) => {
+                let end = text[i + 2..]
+                    .find("*/")
+                    .map(|pos| i + 2 + pos + 2)
+                    .unwrap_or(text.len());
+                (CommentKind::Block, end)
+            }
+            (b'"', _) => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\n' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+                own_line = false;
+                continue;
+            }
+            (b'\n', _) => {
+                i += 1;
+                own_line = true;
+                continue;
+            }
+            (ch, _) => {
+                i += 1;
+                if !ch.is_ascii_whitespace() {
+                    own_line = false;
+                }
+                continue;
+            }
+        };
+
+        let rest = &text[end..];
+        let whitespace = rest.len() - rest.trim_start().len();
+        comments.push(Comment {
+            kind,
+            range: Range { start: i, end },
+            next: end + whitespace,
+            own_line,
+            blank_line_after: rest[..whitespace].matches('\n').count() > 1,
+        });
+        own_line = false;
+        i = end;
+    }
+    comments
+}
+
+/// Returns the contiguous comment block immediately preceding `decl_start`.
+/// Comments separated from the declaration by a blank line and trailing
+/// comments of a previous line don't count. Comments are only available
+/// when `mojom` is created by `parse_with_comments()`.
+pub fn leading_comments(mojom: &MojomFile, decl_start: usize) -> &[Comment] {
+    let comments = &mojom.comments;
+    let end = comments.partition_point(|comment| comment.range.end <= decl_start);
+    let mut start = end;
+    let mut next = decl_start;
+    while start > 0 {
+        let comment = &comments[start - 1];
+        if comment.next != next || comment.blank_line_after || !comment.own_line {
+            break;
+        }
+        next = comment.range.start;
+        start -= 1;
+    }
+    &comments[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::syntax::parse_with_comments;
+    use super::*;
+
+    fn texts<'a>(text: &'a str, comments: &[Comment]) -> Vec<&'a str> {
+        comments
+            .iter()
+            .map(|comment| &text[comment.range.start..comment.range.end])
+            .collect()
+    }
+
+    #[test]
+    fn test_collect_comments() {
+        let input = r#"
+        // line
+        const string kA = "// not a comment /*"; /* block
+        comment */
+        const string kB = "\"//";// end"#;
+        let comments = collect_comments(input);
+        assert_eq!(
+            vec!["// line", "/* block\n        comment */", "// end"],
+            texts(input, &comments)
+        );
+        assert_eq!(CommentKind::Line, comments[0].kind);
+        assert_eq!(CommentKind::Block, comments[1].kind);
+    }
+
+    #[test]
+    fn test_leading_comments() {
+        let input = r#"
+        // File header.
+
+        // Consecutive line comments
+        // are a block.
+        interface A {
+            /* A block comment. */
+            Method();
+
+            /* Not leading. */
+
+            Method2(); // Trailing of Method2.
+            Method3();
+        };
+        "#;
+        let mojom = parse_with_comments(input).unwrap();
+        assert_eq!(6, mojom.comments.len());
+
+        let offset = |pattern: &str| input.find(pattern).unwrap();
+        assert_eq!(
+            vec!["// Consecutive line comments", "// are a block."],
+            texts(input, leading_comments(&mojom, offset("interface A")))
+        );
+        assert_eq!(
+            vec!["/* A block comment. */"],
+            texts(input, leading_comments(&mojom, offset("Method()")))
+        );
+        assert!(leading_comments(&mojom, offset("Method2")).is_empty());
+        assert!(leading_comments(&mojom, offset("Method3")).is_empty());
+
+        // `parse()` doesn't collect comments.
+        let mojom = super::super::syntax::parse(input).unwrap();
+        assert!(leading_comments(&mojom, offset("interface A")).is_empty());
+    }
+}
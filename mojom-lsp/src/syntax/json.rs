@@ -0,0 +1,84 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde_json::{Map, Value};
+
+use super::syntax::MojomFile;
+
+// Returns true when `object` is a serialized `Range`.
+fn is_range(object: &Map<String, Value>) -> bool {
+    object.len() == 2
+        && object.get("start").is_some_and(Value::is_u64)
+        && object.get("end").is_some_and(Value::is_u64)
+}
+
+// Adds the text of `text` to all ranges in `value`.
+fn resolve_ranges(text: &str, value: &mut Value) {
+    match value {
+        Value::Object(object) if is_range(object) => {
+            let start = object["start"].as_u64().unwrap() as usize;
+            let end = object["end"].as_u64().unwrap() as usize;
+            if let Some(range_text) = text.get(start..end) {
+                object.insert("text".to_owned(), range_text.into());
+            }
+        }
+        Value::Object(object) => {
+            for value in object.values_mut() {
+                resolve_ranges(text, value);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                resolve_ranges(text, value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Serializes `mojom` into JSON. Every range has a `text` property in
+/// addition to its byte offsets so that the JSON is readable without `text`,
+/// the text `mojom` was parsed from.
+pub fn to_resolved_json(mojom: &MojomFile, text: &str) -> Value {
+    let mut value = serde_json::to_value(mojom).unwrap();
+    resolve_ranges(text, &mut value);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::syntax::{parse, MojomFile};
+
+    #[test]
+    fn test_to_resolved_json() {
+        let input = "module foo;\nstruct MyStruct { int32 value@1; };\n";
+        let mojom = parse(input).unwrap();
+        let json = to_resolved_json(&mojom, input);
+
+        let module = &json["stmts"][0]["Module"];
+        assert_eq!("foo", module["name"]["text"]);
+        assert_eq!(7, module["name"]["start"]);
+        let field = &json["stmts"][1]["Struct"]["members"][0]["Field"];
+        assert_eq!("int32", field["typ"]["text"]);
+        assert_eq!("value", field["name"]["text"]);
+        assert_eq!("@1", field["ordinal"]["text"]);
+        assert!(field["default"].is_null());
+
+        // The extra texts are ignored when deserializing.
+        let deserialized: MojomFile = serde_json::from_value(json).unwrap();
+        assert_eq!(mojom, deserialized);
+    }
+}
@@ -12,11 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod comment;
+#[cfg(feature = "serde")]
+mod json;
 mod parser;
 mod syntax;
 mod traverse;
 mod typespec;
+mod view;
 
+pub use comment::{leading_comments, Comment, CommentKind};
+#[cfg(feature = "serde")]
+pub use json::to_resolved_json;
 pub use syntax::*;
 pub use traverse::{preorder, Traversal};
-pub use typespec::typespec;
+pub use typespec::{typespec, TypeName, TypeSpec, TypeSpecError};
+pub use view::{
+    AttributeView, ConstView, EnumValueView, EnumView, FeatureView, ImportView, InterfaceView,
+    MethodView, ModuleView, MojomFileView, ParameterView, StructFieldView, StructView,
+    UnionFieldView, UnionView, View,
+};
@@ -16,10 +16,172 @@
 #[grammar = "syntax/mojom.pest"]
 pub(crate) struct MojomParser;
 
+pub(crate) type Pair<'a> = pest::iterators::Pair<'a, Rule>;
 pub(crate) type Pairs<'a> = pest::iterators::Pairs<'a, Rule>;
 
-pub(crate) fn consume_token(rule: Rule, pairs: &mut Pairs) {
-    if pairs.next().unwrap().as_rule() != rule {
-        unreachable!()
+/// A pair that the builders don't expect, or a missing pair. The grammar
+/// shouldn't produce these but they're reported as syntax errors at `span`
+/// rather than panics.
+#[derive(Debug)]
+pub(crate) struct BuildError {
+    message: String,
+    span: (usize, usize),
+}
+
+impl BuildError {
+    pub(crate) fn unexpected(pair: &Pair) -> BuildError {
+        let span = pair.as_span();
+        BuildError {
+            message: format!("unexpected {:?}", pair.as_rule()),
+            span: (span.start(), span.end()),
+        }
+    }
+
+    /// Converts this into an error in `input`, the text the pairs were
+    /// parsed from.
+    pub(crate) fn into_pest_error(self, input: &str) -> pest::error::Error<Rule> {
+        let variant = pest::error::ErrorVariant::CustomError {
+            message: self.message,
+        };
+        match pest::Span::new(input, self.span.0, self.span.1) {
+            Some(span) => pest::error::Error::new_from_span(variant, span),
+            None => pest::error::Error::new_from_pos(variant, pest::Position::from_start(input)),
+        }
+    }
+}
+
+pub(crate) type BuildResult<T> = Result<T, BuildError>;
+
+/// Inner pairs of a pair that the builders consume. A missing pair is
+/// reported at the end of the parent.
+pub(crate) struct Items<'a> {
+    pairs: Pairs<'a>,
+    end: usize,
+}
+
+impl<'a> Items<'a> {
+    pub(crate) fn new(pair: Pair<'a>) -> Items<'a> {
+        let end = pair.as_span().end();
+        Items {
+            pairs: pair.into_inner(),
+            end,
+        }
+    }
+
+    pub(crate) fn next_pair(&mut self) -> BuildResult<Pair<'a>> {
+        self.pairs.next().ok_or(BuildError {
+            message: "unexpected end of input".to_owned(),
+            span: (self.end, self.end),
+        })
+    }
+
+    /// Takes the next pair when it is `rule`.
+    pub(crate) fn next_if(&mut self, rule: Rule) -> Option<Pair<'a>> {
+        match self.pairs.peek() {
+            Some(pair) if pair.as_rule() == rule => self.pairs.next(),
+            _ => None,
+        }
+    }
+
+    /// Takes the next pair, which must be `rule`.
+    pub(crate) fn consume(&mut self, rule: Rule) -> BuildResult<Pair<'a>> {
+        let pair = self.next_pair()?;
+        if pair.as_rule() != rule {
+            return Err(BuildError::unexpected(&pair));
+        }
+        Ok(pair)
+    }
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Pair<'a>;
+
+    fn next(&mut self) -> Option<Pair<'a>> {
+        self.pairs.next()
+    }
+}
+
+// Returns the offset after the string that starts at `start`. None when the
+// string isn't closed.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return Some(i + 1),
+            b'\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// Parsing and building the syntax tree recurse on nested types and braces.
+// Deeper inputs are rejected before parsing so that they can't overflow the
+// stack. Real mojoms nest only a few levels.
+const MAX_NESTING_DEPTH: usize = 100;
+
+// Returns an error at the first `<` or `{` that is nested deeper than
+// `MAX_NESTING_DEPTH`. Angle brackets and braces are counted separately.
+// Comments and strings are skipped, and `>` of `=>` doesn't close anything.
+pub(crate) fn nesting_error(input: &str) -> Option<pest::error::Error<Rule>> {
+    let bytes = input.as_bytes();
+    let (mut angles, mut braces) = (0usize, 0usize);
+    // Once a comment or a string is unclosed, the following ones are also
+    // unclosed. Remembering it keeps this linear.
+    let (mut unclosed_comment, mut unclosed_string) = (false, false);
+    let mut i = 0;
+    while i < bytes.len() {
+        let depth = match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                i = input[i..].find('\n').map_or(bytes.len(), |pos| i + pos);
+                continue;
+            }
+            // Unclosed comments and strings aren't skipped because the
+            // grammar matches the opening characters as any other tokens.
+            (b'/', Some(b'*')) if !unclosed_comment => {
+                match input[i + 2..].find("*/") {
+                    Some(pos) => i += 2 + pos + 2,
+                    None => unclosed_comment = true,
+                }
+                continue;
+            }
+            (b'"', _) if !unclosed_string => {
+                match string_end(bytes, i) {
+                    Some(end) => i = end,
+                    None => unclosed_string = true,
+                }
+                continue;
+            }
+            (b'=', Some(b'>')) => {
+                i += 2;
+                continue;
+            }
+            (b'<', _) => {
+                angles += 1;
+                angles
+            }
+            (b'{', _) => {
+                braces += 1;
+                braces
+            }
+            (b'>', _) => {
+                angles = angles.saturating_sub(1);
+                0
+            }
+            (b'}', _) => {
+                braces = braces.saturating_sub(1);
+                0
+            }
+            _ => 0,
+        };
+        if depth > MAX_NESTING_DEPTH {
+            let message = format!("nesting is deeper than {} levels", MAX_NESTING_DEPTH);
+            let variant = pest::error::ErrorVariant::CustomError { message };
+            // `i` is at `<` or `{` so it is a char boundary.
+            let pos = pest::Position::new(input, i).unwrap();
+            return Some(pest::error::Error::new_from_pos(variant, pos));
+        }
+        i += 1;
     }
+    None
 }
@@ -14,9 +14,14 @@
 
 use pest::{Parser, Position, Span};
 
-use super::parser::{consume_token, MojomParser, Pairs, Rule};
-
-#[derive(Debug, Clone, PartialEq)]
+use super::comment::{collect_comments, Comment};
+use super::parser::{
+    nesting_error, BuildError, BuildResult, Items, MojomParser, Pair, Pairs, Rule,
+};
+use super::typespec::{typespec_at, TypeSpec};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Range {
     pub start: usize,
     pub end: usize,
@@ -31,165 +36,308 @@ impl<'a> From<Span<'a>> for Range {
     }
 }
 
-// Skips attribute list if exists. This is tentative.
-fn skip_attribute_list(pairs: &mut Pairs) {
-    match pairs.peek().unwrap().as_rule() {
-        Rule::attribute_section => {
-            pairs.next();
-        }
-        _ => (),
-    }
+// Parses the type at `typ` of `text`. Ranges in the result point into
+// `text`.
+fn parse_type_spec(text: &str, typ: &Range) -> anyhow::Result<TypeSpec> {
+    typespec_at(&text[typ.start..typ.end], typ.start)
 }
 
-fn consume_semicolon(pairs: &mut Pairs) {
-    match pairs.next().unwrap().as_rule() {
-        Rule::t_semicolon => (),
-        _ => unreachable!(),
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attribute {
+    pub name: Range,
+    pub value: Option<Range>,
+}
+
+fn into_attribute(pair: Pair) -> BuildResult<Attribute> {
+    let mut items = Items::new(pair);
+    let name = items.next_pair()?.as_span().into();
+    let value = match items.next_if(Rule::t_equal) {
+        Some(_) => Some(items.next_pair()?.as_span().into()),
+        None => None,
     };
+    expect_end(items)?;
+    Ok(Attribute { name, value })
+}
+
+/// An attribute section like `[Stable, MinVersion=2]`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttributeList {
+    /// The whole section, including brackets.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
+}
+
+impl AttributeList {
+    /// Returns the attribute of which name is `name` in `text`.
+    pub fn find<'a>(&'a self, text: &str, name: &str) -> Option<&'a Attribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| &text[attribute.name.start..attribute.name.end] == name)
+    }
+}
+
+fn into_attribute_list(pair: Pair) -> BuildResult<AttributeList> {
+    let range = pair.as_span().into();
+    let attributes = pair
+        .into_inner()
+        .filter(|item| item.as_rule() == Rule::attribute)
+        .map(into_attribute)
+        .collect::<BuildResult<_>>()?;
+    Ok(AttributeList { range, attributes })
+}
+
+// Consumes attribute list if exists.
+fn consume_attribute_list(items: &mut Items) -> BuildResult<Option<AttributeList>> {
+    items
+        .next_if(Rule::attribute_section)
+        .map(into_attribute_list)
+        .transpose()
+}
+
+fn consume_semicolon(items: &mut Items) -> BuildResult<()> {
+    items.consume(Rule::t_semicolon)?;
+    Ok(())
 }
 
-fn consume_as_range(pairs: &mut Pairs) -> Range {
-    pairs.next().unwrap().as_span().into()
+// Fails when pairs that the builder doesn't know remain in `items`.
+fn expect_end(mut items: Items) -> BuildResult<()> {
+    match items.next() {
+        Some(pair) => Err(BuildError::unexpected(&pair)),
+        None => Ok(()),
+    }
+}
+
+fn consume_as_range(items: &mut Items) -> BuildResult<Range> {
+    Ok(items.next_pair()?.as_span().into())
+}
+
+// The span of a `type_spec` may end with whitespace and comments that were
+// skipped while looking for `?`. The range ends at the last token instead.
+fn consume_type_range(items: &mut Items) -> BuildResult<Range> {
+    let pair = items.consume(Rule::type_spec)?;
+    let span = pair.as_span();
+    let end = pair
+        .into_inner()
+        .last()
+        .map_or(span.end(), |last| last.as_span().end());
+    Ok(Range {
+        start: span.start(),
+        end,
+    })
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Module {
+    /// The whole statement, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
 }
 
-fn into_module(mut pairs: Pairs) -> Module {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_module, &mut pairs);
-    let name = consume_as_range(&mut pairs);
-    consume_semicolon(&mut pairs);
-    Module { name: name }
+fn into_module(pair: Pair) -> BuildResult<Module> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_module)?;
+    let name = consume_as_range(&mut items)?;
+    consume_semicolon(&mut items)?;
+    Ok(Module {
+        range,
+        attributes,
+        name: name,
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Import {
+    /// The whole statement, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub path: Range,
 }
 
-fn into_import(mut pairs: Pairs) -> Import {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_import, &mut pairs);
-    let path = consume_as_range(&mut pairs);
-    consume_semicolon(&mut pairs);
-    Import { path: path }
+fn into_import(pair: Pair) -> BuildResult<Import> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_import)?;
+    let path = consume_as_range(&mut items)?;
+    consume_semicolon(&mut items)?;
+    Ok(Import {
+        range,
+        attributes,
+        path: path,
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Const {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub typ: Range,
     pub name: Range,
     pub value: Range,
 }
 
-fn into_const(mut pairs: Pairs) -> Const {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_const, &mut pairs);
-    let pair = pairs.next().unwrap();
-    let typ = pair.as_span().into();
-    let name = consume_as_range(&mut pairs);
-    consume_token(Rule::t_equal, &mut pairs);
-    let value = consume_as_range(&mut pairs);
-    consume_semicolon(&mut pairs);
-    Const {
+impl Const {
+    /// Parses the type of this constant in `text`, the text it was parsed from.
+    pub fn type_spec(&self, text: &str) -> anyhow::Result<TypeSpec> {
+        parse_type_spec(text, &self.typ)
+    }
+}
+
+fn into_const(pair: Pair) -> BuildResult<Const> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_const)?;
+    let typ = consume_type_range(&mut items)?;
+    let name = consume_as_range(&mut items)?;
+    items.consume(Rule::t_equal)?;
+    let value = consume_as_range(&mut items)?;
+    consume_semicolon(&mut items)?;
+    Ok(Const {
+        range,
+        attributes,
         typ: typ,
         name: name,
         value: value,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EnumValue {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
     pub value: Option<Range>,
 }
 
-fn into_enum_value(mut pairs: Pairs) -> EnumValue {
-    skip_attribute_list(&mut pairs);
-    let name = consume_as_range(&mut pairs);
-    // The next item should be t_equal when it's Some(item).
-    if let Some(item) = pairs.next() {
-        assert!(item.as_rule() == Rule::t_equal);
-    }
-    let value = pairs.next().map(|item| item.as_span().into());
-    EnumValue {
+fn into_enum_value(pair: Pair) -> BuildResult<EnumValue> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let name = consume_as_range(&mut items)?;
+    let value = match items.next_if(Rule::t_equal) {
+        Some(_) => Some(consume_as_range(&mut items)?),
+        None => None,
+    };
+    expect_end(items)?;
+    Ok(EnumValue {
+        range,
+        attributes,
         name: name,
         value: value,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Enum {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
+    /// The block from `{` to `}`. None for declarations without a body like
+    /// `[Native] enum MyEnum;`.
+    pub body: Option<Range>,
     pub values: Vec<EnumValue>,
 }
 
-fn into_enum(mut pairs: Pairs) -> Enum {
-    skip_attribute_list(&mut pairs);
-    let name = consume_as_range(&mut pairs);
+fn into_enum(pair: Pair) -> BuildResult<Enum> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let name = consume_as_range(&mut items)?;
+    let mut body = None;
     let mut values = Vec::new();
-    for item in pairs {
+    for item in items {
         match item.as_rule() {
             Rule::enum_block => {
-                let mut pairs = item.into_inner();
-                consume_token(Rule::t_lbrace, &mut pairs);
-                for item in pairs {
+                body = Some(item.as_span().into());
+                let mut items = Items::new(item);
+                items.consume(Rule::t_lbrace)?;
+                for item in items {
                     let value = match item.as_rule() {
-                        Rule::enum_value => into_enum_value(item.into_inner()),
+                        Rule::enum_value => into_enum_value(item)?,
                         Rule::t_comma => continue,
                         Rule::t_rbrace => break,
-                        _ => unreachable!(),
+                        _ => return Err(BuildError::unexpected(&item)),
                     };
                     values.push(value);
                 }
             }
             Rule::t_semicolon => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item)),
         }
     }
-    Enum {
+    Ok(Enum {
+        range,
+        attributes,
         name: name,
+        body,
         values: values,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StructField {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
     pub default: Option<Range>,
 }
 
-fn into_struct_field(mut pairs: Pairs) -> StructField {
-    skip_attribute_list(&mut pairs);
-    let typ = consume_as_range(&mut pairs);
-    let name = consume_as_range(&mut pairs);
+impl StructField {
+    /// Parses the type of this field in `text`, the text it was parsed from.
+    pub fn type_spec(&self, text: &str) -> anyhow::Result<TypeSpec> {
+        parse_type_spec(text, &self.typ)
+    }
+}
+
+fn into_struct_field(pair: Pair) -> BuildResult<StructField> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let typ = consume_type_range(&mut items)?;
+    let name = consume_as_range(&mut items)?;
     let mut res = StructField {
+        range,
+        attributes,
         typ: typ,
         name: name,
         ordinal: None,
         default: None,
     };
-    for item in pairs {
+    for item in items {
         match item.as_rule() {
             Rule::ordinal_value => res.ordinal = Some(item.as_span().into()),
             Rule::default => {
-                let mut pairs = item.into_inner();
-                consume_token(Rule::t_equal, &mut pairs);
-                res.default = Some(pairs.next().unwrap().as_span().into());
+                let mut items = Items::new(item);
+                items.consume(Rule::t_equal)?;
+                res.default = Some(consume_as_range(&mut items)?);
             }
             Rule::t_semicolon => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item)),
         }
     }
-    res
+    Ok(res)
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StructBody {
     Const(Const),
     Enum(Enum),
@@ -197,234 +345,363 @@ pub enum StructBody {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Struct {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
+    /// The block from `{` to `}`. None for declarations without a body like
+    /// `[Native] struct MyStruct;`.
+    pub body: Option<Range>,
     pub members: Vec<StructBody>,
 }
 
-fn into_struct_members(mut pairs: Pairs) -> Vec<StructBody> {
-    consume_token(Rule::t_lbrace, &mut pairs);
+fn into_struct_members(pair: Pair) -> BuildResult<Vec<StructBody>> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_lbrace)?;
     let mut members = Vec::new();
-    for item in pairs {
+    for item in items {
         if item.as_rule() == Rule::t_rbrace {
             break;
         }
         // At this point `item` should have only one inner and it should be struct_item.
-        let struct_item = item.into_inner().next().unwrap();
+        let struct_item = Items::new(item).next_pair()?;
         let member = match struct_item.as_rule() {
-            Rule::const_stmt => StructBody::Const(into_const(struct_item.into_inner())),
-            Rule::enum_stmt => StructBody::Enum(into_enum(struct_item.into_inner())),
-            Rule::struct_field => StructBody::Field(into_struct_field(struct_item.into_inner())),
-            _ => unreachable!(),
+            Rule::const_stmt => StructBody::Const(into_const(struct_item)?),
+            Rule::enum_stmt => StructBody::Enum(into_enum(struct_item)?),
+            Rule::struct_field => StructBody::Field(into_struct_field(struct_item)?),
+            _ => return Err(BuildError::unexpected(&struct_item)),
         };
         members.push(member);
     }
-    members
+    Ok(members)
 }
 
-fn into_struct(mut pairs: Pairs) -> Struct {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_struct, &mut pairs);
-    let name = consume_as_range(&mut pairs);
-    let item = pairs.next().unwrap();
+fn into_struct(pair: Pair) -> BuildResult<Struct> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_struct)?;
+    let name = consume_as_range(&mut items)?;
+    let item = items.next_pair()?;
     match item.as_rule() {
-        Rule::t_semicolon => {
-            return Struct {
-                name: name,
-                members: Vec::new(),
-            };
-        }
+        Rule::t_semicolon => Ok(Struct {
+            range,
+            attributes,
+            name: name,
+            body: None,
+            members: Vec::new(),
+        }),
         Rule::struct_body => {
-            let members = into_struct_members(item.into_inner());
-            consume_semicolon(&mut pairs);
-            return Struct {
+            let body = Some(item.as_span().into());
+            let members = into_struct_members(item)?;
+            consume_semicolon(&mut items)?;
+            Ok(Struct {
+                range,
+                attributes,
                 name: name,
+                body,
                 members: members,
-            };
+            })
         }
-        _ => unreachable!(),
+        _ => Err(BuildError::unexpected(&item)),
     }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnionField {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
 }
 
-fn into_union_field(mut pairs: Pairs) -> UnionField {
-    skip_attribute_list(&mut pairs);
-    let typ = consume_as_range(&mut pairs);
-    let name = consume_as_range(&mut pairs);
+impl UnionField {
+    /// Parses the type of this field in `text`, the text it was parsed from.
+    pub fn type_spec(&self, text: &str) -> anyhow::Result<TypeSpec> {
+        parse_type_spec(text, &self.typ)
+    }
+}
+
+fn into_union_field(pair: Pair) -> BuildResult<UnionField> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let typ = consume_type_range(&mut items)?;
+    let name = consume_as_range(&mut items)?;
     let mut ordinal = None;
-    for item in pairs {
+    for item in items {
         match item.as_rule() {
             Rule::ordinal_value => ordinal = Some(item.as_span().into()),
             Rule::t_semicolon => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item)),
         }
     }
-    UnionField {
+    Ok(UnionField {
+        range,
+        attributes,
         typ: typ,
         name: name,
         ordinal: ordinal,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Union {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
+    /// The block from `{` to `}`.
+    pub body: Range,
     pub fields: Vec<UnionField>,
 }
 
-fn into_union(mut pairs: Pairs) -> Union {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_union, &mut pairs);
-    let name = consume_as_range(&mut pairs);
-    consume_token(Rule::t_lbrace, &mut pairs);
+fn into_union(pair: Pair) -> BuildResult<Union> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_union)?;
+    let name = consume_as_range(&mut items)?;
+    let lbrace = consume_as_range(&mut items)?;
     let mut fields = Vec::new();
-    loop {
-        let item = pairs.next().unwrap();
+    let rbrace = loop {
+        let item = items.next_pair()?;
         let item = match item.as_rule() {
-            Rule::union_field => into_union_field(item.into_inner()),
-            Rule::t_rbrace => break,
-            _ => unreachable!(),
+            Rule::union_field => into_union_field(item)?,
+            Rule::t_rbrace => break Range::from(item.as_span()),
+            _ => return Err(BuildError::unexpected(&item)),
         };
         fields.push(item);
-    }
-    consume_semicolon(&mut pairs);
-    Union {
+    };
+    consume_semicolon(&mut items)?;
+    Ok(Union {
+        range,
+        attributes,
         name: name,
+        body: Range {
+            start: lbrace.start,
+            end: rbrace.end,
+        },
         fields: fields,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Parameter {
+    /// The whole parameter, including attributes and the ordinal.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
 }
 
-fn into_parameter(mut pairs: Pairs) -> Parameter {
-    let typ = consume_as_range(&mut pairs);
-    let name = consume_as_range(&mut pairs);
-    let ordinal = pairs.next().map(|ord| ord.as_span().into());
-    Parameter {
+impl Parameter {
+    /// Parses the type of this parameter in `text`, the text it was parsed from.
+    pub fn type_spec(&self, text: &str) -> anyhow::Result<TypeSpec> {
+        parse_type_spec(text, &self.typ)
+    }
+}
+
+fn into_parameter(pair: Pair) -> BuildResult<Parameter> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let typ = consume_type_range(&mut items)?;
+    let name = consume_as_range(&mut items)?;
+    let ordinal = items.next().map(|ord| ord.as_span().into());
+    expect_end(items)?;
+    Ok(Parameter {
+        range,
+        attributes,
         typ: typ,
         name: name,
         ordinal: ordinal,
-    }
+    })
 }
 
-fn parameter_list(mut pairs: Pairs) -> Vec<Parameter> {
-    consume_token(Rule::t_lparen, &mut pairs);
+fn parameter_list(pair: Pair) -> BuildResult<Vec<Parameter>> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_lparen)?;
     let mut params = Vec::new();
-    for item in pairs {
+    for item in items {
         let param = match item.as_rule() {
-            Rule::parameter => into_parameter(item.into_inner()),
+            Rule::parameter => into_parameter(item)?,
             Rule::t_comma => continue,
             Rule::t_rparen => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item)),
         };
         params.push(param);
     }
-    params
+    Ok(params)
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Response {
     pub params: Vec<Parameter>,
 }
 
-fn into_response(mut pairs: Pairs) -> Response {
-    consume_token(Rule::t_arrow, &mut pairs);
-    let params = parameter_list(pairs.next().unwrap().into_inner());
-    Response { params: params }
+fn into_response(pair: Pair) -> BuildResult<Response> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_arrow)?;
+    let params = parameter_list(items.next_pair()?)?;
+    Ok(Response { params: params })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Method {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
     pub ordinal: Option<Range>,
     pub params: Vec<Parameter>,
     pub response: Option<Response>,
 }
 
-fn into_method(mut pairs: Pairs) -> Method {
-    skip_attribute_list(&mut pairs);
-    let name = consume_as_range(&mut pairs);
-    let ordinal = match pairs.peek().unwrap().as_rule() {
-        Rule::ordinal_value => pairs.next().map(|ord| ord.as_span().into()),
-        _ => None,
-    };
-    let params = parameter_list(pairs.next().unwrap().into_inner());
+fn into_method(pair: Pair) -> BuildResult<Method> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    let name = consume_as_range(&mut items)?;
+    let ordinal = items
+        .next_if(Rule::ordinal_value)
+        .map(|ord| ord.as_span().into());
+    let params = parameter_list(items.next_pair()?)?;
     let mut response = None;
-    for item in pairs {
+    for item in items {
         match item.as_rule() {
-            Rule::response => response = Some(into_response(item.into_inner())),
+            Rule::response => response = Some(into_response(item)?),
             Rule::t_semicolon => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item)),
         }
     }
-    Method {
+    Ok(Method {
+        range,
+        attributes,
         name: name,
         ordinal: ordinal,
         params: params,
         response: response,
-    }
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterfaceMember {
     Const(Const),
     Enum(Enum),
     Method(Method),
 }
 
-fn into_interface_member(mut pairs: Pairs) -> InterfaceMember {
-    let member = pairs.next().unwrap();
-    match member.as_rule() {
-        Rule::const_stmt => InterfaceMember::Const(into_const(member.into_inner())),
-        Rule::enum_stmt => InterfaceMember::Enum(into_enum(member.into_inner())),
-        Rule::method_stmt => InterfaceMember::Method(into_method(member.into_inner())),
-        _ => unreachable!(),
-    }
+fn into_interface_member(pair: Pair) -> BuildResult<InterfaceMember> {
+    let member = Items::new(pair).next_pair()?;
+    let member = match member.as_rule() {
+        Rule::const_stmt => InterfaceMember::Const(into_const(member)?),
+        Rule::enum_stmt => InterfaceMember::Enum(into_enum(member)?),
+        Rule::method_stmt => InterfaceMember::Method(into_method(member)?),
+        _ => return Err(BuildError::unexpected(&member)),
+    };
+    Ok(member)
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Interface {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
     pub name: Range,
+    /// The block from `{` to `}`.
+    pub body: Range,
     pub members: Vec<InterfaceMember>,
 }
 
-fn into_interface(mut pairs: Pairs) -> Interface {
-    skip_attribute_list(&mut pairs);
-    consume_token(Rule::t_interface, &mut pairs);
-    let name = consume_as_range(&mut pairs);
-    consume_token(Rule::t_lbrace, &mut pairs);
+fn into_interface(pair: Pair) -> BuildResult<Interface> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_interface)?;
+    let name = consume_as_range(&mut items)?;
+    let lbrace = consume_as_range(&mut items)?;
     let mut members = Vec::new();
-    // `for` takes the ownership of `pairs`. Use `loop`.
-    loop {
-        let item = pairs.next().unwrap(); // Should not be None.
+    // `for` takes the ownership of `items`. Use `loop`.
+    let rbrace = loop {
+        let item = items.next_pair()?;
         match item.as_rule() {
             Rule::interface_body => {
-                let member = into_interface_member(item.into_inner());
+                let member = into_interface_member(item)?;
                 members.push(member);
             }
-            Rule::t_rbrace => break,
-            _ => unreachable!(),
+            Rule::t_rbrace => break Range::from(item.as_span()),
+            _ => return Err(BuildError::unexpected(&item)),
         }
-    }
-    consume_semicolon(&mut pairs);
-    Interface {
+    };
+    consume_semicolon(&mut items)?;
+    Ok(Interface {
+        range,
+        attributes,
         name: name,
+        body: Range {
+            start: lbrace.start,
+            end: rbrace.end,
+        },
         members: members,
-    }
+    })
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Feature {
+    /// The whole declaration, including attributes.
+    pub range: Range,
+    pub attributes: Option<AttributeList>,
+    pub name: Range,
+    /// The block from `{` to `}`.
+    pub body: Range,
+    pub members: Vec<Const>,
+}
+
+fn into_feature(pair: Pair) -> BuildResult<Feature> {
+    let range = pair.as_span().into();
+    let mut items = Items::new(pair);
+    let attributes = consume_attribute_list(&mut items)?;
+    items.consume(Rule::t_feature)?;
+    let name = consume_as_range(&mut items)?;
+    let lbrace = consume_as_range(&mut items)?;
+    let mut members = Vec::new();
+    let rbrace = loop {
+        let item = items.next_pair()?;
+        match item.as_rule() {
+            Rule::const_stmt => members.push(into_const(item)?),
+            Rule::t_rbrace => break Range::from(item.as_span()),
+            _ => return Err(BuildError::unexpected(&item)),
+        }
+    };
+    consume_semicolon(&mut items)?;
+    Ok(Feature {
+        range,
+        attributes,
+        name,
+        body: Range {
+            start: lbrace.start,
+            end: rbrace.end,
+        },
+        members,
+    })
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement {
     Module(Module),
     Import(Import),
@@ -433,38 +710,78 @@ pub enum Statement {
     Union(Union),
     Enum(Enum),
     Const(Const),
+    Feature(Feature),
 }
 
-fn into_statement(mut pairs: Pairs) -> Statement {
-    let stmt = pairs.next().unwrap();
-    match stmt.as_rule() {
-        Rule::module_stmt => Statement::Module(into_module(stmt.into_inner())),
-        Rule::import_stmt => Statement::Import(into_import(stmt.into_inner())),
-        Rule::interface => Statement::Interface(into_interface(stmt.into_inner())),
-        Rule::struct_stmt => Statement::Struct(into_struct(stmt.into_inner())),
-        Rule::union_stmt => Statement::Union(into_union(stmt.into_inner())),
-        Rule::enum_stmt => Statement::Enum(into_enum(stmt.into_inner())),
-        Rule::const_stmt => Statement::Const(into_const(stmt.into_inner())),
-        _ => unreachable!(),
+impl Statement {
+    /// The whole statement, including attributes and the trailing semicolon.
+    pub fn range(&self) -> &Range {
+        match self {
+            Statement::Module(stmt) => &stmt.range,
+            Statement::Import(stmt) => &stmt.range,
+            Statement::Interface(stmt) => &stmt.range,
+            Statement::Struct(stmt) => &stmt.range,
+            Statement::Union(stmt) => &stmt.range,
+            Statement::Enum(stmt) => &stmt.range,
+            Statement::Const(stmt) => &stmt.range,
+            Statement::Feature(stmt) => &stmt.range,
+        }
     }
 }
 
+fn into_statement(pair: Pair) -> BuildResult<Statement> {
+    let stmt = Items::new(pair).next_pair()?;
+    let stmt = match stmt.as_rule() {
+        Rule::module_stmt => Statement::Module(into_module(stmt)?),
+        Rule::import_stmt => Statement::Import(into_import(stmt)?),
+        Rule::interface => Statement::Interface(into_interface(stmt)?),
+        Rule::struct_stmt => Statement::Struct(into_struct(stmt)?),
+        Rule::union_stmt => Statement::Union(into_union(stmt)?),
+        Rule::enum_stmt => Statement::Enum(into_enum(stmt)?),
+        Rule::const_stmt => Statement::Const(into_const(stmt)?),
+        Rule::feature_stmt => Statement::Feature(into_feature(stmt)?),
+        _ => return Err(BuildError::unexpected(&stmt)),
+    };
+    Ok(stmt)
+}
+
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MojomFile {
     pub stmts: Vec<Statement>,
+    /// Comments in the file, ordered by position. Empty unless the file is
+    /// parsed by `parse_with_comments()`.
+    pub comments: Vec<Comment>,
 }
 
-fn into_mojom_file(pairs: Pairs) -> MojomFile {
+impl MojomFile {
+    /// Returns the top-level statements that overlap with `range`. A range
+    /// that cuts through a statement includes the whole statement.
+    pub fn statements_in(&self, range: &Range) -> &[Statement] {
+        let start = self
+            .stmts
+            .partition_point(|stmt| stmt.range().end <= range.start);
+        let end = self
+            .stmts
+            .partition_point(|stmt| stmt.range().start < range.end);
+        &self.stmts[start..end.max(start)]
+    }
+}
+
+fn into_mojom_file(pair: Pair) -> BuildResult<MojomFile> {
     let mut stmts = Vec::new();
-    for stmt in pairs {
+    for stmt in Items::new(pair) {
         let stmt = match stmt.as_rule() {
-            Rule::statement => into_statement(stmt.into_inner()),
+            Rule::statement => into_statement(stmt)?,
             Rule::EOI => break,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&stmt)),
         };
         stmts.push(stmt);
     }
-    MojomFile { stmts: stmts }
+    Ok(MojomFile {
+        stmts: stmts,
+        comments: Vec::new(),
+    })
 }
 
 /// Zero-based line/column in a text.
@@ -480,7 +797,7 @@ type PestError = pest::error::Error<Rule>;
 #[derive(Debug)]
 pub struct SyntaxError<'a> {
     input: &'a str,
-    pest_err: PestError,
+    pest_err: Box<PestError>,
     span: (usize, usize),
 }
 
@@ -492,36 +809,107 @@ impl<'a> SyntaxError<'a> {
         let end = line_col(&self.input, end).unwrap();
         (start, end)
     }
+
+    /// Returns the byte offset range of the error.
+    pub fn span(&self) -> Range {
+        let (start, end) = self.span;
+        Range { start, end }
+    }
+
+    /// Returns the message of errors that aren't described by expected
+    /// tokens, e.g. "structs cannot be declared inside an interface".
+    pub fn message(&self) -> Option<&str> {
+        match &self.pest_err.variant {
+            pest::error::ErrorVariant::CustomError { message } => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Returns the tokens that were expected at the error, e.g. `';'`.
+    pub fn expected(&self) -> Vec<String> {
+        let mut expected = Vec::new();
+        if let pest::error::ErrorVariant::ParsingError { positives, .. } = &self.pest_err.variant {
+            for name in positives.iter().map(rule_name) {
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
+        }
+        expected
+    }
 }
 
 impl<'a> std::fmt::Display for SyntaxError<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.pest_err)
+        write!(f, "{}", self.pest_err.clone().renamed_rules(rule_name))
     }
 }
 
-fn find_token_end_position(input: &str, start: usize) -> usize {
-    let mut end = start;
-    for ch in input[start..].chars() {
-        if ch == ' ' || ch == '\r' || ch == '\n' {
-            break;
+// Returns the range of the token at `start`: a word or a single punctuation
+// character. The range doesn't go beyond the end of the line. When nothing follows
+// `start` on the line, the range covers the last non-whitespace character
+// before `start` instead, e.g. the token after which `;` is missing.
+fn error_span(input: &str, start: usize) -> (usize, usize) {
+    let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let line_end = input[start..]
+        .find(['\r', '\n'])
+        .map_or(input.len(), |i| start + i);
+    let line = &input[start..line_end];
+    match line.chars().next() {
+        Some(ch) if is_word_char(ch) => {
+            let len = line.find(|ch| !is_word_char(ch)).unwrap_or(line.len());
+            return (start, start + len);
         }
-        end += 1;
+        Some(ch) => return (start, start + ch.len_utf8()),
+        None => (),
+    }
+    match input[..start]
+        .char_indices()
+        .rfind(|(_, ch)| !ch.is_whitespace())
+    {
+        Some((i, ch)) => (i, i + ch.len_utf8()),
+        None => (start, start),
     }
+}
 
-    if end > input.len() {
-        end = input.len();
+// Returns the name of `rule` that is shown in error messages.
+fn rule_name(rule: &Rule) -> String {
+    match rule {
+        Rule::EOI => "'End of File'".to_owned(),
+        Rule::mojom_file => "statement".to_owned(),
+        Rule::t_array => "array".to_owned(),
+        Rule::t_associated => "associated".to_owned(),
+        Rule::t_const => "const".to_owned(),
+        Rule::t_feature => "feature".to_owned(),
+        Rule::t_handle => "handle".to_owned(),
+        Rule::t_import => "import".to_owned(),
+        Rule::t_interface => "interface".to_owned(),
+        Rule::t_map => "map".to_owned(),
+        Rule::t_module => "module".to_owned(),
+        Rule::t_struct => "struct".to_owned(),
+        Rule::t_union => "union".to_owned(),
+        Rule::t_amp => "'&'".to_owned(),
+        Rule::t_arrow => "'=>'".to_owned(),
+        Rule::t_comma => "','".to_owned(),
+        Rule::t_equal => "'='".to_owned(),
+        Rule::t_langlebracket => "'<'".to_owned(),
+        Rule::t_lbrace => "'{'".to_owned(),
+        Rule::t_lbracket => "'['".to_owned(),
+        Rule::t_lparen => "'('".to_owned(),
+        Rule::t_nullable => "'?'".to_owned(),
+        Rule::t_ranglebracket => "'>'".to_owned(),
+        Rule::t_rbrace => "'}'".to_owned(),
+        Rule::t_rbracket => "']'".to_owned(),
+        Rule::t_rparen => "')'".to_owned(),
+        Rule::t_semicolon => "';'".to_owned(),
+        _ => format!("{:?}", rule),
     }
-    end
 }
 
 impl<'a> SyntaxError<'a> {
-    fn new(input: &str, err: PestError) -> SyntaxError {
+    fn new(input: &str, err: Box<PestError>) -> SyntaxError {
         let span = match &err.location {
-            pest::error::InputLocation::Pos(start) => {
-                let end = find_token_end_position(&input, *start);
-                (*start, end)
-            }
+            pest::error::InputLocation::Pos(start) => error_span(input, *start),
             pest::error::InputLocation::Span((start, end)) => (*start, *end),
         };
         SyntaxError {
@@ -532,49 +920,152 @@ impl<'a> SyntaxError<'a> {
     }
 }
 
-fn parse_input(input: &str) -> Result<Pairs, PestError> {
-    MojomParser::parse(Rule::mojom_file, input).map_err(|err| {
-        err.renamed_rules(|rule| match rule {
-            Rule::EOI => "'End of File'".to_owned(),
-            Rule::mojom_file => "statement".to_owned(),
-            Rule::t_array => "array".to_owned(),
-            Rule::t_associated => "associated".to_owned(),
-            Rule::t_const => "const".to_owned(),
-            Rule::t_handle => "handle".to_owned(),
-            Rule::t_import => "import".to_owned(),
-            Rule::t_interface => "interface".to_owned(),
-            Rule::t_map => "map".to_owned(),
-            Rule::t_module => "module".to_owned(),
-            Rule::t_struct => "struct".to_owned(),
-            Rule::t_union => "union".to_owned(),
-            Rule::t_amp => "'&'".to_owned(),
-            Rule::t_arrow => "'=>'".to_owned(),
-            Rule::t_comma => "','".to_owned(),
-            Rule::t_equal => "'='".to_owned(),
-            Rule::t_langlebracket => "'<'".to_owned(),
-            Rule::t_lbrace => "'{'".to_owned(),
-            Rule::t_lbracket => "'['".to_owned(),
-            Rule::t_lparen => "'('".to_owned(),
-            Rule::t_nullable => "'?'".to_owned(),
-            Rule::t_ranglebracket => "'>'".to_owned(),
-            Rule::t_rbrace => "'}'".to_owned(),
-            Rule::t_rbracket => "']'".to_owned(),
-            Rule::t_rparen => "')'".to_owned(),
-            Rule::t_semicolon => "';'".to_owned(),
-            _ => format!("{:?}", rule),
-        })
-    })
+// Returns the plural name of the declaration that `text` starts with, e.g.
+// "structs". None when it isn't clear what `text` declares.
+fn declaration_kind(text: &str) -> Option<&'static str> {
+    let is_word_char = |ch: char| ch.is_ascii_alphanumeric() || ch == '_';
+    let word_end = text.find(|ch| !is_word_char(ch)).unwrap_or(text.len());
+    let kind = match &text[..word_end] {
+        "" => return None,
+        "struct" => "structs",
+        "union" => "unions",
+        "interface" => "interfaces",
+        "enum" => "enums",
+        "const" => "constants",
+        "feature" => "features",
+        "module" => "module statements",
+        "import" => "import statements",
+        _ => {
+            // A method looks like `Name@1(`.
+            let rest = text[word_end..].trim_start();
+            let rest = match rest.strip_prefix('@') {
+                Some(rest) => rest.trim_start_matches(|ch: char| ch.is_ascii_digit()),
+                None => rest,
+            };
+            if !rest.trim_start().starts_with('(') {
+                return None;
+            }
+            "methods"
+        }
+    };
+    Some(kind)
 }
 
-fn build_syntax_tree(mut pairs: Pairs) -> MojomFile {
-    let inner = pairs.next().unwrap().into_inner();
-    into_mojom_file(inner)
+// Creates an error for an `unknown_member` or `unknown_enum_value` in a
+// `container` declaration. Members that can't be declared in the container
+// are reported at the first token. Otherwise the member is parsed again as a
+// valid member to find where it goes wrong.
+fn unknown_member_error(input: &str, pair: Pair, container: Rule) -> PestError {
+    let (container_name, allowed, member_rule): (_, &[_], _) = match container {
+        Rule::interface => (
+            "an interface",
+            &["enums", "constants", "methods"],
+            Rule::valid_interface_body,
+        ),
+        Rule::struct_stmt => ("a struct", &["enums", "constants"], Rule::valid_struct_item),
+        Rule::union_stmt => ("a union", &[], Rule::union_field),
+        Rule::feature_stmt => ("a feature", &["constants"], Rule::const_stmt),
+        _ => ("an enum", &[], Rule::enum_value),
+    };
+
+    let start = pair.as_span().start();
+    let tokens = match pair.as_rule() {
+        Rule::unknown_member => pair
+            .clone()
+            .into_inner()
+            .find(|item| item.as_rule() == Rule::member_tokens)
+            .unwrap_or(pair),
+        _ => pair,
+    };
+    let tokens_start = tokens.as_span().start();
+    if let Some(kind) = declaration_kind(tokens.as_str()) {
+        if !allowed.contains(&kind) {
+            let message = format!("{} cannot be declared inside {}", kind, container_name);
+            let variant = pest::error::ErrorVariant::CustomError { message };
+            return PestError::new_from_pos(variant, Position::new(input, tokens_start).unwrap());
+        }
+    }
+
+    let (variant, pos) = match MojomParser::parse(member_rule, &input[start..]) {
+        Err(err) => {
+            let pos = match err.location {
+                pest::error::InputLocation::Pos(pos) => pos,
+                pest::error::InputLocation::Span((start, _)) => start,
+            };
+            (err.variant, start + pos)
+        }
+        // The member is valid but unexpected tokens follow it.
+        Ok(pairs) => {
+            let end = pairs.last().map_or(0, |pair| pair.as_span().end());
+            let variant = pest::error::ErrorVariant::ParsingError {
+                positives: Vec::new(),
+                negatives: Vec::new(),
+            };
+            let rest = &input[start + end..];
+            (
+                variant,
+                start + end + (rest.len() - rest.trim_start().len()),
+            )
+        }
+    };
+    PestError::new_from_pos(variant, Position::new(input, pos).unwrap())
+}
+
+// Returns an error for the first unknown member in `pairs`. `container` is
+// the rule of the innermost declaration that contains `pairs`.
+fn find_unknown_member(input: &str, pairs: Pairs, container: Rule) -> Option<PestError> {
+    for pair in pairs {
+        let err = match pair.as_rule() {
+            Rule::unknown_member | Rule::unknown_enum_value => {
+                Some(unknown_member_error(input, pair, container))
+            }
+            rule @ (Rule::interface
+            | Rule::struct_stmt
+            | Rule::union_stmt
+            | Rule::enum_stmt
+            | Rule::feature_stmt) => find_unknown_member(input, pair.into_inner(), rule),
+            _ => find_unknown_member(input, pair.into_inner(), container),
+        };
+        if err.is_some() {
+            return err;
+        }
+    }
+    None
+}
+
+fn parse_input(input: &str) -> Result<Pairs, Box<PestError>> {
+    if let Some(err) = nesting_error(input) {
+        return Err(Box::new(err));
+    }
+    let pairs = MojomParser::parse(Rule::mojom_file, input)?;
+    match find_unknown_member(input, pairs.clone(), Rule::mojom_file) {
+        Some(err) => Err(Box::new(err)),
+        None => Ok(pairs),
+    }
+}
+
+fn build_syntax_tree(input: &str, mut pairs: Pairs) -> Result<MojomFile, Box<PestError>> {
+    let mojom = match pairs.next() {
+        Some(pair) => into_mojom_file(pair),
+        None => Ok(MojomFile {
+            stmts: Vec::new(),
+            comments: Vec::new(),
+        }),
+    };
+    mojom.map_err(|err| Box::new(err.into_pest_error(input)))
 }
 
 /// Parses `input` into a syntax tree.
 pub fn parse(input: &str) -> Result<MojomFile, SyntaxError> {
-    let pairs = parse_input(input).map_err(|err| SyntaxError::new(input, err))?;
-    let mojom = build_syntax_tree(pairs);
+    parse_input(input)
+        .and_then(|pairs| build_syntax_tree(input, pairs))
+        .map_err(|err| SyntaxError::new(input, err))
+}
+
+/// Parses `input` into a syntax tree and records its comments.
+pub fn parse_with_comments(input: &str) -> Result<MojomFile, SyntaxError<'_>> {
+    let mut mojom = parse(input)?;
+    mojom.comments = collect_comments(input);
     Ok(mojom)
 }
 
@@ -591,6 +1082,7 @@ pub fn line_col(text: &str, offset: usize) -> Option<LineCol> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::syntax::TypeName;
 
     fn partial_text<'t>(text: &'t str, range: &Range) -> &'t str {
         &text[range.start..range.end]
@@ -684,6 +1176,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_attribute_list() {
+        let input = r#"[Stable, MinVersion=2, EnableIf=is_win, Name="a.b"]"#;
+        let parsed = MojomParser::parse(Rule::attribute_section, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let list = into_attribute_list(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &list.range));
+        let attributes = &list.attributes;
+        assert_eq!(4, attributes.len());
+        assert_eq!("Stable", partial_text(&input, &attributes[0].name));
+        assert!(attributes[0].value.is_none());
+        assert_eq!("MinVersion", partial_text(&input, &attributes[1].name));
+        assert_eq!(
+            "2",
+            partial_text(&input, attributes[1].value.as_ref().unwrap())
+        );
+        assert_eq!("EnableIf", partial_text(&input, &attributes[2].name));
+        assert_eq!(
+            "is_win",
+            partial_text(&input, attributes[2].value.as_ref().unwrap())
+        );
+        assert_eq!(
+            r#""a.b""#,
+            partial_text(&input, attributes[3].value.as_ref().unwrap())
+        );
+        let attribute = list.find(&input, "MinVersion").unwrap();
+        assert_eq!(&attributes[1], attribute);
+        assert!(list.find(&input, "Unknown").is_none());
+
+        let parsed = MojomParser::parse(Rule::attribute_section, "[]")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(into_attribute_list(parsed).unwrap().attributes.is_empty());
+    }
+
     #[test]
     fn test_types() {
         macro_rules! parse_type {
@@ -728,7 +1258,8 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_module(parsed.into_inner());
+        let stmt = into_module(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("my.mod", partial_text(&input, &stmt.name));
     }
 
@@ -739,7 +1270,8 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_import(parsed.into_inner());
+        let stmt = into_import(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!(r#""my.mod""#, partial_text(&input, &stmt.path));
 
         let input = r#"[Attr] import "my.mod";"#;
@@ -747,8 +1279,11 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_import(parsed.into_inner());
+        let stmt = into_import(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!(r#""my.mod""#, partial_text(&input, &stmt.path));
+        let attributes = stmt.attributes.unwrap();
+        assert_eq!("[Attr]", partial_text(&input, &attributes.range));
     }
 
     #[test]
@@ -758,7 +1293,8 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_const(parsed.into_inner());
+        let stmt = into_const(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("uint32", partial_text(&input, &stmt.typ));
         assert_eq!("kTheAnswer", partial_text(&input, &stmt.name));
         assert_eq!("42", partial_text(&input, &stmt.value));
@@ -771,11 +1307,17 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
+        assert_eq!(
+            "{ kOne, kTwo=2, kThree=IdentValue, }",
+            partial_text(&input, stmt.body.as_ref().unwrap())
+        );
         let values = &stmt.values;
         assert_eq!(3, values.len());
         assert_eq!("kOne", partial_text(&input, &values[0].name));
+        assert_eq!("kTwo=2", partial_text(&input, &values[1].range));
         assert_eq!("kTwo", partial_text(&input, &values[1].name));
         assert_eq!("2", partial_text(&input, values[1].value.as_ref().unwrap()));
         assert_eq!("kThree", partial_text(&input, &values[2].name));
@@ -789,7 +1331,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed).unwrap();
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.values.len());
 
@@ -798,8 +1340,10 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
+        assert!(stmt.body.is_none());
         assert_eq!(0, stmt.values.len());
     }
 
@@ -810,10 +1354,12 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("MyMethod", partial_text(&input, &stmt.name));
         let params = &stmt.params;
         assert_eq!(2, params.len());
+        assert_eq!("string str_arg", partial_text(&input, &params[0].range));
         assert_eq!("string", partial_text(&input, &params[0].typ));
         assert_eq!("str_arg", partial_text(&input, &params[0].name));
         assert_eq!("int8", partial_text(&input, &params[1].typ));
@@ -828,7 +1374,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed).unwrap();
         assert_eq!("MyMethod2", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.params.len());
         assert!(stmt.response.is_none());
@@ -838,7 +1384,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed).unwrap();
         assert_eq!("MyMethod3", partial_text(&input, &stmt.name));
         assert_eq!(1, stmt.params.len());
         let params = &stmt.params;
@@ -846,6 +1392,108 @@ mod tests {
         assert_eq!("default_int8_arg", partial_text(&input, &params[0].name));
         let response = stmt.response.as_ref().unwrap();
         assert_eq!(0, response.params.len());
+        assert!(stmt.attributes.is_none());
+
+        let input = "[Sync, MinVersion=1] MyMethod4([MinVersion=1] int8 arg@1);";
+        let parsed = MojomParser::parse(Rule::method_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_method(parsed).unwrap();
+        assert_eq!("MyMethod4", partial_text(&input, &stmt.name));
+        let attributes = stmt.attributes.as_ref().unwrap();
+        assert_eq!("Sync", partial_text(&input, &attributes.attributes[0].name));
+        assert_eq!(input, partial_text(&input, &stmt.range));
+        let param = &stmt.params[0];
+        assert_eq!(
+            "[MinVersion=1] int8 arg@1",
+            partial_text(&input, &param.range)
+        );
+        assert_eq!("int8", partial_text(&input, &param.typ));
+        assert_eq!("arg", partial_text(&input, &param.name));
+        let attributes = param.attributes.as_ref().unwrap();
+        assert_eq!("[MinVersion=1]", partial_text(&input, &attributes.range));
+
+        let input = "MyMethod5(int8 a, [MinVersion=2] string? b,) => (bool c,);";
+        let parsed = MojomParser::parse(Rule::method_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_method(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
+        let params = &stmt.params;
+        assert_eq!(2, params.len());
+        assert_eq!("a", partial_text(&input, &params[0].name));
+        assert_eq!(
+            "[MinVersion=2] string? b",
+            partial_text(&input, &params[1].range)
+        );
+        let response = stmt.response.as_ref().unwrap();
+        assert_eq!(1, response.params.len());
+        assert_eq!("c", partial_text(&input, &response.params[0].name));
+
+        let input = "MyMethod6(/* no args */) => /* reply */ (\n  // Nothing.\n);";
+        let parsed = MojomParser::parse(Rule::method_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_method(parsed).unwrap();
+        assert_eq!("MyMethod6", partial_text(&input, &stmt.name));
+        assert!(stmt.params.is_empty());
+        assert!(stmt.response.as_ref().unwrap().params.is_empty());
+
+        // A comma needs a parameter before it.
+        assert!(MojomParser::parse(Rule::method_stmt, "MyMethod7(,);").is_err());
+        assert!(MojomParser::parse(Rule::method_stmt, "MyMethod8(int8 a,,);").is_err());
+    }
+
+    #[test]
+    fn test_type_spec() {
+        let input = "struct MyStruct { array<int32>? values; };";
+        let parsed = MojomParser::parse(Rule::struct_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_struct(parsed).unwrap();
+        let field = match &stmt.members[0] {
+            StructBody::Field(field) => field,
+            _ => unreachable!(),
+        };
+        let spec = field.type_spec(&input).unwrap();
+        assert!(spec.is_nullable);
+        match spec.type_name {
+            TypeName::Array(element) => {
+                assert_eq!(
+                    TypeName::BasicTypeName("int32".to_owned()),
+                    element.type_name
+                );
+                assert!(!element.is_nullable);
+            }
+            _ => panic!("Expected array"),
+        };
+
+        let input = "union MyUnion { MyStruct? value; };";
+        let parsed = MojomParser::parse(Rule::union_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_union(parsed).unwrap();
+        let spec = stmt.fields[0].type_spec(&input).unwrap();
+        assert!(spec.is_nullable);
+        assert_eq!(vec!["MyStruct"], spec.referenced_idents());
+
+        let input = "MyMethod(pending_remote<Foo> remote) => (map<string, bool> result);";
+        let parsed = MojomParser::parse(Rule::method_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_method(parsed).unwrap();
+        let spec = stmt.params[0].type_spec(&input).unwrap();
+        assert_eq!(TypeName::PendingRemote("Foo".to_owned()), spec.type_name);
+        let spec = stmt.response.as_ref().unwrap().params[0]
+            .type_spec(&input)
+            .unwrap();
+        assert!(spec.is_builtin_only());
     }
 
     #[test]
@@ -860,8 +1508,11 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_struct(parsed.into_inner());
+        let stmt = into_struct(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("MyStruct", partial_text(&input, &stmt.name));
+        let body = partial_text(&input, stmt.body.as_ref().unwrap());
+        assert!(body.starts_with("{\n") && body.ends_with('}'));
         let members = &stmt.members;
         assert_eq!(4, members.len());
 
@@ -869,12 +1520,17 @@ mod tests {
             StructBody::Const(item) => item,
             _ => unreachable!(),
         };
+        assert_eq!(
+            "const int64 kInvalidId = -1;",
+            partial_text(&input, &item.range)
+        );
         assert_eq!("kInvalidId", partial_text(&input, &item.name));
 
         let item = match &members[1] {
             StructBody::Field(item) => item,
             _ => unreachable!(),
         };
+        assert_eq!("int64 my_id;", partial_text(&input, &item.range));
         assert_eq!("my_id", partial_text(&input, &item.name));
 
         let item = match &members[2] {
@@ -894,9 +1550,55 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_struct(parsed.into_inner());
+        let stmt = into_struct(parsed).unwrap();
         assert_eq!("MyStruct", partial_text(&input, &stmt.name));
+        assert!(stmt.body.is_none());
         assert_eq!(0, stmt.members.len());
+        let attributes = stmt.attributes.unwrap();
+        assert_eq!(
+            "Native",
+            partial_text(&input, &attributes.attributes[0].name)
+        );
+
+        let input = "[Stable] struct MyStruct {
+            [MinVersion=2] int32 field;
+            [EnableIf=is_win] enum E { [Default] kA };
+        };";
+        let parsed = MojomParser::parse(Rule::struct_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_struct(parsed).unwrap();
+        assert!(partial_text(&input, &stmt.range).starts_with("[Stable] struct"));
+        assert!(stmt.attributes.is_some());
+        let field = match &stmt.members[0] {
+            StructBody::Field(field) => field,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            "[MinVersion=2] int32 field;",
+            partial_text(&input, &field.range)
+        );
+        let attribute = &field.attributes.as_ref().unwrap().attributes[0];
+        assert_eq!("MinVersion", partial_text(&input, &attribute.name));
+        assert_eq!("2", partial_text(&input, attribute.value.as_ref().unwrap()));
+        let item = match &stmt.members[1] {
+            StructBody::Enum(item) => item,
+            _ => unreachable!(),
+        };
+        let attribute = &item.attributes.as_ref().unwrap().attributes[0];
+        assert_eq!(
+            "is_win",
+            partial_text(&input, attribute.value.as_ref().unwrap())
+        );
+        assert_eq!(
+            "[EnableIf=is_win] enum E { [Default] kA };",
+            partial_text(&input, &item.range)
+        );
+        let value = &item.values[0];
+        assert_eq!("[Default] kA", partial_text(&input, &value.range));
+        let attribute = &value.attributes.as_ref().unwrap().attributes[0];
+        assert_eq!("Default", partial_text(&input, &attribute.name));
     }
 
     #[test]
@@ -909,8 +1611,13 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let intr = into_interface(parsed.into_inner());
+        let intr = into_interface(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &intr.range));
         assert_eq!("MyInterface", partial_text(&input, &intr.name));
+        assert_eq!(
+            &input["interface MyInterface ".len()..input.len() - 1],
+            partial_text(&input, &intr.body)
+        );
         let members = &intr.members;
         assert_eq!(2, members.len());
 
@@ -918,12 +1625,15 @@ mod tests {
             InterfaceMember::Method(member) => member,
             _ => unreachable!(),
         };
+        assert_eq!("MyMethod();", partial_text(&input, &member.range));
         assert_eq!("MyMethod", partial_text(&input, &member.name));
 
         let member = match &members[1] {
             InterfaceMember::Enum(member) => member,
             _ => unreachable!(),
         };
+        let range = partial_text(&input, &member.range);
+        assert!(range.starts_with("enum") && range.ends_with(';'));
         assert_eq!("MyEnum", partial_text(&input, &member.name));
     }
 
@@ -938,15 +1648,219 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_union(parsed.into_inner());
+        let stmt = into_union(parsed).unwrap();
+        assert_eq!(input, partial_text(&input, &stmt.range));
         assert_eq!("MyUnion", partial_text(&input, &stmt.name));
+        assert_eq!(
+            &input["union MyUnion ".len()..input.len() - 1],
+            partial_text(&input, &stmt.body)
+        );
         let fields = &stmt.fields;
         assert_eq!(3, fields.len());
+        assert_eq!("string str_field;", partial_text(&input, &fields[0].range));
         assert_eq!("str_field", partial_text(&input, &fields[0].name));
         assert_eq!("pair_field", partial_text(&input, &fields[1].name));
         assert_eq!("int64_field", partial_text(&input, &fields[2].name));
     }
 
+    #[test]
+    fn test_feature_stmt() {
+        // From mojo/public/interfaces/bindings/tests/runtime_feature.test-mojom.
+        let input = r#"
+module mojo.test.runtime_feature;
+
+feature kTestFeatureOn {
+  const string name = "TestFeatureOn";
+  const bool default_state = true;
+};
+
+feature kTestFeatureOff {
+  const string name = "TestFeatureOff";
+  const bool default_state = false;
+};
+
+[RuntimeFeature=kTestFeatureOn]
+interface EnabledInterface {
+  Method();
+};
+
+interface GatedMethods {
+  [RuntimeFeature=kTestFeatureOn]
+  MethodWithFeatureOn(bool arg) => (bool out);
+  [RuntimeFeature=kTestFeatureOff]
+  MethodWithFeatureOff(bool arg) => (bool out);
+};
+"#;
+        let mojom = parse(input).unwrap();
+        assert_eq!(5, mojom.stmts.len());
+        let feature = match &mojom.stmts[1] {
+            Statement::Feature(feature) => feature,
+            stmt => panic!("Expected a feature but got {:?}", stmt),
+        };
+        assert_eq!("kTestFeatureOn", partial_text(input, &feature.name));
+        assert!(partial_text(input, &feature.range).starts_with("feature"));
+        assert!(partial_text(input, &feature.body).starts_with('{'));
+        assert_eq!(2, feature.members.len());
+        assert_eq!("name", partial_text(input, &feature.members[0].name));
+        assert_eq!(
+            "\"TestFeatureOn\"",
+            partial_text(input, &feature.members[0].value)
+        );
+        assert_eq!(
+            "default_state",
+            partial_text(input, &feature.members[1].name)
+        );
+
+        let interface = match &mojom.stmts[3] {
+            Statement::Interface(interface) => interface,
+            stmt => panic!("Expected an interface but got {:?}", stmt),
+        };
+        let attribute = &interface.attributes.as_ref().unwrap().attributes[0];
+        assert_eq!("RuntimeFeature", partial_text(input, &attribute.name));
+        assert_eq!(
+            "kTestFeatureOn",
+            partial_text(input, attribute.value.as_ref().unwrap())
+        );
+
+        // `feature` is still a valid name.
+        let input = "struct Foo { Feature feature; int32 features; };";
+        assert!(parse(input).is_ok());
+
+        let input = "feature kFoo {\n  Bar();\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            Some("methods cannot be declared inside a feature"),
+            err.message()
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_missing_semicolon() {
+        let input = "module foo\nstruct Bar {};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("struct", partial_text(input, &err.span()));
+        assert_eq!(vec!["';'"], err.expected());
+
+        // A field without `;` before `}`.
+        let input = "struct Foo { int32 a };";
+        let err = parse(input).unwrap_err();
+        assert_eq!("}", partial_text(input, &err.span()));
+        assert!(err.expected().contains(&"';'".to_owned()));
+
+        // Missing `;` at the end of the file points at the last token.
+        let input = "const int32 kA = 1\n";
+        let err = parse(input).unwrap_err();
+        assert_eq!("1", partial_text(input, &err.span()));
+        assert_eq!(vec!["';'"], err.expected());
+    }
+
+    #[test]
+    fn test_syntax_error_unclosed_brace() {
+        let input = "struct Foo {\n  int32 a;\n";
+        let err = parse(input).unwrap_err();
+        let span = err.span();
+        assert!(span.start < span.end);
+        assert_eq!(";", partial_text(input, &span));
+        assert!(err.expected().contains(&"'}'".to_owned()));
+    }
+
+    #[test]
+    fn test_syntax_error_invalid_member() {
+        let input = "interface Foo {\n  Bar();\n  struct Baz { int32 a; };\n  Qux();\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("struct", partial_text(input, &err.span()));
+        assert_eq!(2, err.range().0.line);
+        assert_eq!(
+            Some("structs cannot be declared inside an interface"),
+            err.message()
+        );
+
+        let input = "struct Foo {\n  int32 a;\n  [Sync] Bar@1(int32 b) => ();\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("Bar", partial_text(input, &err.span()));
+        assert_eq!(2, err.range().0.line);
+        assert_eq!(
+            Some("methods cannot be declared inside a struct"),
+            err.message()
+        );
+
+        let input = "union Foo {\n  int32 a;\n  const int32 kB = 1;\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("const", partial_text(input, &err.span()));
+        assert_eq!(
+            Some("constants cannot be declared inside a union"),
+            err.message()
+        );
+
+        let input = "enum Foo {\n  kA,\n  123 kB,\n  kC,\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("123", partial_text(input, &err.span()));
+        assert_eq!(2, err.range().0.line);
+        assert!(err.message().is_none());
+
+        let input = "enum Foo {\n  kA,\n  kB kC,\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("kC", partial_text(input, &err.span()));
+        assert!(err.expected().is_empty());
+    }
+
+    #[test]
+    fn test_syntax_error_in_member() {
+        // Errors in allowed members point at the token where parsing fails.
+        let input = "interface Foo {\n  Bar(int32 a b);\n  Baz();\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!("b", partial_text(input, &err.span()));
+        assert_eq!(1, err.range().0.line);
+        assert!(err.expected().contains(&"')'".to_owned()));
+
+        let input = "struct Foo {\n  int32 a = ;\n  int32 b;\n};";
+        let err = parse(input).unwrap_err();
+        assert_eq!(";", partial_text(input, &err.span()));
+        assert_eq!(1, err.range().0.line);
+    }
+
+    #[test]
+    fn test_syntax_error_too_deep() {
+        // These used to overflow the stack.
+        let input = format!(
+            "struct Foo {{\n  {}int32{} a;\n}};",
+            "array<".repeat(100_000),
+            ">".repeat(100_000)
+        );
+        let err = parse(&input).unwrap_err();
+        assert_eq!(Some("nesting is deeper than 100 levels"), err.message());
+        // The `<` of the 101st `array<`.
+        assert_eq!(15 + 600 + 5, err.span().start);
+        assert_eq!("<", partial_text(&input, &err.span()));
+
+        // Unclosed strings and comments don't hide braces.
+        for prefix in &["", "\"", "/*"] {
+            let input = format!(
+                "interface Foo {{ Bar {}{}; }};",
+                prefix,
+                "{".repeat(100_000)
+            );
+            let err = parse(&input).unwrap_err();
+            assert_eq!(Some("nesting is deeper than 100 levels"), err.message());
+        }
+
+        let input = format!("const string kFoo = {}a;", "a.".repeat(100_000));
+        parse(&input).unwrap();
+
+        // Up to 100 levels parse. Brackets in comments and strings, and `=>`
+        // don't count.
+        let input = format!(
+            "struct Foo {{\n  {}int32{} a;\n}};\n// {}\n[A=\"{}\"] interface B {{ C() => (); }};",
+            "array<".repeat(99),
+            ">".repeat(99),
+            "<{".repeat(200),
+            "<{".repeat(200)
+        );
+        parse(&input).unwrap();
+        let input = format!("{}struct Foo {{}};", "{ = > }".repeat(200));
+        assert!(parse(&input).unwrap_err().message().is_none());
+    }
+
     #[test]
     fn test_parse() {
         let input = r#"
@@ -998,5 +1912,180 @@ mod tests {
         "#;
         let res = parse(input).unwrap();
         assert_eq!(16, res.stmts.len());
+
+        let mut counts = std::collections::BTreeMap::new();
+        for traversal in crate::syntax::preorder(&res) {
+            let kind = format!("{:?}", traversal);
+            let kind = kind.split('(').next().unwrap().to_owned();
+            *counts.entry(kind).or_insert(0) += 1;
+        }
+        let counts = counts
+            .iter()
+            .map(|(kind, count)| (kind.as_str(), *count))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            ("Const", 3),
+            ("EnterInterface", 5),
+            ("EnterMojomFile", 1),
+            ("EnterResponse", 4),
+            ("EnterStruct", 2),
+            ("Enum", 3),
+            ("EnumValue", 6),
+            ("Import", 2),
+            ("LeaveEnum", 3),
+            ("LeaveInterface", 5),
+            ("LeaveMethod", 4),
+            ("LeaveMojomFile", 1),
+            ("LeaveResponse", 4),
+            ("LeaveStruct", 2),
+            ("LeaveUnion", 2),
+            ("Method", 4),
+            ("Module", 1),
+            ("Parameter", 6),
+            ("StructField", 2),
+            ("Union", 2),
+            ("UnionField", 4),
+        ];
+        assert_eq!(expected, counts);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&res).unwrap();
+            let deserialized: MojomFile = serde_json::from_str(&json).unwrap();
+            assert_eq!(res, deserialized);
+
+            let json = crate::syntax::to_resolved_json(&res, input).to_string();
+            for name in &[
+                "test.mod",
+                "MyEnum2",
+                "kMyConst2",
+                "my_float_value",
+                "MyUnion2",
+            ] {
+                assert!(json.contains(&format!(r#""text":"{}""#, name)), "{}", name);
+            }
+            for name in &["InterfaceD", "kMessage", "MethodC", "optional_result"] {
+                assert!(json.contains(&format!(r#""text":"{}""#, name)), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_parameters() {
+        // Parameter lists in the style of recent Chromium interfaces.
+        let input = r#"
+        module blink.mojom;
+
+        import "mojo/public/mojom/base/file_path.mojom";
+
+        [Stable, Uuid="2ea9a7a5-4d6c-4c65-9d94-b0c1e2ee9c2a"]
+        interface FileChooser {
+          [Sync]
+          OpenFileChooser@0(
+              FileChooserParams params,
+              [MinVersion=1] bool? is_directory,
+          ) => (FileChooserResult? result);
+
+          EnumerateChosenDirectory@1(
+              mojo_base.mojom.FilePath directory_path) =>
+              // Entries of the directory.
+              (array<mojo_base.mojom.FilePath> entries,);
+
+          Cancel@2(/* no args */) => ();
+        };
+        "#;
+        let res = parse(input).unwrap();
+        let methods = crate::syntax::preorder(&res)
+            .filter_map(|traversal| match traversal {
+                crate::syntax::Traversal::Method(method) => Some(method),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(3, methods.len());
+
+        let params = &methods[0].params;
+        assert_eq!(2, params.len());
+        assert_eq!("params", partial_text(&input, &params[0].name));
+        assert!(params[0].attributes.is_none());
+        assert_eq!("bool?", partial_text(&input, &params[1].typ));
+        let attributes = params[1].attributes.as_ref().unwrap();
+        assert_eq!(
+            "MinVersion",
+            partial_text(&input, &attributes.attributes[0].name)
+        );
+
+        let response = methods[1].response.as_ref().unwrap();
+        assert_eq!(1, response.params.len());
+        assert_eq!(
+            "array<mojo_base.mojom.FilePath>",
+            partial_text(&input, &response.params[0].typ)
+        );
+
+        assert!(methods[2].params.is_empty());
+        assert!(methods[2].response.as_ref().unwrap().params.is_empty());
+    }
+
+    #[test]
+    fn test_statements_in() {
+        let input = r#"
+        module test.mod;
+        struct MyStruct {
+            int32 value;
+        };
+        [Attr]
+        interface MyInterface {};
+        "#;
+        let res = parse(input).unwrap();
+        let texts = |start: &str, end: &str| {
+            let range = Range {
+                start: input.find(start).unwrap(),
+                end: input.find(end).unwrap(),
+            };
+            res.statements_in(&range)
+                .iter()
+                .map(|stmt| partial_text(input, stmt.range()))
+                .collect::<Vec<_>>()
+        };
+
+        // A range inside a statement expands to the whole statement.
+        assert_eq!(
+            vec!["struct MyStruct {\n            int32 value;\n        };"],
+            texts("int32", "value")
+        );
+        assert_eq!(vec!["module test.mod;"], texts("test", "mod;"));
+        // Attributes are part of the statement.
+        assert_eq!(
+            vec!["[Attr]\n        interface MyInterface {};"],
+            texts("Interface", "{};")
+        );
+        assert_eq!(3, texts("test", "Interface").len());
+        // Whitespace between statements.
+        assert!(texts("\n        [Attr]", "[Attr]").is_empty());
+    }
+
+    #[test]
+    fn test_unexpected_pairs() {
+        let span = |err: PestError| match err.location {
+            pest::error::InputLocation::Span(span) => span,
+            pest::error::InputLocation::Pos(pos) => (pos, pos),
+        };
+
+        // A pair of another rule is reported at its span.
+        let input = "enum Foo { kA };";
+        let parsed = MojomParser::parse(Rule::enum_stmt, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = into_struct(parsed).unwrap_err().into_pest_error(input);
+        assert_eq!((5, 8), span(err));
+
+        // A missing pair is reported at the end of its parent.
+        let input = "Foo";
+        let parsed = MojomParser::parse(Rule::identifier, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let err = into_statement(parsed).unwrap_err().into_pest_error(input);
+        assert_eq!((3, 3), span(err));
     }
 }
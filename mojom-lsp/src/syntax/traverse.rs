@@ -14,6 +14,12 @@
 
 use super::syntax::*;
 
+/// A step of a preorder traversal. Methods and enums have children: `Method`
+/// is followed by `Parameter`s of the request, then the response wrapped in
+/// `EnterResponse` and `LeaveResponse`, then `LeaveMethod`. `Enum` is
+/// followed by `EnumValue`s, then `LeaveEnum`. `Union` is followed by
+/// `UnionField`s, then `LeaveUnion`. `Feature` is followed by `Const`s, then
+/// `LeaveFeature`.
 #[derive(Debug, PartialEq)]
 pub enum Traversal<'a> {
     EnterMojomFile(&'a MojomFile),
@@ -25,10 +31,53 @@ pub enum Traversal<'a> {
     Module(&'a Module),
     Import(&'a Import),
     Method(&'a Method),
+    LeaveMethod(&'a Method),
+    Parameter(&'a Parameter),
+    EnterResponse(&'a Response),
+    LeaveResponse(&'a Response),
     Union(&'a Union),
+    LeaveUnion(&'a Union),
+    UnionField(&'a UnionField),
     Enum(&'a Enum),
+    LeaveEnum(&'a Enum),
+    EnumValue(&'a EnumValue),
     Const(&'a Const),
     StructField(&'a StructField),
+    Feature(&'a Feature),
+    LeaveFeature(&'a Feature),
+}
+
+impl<'a> Traversal<'a> {
+    /// Returns the attributes of the node that this step enters or visits.
+    /// Steps that leave a node return None so that each attribute section is
+    /// returned once.
+    pub fn attributes(&self) -> Option<&'a AttributeList> {
+        match self {
+            Traversal::EnterInterface(node) => node.attributes.as_ref(),
+            Traversal::EnterStruct(node) => node.attributes.as_ref(),
+            Traversal::Module(node) => node.attributes.as_ref(),
+            Traversal::Import(node) => node.attributes.as_ref(),
+            Traversal::Method(node) => node.attributes.as_ref(),
+            Traversal::Parameter(node) => node.attributes.as_ref(),
+            Traversal::Union(node) => node.attributes.as_ref(),
+            Traversal::UnionField(node) => node.attributes.as_ref(),
+            Traversal::Enum(node) => node.attributes.as_ref(),
+            Traversal::EnumValue(node) => node.attributes.as_ref(),
+            Traversal::Const(node) => node.attributes.as_ref(),
+            Traversal::StructField(node) => node.attributes.as_ref(),
+            Traversal::Feature(node) => node.attributes.as_ref(),
+            Traversal::EnterMojomFile(_)
+            | Traversal::LeaveMojomFile(_)
+            | Traversal::LeaveInterface(_)
+            | Traversal::LeaveStruct(_)
+            | Traversal::LeaveMethod(_)
+            | Traversal::EnterResponse(_)
+            | Traversal::LeaveResponse(_)
+            | Traversal::LeaveUnion(_)
+            | Traversal::LeaveEnum(_)
+            | Traversal::LeaveFeature(_) => None,
+        }
+    }
 }
 
 enum Node<'a> {
@@ -52,9 +101,9 @@ macro_rules! define_leaf {
 
 define_leaf!(Module);
 define_leaf!(Import);
-define_leaf!(Method);
-define_leaf!(Union);
-define_leaf!(Enum);
+define_leaf!(Parameter);
+define_leaf!(UnionField);
+define_leaf!(EnumValue);
 define_leaf!(Const);
 define_leaf!(StructField);
 
@@ -82,9 +131,10 @@ impl NonLeaf for MojomFile {
             Statement::Import(i) => Node::Leaf(i),
             Statement::Interface(i) => Node::NonLeaf(i),
             Statement::Struct(s) => Node::NonLeaf(s),
-            Statement::Union(u) => Node::Leaf(u),
-            Statement::Enum(e) => Node::Leaf(e),
+            Statement::Union(u) => Node::NonLeaf(u),
+            Statement::Enum(e) => Node::NonLeaf(e),
             Statement::Const(c) => Node::Leaf(c),
+            Statement::Feature(f) => Node::NonLeaf(f),
         };
         Some(node)
     }
@@ -105,8 +155,8 @@ impl NonLeaf for Interface {
         }
         let node = match &self.members[pos] {
             InterfaceMember::Const(c) => Node::Leaf(c),
-            InterfaceMember::Enum(e) => Node::Leaf(e),
-            InterfaceMember::Method(m) => Node::Leaf(m),
+            InterfaceMember::Enum(e) => Node::NonLeaf(e),
+            InterfaceMember::Method(m) => Node::NonLeaf(m),
         };
         Some(node)
     }
@@ -127,13 +177,87 @@ impl NonLeaf for Struct {
         }
         let node = match &self.members[pos] {
             StructBody::Const(c) => Node::Leaf(c),
-            StructBody::Enum(e) => Node::Leaf(e),
+            StructBody::Enum(e) => Node::NonLeaf(e),
             StructBody::Field(f) => Node::Leaf(f),
         };
         Some(node)
     }
 }
 
+impl NonLeaf for Method {
+    fn enter(&self) -> Traversal<'_> {
+        Traversal::Method(self)
+    }
+
+    fn leave(&self) -> Traversal<'_> {
+        Traversal::LeaveMethod(self)
+    }
+
+    fn visit_child(&self, pos: usize) -> Option<Node<'_>> {
+        match self.params.get(pos) {
+            Some(param) => Some(Node::Leaf(param)),
+            None if pos == self.params.len() => self.response.as_ref().map(|r| Node::NonLeaf(r)),
+            None => None,
+        }
+    }
+}
+
+impl NonLeaf for Response {
+    fn enter(&self) -> Traversal<'_> {
+        Traversal::EnterResponse(self)
+    }
+
+    fn leave(&self) -> Traversal<'_> {
+        Traversal::LeaveResponse(self)
+    }
+
+    fn visit_child(&self, pos: usize) -> Option<Node<'_>> {
+        self.params.get(pos).map(|param| Node::Leaf(param))
+    }
+}
+
+impl NonLeaf for Union {
+    fn enter(&self) -> Traversal<'_> {
+        Traversal::Union(self)
+    }
+
+    fn leave(&self) -> Traversal<'_> {
+        Traversal::LeaveUnion(self)
+    }
+
+    fn visit_child(&self, pos: usize) -> Option<Node<'_>> {
+        self.fields.get(pos).map(|field| Node::Leaf(field))
+    }
+}
+
+impl NonLeaf for Enum {
+    fn enter(&self) -> Traversal<'_> {
+        Traversal::Enum(self)
+    }
+
+    fn leave(&self) -> Traversal<'_> {
+        Traversal::LeaveEnum(self)
+    }
+
+    fn visit_child(&self, pos: usize) -> Option<Node<'_>> {
+        self.values.get(pos).map(|value| Node::Leaf(value))
+    }
+}
+
+impl NonLeaf for Feature {
+    fn enter(&self) -> Traversal<'_> {
+        Traversal::Feature(self)
+    }
+
+    fn leave(&self) -> Traversal<'_> {
+        Traversal::LeaveFeature(self)
+    }
+
+    fn visit_child(&self, pos: usize) -> Option<Node<'_>> {
+        self.members.get(pos).map(|member| Node::Leaf(member))
+    }
+}
+
 enum TraversalState<'a> {
     NonLeaf(&'a dyn NonLeaf),
     Child(&'a dyn NonLeaf, usize),
@@ -217,4 +341,100 @@ mod tests {
             .unwrap();
         assert_eq!("MyMethod", partial_text(&input, &method.name));
     }
+
+    // Returns a short description of each step of the traversal.
+    fn describe(input: &str, mojom: &MojomFile) -> Vec<String> {
+        let text = |range: &Range| partial_text(input, range).to_owned();
+        preorder(mojom)
+            .map(|t| match t {
+                Traversal::EnterMojomFile(_) => "EnterMojomFile".to_owned(),
+                Traversal::LeaveMojomFile(_) => "LeaveMojomFile".to_owned(),
+                Traversal::EnterInterface(n) => format!("EnterInterface {}", text(&n.name)),
+                Traversal::LeaveInterface(n) => format!("LeaveInterface {}", text(&n.name)),
+                Traversal::Method(n) => format!("Method {}", text(&n.name)),
+                Traversal::LeaveMethod(n) => format!("LeaveMethod {}", text(&n.name)),
+                Traversal::Parameter(n) => format!("Parameter {}", text(&n.name)),
+                Traversal::EnterResponse(_) => "EnterResponse".to_owned(),
+                Traversal::LeaveResponse(_) => "LeaveResponse".to_owned(),
+                Traversal::Enum(n) => format!("Enum {}", text(&n.name)),
+                Traversal::LeaveEnum(n) => format!("LeaveEnum {}", text(&n.name)),
+                Traversal::EnumValue(n) => format!("EnumValue {}", text(&n.name)),
+                Traversal::Union(n) => format!("Union {}", text(&n.name)),
+                Traversal::LeaveUnion(n) => format!("LeaveUnion {}", text(&n.name)),
+                Traversal::UnionField(n) => format!("UnionField {}", text(&n.name)),
+                Traversal::Feature(n) => format!("Feature {}", text(&n.name)),
+                Traversal::LeaveFeature(n) => format!("LeaveFeature {}", text(&n.name)),
+                Traversal::Const(n) => format!("Const {}", text(&n.name)),
+                t => format!("{:?}", t),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_preorder_children() {
+        let input = r#"
+        interface MyInterface {
+            enum MyEnum { kA, kB };
+            MyMethod(int32 a, string b) => (bool result);
+            NoResponse();
+        };
+        union MyUnion { int32 a; string b; };
+        feature kMyFeature {
+            const string name = "MyFeature";
+            const bool default_state = false;
+        };
+        "#;
+        let mojom = parse(input).unwrap();
+        let expected = vec![
+            "EnterMojomFile",
+            "EnterInterface MyInterface",
+            "Enum MyEnum",
+            "EnumValue kA",
+            "EnumValue kB",
+            "LeaveEnum MyEnum",
+            "Method MyMethod",
+            "Parameter a",
+            "Parameter b",
+            "EnterResponse",
+            "Parameter result",
+            "LeaveResponse",
+            "LeaveMethod MyMethod",
+            "Method NoResponse",
+            "LeaveMethod NoResponse",
+            "LeaveInterface MyInterface",
+            "Union MyUnion",
+            "UnionField a",
+            "UnionField b",
+            "LeaveUnion MyUnion",
+            "Feature kMyFeature",
+            "Const name",
+            "Const default_state",
+            "LeaveFeature kMyFeature",
+            "LeaveMojomFile",
+        ];
+        assert_eq!(expected, describe(input, &mojom));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let input = r#"
+        [JavaPackage="org.chromium.test"]
+        module test;
+        [Stable]
+        interface MyInterface {
+            [MinVersion=1]
+            MyMethod([RuntimeFeature=kMyFeature] int32 a);
+        };
+        "#;
+        let mojom = parse(input).unwrap();
+        let names = preorder(&mojom)
+            .filter_map(|t| t.attributes())
+            .flat_map(|list| list.attributes.iter())
+            .map(|attribute| partial_text(input, &attribute.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["JavaPackage", "Stable", "MinVersion", "RuntimeFeature"],
+            names
+        );
+    }
 }
@@ -14,126 +14,349 @@
 
 use pest::Parser;
 
-use super::parser::{consume_token, MojomParser, Pairs, Rule};
+use super::parser::{nesting_error, BuildError, Items, MojomParser, Pair, Rule};
+use super::syntax::Range;
+
+// Mojo rejects fixed-size arrays larger than this.
+const MAX_FIXED_ARRAY_SIZE: u64 = u32::MAX as u64;
 
-// TODO: Support pending_receiver<T> and pending_remote<T>.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeName {
-    FixedArray(Box<TypeSpec>, u64 /* size */),
+    FixedArray(Box<TypeSpec>, u64 /* size */, Range /* size */),
     Array(Box<TypeSpec>),
-    Map(String, Box<TypeSpec>),
+    Map(Box<TypeSpec>, Box<TypeSpec>),
     InterfaceRequest(String, bool /* associated */),
+    PendingRemote(String),
+    PendingReceiver(String),
+    PendingAssociatedRemote(String),
+    PendingAssociatedReceiver(String),
     Handle(Option<String>),
     Associated(String),
     BasicTypeName(String),
 }
 
-fn into_handle(mut pairs: Pairs) -> TypeName {
-    consume_token(Rule::t_handle, &mut pairs);
+const NUMERIC_TYPES: &[&str] = &[
+    "bool", "int8", "uint8", "int16", "uint16", "int32", "uint32", "int64", "uint64", "float",
+    "double",
+];
+
+fn into_handle(pair: Pair) -> Result<TypeName, BuildTypeError> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_handle)?;
     let mut specific_handle_type = None;
-    if let Some(token) = pairs.next() {
-        assert_eq!(Rule::t_langlebracket, token.as_rule());
-        specific_handle_type = Some(pairs.next().unwrap().as_str().to_owned());
-        consume_token(Rule::t_ranglebracket, &mut pairs);
+    if items.next_if(Rule::t_langlebracket).is_some() {
+        specific_handle_type = Some(items.next_pair()?.as_str().to_owned());
+        items.consume(Rule::t_ranglebracket)?;
     }
-    TypeName::Handle(specific_handle_type)
+    expect_end(items)?;
+    Ok(TypeName::Handle(specific_handle_type))
 }
 
-fn into_basic_name(mut pairs: Pairs) -> TypeName {
-    let item = pairs.next().unwrap();
-    match item.as_rule() {
+// Converts `pending_*<T>` into the interface name `T`.
+fn into_pending_target(pair: Pair) -> Result<String, BuildError> {
+    let mut items = Items::new(pair);
+    loop {
+        let item = items.next_pair()?;
+        if item.as_rule() == Rule::identifier {
+            return Ok(item.as_str().to_owned());
+        }
+    }
+}
+
+fn into_basic_name(pair: Pair) -> Result<TypeName, BuildTypeError> {
+    let mut items = Items::new(pair);
+    let item = items.next_pair()?;
+    let type_name = match item.as_rule() {
         Rule::numeric_type => TypeName::BasicTypeName(item.as_str().to_owned()),
-        Rule::handle_type => into_handle(item.into_inner()),
+        Rule::handle_type => into_handle(item)?,
+        Rule::remote_type => TypeName::PendingRemote(into_pending_target(item)?),
+        Rule::receiver_type => TypeName::PendingReceiver(into_pending_target(item)?),
+        Rule::associated_remote_type => {
+            TypeName::PendingAssociatedRemote(into_pending_target(item)?)
+        }
+        Rule::associated_receiver_type => {
+            TypeName::PendingAssociatedReceiver(into_pending_target(item)?)
+        }
         Rule::t_associated => {
-            let ident = pairs.next().unwrap().as_str().to_owned();
+            let ident = items.next_pair()?.as_str().to_owned();
             TypeName::Associated(ident)
         }
         Rule::identifier => TypeName::BasicTypeName(item.as_str().to_owned()),
-        _ => unreachable!(),
+        _ => return Err(BuildError::unexpected(&item).into()),
+    };
+    Ok(type_name)
+}
+
+/// An error of a type that is syntactically valid but can't be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSpecError {
+    /// The size of a fixed-size array is zero or too large. `range` is the
+    /// size token.
+    FixedArraySizeOutOfRange { size: String, range: Range },
+}
+
+impl TypeSpecError {
+    /// The range of the token that caused the error.
+    pub fn range(&self) -> &Range {
+        match self {
+            TypeSpecError::FixedArraySizeOutOfRange { range, .. } => range,
+        }
     }
 }
 
-fn into_array(mut pairs: Pairs) -> TypeName {
-    consume_token(Rule::t_array, &mut pairs);
-    consume_token(Rule::t_langlebracket, &mut pairs);
-    let type_spec = into_type_spec(pairs.next().unwrap().into_inner());
-    consume_token(Rule::t_ranglebracket, &mut pairs);
-    TypeName::Array(Box::new(type_spec))
+impl std::fmt::Display for TypeSpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypeSpecError::FixedArraySizeOutOfRange { size, .. } => write!(
+                f,
+                "Fixed array size {} is out of range. It must be between 1 and {}.",
+                size, MAX_FIXED_ARRAY_SIZE
+            ),
+        }
+    }
 }
 
-fn into_fixed_array(mut pairs: Pairs) -> TypeName {
-    consume_token(Rule::t_array, &mut pairs);
-    consume_token(Rule::t_langlebracket, &mut pairs);
-    let type_spec = into_type_spec(pairs.next().unwrap().into_inner());
-    consume_token(Rule::t_comma, &mut pairs);
-    let size = pairs.next().unwrap().as_str().parse::<u64>().unwrap();
-    consume_token(Rule::t_ranglebracket, &mut pairs);
-    TypeName::FixedArray(Box::new(type_spec), size)
+impl std::error::Error for TypeSpecError {}
+
+// Errors of the builders. Pairs that the builders don't expect are reported
+// as syntax errors.
+enum BuildTypeError {
+    Build(BuildError),
+    TypeSpec(TypeSpecError),
 }
 
-fn into_map(mut pairs: Pairs) -> TypeName {
-    consume_token(Rule::t_map, &mut pairs);
-    consume_token(Rule::t_langlebracket, &mut pairs);
-    let key_type = pairs.next().unwrap().as_str().to_owned();
-    consume_token(Rule::t_comma, &mut pairs);
-    let value_type = into_type_spec(pairs.next().unwrap().into_inner());
-    consume_token(Rule::t_ranglebracket, &mut pairs);
-    TypeName::Map(key_type, Box::new(value_type))
+impl From<BuildError> for BuildTypeError {
+    fn from(err: BuildError) -> Self {
+        BuildTypeError::Build(err)
+    }
 }
 
-fn into_interface_request(mut pairs: Pairs) -> TypeName {
-    let item = pairs.next().unwrap();
+impl From<TypeSpecError> for BuildTypeError {
+    fn from(err: TypeSpecError) -> Self {
+        BuildTypeError::TypeSpec(err)
+    }
+}
+
+// Fails when pairs that the builder doesn't know remain in `items`.
+fn expect_end(mut items: Items) -> Result<(), BuildError> {
+    match items.next() {
+        Some(pair) => Err(BuildError::unexpected(&pair)),
+        None => Ok(()),
+    }
+}
+
+// Ranges of the input are shifted by `offset` so that they point into the
+// text the type was taken from.
+fn span_range(span: pest::Span, offset: usize) -> Range {
+    Range {
+        start: offset + span.start(),
+        end: offset + span.end(),
+    }
+}
+
+// `array<T>` or `array<T, N>`.
+fn into_array(pair: Pair, offset: usize) -> Result<TypeName, BuildTypeError> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_array)?;
+    items.consume(Rule::t_langlebracket)?;
+    let type_spec = into_type_spec(items.next_pair()?, offset)?;
+    if items.next_if(Rule::t_ranglebracket).is_some() {
+        expect_end(items)?;
+        return Ok(TypeName::Array(Box::new(type_spec)));
+    }
+    items.consume(Rule::t_comma)?;
+
+    let item = items.next_pair()?;
+    let range = span_range(item.as_span(), offset);
+    // The grammar accepts only digits. Parsing fails when the size overflows.
+    let size = match item.as_str().parse::<u64>() {
+        Ok(size) if (1..=MAX_FIXED_ARRAY_SIZE).contains(&size) => size,
+        _ => {
+            return Err(TypeSpecError::FixedArraySizeOutOfRange {
+                size: item.as_str().to_owned(),
+                range,
+            }
+            .into())
+        }
+    };
+    items.consume(Rule::t_ranglebracket)?;
+    expect_end(items)?;
+    Ok(TypeName::FixedArray(Box::new(type_spec), size, range))
+}
+
+fn into_map(pair: Pair, offset: usize) -> Result<TypeName, BuildTypeError> {
+    let mut items = Items::new(pair);
+    items.consume(Rule::t_map)?;
+    items.consume(Rule::t_langlebracket)?;
+    let key_type = into_map_key(items.next_pair()?)?;
+    items.consume(Rule::t_comma)?;
+    let value_type = into_type_spec(items.next_pair()?, offset)?;
+    items.consume(Rule::t_ranglebracket)?;
+    expect_end(items)?;
+    Ok(TypeName::Map(Box::new(key_type), Box::new(value_type)))
+}
+
+fn into_map_key(pair: Pair) -> Result<TypeSpec, BuildError> {
+    let mut items = Items::new(pair);
+    let type_name = TypeName::BasicTypeName(items.next_pair()?.as_str().to_owned());
+    let is_nullable = items.next_if(Rule::t_nullable).is_some();
+    expect_end(items)?;
+    Ok(TypeSpec {
+        type_name,
+        is_nullable,
+    })
+}
+
+fn into_interface_request(pair: Pair) -> Result<TypeName, BuildError> {
+    let mut items = Items::new(pair);
+    let item = items.next_pair()?;
     let (ident, is_associated) = match item.as_rule() {
         Rule::t_associated => {
-            let ident = pairs.next().unwrap().as_str().to_owned();
+            let ident = items.next_pair()?.as_str().to_owned();
             (ident, true)
         }
         Rule::identifier => (item.as_str().to_owned(), false),
-        _ => unreachable!(),
+        _ => return Err(BuildError::unexpected(&item)),
     };
-    consume_token(Rule::t_amp, &mut pairs);
-    TypeName::InterfaceRequest(ident, is_associated)
+    items.consume(Rule::t_amp)?;
+    expect_end(items)?;
+    Ok(TypeName::InterfaceRequest(ident, is_associated))
 }
 
-fn into_type_name(mut pairs: Pairs) -> TypeName {
-    let item = pairs.next().unwrap();
-    match item.as_rule() {
-        Rule::fixed_array => into_fixed_array(item.into_inner()),
-        Rule::array => into_array(item.into_inner()),
-        Rule::map => into_map(item.into_inner()),
-        Rule::interface_request => into_interface_request(item.into_inner()),
-        Rule::basic_type_name => into_basic_name(item.into_inner()),
-        _ => unreachable!(),
-    }
+fn into_type_name(pair: Pair, offset: usize) -> Result<TypeName, BuildTypeError> {
+    let item = Items::new(pair).next_pair()?;
+    let type_name = match item.as_rule() {
+        Rule::array => into_array(item, offset)?,
+        Rule::map => into_map(item, offset)?,
+        Rule::interface_request => into_interface_request(item)?,
+        Rule::basic_type_name => into_basic_name(item)?,
+        _ => return Err(BuildError::unexpected(&item).into()),
+    };
+    Ok(type_name)
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeSpec {
     pub type_name: TypeName,
     pub is_nullable: bool,
 }
 
-fn into_type_spec(mut pairs: Pairs) -> TypeSpec {
-    let type_name = into_type_name(pairs.next().unwrap().into_inner());
+impl TypeSpec {
+    /// Returns true when this is a nullable numeric type like `int32?`. These
+    /// are encoded as a value and a separate presence flag. Nullable enums are
+    /// also value types but named types aren't resolved here.
+    pub fn is_nullable_value_type(&self) -> bool {
+        self.is_nullable && self.is_numeric_type()
+    }
+
+    /// Returns true when this type is encoded as a pointer or a handle so that
+    /// null can be represented without a presence flag. Named types (structs,
+    /// unions, enums and interfaces) aren't resolved and return false.
+    pub fn is_reference_type(&self) -> bool {
+        match &self.type_name {
+            TypeName::BasicTypeName(name) => name == "string",
+            TypeName::FixedArray(_, _, _)
+            | TypeName::Array(_)
+            | TypeName::Map(_, _)
+            | TypeName::InterfaceRequest(_, _)
+            | TypeName::PendingRemote(_)
+            | TypeName::PendingReceiver(_)
+            | TypeName::PendingAssociatedRemote(_)
+            | TypeName::PendingAssociatedReceiver(_)
+            | TypeName::Handle(_)
+            | TypeName::Associated(_) => true,
+        }
+    }
+
+    fn is_numeric_type(&self) -> bool {
+        match &self.type_name {
+            TypeName::BasicTypeName(name) => NUMERIC_TYPES.contains(&name.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Returns the user-defined identifiers this type refers to, including
+    /// elements of arrays, keys and values of maps and interfaces of
+    /// requests, associated interfaces and pending types. Builtin type names
+    /// are excluded. Identifiers may be qualified like `foo.Bar`.
+    pub fn referenced_idents(&self) -> Vec<&str> {
+        let mut idents = Vec::new();
+        self.collect_referenced_idents(&mut idents);
+        idents
+    }
+
+    /// Returns true when this type doesn't refer to any user-defined type.
+    pub fn is_builtin_only(&self) -> bool {
+        self.referenced_idents().is_empty()
+    }
+
+    fn collect_referenced_idents<'a>(&'a self, idents: &mut Vec<&'a str>) {
+        match &self.type_name {
+            TypeName::FixedArray(element, _, _) | TypeName::Array(element) => {
+                element.collect_referenced_idents(idents)
+            }
+            TypeName::Map(key, value) => {
+                key.collect_referenced_idents(idents);
+                value.collect_referenced_idents(idents);
+            }
+            TypeName::InterfaceRequest(ident, _)
+            | TypeName::PendingRemote(ident)
+            | TypeName::PendingReceiver(ident)
+            | TypeName::PendingAssociatedRemote(ident)
+            | TypeName::PendingAssociatedReceiver(ident)
+            | TypeName::Associated(ident) => idents.push(ident),
+            TypeName::BasicTypeName(name) => {
+                if name != "string" && !NUMERIC_TYPES.contains(&name.as_str()) {
+                    idents.push(name);
+                }
+            }
+            TypeName::Handle(_) => (),
+        }
+    }
+}
+
+fn into_type_spec(pair: Pair, offset: usize) -> Result<TypeSpec, BuildTypeError> {
+    let mut items = Items::new(pair);
+    let type_name = into_type_name(items.next_pair()?, offset)?;
 
     let mut is_nullable = false;
-    for item in pairs {
+    for item in items {
         match item.as_rule() {
             Rule::t_nullable => is_nullable = true,
-            _ => unreachable!(),
+            _ => return Err(BuildError::unexpected(&item).into()),
         }
     }
 
-    TypeSpec {
+    Ok(TypeSpec {
         type_name: type_name,
         is_nullable: is_nullable,
-    }
+    })
 }
 
+/// Parses `input` as a type. Types that parse but can't be used, like
+/// `array<uint8, 0>`, fail with a `TypeSpecError`.
 pub fn typespec(input: &str) -> anyhow::Result<TypeSpec> {
+    typespec_at(input, 0)
+}
+
+// Same as `typespec()` except that ranges in the result and errors are
+// shifted by `offset`.
+pub(crate) fn typespec_at(input: &str, offset: usize) -> anyhow::Result<TypeSpec> {
+    if let Some(err) = nesting_error(input) {
+        return Err(err.into());
+    }
     let mut pairs = MojomParser::parse(Rule::type_spec, input)?;
-    let inner = pairs.next().unwrap().into_inner();
-    Ok(into_type_spec(inner))
+    let pair = match pairs.next() {
+        Some(pair) => pair,
+        None => anyhow::bail!("no type in {:?}", input),
+    };
+    match into_type_spec(pair, offset) {
+        Ok(type_spec) => Ok(type_spec),
+        Err(BuildTypeError::Build(err)) => Err(err.into_pest_error(input).into()),
+        Err(BuildTypeError::TypeSpec(err)) => Err(err.into()),
+    }
 }
 
 #[cfg(test)]
@@ -184,12 +407,13 @@ mod tests {
 
         let res = typespec("array<string, 16>").unwrap();
         match res.type_name {
-            TypeName::FixedArray(inner_type, size) => {
+            TypeName::FixedArray(inner_type, size, range) => {
                 assert_eq!(
                     TypeName::BasicTypeName("string".to_owned()),
                     inner_type.type_name
                 );
                 assert_eq!(16, size);
+                assert_eq!(Range { start: 14, end: 16 }, range);
             }
             _ => panic!("Expected fixed array"),
         };
@@ -197,7 +421,11 @@ mod tests {
         let res = typespec("map<int32, MyInterface>").unwrap();
         match res.type_name {
             TypeName::Map(key_type, value_type) => {
-                assert_eq!("int32", key_type);
+                assert_eq!(
+                    TypeName::BasicTypeName("int32".to_owned()),
+                    key_type.type_name
+                );
+                assert!(!key_type.is_nullable);
                 assert_eq!(
                     TypeName::BasicTypeName("MyInterface".to_owned()),
                     value_type.type_name
@@ -234,8 +462,245 @@ mod tests {
             _ => panic!("Expected associated type"),
         };
 
+        let res = typespec("pending_remote<MyInterface>").unwrap();
+        assert_eq!(
+            TypeName::PendingRemote("MyInterface".to_owned()),
+            res.type_name
+        );
+
+        let res = typespec("pending_associated_receiver<MyInterface>").unwrap();
+        assert_eq!(
+            TypeName::PendingAssociatedReceiver("MyInterface".to_owned()),
+            res.type_name
+        );
+
         let input = "array<int32>?";
         let res = typespec(&input).unwrap();
         assert!(res.is_nullable);
     }
+
+    #[test]
+    fn test_fixed_array_size() {
+        let res = typespec("array<uint8, 4294967295>").unwrap();
+        match res.type_name {
+            TypeName::FixedArray(_, size, _) => assert_eq!(4294967295, size),
+            _ => panic!("Expected fixed array"),
+        };
+
+        let size_error = |input: &str| {
+            typespec(input)
+                .unwrap_err()
+                .downcast::<TypeSpecError>()
+                .unwrap()
+        };
+        let err = size_error("array<uint8, 0>");
+        assert_eq!(&Range { start: 13, end: 14 }, err.range());
+        let err = size_error("array<uint8, 4294967296>");
+        assert_eq!(&Range { start: 13, end: 23 }, err.range());
+        // Too large for u64.
+        let err = size_error("map<string, array<bool, 99999999999999999999>>");
+        assert_eq!(&Range { start: 24, end: 44 }, err.range());
+        assert_eq!(
+            "Fixed array size 99999999999999999999 is out of range. It must be between 1 and 4294967295.",
+            err.to_string()
+        );
+
+        // Ranges are shifted by the offset.
+        let err = typespec_at("array<uint8, 0>", 100)
+            .unwrap_err()
+            .downcast::<TypeSpecError>()
+            .unwrap();
+        assert_eq!(
+            &Range {
+                start: 113,
+                end: 114
+            },
+            err.range()
+        );
+
+        // Syntax errors aren't `TypeSpecError`.
+        assert!(typespec("array<uint8, -1>")
+            .unwrap_err()
+            .downcast::<TypeSpecError>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_nested_types() {
+        // Parsing this used to take exponential time in the depth.
+        let input = format!(
+            "{}int32?{}",
+            "array<map<string, ".repeat(20),
+            ">, 2>?".repeat(20)
+        );
+        let mut res = &typespec(&input).unwrap();
+        for _ in 0..20 {
+            let value = match &res.type_name {
+                TypeName::FixedArray(element, 2, _) => match &element.type_name {
+                    TypeName::Map(_, value) => value,
+                    _ => panic!("Expected map"),
+                },
+                _ => panic!("Expected fixed array"),
+            };
+            assert!(res.is_nullable);
+            res = value;
+        }
+        assert_eq!(TypeName::BasicTypeName("int32".to_owned()), res.type_name);
+        assert!(res.is_nullable);
+
+        let input = format!("{}int32{}", "array<".repeat(101), ">".repeat(101));
+        assert!(typespec(&input).is_err());
+    }
+
+    #[test]
+    fn test_pending_types() {
+        let res = typespec("pending_receiver<MyInterface>").unwrap();
+        assert_eq!(
+            TypeName::PendingReceiver("MyInterface".to_owned()),
+            res.type_name
+        );
+
+        let res = typespec("pending_associated_remote<foo.MyInterface>").unwrap();
+        assert_eq!(
+            TypeName::PendingAssociatedRemote("foo.MyInterface".to_owned()),
+            res.type_name
+        );
+
+        let res = typespec("pending_remote<MyInterface>?").unwrap();
+        assert!(res.is_nullable);
+        assert_eq!(
+            TypeName::PendingRemote("MyInterface".to_owned()),
+            res.type_name
+        );
+
+        let res = typespec("array<pending_remote<MyInterface>>").unwrap();
+        match res.type_name {
+            TypeName::Array(inner_type) => assert_eq!(
+                TypeName::PendingRemote("MyInterface".to_owned()),
+                inner_type.type_name
+            ),
+            _ => panic!("Expected array"),
+        };
+
+        let res = typespec("map<string, pending_associated_receiver<MyInterface>?>").unwrap();
+        match res.type_name {
+            TypeName::Map(_, value_type) => {
+                assert!(value_type.is_nullable);
+                assert_eq!(
+                    TypeName::PendingAssociatedReceiver("MyInterface".to_owned()),
+                    value_type.type_name
+                );
+            }
+            _ => panic!("Expected map"),
+        };
+
+        // Names that start with the keywords are still identifiers.
+        let res = typespec("pending_remotes").unwrap();
+        assert_eq!(
+            TypeName::BasicTypeName("pending_remotes".to_owned()),
+            res.type_name
+        );
+        assert!(typespec("pending_remote<>").is_err());
+    }
+
+    #[test]
+    fn test_referenced_idents() {
+        let res = typespec("map<string, array<foo.Bar>?>").unwrap();
+        assert_eq!(vec!["foo.Bar"], res.referenced_idents());
+        assert!(!res.is_builtin_only());
+
+        let res = typespec("map<MyEnum, int32>").unwrap();
+        assert_eq!(vec!["MyEnum"], res.referenced_idents());
+
+        let res = typespec("map<Key, array<Value, 4>>").unwrap();
+        assert_eq!(vec!["Key", "Value"], res.referenced_idents());
+
+        for input in &[
+            "MyInterface&",
+            "associated MyInterface&",
+            "associated MyInterface",
+            "pending_remote<MyInterface>",
+            "pending_associated_receiver<MyInterface>?",
+        ] {
+            let res = typespec(input).unwrap();
+            assert_eq!(vec!["MyInterface"], res.referenced_idents(), "{}", input);
+        }
+
+        for input in &[
+            "int32",
+            "string?",
+            "handle<message_pipe>",
+            "map<string, array<uint8>>",
+        ] {
+            let res = typespec(input).unwrap();
+            assert!(res.referenced_idents().is_empty(), "{}", input);
+            assert!(res.is_builtin_only(), "{}", input);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let res = typespec("map<string, array<pending_remote<foo.Bar>>?>").unwrap();
+        let json = serde_json::to_string(&res).unwrap();
+        assert_eq!(res, serde_json::from_str::<TypeSpec>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_unexpected_pairs() {
+        let input = "int32";
+        let parsed = MojomParser::parse(Rule::numeric_type, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        match into_type_spec(parsed, 0) {
+            Err(BuildTypeError::Build(err)) => {
+                let err = err.into_pest_error(input);
+                assert_eq!(pest::error::InputLocation::Span((5, 5)), err.location);
+            }
+            _ => panic!("A numeric type isn't a type spec"),
+        }
+    }
+
+    #[test]
+    fn test_nullable_value_type() {
+        assert!(typespec("int32?").unwrap().is_nullable_value_type());
+        assert!(typespec("bool?").unwrap().is_nullable_value_type());
+        assert!(!typespec("int32").unwrap().is_nullable_value_type());
+        assert!(!typespec("string?").unwrap().is_nullable_value_type());
+        assert!(!typespec("MyEnum?").unwrap().is_nullable_value_type());
+
+        assert!(typespec("string").unwrap().is_reference_type());
+        assert!(typespec("array<uint8>?").unwrap().is_reference_type());
+        assert!(typespec("map<string, int32>").unwrap().is_reference_type());
+        assert!(typespec("handle<message_pipe>")
+            .unwrap()
+            .is_reference_type());
+        assert!(typespec("pending_remote<Foo>").unwrap().is_reference_type());
+        assert!(!typespec("uint64?").unwrap().is_reference_type());
+        assert!(!typespec("MyStruct").unwrap().is_reference_type());
+
+        let res = typespec("map<string?, int32>").unwrap();
+        match res.type_name {
+            TypeName::Map(key_type, _) => assert!(key_type.is_nullable),
+            _ => panic!("Expected map"),
+        };
+
+        // Containers of nullable value types are reference types.
+        let res = typespec("array<int32?>").unwrap();
+        assert!(!res.is_nullable_value_type());
+        match res.type_name {
+            TypeName::Array(element) => assert!(element.is_nullable_value_type()),
+            _ => panic!("Expected array"),
+        };
+        let res = typespec("map<string, bool?>").unwrap();
+        assert!(res.is_reference_type());
+        match res.type_name {
+            TypeName::Map(key_type, value_type) => {
+                assert!(!key_type.is_nullable_value_type());
+                assert!(value_type.is_nullable_value_type());
+            }
+            _ => panic!("Expected map"),
+        };
+    }
 }
@@ -0,0 +1,405 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::syntax::*;
+use super::typespec::TypeSpec;
+
+/// A syntax tree node paired with the text it was parsed from. Accessors
+/// return `&str` slices of the text instead of ranges.
+#[derive(Debug)]
+pub struct View<'a, T> {
+    text: &'a str,
+    node: &'a T,
+}
+
+// Implemented manually because `T` doesn't need to be `Clone`.
+impl<'a, T> Clone for View<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for View<'a, T> {}
+
+pub type MojomFileView<'a> = View<'a, MojomFile>;
+pub type ModuleView<'a> = View<'a, Module>;
+pub type ImportView<'a> = View<'a, Import>;
+pub type InterfaceView<'a> = View<'a, Interface>;
+pub type MethodView<'a> = View<'a, Method>;
+pub type ParameterView<'a> = View<'a, Parameter>;
+pub type StructView<'a> = View<'a, Struct>;
+pub type StructFieldView<'a> = View<'a, StructField>;
+pub type UnionView<'a> = View<'a, Union>;
+pub type UnionFieldView<'a> = View<'a, UnionField>;
+pub type EnumView<'a> = View<'a, Enum>;
+pub type EnumValueView<'a> = View<'a, EnumValue>;
+pub type ConstView<'a> = View<'a, Const>;
+pub type FeatureView<'a> = View<'a, Feature>;
+pub type AttributeView<'a> = View<'a, Attribute>;
+
+impl<'a, T> View<'a, T> {
+    /// Returns the underlying node.
+    pub fn node(&self) -> &'a T {
+        self.node
+    }
+
+    fn text(&self, range: &Range) -> &'a str {
+        &self.text[range.start..range.end]
+    }
+
+    fn child<U>(&self, node: &'a U) -> View<'a, U> {
+        View {
+            text: self.text,
+            node,
+        }
+    }
+}
+
+impl MojomFile {
+    /// Returns a view of this file. `text` is the text this file was parsed
+    /// from.
+    pub fn view<'a>(&'a self, text: &'a str) -> MojomFileView<'a> {
+        View { text, node: self }
+    }
+}
+
+macro_rules! impl_name {
+    ($($name:ty),*) => {
+        $(
+            impl<'a> View<'a, $name> {
+                pub fn name(&self) -> &'a str {
+                    self.text(&self.node.name)
+                }
+
+                /// The whole declaration, including attributes.
+                pub fn source(&self) -> &'a str {
+                    self.text(&self.node.range)
+                }
+
+                pub fn attributes(&self) -> impl Iterator<Item = AttributeView<'a>> + 'a {
+                    let view = *self;
+                    self.node
+                        .attributes
+                        .iter()
+                        .flat_map(|list| list.attributes.iter())
+                        .map(move |attribute| view.child(attribute))
+                }
+            }
+        )*
+    };
+}
+
+impl_name!(
+    Module,
+    Interface,
+    Method,
+    Parameter,
+    Struct,
+    StructField,
+    Union,
+    UnionField,
+    Enum,
+    EnumValue,
+    Const,
+    Feature
+);
+
+macro_rules! impl_type {
+    ($($name:ty),*) => {
+        $(
+            impl<'a> View<'a, $name> {
+                pub fn type_text(&self) -> &'a str {
+                    self.text(&self.node.typ)
+                }
+
+                pub fn type_spec(&self) -> anyhow::Result<TypeSpec> {
+                    self.node.type_spec(self.text)
+                }
+            }
+        )*
+    };
+}
+
+impl_type!(Parameter, StructField, UnionField, Const);
+
+macro_rules! impl_ordinal {
+    ($($name:ty),*) => {
+        $(
+            impl<'a> View<'a, $name> {
+                /// The ordinal including `@`, e.g. `@1`.
+                pub fn ordinal(&self) -> Option<&'a str> {
+                    self.node.ordinal.as_ref().map(|range| self.text(range))
+                }
+            }
+        )*
+    };
+}
+
+impl_ordinal!(Method, Parameter, StructField, UnionField);
+
+// Returns an iterator over views of `$variant` nodes in `$items`.
+macro_rules! children {
+    ($view:expr, $items:expr, $variant:path) => {{
+        let view = *$view;
+        $items.iter().filter_map(move |item| match item {
+            $variant(node) => Some(view.child(node)),
+            _ => None,
+        })
+    }};
+}
+
+impl<'a> MojomFileView<'a> {
+    pub fn module(&self) -> Option<ModuleView<'a>> {
+        children!(self, self.node.stmts, Statement::Module).next()
+    }
+
+    pub fn imports(&self) -> impl Iterator<Item = ImportView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Import)
+    }
+
+    pub fn interfaces(&self) -> impl Iterator<Item = InterfaceView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Interface)
+    }
+
+    pub fn structs(&self) -> impl Iterator<Item = StructView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Struct)
+    }
+
+    pub fn unions(&self) -> impl Iterator<Item = UnionView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Union)
+    }
+
+    /// Returns top-level enums. Enums nested in interfaces and structs are
+    /// returned by their views.
+    pub fn enums(&self) -> impl Iterator<Item = EnumView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Enum)
+    }
+
+    pub fn consts(&self) -> impl Iterator<Item = ConstView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Const)
+    }
+
+    pub fn features(&self) -> impl Iterator<Item = FeatureView<'a>> + 'a {
+        children!(self, self.node.stmts, Statement::Feature)
+    }
+}
+
+impl<'a> ImportView<'a> {
+    /// The imported path without quotes.
+    pub fn path(&self) -> &'a str {
+        self.text(&self.node.path).trim_matches('"')
+    }
+}
+
+impl<'a> InterfaceView<'a> {
+    pub fn methods(&self) -> impl Iterator<Item = MethodView<'a>> + 'a {
+        children!(self, self.node.members, InterfaceMember::Method)
+    }
+
+    pub fn enums(&self) -> impl Iterator<Item = EnumView<'a>> + 'a {
+        children!(self, self.node.members, InterfaceMember::Enum)
+    }
+
+    pub fn consts(&self) -> impl Iterator<Item = ConstView<'a>> + 'a {
+        children!(self, self.node.members, InterfaceMember::Const)
+    }
+}
+
+impl<'a> MethodView<'a> {
+    pub fn params(&self) -> impl Iterator<Item = ParameterView<'a>> + 'a {
+        let view = *self;
+        self.node.params.iter().map(move |param| view.child(param))
+    }
+
+    /// Returns the response parameters. None when the method has no response.
+    pub fn response_params(&self) -> Option<impl Iterator<Item = ParameterView<'a>> + 'a> {
+        let view = *self;
+        let response = self.node.response.as_ref()?;
+        Some(response.params.iter().map(move |param| view.child(param)))
+    }
+}
+
+impl<'a> StructView<'a> {
+    pub fn fields(&self) -> impl Iterator<Item = StructFieldView<'a>> + 'a {
+        children!(self, self.node.members, StructBody::Field)
+    }
+
+    pub fn enums(&self) -> impl Iterator<Item = EnumView<'a>> + 'a {
+        children!(self, self.node.members, StructBody::Enum)
+    }
+
+    pub fn consts(&self) -> impl Iterator<Item = ConstView<'a>> + 'a {
+        children!(self, self.node.members, StructBody::Const)
+    }
+}
+
+impl<'a> StructFieldView<'a> {
+    pub fn default_value(&self) -> Option<&'a str> {
+        self.node.default.as_ref().map(|range| self.text(range))
+    }
+}
+
+impl<'a> UnionView<'a> {
+    pub fn fields(&self) -> impl Iterator<Item = UnionFieldView<'a>> + 'a {
+        let view = *self;
+        self.node.fields.iter().map(move |field| view.child(field))
+    }
+}
+
+impl<'a> FeatureView<'a> {
+    pub fn consts(&self) -> impl Iterator<Item = ConstView<'a>> + 'a {
+        let view = *self;
+        self.node
+            .members
+            .iter()
+            .map(move |member| view.child(member))
+    }
+}
+
+impl<'a> EnumView<'a> {
+    pub fn values(&self) -> impl Iterator<Item = EnumValueView<'a>> + 'a {
+        let view = *self;
+        self.node.values.iter().map(move |value| view.child(value))
+    }
+}
+
+impl<'a> EnumValueView<'a> {
+    pub fn value(&self) -> Option<&'a str> {
+        self.node.value.as_ref().map(|range| self.text(range))
+    }
+}
+
+impl<'a> ConstView<'a> {
+    pub fn value(&self) -> &'a str {
+        self.text(&self.node.value)
+    }
+}
+
+impl<'a> AttributeView<'a> {
+    pub fn name(&self) -> &'a str {
+        self.text(&self.node.name)
+    }
+
+    pub fn value(&self) -> Option<&'a str> {
+        self.node.value.as_ref().map(|range| self.text(range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::parse;
+
+    #[test]
+    fn test_view_testdata() {
+        let text = std::fs::read_to_string("testdata/my_interface.mojom").unwrap();
+        let mojom = parse(&text).unwrap();
+        let view = mojom.view(&text);
+
+        assert!(view.module().is_none());
+        let imports = view
+            .imports()
+            .map(|import| import.path())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["my_service.mojom"], imports);
+        let names = view
+            .interfaces()
+            .map(|interface| interface.name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["MyInterface"], names);
+
+        let interface = view.interfaces().next().unwrap();
+        let methods = interface
+            .methods()
+            .map(|method| method.name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["GetService", "DoSomething"], methods);
+        let values = interface
+            .enums()
+            .flat_map(|node| node.values())
+            .map(|value| value.name())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["kOne", "kTwo", "KThree"], values);
+
+        let method = interface.methods().next().unwrap();
+        assert_eq!(0, method.params().count());
+        let response = method.response_params().unwrap().next().unwrap();
+        assert_eq!("MyService", response.type_text());
+        assert_eq!("service", response.name());
+    }
+
+    #[test]
+    fn test_view() {
+        let text = r#"
+        [JavaPackage="org.chromium.test"]
+        module test.mod;
+        const int32 kTop = 1;
+        [Extensible] enum Color { kRed = 1, [Default] kGreen };
+        struct Point {
+            const int32 kOrigin = 0;
+            array<int32>? coords@0 = default;
+        };
+        union Value { string str@1; };
+        interface Canvas { Draw@2(Point point) => (); };
+        feature kFast { const string name = "Fast"; };
+        "#;
+        let mojom = parse(text).unwrap();
+        let view = mojom.view(text);
+
+        let module = view.module().unwrap();
+        assert_eq!("test.mod", module.name());
+        let attribute = module.attributes().next().unwrap();
+        assert_eq!("JavaPackage", attribute.name());
+        assert_eq!(Some(r#""org.chromium.test""#), attribute.value());
+
+        let constant = view.consts().next().unwrap();
+        assert_eq!(
+            ("int32", "kTop", "1"),
+            (constant.type_text(), constant.name(), constant.value())
+        );
+        assert_eq!("const int32 kTop = 1;", constant.source());
+
+        let color = view.enums().next().unwrap();
+        let values = color
+            .values()
+            .map(|value| (value.name(), value.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("kRed", Some("1")), ("kGreen", None)], values);
+        let green = color.values().nth(1).unwrap();
+        assert_eq!("Default", green.attributes().next().unwrap().name());
+
+        let point = view.structs().next().unwrap();
+        assert_eq!("kOrigin", point.consts().next().unwrap().name());
+        let field = point.fields().next().unwrap();
+        assert_eq!("coords", field.name());
+        assert_eq!(Some("@0"), field.ordinal());
+        assert_eq!(Some("default"), field.default_value());
+        assert!(field.type_spec().unwrap().is_nullable);
+
+        let field = view.unions().next().unwrap().fields().next().unwrap();
+        assert_eq!(
+            ("string", "str", Some("@1")),
+            (field.type_text(), field.name(), field.ordinal())
+        );
+
+        let method = view.interfaces().next().unwrap().methods().next().unwrap();
+        assert_eq!(Some("@2"), method.ordinal());
+        assert_eq!("point", method.params().next().unwrap().name());
+        assert_eq!(0, method.response_params().unwrap().count());
+
+        let feature = view.features().next().unwrap();
+        assert_eq!("kFast", feature.name());
+        let constant = feature.consts().next().unwrap();
+        assert_eq!(("name", "\"Fast\""), (constant.name(), constant.value()));
+    }
+}
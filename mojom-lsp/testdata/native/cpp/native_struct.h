@@ -0,0 +1,16 @@
+#ifndef NATIVE_CPP_NATIVE_STRUCT_H_
+#define NATIVE_CPP_NATIVE_STRUCT_H_
+
+namespace native {
+
+// A forward declaration comes first.
+class NativeStruct;
+
+class COMPONENT_EXPORT(NATIVE) NativeStruct {
+ public:
+  NativeStruct();
+};
+
+}  // namespace native
+
+#endif  // NATIVE_CPP_NATIVE_STRUCT_H_
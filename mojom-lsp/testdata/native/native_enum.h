@@ -0,0 +1,7 @@
+namespace native {
+
+// Mirrors the mojom enum: mojom::NativeEnum ↔ native::NativeEnum.
+// Préfixe→NativeEnum is not a definition.
+enum class NativeEnum : int32_t { kOne, kTwo };
+
+}  // namespace native
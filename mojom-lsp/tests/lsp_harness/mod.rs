@@ -0,0 +1,228 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// A client that drives `mojom_lsp::server::start()` over pipes, the same way
+// an editor talks to the `mojom-lsp` binary. The workspace root is
+// `testdata`. Every wait has a timeout so that a hung server fails the test.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use lsp_types::notification::{
+    DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized, Notification, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, Initialize, Request, Shutdown};
+use lsp_types::{Diagnostic, Location, Position, Url};
+use serde_json::Value;
+
+use mojom_lsp::server::{
+    Connection, Id, Message, NotificationMessage, RequestMessage, ResponseMessage,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct LspClient {
+    connection: Connection,
+    // Receives the exit code of the server.
+    exit_code: Receiver<anyhow::Result<i32>>,
+    root_path: PathBuf,
+    next_id: i64,
+    // Notifications that arrived while waiting for responses.
+    notifications: VecDeque<NotificationMessage>,
+}
+
+impl LspClient {
+    /// Starts a server and initializes it with `testdata` as the root.
+    pub fn start() -> LspClient {
+        let (server_reader, client_writer) = pipe::pipe();
+        let (client_reader, server_writer) = pipe::pipe();
+        let (exit_sender, exit_code) = channel();
+        thread::spawn(move || {
+            let result = mojom_lsp::server::start(server_reader, server_writer);
+            let _ = exit_sender.send(result);
+        });
+        let (connection, _) = Connection::stream(client_reader, client_writer);
+
+        let root_path = Path::new("testdata").canonicalize().unwrap();
+        let mut client = LspClient {
+            connection,
+            exit_code,
+            root_path,
+            next_id: 1,
+            notifications: VecDeque::new(),
+        };
+        #[allow(deprecated)]
+        let params = lsp_types::InitializeParams {
+            process_id: None,
+            root_path: None,
+            root_uri: Some(Url::from_file_path(&client.root_path).unwrap()),
+            initialization_options: None,
+            capabilities: Default::default(),
+            trace: None,
+            workspace_folders: None,
+            client_info: None,
+            locale: None,
+        };
+        client.request::<Initialize>(params);
+        client.notify::<Initialized>(lsp_types::InitializedParams {});
+        client
+    }
+
+    /// Returns the URI of `path` relative to `testdata`.
+    pub fn uri(&self, path: &str) -> Url {
+        Url::from_file_path(self.root_path.join(path)).unwrap()
+    }
+
+    /// Opens the file at `path` relative to `testdata` with its content on
+    /// the disk. Returns the URI of the file.
+    pub fn open(&mut self, path: &str) -> Url {
+        let uri = self.uri(path);
+        let text = std::fs::read_to_string(self.root_path.join(path)).unwrap();
+        let item = lsp_types::TextDocumentItem::new(uri.clone(), "mojom".to_owned(), 1, text);
+        self.notify::<DidOpenTextDocument>(lsp_types::DidOpenTextDocumentParams {
+            text_document: item,
+        });
+        uri
+    }
+
+    /// Replaces the whole text of the document at `uri`.
+    pub fn change(&mut self, uri: &Url, version: i32, text: &str) {
+        let change = lsp_types::TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: text.to_owned(),
+        };
+        self.notify::<DidChangeTextDocument>(lsp_types::DidChangeTextDocumentParams {
+            text_document: lsp_types::VersionedTextDocumentIdentifier::new(uri.clone(), version),
+            content_changes: vec![change],
+        });
+    }
+
+    /// Waits for diagnostics of `uri`. Diagnostics of other documents are
+    /// skipped.
+    pub fn expect_diagnostics(&mut self, uri: &Url) -> Vec<Diagnostic> {
+        loop {
+            let notification = self.next_notification();
+            if notification.method != PublishDiagnostics::METHOD {
+                continue;
+            }
+            let params: lsp_types::PublishDiagnosticsParams =
+                serde_json::from_value(notification.params).unwrap();
+            if &params.uri == uri {
+                return params.diagnostics;
+            }
+        }
+    }
+
+    /// Sends `textDocument/definition` at the zero-based `line` and
+    /// `character`.
+    pub fn definition(&mut self, uri: &Url, line: u32, character: u32) -> Option<Location> {
+        let params = lsp_types::GotoDefinitionParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                Position::new(line, character),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let response = self.request::<GotoDefinition>(params)?;
+        match response {
+            lsp_types::GotoDefinitionResponse::Scalar(location) => Some(location),
+            lsp_types::GotoDefinitionResponse::Array(locations) => locations.into_iter().next(),
+            lsp_types::GotoDefinitionResponse::Link(links) => links
+                .into_iter()
+                .next()
+                .map(|link| Location::new(link.target_uri, link.target_selection_range)),
+        }
+    }
+
+    /// Shuts the server down and checks that it exits successfully.
+    pub fn shutdown(mut self) {
+        self.request::<Shutdown>(());
+        self.notify::<Exit>(());
+        let exit_code = self
+            .exit_code
+            .recv_timeout(TIMEOUT)
+            .expect("Timed out waiting for the server to exit");
+        assert_eq!(0, exit_code.unwrap());
+    }
+
+    pub fn notify<N: Notification>(&mut self, params: N::Params) {
+        let message = Message::Notification(NotificationMessage {
+            method: N::METHOD.to_owned(),
+            params: serde_json::to_value(params).unwrap(),
+        });
+        self.connection.sender.send(message).unwrap();
+    }
+
+    /// Sends a request and waits for its result. Fails when the server
+    /// returns an error.
+    pub fn request<R: Request>(&mut self, params: R::Params) -> R::Result {
+        let id = Id::Number(self.next_id);
+        self.next_id += 1;
+        let message = Message::Request(RequestMessage {
+            id: id.clone(),
+            method: R::METHOD.to_owned(),
+            params: serde_json::to_value(params).unwrap(),
+        });
+        self.connection.sender.send(message).unwrap();
+        loop {
+            match self.receive(R::METHOD) {
+                Message::Response(response) if response.id == id => {
+                    if let Some(err) = response.error {
+                        panic!("{} failed: {} ({})", R::METHOD, err.message, err.code);
+                    }
+                    let result = response.result.unwrap_or(Value::Null);
+                    return serde_json::from_value(result).unwrap();
+                }
+                Message::Response(response) => panic!("Unexpected response: {:?}", response),
+                Message::Notification(notification) => self.notifications.push_back(notification),
+                Message::Request(request) => self.reply(request),
+            }
+        }
+    }
+
+    fn next_notification(&mut self) -> NotificationMessage {
+        if let Some(notification) = self.notifications.pop_front() {
+            return notification;
+        }
+        loop {
+            match self.receive("a notification") {
+                Message::Notification(notification) => return notification,
+                Message::Request(request) => self.reply(request),
+                Message::Response(response) => panic!("Unexpected response: {:?}", response),
+            }
+        }
+    }
+
+    // Accepts requests from the server, e.g. `client/registerCapability`.
+    fn reply(&mut self, request: RequestMessage) {
+        let response = Message::Response(ResponseMessage {
+            id: request.id,
+            result: Some(Value::Null),
+            error: None,
+        });
+        self.connection.sender.send(response).unwrap();
+    }
+
+    fn receive(&mut self, waiting_for: &str) -> Message {
+        match self.connection.receiver.recv_timeout(TIMEOUT) {
+            Ok(message) => message,
+            Err(err) => panic!("No message while waiting for {}: {}", waiting_for, err),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod lsp_harness;
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+use lsp_harness::LspClient;
+
+// Checks that the only diagnostic of `my_service.mojom` is the hint for
+// `foo_module/foo.mojom`, which the file imports but doesn't use.
+fn assert_unused_import_only(diagnostics: &[Diagnostic]) {
+    assert_eq!(1, diagnostics.len(), "{:?}", diagnostics);
+    assert_eq!(Some(DiagnosticSeverity::HINT), diagnostics[0].severity);
+    assert_eq!(1, diagnostics[0].range.start.line);
+}
+
+#[test]
+fn test_open_without_errors() {
+    let mut client = LspClient::start();
+    let uri = client.open("my_service.mojom");
+    assert_unused_import_only(&client.expect_diagnostics(&uri));
+    client.shutdown();
+}
+
+#[test]
+fn test_syntax_error_after_change() {
+    let mut client = LspClient::start();
+    let uri = client.open("my_service.mojom");
+    assert_unused_import_only(&client.expect_diagnostics(&uri));
+
+    let text = std::fs::read_to_string("testdata/my_service.mojom").unwrap();
+    // Drop `;` after the method.
+    let text = text.replacen("my_interface);", "my_interface)", 1);
+    client.change(&uri, 2, &text);
+    let diagnostics = client.expect_diagnostics(&uri);
+    assert_eq!(1, diagnostics.len(), "{:?}", diagnostics);
+    assert_eq!(Some(DiagnosticSeverity::ERROR), diagnostics[0].severity);
+    // Points at `}` on the next line, where `;` is expected.
+    assert_eq!(
+        Range::new(Position::new(5, 0), Position::new(5, 1)),
+        diagnostics[0].range
+    );
+
+    client.shutdown();
+}
+
+#[test]
+fn test_goto_imported_definition() {
+    let mut client = LspClient::start();
+    let uri = client.open("my_service.mojom");
+    client.expect_diagnostics(&uri);
+
+    // `MyInterface` in `=> (MyInterface my_interface)`.
+    let location = client.definition(&uri, 4, 27).unwrap();
+    assert_eq!(client.uri("my_interface.mojom"), location.uri);
+    assert_eq!(
+        Range::new(Position::new(3, 10), Position::new(3, 21)),
+        location.range
+    );
+
+    client.shutdown();
+}
@@ -0,0 +1,194 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Feeds random bytes, random tokens and mutations of the mojoms in
+// `testdata` to the parser and checks that it never panics. The inputs are
+// generated from a fixed seed so that failures are reproducible. Set
+// `MOJOM_FUZZ_ITERATIONS` to run longer, and `MOJOM_FUZZ_SEED` to explore
+// other inputs.
+//
+// Inputs that used to hang or crash the parser are kept in
+// `tests/fuzz_regressions` and are also used as seeds. Inputs too large to
+// keep as files are tested in `syntax`.
+
+use std::panic;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_ITERATIONS: usize = 5_000;
+
+// Tokens of the grammar. Random sequences of these get further into the
+// parser than random bytes.
+const WORDS: &str = "module import struct union interface enum const feature array map handle
+    associated pending_remote pending_receiver pending_associated_remote
+    pending_associated_receiver message_pipe bool int32 uint64 double string true false default
+    Foo foo.bar kValue @0 @1 0 -1 0x1F 1.5e3 .5 [ ] { } ( ) < > => = , ; ? & . /* \"";
+// Tokens that contain whitespace, and non-ASCII characters.
+const SPECIAL_TOKENS: &[&str] = &[
+    " ", "\n", "\r\n", "\t", "// c\n", "/* c */", "\"s\"", "\"\\\"\"", "é", "日本", "😀",
+];
+
+fn tokens() -> Vec<&'static str> {
+    WORDS
+        .split_whitespace()
+        .chain(SPECIAL_TOKENS.iter().cloned())
+        .collect()
+}
+
+// xorshift64*. Good enough to pick mutations and doesn't need a crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn collect_mojoms(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_mojoms(&path, paths);
+        } else if path.extension().is_some_and(|ext| ext == "mojom") {
+            paths.push(path);
+        }
+    }
+}
+
+fn read_mojoms(dir: &str) -> Vec<Vec<u8>> {
+    let mut paths = Vec::new();
+    collect_mojoms(Path::new(dir), &mut paths);
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| std::fs::read(path).unwrap())
+        .collect()
+}
+
+fn random_bytes(rng: &mut Rng) -> Vec<u8> {
+    let len = rng.below(64);
+    (0..len).map(|_| rng.next() as u8).collect()
+}
+
+fn random_tokens(rng: &mut Rng, tokens: &[&str]) -> Vec<u8> {
+    let len = rng.below(48);
+    let mut bytes = Vec::new();
+    for _ in 0..len {
+        bytes.extend_from_slice(tokens[rng.below(tokens.len())].as_bytes());
+        if rng.below(2) == 0 {
+            bytes.push(b' ');
+        }
+    }
+    bytes
+}
+
+fn mutate(rng: &mut Rng, tokens: &[&str], seed: &[u8]) -> Vec<u8> {
+    let mut bytes = seed.to_vec();
+    for _ in 0..1 + rng.below(4) {
+        let at = rng.below(bytes.len() + 1);
+        match rng.below(5) {
+            // Delete a range.
+            0 => {
+                let end = (at + rng.below(16)).min(bytes.len());
+                bytes.drain(at..end);
+            }
+            // Insert a token.
+            1 => {
+                let token = tokens[rng.below(tokens.len())].as_bytes();
+                bytes.splice(at..at, token.iter().cloned());
+            }
+            // Replace a byte.
+            2 if at < bytes.len() => bytes[at] = rng.next() as u8,
+            // Duplicate a range.
+            3 => {
+                let end = (at + rng.below(32)).min(bytes.len());
+                let range = bytes[at..end].to_vec();
+                bytes.splice(at..at, range);
+            }
+            // Truncate.
+            _ => bytes.truncate(at),
+        }
+    }
+    bytes
+}
+
+// Parses `bytes` as a mojom and as a type. Returns the panic message if
+// either panics.
+fn check(bytes: &[u8]) -> Result<(), String> {
+    let text = String::from_utf8_lossy(bytes);
+    panic::catch_unwind(|| {
+        // Errors are also formatted because that looks at the input again.
+        if let Err(err) = mojom_lsp::syntax::parse_with_comments(&text) {
+            let _ = (err.range(), err.message(), err.expected(), err.to_string());
+        }
+        let _ = mojom_lsp::syntax::typespec(&text);
+    })
+    .map_err(|err| {
+        err.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| err.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_default()
+    })
+}
+
+#[test]
+fn test_fuzz_parse() {
+    let mut seeds = read_mojoms("testdata");
+    seeds.extend(read_mojoms("tests/fuzz_regressions"));
+    let tokens = tokens();
+    assert!(!seeds.is_empty());
+    let iterations = env_or("MOJOM_FUZZ_ITERATIONS", DEFAULT_ITERATIONS as u64) as usize;
+    let mut rng = Rng(env_or("MOJOM_FUZZ_SEED", 0x006d_6f6a_6f6d));
+
+    // Silence the default hook; failures are reported with the input.
+    panic::set_hook(Box::new(|_| {}));
+    let mut failures = Vec::new();
+    for seed in seeds.iter() {
+        if let Err(message) = check(seed) {
+            failures.push((message, seed.clone()));
+        }
+    }
+    for i in 0..iterations {
+        let bytes = match i % 4 {
+            0 => random_bytes(&mut rng),
+            1 => random_tokens(&mut rng, &tokens),
+            _ => {
+                let seed = &seeds[rng.below(seeds.len())];
+                mutate(&mut rng, &tokens, seed)
+            }
+        };
+        if let Err(message) = check(&bytes) {
+            failures.push((message, bytes));
+        }
+    }
+    let _ = panic::take_hook();
+
+    for (message, bytes) in failures.iter().take(20) {
+        eprintln!("{}\n---\n{}\n---", message, String::from_utf8_lossy(bytes));
+    }
+    assert!(failures.is_empty(), "{} inputs panicked", failures.len());
+}
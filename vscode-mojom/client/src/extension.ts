@@ -193,6 +193,14 @@ export async function activate(context: vscode.ExtensionContext) {
   subscriptions.push(vscode.commands.registerCommand('mojom.installLanguageServer', async () => {
     installServerBinary();
   }));
+  // Reference code lenses of the server pass a URI and a position.
+  subscriptions.push(vscode.commands.registerCommand('mojom.showReferences', async (uri: string, position: { line: number, character: number }) => {
+    await vscode.commands.executeCommand(
+      'editor.action.findReferences',
+      vscode.Uri.parse(uri),
+      new vscode.Position(position.line, position.character)
+    );
+  }));
 
   applyConfigurations();
 }